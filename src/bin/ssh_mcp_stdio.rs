@@ -1,8 +1,8 @@
 #![deny(warnings)]
 #![deny(clippy::unwrap_used)]
 
-use poem_mcpserver::McpServer;
-use ssh_mcp::mcp::McpSSHCommands;
+use ssh_mcp::mcp::self_test::{self, CHECK_CONFIG_FLAG};
+use ssh_mcp::mcp::transport::{self, McpHandler};
 use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
@@ -13,7 +13,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_writer(std::io::stderr)
         .init();
 
-    poem_mcpserver::stdio::stdio(McpServer::new().tools(McpSSHCommands {})).await?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    self_test::apply_config_arg(&args)?;
+    if args.iter().any(|arg| arg == CHECK_CONFIG_FLAG) {
+        let report = self_test::check_config(true).await;
+        println!("{}", self_test::render_report(&report));
+        std::process::exit(if report.ok { 0 } else { 1 });
+    }
+    // Fail fast on bad configuration instead of on the first ssh_connect
+    self_test::startup_self_test().await?;
+
+    transport::stdio::serve(McpHandler::new()).await?;
 
     Ok(())
 }
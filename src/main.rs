@@ -1,40 +1,188 @@
 #![deny(warnings)]
 #![deny(clippy::unwrap_used)]
 
+use clap::{Parser, Subcommand, ValueEnum};
 use dotenv::dotenv;
-use poem::{EndpointExt, Route, Server, listener::TcpListener, middleware::Tracing};
-use poem_mcpserver::{McpServer, streamable_http};
-use tracing::info;
+use poem::listener::{Listener, TcpListener};
+use poem::{EndpointExt, Route, Server, middleware::Tracing};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
 
-mod mcp;
+use ssh_mcp::mcp::self_test;
+use ssh_mcp::mcp::transport::tls::{self, TlsFiles};
+use ssh_mcp::mcp::transport::token_auth::{self, TokenAuth};
+use ssh_mcp::mcp::transport::{self, HttpTransports, McpHandler, Serving};
+
+/// SSH MCP server: SSH sessions, commands and file transfer as MCP tools.
+///
+/// Without a subcommand it runs `serve`. Settings not given on the command
+/// line come from environment variables, then the configuration file.
+#[derive(Debug, Parser)]
+#[command(name = "ssh-mcp", version)]
+struct Cli {
+    /// TOML server configuration file (overrides SSH_MCP_CONFIG)
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<String>,
+
+    /// Print the configuration report and exit (same as `check-config`)
+    #[arg(long, hide = true)]
+    check_config: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the MCP server (default)
+    Serve(ServeArgs),
+    /// Validate the configuration, print the report and exit (status 1 on errors)
+    CheckConfig,
+    /// Print the version and enabled features
+    Version,
+}
+
+#[derive(Debug, Default, clap::Args)]
+struct ServeArgs {
+    /// HTTP port (overrides MCP_PORT and [server] port; default: 8000)
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    port: Option<u16>,
+
+    /// `http` serves the transports selected by MCP_TRANSPORT on the port;
+    /// `stdio` speaks newline-delimited JSON-RPC on stdin/stdout
+    #[arg(long, value_enum, default_value_t = Transport::Http)]
+    transport: Transport,
+}
+
+/// How `serve` talks to MCP clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Transport {
+    #[default]
+    Http,
+    Stdio,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
+    let cli = Cli::parse();
+    let command = match (cli.command, cli.check_config) {
+        (None, true) => Command::CheckConfig,
+        (None, false) => Command::Serve(ServeArgs::default()),
+        (Some(command), _) => command,
+    };
+
+    if let Command::Version = command {
+        println!("{}", version());
+        return Ok(());
+    }
 
-    // Initialize logging with proper tracing default
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("info".parse().expect("valid directive")),
-        )
-        .init();
+    // stdout carries the protocol in stdio mode, so logs go to stderr there
+    let filter =
+        EnvFilter::from_default_env().add_directive("info".parse().expect("valid directive"));
+    match &command {
+        Command::Serve(args) if args.transport == Transport::Stdio => tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .init(),
+        _ => tracing_subscriber::fmt().with_env_filter(filter).init(),
+    }
+
+    if let Some(path) = cli.config {
+        self_test::use_config_file(path)?;
+    }
+
+    match command {
+        Command::CheckConfig => {
+            let report = self_test::check_config(true).await;
+            println!("{}", self_test::render_report(&report));
+            std::process::exit(if report.ok { 0 } else { 1 });
+        }
+        Command::Serve(args) => serve(args).await,
+        Command::Version => Ok(()),
+    }
+}
+
+/// `ssh-mcp <version> (features: ...)`
+fn version() -> String {
+    let features: Vec<&str> = [
+        ("port_forward", cfg!(feature = "port_forward")),
+        ("redis", cfg!(feature = "redis")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    format!(
+        "ssh-mcp {} (features: {})",
+        env!("CARGO_PKG_VERSION"),
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    )
+}
+
+async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    // Fail fast on bad configuration instead of on the first ssh_connect
+    self_test::startup_self_test().await?;
+
+    if args.transport == Transport::Stdio {
+        transport::stdio::serve(McpHandler::new()).await?;
+        return Ok(());
+    }
 
     // Setup MCP server
-    let mcp_port: u16 = std::env::var("MCP_PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(8000);
-    let mcp_addr = format!("0.0.0.0:{}", mcp_port);
-    info!("Starting MCP server on {}", mcp_addr);
-
-    // Setup the poem-mcpserver endpoint with SSH commands
-    let app = Route::new()
-        .at(
-            "/",
-            streamable_http::endpoint(|_| McpServer::new().tools(mcp::McpSSHCommands {})),
-        )
-        .with(Tracing);
+    let mcp_addr = format!("0.0.0.0:{}", args.port.unwrap_or_else(self_test::mcp_port));
+    let listener = match TlsFiles::resolve()? {
+        Some(files) => {
+            let config = files.load()?;
+            info!("Starting MCP server on https://{}", mcp_addr);
+            TcpListener::bind(mcp_addr).rustls(config).boxed()
+        }
+        None => {
+            warn!(
+                "Serving plain HTTP; set {} and {} (or [server] tls_cert and tls_key) to enable TLS",
+                tls::TLS_CERT_ENV_VAR,
+                tls::TLS_KEY_ENV_VAR
+            );
+            info!("Starting MCP server on http://{}", mcp_addr);
+            TcpListener::bind(mcp_addr).boxed()
+        }
+    };
+
+    // Setup the MCP endpoints with SSH commands; every transport shares the tool registry
+    // A client's token decides which agent its tool calls act as
+    let handler =
+        |request: &poem::Request| McpHandler::new().with_principal(token_auth::principal(request));
+    let transports = HttpTransports::from_env();
+    transport::set_serving(Serving::Http(transports));
+    let mut routes = Route::new();
+    if transports.streamable_http() {
+        routes = routes.at("/", transport::http::endpoint(handler));
+        info!("Streamable HTTP transport on /");
+    }
+    if transports.sse() {
+        routes = transport::sse::mount(routes, handler);
+        info!(
+            "SSE transport on {} (messages on {})",
+            transport::sse::SSE_PATH,
+            transport::sse::MESSAGES_PATH
+        );
+    }
+    let auth = TokenAuth::from_env();
+    match &auth {
+        Some(auth) => info!("HTTP clients must present one of {} token(s)", auth.len()),
+        None => warn!(
+            "HTTP endpoint is unauthenticated; set {} to require a token",
+            token_auth::AUTH_TOKEN_ENV_VAR
+        ),
+    }
+    let app = match auth {
+        Some(auth) => routes.with(auth).boxed(),
+        None => routes.boxed(),
+    }
+    .with(Tracing);
 
     info!("MCP Server with SSH client support is ready");
     info!("Use the ssh_connect command to establish SSH connections");
@@ -42,10 +190,60 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Use the ssh_forward command to set up port forwarding");
 
     // Run the MCP server
-    Server::new(TcpListener::bind(mcp_addr))
+    Server::new(listener)
         .name("SSH MCP Server")
         .run(app)
         .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn try_parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("ssh-mcp").chain(args.iter().copied()))
+    }
+
+    fn parse(args: &[&str]) -> Cli {
+        try_parse(args).expect("valid arguments")
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_serve_args() {
+        let cli = parse(&["serve", "--port", "9000", "--transport", "stdio"]);
+        let Some(Command::Serve(args)) = cli.command else {
+            panic!("expected serve");
+        };
+        assert_eq!(args.port, Some(9000));
+        assert_eq!(args.transport, Transport::Stdio);
+
+        let cli = parse(&["serve", "--config", "ssh-mcp.toml"]);
+        assert_eq!(cli.config.as_deref(), Some("ssh-mcp.toml"));
+
+        assert!(try_parse(&["serve", "--port", "0"]).is_err());
+        assert!(try_parse(&["serve", "--transport", "sse"]).is_err());
+    }
+
+    #[test]
+    fn test_subcommands_and_legacy_flag() {
+        assert!(parse(&[]).command.is_none());
+        assert!(matches!(
+            parse(&["check-config", "--config", "a.toml"]).command,
+            Some(Command::CheckConfig)
+        ));
+        assert!(matches!(
+            parse(&["version"]).command,
+            Some(Command::Version)
+        ));
+        assert!(parse(&["--check-config"]).check_config);
+        assert!(version().starts_with(&format!("ssh-mcp {}", env!("CARGO_PKG_VERSION"))));
+    }
+}
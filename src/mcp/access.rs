@@ -0,0 +1,282 @@
+//! Cross-agent session access.
+//!
+//! A session opened with an `agent_id` is owned by that agent. By default other
+//! agents do not see it in `ssh_list_sessions` and may not use it. Opening it
+//! with `shared=true` makes it visible to every agent, either read-only (reuse
+//! through `ssh_connect`, listing) or with full access (execute, shells,
+//! forwarding, disconnect).
+//!
+//! Tools identify the calling agent through their optional `agent_id`
+//! parameter. Calls without an `agent_id`, and sessions opened without one,
+//! are not restricted, so single-agent setups behave as before.
+//!
+//! An HTTP client whose token is bound to an agent (see
+//! `transport::token_auth`) always calls as that agent, and private sessions
+//! without an owner are closed to it too.
+
+use std::future::Future;
+
+use super::error::{ErrorCode, ToolError};
+use super::types::{SessionInfo, ShareMode};
+
+tokio::task_local! {
+    static BOUND_AGENT: String;
+}
+
+/// Run `future` (a request) for a client whose token is bound to `agent_id`.
+pub(crate) async fn with_bound_agent<F: Future>(agent_id: String, future: F) -> F::Output {
+    BOUND_AGENT.scope(agent_id, future).await
+}
+
+/// The agent the calling client is bound to, if any.
+fn bound_agent() -> Option<String> {
+    BOUND_AGENT.try_with(String::clone).ok()
+}
+
+/// The agent a request acts as: the bound agent, else the `agent_id` it passed.
+pub(crate) fn acting_agent(caller: Option<&str>) -> Option<String> {
+    bound_agent().or_else(|| caller.map(str::to_string))
+}
+
+/// Kind of access a tool needs on a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SessionAccess {
+    /// Inspect or reuse the session without changing the remote host
+    Read,
+    /// Run commands, open channels or tear the session down
+    Write,
+}
+
+/// Resolve the `shared`/`share_mode` parameters of `ssh_connect`.
+///
+/// `share_mode` defaults to `read_only` and is only meaningful with `shared=true`.
+pub(crate) fn resolve_share_mode(
+    shared: Option<bool>,
+    share_mode: Option<&str>,
+) -> Result<Option<ShareMode>, String> {
+    let mode = match share_mode {
+        None | Some("read_only") => ShareMode::ReadOnly,
+        Some("full") => ShareMode::Full,
+        Some(other) => {
+            return Err(format!(
+                "share_mode must be \"read_only\" or \"full\", got \"{}\"",
+                other
+            ));
+        }
+    };
+
+    match shared {
+        Some(true) => Ok(Some(mode)),
+        _ if share_mode.is_some() => Err("share_mode requires shared=true".to_string()),
+        _ => Ok(None),
+    }
+}
+
+/// Whether a session shows up in `ssh_list_sessions` filtered by `agent_id`.
+pub(crate) fn is_visible_to(info: &SessionInfo, agent_id: &str) -> bool {
+    info.agent_id.as_deref() == Some(agent_id) || info.shared.is_some()
+}
+
+/// Check that `caller` may use the session with the requested access.
+pub(crate) fn check_session_access(
+    info: &SessionInfo,
+    caller: Option<&str>,
+    access: SessionAccess,
+) -> Result<(), ToolError> {
+    let bound = bound_agent();
+    let acting = acting_agent(caller);
+    let (Some(caller), Some(owner)) = (acting.as_deref(), info.agent_id.as_deref()) else {
+        if let Some(bound) = bound
+            && info.shared.is_none()
+        {
+            return Err(ToolError::new(
+                ErrorCode::AccessDenied,
+                format!(
+                    "Session {} has no owner; this client is bound to agent '{}'",
+                    info.session_id, bound
+                ),
+            )
+            .with_detail("session_id", info.session_id.as_str()));
+        }
+        return Ok(());
+    };
+    if caller == owner {
+        return Ok(());
+    }
+
+    let reason = match (info.shared, access) {
+        (Some(ShareMode::Full), _) | (Some(ShareMode::ReadOnly), SessionAccess::Read) => {
+            return Ok(());
+        }
+        (Some(ShareMode::ReadOnly), SessionAccess::Write) => "is shared read-only",
+        (None, _) => "is not shared",
+    };
+
+    Err(ToolError::new(
+        ErrorCode::AccessDenied,
+        format!(
+            "Session {} is owned by agent '{}' and {}",
+            info.session_id, owner, reason
+        ),
+    )
+    .with_detail("session_id", info.session_id.as_str())
+    .with_detail("owner_agent_id", owner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(owner: Option<&str>, shared: Option<ShareMode>) -> SessionInfo {
+        SessionInfo {
+            session_id: "s1".to_string(),
+            name: None,
+            agent_id: owner.map(str::to_string),
+            shared,
+            host: "host:22".to_string(),
+            username: "deploy".to_string(),
+            connected_at: "2024-01-01T00:00:00Z".to_string(),
+            default_timeout_secs: 30,
+            retry_attempts: 0,
+            compression_enabled: false,
+            compression_level: None,
+            keepalive_interval_secs: 30,
+            keepalive_max: 3,
+            algorithms: None,
+            last_health_check: None,
+            healthy: None,
+            health_history: Vec::new(),
+            flapping: false,
+            identity: None,
+            rtt_ms: None,
+            rtt_avg_ms: None,
+            region: None,
+            datacenter: None,
+            jump_hosts: Vec::new(),
+            rebooting: false,
+            read_only: false,
+            agent_forwarding: false,
+            profile: None,
+            command_timeout_secs: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            stats: Default::default(),
+            labels: Default::default(),
+        }
+    }
+
+    mod share_mode {
+        use super::*;
+
+        #[test]
+        fn test_not_shared_by_default() {
+            assert_eq!(resolve_share_mode(None, None), Ok(None));
+            assert_eq!(resolve_share_mode(Some(false), None), Ok(None));
+        }
+
+        #[test]
+        fn test_shared_defaults_to_read_only() {
+            assert_eq!(
+                resolve_share_mode(Some(true), None),
+                Ok(Some(ShareMode::ReadOnly))
+            );
+        }
+
+        #[test]
+        fn test_full_mode() {
+            assert_eq!(
+                resolve_share_mode(Some(true), Some("full")),
+                Ok(Some(ShareMode::Full))
+            );
+        }
+
+        #[test]
+        fn test_unknown_mode_is_error() {
+            assert!(resolve_share_mode(Some(true), Some("write")).is_err());
+        }
+
+        #[test]
+        fn test_mode_without_shared_is_error() {
+            assert!(resolve_share_mode(None, Some("full")).is_err());
+        }
+    }
+
+    mod access {
+        use super::*;
+
+        #[test]
+        fn test_owner_has_full_access() {
+            let info = session(Some("deploy"), None);
+            assert!(check_session_access(&info, Some("deploy"), SessionAccess::Write).is_ok());
+        }
+
+        #[test]
+        fn test_anonymous_caller_is_not_restricted() {
+            let info = session(Some("deploy"), None);
+            assert!(check_session_access(&info, None, SessionAccess::Write).is_ok());
+        }
+
+        #[test]
+        fn test_unowned_session_is_not_restricted() {
+            let info = session(None, None);
+            assert!(check_session_access(&info, Some("monitor"), SessionAccess::Write).is_ok());
+        }
+
+        #[test]
+        fn test_private_session_denies_other_agents() {
+            let info = session(Some("deploy"), None);
+            let err =
+                check_session_access(&info, Some("monitor"), SessionAccess::Read).unwrap_err();
+            assert_eq!(err.code, ErrorCode::AccessDenied);
+            assert!(err.message.contains("not shared"));
+            assert_eq!(err.details["owner_agent_id"], "deploy");
+        }
+
+        #[test]
+        fn test_read_only_share() {
+            let info = session(Some("deploy"), Some(ShareMode::ReadOnly));
+            assert!(check_session_access(&info, Some("monitor"), SessionAccess::Read).is_ok());
+            let err =
+                check_session_access(&info, Some("monitor"), SessionAccess::Write).unwrap_err();
+            assert!(err.message.contains("read-only"));
+        }
+
+        #[test]
+        fn test_full_share() {
+            let info = session(Some("deploy"), Some(ShareMode::Full));
+            assert!(check_session_access(&info, Some("monitor"), SessionAccess::Write).is_ok());
+        }
+
+        #[tokio::test]
+        async fn test_bound_client_calls_as_its_agent() {
+            with_bound_agent("ci".to_string(), async {
+                let own = session(Some("ci"), None);
+                assert!(check_session_access(&own, Some("deploy"), SessionAccess::Write).is_ok());
+                let other = session(Some("deploy"), None);
+                assert!(check_session_access(&other, None, SessionAccess::Read).is_err());
+                // Unowned sessions are closed unless shared
+                let err = check_session_access(&session(None, None), None, SessionAccess::Read)
+                    .unwrap_err();
+                assert_eq!(err.code, ErrorCode::AccessDenied);
+                let shared = session(None, Some(ShareMode::Full));
+                assert!(check_session_access(&shared, None, SessionAccess::Write).is_ok());
+            })
+            .await;
+        }
+    }
+
+    mod visibility {
+        use super::*;
+
+        #[test]
+        fn test_own_and_shared_sessions_are_visible() {
+            assert!(is_visible_to(&session(Some("deploy"), None), "deploy"));
+            assert!(is_visible_to(
+                &session(Some("deploy"), Some(ShareMode::ReadOnly)),
+                "monitor"
+            ));
+            assert!(!is_visible_to(&session(Some("deploy"), None), "monitor"));
+            assert!(!is_visible_to(&session(None, None), "monitor"));
+        }
+    }
+}
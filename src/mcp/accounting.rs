@@ -0,0 +1,332 @@
+//! Per-agent usage accounting.
+//!
+//! Shared ssh-mcp instances serve many agents; platform teams need to know
+//! who used what. [`USAGE`] keeps cumulative counters per agent since the
+//! server started:
+//!
+//! - successful connects
+//! - commands run (`ssh_execute`, `ssh_execute_sync`, `ssh_execute_dag` nodes,
+//!   `ssh_execute_batch` targets)
+//! - output bytes those commands produced (stdout plus stderr)
+//! - shells opened and their wall-clock time (open shells count up to now)
+//! - bytes sent and received on their sessions (see `traffic`)
+//!
+//! # Attribution
+//!
+//! Usage is charged to the calling agent (`agent_id` parameter) when given,
+//! otherwise to the agent owning the session, otherwise to
+//! [`ANONYMOUS_AGENT`]. Counters live in memory and reset on restart.
+//!
+//! `ssh_usage` reports the counters as JSON or in the Prometheus text format
+//! with an `agent_id` label, ready for a scraper or billing export.
+
+use std::fmt::Write as _;
+use std::time::Instant;
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+use super::types::AgentUsage;
+
+/// Agent name charged when neither the caller nor the session has an agent id
+pub(crate) const ANONYMOUS_AGENT: &str = "anonymous";
+
+/// Global usage ledger.
+pub(crate) static USAGE: Lazy<UsageLedger> = Lazy::new(UsageLedger::new);
+
+/// Pick the agent usage is charged to.
+pub(crate) fn billed_agent<'a>(caller: Option<&'a str>, owner: Option<&'a str>) -> &'a str {
+    caller.or(owner).unwrap_or(ANONYMOUS_AGENT)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    connects: u64,
+    commands: u64,
+    output_bytes: u64,
+    shells_opened: u64,
+    closed_shell_secs: f64,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+/// Cumulative per-agent counters.
+pub(crate) struct UsageLedger {
+    agents: DashMap<String, Counters>,
+    /// Open shells: shell id to (agent, open time)
+    open_shells: DashMap<String, (String, Instant)>,
+    since: String,
+}
+
+impl UsageLedger {
+    /// Create an empty ledger starting now.
+    pub fn new() -> Self {
+        Self {
+            agents: DashMap::new(),
+            open_shells: DashMap::new(),
+            since: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// When counting started (RFC3339).
+    pub fn since(&self) -> &str {
+        &self.since
+    }
+
+    fn update(&self, agent: &str, apply: impl FnOnce(&mut Counters)) {
+        apply(&mut self.agents.entry(agent.to_string()).or_default());
+    }
+
+    /// Record a successful connect.
+    pub fn record_connect(&self, agent: &str) {
+        self.update(agent, |c| c.connects += 1);
+    }
+
+    /// Record a command start.
+    pub fn record_command(&self, agent: &str) {
+        self.update(agent, |c| c.commands += 1);
+    }
+
+    /// Record output bytes produced by a finished command.
+    pub fn record_output(&self, agent: &str, bytes: usize) {
+        self.update(agent, |c| c.output_bytes += bytes as u64);
+    }
+
+    /// Record channel bytes of a session owned by `agent`.
+    pub fn record_traffic(&self, agent: &str, sent: u64, received: u64) {
+        // Called per channel message; skip the key allocation once the agent exists
+        if let Some(mut counters) = self.agents.get_mut(agent) {
+            counters.bytes_sent += sent;
+            counters.bytes_received += received;
+            return;
+        }
+        self.update(agent, |c| {
+            c.bytes_sent += sent;
+            c.bytes_received += received;
+        });
+    }
+
+    /// Start the wall clock of a shell.
+    pub fn shell_opened(&self, agent: &str, shell_id: &str) {
+        self.update(agent, |c| c.shells_opened += 1);
+        self.open_shells
+            .insert(shell_id.to_string(), (agent.to_string(), Instant::now()));
+    }
+
+    /// Stop the wall clock of a shell. Unknown or already closed shells are ignored.
+    pub fn shell_closed(&self, shell_id: &str) {
+        if let Some((_, (agent, opened))) = self.open_shells.remove(shell_id) {
+            let secs = opened.elapsed().as_secs_f64();
+            self.update(&agent, |c| c.closed_shell_secs += secs);
+        }
+    }
+
+    /// Usage per agent, sorted by agent id, optionally for one agent only.
+    pub fn snapshot(&self, agent: Option<&str>) -> Vec<AgentUsage> {
+        let mut open: Vec<(String, f64, u64)> = Vec::new();
+        for shell in self.open_shells.iter() {
+            let (agent, opened) = shell.value();
+            match open.iter_mut().find(|(name, _, _)| name == agent) {
+                Some(entry) => {
+                    entry.1 += opened.elapsed().as_secs_f64();
+                    entry.2 += 1;
+                }
+                None => open.push((agent.clone(), opened.elapsed().as_secs_f64(), 1)),
+            }
+        }
+
+        let mut usage: Vec<AgentUsage> = self
+            .agents
+            .iter()
+            .filter(|entry| agent.is_none_or(|agent| entry.key() == agent))
+            .map(|entry| {
+                let counters = *entry.value();
+                let (open_secs, open_shells) = open
+                    .iter()
+                    .find(|(name, _, _)| name == entry.key())
+                    .map(|(_, secs, count)| (*secs, *count))
+                    .unwrap_or_default();
+                AgentUsage {
+                    agent_id: entry.key().clone(),
+                    connects: counters.connects,
+                    commands: counters.commands,
+                    output_bytes: counters.output_bytes,
+                    shells_opened: counters.shells_opened,
+                    open_shells,
+                    shell_seconds: round_secs(counters.closed_shell_secs + open_secs),
+                    bytes_sent: counters.bytes_sent,
+                    bytes_received: counters.bytes_received,
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+        usage
+    }
+}
+
+fn round_secs(secs: f64) -> f64 {
+    (secs * 1000.0).round() / 1000.0
+}
+
+/// Render usage in the Prometheus text exposition format.
+pub(crate) fn render_metrics(usage: &[AgentUsage]) -> String {
+    type Metric = (
+        &'static str,
+        &'static str,
+        &'static str,
+        fn(&AgentUsage) -> String,
+    );
+    const METRICS: &[Metric] = &[
+        (
+            "ssh_mcp_connects_total",
+            "counter",
+            "Successful SSH connects",
+            |u| u.connects.to_string(),
+        ),
+        (
+            "ssh_mcp_commands_total",
+            "counter",
+            "Commands started",
+            |u| u.commands.to_string(),
+        ),
+        (
+            "ssh_mcp_output_bytes_total",
+            "counter",
+            "Command output bytes (stdout and stderr)",
+            |u| u.output_bytes.to_string(),
+        ),
+        (
+            "ssh_mcp_shells_opened_total",
+            "counter",
+            "Interactive shells opened",
+            |u| u.shells_opened.to_string(),
+        ),
+        (
+            "ssh_mcp_open_shells",
+            "gauge",
+            "Interactive shells currently open",
+            |u| u.open_shells.to_string(),
+        ),
+        (
+            "ssh_mcp_shell_seconds_total",
+            "counter",
+            "Wall-clock seconds of interactive shells",
+            |u| u.shell_seconds.to_string(),
+        ),
+        (
+            "ssh_mcp_bytes_sent_total",
+            "counter",
+            "Channel bytes sent to remote hosts",
+            |u| u.bytes_sent.to_string(),
+        ),
+        (
+            "ssh_mcp_bytes_received_total",
+            "counter",
+            "Channel bytes received from remote hosts",
+            |u| u.bytes_received.to_string(),
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, kind, help, value) in METRICS {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for agent in usage {
+            let _ = writeln!(
+                out,
+                "{}{{agent_id=\"{}\"}} {}",
+                name,
+                escape_label(&agent.agent_id),
+                value(agent)
+            );
+        }
+    }
+    out
+}
+
+/// Escape a Prometheus label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod attribution {
+        use super::*;
+
+        #[test]
+        fn test_billed_agent() {
+            assert_eq!(billed_agent(Some("caller"), Some("owner")), "caller");
+            assert_eq!(billed_agent(None, Some("owner")), "owner");
+            assert_eq!(billed_agent(None, None), ANONYMOUS_AGENT);
+        }
+    }
+
+    mod ledger {
+        use super::*;
+
+        #[test]
+        fn test_counters_per_agent() {
+            let ledger = UsageLedger::new();
+            ledger.record_connect("a");
+            ledger.record_command("a");
+            ledger.record_command("a");
+            ledger.record_output("a", 100);
+            ledger.record_command("b");
+
+            let usage = ledger.snapshot(None);
+            assert_eq!(usage.len(), 2);
+            assert_eq!(usage[0].agent_id, "a");
+            assert_eq!(usage[0].connects, 1);
+            assert_eq!(usage[0].commands, 2);
+            assert_eq!(usage[0].output_bytes, 100);
+            assert_eq!(usage[1].agent_id, "b");
+            assert_eq!(usage[1].connects, 0);
+        }
+
+        #[test]
+        fn test_filter_by_agent() {
+            let ledger = UsageLedger::new();
+            ledger.record_connect("a");
+            ledger.record_connect("b");
+            let usage = ledger.snapshot(Some("b"));
+            assert_eq!(usage.len(), 1);
+            assert_eq!(usage[0].agent_id, "b");
+            assert!(ledger.snapshot(Some("c")).is_empty());
+        }
+
+        #[test]
+        fn test_shell_wall_clock() {
+            let ledger = UsageLedger::new();
+            ledger.shell_opened("a", "shell-1");
+            let open = ledger.snapshot(None);
+            assert_eq!(open[0].shells_opened, 1);
+            assert_eq!(open[0].open_shells, 1);
+
+            ledger.shell_closed("shell-1");
+            ledger.shell_closed("shell-1");
+            let closed = ledger.snapshot(None);
+            assert_eq!(closed[0].open_shells, 0);
+            assert!(closed[0].shell_seconds >= 0.0);
+        }
+    }
+
+    mod metrics {
+        use super::*;
+
+        #[test]
+        fn test_render_metrics() {
+            let ledger = UsageLedger::new();
+            ledger.record_command("team \"x\"");
+            let text = render_metrics(&ledger.snapshot(None));
+            assert!(text.contains("# TYPE ssh_mcp_commands_total counter"));
+            assert!(text.contains("ssh_mcp_commands_total{agent_id=\"team \\\"x\\\"\"} 1"));
+            assert!(text.contains("# TYPE ssh_mcp_open_shells gauge"));
+        }
+    }
+}
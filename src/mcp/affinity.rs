@@ -0,0 +1,235 @@
+//! Region and datacenter affinity.
+//!
+//! One MCP server may front hosts in several regions with very different
+//! latencies. `ssh_connect` can tag a session with a `region` and a
+//! `datacenter`; `ssh_select_session` and `ssh_execute_dag` nodes can then ask
+//! for "a session in eu-west-1" instead of naming one.
+//!
+//! # Selection
+//!
+//! Every field given in the [`SessionAffinity`] must match (case-insensitive).
+//! Among matching sessions the best one is picked by:
+//!
+//! 1. not known to be unhealthy (`healthy` is not `false`)
+//! 2. not flapping between healthy and failed checks
+//! 3. lowest smoothed round-trip time (sessions without a measurement last)
+//! 4. session_id, so the choice is stable
+
+use std::cmp::Ordering;
+
+use super::types::{SessionAffinity, SessionInfo};
+
+/// Longest accepted region or datacenter label
+pub(crate) const MAX_AFFINITY_LABEL_LEN: usize = 64;
+
+/// Validate a `region` / `datacenter` label: trimmed, blank means unset.
+pub(crate) fn normalize_affinity_label(
+    field: &str,
+    value: Option<String>,
+) -> Result<Option<String>, String> {
+    let Some(value) = value.map(|value| value.trim().to_string()) else {
+        return Ok(None);
+    };
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if value.chars().count() > MAX_AFFINITY_LABEL_LEN {
+        return Err(format!(
+            "{} must be at most {} characters",
+            field, MAX_AFFINITY_LABEL_LEN
+        ));
+    }
+    Ok(Some(value))
+}
+
+impl SessionAffinity {
+    /// Whether neither region nor datacenter is requested.
+    pub fn is_empty(&self) -> bool {
+        self.region.is_none() && self.datacenter.is_none()
+    }
+
+    /// Whether a session satisfies every requested field.
+    pub fn matches(&self, info: &SessionInfo) -> bool {
+        fn field_matches(wanted: Option<&str>, actual: Option<&str>) -> bool {
+            match (wanted, actual) {
+                (None, _) => true,
+                (Some(wanted), Some(actual)) => wanted.eq_ignore_ascii_case(actual),
+                (Some(_), None) => false,
+            }
+        }
+        field_matches(self.region.as_deref(), info.region.as_deref())
+            && field_matches(self.datacenter.as_deref(), info.datacenter.as_deref())
+    }
+}
+
+impl std::fmt::Display for SessionAffinity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.region, &self.datacenter) {
+            (Some(region), Some(datacenter)) => {
+                write!(f, "region '{}', datacenter '{}'", region, datacenter)
+            }
+            (Some(region), None) => write!(f, "region '{}'", region),
+            (None, Some(datacenter)) => write!(f, "datacenter '{}'", datacenter),
+            (None, None) => write!(f, "any location"),
+        }
+    }
+}
+
+/// Order two matching sessions, best first.
+fn preference(a: &SessionInfo, b: &SessionInfo) -> Ordering {
+    let unhealthy = |info: &SessionInfo| info.healthy == Some(false);
+    let rtt = |info: &SessionInfo| info.rtt_avg_ms.or(info.rtt_ms);
+    unhealthy(a)
+        .cmp(&unhealthy(b))
+        .then_with(|| a.flapping.cmp(&b.flapping))
+        .then_with(|| match (rtt(a), rtt(b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        })
+        .then_with(|| a.session_id.cmp(&b.session_id))
+}
+
+/// Sessions matching `affinity`, best first.
+pub(crate) fn rank_sessions<'a>(
+    candidates: impl IntoIterator<Item = &'a SessionInfo>,
+    affinity: &SessionAffinity,
+) -> Vec<&'a SessionInfo> {
+    let mut matching: Vec<&SessionInfo> = candidates
+        .into_iter()
+        .filter(|info| affinity.matches(info))
+        .collect();
+    matching.sort_by(|a, b| preference(a, b));
+    matching
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, region: Option<&str>, rtt: Option<f64>) -> SessionInfo {
+        SessionInfo {
+            session_id: id.to_string(),
+            name: None,
+            agent_id: None,
+            shared: None,
+            host: format!("{}:22", id),
+            username: "deploy".to_string(),
+            connected_at: "2024-01-01T00:00:00Z".to_string(),
+            default_timeout_secs: 30,
+            retry_attempts: 0,
+            compression_enabled: false,
+            compression_level: None,
+            keepalive_interval_secs: 30,
+            keepalive_max: 3,
+            algorithms: None,
+            last_health_check: None,
+            healthy: None,
+            health_history: Vec::new(),
+            flapping: false,
+            identity: None,
+            rtt_ms: None,
+            rtt_avg_ms: rtt,
+            region: region.map(str::to_string),
+            datacenter: None,
+            jump_hosts: Vec::new(),
+            rebooting: false,
+            read_only: false,
+            agent_forwarding: false,
+            profile: None,
+            command_timeout_secs: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            stats: Default::default(),
+            labels: Default::default(),
+        }
+    }
+
+    fn affinity(region: Option<&str>, datacenter: Option<&str>) -> SessionAffinity {
+        SessionAffinity {
+            region: region.map(str::to_string),
+            datacenter: datacenter.map(str::to_string),
+        }
+    }
+
+    mod matching {
+        use super::*;
+
+        #[test]
+        fn test_case_insensitive_region() {
+            let info = session("a", Some("eu-west-1"), None);
+            assert!(affinity(Some("EU-West-1"), None).matches(&info));
+            assert!(!affinity(Some("us-east-1"), None).matches(&info));
+        }
+
+        #[test]
+        fn test_untagged_session_only_matches_empty_affinity() {
+            let info = session("a", None, None);
+            assert!(affinity(None, None).matches(&info));
+            assert!(!affinity(Some("eu-west-1"), None).matches(&info));
+        }
+
+        #[test]
+        fn test_datacenter_must_match_too() {
+            let mut info = session("a", Some("eu-west-1"), None);
+            info.datacenter = Some("dub-2".to_string());
+            assert!(affinity(Some("eu-west-1"), Some("dub-2")).matches(&info));
+            assert!(!affinity(Some("eu-west-1"), Some("dub-1")).matches(&info));
+        }
+    }
+
+    mod ranking {
+        use super::*;
+
+        #[test]
+        fn test_lowest_rtt_wins() {
+            let sessions = [
+                session("a", Some("eu"), Some(40.0)),
+                session("b", Some("eu"), Some(12.5)),
+                session("c", Some("us"), Some(1.0)),
+                session("d", Some("eu"), None),
+            ];
+            let ranked: Vec<&str> = rank_sessions(&sessions, &affinity(Some("eu"), None))
+                .iter()
+                .map(|info| info.session_id.as_str())
+                .collect();
+            assert_eq!(ranked, vec!["b", "a", "d"]);
+        }
+
+        #[test]
+        fn test_unhealthy_sessions_last() {
+            let mut fast = session("a", Some("eu"), Some(1.0));
+            fast.healthy = Some(false);
+            let sessions = [fast, session("b", Some("eu"), Some(50.0))];
+            let ranked = rank_sessions(&sessions, &affinity(Some("eu"), None));
+            assert_eq!(ranked[0].session_id, "b");
+        }
+
+        #[test]
+        fn test_flapping_sessions_after_stable() {
+            let mut fast = session("a", Some("eu"), Some(1.0));
+            fast.flapping = true;
+            let sessions = [fast, session("b", Some("eu"), Some(50.0))];
+            let ranked = rank_sessions(&sessions, &affinity(Some("eu"), None));
+            assert_eq!(ranked[0].session_id, "b");
+        }
+    }
+
+    mod labels {
+        use super::*;
+
+        #[test]
+        fn test_normalize() {
+            assert_eq!(
+                normalize_affinity_label("region", Some(" eu-west-1 ".to_string())).unwrap(),
+                Some("eu-west-1".to_string())
+            );
+            assert_eq!(
+                normalize_affinity_label("region", Some("  ".to_string())).unwrap(),
+                None
+            );
+            assert!(normalize_affinity_label("region", Some("x".repeat(65))).is_err());
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! SSH agent forwarding (`ssh_connect` `agent_forwarding`).
+//!
+//! A session connected with `agent_forwarding` sends the
+//! `auth-agent-req@openssh.com` request on the channels of its commands and
+//! shells, like `ssh -A`. sshd then sets `SSH_AUTH_SOCK` for them, and every
+//! use of that socket (an onward `ssh`, `git` over SSH) opens an
+//! `auth-agent@openssh.com` channel back to this server, which [`serve`]
+//! pipes to the local agent at `SSH_AUTH_SOCK`. Keys never leave the agent;
+//! the remote host can only ask it to sign while the session is connected.
+//!
+//! Connections made without the option close agent channels unserved, so a
+//! server cannot reach the agent unasked. Channels the server opens for its
+//! own probes, transfers and helpers never request forwarding.
+
+use russh::Channel;
+use russh::client::Msg;
+use tracing::{debug, warn};
+
+/// Environment variable naming the local agent socket
+const AGENT_SOCK_ENV: &str = "SSH_AUTH_SOCK";
+
+/// The agent socket path given by `value` of [`AGENT_SOCK_ENV`].
+fn agent_socket(value: Option<String>) -> Result<String, String> {
+    value.filter(|path| !path.is_empty()).ok_or_else(|| {
+        format!(
+            "agent_forwarding requires a local ssh-agent ({} is not set)",
+            AGENT_SOCK_ENV
+        )
+    })
+}
+
+/// Path of the local agent socket.
+pub(crate) fn local_agent_socket() -> Result<String, String> {
+    if !cfg!(unix) {
+        return Err("agent_forwarding is only supported on Unix".to_string());
+    }
+    agent_socket(std::env::var(AGENT_SOCK_ENV).ok())
+}
+
+/// Serve an agent channel opened by the remote host: piped to the local
+/// agent when the connection was made with `enabled` forwarding, closed
+/// otherwise.
+pub(crate) fn serve(channel: Channel<Msg>, enabled: bool) {
+    tokio::spawn(async move {
+        if !enabled {
+            warn!("Closing agent channel of a connection without agent_forwarding");
+            let _ = channel.close().await;
+            return;
+        }
+        pipe(channel).await;
+    });
+}
+
+#[cfg(unix)]
+async fn pipe(channel: Channel<Msg>) {
+    let agent = match local_agent_socket() {
+        Ok(path) => tokio::net::UnixStream::connect(&path)
+            .await
+            .map_err(|e| format!("connect {}: {}", path, e)),
+        Err(e) => Err(e),
+    };
+    match agent {
+        Ok(mut agent) => {
+            let mut stream = channel.into_stream();
+            if let Err(e) = tokio::io::copy_bidirectional(&mut stream, &mut agent).await {
+                debug!("Agent channel ended: {}", e);
+            }
+        }
+        Err(e) => {
+            warn!("Cannot reach the local ssh-agent: {}", e);
+            let _ = channel.close().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn pipe(channel: Channel<Msg>) {
+    debug!("Agent forwarding is only supported on Unix");
+    let _ = channel.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_socket_required() {
+        assert_eq!(
+            agent_socket(Some("/tmp/ssh-XXXX/agent.1".to_string())).unwrap(),
+            "/tmp/ssh-XXXX/agent.1"
+        );
+        assert!(agent_socket(Some(String::new())).is_err());
+        assert!(
+            agent_socket(None)
+                .unwrap_err()
+                .contains("SSH_AUTH_SOCK is not set")
+        );
+    }
+}
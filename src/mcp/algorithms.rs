@@ -0,0 +1,204 @@
+//! SSH algorithm preferences (`ssh_connect` `ciphers`, `kex_algorithms`,
+//! `host_key_algorithms`) and the algorithms a connection negotiated.
+//!
+//! A preference list replaces what the client offers for that kind, in the
+//! given order, so it can both allow a legacy algorithm that is off by
+//! default (e.g. `aes128-cbc` or `diffie-hellman-group14-sha1` for an old
+//! appliance) and restrict the offer to an approved set (e.g. FIPS). Names are
+//! checked against what the SSH library implements; an unknown name fails the
+//! call with the supported names listed. Lists that are not given keep the
+//! library's defaults. The `ext-info-c` and strict key exchange markers are
+//! always appended to a `kex_algorithms` list, so server signature algorithm
+//! negotiation and the Terrapin mitigation stay on.
+//!
+//! The algorithms of the last key exchange are reported as `algorithms` of
+//! each session.
+
+use std::borrow::Cow;
+
+use russh::keys::Algorithm;
+use russh::keys::key::ALL_KEY_TYPES;
+use russh::{Names, Preferred, cipher, kex};
+
+use super::types::NegotiatedAlgorithms;
+
+/// Key exchange markers kept at the end of a custom `kex_algorithms` list
+const KEX_EXTENSIONS: [kex::Name; 2] = [
+    kex::EXTENSION_SUPPORT_AS_CLIENT,
+    kex::EXTENSION_OPENSSH_STRICT_KEX_AS_CLIENT,
+];
+
+/// Algorithm lists requested for a connection; empty lists keep the defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct AlgorithmPreferences {
+    pub kex: Vec<kex::Name>,
+    pub host_key: Vec<Algorithm>,
+    pub cipher: Vec<cipher::Name>,
+}
+
+impl AlgorithmPreferences {
+    /// Check the `ssh_connect` lists against the supported algorithms.
+    pub(crate) fn parse(
+        ciphers: Option<&[String]>,
+        kex_algorithms: Option<&[String]>,
+        host_key_algorithms: Option<&[String]>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            kex: parse_list("kex_algorithms", kex_algorithms, &supported_kex())?,
+            host_key: parse_list(
+                "host_key_algorithms",
+                host_key_algorithms,
+                &supported_host_keys(),
+            )?,
+            cipher: parse_list("ciphers", ciphers, &supported_ciphers())?,
+        })
+    }
+
+    /// Whether no list was given.
+    pub(crate) fn is_default(&self) -> bool {
+        self.kex.is_empty() && self.host_key.is_empty() && self.cipher.is_empty()
+    }
+
+    /// `preferred` with the requested lists in place of its own.
+    pub(crate) fn apply(&self, mut preferred: Preferred) -> Preferred {
+        if !self.kex.is_empty() {
+            let mut kex = self.kex.clone();
+            kex.extend(KEX_EXTENSIONS);
+            preferred.kex = Cow::Owned(kex);
+        }
+        if !self.host_key.is_empty() {
+            preferred.key = Cow::Owned(self.host_key.clone());
+        }
+        if !self.cipher.is_empty() {
+            preferred.cipher = Cow::Owned(self.cipher.clone());
+        }
+        preferred
+    }
+}
+
+/// Key exchange algorithms that can be requested.
+fn supported_kex() -> Vec<(&'static str, kex::Name)> {
+    kex::ALL_KEX_ALGORITHMS
+        .iter()
+        .filter(|name| **name != &kex::NONE)
+        .map(|name: &&'static kex::Name| ((*name).as_ref(), **name))
+        .collect()
+}
+
+/// Host key algorithms that can be requested.
+fn supported_host_keys() -> Vec<(&'static str, Algorithm)> {
+    ALL_KEY_TYPES
+        .iter()
+        .map(|algorithm| (algorithm.as_str(), algorithm.clone()))
+        .collect()
+}
+
+/// Ciphers that can be requested.
+fn supported_ciphers() -> Vec<(&'static str, cipher::Name)> {
+    cipher::ALL_CIPHERS
+        .iter()
+        .filter(|name| **name != &cipher::NONE && **name != &cipher::CLEAR)
+        .map(|name: &&'static cipher::Name| ((*name).as_ref(), **name))
+        .collect()
+}
+
+/// Resolve `names` of parameter `param` in order; duplicates are dropped.
+fn parse_list<T: Clone + PartialEq>(
+    param: &str,
+    names: Option<&[String]>,
+    supported: &[(&'static str, T)],
+) -> Result<Vec<T>, String> {
+    let Some(names) = names else {
+        return Ok(Vec::new());
+    };
+    if names.is_empty() {
+        return Err(format!("{} must not be empty", param));
+    }
+    let mut resolved = Vec::new();
+    for name in names {
+        let name = name.trim();
+        let Some((_, algorithm)) = supported.iter().find(|(known, _)| *known == name) else {
+            let known: Vec<&str> = supported.iter().map(|(known, _)| *known).collect();
+            return Err(format!(
+                "Unsupported {} entry '{}'; supported: {}",
+                param,
+                name,
+                known.join(", ")
+            ));
+        };
+        if !resolved.contains(algorithm) {
+            resolved.push(algorithm.clone());
+        }
+    }
+    Ok(resolved)
+}
+
+/// The algorithms of a completed key exchange.
+pub(crate) fn negotiated(names: &Names) -> NegotiatedAlgorithms {
+    NegotiatedAlgorithms {
+        kex: names.kex.as_ref().to_string(),
+        host_key: names.key.as_str().to_string(),
+        cipher: names.cipher.as_ref().to_string(),
+        mac: names.client_mac.as_ref().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_keeps_order() {
+        let ciphers = list(&["aes128-cbc", "aes256-ctr", "aes128-cbc"]);
+        let kex = list(&["diffie-hellman-group14-sha1"]);
+        let host_keys = list(&["ssh-rsa", "rsa-sha2-256"]);
+        let preferences =
+            AlgorithmPreferences::parse(Some(&ciphers), Some(&kex), Some(&host_keys)).unwrap();
+        assert_eq!(
+            preferences.cipher,
+            vec![cipher::AES_128_CBC, cipher::AES_256_CTR]
+        );
+        assert_eq!(preferences.kex, vec![kex::DH_G14_SHA1]);
+        assert_eq!(preferences.host_key[0], Algorithm::Rsa { hash: None });
+        assert!(!preferences.is_default());
+
+        let preferred = preferences.apply(Preferred::default());
+        assert_eq!(
+            preferred.kex.as_ref(),
+            &[
+                kex::DH_G14_SHA1,
+                kex::EXTENSION_SUPPORT_AS_CLIENT,
+                kex::EXTENSION_OPENSSH_STRICT_KEX_AS_CLIENT
+            ]
+        );
+        assert_eq!(preferred.cipher.as_ref(), preferences.cipher.as_slice());
+        // Lists that were not given keep the defaults
+        let preferences = AlgorithmPreferences::parse(Some(&ciphers), None, None).unwrap();
+        let preferred = preferences.apply(Preferred::default());
+        assert_eq!(preferred.kex, Preferred::default().kex);
+        assert!(
+            AlgorithmPreferences::parse(None, None, None)
+                .unwrap()
+                .is_default()
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_and_empty() {
+        let error = AlgorithmPreferences::parse(Some(&list(&["rot13"])), None, None).unwrap_err();
+        assert!(
+            error.contains("Unsupported ciphers entry 'rot13'"),
+            "{}",
+            error
+        );
+        assert!(error.contains("aes256-gcm@openssh.com"), "{}", error);
+        // The null cipher is never accepted
+        assert!(AlgorithmPreferences::parse(Some(&list(&["none"])), None, None).is_err());
+        assert!(AlgorithmPreferences::parse(None, Some(&list(&["none"])), None).is_err());
+        assert!(AlgorithmPreferences::parse(None, None, Some(&[])).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! MCP tool annotations (safety hints).
+//!
+//! MCP clients that honor annotations use these hints to decide which tools may
+//! run without confirmation. `poem-mcpserver` does not model annotations, so the
+//! transport layer injects them into `tools/list` results using this table.
+//!
+//! | Hint | Meaning |
+//! |------|---------|
+//! | `readOnlyHint` | The tool does not modify the remote host or server state |
+//! | `destructiveHint` | The tool may perform destructive or irreversible changes |
+//! | `idempotentHint` | Repeating the call with the same arguments has no additional effect |
+//! | `openWorldHint` | The tool interacts with external systems (remote hosts) |
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Safety hints attached to a tool definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    pub read_only_hint: bool,
+    pub destructive_hint: bool,
+    pub idempotent_hint: bool,
+    pub open_world_hint: bool,
+}
+
+impl ToolAnnotations {
+    /// Tool that only inspects server-side state.
+    const READ_ONLY: Self = Self {
+        read_only_hint: true,
+        destructive_hint: false,
+        idempotent_hint: true,
+        open_world_hint: false,
+    };
+
+    /// Tool that creates new state (connections, channels) without destroying anything.
+    const ADDITIVE: Self = Self {
+        read_only_hint: false,
+        destructive_hint: false,
+        idempotent_hint: false,
+        open_world_hint: true,
+    };
+
+    /// Tool that runs arbitrary input on the remote host.
+    const REMOTE_WRITE: Self = Self {
+        read_only_hint: false,
+        destructive_hint: true,
+        idempotent_hint: false,
+        open_world_hint: true,
+    };
+
+    /// Tool that only changes server-side bookkeeping; repeating it has no further effect.
+    const SERVER_STATE: Self = Self {
+        read_only_hint: false,
+        destructive_hint: false,
+        idempotent_hint: true,
+        open_world_hint: false,
+    };
+
+    /// Tool that tears down existing state; repeating it has no further effect.
+    const TEARDOWN: Self = Self {
+        read_only_hint: false,
+        destructive_hint: true,
+        idempotent_hint: true,
+        open_world_hint: false,
+    };
+}
+
+/// Get the annotations for a tool by its (unprefixed) name.
+pub fn annotations_for(tool_name: &str) -> Option<ToolAnnotations> {
+    let annotations = match tool_name {
+        "ssh_list_sessions"
+        | "ssh_select_session"
+        | "ssh_capabilities"
+        | "ssh_check_config"
+        | "ssh_list_managed_hosts"
+        | "ssh_list_profiles"
+        | "ssh_usage"
+        | "ssh_get_audit_log"
+        | "ssh_list_forwards"
+        | "ssh_list_reverse_forwards"
+        | "ssh_list_commands"
+        | "ssh_get_command_output"
+        | "ssh_tail_read"
+        | "ssh_wait_commands" => ToolAnnotations::READ_ONLY,
+        // Reading consumes the shell buffer (clear=true); pager="continue"/"quit" only
+        // answers a pager prompt and is opt-in. Expect only waits and consumes.
+        "ssh_shell_read" | "ssh_shell_expect" => ToolAnnotations {
+            idempotent_hint: false,
+            ..ToolAnnotations::READ_ONLY
+        },
+        // Only watches shell output; each call starts another stream
+        "ssh_shell_subscribe" => ToolAnnotations {
+            idempotent_hint: false,
+            ..ToolAnnotations::READ_ONLY
+        },
+        // Only reads the file; each call starts another tail
+        "ssh_tail_file" => ToolAnnotations {
+            idempotent_hint: false,
+            open_world_hint: true,
+            ..ToolAnnotations::READ_ONLY
+        },
+        // Runs a listing command on the remote host without changing it
+        "ssh_tmux_list"
+        | "ssh_check_port"
+        | "ssh_check_privileges"
+        | "ssh_gather_facts"
+        | "ssh_readlink"
+        | "ssh_glob"
+        | "ssh_list_dir"
+        | "ssh_manifest" => ToolAnnotations {
+            open_world_hint: true,
+            ..ToolAnnotations::READ_ONLY
+        },
+        "ssh_connect"
+        | "ssh_connect_many"
+        | "ssh_forward"
+        | "ssh_reverse_forward"
+        | "ssh_shell_open"
+        | "ssh_tmux_attach"
+        | "ssh_open_console" => ToolAnnotations::ADDITIVE,
+        "ssh_execute"
+        | "ssh_execute_sync"
+        | "ssh_execute_parsed"
+        | "ssh_execute_dag"
+        | "ssh_execute_batch"
+        | "ssh_run_helper"
+        | "ssh_shell_write"
+        | "ssh_shell_signal"
+        | "ssh_command_signal"
+        | "ssh_shell_switch_user"
+        | "ssh_reboot_and_wait"
+        | "ssh_patch_file" => ToolAnnotations::REMOTE_WRITE,
+        // force=true replaces whatever was at link_path; repeating the same link is a no-op
+        "ssh_symlink" => ToolAnnotations {
+            idempotent_hint: true,
+            ..ToolAnnotations::REMOTE_WRITE
+        },
+        // Replaces the file; writing the same content again converges
+        "ssh_write_file" => ToolAnnotations {
+            idempotent_hint: true,
+            ..ToolAnnotations::REMOTE_WRITE
+        },
+        // Overwrites files under remote_path; re-uploading the same tree converges
+        "ssh_upload_dir" => ToolAnnotations {
+            idempotent_hint: true,
+            ..ToolAnnotations::REMOTE_WRITE
+        },
+        // Replaces path_b; copying the same file again converges
+        "ssh_copy_between" => ToolAnnotations {
+            idempotent_hint: true,
+            ..ToolAnnotations::REMOTE_WRITE
+        },
+        // Only invalidates cached sudo credentials; repeating the check converges
+        "ssh_verify_privilege_drop" => ToolAnnotations {
+            read_only_hint: false,
+            destructive_hint: false,
+            idempotent_hint: true,
+            open_world_hint: true,
+        },
+        // Reads the remote host; only writes the archive on the MCP server
+        "ssh_collect" => ToolAnnotations::ADDITIVE,
+        "ssh_lock_session"
+        | "ssh_unlock_session"
+        | "ssh_update_session"
+        | "ssh_shell_unsubscribe" => ToolAnnotations::SERVER_STATE,
+        "ssh_disconnect"
+        | "ssh_disconnect_agent"
+        | "ssh_cancel_command"
+        | "ssh_cancel_session_commands"
+        | "ssh_cancel_forward"
+        | "ssh_cancel_reverse_forward"
+        | "ssh_shell_close" => ToolAnnotations::TEARDOWN,
+        _ => return None,
+    };
+    Some(annotations)
+}
+
+/// Add an `annotations` object to every known tool in a `tools/list` result.
+pub fn annotate_tools_list(result: &mut Value) {
+    let Some(tools) = result.get_mut("tools").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    for tool in tools {
+        let Some(name) = tool.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        if let Some(annotations) = annotations_for(name)
+            && let Ok(value) = serde_json::to_value(annotations)
+            && let Some(object) = tool.as_object_mut()
+        {
+            object.insert("annotations".to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::McpSSHCommands;
+    use poem_mcpserver::tool::Tools;
+    use serde_json::json;
+
+    mod annotations_table {
+        use super::*;
+
+        #[test]
+        fn test_every_tool_is_annotated() {
+            for tool in McpSSHCommands::list() {
+                assert!(
+                    annotations_for(tool.name).is_some(),
+                    "missing annotations for {}",
+                    tool.name
+                );
+            }
+        }
+
+        #[test]
+        fn test_list_sessions_is_read_only() {
+            let annotations = annotations_for("ssh_list_sessions").unwrap();
+            assert!(annotations.read_only_hint);
+            assert!(!annotations.destructive_hint);
+        }
+
+        #[test]
+        fn test_execute_is_destructive() {
+            let annotations = annotations_for("ssh_execute").unwrap();
+            assert!(!annotations.read_only_hint);
+            assert!(annotations.destructive_hint);
+            assert!(annotations.open_world_hint);
+        }
+
+        #[test]
+        fn test_disconnect_is_idempotent_teardown() {
+            let annotations = annotations_for("ssh_disconnect").unwrap();
+            assert!(annotations.destructive_hint);
+            assert!(annotations.idempotent_hint);
+        }
+
+        #[test]
+        fn test_unknown_tool() {
+            assert!(annotations_for("not_a_tool").is_none());
+        }
+    }
+
+    mod annotate_tools_list {
+        use super::*;
+
+        #[test]
+        fn test_injects_camel_case_hints() {
+            let mut result = json!({
+                "tools": [
+                    {"name": "ssh_list_sessions", "description": "", "inputSchema": {}},
+                    {"name": "unknown", "description": "", "inputSchema": {}}
+                ]
+            });
+
+            annotate_tools_list(&mut result);
+
+            let annotations = &result["tools"][0]["annotations"];
+            assert_eq!(annotations["readOnlyHint"], true);
+            assert_eq!(annotations["destructiveHint"], false);
+            assert_eq!(annotations["idempotentHint"], true);
+            assert_eq!(annotations["openWorldHint"], false);
+            assert!(result["tools"][1].get("annotations").is_none());
+        }
+
+        #[test]
+        fn test_ignores_non_list_results() {
+            let mut result = json!({"content": []});
+            annotate_tools_list(&mut result);
+            assert_eq!(result, json!({"content": []}));
+        }
+    }
+}
@@ -13,6 +13,16 @@
 //!
 //! - Maximum 100 concurrent async commands per session
 //! - Completed commands are automatically cleaned up when session disconnects
+//! - stdout and stderr each hold at most `SSH_MAX_OUTPUT_BYTES` (default
+//!   64 MiB, `max_output_bytes` per command); past the cap the oldest output
+//!   is evicted (`ring`) or new output discarded (`head`), and the lost bytes
+//!   are reported as `dropped_bytes`
+//!
+//! # Labels
+//!
+//! A command may carry a short free-form label describing its purpose
+//! (`"deploy v1.2.3 step 2/5"`). `ssh_list_commands` searches labels by
+//! case-insensitive substring, which keeps a busy command list navigable.
 
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
@@ -20,13 +30,81 @@ use std::sync::atomic::AtomicBool;
 use tokio::sync::{Mutex, watch};
 use tokio_util::sync::CancellationToken;
 
-use super::types::{AsyncCommandInfo, AsyncCommandStatus};
+use super::compress::OutputFormat;
+use super::signal::SignalSender;
+use super::types::{AsyncCommandInfo, AsyncCommandStatus, PatternMatch};
+
+/// What happens to output past the buffer cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputOverflow {
+    /// Keep the newest output, evicting the oldest
+    #[default]
+    Ring,
+    /// Keep the first output, discarding what follows
+    Head,
+}
+
+impl OutputOverflow {
+    /// Parse `ring` or `head`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ring" | "tail" => Ok(Self::Ring),
+            "head" | "truncate" => Ok(Self::Head),
+            other => Err(format!(
+                "Unknown output overflow mode '{}'. Use 'ring' or 'head'",
+                other
+            )),
+        }
+    }
+}
+
+/// Cap on each output stream of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// Bytes kept per stream (stdout and stderr each)
+    pub max_bytes: usize,
+    pub overflow: OutputOverflow,
+}
+
+impl OutputLimit {
+    /// Append `data` to `buffer` within the cap, draining `data`; returns the
+    /// bytes dropped.
+    fn append(&self, buffer: &mut Vec<u8>, data: &mut Vec<u8>) -> u64 {
+        match self.overflow {
+            OutputOverflow::Ring => {
+                buffer.append(data);
+                if buffer.len() <= self.max_bytes {
+                    return 0;
+                }
+                // Evict in blocks of an eighth of the cap so a busy stream
+                // does not shift the whole buffer on every chunk
+                let evict = (buffer.len() - self.max_bytes)
+                    .max(self.max_bytes / 8)
+                    .min(buffer.len());
+                buffer.drain(..evict);
+                evict as u64
+            }
+            OutputOverflow::Head => {
+                let kept = self.max_bytes.saturating_sub(buffer.len()).min(data.len());
+                buffer.extend_from_slice(&data[..kept]);
+                let dropped = (data.len() - kept) as u64;
+                data.clear();
+                dropped
+            }
+        }
+    }
+}
 
 /// Output buffer for collecting command output
 #[derive(Debug, Default)]
 pub struct OutputBuffer {
     pub stdout: Vec<u8>,
     pub stderr: Vec<u8>,
+    /// Bytes of stdout lost to the cap
+    pub dropped_stdout: u64,
+    /// Bytes of stderr lost to the cap
+    pub dropped_stderr: u64,
+    limit: Option<OutputLimit>,
 }
 
 impl OutputBuffer {
@@ -37,6 +115,53 @@ impl OutputBuffer {
         Self {
             stdout: Vec::with_capacity(stdout_cap),
             stderr: Vec::with_capacity(stderr_cap),
+            ..Default::default()
+        }
+    }
+
+    /// Cap each stream at `limit` (`None`: unlimited).
+    pub fn with_limit(mut self, limit: Option<OutputLimit>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Append stdout output, draining `data`.
+    pub fn push_stdout(&mut self, data: &mut Vec<u8>) {
+        match self.limit {
+            Some(limit) => self.dropped_stdout += limit.append(&mut self.stdout, data),
+            None => self.stdout.append(data),
+        }
+    }
+
+    /// Append stderr output, draining `data`.
+    pub fn push_stderr(&mut self, data: &mut Vec<u8>) {
+        match self.limit {
+            Some(limit) => self.dropped_stderr += limit.append(&mut self.stderr, data),
+            None => self.stderr.append(data),
+        }
+    }
+
+    /// Bytes collected so far on stdout and stderr together.
+    pub fn total_len(&self) -> usize {
+        self.stdout.len() + self.stderr.len()
+    }
+
+    /// Bytes lost to the cap on both streams.
+    pub fn dropped_bytes(&self) -> u64 {
+        self.dropped_stdout + self.dropped_stderr
+    }
+
+    /// Bytes received so far, including those lost to the cap.
+    pub fn total_received(&self) -> usize {
+        self.total_len() + self.dropped_bytes() as usize
+    }
+
+    /// Stream positions of the first held stdout and stderr bytes: the bytes
+    /// evicted from the front in `ring` mode.
+    pub fn stream_starts(&self) -> (u64, u64) {
+        match self.limit.map(|limit| limit.overflow) {
+            Some(OutputOverflow::Ring) => (self.dropped_stdout, self.dropped_stderr),
+            _ => (0, 0),
         }
     }
 }
@@ -47,6 +172,8 @@ pub struct RunningCommand {
     pub info: AsyncCommandInfo,
     /// Token to cancel the command
     pub cancel_token: CancellationToken,
+    /// Hands `ssh_command_signal` requests to the command's background task
+    pub signal_tx: SignalSender,
     /// Receiver for status updates
     pub status_rx: watch::Receiver<AsyncCommandStatus>,
     /// Sender for status updates (kept alive to prevent channel closure)
@@ -60,15 +187,88 @@ pub struct RunningCommand {
     pub error: Arc<Mutex<Option<String>>>,
     /// Whether the command timed out
     pub timed_out: Arc<AtomicBool>,
+    /// Output pattern match that settled the status, if any
+    pub pattern_match: Arc<Mutex<Option<PatternMatch>>>,
+    /// How output is sent unless a read asks otherwise (`output_encoding`)
+    pub output_format: OutputFormat,
+}
+
+impl RunningCommand {
+    /// Whether the command is still running: its status is `running`, or it
+    /// kept running after an output pattern settled its status.
+    pub async fn is_active(&self) -> bool {
+        *self.status_rx.borrow() == AsyncCommandStatus::Running
+            || self
+                .pattern_match
+                .lock()
+                .await
+                .as_ref()
+                .is_some_and(|found| found.running)
+    }
 }
 
 /// Maximum number of concurrent async commands (multiplexed channels) per session
 pub const MAX_ASYNC_COMMANDS_PER_SESSION: usize = 100;
 
+/// Maximum length of a command label, in characters
+pub const MAX_COMMAND_LABEL_LEN: usize = 200;
+
+/// Trim a command label; blank labels become `None`.
+///
+/// Returns an error when the label is longer than [`MAX_COMMAND_LABEL_LEN`].
+pub fn normalize_label(label: Option<String>) -> Result<Option<String>, String> {
+    let Some(label) = label else {
+        return Ok(None);
+    };
+    let label = label.trim();
+    if label.chars().count() > MAX_COMMAND_LABEL_LEN {
+        return Err(format!(
+            "label is longer than {} characters",
+            MAX_COMMAND_LABEL_LEN
+        ));
+    }
+    Ok((!label.is_empty()).then(|| label.to_string()))
+}
+
+/// Whether `label` contains `query`, ignoring case.
+pub fn label_matches(label: Option<&str>, query: &str) -> bool {
+    label.is_some_and(|label| label.to_lowercase().contains(&query.to_lowercase()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    mod labels {
+        use super::*;
+
+        #[test]
+        fn test_normalize_trims_and_drops_blank() {
+            assert_eq!(
+                normalize_label(Some("  deploy v1.2.3 step 2/5 ".to_string())),
+                Ok(Some("deploy v1.2.3 step 2/5".to_string()))
+            );
+            assert_eq!(normalize_label(Some("   ".to_string())), Ok(None));
+            assert_eq!(normalize_label(None), Ok(None));
+        }
+
+        #[test]
+        fn test_normalize_rejects_long_label() {
+            let label = "x".repeat(MAX_COMMAND_LABEL_LEN + 1);
+            assert!(normalize_label(Some(label)).is_err());
+            let label = "x".repeat(MAX_COMMAND_LABEL_LEN);
+            assert!(normalize_label(Some(label)).is_ok());
+        }
+
+        #[test]
+        fn test_substring_match_ignores_case() {
+            assert!(label_matches(Some("Deploy v1.2.3 step 2/5"), "deploy"));
+            assert!(label_matches(Some("Deploy v1.2.3 step 2/5"), "STEP 2"));
+            assert!(!label_matches(Some("backup"), "deploy"));
+            assert!(!label_matches(None, "deploy"));
+        }
+    }
+
     mod output_buffer {
         use super::*;
 
@@ -79,6 +279,14 @@ mod tests {
             assert!(buffer.stderr.is_empty());
         }
 
+        #[test]
+        fn test_total_len() {
+            let mut buffer = OutputBuffer::default();
+            buffer.stdout.extend_from_slice(b"hello");
+            buffer.stderr.extend_from_slice(b"warn");
+            assert_eq!(buffer.total_len(), 9);
+        }
+
         #[test]
         fn test_with_capacity() {
             let buffer = OutputBuffer::with_capacity(4096, 1024);
@@ -105,6 +313,65 @@ mod tests {
         }
     }
 
+    mod output_limit {
+        use super::*;
+
+        fn capped(max_bytes: usize, overflow: OutputOverflow) -> OutputBuffer {
+            OutputBuffer::default().with_limit(Some(OutputLimit {
+                max_bytes,
+                overflow,
+            }))
+        }
+
+        #[test]
+        fn test_ring_keeps_newest() {
+            let mut buffer = capped(16, OutputOverflow::Ring);
+            buffer.push_stdout(&mut b"0123456789".to_vec());
+            buffer.push_stdout(&mut b"abcdefghij".to_vec());
+            assert_eq!(buffer.stdout, b"456789abcdefghij");
+            assert_eq!(buffer.dropped_stdout, 4);
+            assert_eq!(buffer.stream_starts(), (4, 0));
+            assert_eq!(buffer.total_received(), 20);
+        }
+
+        #[test]
+        fn test_ring_evicts_in_blocks() {
+            let mut buffer = capped(64, OutputOverflow::Ring);
+            buffer.push_stdout(&mut vec![b'x'; 64]);
+            buffer.push_stdout(&mut vec![b'y'; 1]);
+            // One byte over evicts an eighth of the cap
+            assert_eq!(buffer.stdout.len(), 57);
+            assert_eq!(buffer.dropped_stdout, 8);
+        }
+
+        #[test]
+        fn test_head_keeps_first() {
+            let mut buffer = capped(8, OutputOverflow::Head);
+            let mut data = b"0123456789".to_vec();
+            buffer.push_stderr(&mut data);
+            assert!(data.is_empty());
+            buffer.push_stderr(&mut b"abc".to_vec());
+            assert_eq!(buffer.stderr, b"01234567");
+            assert_eq!(buffer.dropped_stderr, 5);
+            assert_eq!(buffer.stream_starts(), (0, 0));
+        }
+
+        #[test]
+        fn test_unlimited_by_default() {
+            let mut buffer = OutputBuffer::default();
+            buffer.push_stdout(&mut vec![b'x'; 1 << 20]);
+            assert_eq!(buffer.dropped_bytes(), 0);
+            assert_eq!(buffer.stdout.len(), 1 << 20);
+        }
+
+        #[test]
+        fn test_parse_overflow() {
+            assert_eq!(OutputOverflow::parse("RING"), Ok(OutputOverflow::Ring));
+            assert_eq!(OutputOverflow::parse("head"), Ok(OutputOverflow::Head));
+            assert!(OutputOverflow::parse("drop").is_err());
+        }
+    }
+
     mod constants {
         use super::*;
 
@@ -116,9 +383,9 @@ mod tests {
         #[test]
         fn test_max_commands_is_reasonable() {
             // Should support at least 10 concurrent commands
-            assert!(MAX_ASYNC_COMMANDS_PER_SESSION >= 10);
+            const { assert!(MAX_ASYNC_COMMANDS_PER_SESSION >= 10) };
             // Should not exceed SSH multiplexing practical limits
-            assert!(MAX_ASYNC_COMMANDS_PER_SESSION <= 256);
+            const { assert!(MAX_ASYNC_COMMANDS_PER_SESSION <= 256) };
         }
     }
 
@@ -0,0 +1,398 @@
+//! Audit events.
+//!
+//! Tools that change remote state or open access (connect, command execution,
+//! shells, transfers, forwards) record an [`AuditEvent`]. Events are handed to
+//! every configured exporter (see the `siem` module) without blocking the tool
+//! call: each exporter has a bounded queue, and events that do not fit are
+//! dropped with a warning rather than slowing down the agent.
+//!
+//! With no `[[audit.exporters]]` configured, recording is a no-op.
+//!
+//! A `file` exporter writing `json` is an append-only JSONL log on local disk;
+//! `ssh_get_audit_log` reads the first one back with filters (see
+//! [`read_log`]).
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, FixedOffset};
+use once_cell::sync::Lazy;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use super::config::{AuditExporterKind, AuditFormat, server_config};
+use super::siem::spawn_exporter;
+use super::types::SessionInfo;
+pub(crate) use super::types::{AuditEvent, AuditOutcome};
+
+/// Exporter queues, started on first use.
+static EXPORTERS: Lazy<Vec<mpsc::Sender<AuditEvent>>> = Lazy::new(|| {
+    server_config()
+        .audit
+        .exporters
+        .iter()
+        .filter_map(|config| {
+            spawn_exporter(config)
+                .map_err(|e| error!("Audit exporter disabled: {}", e))
+                .ok()
+        })
+        .collect()
+});
+
+/// Events dropped because an exporter queue was full
+static DROPPED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+impl AuditEvent {
+    /// Start a successful event for `action`.
+    pub fn new(action: &str) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            action: action.to_string(),
+            outcome: AuditOutcome::Success,
+            agent_id: None,
+            session_id: None,
+            host: None,
+            username: None,
+            target: None,
+            command_id: None,
+            exit_code: None,
+            error: None,
+        }
+    }
+
+    /// Fill session, host and user from a session; the caller (if any) is the agent.
+    pub fn session(mut self, info: &SessionInfo, caller: Option<&str>) -> Self {
+        self.session_id = Some(info.session_id.clone());
+        self.host = Some(info.host.clone());
+        self.username = Some(info.username.clone());
+        self.agent_id = caller.map(str::to_string).or_else(|| info.agent_id.clone());
+        self
+    }
+
+    pub fn agent(mut self, agent_id: Option<&str>) -> Self {
+        self.agent_id = agent_id.map(str::to_string);
+        self
+    }
+
+    pub fn host(mut self, host: &str, username: &str) -> Self {
+        self.host = Some(host.to_string());
+        self.username = Some(username.to_string());
+        self
+    }
+
+    pub fn session_id(mut self, session_id: &str) -> Self {
+        self.session_id = Some(session_id.to_string());
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn command_id(mut self, command_id: &str) -> Self {
+        self.command_id = Some(command_id.to_string());
+        self
+    }
+
+    pub fn exit_code(mut self, exit_code: Option<i32>) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    /// Mark the event as failed with `error`.
+    pub fn failed(mut self, error: impl ToString) -> Self {
+        self.outcome = AuditOutcome::Failure;
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+/// Hand an event to every exporter without waiting.
+pub(crate) fn record(event: AuditEvent) {
+    let Some((last, rest)) = EXPORTERS.split_last() else {
+        return;
+    };
+    for exporter in rest {
+        enqueue(exporter, event.clone());
+    }
+    enqueue(last, event);
+}
+
+fn enqueue(exporter: &mpsc::Sender<AuditEvent>, event: AuditEvent) {
+    if exporter.try_send(event).is_err() {
+        let dropped = DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed) + 1;
+        // Log the first drop and then every 1000th to avoid flooding the log
+        if dropped == 1 || dropped.is_multiple_of(1000) {
+            warn!(
+                "Audit exporter queue full or closed; {} event(s) dropped so far",
+                dropped
+            );
+        }
+    }
+}
+
+/// Events returned by `ssh_get_audit_log` when no limit is given
+pub(crate) const DEFAULT_LOG_LIMIT: usize = 100;
+
+/// Most events one `ssh_get_audit_log` call returns
+pub(crate) const MAX_LOG_LIMIT: usize = 1000;
+
+/// Filters of `ssh_get_audit_log`; unset fields match every event.
+#[derive(Debug, Default)]
+pub(crate) struct AuditQuery {
+    pub agent_id: Option<String>,
+    pub session_id: Option<String>,
+    pub action: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    /// Only events at or after this time
+    pub since: Option<DateTime<FixedOffset>>,
+}
+
+impl AuditQuery {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        let same = |filter: &Option<String>, value: &Option<String>| {
+            filter.is_none() || filter.as_deref() == value.as_deref()
+        };
+        same(&self.agent_id, &event.agent_id)
+            && same(&self.session_id, &event.session_id)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| *action == event.action)
+            && self.outcome.is_none_or(|outcome| outcome == event.outcome)
+            && self.since.is_none_or(|since| {
+                DateTime::parse_from_rfc3339(&event.timestamp).is_ok_and(|time| time >= since)
+            })
+    }
+}
+
+/// Audit target of `ssh_shell_write`: the input text, or only its size with
+/// `[audit] redact_shell_input`.
+pub(crate) fn shell_input_target(shell_id: &str, input: &str, redact: bool) -> String {
+    if redact {
+        format!("shell {} ({} bytes)", shell_id, input.len())
+    } else {
+        format!("shell {}: {}", shell_id, input)
+    }
+}
+
+/// The log `ssh_get_audit_log` reads: the path of the first `file` exporter
+/// writing `json`.
+pub(crate) fn log_path() -> Option<&'static str> {
+    server_config()
+        .audit
+        .exporters
+        .iter()
+        .find(|exporter| {
+            exporter.kind == AuditExporterKind::File && exporter.format == AuditFormat::Json
+        })
+        .and_then(|exporter| exporter.path.as_deref())
+}
+
+/// Read the newest `limit` events of the JSONL log at `path` that match
+/// `query`, newest first, and whether older matches were left out.
+///
+/// Lines that are not events (e.g. one still being written) are skipped.
+pub(crate) async fn read_log(
+    path: &str,
+    query: &AuditQuery,
+    limit: usize,
+) -> Result<(Vec<AuditEvent>, bool), String> {
+    let file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        // Nothing recorded yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), false)),
+        Err(e) => return Err(format!("Cannot open audit log {}: {}", path, e)),
+    };
+    let mut lines = BufReader::new(file).lines();
+    let mut newest = VecDeque::with_capacity(limit.min(MAX_LOG_LIMIT));
+    let mut truncated = false;
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .map_err(|e| format!("Cannot read audit log {}: {}", path, e))?
+    {
+        let Ok(event) = serde_json::from_str::<AuditEvent>(&line) else {
+            continue;
+        };
+        if !query.matches(&event) {
+            continue;
+        }
+        if newest.len() == limit {
+            newest.pop_front();
+            truncated = true;
+        }
+        if limit > 0 {
+            newest.push_back(event);
+        }
+    }
+    Ok((newest.into_iter().rev().collect(), truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod events {
+        use super::*;
+
+        #[test]
+        fn test_builder_and_serialization() {
+            let event = AuditEvent::new("ssh_execute")
+                .agent(Some("deploy"))
+                .host("web-1:22", "root")
+                .session_id("s-1")
+                .target("systemctl restart nginx");
+
+            let json = serde_json::to_value(&event).unwrap();
+            assert_eq!(json["action"], "ssh_execute");
+            assert_eq!(json["outcome"], "success");
+            assert_eq!(json["agent_id"], "deploy");
+            assert_eq!(json["host"], "web-1:22");
+            assert_eq!(json["target"], "systemctl restart nginx");
+            assert!(json.get("error").is_none());
+        }
+
+        #[test]
+        fn test_shell_input_target() {
+            assert_eq!(
+                shell_input_target("sh-1", "systemctl stop nginx\n", false),
+                "shell sh-1: systemctl stop nginx\n"
+            );
+            assert_eq!(
+                shell_input_target("sh-1", "hunter2\n", true),
+                "shell sh-1 (8 bytes)"
+            );
+        }
+
+        #[test]
+        fn test_failed_event() {
+            let event = AuditEvent::new("ssh_connect").failed("Authentication failed");
+            assert_eq!(event.outcome, AuditOutcome::Failure);
+            assert_eq!(event.error.as_deref(), Some("Authentication failed"));
+        }
+
+        #[test]
+        fn test_record_without_exporters_is_noop() {
+            record(AuditEvent::new("ssh_execute"));
+        }
+
+        #[test]
+        fn test_round_trip_with_exit_code() {
+            let event = AuditEvent::new("command_finished")
+                .command_id("c-1")
+                .exit_code(Some(2));
+            let line = serde_json::to_string(&event).unwrap();
+            let parsed: AuditEvent = serde_json::from_str(&line).unwrap();
+            assert_eq!(parsed.command_id.as_deref(), Some("c-1"));
+            assert_eq!(parsed.exit_code, Some(2));
+            assert_eq!(parsed.outcome, AuditOutcome::Success);
+        }
+    }
+
+    mod log {
+        use super::*;
+
+        fn event(action: &str, agent: &str, timestamp: &str) -> AuditEvent {
+            let mut event = AuditEvent::new(action).agent(Some(agent));
+            event.timestamp = timestamp.to_string();
+            event
+        }
+
+        fn temp_log() -> std::path::PathBuf {
+            std::env::temp_dir().join(format!("ssh-mcp-audit-{}.jsonl", uuid::Uuid::new_v4()))
+        }
+
+        async fn write_log(events: &[AuditEvent]) -> std::path::PathBuf {
+            let path = temp_log();
+            let mut lines: Vec<String> = events
+                .iter()
+                .map(|event| serde_json::to_string(event).unwrap())
+                .collect();
+            // A line cut short by a crash is skipped
+            lines.insert(1, "{\"timestamp\":".to_string());
+            tokio::fs::write(&path, lines.join("\n")).await.unwrap();
+            path
+        }
+
+        #[test]
+        fn test_query_filters() {
+            let event = event("ssh_execute", "a", "2025-01-15T10:00:00+00:00");
+            assert!(AuditQuery::default().matches(&event));
+
+            let query = AuditQuery {
+                agent_id: Some("a".to_string()),
+                action: Some("ssh_execute".to_string()),
+                outcome: Some(AuditOutcome::Success),
+                since: DateTime::parse_from_rfc3339("2025-01-15T10:00:00Z").ok(),
+                ..Default::default()
+            };
+            assert!(query.matches(&event));
+
+            for query in [
+                AuditQuery {
+                    agent_id: Some("b".to_string()),
+                    ..Default::default()
+                },
+                AuditQuery {
+                    session_id: Some("s-1".to_string()),
+                    ..Default::default()
+                },
+                AuditQuery {
+                    outcome: Some(AuditOutcome::Failure),
+                    ..Default::default()
+                },
+                AuditQuery {
+                    since: DateTime::parse_from_rfc3339("2025-01-15T10:00:01Z").ok(),
+                    ..Default::default()
+                },
+            ] {
+                assert!(!query.matches(&event), "{:?}", query);
+            }
+        }
+
+        #[tokio::test]
+        async fn test_read_log_newest_first() {
+            let log = write_log(&[
+                event("ssh_connect", "a", "2025-01-15T10:00:00Z"),
+                event("ssh_execute", "a", "2025-01-15T10:00:01Z"),
+                event("ssh_execute", "b", "2025-01-15T10:00:02Z"),
+                event("ssh_execute", "a", "2025-01-15T10:00:03Z"),
+            ])
+            .await;
+            let path = log.to_str().unwrap();
+            let query = AuditQuery {
+                agent_id: Some("a".to_string()),
+                ..Default::default()
+            };
+
+            let (events, truncated) = read_log(path, &query, 10).await.unwrap();
+            let times: Vec<_> = events.iter().map(|e| e.timestamp.as_str()).collect();
+            assert_eq!(
+                times,
+                [
+                    "2025-01-15T10:00:03Z",
+                    "2025-01-15T10:00:01Z",
+                    "2025-01-15T10:00:00Z"
+                ]
+            );
+            assert!(!truncated);
+
+            let (events, truncated) = read_log(path, &query, 1).await.unwrap();
+            assert_eq!(events[0].timestamp, "2025-01-15T10:00:03Z");
+            assert!(truncated);
+            let _ = std::fs::remove_file(&log);
+        }
+
+        #[tokio::test]
+        async fn test_missing_log_is_empty() {
+            let path = temp_log();
+            let (events, truncated) = read_log(path.to_str().unwrap(), &AuditQuery::default(), 10)
+                .await
+                .unwrap();
+            assert!(events.is_empty() && !truncated);
+        }
+    }
+}
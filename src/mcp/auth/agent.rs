@@ -100,12 +100,14 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::default_constructed_unit_structs)]
     fn test_agent_auth_default() {
         let auth = AgentAuth::default();
         assert_eq!(auth.name(), "agent");
     }
 
     #[test]
+    #[allow(clippy::default_constructed_unit_structs)]
     fn test_agent_auth_new_equals_default() {
         let auth_new = AgentAuth::new();
         let auth_default = AgentAuth::default();
@@ -119,6 +121,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::default_constructed_unit_structs)]
     fn test_agent_auth_multiple_instances() {
         // Verify we can create multiple independent instances
         let auth1 = AgentAuth::new();
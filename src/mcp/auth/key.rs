@@ -45,24 +45,7 @@ impl AuthStrategy for KeyAuth {
         let key_pair = keys::load_secret_key(path, None)
             .map_err(|e| format!("Failed to load private key from {:?}: {}", self.key_path, e))?;
 
-        // For RSA keys, use the best supported hash algorithm
-        let hash_alg = handle
-            .best_supported_rsa_hash()
-            .await
-            .ok()
-            .flatten()
-            .flatten();
-        debug!("Using RSA hash algorithm for key auth: {:?}", hash_alg);
-
-        // Wrap the key with the preferred hash algorithm
-        let key_with_hash = keys::PrivateKeyWithHashAlg::new(Arc::new(key_pair), hash_alg);
-
-        let result = handle
-            .authenticate_publickey(username, key_with_hash)
-            .await
-            .map_err(|e| format!("Key authentication failed: {}", e))?;
-
-        Ok(result.success())
+        authenticate_with_key(handle, username, key_pair).await
     }
 
     fn name(&self) -> &'static str {
@@ -70,6 +53,32 @@ impl AuthStrategy for KeyAuth {
     }
 }
 
+/// Public key authentication with a loaded private key.
+pub(super) async fn authenticate_with_key(
+    handle: &mut client::Handle<SshClientHandler>,
+    username: &str,
+    key_pair: keys::PrivateKey,
+) -> Result<bool, String> {
+    // For RSA keys, use the best supported hash algorithm
+    let hash_alg = handle
+        .best_supported_rsa_hash()
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    debug!("Using RSA hash algorithm for key auth: {:?}", hash_alg);
+
+    // Wrap the key with the preferred hash algorithm
+    let key_with_hash = keys::PrivateKeyWithHashAlg::new(Arc::new(key_pair), hash_alg);
+
+    let result = handle
+        .authenticate_publickey(username, key_with_hash)
+        .await
+        .map_err(|e| format!("Key authentication failed: {}", e))?;
+
+    Ok(result.success())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
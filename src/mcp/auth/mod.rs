@@ -8,6 +8,7 @@
 //!
 //! - [`PasswordAuth`]: Password-based authentication
 //! - [`KeyAuth`]: Private key file authentication
+//! - [`InlineKeyAuth`]: Private key material passed inline
 //! - [`AgentAuth`]: SSH agent authentication
 //!
 //! # Example
@@ -25,12 +26,14 @@
 
 mod agent;
 mod chain;
+mod inline_key;
 mod key;
 mod password;
 mod traits;
 
 pub use agent::AgentAuth;
 pub use chain::AuthChain;
+pub use inline_key::InlineKeyAuth;
 pub use key::KeyAuth;
 pub use password::PasswordAuth;
 pub use traits::AuthStrategy;
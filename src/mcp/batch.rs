@@ -0,0 +1,188 @@
+//! Fleet-wide operations: `ssh_execute_batch` and `ssh_connect_many`.
+//!
+//! A batch runs one command per session, concurrently, and returns every
+//! result in a single call instead of one `ssh_execute_sync` per host. Targets
+//! are given either as `{session_id, command}` pairs or as one `command` with
+//! many `session_ids`; a pair without a command uses the shared one.
+//!
+//! Unlike `ssh_execute_dag`, targets are independent: a session that cannot be
+//! used (unknown, owned by another agent, command refused by policy) is
+//! reported as `rejected` and the remaining targets still run. Results are
+//! keyed by session, so each session appears at most once per batch.
+//!
+//! `ssh_connect_many` opens one session per address with shared credentials,
+//! each through the normal `ssh_connect` path (retries, quotas, host caps,
+//! audit). A host that fails does not stop the others; the connected
+//! `session_ids` can be passed straight to `ssh_execute_batch`. Both tools
+//! share the target and concurrency limits below.
+
+use std::collections::HashSet;
+
+use super::types::BatchTarget;
+
+/// Largest number of targets in one batch, or addresses in one ssh_connect_many
+pub(crate) const MAX_BATCH_TARGETS: usize = 100;
+
+/// Targets run in parallel when `concurrency` is not given
+pub(crate) const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+/// Largest accepted `concurrency`
+pub(crate) const MAX_BATCH_CONCURRENCY: usize = 32;
+
+/// Resolve the target concurrency, clamped to 1..=`MAX_BATCH_CONCURRENCY`.
+pub(crate) fn resolve_batch_concurrency(concurrency: Option<usize>) -> usize {
+    concurrency
+        .unwrap_or(DEFAULT_BATCH_CONCURRENCY)
+        .clamp(1, MAX_BATCH_CONCURRENCY)
+}
+
+/// Resolve the request to `(session_id, command)` pairs in request order.
+///
+/// `targets` come first, then `session_ids`; both fall back to `command`.
+/// Rejects an empty or oversized batch, a target without a command, and
+/// sessions named more than once.
+pub(crate) fn resolve_batch_targets(
+    targets: Vec<BatchTarget>,
+    session_ids: Vec<String>,
+    command: Option<&str>,
+) -> Result<Vec<(String, String)>, String> {
+    let command = command.filter(|command| !command.trim().is_empty());
+    let requested = targets
+        .into_iter()
+        .map(|target| (target.session_id, target.command))
+        .chain(session_ids.into_iter().map(|session_id| (session_id, None)));
+
+    let mut resolved = Vec::new();
+    let mut seen = HashSet::new();
+    for (session_id, own_command) in requested {
+        let Some(command) = own_command
+            .filter(|command| !command.trim().is_empty())
+            .or_else(|| command.map(str::to_string))
+        else {
+            return Err(format!(
+                "Target '{}' has no command and no shared command was given",
+                session_id
+            ));
+        };
+        if !seen.insert(session_id.clone()) {
+            return Err(format!(
+                "Session '{}' is targeted more than once; use ssh_execute_dag to run several commands on one session",
+                session_id
+            ));
+        }
+        resolved.push((session_id, command));
+    }
+
+    if resolved.is_empty() {
+        return Err("Give targets or session_ids with at least one session".to_string());
+    }
+    if resolved.len() > MAX_BATCH_TARGETS {
+        return Err(format!(
+            "Too many targets ({}); at most {} are allowed",
+            resolved.len(),
+            MAX_BATCH_TARGETS
+        ));
+    }
+    Ok(resolved)
+}
+
+/// Check the addresses of an ssh_connect_many request: at least one, at most
+/// [`MAX_BATCH_TARGETS`], none empty or repeated.
+pub(crate) fn resolve_connect_addresses(addresses: Vec<String>) -> Result<Vec<String>, String> {
+    if addresses.is_empty() {
+        return Err("addresses must contain at least one host".to_string());
+    }
+    if addresses.len() > MAX_BATCH_TARGETS {
+        return Err(format!(
+            "Too many addresses ({}); at most {} are allowed",
+            addresses.len(),
+            MAX_BATCH_TARGETS
+        ));
+    }
+    let mut seen = HashSet::new();
+    addresses
+        .into_iter()
+        .map(|address| address.trim().to_string())
+        .map(|address| {
+            if address.is_empty() {
+                Err("addresses must not be empty".to_string())
+            } else if !seen.insert(address.clone()) {
+                Err(format!("Address '{}' is listed more than once", address))
+            } else {
+                Ok(address)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(session_id: &str, command: Option<&str>) -> BatchTarget {
+        BatchTarget {
+            session_id: session_id.to_string(),
+            command: command.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_pairs_and_shared_command() {
+        let resolved = resolve_batch_targets(
+            vec![target("web-1", Some("uptime")), target("web-2", None)],
+            vec!["db".to_string()],
+            Some("df -h"),
+        )
+        .unwrap();
+        assert_eq!(
+            resolved,
+            vec![
+                ("web-1".to_string(), "uptime".to_string()),
+                ("web-2".to_string(), "df -h".to_string()),
+                ("db".to_string(), "df -h".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_batches() {
+        assert!(resolve_batch_targets(vec![], vec![], Some("uptime")).is_err());
+        assert!(
+            resolve_batch_targets(vec![], vec!["web-1".to_string()], Some("  "))
+                .unwrap_err()
+                .contains("no command")
+        );
+        assert!(
+            resolve_batch_targets(
+                vec![target("web-1", Some("uptime"))],
+                vec!["web-1".to_string()],
+                Some("df -h"),
+            )
+            .unwrap_err()
+            .contains("more than once")
+        );
+        let many = (0..=MAX_BATCH_TARGETS).map(|n| n.to_string()).collect();
+        assert!(
+            resolve_batch_targets(vec![], many, Some("uptime"))
+                .unwrap_err()
+                .contains("Too many")
+        );
+        assert_eq!(resolve_batch_concurrency(Some(0)), 1);
+        assert_eq!(resolve_batch_concurrency(Some(1000)), MAX_BATCH_CONCURRENCY);
+    }
+
+    #[test]
+    fn test_connect_addresses() {
+        assert_eq!(
+            resolve_connect_addresses(vec![" web-1:22".to_string(), "web-2:22".to_string()]),
+            Ok(vec!["web-1:22".to_string(), "web-2:22".to_string()])
+        );
+        assert!(resolve_connect_addresses(vec![]).is_err());
+        assert!(resolve_connect_addresses(vec![" ".to_string()]).is_err());
+        assert!(
+            resolve_connect_addresses(vec!["web-1:22".to_string(), "web-1:22 ".to_string()])
+                .unwrap_err()
+                .contains("more than once")
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Server capability report for `ssh_capabilities`.
+//!
+//! Builds differ (the `port_forward` feature), operators hide tools, cap hosts
+//! and sandbox commands. Instead of discovering this through errors, an agent
+//! can ask once and plan around what this server actually offers.
+
+use super::async_command::MAX_ASYNC_COMMANDS_PER_SESSION;
+use super::batch::{MAX_BATCH_CONCURRENCY, MAX_BATCH_TARGETS};
+use super::config::{
+    AuditExporterKind, DEFAULT_INACTIVITY_TIMEOUT, ServerConfig, resolve_command_timeout,
+    resolve_connect_timeout, resolve_identity_probe, resolve_inactivity_timeout,
+    resolve_max_retries, resolve_max_sessions, resolve_max_sessions_per_agent,
+    resolve_max_sessions_per_host, resolve_read_only, resolve_tool_prefix,
+};
+use super::dag::{MAX_DAG_CONCURRENCY, MAX_DAG_NODES};
+use super::exposure::ToolExposure;
+use super::health::describe_monitor;
+use super::known_hosts::resolve_host_key_checking;
+use super::lease::MAX_LEASE_TTL_SECS;
+use super::policy::Policy;
+use super::shell::MAX_SHELLS_PER_SESSION;
+use super::transfer::{MAX_UPLOAD_CONCURRENCY, MAX_UPLOAD_ENTRIES};
+use super::transport::serving;
+use super::types::{
+    SandboxLimits, ServerFeatures, ServerLimits, ServerPolicies, SshCapabilitiesResponse,
+};
+
+/// Compile-time features of this build.
+pub(crate) fn build_features() -> ServerFeatures {
+    ServerFeatures {
+        port_forward: cfg!(feature = "port_forward"),
+        // ssh_list_dir opens the SFTP subsystem; file transfers run over exec channels
+        sftp: true,
+    }
+}
+
+/// Describe what this server offers under `config`.
+pub(crate) fn capabilities(config: &ServerConfig) -> SshCapabilitiesResponse {
+    let prefix = resolve_tool_prefix(config.tools.prefix.as_deref());
+    let exposure = ToolExposure::from_config(&config.tools)
+        .with_read_only(resolve_read_only(None))
+        .with_prefix(prefix.clone());
+    let tools = exposure.exposed_tools();
+    let mut disabled_tools: Vec<String> = exposure.disabled().map(str::to_string).collect();
+    disabled_tools.sort();
+
+    let features = build_features();
+    let limits = ServerLimits {
+        connect_timeout_secs: resolve_connect_timeout(None).as_secs(),
+        command_timeout_secs: resolve_command_timeout(None).as_secs(),
+        inactivity_timeout_secs: resolve_inactivity_timeout().as_secs(),
+        max_retries: resolve_max_retries(None),
+        max_sessions: resolve_max_sessions(),
+        max_sessions_per_agent: resolve_max_sessions_per_agent(),
+        // No host name matches "", so this is the cap for hosts without an override
+        max_sessions_per_host: resolve_max_sessions_per_host("", &config.host_limits),
+        max_async_commands_per_session: MAX_ASYNC_COMMANDS_PER_SESSION,
+        max_shells_per_session: MAX_SHELLS_PER_SESSION,
+        max_dag_nodes: MAX_DAG_NODES,
+        max_dag_concurrency: MAX_DAG_CONCURRENCY,
+        max_batch_targets: MAX_BATCH_TARGETS,
+        max_batch_concurrency: MAX_BATCH_CONCURRENCY,
+        max_upload_concurrency: MAX_UPLOAD_CONCURRENCY,
+        max_upload_entries: MAX_UPLOAD_ENTRIES,
+        max_lease_ttl_secs: MAX_LEASE_TTL_SECS,
+    };
+    let policies = ServerPolicies {
+        sandbox: (config.sandbox != SandboxLimits::default()).then_some(config.sandbox),
+        host_session_limits: config
+            .host_limits
+            .hosts
+            .iter()
+            .map(|(host, limit)| (host.clone(), *limit))
+            .collect(),
+        audit_exporters: config
+            .audit
+            .exporters
+            .iter()
+            .map(|exporter| match exporter.kind {
+                AuditExporterKind::Syslog => "syslog".to_string(),
+                AuditExporterKind::Http => "http".to_string(),
+                AuditExporterKind::File => "file".to_string(),
+            })
+            .collect(),
+        callback_hosts: config.callbacks.allowed_hosts.clone(),
+        identity_probe: resolve_identity_probe(),
+        host_key_checking: resolve_host_key_checking(None).as_str().to_string(),
+        denied_commands: Policy::load(&config.policy)
+            .map(|policy| policy.denied_patterns())
+            .unwrap_or_default(),
+        read_only: resolve_read_only(None),
+        credential_refs: config.credentials.keys().cloned().collect(),
+    };
+
+    let mut notes = Vec::new();
+    if !features.port_forward {
+        notes.push("port forwarding not built".to_string());
+    }
+    if !disabled_tools.is_empty() {
+        notes.push(format!("{} tool(s) disabled", disabled_tools.len()));
+    }
+    if policies.read_only {
+        notes.push("read-only mode".to_string());
+    }
+    if policies.host_key_checking == "no" {
+        notes.push("host keys not verified".to_string());
+    }
+    if policies.sandbox.is_some() {
+        notes.push("commands sandboxed".to_string());
+    }
+    if !policies.denied_commands.is_empty() {
+        notes.push(format!(
+            "{} command pattern(s) denied",
+            policies.denied_commands.len()
+        ));
+    }
+    if let Some(monitor) = describe_monitor(&config.health) {
+        notes.push(monitor);
+    }
+    if limits.inactivity_timeout_secs != DEFAULT_INACTIVITY_TIMEOUT.as_secs() {
+        notes.push(format!(
+            "sessions idle out after {}s",
+            limits.inactivity_timeout_secs
+        ));
+    }
+    let message = if notes.is_empty() {
+        format!(
+            "ssh-mcp {} with {} tool(s), no restrictions",
+            env!("CARGO_PKG_VERSION"),
+            tools.len()
+        )
+    } else {
+        format!(
+            "ssh-mcp {} with {} tool(s); {}",
+            env!("CARGO_PKG_VERSION"),
+            tools.len(),
+            notes.join(", ")
+        )
+    };
+
+    SshCapabilitiesResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        features,
+        transports: serving()
+            .transports()
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        tools,
+        disabled_tools,
+        tool_prefix: (!prefix.is_empty()).then_some(prefix),
+        limits,
+        policies,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod report {
+        use super::*;
+
+        #[test]
+        fn test_default_config_exposes_every_tool() {
+            let report = capabilities(&ServerConfig::default());
+            assert!(
+                report
+                    .tools
+                    .iter()
+                    .any(|tool| tool.ends_with("ssh_capabilities"))
+            );
+            assert!(report.disabled_tools.is_empty());
+            assert!(report.policies.sandbox.is_none());
+            assert!(report.policies.audit_exporters.is_empty());
+            assert_eq!(report.features.port_forward, cfg!(feature = "port_forward"));
+            assert!(report.features.sftp);
+            assert_eq!(report.transports, serving().transports());
+            assert_eq!(report.limits.max_dag_concurrency, MAX_DAG_CONCURRENCY);
+        }
+
+        #[test]
+        fn test_reports_configured_policies() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [tools]
+                disabled = ["shell"]
+
+                [sandbox]
+                wall_secs = 600
+
+                [host_limits.hosts]
+                "bmc-rack1" = 1
+
+                [[audit.exporters]]
+                kind = "syslog"
+                address = "siem:514"
+
+                [[policy.deny]]
+                pattern = "reboot*"
+                "#,
+            )
+            .unwrap();
+
+            let report = capabilities(&config);
+            assert!(
+                report
+                    .disabled_tools
+                    .contains(&"ssh_shell_open".to_string())
+            );
+            assert!(!report.tools.contains(&"ssh_shell_open".to_string()));
+            assert_eq!(
+                report.policies.sandbox.and_then(|limits| limits.wall_secs),
+                Some(600)
+            );
+            assert_eq!(report.policies.host_session_limits["bmc-rack1"], 1);
+            assert_eq!(report.policies.audit_exporters, vec!["syslog"]);
+            assert_eq!(report.policies.denied_commands, vec!["reboot*"]);
+            assert!(report.message.contains("tool(s) disabled"));
+            assert!(report.message.contains("1 command pattern(s) denied"));
+        }
+    }
+}
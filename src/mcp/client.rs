@@ -11,7 +11,9 @@
 //!    keepalive, and compression settings.
 //!
 //! 3. **Connection Establishment**: Establish TCP connection to the SSH server
-//!    with configurable timeout.
+//!    with configurable timeout. With jump hosts (ProxyJump), each hop is
+//!    reached through a `direct-tcpip` channel of the previous one, and the
+//!    target through the last hop (see [`connect_via_jump`]).
 //!
 //! 4. **Authentication**: Authenticate using one of:
 //!    - Password authentication
@@ -21,6 +23,15 @@
 //! 5. **Command Execution**: Execute commands on established sessions and
 //!    collect stdout, stderr, and exit code.
 //!
+//! ## Wedged Servers
+//!
+//! Every channel setup step (open, pty, exec, shell) runs under its own
+//! timeout (`SSH_CHANNEL_OPEN_TIMEOUT`), so an unresponsive server fails the
+//! call instead of hanging it. Async commands also have a watchdog: after
+//! `SSH_CHANNEL_IDLE_TIMEOUT` without output or close, the connection is
+//! pinged; if the server does not answer, the channel is force-closed and the
+//! command marked failed. Silent commands on a healthy connection keep running.
+//!
 //! ## Retry Strategy
 //!
 //! Connection attempts use exponential backoff with jitter via the `backon` crate:
@@ -37,6 +48,8 @@
 //!
 //! Authentication failures are never retried to avoid account lockouts.
 
+use std::collections::BTreeMap;
+use std::fmt::Display;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
@@ -48,18 +61,39 @@ use tracing::{error, info, warn};
 use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 
+use crate::mcp::algorithms::AlgorithmPreferences;
 use crate::mcp::async_command::OutputBuffer;
 use crate::mcp::auth::{AuthChain, AuthStrategy};
-use crate::mcp::config::MAX_RETRY_DELAY;
+use crate::mcp::command_wrap::shell_quote;
+use crate::mcp::config::{
+    Keepalive, MAX_RETRY_DELAY, resolve_channel_idle_timeout, resolve_channel_open_timeout,
+};
 use crate::mcp::error::is_retryable_error;
+use crate::mcp::exit_pattern::{ExitPatterns, PatternWatch};
+use crate::mcp::known_hosts::{HostKeyCheck, HostKeyChecking};
+use crate::mcp::reverse::ReverseForwards;
 use crate::mcp::session::SshClientHandler;
-use crate::mcp::types::{AsyncCommandStatus, SshCommandResponse};
+use crate::mcp::session_env::apply_env;
+use crate::mcp::signal::SignalReceiver;
+use crate::mcp::traffic::ByteCounters;
+use crate::mcp::types::{
+    AsyncCommandStatus, JumpHost, PatternMatch, PatternOutcome, SshCommandResponse,
+};
+
+/// Longest accepted `jump_hosts` chain
+pub(crate) const MAX_JUMP_HOSTS: usize = 4;
+
+/// zlib level used by the SSH transport when compression is negotiated.
+///
+/// russh creates its deflate stream with `flate2::Compression::fast()` and does
+/// not expose the level, so every compressed session runs at level 1.
+pub(crate) const TRANSPORT_ZLIB_LEVEL: u32 = 1;
 
 /// Build russh client configuration with the specified settings.
 ///
 /// Creates an `Arc<client::Config>` with:
 /// - Inactivity timeout from dedicated parameter (or `None` if `persistent` is true)
-/// - Keepalive interval of 30 seconds with max 3 keepalives
+/// - Keepalive interval and unanswered keepalive limit from `keepalive`
 /// - Compression preference based on `compress` flag (ZLIB if enabled, NONE if disabled)
 ///
 /// # Arguments
@@ -67,20 +101,27 @@ use crate::mcp::types::{AsyncCommandStatus, SshCommandResponse};
 /// * `inactivity_timeout` - Session inactivity timeout (ignored if `persistent` is true)
 /// * `compress` - Whether to enable zlib compression
 /// * `persistent` - If true, disables inactivity timeout to keep the session open indefinitely
+/// * `keepalive` - Keepalive interval (0 disables) and unanswered limit (0: never drop)
+/// * `algorithms` - Cipher, key exchange and host key algorithm lists replacing the defaults
 ///
 /// # Examples
 ///
 /// ```ignore
-/// let config = build_client_config(Duration::from_secs(300), true, false);
+/// let keepalive = resolve_keepalive(None, None);
+/// let algorithms = AlgorithmPreferences::default();
+/// let config = build_client_config(Duration::from_secs(300), true, false, keepalive, &algorithms);
 /// assert_eq!(config.inactivity_timeout, Some(Duration::from_secs(300)));
 ///
-/// let persistent_config = build_client_config(Duration::from_secs(300), true, true);
+/// let persistent_config =
+///     build_client_config(Duration::from_secs(300), true, true, keepalive, &algorithms);
 /// assert_eq!(persistent_config.inactivity_timeout, None);
 /// ```
 pub(crate) fn build_client_config(
     inactivity_timeout: Duration,
     compress: bool,
     persistent: bool,
+    keepalive: Keepalive,
+    algorithms: &AlgorithmPreferences,
 ) -> Arc<client::Config> {
     let compression = if compress {
         (&[russh::compression::ZLIB, russh::compression::NONE][..]).into()
@@ -88,10 +129,10 @@ pub(crate) fn build_client_config(
         (&[russh::compression::NONE][..]).into()
     };
 
-    let preferred = russh::Preferred {
+    let preferred = algorithms.apply(russh::Preferred {
         compression,
         ..Default::default()
-    };
+    });
 
     let timeout = if persistent {
         None
@@ -101,8 +142,8 @@ pub(crate) fn build_client_config(
 
     Arc::new(client::Config {
         inactivity_timeout: timeout,
-        keepalive_interval: Some(Duration::from_secs(30)),
-        keepalive_max: 3,
+        keepalive_interval: keepalive.interval(),
+        keepalive_max: keepalive.max as usize,
         preferred,
         ..Default::default()
     })
@@ -159,12 +200,20 @@ pub(crate) fn parse_address(address: &str) -> Result<(String, u16), String> {
 /// * `username` - SSH username for authentication
 /// * `password` - Optional password for password authentication
 /// * `key_path` - Optional path to private key file
+/// * `key_data` - Optional private key content, used instead of a key file
 /// * `timeout` - Connection timeout duration
 /// * `inactivity_timeout` - Session inactivity timeout duration
 /// * `max_retries` - Maximum number of retry attempts
 /// * `min_delay` - Initial delay between retries
 /// * `compress` - Whether to enable compression
 /// * `persistent` - If true, disables inactivity timeout to keep the session open indefinitely
+/// * `keepalive` - SSH keepalive settings, applied to every hop
+/// * `algorithms` - Algorithm lists for the target; jump hosts keep the defaults
+/// * `host_key_checking` - Host key verification mode, applied to every hop
+/// * `jump_hosts` - Hosts to tunnel through, first hop first (empty: connect directly)
+/// * `agent_forwarding` - Serve agent channels the target opens to the local agent
+/// * `reverse` - Reverse forward table the connection's handler serves
+/// * `traffic` - Byte counters of the connection
 ///
 /// # Returns
 ///
@@ -183,12 +232,20 @@ pub(crate) async fn connect_to_ssh_with_retry(
     username: &str,
     password: Option<&str>,
     key_path: Option<&str>,
+    key_data: Option<&str>,
     timeout: Duration,
     inactivity_timeout: Duration,
     max_retries: u32,
     min_delay: Duration,
     compress: bool,
     persistent: bool,
+    keepalive: Keepalive,
+    algorithms: &AlgorithmPreferences,
+    host_key_checking: HostKeyChecking,
+    jump_hosts: &[JumpHost],
+    agent_forwarding: bool,
+    reverse: &Arc<ReverseForwards>,
+    traffic: &Arc<ByteCounters>,
 ) -> Result<(client::Handle<SshClientHandler>, u32), String> {
     // Track retry attempts using atomic counter
     let attempt_counter = AtomicU32::new(0);
@@ -198,6 +255,7 @@ pub(crate) async fn connect_to_ssh_with_retry(
     let username = username.to_string();
     let password = password.map(|s| s.to_string());
     let key_path = key_path.map(|s| s.to_string());
+    let key_data = key_data.map(|s| s.to_string());
 
     let backoff = ExponentialBuilder::default()
         .with_min_delay(min_delay)
@@ -215,17 +273,27 @@ pub(crate) async fn connect_to_ssh_with_retry(
             );
         }
 
-        connect_to_ssh(
-            &address,
-            &username,
-            password.as_deref(),
-            key_path.as_deref(),
+        let target = Target {
+            address: &address,
+            username: &username,
+            password: password.as_deref(),
+            key_path: key_path.as_deref(),
+            key_data: key_data.as_deref(),
+        };
+        let settings = ConnectSettings {
             timeout,
             inactivity_timeout,
             compress,
             persistent,
-        )
-        .await
+            keepalive,
+            host_key_checking,
+            agent_forwarding,
+        };
+        if jump_hosts.is_empty() {
+            connect_to_ssh(None, target, &settings, algorithms, reverse, traffic).await
+        } else {
+            connect_via_jump(jump_hosts, target, &settings, algorithms, reverse, traffic).await
+        }
     })
     .retry(backoff)
     .when(|e| {
@@ -269,43 +337,94 @@ pub(crate) async fn connect_to_ssh_with_retry(
     }
 }
 
-/// Establish an SSH connection and authenticate.
-///
-/// This is the core connection function that:
-/// 1. Builds client configuration
-/// 2. Parses the address
-/// 3. Connects with timeout
-/// 4. Authenticates using the appropriate method via [`AuthChain`]
-#[allow(clippy::too_many_arguments)]
-async fn connect_to_ssh(
-    address: &str,
-    username: &str,
-    password: Option<&str>,
-    key_path: Option<&str>,
+/// Host and credentials of one connection of a chain.
+#[derive(Clone, Copy)]
+struct Target<'a> {
+    address: &'a str,
+    username: &'a str,
+    password: Option<&'a str>,
+    key_path: Option<&'a str>,
+    key_data: Option<&'a str>,
+}
+
+/// Settings shared by every connection of a chain.
+#[derive(Clone, Copy)]
+struct ConnectSettings {
     timeout: Duration,
     inactivity_timeout: Duration,
     compress: bool,
     persistent: bool,
-) -> Result<client::Handle<SshClientHandler>, String> {
-    let config = build_client_config(inactivity_timeout, compress, persistent);
-    let handler = SshClientHandler;
+    keepalive: Keepalive,
+    host_key_checking: HostKeyChecking,
+    /// Serve agent channels (target connection only)
+    agent_forwarding: bool,
+}
 
+/// Establish an SSH connection and authenticate.
+///
+/// This is the core connection function that:
+/// 1. Parses the address
+/// 2. Connects with timeout, over TCP or through a `direct-tcpip` channel of
+///    the jump host connection `via`, verifying the host key per
+///    `host_key_checking`
+/// 3. Authenticates using the appropriate method via [`AuthChain`]
+///
+/// `via` is handed to the new connection's handler, so the jump host stays
+/// connected exactly as long as the new connection.
+async fn connect_to_ssh(
+    via: Option<client::Handle<SshClientHandler>>,
+    target: Target<'_>,
+    settings: &ConnectSettings,
+    algorithms: &AlgorithmPreferences,
+    reverse: &Arc<ReverseForwards>,
+    traffic: &Arc<ByteCounters>,
+) -> Result<client::Handle<SshClientHandler>, String> {
     // Parse address into host and port
-    let (host, port) = parse_address(address)?;
+    let (host, port) = parse_address(target.address)?;
+    let host_key = HostKeyCheck::new(&host, port, settings.host_key_checking);
+    let config = build_client_config(
+        settings.inactivity_timeout,
+        settings.compress,
+        settings.persistent,
+        settings.keepalive,
+        algorithms,
+    );
 
     // Connect with timeout
-    let connect_future = client::connect(config, (host.as_str(), port), handler);
+    let connect_future = async {
+        let tunnel = match &via {
+            Some(jump) => Some(
+                jump.channel_open_direct_tcpip(host.as_str(), port.into(), "127.0.0.1", 0)
+                    .await?
+                    .into_stream(),
+            ),
+            None => None,
+        };
+        let handler = SshClientHandler::new(reverse.clone(), traffic.clone(), host_key.clone())
+            .with_agent_forwarding(settings.agent_forwarding)
+            .with_jump(via);
+        match tunnel {
+            Some(stream) => client::connect_stream(config, stream, handler).await,
+            None => client::connect(config, (host.as_str(), port), handler).await,
+        }
+    };
 
-    let mut handle = tokio::time::timeout(timeout, connect_future)
+    let mut handle = tokio::time::timeout(settings.timeout, connect_future)
         .await
-        .map_err(|_| format!("Connection timed out after {:?}", timeout))?
-        .map_err(|e| format!("Failed to connect: {}", e))?;
+        .map_err(|_| format!("Connection timed out after {:?}", settings.timeout))?
+        .map_err(|e| {
+            host_key
+                .rejection()
+                .unwrap_or_else(|| format!("Failed to connect: {}", e))
+        })?;
 
     // Build authentication chain based on provided credentials
-    let auth_chain = build_auth_chain(password, key_path);
+    let auth_chain = build_auth_chain(target.password, target.key_path, target.key_data);
 
     // Authenticate using the chain
-    let success = auth_chain.authenticate(&mut handle, username).await?;
+    let success = auth_chain
+        .authenticate(&mut handle, target.username)
+        .await?;
 
     if !success {
         return Err("Authentication failed: no authentication methods succeeded".to_string());
@@ -314,13 +433,104 @@ async fn connect_to_ssh(
     Ok(handle)
 }
 
+/// Connect to `target` through a chain of jump hosts (like `ssh -J`).
+///
+/// The first hop is dialed over TCP; every later hop and finally the target
+/// are reached through a `direct-tcpip` channel of the previous hop, so only
+/// the first hop must be reachable from this server. Each hop authenticates
+/// with its own credentials (its username defaults to the target's) and has
+/// its host key verified like the target. Every connection owns the one it is
+/// tunneled through, so disconnecting the target closes the whole chain.
+///
+/// Errors name the hop that failed, e.g. `Jump host 2 (ops@10.0.1.5:22): ...`.
+async fn connect_via_jump(
+    jump_hosts: &[JumpHost],
+    target: Target<'_>,
+    settings: &ConnectSettings,
+    algorithms: &AlgorithmPreferences,
+    reverse: &Arc<ReverseForwards>,
+    traffic: &Arc<ByteCounters>,
+) -> Result<client::Handle<SshClientHandler>, String> {
+    // Hops have no idle timeout of their own: the target's traffic and
+    // keepalives flow through them, and they close with it; only the target
+    // runs commands, so only it forwards the agent
+    let hop_settings = ConnectSettings {
+        persistent: true,
+        agent_forwarding: false,
+        ..*settings
+    };
+
+    let mut via = None;
+    for (index, jump) in jump_hosts.iter().enumerate() {
+        let hop = Target {
+            address: &jump.address,
+            username: jump.username.as_deref().unwrap_or(target.username),
+            password: jump.password.as_deref(),
+            key_path: jump.key_path.as_deref(),
+            key_data: jump.key_data.as_deref(),
+        };
+        // Reverse forwards are only served, and traffic only counted, on the
+        // target connection
+        let hop_reverse = Arc::new(ReverseForwards::default());
+        let hop_traffic = Arc::new(ByteCounters::default());
+        let handle = connect_to_ssh(
+            via,
+            hop,
+            &hop_settings,
+            &AlgorithmPreferences::default(),
+            &hop_reverse,
+            &hop_traffic,
+        )
+        .await
+        .map_err(|e| {
+            format!(
+                "Jump host {} ({}): {}",
+                index + 1,
+                jump.label(target.username),
+                e
+            )
+        })?;
+        info!(
+            "Connected to jump host {} ({})",
+            index + 1,
+            jump.label(target.username)
+        );
+        via = Some(handle);
+    }
+
+    connect_to_ssh(via, target, settings, algorithms, reverse, traffic).await
+}
+
+/// Check a `jump_hosts` chain before dialing.
+pub(crate) fn validate_jump_hosts(jump_hosts: &[JumpHost]) -> Result<(), String> {
+    if jump_hosts.len() > MAX_JUMP_HOSTS {
+        return Err(format!(
+            "At most {} jump hosts are supported, got {}",
+            MAX_JUMP_HOSTS,
+            jump_hosts.len()
+        ));
+    }
+    for (index, jump) in jump_hosts.iter().enumerate() {
+        if jump.address.trim().is_empty() {
+            return Err(format!("Jump host {} has an empty address", index + 1));
+        }
+        parse_address(&jump.address).map_err(|e| format!("Jump host {}: {}", index + 1, e))?;
+    }
+    Ok(())
+}
+
 /// Build an authentication chain based on the provided credentials.
 ///
 /// The chain is built with the following priority:
 /// 1. Password authentication (if password is provided)
 /// 2. Key-based authentication (if key_path is provided)
-/// 3. SSH agent authentication (fallback if no explicit credentials)
-fn build_auth_chain(password: Option<&str>, key_path: Option<&str>) -> AuthChain {
+/// 3. Inline key authentication (if key_data is provided)
+/// 4. SSH agent authentication (fallback if no explicit credentials)
+fn build_auth_chain(
+    password: Option<&str>,
+    key_path: Option<&str>,
+    key_data: Option<&str>,
+) -> AuthChain {
     let mut chain = AuthChain::new();
 
     if let Some(password) = password {
@@ -331,6 +541,10 @@ fn build_auth_chain(password: Option<&str>, key_path: Option<&str>) -> AuthChain
         chain = chain.with_key(key_path);
     }
 
+    if let Some(key_data) = key_data {
+        chain = chain.with_key_data(key_data);
+    }
+
     // If no explicit credentials, use SSH agent as fallback
     if chain.is_empty() {
         chain = chain.with_agent();
@@ -339,6 +553,45 @@ fn build_auth_chain(password: Option<&str>, key_path: Option<&str>) -> AuthChain
     chain
 }
 
+/// Maximum time to wait for an RTT ping reply
+pub(crate) const RTT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Measure the SSH round-trip time with a keepalive ping.
+///
+/// Uses a `keepalive@openssh.com` request that the server must answer, so the
+/// measurement covers the network and SSH transport without spawning a remote
+/// process. Returns `None` if the ping fails or exceeds `timeout`.
+pub(crate) async fn measure_rtt(
+    handle_arc: &Arc<client::Handle<SshClientHandler>>,
+    timeout: Duration,
+) -> Option<Duration> {
+    let start = std::time::Instant::now();
+    match tokio::time::timeout(timeout, handle_arc.send_ping()).await {
+        Ok(Ok(())) => Some(start.elapsed()),
+        _ => None,
+    }
+}
+
+/// Run one channel setup step (open, pty, exec, shell) under `timeout`.
+///
+/// Errors read `Failed to <step>: <reason>`; a timeout mentions "timeout" so
+/// it is classified as retryable.
+pub(crate) async fn channel_step<T, E: Display>(
+    step: &str,
+    timeout: Duration,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, String> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(format!("Failed to {}: {}", step, e)),
+        Err(_) => Err(format!(
+            "Failed to {}: timeout after {}s (server not responding)",
+            step,
+            timeout.as_secs()
+        )),
+    }
+}
+
 /// Execute a command on an SSH session with timeout support.
 ///
 /// Opens a session channel, executes the command, and collects the output.
@@ -372,17 +625,18 @@ pub(crate) async fn execute_ssh_command(
     command: &str,
     timeout: Duration,
 ) -> Result<SshCommandResponse, String> {
+    let open_timeout = resolve_channel_open_timeout();
+
     // Open a session channel
-    let mut channel = handle_arc
-        .channel_open_session()
-        .await
-        .map_err(|e| format!("Failed to open channel: {}", e))?;
+    let mut channel = channel_step(
+        "open channel",
+        open_timeout,
+        handle_arc.channel_open_session(),
+    )
+    .await?;
 
     // Execute the command
-    channel
-        .exec(true, command)
-        .await
-        .map_err(|e| format!("Failed to execute command: {}", e))?;
+    channel_step("execute command", open_timeout, channel.exec(true, command)).await?;
 
     // Pre-allocate buffers to reduce reallocations during output collection
     let mut stdout = Vec::with_capacity(4096);
@@ -461,38 +715,72 @@ pub(crate) async fn execute_ssh_command(
 ///
 /// * `handle` - Shared handle to the SSH session
 /// * `command` - Shell command to execute
+/// * `env` - Environment variables for the command
+/// * `forward_agent` - Request agent forwarding for the command's channel
 /// * `timeout` - Command execution timeout duration
 /// * `output` - Shared buffer for collecting stdout/stderr
 /// * `status_tx` - Channel to send status updates
 /// * `cancel_token` - Token to signal cancellation
+/// * `signals` - Signals to send to the command while it runs
 /// * `exit_code` - Shared storage for exit code
 /// * `error` - Shared storage for error message
 /// * `timed_out` - Shared flag for timeout status
+/// * `patterns` - Output patterns that settle the status early
+/// * `pattern_match` - Shared storage for the pattern match
 #[allow(clippy::too_many_arguments)]
 pub(crate) async fn execute_ssh_command_async(
     handle: Arc<client::Handle<SshClientHandler>>,
     command: String,
+    env: BTreeMap<String, String>,
+    forward_agent: bool,
     timeout: Duration,
     output: Arc<tokio::sync::Mutex<OutputBuffer>>,
     status_tx: watch::Sender<AsyncCommandStatus>,
     cancel_token: CancellationToken,
+    mut signals: SignalReceiver,
     exit_code: Arc<tokio::sync::Mutex<Option<i32>>>,
     error: Arc<tokio::sync::Mutex<Option<String>>>,
     timed_out: Arc<std::sync::atomic::AtomicBool>,
+    patterns: Option<ExitPatterns>,
+    pattern_match: Arc<tokio::sync::Mutex<Option<PatternMatch>>>,
 ) {
+    let open_timeout = resolve_channel_open_timeout();
+
     // Open a session channel
-    let mut channel = match handle.channel_open_session().await {
-        Ok(ch) => ch,
+    let mut channel =
+        match channel_step("open channel", open_timeout, handle.channel_open_session()).await {
+            Ok(ch) => ch,
+            Err(e) => {
+                *error.lock().await = Some(e);
+                let _ = status_tx.send(AsyncCommandStatus::Failed);
+                return;
+            }
+        };
+    if forward_agent && let Err(e) = request_agent_forwarding(&channel, open_timeout).await {
+        *error.lock().await = Some(e);
+        let _ = status_tx.send(AsyncCommandStatus::Failed);
+        return;
+    }
+
+    // Set environment variables, exporting those the server rejects
+    let exec_command = match apply_env(&mut channel, &env, &command, open_timeout).await {
+        Ok(exec_command) => exec_command,
         Err(e) => {
-            *error.lock().await = Some(format!("Failed to open channel: {}", e));
+            *error.lock().await = Some(e);
             let _ = status_tx.send(AsyncCommandStatus::Failed);
             return;
         }
     };
 
     // Execute the command
-    if let Err(e) = channel.exec(true, command.as_str()).await {
-        *error.lock().await = Some(format!("Failed to execute command: {}", e));
+    if let Err(e) = channel_step(
+        "execute command",
+        open_timeout,
+        channel.exec(true, exec_command.as_str()),
+    )
+    .await
+    {
+        *error.lock().await = Some(e);
         let _ = status_tx.send(AsyncCommandStatus::Failed);
         return;
     }
@@ -505,7 +793,7 @@ pub(crate) async fn execute_ssh_command_async(
         _ = cancel_token.cancelled() => {
             warn!("Async command cancelled: {}", command);
             let _ = channel.close().await;
-            let _ = status_tx.send(AsyncCommandStatus::Cancelled);
+            settle(&status_tx, AsyncCommandStatus::Cancelled);
         }
 
         // Check for timeout
@@ -516,15 +804,72 @@ pub(crate) async fn execute_ssh_command_async(
             );
             timed_out.store(true, Ordering::SeqCst);
             let _ = channel.close().await;
-            let _ = status_tx.send(AsyncCommandStatus::Completed);
+            settle(&status_tx, AsyncCommandStatus::Completed);
         }
 
         // Collect output
-        result = collect_async_output(&mut channel, &output) => {
-            *exit_code.lock().await = result;
-            let _ = status_tx.send(AsyncCommandStatus::Completed);
+        result = collect_async_output(
+            &handle,
+            &mut channel,
+            &mut signals,
+            &output,
+            patterns.map(ExitPatterns::watch),
+            &pattern_match,
+            &status_tx,
+        ) => {
+            finish_async_command(result, &exit_code, &error, &status_tx).await;
         }
     }
+    pattern_match_ended(&pattern_match).await;
+}
+
+/// Ask the server to forward the agent to the session `channel` (like
+/// `ssh -A`); sent before the command or shell starts so it sees
+/// `SSH_AUTH_SOCK`. Servers that refuse, e.g. with `AllowAgentForwarding no`,
+/// simply run it without.
+async fn request_agent_forwarding(
+    channel: &russh::Channel<client::Msg>,
+    open_timeout: Duration,
+) -> Result<(), String> {
+    channel_step(
+        "request agent forwarding",
+        open_timeout,
+        channel.agent_forward(false),
+    )
+    .await
+}
+
+/// Login shell started instead of the default shell when a shell needs a
+/// working directory
+const LOGIN_SHELL: &str = "exec \"${SHELL:-/bin/sh}\" -l";
+
+/// Check a `cwd` parameter: non-empty, without NUL bytes.
+pub(crate) fn validate_cwd(cwd: &str) -> Result<(), String> {
+    if cwd.trim().is_empty() {
+        return Err("cwd must not be empty".to_string());
+    }
+    if cwd.contains('\0') {
+        return Err("cwd contains a NUL byte".to_string());
+    }
+    Ok(())
+}
+
+/// Prefix `command` with a change into `cwd`; the command does not run when
+/// the directory cannot be entered (exit status 1, the shell's error on
+/// stderr).
+///
+/// `cwd` is passed as one quoted word, so spaces and metacharacters are
+/// literal. A leading `~` or `~/` is resolved against `$HOME`, since a quoted
+/// tilde would not be expanded.
+pub(crate) fn cd_command(cwd: &str, command: &str) -> String {
+    let target = if cwd == "~" {
+        "\"$HOME\"".to_string()
+    } else if let Some(rest) = cwd.strip_prefix("~/") {
+        format!("\"$HOME\"/{}", shell_quote(rest))
+    } else {
+        shell_quote(cwd)
+    };
+    format!("cd -- {} || exit 1; {}", target, command)
 }
 
 /// Open a PTY channel with an interactive shell.
@@ -538,6 +883,9 @@ pub(crate) async fn execute_ssh_command_async(
 /// * `term` - Terminal type (e.g., "xterm", "vt100", "ansi")
 /// * `cols` - Terminal width in columns
 /// * `rows` - Terminal height in rows
+/// * `cwd` - Directory the shell starts in; a login shell is exec'd there
+///   instead of requesting the default shell
+/// * `forward_agent` - Request agent forwarding for the shell's channel
 ///
 /// # Returns
 ///
@@ -548,21 +896,30 @@ pub(crate) async fn open_pty_shell(
     term: &str,
     cols: u32,
     rows: u32,
+    cwd: Option<&str>,
+    forward_agent: bool,
 ) -> Result<russh::Channel<client::Msg>, String> {
-    let channel = handle
-        .channel_open_session()
-        .await
-        .map_err(|e| format!("Failed to open channel: {}", e))?;
+    let open_timeout = resolve_channel_open_timeout();
 
-    channel
-        .request_pty(true, term, cols, rows, 0, 0, &[])
-        .await
-        .map_err(|e| format!("Failed to request PTY: {}", e))?;
+    let channel = channel_step("open channel", open_timeout, handle.channel_open_session()).await?;
+    if forward_agent {
+        request_agent_forwarding(&channel, open_timeout).await?;
+    }
 
-    channel
-        .request_shell(true)
-        .await
-        .map_err(|e| format!("Failed to request shell: {}", e))?;
+    channel_step(
+        "request PTY",
+        open_timeout,
+        channel.request_pty(true, term, cols, rows, 0, 0, &[]),
+    )
+    .await?;
+
+    match cwd {
+        Some(cwd) => {
+            let command = cd_command(cwd, LOGIN_SHELL);
+            channel_step("request shell", open_timeout, channel.exec(true, command)).await?
+        }
+        None => channel_step("request shell", open_timeout, channel.request_shell(true)).await?,
+    }
 
     Ok(channel)
 }
@@ -577,34 +934,69 @@ pub(crate) async fn open_pty_shell(
 pub(crate) async fn execute_ssh_command_async_pty(
     handle: Arc<client::Handle<SshClientHandler>>,
     command: String,
+    env: BTreeMap<String, String>,
+    forward_agent: bool,
     timeout: Duration,
     output: Arc<tokio::sync::Mutex<OutputBuffer>>,
     status_tx: watch::Sender<AsyncCommandStatus>,
     cancel_token: CancellationToken,
+    mut signals: SignalReceiver,
     exit_code: Arc<tokio::sync::Mutex<Option<i32>>>,
     error: Arc<tokio::sync::Mutex<Option<String>>>,
     timed_out: Arc<std::sync::atomic::AtomicBool>,
+    patterns: Option<ExitPatterns>,
+    pattern_match: Arc<tokio::sync::Mutex<Option<PatternMatch>>>,
 ) {
+    let open_timeout = resolve_channel_open_timeout();
+
     // Open a session channel
-    let mut channel = match handle.channel_open_session().await {
-        Ok(ch) => ch,
-        Err(e) => {
-            *error.lock().await = Some(format!("Failed to open channel: {}", e));
-            let _ = status_tx.send(AsyncCommandStatus::Failed);
-            return;
-        }
-    };
+    let mut channel =
+        match channel_step("open channel", open_timeout, handle.channel_open_session()).await {
+            Ok(ch) => ch,
+            Err(e) => {
+                *error.lock().await = Some(e);
+                let _ = status_tx.send(AsyncCommandStatus::Failed);
+                return;
+            }
+        };
+    if forward_agent && let Err(e) = request_agent_forwarding(&channel, open_timeout).await {
+        *error.lock().await = Some(e);
+        let _ = status_tx.send(AsyncCommandStatus::Failed);
+        return;
+    }
 
     // Request PTY before exec (xterm 80x24 default)
-    if let Err(e) = channel.request_pty(true, "xterm", 80, 24, 0, 0, &[]).await {
-        *error.lock().await = Some(format!("Failed to request PTY: {}", e));
+    if let Err(e) = channel_step(
+        "request PTY",
+        open_timeout,
+        channel.request_pty(true, "xterm", 80, 24, 0, 0, &[]),
+    )
+    .await
+    {
+        *error.lock().await = Some(e);
         let _ = status_tx.send(AsyncCommandStatus::Failed);
         return;
     }
 
+    // Set environment variables, exporting those the server rejects
+    let exec_command = match apply_env(&mut channel, &env, &command, open_timeout).await {
+        Ok(exec_command) => exec_command,
+        Err(e) => {
+            *error.lock().await = Some(e);
+            let _ = status_tx.send(AsyncCommandStatus::Failed);
+            return;
+        }
+    };
+
     // Execute the command
-    if let Err(e) = channel.exec(true, command.as_str()).await {
-        *error.lock().await = Some(format!("Failed to execute command: {}", e));
+    if let Err(e) = channel_step(
+        "execute command",
+        open_timeout,
+        channel.exec(true, exec_command.as_str()),
+    )
+    .await
+    {
+        *error.lock().await = Some(e);
         let _ = status_tx.send(AsyncCommandStatus::Failed);
         return;
     }
@@ -617,7 +1009,7 @@ pub(crate) async fn execute_ssh_command_async_pty(
         _ = cancel_token.cancelled() => {
             warn!("Async PTY command cancelled: {}", command);
             let _ = channel.close().await;
-            let _ = status_tx.send(AsyncCommandStatus::Cancelled);
+            settle(&status_tx, AsyncCommandStatus::Cancelled);
         }
 
         _ = tokio::time::sleep(timeout) => {
@@ -627,55 +1019,174 @@ pub(crate) async fn execute_ssh_command_async_pty(
             );
             timed_out.store(true, Ordering::SeqCst);
             let _ = channel.close().await;
-            let _ = status_tx.send(AsyncCommandStatus::Completed);
+            settle(&status_tx, AsyncCommandStatus::Completed);
         }
 
-        result = collect_async_output(&mut channel, &output) => {
-            *exit_code.lock().await = result;
-            let _ = status_tx.send(AsyncCommandStatus::Completed);
+        result = collect_async_output(
+            &handle,
+            &mut channel,
+            &mut signals,
+            &output,
+            patterns.map(ExitPatterns::watch),
+            &pattern_match,
+            &status_tx,
+        ) => {
+            finish_async_command(result, &exit_code, &error, &status_tx).await;
         }
     }
+    pattern_match_ended(&pattern_match).await;
 }
 
 /// Flush threshold for batched output (8KB)
 const FLUSH_THRESHOLD: usize = 8192;
 
+/// Longest time batched output waits before it is flushed, so pollers and
+/// output streams see slow output promptly
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Move batched output into the shared buffer.
+async fn flush_output(
+    output: &tokio::sync::Mutex<OutputBuffer>,
+    local_stdout: &mut Vec<u8>,
+    local_stderr: &mut Vec<u8>,
+) {
+    let mut buf = output.lock().await;
+    buf.push_stdout(local_stdout);
+    buf.push_stderr(local_stderr);
+}
+
+/// Move an async command out of `running`; a status an output pattern
+/// already settled is kept.
+fn settle(status_tx: &watch::Sender<AsyncCommandStatus>, status: AsyncCommandStatus) {
+    status_tx.send_if_modified(|current| {
+        let running = *current == AsyncCommandStatus::Running;
+        if running {
+            *current = status;
+        }
+        running
+    });
+}
+
+/// Note that a command which kept running after its pattern match has ended.
+async fn pattern_match_ended(pattern_match: &tokio::sync::Mutex<Option<PatternMatch>>) {
+    if let Some(found) = pattern_match.lock().await.as_mut() {
+        found.running = false;
+    }
+}
+
+/// Record the outcome of an async command whose channel finished or hung.
+async fn finish_async_command(
+    result: Result<Option<i32>, String>,
+    exit_code: &tokio::sync::Mutex<Option<i32>>,
+    error: &tokio::sync::Mutex<Option<String>>,
+    status_tx: &watch::Sender<AsyncCommandStatus>,
+) {
+    match result {
+        Ok(code) => {
+            *exit_code.lock().await = code;
+            settle(status_tx, AsyncCommandStatus::Completed);
+        }
+        Err(e) => {
+            error!("{}", e);
+            *error.lock().await = Some(e);
+            settle(status_tx, AsyncCommandStatus::Failed);
+        }
+    }
+}
+
+/// Wait for the next message on a command's channel, sending the signals
+/// requested in the meantime.
+async fn next_message(
+    channel: &mut russh::Channel<russh::client::Msg>,
+    signals: &mut SignalReceiver,
+) -> Option<ChannelMsg> {
+    loop {
+        let request = tokio::select! {
+            msg = channel.wait() => return msg,
+            Some(request) = signals.recv() => request,
+        };
+        let result = channel
+            .signal(request.signal)
+            .await
+            .map_err(|e| format!("Failed to send signal: {}", e));
+        let _ = request.reply.send(result);
+    }
+}
+
 /// Collect output from an SSH channel into the shared buffer.
 ///
 /// Uses batched writes to reduce lock contention - data is accumulated
-/// in local buffers and flushed to the shared buffer periodically or on exit.
+/// in local buffers and flushed to the shared buffer once it reaches
+/// [`FLUSH_THRESHOLD`], after [`FLUSH_INTERVAL`], or on exit.
 ///
-/// Returns the exit code when the channel closes.
+/// When `patterns` match, the output so far is flushed, the status settled
+/// and, with `terminate_on_match`, the command sent SIGTERM and closed.
+///
+/// Returns the exit code when the channel closes, or an error when the
+/// hung-channel watchdog force-closed it.
 async fn collect_async_output(
+    handle: &Arc<client::Handle<SshClientHandler>>,
     channel: &mut russh::Channel<russh::client::Msg>,
+    signals: &mut SignalReceiver,
     output: &Arc<tokio::sync::Mutex<OutputBuffer>>,
-) -> Option<i32> {
+    mut patterns: Option<PatternWatch>,
+    pattern_match: &tokio::sync::Mutex<Option<PatternMatch>>,
+    status_tx: &watch::Sender<AsyncCommandStatus>,
+) -> Result<Option<i32>, String> {
     use russh::ChannelMsg;
 
     let mut exit_code: Option<i32> = None;
+    let idle_timeout = resolve_channel_idle_timeout();
+    let mut hung = None;
 
     // Local buffers to batch output and reduce lock contention
     let mut local_stdout = Vec::with_capacity(4096);
     let mut local_stderr = Vec::with_capacity(1024);
 
     loop {
-        match channel.wait().await {
+        let batched = !local_stdout.is_empty() || !local_stderr.is_empty();
+        let msg = match idle_timeout {
+            _ if batched => {
+                match tokio::time::timeout(FLUSH_INTERVAL, next_message(channel, signals)).await {
+                    Ok(msg) => msg,
+                    Err(_) => {
+                        flush_output(output, &mut local_stdout, &mut local_stderr).await;
+                        continue;
+                    }
+                }
+            }
+            Some(idle) => match tokio::time::timeout(idle, next_message(channel, signals)).await {
+                Ok(msg) => msg,
+                Err(_) => {
+                    // A silent command on a live connection keeps running
+                    if measure_rtt(handle, RTT_PROBE_TIMEOUT).await.is_some() {
+                        continue;
+                    }
+                    hung = Some(idle);
+                    break;
+                }
+            },
+            None => next_message(channel, signals).await,
+        };
+
+        let mut found = None;
+        match msg {
             Some(ChannelMsg::Data { data }) => {
+                found = patterns.as_mut().and_then(|watch| watch.feed(false, &data));
                 local_stdout.extend_from_slice(&data);
                 // Flush when buffer exceeds threshold
                 if local_stdout.len() >= FLUSH_THRESHOLD {
-                    let mut buf = output.lock().await;
-                    buf.stdout.append(&mut local_stdout);
+                    output.lock().await.push_stdout(&mut local_stdout);
                 }
             }
             Some(ChannelMsg::ExtendedData { data, ext }) => {
                 // ext == 1 is stderr in SSH protocol
                 if ext == 1 {
+                    found = patterns.as_mut().and_then(|watch| watch.feed(true, &data));
                     local_stderr.extend_from_slice(&data);
                     // Flush when buffer exceeds threshold
                     if local_stderr.len() >= FLUSH_THRESHOLD {
-                        let mut buf = output.lock().await;
-                        buf.stderr.append(&mut local_stderr);
+                        output.lock().await.push_stderr(&mut local_stderr);
                     }
                 }
             }
@@ -699,25 +1210,86 @@ async fn collect_async_output(
                 break;
             }
         }
+
+        if let Some(found) = found {
+            patterns = None;
+            let terminate = found.terminated;
+            flush_output(output, &mut local_stdout, &mut local_stderr).await;
+            info!(
+                "Async command output matched its {:?} pattern: {}",
+                found.outcome, found.text
+            );
+            let status = match found.outcome {
+                PatternOutcome::Success => AsyncCommandStatus::Completed,
+                PatternOutcome::Failure => AsyncCommandStatus::Failed,
+            };
+            *pattern_match.lock().await = Some(found);
+            settle(status_tx, status);
+            if terminate {
+                let _ = channel.signal(russh::Sig::TERM).await;
+                break;
+            }
+        }
     }
 
     // Final flush of remaining local data
     if !local_stdout.is_empty() || !local_stderr.is_empty() {
-        let mut buf = output.lock().await;
-        buf.stdout.append(&mut local_stdout);
-        buf.stderr.append(&mut local_stderr);
+        flush_output(output, &mut local_stdout, &mut local_stderr).await;
+    }
+
+    if let Some(idle) = hung {
+        // The server stopped answering; do not wait on it to acknowledge the close
+        let _ = tokio::time::timeout(RTT_PROBE_TIMEOUT, channel.close()).await;
+        return Err(format!(
+            "Channel hung: no output or close for {}s and the server did not answer a keepalive; channel force-closed",
+            idle.as_secs()
+        ));
     }
 
     // Close channel gracefully
     let _ = channel.close().await;
 
-    exit_code
+    Ok(exit_code)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    mod working_directory {
+        use super::*;
+
+        #[test]
+        fn test_cwd_is_one_quoted_word() {
+            assert_eq!(
+                cd_command("/srv/my app", "make"),
+                "cd -- '/srv/my app' || exit 1; make"
+            );
+            assert_eq!(
+                cd_command("/tmp/x'; rm -rf /", "ls"),
+                r"cd -- '/tmp/x'\''; rm -rf /' || exit 1; ls"
+            );
+        }
+
+        #[test]
+        fn test_tilde_expands_to_home() {
+            assert_eq!(cd_command("~", "ls"), "cd -- \"$HOME\" || exit 1; ls");
+            assert_eq!(
+                cd_command("~/build dir", "ls"),
+                "cd -- \"$HOME\"/'build dir' || exit 1; ls"
+            );
+            // Only a leading ~/ is special
+            assert_eq!(cd_command("~root", "ls"), "cd -- '~root' || exit 1; ls");
+        }
+
+        #[test]
+        fn test_validate_cwd() {
+            assert!(validate_cwd("/var/log").is_ok());
+            assert!(validate_cwd("  ").is_err());
+            assert!(validate_cwd("/tmp/\0").is_err());
+        }
+    }
+
     mod address_parsing {
         use super::*;
 
@@ -828,35 +1400,88 @@ mod tests {
 
         #[test]
         fn test_builds_config_with_inactivity_timeout() {
-            let config = build_client_config(Duration::from_secs(300), true, false);
+            let config = build_client_config(
+                Duration::from_secs(300),
+                true,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
             assert_eq!(config.inactivity_timeout, Some(Duration::from_secs(300)));
         }
 
         #[test]
         fn test_builds_config_with_keepalive() {
-            let config = build_client_config(Duration::from_secs(300), true, false);
+            let config = build_client_config(
+                Duration::from_secs(300),
+                true,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
             assert_eq!(config.keepalive_interval, Some(Duration::from_secs(30)));
             assert_eq!(config.keepalive_max, 3);
         }
 
+        #[test]
+        fn test_keepalive_zero_interval_disables() {
+            let keepalive = Keepalive {
+                interval_secs: 0,
+                max: 0,
+            };
+            let config = build_client_config(
+                Duration::from_secs(300),
+                true,
+                false,
+                keepalive,
+                &AlgorithmPreferences::default(),
+            );
+            assert_eq!(config.keepalive_interval, None);
+            assert_eq!(config.keepalive_max, 0);
+        }
+
         #[test]
         fn test_compression_enabled_includes_zlib() {
-            let config = build_client_config(Duration::from_secs(300), true, false);
+            let config = build_client_config(
+                Duration::from_secs(300),
+                true,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
             let compression = &config.preferred.compression;
             assert!(!compression.is_empty());
         }
 
         #[test]
         fn test_compression_disabled() {
-            let config = build_client_config(Duration::from_secs(300), false, false);
+            let config = build_client_config(
+                Duration::from_secs(300),
+                false,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
             let compression = &config.preferred.compression;
             assert!(!compression.is_empty());
         }
 
         #[test]
         fn test_different_inactivity_timeouts() {
-            let config1 = build_client_config(Duration::from_secs(60), true, false);
-            let config2 = build_client_config(Duration::from_secs(600), true, false);
+            let config1 = build_client_config(
+                Duration::from_secs(60),
+                true,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
+            let config2 = build_client_config(
+                Duration::from_secs(600),
+                true,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
 
             assert_eq!(config1.inactivity_timeout, Some(Duration::from_secs(60)));
             assert_eq!(config2.inactivity_timeout, Some(Duration::from_secs(600)));
@@ -864,18 +1489,104 @@ mod tests {
 
         #[test]
         fn test_persistent_disables_inactivity_timeout() {
-            let config = build_client_config(Duration::from_secs(300), true, true);
+            let config = build_client_config(
+                Duration::from_secs(300),
+                true,
+                true,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
             assert_eq!(config.inactivity_timeout, None);
             assert_eq!(config.keepalive_interval, Some(Duration::from_secs(30)));
         }
 
         #[test]
         fn test_non_persistent_has_inactivity_timeout() {
-            let config = build_client_config(Duration::from_secs(300), true, false);
+            let config = build_client_config(
+                Duration::from_secs(300),
+                true,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+            );
             assert_eq!(config.inactivity_timeout, Some(Duration::from_secs(300)));
         }
     }
 
+    mod jump_hosts {
+        use super::*;
+
+        fn jump(address: &str) -> JumpHost {
+            JumpHost {
+                address: address.to_string(),
+                username: None,
+                password: None,
+                key_path: None,
+                key_data: None,
+            }
+        }
+
+        #[test]
+        fn test_validate_chain() {
+            assert!(validate_jump_hosts(&[]).is_ok());
+            assert!(validate_jump_hosts(&[jump("bastion:2222"), jump("10.0.1.5")]).is_ok());
+
+            let error = validate_jump_hosts(&[jump("bastion"), jump("inner:ssh")]).unwrap_err();
+            assert!(
+                error.starts_with("Jump host 2: Invalid port number"),
+                "{}",
+                error
+            );
+            assert_eq!(
+                validate_jump_hosts(&[jump(" ")]).unwrap_err(),
+                "Jump host 1 has an empty address"
+            );
+
+            let too_long = vec![jump("bastion"); MAX_JUMP_HOSTS + 1];
+            assert!(validate_jump_hosts(&too_long).is_err());
+        }
+
+        #[test]
+        fn test_label_defaults_to_target_user() {
+            let mut hop = jump("bastion:2222");
+            assert_eq!(hop.label("deploy"), "deploy@bastion:2222");
+            hop.username = Some("ops".to_string());
+            assert_eq!(hop.label("deploy"), "ops@bastion:2222");
+        }
+
+        #[tokio::test]
+        async fn test_failed_hop_is_named() {
+            // Nothing listens on port 1, so the first hop is refused
+            let result = connect_to_ssh_with_retry(
+                "10.0.1.5:22",
+                "deploy",
+                None,
+                None,
+                None,
+                Duration::from_secs(5),
+                Duration::from_secs(60),
+                0,
+                Duration::from_millis(10),
+                false,
+                false,
+                Keepalive::default(),
+                &AlgorithmPreferences::default(),
+                HostKeyChecking::No,
+                &[jump("127.0.0.1:1")],
+                false,
+                &Arc::new(ReverseForwards::default()),
+                &Arc::new(ByteCounters::default()),
+            )
+            .await;
+            let error = result.err().unwrap();
+            assert!(
+                error.contains("Jump host 1 (deploy@127.0.0.1:1): Failed to connect"),
+                "{}",
+                error
+            );
+        }
+    }
+
     mod retry_delay_constant {
         use super::*;
 
@@ -897,4 +1608,39 @@ mod tests {
             assert_eq!(MAX_RETRY_DELAY.as_millis(), 10_000);
         }
     }
+
+    mod channel_steps {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_success_passes_value() {
+            let result = channel_step("open channel", Duration::from_secs(1), async {
+                Ok::<_, String>(7)
+            })
+            .await;
+            assert_eq!(result, Ok(7));
+        }
+
+        #[tokio::test]
+        async fn test_error_names_step() {
+            let result = channel_step("request PTY", Duration::from_secs(1), async {
+                Err::<(), _>("rejected")
+            })
+            .await;
+            assert_eq!(result, Err("Failed to request PTY: rejected".to_string()));
+        }
+
+        #[tokio::test]
+        async fn test_hanging_step_times_out_as_retryable() {
+            let result = channel_step(
+                "open channel",
+                Duration::from_millis(10),
+                std::future::pending::<Result<(), String>>(),
+            )
+            .await;
+            let message = result.unwrap_err();
+            assert!(message.starts_with("Failed to open channel: timeout after"));
+            assert!(is_retryable_error(&message));
+        }
+    }
 }
@@ -0,0 +1,238 @@
+//! Live output of async commands (`ssh_execute` with `stream_output=true`).
+//!
+//! Instead of polling `ssh_get_command_output`, the client that started a
+//! command can have its output pushed as MCP logging notifications while it
+//! runs:
+//!
+//! ```json
+//! {"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","logger":"ssh_command",
+//!  "data":{"command_id":"...","seq":1,"stdout":"Compiling app v0.1.0\n"}}}
+//! ```
+//!
+//! `stdout` and `stderr` are present when the stream produced text since the
+//! previous notification. The last notification carries `"closed": true`,
+//! the final `status` and, once known, the `exit_code`.
+//!
+//! MCP progress notifications would need the request's `progressToken`,
+//! which the protocol library does not pass on to tools, so logging
+//! notifications are used as for shell subscriptions. The output also stays in
+//! the command's buffer, so polling works alongside streaming.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use tokio::sync::{Mutex, watch};
+use tracing::{debug, warn};
+
+use super::async_command::OutputBuffer;
+use super::notify::{NotificationSink, NotifyError};
+use super::subscription::take_utf8;
+use super::types::AsyncCommandStatus;
+
+/// `logger` of command output notifications
+pub(crate) const COMMAND_OUTPUT_LOGGER: &str = "ssh_command";
+
+/// Notification method used for command output
+pub(crate) const COMMAND_OUTPUT_METHOD: &str = "notifications/message";
+
+/// How often new output is checked for
+const STREAM_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Largest output of one stream per notification; the rest follows at once
+const MAX_NOTIFICATION_BYTES: usize = 64 * 1024;
+
+/// Stream position of one output stream already taken, and text waiting to
+/// be sent.
+#[derive(Default)]
+struct StreamCursor {
+    offset: u64,
+    pending: Vec<u8>,
+}
+
+impl StreamCursor {
+    /// Take up to [`MAX_NOTIFICATION_BYTES`] new bytes of `buffer`, whose
+    /// first byte is at stream position `start`; returns whether more are
+    /// left.
+    fn advance(&mut self, buffer: &[u8], start: u64) -> bool {
+        // Output evicted before it was taken is skipped
+        let from = (self.offset.saturating_sub(start) as usize).min(buffer.len());
+        let to = buffer.len().min(from + MAX_NOTIFICATION_BYTES);
+        self.pending.extend_from_slice(&buffer[from..to]);
+        self.offset = start + to as u64;
+        to < buffer.len()
+    }
+
+    /// Complete characters taken so far; everything once `last`.
+    fn text(&mut self, last: bool) -> String {
+        if last {
+            let text = String::from_utf8_lossy(&self.pending).into_owned();
+            self.pending.clear();
+            text
+        } else {
+            take_utf8(&mut self.pending)
+        }
+    }
+}
+
+fn notification(command_id: &str, data: Value) -> Value {
+    let mut data = data;
+    data["command_id"] = json!(command_id);
+    json!({"level": "info", "logger": COMMAND_OUTPUT_LOGGER, "data": data})
+}
+
+/// Push the output of `command_id` to `sink` until the command finishes.
+pub(crate) fn stream(
+    command_id: String,
+    output: Arc<Mutex<OutputBuffer>>,
+    status: watch::Receiver<AsyncCommandStatus>,
+    exit_code: Arc<Mutex<Option<i32>>>,
+    sink: NotificationSink,
+) {
+    tokio::spawn(forward(command_id, output, status, exit_code, sink));
+}
+
+async fn forward(
+    command_id: String,
+    output: Arc<Mutex<OutputBuffer>>,
+    mut status: watch::Receiver<AsyncCommandStatus>,
+    exit_code: Arc<Mutex<Option<i32>>>,
+    sink: NotificationSink,
+) {
+    let mut stdout = StreamCursor::default();
+    let mut stderr = StreamCursor::default();
+    let mut seq: u64 = 0;
+    let mut finished = false;
+
+    loop {
+        let more = {
+            let buffer = output.lock().await;
+            let (stdout_start, stderr_start) = buffer.stream_starts();
+            let more_stdout = stdout.advance(&buffer.stdout, stdout_start);
+            let more_stderr = stderr.advance(&buffer.stderr, stderr_start);
+            more_stdout || more_stderr
+        };
+        let last = finished && !more;
+
+        let mut data = json!({});
+        for (key, cursor) in [("stdout", &mut stdout), ("stderr", &mut stderr)] {
+            let text = cursor.text(last);
+            if !text.is_empty() {
+                data[key] = json!(text);
+            }
+        }
+        if data.as_object().is_some_and(|fields| !fields.is_empty()) {
+            seq += 1;
+            data["seq"] = json!(seq);
+            match sink.notify(COMMAND_OUTPUT_METHOD, notification(&command_id, data)) {
+                Ok(()) => {}
+                Err(NotifyError::Full) => warn!(
+                    "Client is not reading notifications; dropped output of command {}",
+                    command_id
+                ),
+                Err(NotifyError::Closed) => {
+                    debug!("Output subscriber of command {} is gone", command_id);
+                    return;
+                }
+            }
+        }
+
+        if last {
+            break;
+        }
+        if !more && !finished {
+            // A dropped status sender means the command is gone too
+            finished = tokio::select! {
+                _ = status.wait_for(|status| *status != AsyncCommandStatus::Running) => true,
+                _ = tokio::time::sleep(STREAM_INTERVAL) => false,
+            };
+        }
+    }
+
+    let final_status = *status.borrow();
+    let mut data = json!({"seq": seq + 1, "closed": true, "status": final_status});
+    if let Some(code) = *exit_code.lock().await {
+        data["exit_code"] = json!(code);
+    }
+    let _ = sink.notify(COMMAND_OUTPUT_METHOD, notification(&command_id, data));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    async fn next(rx: &mut mpsc::Receiver<Value>) -> Value {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cursor_caps_each_take() {
+        let buffer = vec![b'x'; MAX_NOTIFICATION_BYTES + 10];
+        let mut cursor = StreamCursor::default();
+        assert!(cursor.advance(&buffer, 0));
+        assert_eq!(cursor.text(false).len(), MAX_NOTIFICATION_BYTES);
+        assert!(!cursor.advance(&buffer, 0));
+        assert_eq!(cursor.text(false).len(), 10);
+    }
+
+    #[test]
+    fn test_cursor_flushes_split_character_at_end() {
+        let mut cursor = StreamCursor::default();
+        cursor.advance(b"caf\xC3", 0);
+        assert_eq!(cursor.text(false), "caf");
+        assert_eq!(cursor.text(true), "\u{FFFD}");
+    }
+
+    #[test]
+    fn test_cursor_follows_evicted_buffer() {
+        let mut cursor = StreamCursor::default();
+        cursor.advance(b"abcd", 0);
+        assert_eq!(cursor.text(false), "abcd");
+        // "ab" evicted, "ef" appended
+        cursor.advance(b"cdef", 2);
+        assert_eq!(cursor.text(false), "ef");
+        // Evicted past the cursor: the lost output is skipped
+        cursor.advance(b"ijkl", 8);
+        assert_eq!(cursor.text(false), "ijkl");
+    }
+
+    #[tokio::test]
+    async fn test_streams_until_finished() {
+        let output = Arc::new(Mutex::new(OutputBuffer::default()));
+        let (status_tx, status_rx) = watch::channel(AsyncCommandStatus::Running);
+        let exit_code = Arc::new(Mutex::new(None));
+        let (sink, mut rx) = NotificationSink::channel();
+        stream(
+            "cmd-1".to_string(),
+            output.clone(),
+            status_rx,
+            exit_code.clone(),
+            sink,
+        );
+
+        output.lock().await.stdout.extend_from_slice(b"building\n");
+        let first = next(&mut rx).await;
+        assert_eq!(first["method"], COMMAND_OUTPUT_METHOD);
+        assert_eq!(first["params"]["logger"], COMMAND_OUTPUT_LOGGER);
+        assert_eq!(first["params"]["data"]["command_id"], "cmd-1");
+        assert_eq!(first["params"]["data"]["stdout"], "building\n");
+        assert_eq!(first["params"]["data"]["seq"], 1);
+
+        output.lock().await.stderr.extend_from_slice(b"warning\n");
+        *exit_code.lock().await = Some(0);
+        status_tx.send(AsyncCommandStatus::Completed).unwrap();
+        let second = next(&mut rx).await;
+        assert_eq!(second["params"]["data"]["stderr"], "warning\n");
+        assert!(second["params"]["data"].get("stdout").is_none());
+
+        let last = next(&mut rx).await;
+        assert_eq!(last["params"]["data"]["closed"], true);
+        assert_eq!(last["params"]["data"]["status"], "completed");
+        assert_eq!(last["params"]["data"]["exit_code"], 0);
+        assert_eq!(last["params"]["data"]["seq"], 3);
+    }
+}
@@ -0,0 +1,457 @@
+//! Remote command wrapping.
+//!
+//! Options that change how a command runs on the remote host (CPU and I/O
+//! priority, resource limits) are applied by wrapping the command line before
+//! it is sent to the exec channel. The original command is kept for responses and
+//! listings; only the executed string is wrapped.
+//!
+//! Wrappers are POSIX `sh` snippets. Tools that may be missing on the remote
+//! OS (e.g. `ionice` on BSD/macOS) are probed with `command -v` and skipped
+//! when unavailable, so the command still runs.
+//!
+//! Wrappers nest: the [`Sandbox`] wraps the [`Priority`]-wrapped command, so
+//! resource limits also cover `nice`/`ionice`.
+
+use std::fmt;
+
+use super::types::SandboxLimits;
+
+/// Quote a string for safe use as a single POSIX shell word.
+pub(crate) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Range accepted by `nice -n`
+const NICE_RANGE: std::ops::RangeInclusive<i32> = -20..=19;
+
+/// I/O scheduling class passed to `ionice -c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IoClass {
+    /// Only gets disk time when no other process needs it
+    Idle,
+    /// Default class, scheduled fairly with other processes
+    BestEffort,
+    /// Always served first (requires root on most systems)
+    Realtime,
+}
+
+impl IoClass {
+    fn ionice_class(self) -> u8 {
+        match self {
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        }
+    }
+}
+
+impl fmt::Display for IoClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IoClass::Idle => write!(f, "idle"),
+            IoClass::BestEffort => write!(f, "best-effort"),
+            IoClass::Realtime => write!(f, "realtime"),
+        }
+    }
+}
+
+/// CPU (`nice`) and I/O (`ionice`) priority for a remote command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Priority {
+    /// Niceness adjustment, -20 (highest priority) to 19 (lowest)
+    pub nice: Option<i32>,
+    /// I/O scheduling class
+    pub io_class: Option<IoClass>,
+}
+
+impl Priority {
+    /// Validate tool parameters.
+    pub fn from_params(nice: Option<i32>, ionice: Option<&str>) -> Result<Self, String> {
+        if let Some(value) = nice
+            && !NICE_RANGE.contains(&value)
+        {
+            return Err(format!(
+                "nice must be between {} and {}, got {}",
+                NICE_RANGE.start(),
+                NICE_RANGE.end(),
+                value
+            ));
+        }
+
+        let io_class = match ionice {
+            None => None,
+            Some("idle") => Some(IoClass::Idle),
+            Some("best-effort") => Some(IoClass::BestEffort),
+            Some("realtime") => Some(IoClass::Realtime),
+            Some(other) => {
+                return Err(format!(
+                    "ionice must be \"idle\", \"best-effort\" or \"realtime\", got \"{}\"",
+                    other
+                ));
+            }
+        };
+
+        Ok(Self { nice, io_class })
+    }
+
+    /// Whether any priority setting is requested.
+    pub fn is_default(&self) -> bool {
+        self.nice.is_none() && self.io_class.is_none()
+    }
+
+    /// Wrap `command` so it runs with this priority where the remote OS supports it.
+    pub fn wrap(&self, command: &str) -> String {
+        if self.is_default() {
+            return command.to_string();
+        }
+
+        let mut script = String::from("p=");
+        if let Some(nice) = self.nice {
+            script.push_str(&format!(
+                "; command -v nice >/dev/null 2>&1 && p=\"$p nice -n {}\"",
+                nice
+            ));
+        }
+        if let Some(io_class) = self.io_class {
+            script.push_str(&format!(
+                "; command -v ionice >/dev/null 2>&1 && p=\"$p ionice -c {}\"",
+                io_class.ionice_class()
+            ));
+        }
+        script.push_str(&format!("; exec $p sh -c {}", shell_quote(command)));
+        script
+    }
+}
+
+/// Grace period between SIGTERM and SIGKILL for the wall-clock limit
+const SANDBOX_KILL_AFTER_SECS: u64 = 5;
+
+/// Largest `cgroup_cpu_percent` accepted (256 cores)
+const MAX_CGROUP_CPU_PERCENT: u64 = 25_600;
+
+/// Resource limits enforced on the remote host.
+///
+/// `ulimit` caps are set in a POSIX `sh` before the command starts and fail
+/// closed: if a cap cannot be applied the command does not run (exit 126).
+/// `timeout(1)` and `systemd-run` are probed; when missing, a `sandbox:` notice
+/// goes to stderr and the command runs without that limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Sandbox {
+    pub limits: SandboxLimits,
+}
+
+impl Sandbox {
+    /// Combine per-call limits with the operator's ceiling, keeping the stricter
+    /// value of each limit.
+    pub fn resolve(
+        requested: Option<SandboxLimits>,
+        ceiling: &SandboxLimits,
+    ) -> Result<Self, String> {
+        let requested = requested.unwrap_or_default();
+        let fields = [
+            ("wall_secs", requested.wall_secs),
+            ("cpu_secs", requested.cpu_secs),
+            ("max_file_mb", requested.max_file_mb),
+            ("max_open_files", requested.max_open_files),
+            ("max_memory_mb", requested.max_memory_mb),
+            ("cgroup_memory_mb", requested.cgroup_memory_mb),
+            ("cgroup_cpu_percent", requested.cgroup_cpu_percent),
+        ];
+        if let Some((name, _)) = fields.iter().find(|(_, value)| *value == Some(0)) {
+            return Err(format!("sandbox.{} must be at least 1", name));
+        }
+        if let Some(percent) = requested.cgroup_cpu_percent
+            && percent > MAX_CGROUP_CPU_PERCENT
+        {
+            return Err(format!(
+                "sandbox.cgroup_cpu_percent must be at most {}, got {}",
+                MAX_CGROUP_CPU_PERCENT, percent
+            ));
+        }
+
+        fn strictest(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        // A zero in the operator config means "no limit"
+        let ceiling_value = |value: Option<u64>| value.filter(|value| *value > 0);
+        Ok(Self {
+            limits: SandboxLimits {
+                wall_secs: strictest(requested.wall_secs, ceiling_value(ceiling.wall_secs)),
+                cpu_secs: strictest(requested.cpu_secs, ceiling_value(ceiling.cpu_secs)),
+                max_file_mb: strictest(requested.max_file_mb, ceiling_value(ceiling.max_file_mb)),
+                max_open_files: strictest(
+                    requested.max_open_files,
+                    ceiling_value(ceiling.max_open_files),
+                ),
+                max_memory_mb: strictest(
+                    requested.max_memory_mb,
+                    ceiling_value(ceiling.max_memory_mb),
+                ),
+                cgroup_memory_mb: strictest(
+                    requested.cgroup_memory_mb,
+                    ceiling_value(ceiling.cgroup_memory_mb),
+                ),
+                cgroup_cpu_percent: strictest(
+                    requested.cgroup_cpu_percent,
+                    ceiling_value(ceiling.cgroup_cpu_percent),
+                ),
+            },
+        })
+    }
+
+    /// Whether any limit is set.
+    pub fn is_default(&self) -> bool {
+        self.limits == SandboxLimits::default()
+    }
+
+    /// Wrap `command` so it runs under these limits.
+    pub fn wrap(&self, command: &str) -> String {
+        if self.is_default() {
+            return command.to_string();
+        }
+        let limits = &self.limits;
+
+        let mut script = vec![r#"fail() { echo "sandbox: $1" >&2; exit 126; }"#.to_string()];
+        // POSIX sh counts ulimit -f in 512-byte blocks and -v in KiB
+        let ulimits = [
+            ("t", limits.cpu_secs),
+            ("f", limits.max_file_mb.map(|mb| mb.saturating_mul(2048))),
+            ("n", limits.max_open_files),
+            ("v", limits.max_memory_mb.map(|mb| mb.saturating_mul(1024))),
+        ];
+        for (flag, value) in ulimits {
+            if let Some(value) = value {
+                script.push(format!(
+                    "ulimit -{flag} {value} || fail 'cannot set ulimit -{flag} {value}'"
+                ));
+            }
+        }
+
+        script.push("p=".to_string());
+        if let Some(secs) = limits.wall_secs {
+            script.push(format!(
+                "if command -v timeout >/dev/null 2>&1; then p=\"timeout -k {} {}\"; \
+                 else echo 'sandbox: timeout(1) not found, wall-clock limit skipped' >&2; fi",
+                SANDBOX_KILL_AFTER_SECS, secs
+            ));
+        }
+        let mut properties = Vec::new();
+        if let Some(mb) = limits.cgroup_memory_mb {
+            properties.push(format!("-p MemoryMax={}M", mb));
+        }
+        if let Some(percent) = limits.cgroup_cpu_percent {
+            properties.push(format!("-p CPUQuota={}%", percent));
+        }
+        if !properties.is_empty() {
+            script.push(format!(
+                "if command -v systemd-run >/dev/null 2>&1 && systemd-run --user --scope --quiet true >/dev/null 2>&1; \
+                 then p=\"systemd-run --user --scope --quiet {} $p\"; \
+                 else echo 'sandbox: systemd-run --user unavailable, cgroup limits skipped' >&2; fi",
+                properties.join(" ")
+            ));
+        }
+        script.push(format!("exec $p sh -c {}", shell_quote(command)));
+
+        format!("sh -c {}", shell_quote(&script.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod quoting {
+        use super::*;
+
+        #[test]
+        fn test_plain_string() {
+            assert_eq!(shell_quote("ls -la"), "'ls -la'");
+        }
+
+        #[test]
+        fn test_embedded_single_quote() {
+            assert_eq!(shell_quote("echo 'hi'"), r"'echo '\''hi'\'''");
+        }
+
+        #[test]
+        fn test_empty_string() {
+            assert_eq!(shell_quote(""), "''");
+        }
+    }
+
+    mod priority {
+        use super::*;
+
+        #[test]
+        fn test_default_leaves_command_untouched() {
+            let priority = Priority::from_params(None, None).unwrap();
+            assert!(priority.is_default());
+            assert_eq!(priority.wrap("make -j8"), "make -j8");
+        }
+
+        #[test]
+        fn test_nice_only() {
+            let priority = Priority::from_params(Some(10), None).unwrap();
+            assert_eq!(
+                priority.wrap("make -j8"),
+                "p=; command -v nice >/dev/null 2>&1 && p=\"$p nice -n 10\"; exec $p sh -c 'make -j8'"
+            );
+        }
+
+        #[test]
+        fn test_nice_and_ionice() {
+            let priority = Priority::from_params(Some(19), Some("idle")).unwrap();
+            let wrapped = priority.wrap("tar czf /tmp/a.tgz /var/log");
+            assert!(wrapped.contains("nice -n 19"));
+            assert!(wrapped.contains("ionice -c 3"));
+            assert!(wrapped.ends_with("exec $p sh -c 'tar czf /tmp/a.tgz /var/log'"));
+        }
+
+        #[test]
+        fn test_ionice_classes() {
+            for (name, class) in [("idle", 3), ("best-effort", 2), ("realtime", 1)] {
+                let priority = Priority::from_params(None, Some(name)).unwrap();
+                assert!(
+                    priority
+                        .wrap("true")
+                        .contains(&format!("ionice -c {}", class))
+                );
+                assert_eq!(priority.io_class.unwrap().to_string(), name);
+            }
+        }
+
+        #[test]
+        fn test_nice_out_of_range() {
+            assert!(Priority::from_params(Some(20), None).is_err());
+            assert!(Priority::from_params(Some(-21), None).is_err());
+            assert!(Priority::from_params(Some(-20), None).is_ok());
+        }
+
+        #[test]
+        fn test_unknown_ionice_class() {
+            let err = Priority::from_params(None, Some("low")).unwrap_err();
+            assert!(err.contains("ionice"));
+        }
+
+        #[test]
+        fn test_command_with_quotes_is_escaped() {
+            let priority = Priority::from_params(Some(5), None).unwrap();
+            assert!(
+                priority
+                    .wrap("echo 'a b'")
+                    .ends_with(r"sh -c 'echo '\''a b'\'''")
+            );
+        }
+    }
+
+    mod sandbox {
+        use super::*;
+
+        fn limits() -> SandboxLimits {
+            SandboxLimits::default()
+        }
+
+        #[test]
+        fn test_default_leaves_command_untouched() {
+            let sandbox = Sandbox::resolve(None, &limits()).unwrap();
+            assert!(sandbox.is_default());
+            assert_eq!(sandbox.wrap("make -j8"), "make -j8");
+        }
+
+        #[test]
+        fn test_strictest_limit_wins() {
+            let ceiling = SandboxLimits {
+                wall_secs: Some(600),
+                cpu_secs: Some(0),
+                max_open_files: Some(256),
+                ..limits()
+            };
+            let requested = SandboxLimits {
+                wall_secs: Some(60),
+                cpu_secs: Some(30),
+                max_open_files: Some(1024),
+                ..limits()
+            };
+            let sandbox = Sandbox::resolve(Some(requested), &ceiling).unwrap();
+            assert_eq!(sandbox.limits.wall_secs, Some(60));
+            assert_eq!(sandbox.limits.cpu_secs, Some(30));
+            assert_eq!(sandbox.limits.max_open_files, Some(256));
+
+            let operator_only = Sandbox::resolve(None, &ceiling).unwrap();
+            assert_eq!(operator_only.limits.wall_secs, Some(600));
+            assert_eq!(operator_only.limits.cpu_secs, None);
+        }
+
+        #[test]
+        fn test_invalid_limits() {
+            let zero = SandboxLimits {
+                max_file_mb: Some(0),
+                ..limits()
+            };
+            let err = Sandbox::resolve(Some(zero), &limits()).unwrap_err();
+            assert!(err.contains("max_file_mb"));
+
+            let cpu = SandboxLimits {
+                cgroup_cpu_percent: Some(MAX_CGROUP_CPU_PERCENT + 1),
+                ..limits()
+            };
+            assert!(Sandbox::resolve(Some(cpu), &limits()).is_err());
+        }
+
+        #[test]
+        fn test_ulimit_units() {
+            let requested = SandboxLimits {
+                cpu_secs: Some(10),
+                max_file_mb: Some(100),
+                max_open_files: Some(64),
+                max_memory_mb: Some(512),
+                ..limits()
+            };
+            let wrapped = Sandbox::resolve(Some(requested), &limits())
+                .unwrap()
+                .wrap("true");
+            assert!(wrapped.starts_with("sh -c '"));
+            assert!(wrapped.contains("ulimit -t 10 || fail"));
+            assert!(wrapped.contains("ulimit -f 204800 || fail"));
+            assert!(wrapped.contains("ulimit -n 64 || fail"));
+            assert!(wrapped.contains("ulimit -v 524288 || fail"));
+            assert!(!wrapped.contains("timeout -k"));
+            assert!(!wrapped.contains("systemd-run"));
+        }
+
+        #[test]
+        fn test_timeout_and_cgroup() {
+            let requested = SandboxLimits {
+                wall_secs: Some(30),
+                cgroup_memory_mb: Some(256),
+                cgroup_cpu_percent: Some(50),
+                ..limits()
+            };
+            let wrapped = Sandbox::resolve(Some(requested), &limits())
+                .unwrap()
+                .wrap("make");
+            assert!(wrapped.contains("timeout -k 5 30"));
+            assert!(wrapped.contains("-p MemoryMax=256M -p CPUQuota=50%"));
+            assert!(!wrapped.contains("ulimit"));
+        }
+
+        #[test]
+        fn test_nested_quoting() {
+            let requested = SandboxLimits {
+                wall_secs: Some(5),
+                ..limits()
+            };
+            let wrapped = Sandbox::resolve(Some(requested), &limits())
+                .unwrap()
+                .wrap("echo 'a b'");
+            let inner = format!("exec $p sh -c {}", shell_quote("echo 'a b'"));
+            // The outer script is quoted once more for `sh -c`
+            assert!(wrapped.ends_with(&shell_quote(&inner)[1..]));
+            assert!(wrapped.starts_with("sh -c 'fail() {"));
+        }
+    }
+}
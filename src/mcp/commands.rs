@@ -4,55 +4,194 @@
 //!
 //! - `ssh_connect`: Connect to an SSH server with retry logic
 //! - `ssh_execute`: Execute commands asynchronously (returns command_id for polling)
+//! - `ssh_execute_sync`: Execute a quick command and return its result in one call
 //! - `ssh_get_command_output`: Get output and status of a running command
+//! - `ssh_wait_commands`: Wait for several commands to finish
+//! - `ssh_execute_dag`: Run commands across sessions in dependency order
 //! - `ssh_list_commands`: List all async commands
 //! - `ssh_cancel_command`: Cancel a running command
+//! - `ssh_cancel_session_commands`: Cancel all running commands on a session
+//! - `ssh_reboot_and_wait`: Reboot a host and reconnect the session when it is back
 //! - `ssh_forward`: Setup port forwarding (feature-gated)
+//! - `ssh_check_port`: Test TCP reachability from the remote host
+//! - `ssh_open_console`: Serial-over-LAN / BMC console with vendor presets
+//! - `ssh_tmux_attach` / `ssh_tmux_list`: Persistent tmux/screen sessions bridged to shells
+//! - `ssh_symlink` / `ssh_readlink`: Create and read remote symbolic links
+//! - `ssh_glob`: Expand a remote glob pattern into matching paths
+//! - `ssh_upload_dir`: Recursively upload a local directory tree
+//! - `ssh_collect`: Archive remote paths and download them as one `.tar.gz`
 //! - `ssh_disconnect`: Disconnect and cleanup a session
 //! - `ssh_list_sessions`: List all active sessions
+//! - `ssh_lock_session` / `ssh_unlock_session`: Claim and release exclusive use of a session
 
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use futures::future::join_all;
+use futures::future::{join_all, select_all};
+use futures::stream::StreamExt;
 use poem_mcpserver::{Tools, content::Text, tool::StructuredContent};
 use russh::Disconnect;
-use tokio::sync::{Mutex, watch};
+use tokio::sync::{Mutex, broadcast, watch};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use super::async_command::{MAX_ASYNC_COMMANDS_PER_SESSION, OutputBuffer, RunningCommand};
+use super::access::{
+    SessionAccess, acting_agent, check_session_access, is_visible_to, resolve_share_mode,
+};
+use super::accounting::{USAGE, billed_agent, render_metrics};
+use super::affinity::{normalize_affinity_label, rank_sessions};
+use super::agent_forward;
+use super::algorithms::AlgorithmPreferences;
+use super::async_command::{
+    MAX_ASYNC_COMMANDS_PER_SESSION, OutputBuffer, OutputLimit, RunningCommand, normalize_label,
+};
+use super::audit::{self, AuditEvent, AuditQuery, DEFAULT_LOG_LIMIT, MAX_LOG_LIMIT, read_log};
+use super::batch::{resolve_batch_concurrency, resolve_batch_targets, resolve_connect_addresses};
+use super::capabilities::capabilities;
 use super::client::{
-    connect_to_ssh_with_retry, execute_ssh_command, execute_ssh_command_async,
-    execute_ssh_command_async_pty, open_pty_shell,
+    RTT_PROBE_TIMEOUT, TRANSPORT_ZLIB_LEVEL, cd_command, connect_to_ssh_with_retry,
+    execute_ssh_command, execute_ssh_command_async, execute_ssh_command_async_pty, measure_rtt,
+    open_pty_shell, validate_cwd, validate_jump_hosts,
 };
+use super::command_stream;
+use super::command_wrap::{Priority, Sandbox};
+use super::compress::{OutputFormat, encode_output, is_truncated_utf8};
 use super::config::{
-    resolve_command_timeout, resolve_compression, resolve_connect_timeout,
-    resolve_inactivity_timeout, resolve_max_retries, resolve_retry_delay,
+    MAX_COMPRESSION_LEVEL, resolve_auto_reconnect, resolve_channel_open_timeout,
+    resolve_command_timeout, resolve_compression, resolve_compression_level,
+    resolve_connect_timeout, resolve_identity_probe, resolve_inactivity_timeout, resolve_keepalive,
+    resolve_max_retries, resolve_output_limit, resolve_read_only, resolve_retry_delay,
+    server_config,
+    ssh_config::{SshConfig, existing_identity},
 };
+use super::console::{CONSOLE_COLS, CONSOLE_ROWS, CONSOLE_TERM, console_preset};
+use super::credentials::resolve_credential;
+use super::dag::{DagPlan, NodeRun, plan_dag, resolve_dag_concurrency, run_dag};
+use super::edit::{
+    MAX_EDIT_FILE_BYTES, WriteOptions, Written, apply_patch, check_path, parse_mode,
+    parse_unified_diff, read_file, sha256_hex, write_file,
+};
+use super::elicit;
+use super::error::{ErrorCode, ToolError};
+use super::exit_pattern::ExitPatterns;
+use super::expect::{ExpectPattern, expect, resolve_expect_timeout};
+use super::facts::{facts_summary, probe_facts, unavailable_facts};
 #[cfg(feature = "port_forward")]
 use super::forward::setup_port_forwarding;
+use super::glob::{glob_command, parse_matches, resolve_max_matches};
+use super::health::{check_sessions, monitor_interval};
+use super::helpers::{
+    HelperLibrary, forget_session as forget_helper_sync, is_synced, sync_helpers, sync_on_connect,
+};
+use super::host_limit::reserve_host_slot;
+use super::identity::probe_identity;
+use super::known_hosts::{HostKeyChecking, resolve_host_key_checking};
+use super::labels::{LabelSelector, update_labels, validate_labels};
+use super::lease::{SESSION_LEASES, resolve_lease_ttl, session_locked};
+use super::managed::{ensure_managed_session, managed_host_infos, take_pooled_session};
+use super::manifest::{
+    manifest_command, parse_manifest, resolve_manifest_depth, resolve_manifest_entries,
+};
 use super::message::{
     AgentDisconnectMessageBuilder, ConnectMessageBuilder, ExecuteMessageBuilder,
     ShellOpenMessageBuilder,
 };
+use super::notify::{self, NotificationSink};
+use super::output_parser::{OutputParser, parsed_response};
+use super::pager::{
+    MAX_PAGER_KEYS, PAGER_QUIET_PERIOD, PAGER_SETTLE_TIMEOUT, PagerPolicy, detect_pager_prompt,
+};
+use super::policy;
+use super::pool;
+use super::port_check::{check_port, resolve_port_check_timeout};
+use super::privilege_drop::verify_privilege_drop;
+use super::privileges::probe_privileges;
+use super::profiles::{find_profile, profile_address, profile_info, profile_jump_hosts, profiles};
+use super::quota::reserve_session_quota;
+use super::rate_limit::resolve_rate_limit;
+use super::read_only;
+use super::reboot::{FreshCredentials, reboot_and_wait, reboot_command, resolve_reboot_timeout};
+use super::reconnect::{self, ConnectParams, connection_lost};
+#[cfg(feature = "port_forward")]
+use super::reverse::{DEFAULT_LOCAL_HOST, DEFAULT_REMOTE_BIND_ADDRESS};
+use super::reverse::{REVERSE_FORWARDS, ReverseForwards, forward_info, restore_tunnels};
+use super::self_test::check_config;
+use super::session_env::{self, validate_env};
+use super::sftp::{list_dir, resolve_list_dir_entries};
 use super::shell::{ChannelWriter, MAX_SHELLS_PER_SESSION, RunningShell};
+use super::siem::HttpTarget;
+use super::signal::{self, SIGNAL_SETTLE_TIMEOUT, parse_signal, signal_channel};
 use super::storage::{
-    COMMAND_STORAGE, CommandStorage, SESSION_STORAGE, SHELL_STORAGE, SessionStorage, ShellStorage,
+    COMMAND_STORAGE, FORWARD_STORAGE, ForwardStorage, SESSION_STORAGE, SHELL_STORAGE, SessionRef,
+    ShellStorage, persistent,
+};
+use super::subscription;
+use super::symlink::{failure_message, parse_link_target, readlink_command, symlink_command};
+use super::tail::{
+    DEFAULT_TAIL_READ_LINES, DEFAULT_TAIL_TIMEOUT, MAX_TAIL_READ_LINES, MAX_TAIL_WAIT_SECS,
+    TAIL_OUTPUT_LIMIT, TAIL_POLL_INTERVAL, read_lines, tail_command,
+};
+use super::tmux::{Multiplexer, validate_session_name};
+use super::traffic::ByteCounters;
+use super::transfer::{
+    DEFAULT_COLLECT_MAX_BYTES, DEFAULT_COLLECT_TIMEOUT, DEFAULT_RELAY_TIMEOUT, DownloadError,
+    RelayError, collect_command, collect_warnings, confine_local_path, create_remote_dirs,
+    download_command_output, extract_archive, local_mode, relay_file, resolve_upload_concurrency,
+    upload_entries, walk_local_dir,
 };
 use super::types::{
-    AgentDisconnectResponse, AsyncCommandInfo, AsyncCommandStatus, PortForwardingResponse,
-    SessionInfo, SessionListResponse, ShellInfo, ShellStatus, SshAsyncOutputResponse,
-    SshCancelCommandResponse, SshConnectResponse, SshExecuteResponse, SshListCommandsResponse,
-    SshShellCloseResponse, SshShellOpenResponse, SshShellReadResponse,
+    AgentDisconnectResponse, AsyncCommandInfo, AsyncCommandStatus, AuditOutcome, BatchTarget,
+    BatchTargetResult, BatchTargetStatus, CommandWaitStatus, ConnectManyResult, DagNodeResult,
+    DagNodeSpec, DagNodeStatus, ForwardInfo, JumpHost, ManifestEntry, ManifestEntryKind,
+    PortForwardingResponse, PortState, PrivilegeDropReport, PrivilegeDropStatus,
+    ReverseForwardInfo, SandboxLimits, SessionActivity, SessionAffinity, SessionInfo,
+    SessionListResponse, ShellInfo, ShellStatus, SshAsyncOutputResponse, SshAuditLogResponse,
+    SshCancelCommandResponse, SshCancelForwardResponse, SshCancelReverseForwardResponse,
+    SshCancelSessionCommandsResponse, SshCapabilitiesResponse, SshCheckConfigResponse,
+    SshCheckPortResponse, SshCheckPrivilegesResponse, SshCollectResponse, SshCommandResponse,
+    SshCommandSignalResponse, SshConnectManyResponse, SshConnectResponse, SshCopyBetweenResponse,
+    SshExecuteBatchResponse, SshExecuteDagResponse, SshExecuteParsedResponse, SshExecuteResponse,
+    SshExecuteSyncResponse, SshGatherFactsResponse, SshGlobResponse, SshListCommandsResponse,
+    SshListDirResponse, SshListForwardsResponse, SshListManagedHostsResponse,
+    SshListProfilesResponse, SshListReverseForwardsResponse, SshLockSessionResponse,
+    SshManifestResponse, SshOpenConsoleResponse, SshPatchFileResponse, SshReadlinkResponse,
+    SshRebootAndWaitResponse, SshReverseForwardResponse, SshSelectSessionResponse,
+    SshShellCloseResponse, SshShellExpectResponse, SshShellOpenResponse, SshShellReadResponse,
+    SshShellSignalResponse, SshShellSubscribeResponse, SshShellSwitchUserResponse,
+    SshShellUnsubscribeResponse, SshSymlinkResponse, SshTailFileResponse, SshTailReadResponse,
+    SshTmuxAttachResponse, SshTmuxListResponse, SshUnlockSessionResponse, SshUpdateSessionResponse,
+    SshUploadDirResponse, SshUsageResponse, SshVerifyPrivilegeDropResponse,
+    SshWaitCommandsResponse, SshWriteFileResponse, SudoAccess, TransferStatus, WaitProgress,
+    round_ms,
+};
+use super::user_switch::{
+    DEFAULT_SWITCH_TIMEOUT, MAX_SWITCH_TIMEOUT_SECS, SWITCH_QUIET_PERIOD, SwitchMethod,
+    VERIFY_COMMAND, detect_failure, detect_password_prompt, parse_verified_user, validate_user,
 };
+use super::webhook::{deliver_callback, resolve_callback};
+
+/// Maximum time to wait for a tmux/screen session listing
+const MULTIPLEXER_LIST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum time for a remote symlink operation
+const SYMLINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum time for a remote glob expansion
+const GLOB_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest a single ssh_get_command_output call waits with renew_wait=true
+const MAX_RENEWED_WAIT: Duration = Duration::from_secs(1800);
 
 /// MCP SSH Commands tool implementation.
 ///
 /// This struct provides all SSH-related MCP tools for connecting to servers,
-/// executing commands, and managing port forwarding.
+/// executing commands, and managing port forwarding. Sessions and async
+/// commands live in the process-wide storage (see `storage::backend`), which
+/// every instance shares with the background tasks.
+#[derive(Default)]
 pub struct McpSSHCommands;
 
 #[Tools]
@@ -69,44 +208,269 @@ impl McpSSHCommands {
     /// `ssh_execute` provides non-blocking execution with progress monitoring.
     ///
     /// Use `persistent=true` for sessions that should remain open indefinitely.
+    ///
+    /// **Auto-reconnect:** With `auto_reconnect=true` the server keeps the
+    /// password and key content in memory (other sessions do not keep them)
+    /// and, when a command finds the connection dropped,
+    /// dials the host again under the same session_id and retries once; the
+    /// tool's response then has `reconnected: true`. Shells, async commands
+    /// and local forwards of the old connection are gone.
+    ///
+    /// **Sharing:** Sessions are private to their `agent_id` by default. Pass
+    /// `shared=true` to let other agents see the session in ssh_list_sessions and
+    /// reuse it (`share_mode="read_only"`, default) or also run commands, open
+    /// shells and forwards on it (`share_mode="full"`).
+    ///
+    /// **Jump hosts:** To reach a host that is only reachable through bastions,
+    /// list them in `jump_hosts` (first hop first, like `ssh -J`). Each hop is
+    /// tunneled through the previous one and can have its own username and
+    /// credentials; the chain closes when the session is disconnected.
+    ///
+    /// **ssh_config:** With `use_ssh_config=true` the address host is looked up
+    /// as a `Host` alias in `~/.ssh/config` (or `SSH_CONFIG_FILE`): HostName,
+    /// Port, User, IdentityFile and ProxyJump fill in what is not given.
+    ///
+    /// **Profiles:** `profile` names a connection target the operator defined
+    /// on the server (see ssh_list_profiles): its address, username, key or
+    /// credential, jump hosts and timeouts apply unless given here.
+    ///
+    /// **Credentials:** Prefer `credential_ref` over `password`/`key_data`: the
+    /// server looks the named credential up (environment or Vault), so secrets
+    /// stay out of the conversation. A credential is only sent to the hosts the
+    /// operator listed for it; other targets or jump hosts get `access_denied`.
+    /// Vault is reached through a local Vault Agent sidecar (plain HTTP on
+    /// loopback); without one, Vault credentials fail with `invalid_state`.
+    ///
+    /// **Password prompt:** Without any credentials only the local SSH agent is
+    /// tried. If the host refuses it the call fails with
+    /// `authentication_failed`, suggesting `credential_ref`. Only when the
+    /// operator enabled `[elicitation] password_prompt` and the MCP client
+    /// supports elicitation is the user asked for the password instead.
+    ///
+    /// **Environment:** `env` variables apply to every command started on the
+    /// session (ssh_execute, ssh_execute_sync, ssh_run_helper).
+    ///
+    /// **Read-only:** With `read_only=true` (or server-wide `SSH_READ_ONLY`)
+    /// commands that would change the host (`rm`, `sed -i`, package installs,
+    /// service restarts, redirects into files...) are refused with
+    /// `policy_violation`, and so are shell input, uploads, symlinks, helpers
+    /// and reboots.
     #[allow(clippy::too_many_arguments)]
     async fn ssh_connect(
         &self,
         /// Optional session ID to reuse - if provided and still connected, returns existing session
         session_id: Option<String>,
-        /// SSH server address in format "host:port" (e.g., "192.168.1.1:22")
-        address: String,
-        /// SSH username for authentication
-        username: String,
+        /// SSH server address in format "host:port" (e.g., "192.168.1.1:22"); optional with profile
+        address: Option<String>,
+        /// Name of a server-side connection profile (see ssh_list_profiles) supplying address, username, key, jump hosts and timeouts
+        profile: Option<String>,
+        /// SSH username for authentication (optional only when use_ssh_config resolves a User)
+        username: Option<String>,
         /// Password for password-based authentication (optional if using key or agent)
         password: Option<String>,
         /// Path to private key file for key-based authentication (optional)
         key_path: Option<String>,
+        /// Private key content (OpenSSH or PEM, passphrase-less) for when no key file is available, e.g. in a container (optional)
+        key_data: Option<String>,
+        /// Name of a server-side credential (see ssh_capabilities policies.credential_refs) supplying username, password and/or key, instead of passing secrets
+        credential_ref: Option<String>,
         /// Connection timeout in seconds (default: 30, env: SSH_CONNECT_TIMEOUT)
         timeout_secs: Option<u64>,
+        /// Default timeout in seconds of commands run on this session when a call gives none (default: SSH_COMMAND_TIMEOUT)
+        command_timeout_secs: Option<u64>,
         /// Maximum retry attempts for transient connection failures (default: 3, env: SSH_MAX_RETRIES)
         max_retries: Option<u32>,
         /// Initial delay between retries in milliseconds, uses exponential backoff (default: 1000, env: SSH_RETRY_DELAY_MS)
         retry_delay_ms: Option<u64>,
         /// Enable zlib compression for the SSH connection (default: true, env: SSH_COMPRESSION)
         compress: Option<bool>,
+        /// zlib compression level 0-1; 0 disables compression (env: SSH_COMPRESSION_LEVEL). The SSH transport only compresses at level 1, so higher levels are rejected.
+        compression_level: Option<u32>,
+        /// Seconds between SSH keepalives while the server is silent; 0 disables them (default: 30, env: SSH_KEEPALIVE_INTERVAL)
+        keepalive_interval_secs: Option<u64>,
+        /// Unanswered keepalives after which the connection is considered dead and dropped; 0 never drops it (default: 3, env: SSH_KEEPALIVE_MAX)
+        keepalive_max: Option<u32>,
+        /// Ciphers to offer, most preferred first, replacing the defaults: e.g. ["aes256-gcm@openssh.com", "aes256-ctr"] to restrict to FIPS-approved ones, or ["aes128-cbc"] for a legacy appliance
+        ciphers: Option<Vec<String>>,
+        /// Key exchange algorithms to offer, most preferred first, replacing the defaults (e.g. ["diffie-hellman-group14-sha1"])
+        kex_algorithms: Option<Vec<String>>,
+        /// Server host key algorithms to accept, most preferred first, replacing the defaults (e.g. ["ssh-rsa"])
+        host_key_algorithms: Option<Vec<String>>,
         /// Optional human-readable name for the session (helps identify sessions, e.g., "production-db", "staging-server")
         name: Option<String>,
+        /// Region hint for affinity-based selection (e.g., "eu-west-1"), see ssh_select_session
+        region: Option<String>,
+        /// Datacenter hint for affinity-based selection (e.g., "dub-2")
+        datacenter: Option<String>,
+        /// Labels for finding the session later, e.g. {"env": "prod", "role": "db"}; see ssh_list_sessions `labels` and ssh_update_session
+        labels: Option<BTreeMap<String, String>>,
         /// Keep session open indefinitely until explicitly disconnected (disables inactivity timeout, default: false)
         persistent: Option<bool>,
+        /// Reconnect with the same credentials when the connection drops and retry the failed command once; credentials stay in server memory for the session's lifetime (default: false, env: SSH_AUTO_RECONNECT)
+        auto_reconnect: Option<bool>,
+        /// Refuse commands that change the host and disable the write tools, for diagnostics without change risk (default: false; always on with env SSH_READ_ONLY)
+        read_only: Option<bool>,
         /// Optional agent identifier for grouping sessions (e.g., "claude-code-instance-abc123"). Use ssh_disconnect_agent to disconnect all sessions for an agent.
         agent_id: Option<String>,
-    ) -> Result<StructuredContent<SshConnectResponse>, String> {
+        /// Make the session visible and usable by other agent_ids (default: false, requires agent_id)
+        shared: Option<bool>,
+        /// Access for other agents when shared: "read_only" (default) or "full"
+        share_mode: Option<String>,
+        /// Host key verification against known_hosts: "yes" (host must be listed), "accept-new" (record unknown hosts, reject changed keys) or "no" (skip). Can only be stricter than the server's SSH_STRICT_HOST_KEY_CHECKING (default: accept-new)
+        strict_host_key_checking: Option<String>,
+        /// Bastions to tunnel through, first hop first (like ProxyJump / ssh -J); each with address and optional username, password, key_path, key_data
+        jump_hosts: Option<Vec<JumpHost>>,
+        /// Resolve the address as a Host alias of the OpenSSH client config (~/.ssh/config or SSH_CONFIG_FILE): HostName, Port, User, IdentityFile, ProxyJump (default: false)
+        use_ssh_config: Option<bool>,
+        /// Environment variables for every command run on this session (e.g. {"LANG": "C.UTF-8", "PATH": "/opt/bin:/usr/bin:/bin"}). Sent as SSH env requests; variables the server rejects are exported in the command line instead
+        env: Option<BTreeMap<String, String>>,
+        /// Forward the local ssh-agent (SSH_AUTH_SOCK) to commands and shells, like ssh -A, so they can run onward ssh or git over SSH with its keys; the remote host can use the agent while the session is open (default: false)
+        agent_forwarding: Option<bool>,
+    ) -> Result<StructuredContent<SshConnectResponse>, ToolError> {
+        let env = env.unwrap_or_default();
+        validate_env(&env).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let labels = labels.unwrap_or_default();
+        validate_labels(&labels).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let (mut username, mut key_path, mut credential_ref) = (username, key_path, credential_ref);
+        let (mut password, mut key_data) = (password, key_data);
+        let (mut jump_hosts, mut name) = (jump_hosts, name);
+        let (mut timeout_secs, mut command_timeout_secs) = (timeout_secs, command_timeout_secs);
+        let (mut region, mut datacenter) = (region, datacenter);
+        let mut address = match (address, profile.as_deref()) {
+            (address, Some(profile_name)) => {
+                let profile = find_profile(profile_name)?;
+                // Explicit credentials replace the profile's entirely
+                if password.is_none()
+                    && key_path.is_none()
+                    && key_data.is_none()
+                    && credential_ref.is_none()
+                {
+                    key_path = profile.key_path.clone();
+                    credential_ref = profile.credential_ref.clone();
+                }
+                username = username.or_else(|| profile.username.clone());
+                jump_hosts = jump_hosts.or_else(|| Some(profile_jump_hosts(profile)));
+                name = name.or_else(|| Some(profile_name.to_string()));
+                timeout_secs = timeout_secs.or(profile.connect_timeout_secs);
+                command_timeout_secs = command_timeout_secs.or(profile.command_timeout_secs);
+                region = region.or_else(|| profile.region.clone());
+                datacenter = datacenter.or_else(|| profile.datacenter.clone());
+                address.unwrap_or_else(|| profile_address(profile))
+            }
+            (Some(address), None) => address,
+            (None, None) => {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    "address is required unless a profile is given",
+                ));
+            }
+        };
+        let profile_name = profile;
+        let mut jump_hosts = jump_hosts.unwrap_or_default();
+        if credential_ref.is_some()
+            && (password.is_some() || key_path.is_some() || key_data.is_some())
+        {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                "credential_ref cannot be combined with password, key_path or key_data",
+            ));
+        }
+        let (mut config_user, mut config_identity) = (None, None);
+        if use_ssh_config.unwrap_or(false) {
+            let config =
+                SshConfig::load().map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+            let (resolved, options) = config.resolve_address(&address);
+            info!("Resolved {} via ssh_config to {}", address, resolved);
+            address = resolved;
+            config_user = options.user.clone();
+            config_identity = existing_identity(&options);
+            if jump_hosts.is_empty()
+                && let Some(proxy_jump) = options.proxy_jump.as_deref()
+            {
+                jump_hosts = config.jump_hosts(proxy_jump);
+            }
+        }
+        // Resolved once the hosts are final, so the credential's host list
+        // covers the address and hops actually dialed
+        if let Some(name) = credential_ref.as_deref() {
+            let targets: Vec<&str> = std::iter::once(address.as_str())
+                .chain(jump_hosts.iter().map(|jump| jump.address.as_str()))
+                .collect();
+            let credential = resolve_credential(name, agent_id.as_deref(), &targets).await?;
+            username = username.or(credential.username);
+            password = credential.password;
+            key_path = credential.key_path;
+            key_data = credential.key_data;
+        }
+        username = username.or(config_user);
+        if key_path.is_none() && key_data.is_none() {
+            key_path = config_identity;
+        }
+        let username = username.ok_or_else(|| {
+            ToolError::new(
+                ErrorCode::InvalidArgument,
+                "username is required unless use_ssh_config resolves a User for the host",
+            )
+        })?;
+        validate_jump_hosts(&jump_hosts)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let host_key_checking = strict_host_key_checking
+            .as_deref()
+            .map(HostKeyChecking::parse)
+            .transpose()
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let shared = resolve_share_mode(shared, share_mode.as_deref())
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        if shared.is_some() && agent_id.is_none() {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                "shared sessions require agent_id to record the owner",
+            ));
+        }
+        let region = normalize_affinity_label("region", region)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let datacenter = normalize_affinity_label("datacenter", datacenter)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
         let timeout = resolve_connect_timeout(timeout_secs);
         let inactivity_timeout = resolve_inactivity_timeout();
+        let keepalive = resolve_keepalive(keepalive_interval_secs, keepalive_max);
+        let algorithms = AlgorithmPreferences::parse(
+            ciphers.as_deref(),
+            kex_algorithms.as_deref(),
+            host_key_algorithms.as_deref(),
+        )
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
         let max_retries_val = resolve_max_retries(max_retries);
         let retry_delay = resolve_retry_delay(retry_delay_ms);
-        let compress = resolve_compression(compress);
+        if let Some(level) = compression_level
+            && level > MAX_COMPRESSION_LEVEL
+        {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "compression_level {} is not supported: the SSH transport compresses at level {}; use 0 to disable compression",
+                    level, TRANSPORT_ZLIB_LEVEL
+                ),
+            )
+            .with_detail("compression_level", level));
+        }
+        let compression_level = resolve_compression_level(compression_level);
+        let compress = resolve_compression(compress) && compression_level != Some(0);
         let persistent = persistent.unwrap_or(false);
+        let read_only = resolve_read_only(read_only);
+        let agent_forwarding = agent_forwarding.unwrap_or(false);
+        if agent_forwarding {
+            agent_forward::local_agent_socket()
+                .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        }
+        // Reverse forwards of a dead session, re-established on the new connection
+        let mut carried_reverse = Vec::new();
 
         // Check if session_id was provided for potential reuse
         if let Some(ref sid) = session_id {
             if let Some(session_ref) = SESSION_STORAGE.get(sid) {
+                check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
                 // Health check with 5 second timeout
                 let health_timeout = Duration::from_secs(5);
                 let now = chrono::Utc::now().to_rfc3339();
@@ -115,6 +479,10 @@ impl McpSSHCommands {
                     Ok(response) if !response.timed_out && response.exit_code == 0 => {
                         // Update health status in storage
                         SESSION_STORAGE.update_health(sid, now, true);
+                        if let Some(rtt) = measure_rtt(&session_ref.handle, RTT_PROBE_TIMEOUT).await
+                        {
+                            SESSION_STORAGE.record_rtt(sid, rtt);
+                        }
 
                         info!("Reusing healthy session {}", sid);
                         let reuse_agent_id = session_ref.info.agent_id.clone();
@@ -126,6 +494,7 @@ impl McpSSHCommands {
                         .with_agent_id(reuse_agent_id.as_deref())
                         .with_name(session_ref.info.name.as_deref())
                         .reused(true)
+                        .with_template(server_config().messages.connect_reused.as_deref())
                         .build();
                         return Ok(StructuredContent(SshConnectResponse {
                             session_id: sid.clone(),
@@ -133,19 +502,77 @@ impl McpSSHCommands {
                             message,
                             authenticated: true,
                             retry_attempts: 0,
+                            identity: session_ref.info.identity.clone(),
+                            reverse_forwards: Vec::new(),
+                            pooled: false,
                         }));
                     }
                     _ => {
                         // Session dead - remove it
                         warn!("Session {} is dead, removing", sid);
                         SESSION_STORAGE.remove(sid);
+                        carried_reverse =
+                            REVERSE_FORWARDS.take_for_reconnect(sid, agent_id.as_deref());
                     }
                 }
             } else {
                 info!("Session {} not found, creating new connection", sid);
+                carried_reverse = REVERSE_FORWARDS.take_for_reconnect(sid, agent_id.as_deref());
             }
         }
 
+        // A spare of a managed host pool skips the handshake and is already
+        // counted against the host cap; spares were verified with the default
+        // host key mode, so an explicit mode always dials; spares are direct
+        // connections, so a jump host chain does too; they do not forward
+        // the agent either, and are kept alive and negotiated with the default
+        // keepalive settings and algorithms
+        let dials = !jump_hosts.is_empty()
+            || agent_forwarding
+            || keepalive != resolve_keepalive(None, None)
+            || !algorithms.is_default();
+        let pooled = match host_key_checking {
+            Some(_) => None,
+            None if dials => None,
+            None => take_pooled_session(&address, &username, compress, persistent),
+        };
+        let mut params = ConnectParams {
+            address: address.clone(),
+            username: username.clone(),
+            password,
+            key_path,
+            key_data,
+            timeout,
+            inactivity_timeout,
+            compress,
+            persistent,
+            keepalive,
+            algorithms,
+            host_key_checking: resolve_host_key_checking(host_key_checking),
+            jump_hosts,
+            auto_reconnect: resolve_auto_reconnect(auto_reconnect),
+            credential_ref,
+            agent_forwarding,
+            redacted: None,
+        };
+        // A connection this agent parked with the same credentials skips the
+        // handshake too, and is also counted against the host cap already
+        let idle = match pooled {
+            None if pool::poolable(&params) => {
+                pool::take(&pool::pool_key(agent_id.as_deref(), &params)).await
+            }
+            _ => None,
+        };
+        let reused = pooled.is_some() || idle.is_some();
+        // Held until the new session is registered (or the connect fails)
+        let (_quota, _host_slot) = match reused {
+            true => (None, None),
+            false => (
+                Some(reserve_session_quota(agent_id.as_deref())?),
+                reserve_host_slot(&address)?,
+            ),
+        };
+
         info!(
             "Attempting SSH connection to {}@{} with timeout {}s, max_retries={}, retry_delay={}ms, compress={}, persistent={}, name={:?}, agent_id={:?}",
             username,
@@ -159,51 +586,155 @@ impl McpSSHCommands {
             agent_id
         );
 
-        match connect_to_ssh_with_retry(
-            &address,
-            &username,
-            password.as_deref(),
-            key_path.as_deref(),
-            timeout,
-            inactivity_timeout,
-            max_retries_val,
-            retry_delay,
-            compress,
-            persistent,
-        )
-        .await
-        {
+        let (reverse, traffic, connected) = match (pooled, idle) {
+            (Some(warm), _) => (warm.reverse, warm.traffic, Ok((warm.handle, 0))),
+            (None, Some(idle)) => (idle.reverse, idle.traffic, Ok((idle.handle, 0))),
+            (None, None) => {
+                let reverse = Arc::new(ReverseForwards::default());
+                let traffic = Arc::new(ByteCounters::default());
+                let connected = connect_to_ssh_with_retry(
+                    &address,
+                    &username,
+                    params.password.as_deref(),
+                    params.key_path.as_deref(),
+                    params.key_data.as_deref(),
+                    timeout,
+                    inactivity_timeout,
+                    max_retries_val,
+                    retry_delay,
+                    compress,
+                    persistent,
+                    keepalive,
+                    &params.algorithms,
+                    params.host_key_checking,
+                    &params.jump_hosts,
+                    agent_forwarding,
+                    &reverse,
+                    &traffic,
+                )
+                .await
+                .map(|(handle, retry_attempts)| (Arc::new(handle), retry_attempts));
+                // Only the agent was tried: ask the user for the password
+                let connected = match connected {
+                    Err(e)
+                        if params.password.is_none()
+                            && params.key_path.is_none()
+                            && params.key_data.is_none()
+                            && elicit::agent_auth_failed(&e) =>
+                    {
+                        match elicit::ask_password(&username, &address).await {
+                            Some(password) => {
+                                params.password = Some(password);
+                                params
+                                    .dial(&reverse, &traffic)
+                                    .await
+                                    .map(|handle| (Arc::new(handle), 0))
+                            }
+                            None => Err(e),
+                        }
+                    }
+                    connected => connected,
+                };
+                (reverse, traffic, connected)
+            }
+        };
+        match connected {
             Ok((handle, retry_attempts)) => {
+                let identity = if resolve_identity_probe() {
+                    probe_identity(&handle).await
+                } else {
+                    None
+                };
+
                 let new_session_id = Uuid::new_v4().to_string();
                 let connected_at = chrono::Utc::now().to_rfc3339();
 
-                let session_info = SessionInfo {
+                let mut session_info = SessionInfo {
                     session_id: new_session_id.clone(),
                     name: name.clone(),
                     agent_id: agent_id.clone(),
+                    shared,
                     host: address.clone(),
                     username: username.clone(),
                     connected_at,
                     default_timeout_secs: timeout.as_secs(),
                     retry_attempts,
                     compression_enabled: compress,
+                    compression_level: compress.then_some(TRANSPORT_ZLIB_LEVEL),
+                    keepalive_interval_secs: keepalive.interval_secs,
+                    keepalive_max: keepalive.max,
+                    algorithms: None,
                     last_health_check: None,
                     healthy: None,
+                    health_history: Vec::new(),
+                    flapping: false,
+                    identity: identity.clone(),
+                    rtt_ms: None,
+                    rtt_avg_ms: None,
+                    region,
+                    datacenter,
+                    jump_hosts: params
+                        .jump_hosts
+                        .iter()
+                        .map(|jump| jump.label(&username))
+                        .collect(),
+                    rebooting: false,
+                    read_only,
+                    agent_forwarding,
+                    profile: profile_name,
+                    command_timeout_secs,
+                    bytes_sent: 0,
+                    bytes_received: 0,
+                    stats: Default::default(),
+                    labels,
                 };
+                if let Some(rtt) = measure_rtt(&handle, RTT_PROBE_TIMEOUT).await {
+                    session_info.record_rtt(rtt);
+                }
+
+                if !carried_reverse.is_empty() {
+                    info!(
+                        "Re-establishing {} reverse forward(s) of session {:?}",
+                        carried_reverse.len(),
+                        session_id
+                    );
+                }
+                let reverse_forwards: Vec<ReverseForwardInfo> =
+                    restore_tunnels(&handle, &reverse, carried_reverse)
+                        .await
+                        .iter()
+                        .map(|tunnel| forward_info(&new_session_id, &reverse, tunnel))
+                        .collect();
 
+                // Helpers are scripts written to the host, and read-only sessions cannot run them
+                if !read_only {
+                    sync_on_connect(handle.clone(), traffic.clone(), new_session_id.clone());
+                }
                 // Insert session using storage abstraction
-                SESSION_STORAGE.insert(new_session_id.clone(), session_info, Arc::new(handle));
+                traffic.bill_to(billed_agent(agent_id.as_deref(), None));
+                SESSION_STORAGE.insert(new_session_id.clone(), session_info, handle, traffic);
+                REVERSE_FORWARDS.attach(&new_session_id, agent_id.as_deref(), reverse);
+                session_env::set_session_env(&new_session_id, env);
+                reconnect::remember(&new_session_id, params);
 
                 // Register in agent index if agent_id is provided
                 if let Some(ref aid) = agent_id {
                     SESSION_STORAGE.register_agent(aid, &new_session_id);
                 }
+                USAGE.record_connect(billed_agent(agent_id.as_deref(), None));
+                audit::record(
+                    AuditEvent::new("ssh_connect")
+                        .agent(agent_id.as_deref())
+                        .host(&address, &username)
+                        .session_id(&new_session_id),
+                );
 
                 let message = ConnectMessageBuilder::new(&new_session_id, &username, &address)
                     .with_agent_id(agent_id.as_deref())
                     .with_name(name.as_deref())
                     .with_retry_attempts(retry_attempts)
                     .with_persistent(persistent)
+                    .with_template(server_config().messages.connect.as_deref())
                     .build();
 
                 Ok(StructuredContent(SshConnectResponse {
@@ -212,15 +743,194 @@ impl McpSSHCommands {
                     message,
                     authenticated: true,
                     retry_attempts,
+                    identity,
+                    reverse_forwards,
+                    pooled: reused,
                 }))
             }
             Err(e) => {
                 error!("SSH connection failed: {}", e);
-                Err(e)
+                audit::record(
+                    AuditEvent::new("ssh_connect")
+                        .agent(agent_id.as_deref())
+                        .host(&address, &username)
+                        .failed(&e),
+                );
+                // Keep the listeners for the next reconnect attempt
+                if let Some(ref sid) = session_id {
+                    REVERSE_FORWARDS.park(sid, agent_id.as_deref(), carried_reverse);
+                }
+                let agent_only = params.password.is_none()
+                    && params.key_path.is_none()
+                    && params.key_data.is_none();
+                let error = ToolError::connection(e.as_str())
+                    .with_stage("connect")
+                    .with_detail("host", address);
+                Err(if agent_only && elicit::agent_auth_failed(&e) {
+                    error.with_detail("suggestions", vec![elicit::CREDENTIAL_REF_HINT])
+                } else {
+                    error
+                })
             }
         }
     }
 
+    /// Connect to many hosts with shared credentials in one call.
+    ///
+    /// **Recommended for:** Orchestrating a cluster: open sessions to every
+    /// node, then pass `session_ids` to ssh_execute_batch.
+    ///
+    /// Each address is connected like ssh_connect (retries with backoff,
+    /// session quotas, host caps, audit), up to `concurrency` at a time. A host
+    /// that fails does not affect the others: `results` reports every address
+    /// with its `session_id` or its `error_code` and `error`, and `session_ids`
+    /// lists the sessions opened, in request order.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_connect_many(
+        &self,
+        /// SSH server addresses in format "host:port" (max 100)
+        addresses: Vec<String>,
+        /// SSH username for every host (optional only when use_ssh_config resolves a User)
+        username: Option<String>,
+        /// Password for password-based authentication (optional if using key or agent)
+        password: Option<String>,
+        /// Path to private key file for key-based authentication (optional)
+        key_path: Option<String>,
+        /// Private key content (OpenSSH or PEM, passphrase-less) when no key file is available (optional)
+        key_data: Option<String>,
+        /// Name of a server-side credential supplying username, password and/or key, instead of passing secrets
+        credential_ref: Option<String>,
+        /// Connection timeout in seconds per host (default: 30, env: SSH_CONNECT_TIMEOUT)
+        timeout_secs: Option<u64>,
+        /// Default timeout in seconds of commands run on these sessions when a call gives none (default: SSH_COMMAND_TIMEOUT)
+        command_timeout_secs: Option<u64>,
+        /// Maximum retry attempts per host for transient connection failures (default: 3, env: SSH_MAX_RETRIES)
+        max_retries: Option<u32>,
+        /// Initial delay between retries in milliseconds, uses exponential backoff (default: 1000, env: SSH_RETRY_DELAY_MS)
+        retry_delay_ms: Option<u64>,
+        /// Hosts connected in parallel (default: 8, max: 32)
+        concurrency: Option<usize>,
+        /// Region hint recorded on every session, see ssh_select_session
+        region: Option<String>,
+        /// Datacenter hint recorded on every session
+        datacenter: Option<String>,
+        /// Labels recorded on every session, e.g. {"env": "prod", "role": "db"}
+        labels: Option<BTreeMap<String, String>>,
+        /// Keep the sessions open until explicitly disconnected (default: false)
+        persistent: Option<bool>,
+        /// Reconnect dropped sessions with the same credentials (default: false, env: SSH_AUTO_RECONNECT)
+        auto_reconnect: Option<bool>,
+        /// Refuse commands that change the hosts and disable the write tools (default: false; always on with env SSH_READ_ONLY)
+        read_only: Option<bool>,
+        /// Agent identifier owning every session; ssh_disconnect_agent closes them all
+        agent_id: Option<String>,
+        /// Make the sessions visible and usable by other agent_ids (default: false, requires agent_id)
+        shared: Option<bool>,
+        /// Access for other agents when shared: "read_only" (default) or "full"
+        share_mode: Option<String>,
+        /// Host key verification: "yes", "accept-new" or "no"; only stricter than SSH_STRICT_HOST_KEY_CHECKING (default: accept-new)
+        strict_host_key_checking: Option<String>,
+        /// Bastions to tunnel through for every host, first hop first (like ssh -J)
+        jump_hosts: Option<Vec<JumpHost>>,
+        /// Resolve each address as a Host alias of the OpenSSH client config (default: false)
+        use_ssh_config: Option<bool>,
+        /// Environment variables for every command run on these sessions
+        env: Option<BTreeMap<String, String>>,
+    ) -> Result<StructuredContent<SshConnectManyResponse>, ToolError> {
+        let start = std::time::Instant::now();
+        let addresses = resolve_connect_addresses(addresses)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let concurrency = resolve_batch_concurrency(concurrency);
+
+        let outcomes: Vec<Result<SshConnectResponse, ToolError>> =
+            futures::stream::iter(addresses.clone().into_iter().map(|address| {
+                self.ssh_connect(
+                    None,
+                    Some(address),
+                    None,
+                    username.clone(),
+                    password.clone(),
+                    key_path.clone(),
+                    key_data.clone(),
+                    credential_ref.clone(),
+                    timeout_secs,
+                    command_timeout_secs,
+                    max_retries,
+                    retry_delay_ms,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    region.clone(),
+                    datacenter.clone(),
+                    labels.clone(),
+                    persistent,
+                    auto_reconnect,
+                    read_only,
+                    agent_id.clone(),
+                    shared,
+                    share_mode.clone(),
+                    strict_host_key_checking.clone(),
+                    jump_hosts.clone(),
+                    use_ssh_config,
+                    env.clone(),
+                    None,
+                )
+            }))
+            .buffered(concurrency)
+            .map(|outcome| outcome.map(|StructuredContent(response)| response))
+            .collect()
+            .await;
+
+        let mut session_ids = Vec::new();
+        let mut results = BTreeMap::new();
+        for (address, outcome) in addresses.into_iter().zip(outcomes) {
+            let result = match outcome {
+                Ok(response) => {
+                    session_ids.push(response.session_id.clone());
+                    ConnectManyResult {
+                        success: true,
+                        session_id: Some(response.session_id),
+                        retry_attempts: response.retry_attempts,
+                        pooled: response.pooled,
+                        error_code: None,
+                        error: None,
+                    }
+                }
+                Err(e) => ConnectManyResult {
+                    success: false,
+                    session_id: None,
+                    retry_attempts: 0,
+                    pooled: false,
+                    error_code: Some(e.code),
+                    error: Some(e.message),
+                },
+            };
+            results.insert(address, result);
+        }
+
+        let connected = session_ids.len();
+        let failed = results.len() - connected;
+        info!(
+            "Connected to {} of {} host(s), {} failed",
+            connected,
+            results.len(),
+            failed
+        );
+        Ok(StructuredContent(SshConnectManyResponse {
+            success: failed == 0,
+            results,
+            session_ids,
+            connected,
+            failed,
+            elapsed_ms: round_ms(start.elapsed().as_secs_f64() * 1000.0),
+        }))
+    }
+
     /// Disconnect an SSH session and release resources.
     ///
     /// **Important:** This automatically cancels all running async commands
@@ -230,47 +940,20 @@ impl McpSSHCommands {
         &self,
         /// Session ID to disconnect
         session_id: String,
-    ) -> Result<Text<String>, String> {
-        info!("Disconnecting SSH session: {}", session_id);
-
-        // Close all interactive shells for this session
-        let shell_ids = SHELL_STORAGE.list_by_session(&session_id);
-        if !shell_ids.is_empty() {
-            info!(
-                "Closing {} interactive shells for session {}",
-                shell_ids.len(),
-                session_id
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<Text<String>, ToolError> {
+        let mut parking = None;
+        if let Some(session_ref) = SESSION_STORAGE.get(&session_id) {
+            authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+            audit::record(
+                AuditEvent::new("ssh_disconnect").session(&session_ref.info, agent_id.as_deref()),
             );
-            for shell_id in &shell_ids {
-                if let Some(shell) = SHELL_STORAGE.unregister(shell_id) {
-                    shell.cancel_token.cancel();
-                    let writer = shell.channel_writer.lock().await;
-                    let _ = writer.close().await;
-                }
-            }
+            parking = pool::parking_spot(&session_id, session_ref.info.agent_id.as_deref());
         }
 
-        // Cancel all async commands for this session (sync O(1) lookup)
-        let command_ids = COMMAND_STORAGE.list_by_session(&session_id);
-        if !command_ids.is_empty() {
-            info!(
-                "Cancelling {} async commands for session {}",
-                command_ids.len(),
-                session_id
-            );
-
-            // Cancel and remove each command
-            for cmd_id in &command_ids {
-                if let Some(cmd_ref) = COMMAND_STORAGE.get_ref(cmd_id) {
-                    cmd_ref.running.cancel_token.cancel();
-                }
-            }
-
-            // Remove cancelled commands
-            for cmd_id in command_ids {
-                COMMAND_STORAGE.unregister(&cmd_id);
-            }
-        }
+        info!("Disconnecting SSH session: {}", session_id);
+        let teardown = tear_down_session(&session_id).await;
 
         // Remove session from storage
         if let Some(session_ref) = SESSION_STORAGE.remove(&session_id) {
@@ -279,7 +962,25 @@ impl McpSSHCommands {
                 SESSION_STORAGE.unregister_agent(agent_id, &session_id);
             }
 
-            // Gracefully disconnect the session
+            // Park the connection for the next ssh_connect, or close it
+            if let Some((key, reverse)) = parking
+                && !session_ref.handle.is_closed()
+            {
+                pool::park(
+                    key,
+                    &session_ref.info.host,
+                    session_ref.info.agent_id.clone(),
+                    session_ref.handle,
+                    reverse,
+                    session_ref.traffic,
+                )
+                .await;
+                return Ok(Text(format!(
+                    "Session {} disconnected successfully; connection kept for reuse{}",
+                    session_id,
+                    teardown.summary()
+                )));
+            }
             if let Err(e) = session_ref
                 .handle
                 .disconnect(Disconnect::ByApplication, "Session closed by user", "en")
@@ -288,108 +989,321 @@ impl McpSSHCommands {
                 warn!("Error during disconnect: {}", e);
             }
             Ok(Text(format!(
-                "Session {} disconnected successfully",
-                session_id
+                "Session {} disconnected successfully{}",
+                session_id,
+                teardown.summary()
             )))
         } else {
-            Err(format!("No active SSH session with ID: {}", session_id))
+            Err(ToolError::session_not_found(&session_id))
         }
     }
 
     /// List all active SSH sessions with their metadata.
     ///
     /// Performs a health check on each session and automatically removes
-    /// dead/disconnected sessions from the list. Use this to find available
-    /// session_ids for command execution.
+    /// disconnected sessions from the list. A session whose connection is
+    /// still up but failed the check is kept with `healthy: false`. The last
+    /// 10 results are in `health_history`; `flapping: true` marks a session
+    /// that keeps alternating between passing and failing, which should not be
+    /// trusted with long-running work. Use this to find available session_ids
+    /// for command execution.
+    ///
+    /// **Cached health:** When the server runs the background health monitor
+    /// (`[health] interval_secs`), the list returns at once with the monitor's
+    /// last results (`cached: true`); `last_health_check` tells how fresh each
+    /// one is. Pass `refresh=true` to probe every listed session now instead.
     ///
     /// **Filtering by agent_id:** When provided, only sessions belonging to that
-    /// agent are returned. This is useful when multiple agents share an MCP server.
+    /// agent and sessions other agents opened with `shared=true` are returned.
+    /// This is useful when multiple agents share an MCP server.
+    ///
+    /// **Filtering by labels:** `labels` selects sessions by the labels set in
+    /// ssh_connect or ssh_update_session: comma-separated requirements that
+    /// must all hold, `key=value`, `key!=value`, `key` (set) or `!key` (not
+    /// set), e.g. `env=prod,role=db`. Only selected sessions are probed.
     async fn ssh_list_sessions(
         &self,
-        /// Filter by agent ID to list only sessions for a specific agent
+        /// Filter by agent ID to list only sessions for a specific agent (plus sessions shared with it)
         agent_id: Option<String>,
-    ) -> StructuredContent<SessionListResponse> {
-        let health_timeout = Duration::from_secs(5);
-
-        // Get session IDs to check (either all or filtered by agent)
-        let session_ids_to_check: Vec<String> = if let Some(ref aid) = agent_id {
-            SESSION_STORAGE.get_agent_sessions(aid)
-        } else {
-            SESSION_STORAGE.session_ids()
-        };
+        /// Label selector, e.g. "env=prod,role=db" (also key!=value, key, !key)
+        labels: Option<String>,
+        /// Probe sessions now instead of returning the background monitor's results (default: false)
+        refresh: Option<bool>,
+    ) -> Result<StructuredContent<SessionListResponse>, ToolError> {
+        let selector = LabelSelector::parse(labels.as_deref().unwrap_or_default())
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let health_config = &server_config().health;
+        let cached = monitor_interval(health_config).is_some() && !refresh.unwrap_or(false);
 
-        // Get sessions and their handles for health checks
-        let sessions_snapshot: Vec<_> = session_ids_to_check
-            .into_iter()
-            .filter_map(|session_id| {
-                SESSION_STORAGE.get(&session_id).map(|session_ref| {
-                    (
-                        session_id,
-                        session_ref.handle.clone(),
-                        session_ref.info.clone(),
-                    )
+        let visible_ids = || -> Vec<String> {
+            SESSION_STORAGE
+                .session_ids()
+                .into_iter()
+                .filter(|session_id| {
+                    SESSION_STORAGE.get(session_id).is_some_and(|session_ref| {
+                        agent_id
+                            .as_deref()
+                            .is_none_or(|aid| is_visible_to(&session_ref.info, aid))
+                            && selector.matches(&session_ref.info.labels)
+                    })
                 })
-            })
-            .collect();
-
-        // Run health checks in PARALLEL using join_all
-        let health_futures: Vec<_> = sessions_snapshot
-            .into_iter()
-            .map(|(session_id, handle_arc, info)| async move {
-                let now = chrono::Utc::now().to_rfc3339();
-                let result = execute_ssh_command(&handle_arc, "echo 1", health_timeout).await;
-                (session_id, info, now, result)
-            })
-            .collect();
-
-        let results = join_all(health_futures).await;
-
-        // Process results
-        let mut healthy_sessions = Vec::new();
-        let mut dead_session_ids = Vec::new();
+                .collect()
+        };
 
-        for (session_id, mut info, now, result) in results {
-            match result {
-                Ok(response) if !response.timed_out && response.exit_code == 0 => {
-                    info.last_health_check = Some(now);
-                    info.healthy = Some(true);
-                    healthy_sessions.push((session_id, info));
-                }
-                _ => {
-                    info.last_health_check = Some(now);
-                    info.healthy = Some(false);
-                    dead_session_ids.push(session_id);
-                }
-            }
+        if !cached {
+            // Records results in storage and removes disconnected sessions
+            check_sessions(visible_ids(), health_config).await;
         }
 
-        // Update healthy sessions using storage abstraction
-        for (id, info) in &healthy_sessions {
-            if let Some(ref last_check) = info.last_health_check {
-                SESSION_STORAGE.update_health(
-                    id,
-                    last_check.clone(),
-                    info.healthy.unwrap_or(false),
-                );
+        let mut session_infos = Vec::new();
+        for session_id in visible_ids() {
+            let Some(session_ref) = SESSION_STORAGE.get(&session_id) else {
+                continue;
+            };
+            // A rebooting host is expected to be down; ssh_reboot_and_wait reconnects it
+            if session_ref.handle.is_closed() && !session_ref.info.rebooting {
+                warn!("Removing dead session {} from storage", session_id);
+                SESSION_LEASES.remove(&session_id);
+                SESSION_STORAGE.remove(&session_id);
+                reconnect::forget(&session_id);
+                session_env::forget(&session_id);
+                continue;
             }
+            session_infos.push(session_ref.info.clone());
         }
-
-        // Remove dead sessions using storage abstraction
-        for id in &dead_session_ids {
-            warn!("Removing dead session {} from storage", id);
-            SESSION_STORAGE.remove(id);
-        }
-
-        let session_infos: Vec<SessionInfo> =
-            healthy_sessions.into_iter().map(|(_, info)| info).collect();
         let count = session_infos.len();
 
-        StructuredContent(SessionListResponse {
+        Ok(StructuredContent(SessionListResponse {
             sessions: session_infos,
             count,
+            cached,
+            lost_sessions: persistent::lost_sessions(agent_id.as_deref()),
+        }))
+    }
+
+    /// Change the labels of a session.
+    ///
+    /// `labels` are merged into the session's labels (replacing the values of
+    /// keys already set), then the keys in `remove_labels` are dropped. Select
+    /// sessions by label with ssh_list_sessions `labels`. Sessions owned by
+    /// another agent need a full share.
+    async fn ssh_update_session(
+        &self,
+        /// Session ID to update
+        session_id: String,
+        /// Labels to set, e.g. {"env": "prod", "role": "db"}
+        labels: Option<BTreeMap<String, String>>,
+        /// Label keys to remove
+        remove_labels: Option<Vec<String>>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshUpdateSessionResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Write)?;
+
+        let labels = update_labels(
+            &session_ref.info.labels,
+            labels.unwrap_or_default(),
+            &remove_labels.unwrap_or_default(),
+        )
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        SESSION_STORAGE.set_labels(&session_id, labels.clone());
+        info!("Session {} labels set to {:?}", session_id, labels);
+
+        Ok(StructuredContent(SshUpdateSessionResponse {
+            message: format!(
+                "Session '{}' now has {} label(s).",
+                session_id,
+                labels.len()
+            ),
+            session_id,
+            labels,
+        }))
+    }
+
+    /// Describe what this server build and configuration offer.
+    ///
+    /// Returns compile-time features (`port_forward`), the available transports,
+    /// the callable tool names (prefix applied) and the ones disabled by the
+    /// operator, effective defaults and limits (timeouts, per-session and
+    /// per-call caps), and active policies (command sandbox, per-host session
+    /// caps, audit export). Call it once before planning instead of finding
+    /// missing features through errors.
+    async fn ssh_capabilities(&self) -> StructuredContent<SshCapabilitiesResponse> {
+        StructuredContent(capabilities(server_config()))
+    }
+
+    /// Validate the server configuration and report every problem found.
+    ///
+    /// Checks set environment variables, the `SSH_MCP_CONFIG` file, `[tools]`
+    /// entries, managed hosts (address, key file, password variable), audit
+    /// exporters and the helper library. The same checks run at startup, where
+    /// any error stops the server; use this after editing the environment of a
+    /// running server or to see warnings. With `dial_canary=true` the
+    /// `[self_test] canary` managed host is also connected once.
+    async fn ssh_check_config(
+        &self,
+        /// Also dial the configured canary host (default: false)
+        dial_canary: Option<bool>,
+    ) -> StructuredContent<SshCheckConfigResponse> {
+        StructuredContent(check_config(dial_canary.unwrap_or(false)).await)
+    }
+
+    /// List the hosts the server connects to itself (`[[managed_hosts]]`).
+    ///
+    /// Each host's `name` is a ready-to-use session_id: pass it to any tool
+    /// without calling ssh_connect. Hosts with `connect="lazy"` are connected
+    /// on first use. Dropped sessions are reconnected automatically; check
+    /// `last_error` when a host stays disconnected.
+    async fn ssh_list_managed_hosts(&self) -> StructuredContent<SshListManagedHostsResponse> {
+        let hosts = managed_host_infos();
+        StructuredContent(SshListManagedHostsResponse {
+            count: hosts.len(),
+            hosts,
         })
     }
 
+    /// List the connection profiles defined on the server.
+    ///
+    /// Pass a profile's `name` as ssh_connect `profile` instead of an address
+    /// and credentials. Shows address, username, how the profile authenticates
+    /// (`auth`), jump hosts and default timeouts; secrets and key paths are
+    /// never included.
+    async fn ssh_list_profiles(
+        &self,
+    ) -> Result<StructuredContent<SshListProfilesResponse>, ToolError> {
+        let profiles: Vec<_> = profiles()?
+            .iter()
+            .map(|(name, profile)| profile_info(name, profile))
+            .collect();
+        Ok(StructuredContent(SshListProfilesResponse {
+            count: profiles.len(),
+            profiles,
+        }))
+    }
+
+    /// Report cumulative usage per agent since the server started.
+    ///
+    /// Counts successful connects, commands started, command output bytes,
+    /// shells opened and shell wall-clock time. Usage is charged to the calling
+    /// agent when a tool received `agent_id`, else to the session owner, else
+    /// to "anonymous".
+    ///
+    /// With `format="prometheus"`, `metrics` also carries the counters in the
+    /// Prometheus text format with an `agent_id` label.
+    async fn ssh_usage(
+        &self,
+        /// Only report this agent's usage
+        agent_id: Option<String>,
+        /// "json" (default) or "prometheus" to add a Prometheus text rendering
+        format: Option<String>,
+    ) -> Result<StructuredContent<SshUsageResponse>, ToolError> {
+        let prometheus = match format.as_deref().map(str::trim) {
+            None | Some("") | Some("json") => false,
+            Some("prometheus") => true,
+            Some(other) => {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Unknown format '{}'. Use 'json' or 'prometheus'", other),
+                ));
+            }
+        };
+
+        let agents = USAGE.snapshot(agent_id.as_deref());
+        let metrics = prometheus.then(|| render_metrics(&agents));
+        let commands: u64 = agents.iter().map(|usage| usage.commands).sum();
+        let message = format!(
+            "{} agent(s), {} command(s) since {}",
+            agents.len(),
+            commands,
+            USAGE.since()
+        );
+
+        Ok(StructuredContent(SshUsageResponse {
+            since: USAGE.since().to_string(),
+            agents,
+            metrics,
+            message,
+        }))
+    }
+
+    /// Read the audit log: connects, executed commands with their exit codes,
+    /// shell input, forwards and the other audited actions, newest first.
+    ///
+    /// Needs a `[[audit.exporters]]` entry with `kind="file"` and `format="json"`.
+    /// Events reach the file within `flush_interval_ms` (1s by default), so
+    /// the last second of activity may be missing.
+    async fn ssh_get_audit_log(
+        &self,
+        /// Only events of this agent
+        agent_id: Option<String>,
+        /// Only events of this session
+        session_id: Option<String>,
+        /// Only this action, e.g. "ssh_execute", "command_finished", "ssh_shell_write"
+        action: Option<String>,
+        /// Only "success" or "failure" events
+        outcome: Option<String>,
+        /// Only events at or after this time (RFC3339)
+        since: Option<String>,
+        /// Most events returned (default: 100, max: 1000)
+        limit: Option<usize>,
+    ) -> Result<StructuredContent<SshAuditLogResponse>, ToolError> {
+        let path = audit::log_path().ok_or_else(|| {
+            ToolError::new(
+                ErrorCode::InvalidState,
+                "No audit log configured. Add an [[audit.exporters]] entry with kind = \"file\" and a path.",
+            )
+        })?;
+        let outcome = match outcome.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some("success") => Some(AuditOutcome::Success),
+            Some("failure") => Some(AuditOutcome::Failure),
+            Some(other) => {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Unknown outcome '{}'. Use 'success' or 'failure'", other),
+                ));
+            }
+        };
+        let since = since
+            .as_deref()
+            .map(|since| {
+                chrono::DateTime::parse_from_rfc3339(since).map_err(|e| {
+                    ToolError::new(
+                        ErrorCode::InvalidArgument,
+                        format!("Invalid 'since' timestamp '{}': {}", since, e),
+                    )
+                })
+            })
+            .transpose()?;
+        let query = AuditQuery {
+            agent_id,
+            session_id,
+            action,
+            outcome,
+            since,
+        };
+        let limit = limit.unwrap_or(DEFAULT_LOG_LIMIT).min(MAX_LOG_LIMIT);
+
+        let (events, truncated) = read_log(path, &query, limit)
+            .await
+            .map_err(|e| ToolError::new(ErrorCode::InvalidState, e))?;
+        let message = if truncated {
+            format!(
+                "{} most recent matching event(s); older ones were left out, narrow the filters or pass 'since'",
+                events.len()
+            )
+        } else {
+            format!("{} matching event(s)", events.len())
+        };
+        Ok(StructuredContent(SshAuditLogResponse {
+            count: events.len(),
+            events,
+            truncated,
+            message,
+        }))
+    }
+
     /// Setup port forwarding on an existing SSH session
     #[allow(unused_variables)]
     async fn ssh_forward(
@@ -402,52 +1316,683 @@ impl McpSSHCommands {
         remote_address: String,
         /// Remote port to forward to (e.g., 3306 for MySQL)
         remote_port: u16,
-    ) -> Result<StructuredContent<PortForwardingResponse>, String> {
+        /// Bandwidth cap in bytes per second, each direction, shared by all forwarded connections (min 1024; default: unlimited)
+        rate_limit_bytes_per_sec: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<PortForwardingResponse>, ToolError> {
         #[cfg(feature = "port_forward")]
         {
+            let bandwidth = resolve_rate_limit(rate_limit_bytes_per_sec)
+                .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
             info!(
                 "Setting up port forwarding from local port {} to {}:{} using session {}",
                 local_port, remote_address, remote_port, session_id
             );
 
             // Get session handle using storage abstraction
-            let handle_arc = SESSION_STORAGE
-                .get(&session_id)
-                .map(|s| s.handle.clone())
-                .ok_or_else(|| format!("No active SSH session with ID: {}", session_id))?;
+            let session_ref = get_session(&session_id).await?;
+            authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+            audit::record(
+                AuditEvent::new("ssh_forward")
+                    .session(&session_ref.info, agent_id.as_deref())
+                    .target(format!(
+                        "127.0.0.1:{} -> {}:{}",
+                        local_port, remote_address, remote_port
+                    )),
+            );
+            let handle_arc = session_ref.handle.clone();
 
-            match setup_port_forwarding(handle_arc, local_port, &remote_address, remote_port).await
+            match setup_port_forwarding(
+                &session_id,
+                handle_arc,
+                session_ref.traffic.clone(),
+                bandwidth,
+                local_port,
+                &remote_address,
+                remote_port,
+            )
+            .await
             {
-                Ok(local_addr) => Ok(StructuredContent(PortForwardingResponse {
-                    local_address: local_addr.to_string(),
-                    remote_address: format!("{}:{}", remote_address, remote_port),
-                    active: true,
-                })),
+                Ok(forward) => {
+                    SESSION_STORAGE
+                        .record_activity(&session_id, SessionActivity::ForwardEstablished);
+                    Ok(StructuredContent(PortForwardingResponse {
+                        forward_id: forward.forward_id,
+                        local_address: forward.local_address,
+                        remote_address: forward.remote_address,
+                        active: true,
+                    }))
+                }
                 Err(e) => {
                     error!("Port forwarding setup failed: {}", e);
-                    Err(e)
+                    Err(ToolError::channel(e)
+                        .with_stage("port_forward")
+                        .with_detail("session_id", session_id))
                 }
             }
         }
 
         #[cfg(not(feature = "port_forward"))]
         {
-            Err(
-                "Port forwarding feature is not enabled. Rebuild with --features port_forward"
-                    .to_string(),
+            Err(ToolError::new(
+                ErrorCode::FeatureDisabled,
+                "Port forwarding feature is not enabled. Rebuild with --features port_forward",
+            ))
+        }
+    }
+
+    /// Stop a local port forward started with ssh_forward.
+    ///
+    /// Closes the listener, releasing the local port, and the connections it
+    /// is forwarding.
+    async fn ssh_cancel_forward(
+        &self,
+        /// Forward ID returned from ssh_forward or ssh_list_forwards
+        forward_id: String,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCancelForwardResponse>, ToolError> {
+        let Some(forward) = FORWARD_STORAGE.get_info(&forward_id) else {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!("No port forward with ID {}", forward_id),
             )
+            .with_detail("forward_id", forward_id));
+        };
+        let session_ref = get_session(&forward.session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+        audit::record(
+            AuditEvent::new("ssh_cancel_forward")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!(
+                    "{} -> {}",
+                    forward.local_address, forward.remote_address
+                )),
+        );
+
+        if let Some(running) = FORWARD_STORAGE.unregister(&forward_id) {
+            running.cancel_token.cancel();
         }
+        info!(
+            "Cancelled port forward {} ({} -> {}) of session {}",
+            forward_id, forward.local_address, forward.remote_address, forward.session_id
+        );
+        let message = format!(
+            "Port forward {} -> {} closed after {} connection(s)",
+            forward.local_address, forward.remote_address, forward.connections
+        );
+        Ok(StructuredContent(SshCancelForwardResponse {
+            forward_id,
+            session_id: forward.session_id,
+            local_address: forward.local_address,
+            message,
+        }))
     }
 
-    /// Execute a command asynchronously on a connected SSH session.
+    /// List local port forwards with their destinations and connection counts.
     ///
-    /// **Recommended for:** Long-running commands (builds, deployments, batch jobs,
-    /// data processing) that may exceed the default timeout or benefit from
-    /// progress monitoring and cancellation.
+    /// Without `session_id`, lists the forwards of every session visible to
+    /// `agent_id` (all sessions when `agent_id` is omitted).
+    async fn ssh_list_forwards(
+        &self,
+        /// Only list forwards of this session
+        session_id: Option<String>,
+        /// Calling agent's ID; filters sessions like ssh_list_sessions
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshListForwardsResponse>, ToolError> {
+        if let Some(session_id) = &session_id {
+            let session_ref = get_session(session_id).await?;
+            check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+        }
+        let forwards: Vec<ForwardInfo> = FORWARD_STORAGE
+            .list_filtered(session_id.as_deref())
+            .into_iter()
+            .filter(|forward| {
+                session_id.is_some()
+                    || SESSION_STORAGE
+                        .get(&forward.session_id)
+                        .is_some_and(|session_ref| {
+                            agent_id
+                                .as_deref()
+                                .is_none_or(|aid| is_visible_to(&session_ref.info, aid))
+                        })
+            })
+            .collect();
+        let count = forwards.len();
+
+        Ok(StructuredContent(SshListForwardsResponse {
+            forwards,
+            count,
+        }))
+    }
+
+    /// Listen on a port of the remote host and forward its connections back.
     ///
-    /// Returns command_id, session_id, and agent_id that you MUST remember.
+    /// The SSH server listens on `remote_bind_address:remote_port` (like
+    /// `ssh -R`); every connection is bridged to `local_host:local_port` as
+    /// seen from the MCP server. Use it for callbacks from remote jobs, e.g. a
+    /// webhook or a package mirror on the MCP side.
     ///
-    /// **Important identifiers in response:**
+    /// `remote_port=0` lets the server pick a free port; the bound port is
+    /// returned. Binding to anything but loopback requires `GatewayPorts` on
+    /// the server.
+    ///
+    /// **Reconnects:** when a session dies and ssh_connect is called again with
+    /// its session_id, its reverse forwards are re-established on the new
+    /// session with the same ports and targets (see `reverse_forwards` in the
+    /// ssh_connect response).
+    #[allow(unused_variables)]
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_reverse_forward(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Port to listen on on the remote host; 0 lets the server choose
+        remote_port: u16,
+        /// Port of the target on the MCP server side (e.g., 8080)
+        local_port: u16,
+        /// Remote address to listen on (default: "localhost"; "0.0.0.0" needs GatewayPorts)
+        remote_bind_address: Option<String>,
+        /// Target host, resolved on the MCP server (default: "127.0.0.1")
+        local_host: Option<String>,
+        /// Bandwidth cap in bytes per second, each direction, shared by all forwarded connections (min 1024; default: unlimited)
+        rate_limit_bytes_per_sec: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshReverseForwardResponse>, ToolError> {
+        #[cfg(feature = "port_forward")]
+        {
+            let bind_address =
+                remote_bind_address.unwrap_or_else(|| DEFAULT_REMOTE_BIND_ADDRESS.to_string());
+            let local_host = local_host.unwrap_or_else(|| DEFAULT_LOCAL_HOST.to_string());
+
+            let session_ref = get_session(&session_id).await?;
+            authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+            let forwards = REVERSE_FORWARDS
+                .get(&session_id)
+                .ok_or_else(|| ToolError::session_not_found(&session_id))?;
+            let event = AuditEvent::new("ssh_reverse_forward")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!(
+                    "{}:{} -> {}:{}",
+                    bind_address, remote_port, local_host, local_port
+                ));
+
+            match forwards
+                .open(
+                    &session_ref.handle,
+                    &bind_address,
+                    remote_port as u32,
+                    &local_host,
+                    local_port,
+                    rate_limit_bytes_per_sec,
+                )
+                .await
+            {
+                Ok(tunnel) => {
+                    audit::record(event);
+                    SESSION_STORAGE
+                        .record_activity(&session_id, SessionActivity::ForwardEstablished);
+                    let forward = forward_info(&session_id, &forwards, &tunnel);
+                    let message = format!(
+                        "Remote {} now forwards to {}. Cancel with ssh_cancel_reverse_forward(session_id='{}', remote_port={}).",
+                        forward.remote_address,
+                        forward.local_address,
+                        session_id,
+                        forward.remote_port
+                    );
+                    Ok(StructuredContent(SshReverseForwardResponse {
+                        forward,
+                        message,
+                    }))
+                }
+                Err(e) => {
+                    error!("Reverse forward setup failed: {}", e);
+                    audit::record(event.failed(&e));
+                    Err(ToolError::channel(e)
+                        .with_stage("reverse_forward")
+                        .with_detail("session_id", session_id))
+                }
+            }
+        }
+
+        #[cfg(not(feature = "port_forward"))]
+        {
+            Err(ToolError::new(
+                ErrorCode::FeatureDisabled,
+                "Port forwarding feature is not enabled. Rebuild with --features port_forward",
+            ))
+        }
+    }
+
+    /// Stop a reverse forward (`cancel-tcpip-forward`).
+    ///
+    /// The listener is forgotten even when the server does not confirm, so it
+    /// is not re-established on reconnect. Connections already bridged keep
+    /// running until either side closes them.
+    #[allow(unused_variables)]
+    async fn ssh_cancel_reverse_forward(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Remote port of the forward, as returned by ssh_reverse_forward
+        remote_port: u16,
+        /// Remote bind address, only needed when the port is forwarded on several addresses
+        remote_bind_address: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCancelReverseForwardResponse>, ToolError> {
+        #[cfg(feature = "port_forward")]
+        {
+            let session_ref = get_session(&session_id).await?;
+            authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+            let tunnel = REVERSE_FORWARDS.get(&session_id).and_then(|forwards| {
+                forwards.find(remote_bind_address.as_deref(), remote_port as u32)
+            });
+            let Some(tunnel) = tunnel else {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "No reverse forward on remote port {} of session {}",
+                        remote_port, session_id
+                    ),
+                )
+                .with_detail("session_id", session_id));
+            };
+            let remote_address = format!("{}:{}", tunnel.bind_address, tunnel.remote_port);
+            audit::record(
+                AuditEvent::new("ssh_cancel_reverse_forward")
+                    .session(&session_ref.info, agent_id.as_deref())
+                    .target(remote_address.as_str()),
+            );
+
+            let forwards = REVERSE_FORWARDS
+                .get(&session_id)
+                .ok_or_else(|| ToolError::session_not_found(&session_id))?;
+            let result = forwards
+                .close(
+                    &session_ref.handle,
+                    Some(&tunnel.bind_address),
+                    tunnel.remote_port,
+                )
+                .await;
+            let (confirmed, message) = match result {
+                Ok(_) => (
+                    true,
+                    format!("Reverse forward {} cancelled", remote_address),
+                ),
+                Err(e) => {
+                    warn!("cancel-tcpip-forward for {}: {}", remote_address, e);
+                    (
+                        false,
+                        format!(
+                            "Reverse forward {} removed; the server did not confirm: {}",
+                            remote_address, e
+                        ),
+                    )
+                }
+            };
+            Ok(StructuredContent(SshCancelReverseForwardResponse {
+                session_id,
+                remote_address,
+                confirmed,
+                message,
+            }))
+        }
+
+        #[cfg(not(feature = "port_forward"))]
+        {
+            Err(ToolError::new(
+                ErrorCode::FeatureDisabled,
+                "Port forwarding feature is not enabled. Rebuild with --features port_forward",
+            ))
+        }
+    }
+
+    /// List reverse forwards with their targets and connection counts.
+    ///
+    /// Without `session_id`, lists the forwards of every session visible to
+    /// `agent_id` (all sessions when `agent_id` is omitted).
+    async fn ssh_list_reverse_forwards(
+        &self,
+        /// Only list forwards of this session
+        session_id: Option<String>,
+        /// Calling agent's ID; filters sessions like ssh_list_sessions
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshListReverseForwardsResponse>, ToolError> {
+        let session_ids = match session_id {
+            Some(session_id) => {
+                let session_ref = get_session(&session_id).await?;
+                check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+                vec![session_id]
+            }
+            None => REVERSE_FORWARDS
+                .session_ids()
+                .into_iter()
+                .filter(|id| {
+                    SESSION_STORAGE.get(id).is_some_and(|session_ref| {
+                        agent_id
+                            .as_deref()
+                            .is_none_or(|aid| is_visible_to(&session_ref.info, aid))
+                    })
+                })
+                .collect(),
+        };
+
+        let forwards: Vec<ReverseForwardInfo> = session_ids
+            .iter()
+            .filter_map(|id| Some((id, REVERSE_FORWARDS.get(id)?)))
+            .flat_map(|(id, forwards)| {
+                forwards
+                    .tunnels()
+                    .iter()
+                    .map(|tunnel| forward_info(id, &forwards, tunnel))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let count = forwards.len();
+
+        Ok(StructuredContent(SshListReverseForwardsResponse {
+            forwards,
+            count,
+        }))
+    }
+
+    /// Test whether a TCP port is reachable from the remote host.
+    ///
+    /// Opens a `direct-tcpip` channel through the session (no data is sent) and
+    /// reports `open`, `closed` (connection refused) or `filtered` (no answer
+    /// before `timeout_secs`). When the server disables TCP forwarding, a probe
+    /// with `nc`/bash runs on the remote host instead.
+    ///
+    /// Use before ssh_forward, or to debug which interface a service binds to.
+    async fn ssh_check_port(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Host to test, resolved from the remote side (e.g., "localhost", "10.0.0.5")
+        host: String,
+        /// TCP port to test
+        port: u16,
+        /// Seconds to wait for an answer (default: 5, max: 60)
+        timeout_secs: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCheckPortResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let timeout = resolve_port_check_timeout(timeout_secs);
+        let check = check_port(&session_ref.handle, &host, port, timeout)
+            .await
+            .map_err(|e| {
+                ToolError::channel(e)
+                    .with_stage("check_port")
+                    .with_detail("session_id", session_id.as_str())
+                    .with_detail("host", host.as_str())
+            })?;
+
+        let message = match check.state {
+            PortState::Open => format!("{}:{} is open", host, port),
+            PortState::Closed => format!("{}:{} is closed (connection refused)", host, port),
+            PortState::Filtered => format!(
+                "{}:{} did not answer within {}s (filtered or host down)",
+                host,
+                port,
+                timeout.as_secs()
+            ),
+        };
+
+        Ok(StructuredContent(SshCheckPortResponse {
+            session_id,
+            host,
+            port,
+            state: check.state,
+            method: check.method.to_string(),
+            elapsed_ms: round_ms(check.elapsed.as_secs_f64() * 1000.0),
+            message,
+        }))
+    }
+
+    /// Report whether the session user can use sudo, and for which commands.
+    ///
+    /// Runs `sudo -n -l` (never prompts for a password) and parses the rule
+    /// listing. `sudo` is one of: `root` (already root), `allowed` (rules
+    /// listed), `password_required` (sudo needs a password even to list),
+    /// `denied`, `unavailable` (sudo not installed) or `unknown`.
+    ///
+    /// `full_root` is true when any command can run as root; `passwordless_root`
+    /// when that works without a password, so `sudo -n` remediation is possible.
+    async fn ssh_check_privileges(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCheckPrivilegesResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let report = probe_privileges(&session_ref.handle).await.map_err(|e| {
+            ToolError::channel(e)
+                .with_stage("check_privileges")
+                .with_detail("session_id", session_id.as_str())
+        })?;
+
+        let full_root = report.full_root();
+        let passwordless_root = report.uid == Some(0) || report.passwordless_root();
+        let message = match report.access {
+            SudoAccess::Root => "Session user is root".to_string(),
+            SudoAccess::Allowed if passwordless_root => {
+                "sudo allows any command as root without a password".to_string()
+            }
+            SudoAccess::Allowed if full_root => {
+                "sudo allows any command as root (password required)".to_string()
+            }
+            SudoAccess::Allowed => format!("sudo allows {} rule(s)", report.rules.len()),
+            SudoAccess::PasswordRequired => {
+                "sudo requires a password; rules could not be listed".to_string()
+            }
+            SudoAccess::Denied => "Session user may not run sudo".to_string(),
+            SudoAccess::Unavailable if full_root => {
+                "Session user is root (sudo not installed)".to_string()
+            }
+            SudoAccess::Unavailable => "sudo is not installed".to_string(),
+            SudoAccess::Unknown => "sudo access could not be determined".to_string(),
+        };
+
+        Ok(StructuredContent(SshCheckPrivilegesResponse {
+            session_id,
+            uid: report.uid,
+            is_root: report.uid == Some(0),
+            sudo: report.access,
+            rules: report.rules,
+            full_root,
+            passwordless_root,
+            detail: report.detail,
+            message,
+        }))
+    }
+
+    /// Gather typed facts about the remote host in one call.
+    ///
+    /// Runs a curated read-only probe: OS release (`/etc/os-release` or
+    /// `sw_vers`), kernel and architecture, CPU count and model, load
+    /// average, memory and swap, mounted filesystems (pseudo filesystems
+    /// left out), uptime, package manager, init system and virtualization.
+    ///
+    /// Facts the host cannot provide are omitted and named in `unavailable`;
+    /// a missing fact is not an error. Use it to pick package commands and
+    /// paths instead of probing each one with ssh_execute.
+    async fn ssh_gather_facts(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshGatherFactsResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let facts = probe_facts(&session_ref.handle).await.map_err(|e| {
+            ToolError::channel(e)
+                .with_stage("gather_facts")
+                .with_detail("session_id", session_id.as_str())
+        })?;
+
+        Ok(StructuredContent(SshGatherFactsResponse {
+            session_id,
+            unavailable: unavailable_facts(&facts),
+            message: facts_summary(&facts),
+            facts,
+        }))
+    }
+
+    /// Check that a sudo operation left no privileges behind.
+    ///
+    /// Invalidates cached sudo credentials (`sudo -k`) and lists privileged
+    /// processes still running: `sudo` processes started by the session user
+    /// (e.g. a `sudo -i` shell left open) and root processes matching the
+    /// commands `command` ran through sudo (e.g. `sudo nohup worker &`).
+    ///
+    /// `status` is `clean`, `lingering` (see `lingering` for pids and command
+    /// lines) or `not_applicable` when the session user is root. Call it after
+    /// privileged work in ssh_execute, shells or consoles; ssh_execute_sync
+    /// can run it itself with `verify_privilege_drop=true`.
+    async fn ssh_verify_privilege_drop(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// The sudo command that was run (e.g., "sudo systemctl restart nginx"); root processes of the commands it wrapped are searched for
+        command: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshVerifyPrivilegeDropResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+
+        let report =
+            check_privilege_drop(&session_ref, command.as_deref(), agent_id.as_deref()).await?;
+        let message = report.summary();
+        Ok(StructuredContent(SshVerifyPrivilegeDropResponse {
+            session_id,
+            privilege_drop: report,
+            message,
+        }))
+    }
+
+    /// Reboot the session's host and wait until it accepts connections again.
+    ///
+    /// Sends the reboot, marks the session `rebooting` while the host is down,
+    /// then reconnects with the parameters the session was opened with and
+    /// keeps the same session_id. Reports the downtime and whether the kernel
+    /// boot id changed. Shells and async commands of the session end with the
+    /// old connection; reverse forwards are re-established.
+    ///
+    /// The default command is `reboot` for root and `sudo -n reboot`
+    /// otherwise (sudo must not need a password); both are started detached.
+    /// The call blocks until the host is back or `timeout_secs` passes.
+    ///
+    /// **Credentials:** Only sessions opened with `auto_reconnect=true` keep
+    /// their password or key content. For other sessions pass `password`,
+    /// `key_path`, `key_data` or `credential_ref` to reconnect with (a session
+    /// opened with `credential_ref` looks it up again); otherwise the call
+    /// fails with `invalid_argument` before the reboot is sent.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_reboot_and_wait(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Seconds allowed from sending the reboot until the session is back (default: 600, min: 30, max: 3600)
+        timeout_secs: Option<u64>,
+        /// Reboot command to run instead of the default (e.g., "sudo systemctl reboot")
+        command: Option<String>,
+        /// Password to reconnect with; required for sessions connected with a password and without auto_reconnect
+        password: Option<String>,
+        /// Path to private key file to reconnect with
+        key_path: Option<String>,
+        /// Private key content to reconnect with; required for sessions connected with key_data and without auto_reconnect
+        key_data: Option<String>,
+        /// Name of a server-side credential to reconnect with (default: the session's own credential_ref)
+        credential_ref: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshRebootAndWaitResponse>, ToolError> {
+        if credential_ref.is_some()
+            && (password.is_some() || key_path.is_some() || key_data.is_some())
+        {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                "credential_ref cannot be combined with password, key_path or key_data",
+            ));
+        }
+        let session_ref = get_session(&session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+        read_only::check_tool(&session_ref.info, "ssh_reboot_and_wait")?;
+        if session_ref.info.rebooting {
+            return Err(ToolError::new(
+                ErrorCode::InvalidState,
+                format!("Session {} is already rebooting", session_id),
+            ));
+        }
+        let command = match command.map(|command| command.trim().to_string()) {
+            Some(command) if command.is_empty() => {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    "command must not be empty",
+                ));
+            }
+            Some(command) => command,
+            None => {
+                let is_root = session_ref
+                    .info
+                    .identity
+                    .as_ref()
+                    .is_some_and(|identity| identity.is_root);
+                reboot_command(is_root).to_string()
+            }
+        };
+        enforce_command_policy(
+            "ssh_reboot_and_wait",
+            &session_id,
+            &command,
+            agent_id.as_deref(),
+        )?;
+
+        let event = AuditEvent::new("ssh_reboot_and_wait")
+            .session(&session_ref.info, agent_id.as_deref())
+            .target(command.as_str());
+        let fresh = FreshCredentials {
+            password,
+            key_path,
+            key_data,
+            credential_ref,
+        };
+        let result = reboot_and_wait(
+            session_ref,
+            command,
+            resolve_reboot_timeout(timeout_secs),
+            fresh,
+            agent_id.as_deref(),
+        )
+        .await;
+        match &result {
+            Ok(_) => audit::record(event),
+            Err(e) => audit::record(event.failed(&e.message)),
+        }
+        result.map(StructuredContent)
+    }
+
+    /// Execute a command asynchronously on a connected SSH session.
+    ///
+    /// **Recommended for:** Long-running commands (builds, deployments, batch jobs,
+    /// data processing) that may exceed the default timeout or benefit from
+    /// progress monitoring and cancellation.
+    ///
+    /// Returns command_id, session_id, and agent_id that you MUST remember.
+    ///
+    /// **Important identifiers in response:**
     /// - `command_id`: Use with ssh_get_command_output (poll), ssh_cancel_command (cancel)
     /// - `session_id`: The session running this command
     /// - `agent_id`: The agent that owns this session (if set)
@@ -456,11 +2001,36 @@ impl McpSSHCommands {
     /// 1. ssh_execute → get command_id
     /// 2. ssh_get_command_output(command_id, wait=true) → get result
     ///
+    /// **Streaming:** With `stream_output=true` new output is also pushed to
+    /// this client as `notifications/message` (logger `ssh_command`) while the
+    /// command runs; the last notification has `closed: true`.
+    ///
+    /// **Environment:** `env` sets variables for this command on top of those
+    /// given to ssh_connect; `cwd` runs it in a remote directory without a
+    /// `cd ... &&` prefix in `command`.
+    ///
+    /// **Output patterns:** with `success_pattern` the command is marked
+    /// `completed` as soon as its output matches (e.g. a service logging that
+    /// it is ready), with `failure_pattern` `failed`; the response of
+    /// ssh_get_command_output then carries `matched_pattern`. The command is
+    /// sent SIGTERM on the match unless `terminate_on_match=false`, which keeps
+    /// it running (it can still be cancelled).
+    ///
+    /// **Output cap:** stdout and stderr each keep at most `max_output_bytes`
+    /// (default 64 MiB). Past it the oldest output is evicted (`ring`) or new
+    /// output discarded (`head`); ssh_get_command_output reports the lost
+    /// bytes as `dropped_bytes`.
+    ///
     /// **Limits:** Up to 100 concurrent multiplexed commands per session.
     /// When the limit is reached, you must wait for existing commands to complete
     /// or cancel them using ssh_cancel_command before starting new ones.
     ///
+    /// **Policy:** Commands matching an operator deny rule fail with
+    /// `policy_violation`; its `reasons` and `suggestions` say what to do instead.
+    /// Repeating the same command will not succeed.
+    ///
     /// Returns immediately with a command_id for polling or cancellation.
+    #[allow(clippy::too_many_arguments)]
     async fn ssh_execute(
         &self,
         /// Session ID returned from ssh_connect
@@ -471,103 +2041,90 @@ impl McpSSHCommands {
         timeout_secs: Option<u64>,
         /// Allocate a pseudo-terminal (PTY) for the command. Use for commands requiring a terminal (sudo, top). All output goes to stdout in PTY mode (no stderr separation).
         pty: Option<bool>,
-    ) -> Result<StructuredContent<SshExecuteResponse>, String> {
-        let timeout = resolve_command_timeout(timeout_secs);
-
-        // Check session limit (sync O(1) lookup)
-        let current_count = COMMAND_STORAGE.count_by_session(&session_id);
-        if current_count >= MAX_ASYNC_COMMANDS_PER_SESSION {
-            return Err(format!(
-                "Maximum async commands per session reached ({}). Cancel or wait for existing commands to complete.",
-                MAX_ASYNC_COMMANDS_PER_SESSION
-            ));
+        /// CPU priority via `nice -n` (-20 highest to 19 lowest). Use 10-19 for heavy jobs on production hosts.
+        nice: Option<i32>,
+        /// I/O priority class via `ionice`: "idle", "best-effort" or "realtime". Skipped where ionice is unavailable.
+        ionice: Option<String>,
+        /// Resource limits on the remote host: wall_secs (timeout), cpu_secs, max_file_mb, max_open_files, max_memory_mb (ulimit), cgroup_memory_mb, cgroup_cpu_percent (systemd-run). The operator's [sandbox] config is a ceiling.
+        sandbox: Option<SandboxLimits>,
+        /// Short purpose label shown in ssh_list_commands (e.g., "deploy v1.2.3 step 2/5")
+        label: Option<String>,
+        /// http:// URL the final output (as returned by ssh_get_command_output) is POSTed to when the command finishes. The host must be allowed by the server's [callbacks] configuration.
+        callback_url: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+        /// Push output to this client as it arrives, as notifications/message (logger "ssh_command") with command_id, seq, stdout/stderr; the last one has closed=true, status and exit_code (default: false)
+        stream_output: Option<bool>,
+        /// Output kept per stream (stdout and stderr each), in bytes; 0 for unlimited (default: 67108864, env: SSH_MAX_OUTPUT_BYTES)
+        max_output_bytes: Option<u64>,
+        /// Past max_output_bytes: "ring" keeps the newest output, "head" keeps the first (default: "ring", env: SSH_OUTPUT_OVERFLOW)
+        output_overflow: Option<String>,
+        /// Environment variables for this command, merged over those given to ssh_connect (e.g. {"LANG": "C.UTF-8"}). Sent as SSH env requests; variables the server rejects are exported in the command line instead
+        env: Option<BTreeMap<String, String>>,
+        /// Remote directory to run the command in (quoted safely; a leading ~/ means the home directory). If it cannot be entered the command does not run and exits with 1
+        cwd: Option<String>,
+        /// Regex marking the command completed as soon as stdout or stderr matches (e.g. "Listening on port \d+")
+        success_pattern: Option<String>,
+        /// Regex marking the command failed as soon as stdout or stderr matches; checked before success_pattern (e.g. "(?i)fatal|panic")
+        failure_pattern: Option<String>,
+        /// Send SIGTERM and close the command when a pattern matches; false keeps it running (default: true)
+        terminate_on_match: Option<bool>,
+        /// How ssh_get_command_output and the callback send stdout/stderr: "text" (invalid UTF-8 replaced) or "base64" for binary output such as tarballs or images (default: "text")
+        output_encoding: Option<String>,
+    ) -> Result<StructuredContent<SshExecuteResponse>, ToolError> {
+        if let Some(cwd) = &cwd {
+            validate_cwd(cwd).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
         }
-
-        // Get session handle and agent_id using storage abstraction
-        let (handle_arc, agent_id) = SESSION_STORAGE
-            .get(&session_id)
-            .map(|s| (s.handle.clone(), s.info.agent_id.clone()))
-            .ok_or_else(|| format!("No active SSH session with ID: {}", session_id))?;
-
-        let command_id = Uuid::new_v4().to_string();
-        let started_at = chrono::Utc::now().to_rfc3339();
-
-        // Create shared state with pre-allocated buffers
-        let (status_tx, status_rx) = watch::channel(AsyncCommandStatus::Running);
-        let output = Arc::new(Mutex::new(OutputBuffer::with_capacity(4096, 1024)));
-        let exit_code = Arc::new(Mutex::new(None));
-        let error = Arc::new(Mutex::new(None));
-        let timed_out = Arc::new(AtomicBool::new(false));
-        let cancel_token = CancellationToken::new();
-
-        // Create command info
-        let cmd_info = AsyncCommandInfo {
-            command_id: command_id.clone(),
-            session_id: session_id.clone(),
-            command: command.clone(),
-            status: AsyncCommandStatus::Running,
-            started_at: started_at.clone(),
-        };
-
-        // Store running command using storage abstraction
-        COMMAND_STORAGE.register(
-            command_id.clone(),
-            RunningCommand {
-                info: cmd_info,
-                cancel_token: cancel_token.clone(),
-                status_rx,
-                status_tx: status_tx.clone(),
-                output: output.clone(),
-                exit_code: exit_code.clone(),
-                error: error.clone(),
-                timed_out: timed_out.clone(),
-            },
-        );
-
-        info!(
-            "Starting async command {} on session {}: {}",
-            command_id, session_id, command
-        );
-
-        // Spawn background task (with or without PTY)
-        if pty.unwrap_or(false) {
-            tokio::spawn(execute_ssh_command_async_pty(
-                handle_arc,
-                command.clone(),
-                timeout,
-                output,
-                status_tx,
-                cancel_token,
-                exit_code,
-                error,
-                timed_out,
-            ));
-        } else {
-            tokio::spawn(execute_ssh_command_async(
-                handle_arc,
-                command.clone(),
-                timeout,
-                output,
-                status_tx,
-                cancel_token,
-                exit_code,
-                error,
-                timed_out,
-            ));
+        let patterns = ExitPatterns::resolve(
+            success_pattern.as_deref(),
+            failure_pattern.as_deref(),
+            terminate_on_match,
+        )
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let output_format = output_format(output_encoding.as_deref())?.unwrap_or_default();
+        let output_limit = output_limit(max_output_bytes, output_overflow.as_deref())?;
+        if let Some(env) = &env {
+            validate_env(env).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
         }
-
-        let message = ExecuteMessageBuilder::new(&command_id, &session_id, &command)
-            .with_agent_id(agent_id.as_deref())
-            .build();
-
-        Ok(StructuredContent(SshExecuteResponse {
-            command_id,
+        let priority = resolve_priority(nice, ionice.as_deref())?;
+        let sandbox = resolve_sandbox(sandbox)?;
+        let label =
+            normalize_label(label).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let callback = callback_url
+            .map(|url| {
+                resolve_callback(&url, &server_config().callbacks)
+                    .map(|target| (url, target))
+                    .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))
+            })
+            .transpose()?;
+        let stream = match stream_output.unwrap_or(false) {
+            true => Some(notify::current_client().ok_or_else(|| {
+                ToolError::new(
+                    ErrorCode::InvalidState,
+                    "This transport cannot deliver notifications; poll with ssh_get_command_output instead",
+                )
+            })?),
+            false => None,
+        };
+        start_command(
             session_id,
-            agent_id,
             command,
-            started_at,
-            message,
-        }))
+            timeout_secs,
+            pty,
+            priority,
+            sandbox,
+            label,
+            callback,
+            stream,
+            output_limit,
+            env,
+            cwd,
+            patterns,
+            output_format,
+            agent_id.as_deref(),
+        )
+        .await
+        .map(StructuredContent)
     }
 
     /// Get the current output and status of an async command.
@@ -578,7 +2135,15 @@ impl McpSSHCommands {
     /// **Blocking mode** (`wait=true`): Waits until the command completes or timeout expires.
     /// Use this when you need the final result and can wait.
     ///
+    /// **Long builds:** with `renew_wait=true` the wait is extended by another
+    /// `wait_timeout_secs` whenever the command produced output during the last
+    /// window (up to 30 minutes per call), so a build that keeps logging is
+    /// followed to the end. When a wait ends with the command still running,
+    /// `wait.expired` is true and `status` is `running`: the command has NOT
+    /// finished; call again to keep waiting.
+    ///
     /// **Status values:** `running`, `completed`, `cancelled`, `failed`
+    #[allow(clippy::too_many_arguments)]
     async fn ssh_get_command_output(
         &self,
         /// Command ID returned from ssh_execute_async
@@ -587,70 +2152,949 @@ impl McpSSHCommands {
         wait: Option<bool>,
         /// Max seconds to wait when wait=true (default: 30, max: 300)
         wait_timeout_secs: Option<u64>,
-    ) -> Result<StructuredContent<SshAsyncOutputResponse>, String> {
+        /// Keep waiting while the command is still producing output (default: false)
+        renew_wait: Option<bool>,
+        /// Send stdout/stderr above 64 KiB as gzip+base64, declared in stdout_encoding/stderr_encoding (default: false)
+        compress: Option<bool>,
+        /// "text" or "base64" (raw bytes, declared in stdout_encoding/stderr_encoding); overrides the command's ssh_execute output_encoding for this call
+        output_encoding: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshAsyncOutputResponse>, ToolError> {
+        let format = output_format(output_encoding.as_deref())?;
+        let session_id = command_session_id(&command_id)?;
+        authorize_owning_session(&session_id, agent_id.as_deref(), SessionAccess::Read).await?;
         let wait = wait.unwrap_or(false);
         let wait_timeout = Duration::from_secs(wait_timeout_secs.unwrap_or(30).min(300));
+        let renew_wait = renew_wait.unwrap_or(false);
 
-        // Get command using storage abstraction
-        let (status_rx, output, exit_code, error, timed_out) = COMMAND_STORAGE
-            .get_direct(&command_id)
-            .map(|cmd| {
-                (
-                    cmd.status_rx.clone(),
-                    cmd.output.clone(),
-                    cmd.exit_code.clone(),
-                    cmd.error.clone(),
-                    cmd.timed_out.clone(),
-                )
-            })
-            .ok_or_else(|| format!("No async command with ID: {}", command_id))?;
-
-        // Optionally wait for completion
-        if wait {
-            let mut rx = status_rx.clone();
-            let _ = tokio::time::timeout(wait_timeout, async {
-                loop {
-                    let status = *rx.borrow();
-                    if status != AsyncCommandStatus::Running {
-                        break;
-                    }
-                    if rx.changed().await.is_err() {
-                        break;
-                    }
-                }
-            })
-            .await;
-        }
-
-        // Get current state
-        let status = *status_rx.borrow();
-        let output_buf = output.lock().await;
-        let exit_code_val = *exit_code.lock().await;
-        let error_val = error.lock().await.clone();
-        let timed_out_val = timed_out.load(Ordering::SeqCst);
-
-        Ok(StructuredContent(SshAsyncOutputResponse {
+        command_output(
             command_id,
-            status,
-            stdout: String::from_utf8_lossy(&output_buf.stdout).into_owned(),
-            stderr: String::from_utf8_lossy(&output_buf.stderr).into_owned(),
-            exit_code: exit_code_val,
-            error: error_val,
-            timed_out: timed_out_val,
+            wait.then_some(wait_timeout),
+            renew_wait,
+            compress.unwrap_or(false),
+            format,
+        )
+        .await
+        .map(StructuredContent)
+    }
+
+    /// Follow a remote file and read new lines incrementally.
+    ///
+    /// Starts `tail -F` (follows rotation and files that appear later) as an
+    /// async command: the last `lines` lines first, then every appended line.
+    /// With `pattern`, only lines matching the extended regex are kept, by
+    /// `grep` on the remote host, so unrelated log noise costs no tokens.
+    ///
+    /// Read with ssh_tail_read(tail_id, cursor); stop with
+    /// ssh_cancel_command(tail_id). The tail ends by itself after
+    /// `timeout_secs` (default: 3600).
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_tail_file(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Remote file to follow
+        path: String,
+        /// Existing lines to return before following (default: 10, max: 10000; 0 for new lines only)
+        lines: Option<u32>,
+        /// Extended regex (grep -E); only matching lines are returned
+        pattern: Option<String>,
+        /// Match `pattern` case-insensitively (default: false)
+        ignore_case: Option<bool>,
+        /// Seconds until the tail stops by itself (default: 3600)
+        timeout_secs: Option<u64>,
+        /// Short label shown in ssh_list_commands (default: "tail <path>")
+        label: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshTailFileResponse>, ToolError> {
+        let command = tail_command(
+            &path,
+            lines,
+            pattern.as_deref(),
+            ignore_case.unwrap_or(false),
+        )
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let label = normalize_label(label.or_else(|| Some(format!("tail {}", path))))
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+
+        let started = start_command(
+            session_id,
+            command,
+            Some(timeout_secs.unwrap_or(DEFAULT_TAIL_TIMEOUT.as_secs())),
+            None,
+            Priority::default(),
+            resolve_sandbox(None)?,
+            label,
+            None,
+            None,
+            Some(TAIL_OUTPUT_LIMIT),
+            None,
+            None,
+            None,
+            OutputFormat::Text,
+            agent_id.as_deref(),
+        )
+        .await?;
+
+        let message = format!(
+            "Following {} on session {}. Read new lines with ssh_tail_read(tail_id='{}'), passing back the returned cursor; stop with ssh_cancel_command(command_id='{}')",
+            path, started.session_id, started.command_id, started.command_id
+        );
+        Ok(StructuredContent(SshTailFileResponse {
+            tail_id: started.command_id,
+            session_id: started.session_id,
+            path,
+            command: started.command,
+            started_at: started.started_at,
+            message,
+        }))
+    }
+
+    /// Read the lines a tail collected since `cursor`.
+    ///
+    /// Returns complete lines only and the `cursor` to pass next time; start
+    /// with cursor 0 (the default). With `wait_secs`, waits up to that long
+    /// for a new line when none is there yet, instead of returning empty.
+    ///
+    /// `more=true` means lines beyond `max_lines` are waiting. `skipped_bytes`
+    /// counts output evicted from the 4 MiB buffer before it was read. A
+    /// `status` other than `running` means the tail has ended (see `error`
+    /// and `notice`).
+    async fn ssh_tail_read(
+        &self,
+        /// Tail ID returned from ssh_tail_file
+        tail_id: String,
+        /// `cursor` of the previous ssh_tail_read (default: 0, from the start)
+        cursor: Option<u64>,
+        /// Lines to return at most (default: 200, max: 5000)
+        max_lines: Option<usize>,
+        /// Seconds to wait for a new line when none is available (default: 0, max: 60)
+        wait_secs: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshTailReadResponse>, ToolError> {
+        let (status_rx, output, error, session_id) = COMMAND_STORAGE
+            .get(&tail_id)
+            .map(|cmd| {
+                (
+                    cmd.status_rx.clone(),
+                    cmd.output.clone(),
+                    cmd.error.clone(),
+                    cmd.info.session_id.clone(),
+                )
+            })
+            .ok_or_else(|| ToolError::command_not_found(&tail_id))?;
+        authorize_owning_session(&session_id, agent_id.as_deref(), SessionAccess::Read).await?;
+        let cursor = cursor.unwrap_or(0);
+        let max_lines = max_lines
+            .unwrap_or(DEFAULT_TAIL_READ_LINES)
+            .clamp(1, MAX_TAIL_READ_LINES);
+        let deadline = tokio::time::Instant::now()
+            + Duration::from_secs(wait_secs.unwrap_or(0).min(MAX_TAIL_WAIT_SECS));
+
+        loop {
+            let status = *status_rx.borrow();
+            let finished = status != AsyncCommandStatus::Running;
+            let buffer = output.lock().await;
+            let (stream_start, _) = buffer.stream_starts();
+            let chunk = read_lines(&buffer.stdout, stream_start, cursor, max_lines, finished);
+            if chunk.lines.is_empty() && !finished && tokio::time::Instant::now() < deadline {
+                drop(buffer);
+                tokio::time::sleep(TAIL_POLL_INTERVAL).await;
+                continue;
+            }
+
+            let notice = String::from_utf8_lossy(&buffer.stderr)
+                .lines()
+                .rev()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_string);
+            return Ok(StructuredContent(SshTailReadResponse {
+                tail_id,
+                status,
+                lines: chunk.lines,
+                cursor: chunk.cursor,
+                skipped_bytes: chunk.skipped_bytes,
+                more: chunk.more,
+                notice,
+                error: error.lock().await.clone(),
+            }));
+        }
+    }
+
+    /// Execute a command and wait for its result in a single call.
+    ///
+    /// **Recommended for:** Quick commands (`uname -a`, `df -h`, `systemctl status`)
+    /// where the execute → poll two-step of `ssh_execute` is unnecessary.
+    ///
+    /// Returns the final stdout, stderr and exit code when the command finishes
+    /// within `wait_timeout_secs`. Otherwise returns `status: running` with the
+    /// `command_id`; continue with ssh_get_command_output or ssh_cancel_command.
+    ///
+    /// Finished commands are removed immediately and do not count against the
+    /// per-session command limit.
+    ///
+    /// With `verify_privilege_drop=true` a finished command is followed by the
+    /// ssh_verify_privilege_drop check, reported in `privilege_drop`.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_execute_sync(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Shell command to execute on the remote server
+        command: String,
+        /// Command execution timeout in seconds (default: 180, env: SSH_COMMAND_TIMEOUT)
+        timeout_secs: Option<u64>,
+        /// Allocate a pseudo-terminal (PTY) for the command. All output goes to stdout in PTY mode.
+        pty: Option<bool>,
+        /// Max seconds to wait for the result (default: 30, max: 300)
+        wait_timeout_secs: Option<u64>,
+        /// CPU priority via `nice -n` (-20 highest to 19 lowest)
+        nice: Option<i32>,
+        /// I/O priority class via `ionice`: "idle", "best-effort" or "realtime"
+        ionice: Option<String>,
+        /// Resource limits on the remote host (see ssh_execute)
+        sandbox: Option<SandboxLimits>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+        /// Send stdout/stderr above 64 KiB as gzip+base64 (see ssh_get_command_output)
+        compress: Option<bool>,
+        /// After the command finishes, invalidate sudo credentials and report lingering privileged processes (default: false)
+        verify_privilege_drop: Option<bool>,
+    ) -> Result<StructuredContent<SshExecuteSyncResponse>, ToolError> {
+        let wait_timeout_secs = wait_timeout_secs.unwrap_or(30).min(300);
+        let priority = resolve_priority(nice, ionice.as_deref())?;
+        let sandbox = resolve_sandbox(sandbox)?;
+
+        let started = start_command(
+            session_id,
+            command,
+            timeout_secs,
+            pty,
+            priority,
+            sandbox,
+            None,
+            None,
+            None,
+            output_limit(None, None)?,
+            None,
+            None,
+            None,
+            OutputFormat::Text,
+            agent_id.as_deref(),
+        )
+        .await?;
+        let mut response =
+            wait_for_result(started, wait_timeout_secs, compress.unwrap_or(false)).await?;
+        if verify_privilege_drop.unwrap_or(false) && response.status != AsyncCommandStatus::Running
+        {
+            let session_ref = get_session(&response.session_id).await?;
+            let report =
+                check_privilege_drop(&session_ref, Some(&response.command), agent_id.as_deref())
+                    .await?;
+            response.message = format!("{}. {}", response.message, report.summary());
+            response.privilege_drop = Some(report);
+        }
+        Ok(StructuredContent(response))
+    }
+
+    /// Run a command and return its output parsed into structured rows.
+    ///
+    /// **Recommended for:** disk usage, process lists, listening ports and
+    /// systemd units, where the raw table would cost many tokens.
+    ///
+    /// `parser` selects the format: `df` (filesystems), `ps` (processes),
+    /// `ss` (sockets) or `systemctl` (units). Without `command` the matching
+    /// default runs: `df -hP`, `ps aux`, `ss -tlnp` or `systemctl list-units`.
+    /// A custom `command` must print the same format (e.g. `df -h /var`,
+    /// `sudo ss -tulnp`).
+    ///
+    /// Rows are returned in `filesystems`, `processes`, `sockets` or `units`;
+    /// lines the parser did not recognize are listed in `unparsed_lines`.
+    /// Waits like ssh_execute_sync; a command still running after
+    /// `wait_timeout_secs` returns `status: running` and nothing parsed.
+    /// `nice`, `ionice` and `sandbox` bound the impact of the diagnostic on a
+    /// busy host, as in ssh_execute.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_execute_parsed(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Output format: "df", "ps", "ss" or "systemctl"
+        parser: String,
+        /// Command printing that format (default: "df -hP", "ps aux", "ss -tlnp" or "systemctl list-units --no-pager --plain --no-legend", run with LC_ALL=C)
+        command: Option<String>,
+        /// Command execution timeout in seconds (default: 180, env: SSH_COMMAND_TIMEOUT)
+        timeout_secs: Option<u64>,
+        /// Max seconds to wait for the result (default: 30, max: 300)
+        wait_timeout_secs: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+        /// CPU priority via `nice -n` (-20 highest to 19 lowest)
+        nice: Option<i32>,
+        /// I/O priority class via `ionice`: "idle", "best-effort" or "realtime"
+        ionice: Option<String>,
+        /// Resource limits on the remote host (see ssh_execute)
+        sandbox: Option<SandboxLimits>,
+    ) -> Result<StructuredContent<SshExecuteParsedResponse>, ToolError> {
+        let parser = OutputParser::from_name(&parser)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let command = match command.map(|command| command.trim().to_string()) {
+            Some(command) if command.is_empty() => {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    "command must not be empty",
+                ));
+            }
+            Some(command) => command,
+            None => parser.default_command().to_string(),
+        };
+        let wait_timeout_secs = wait_timeout_secs.unwrap_or(30).min(300);
+        let priority = resolve_priority(nice, ionice.as_deref())?;
+        let sandbox = resolve_sandbox(sandbox)?;
+
+        let started = start_command(
+            session_id,
+            command,
+            timeout_secs,
+            None,
+            priority,
+            sandbox,
+            None,
+            None,
+            None,
+            output_limit(None, None)?,
+            None,
+            None,
+            None,
+            OutputFormat::Text,
+            agent_id.as_deref(),
+        )
+        .await?;
+        let result = wait_for_result(started, wait_timeout_secs, false).await?;
+        Ok(StructuredContent(parsed_response(parser, result)))
+    }
+
+    /// Run an operator-vetted helper script and wait for its result.
+    ///
+    /// Helpers are scripts the server operator reviewed and placed in the
+    /// `[helpers]` library; the server keeps them current on every host under
+    /// `~/.ssh-mcp/helpers`. Only library names can be run, which makes them
+    /// safe building blocks (disk reports, log rotation, service checks).
+    /// Calling an unknown name returns the available ones.
+    ///
+    /// `args` are passed to the script as separate, shell-quoted arguments.
+    /// `nice`, `ionice` and `sandbox` apply as in ssh_execute. The response is
+    /// the same as ssh_execute_sync.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_run_helper(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Helper script name from the library (e.g., "disk-report.sh")
+        name: String,
+        /// Arguments passed to the script
+        args: Option<Vec<String>>,
+        /// Script execution timeout in seconds (default: 180, env: SSH_COMMAND_TIMEOUT)
+        timeout_secs: Option<u64>,
+        /// Max seconds to wait for the result (default: 30, max: 300)
+        wait_timeout_secs: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+        /// CPU priority via `nice -n` (-20 highest to 19 lowest)
+        nice: Option<i32>,
+        /// I/O priority class via `ionice`: "idle", "best-effort" or "realtime"
+        ionice: Option<String>,
+        /// Resource limits on the remote host (see ssh_execute)
+        sandbox: Option<SandboxLimits>,
+    ) -> Result<StructuredContent<SshExecuteSyncResponse>, ToolError> {
+        let wait_timeout_secs = wait_timeout_secs.unwrap_or(30).min(300);
+        let priority = resolve_priority(nice, ionice.as_deref())?;
+        let sandbox = resolve_sandbox(sandbox)?;
+        let library = HelperLibrary::load(&server_config().helpers)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidState, e))?
+            .ok_or_else(|| {
+                ToolError::new(
+                    ErrorCode::FeatureDisabled,
+                    "No helper library is configured ([helpers] dir)",
+                )
+            })?;
+        if library.get(&name).is_none() {
+            let available: Vec<&str> = library
+                .helpers
+                .iter()
+                .map(|helper| helper.name.as_str())
+                .collect();
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Unknown helper '{}'. Available: {}",
+                    name,
+                    if available.is_empty() {
+                        "none".to_string()
+                    } else {
+                        available.join(", ")
+                    }
+                ),
+            )
+            .with_detail("name", name));
+        }
+
+        let session_ref = get_session(&session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+        read_only::check_tool(&session_ref.info, "ssh_run_helper")?;
+        if !is_synced(&session_id, &library) {
+            let sync = sync_helpers(
+                &session_ref.handle,
+                &session_ref.traffic,
+                &session_id,
+                &library,
+            )
+            .await
+            .map_err(|e| {
+                ToolError::new(ErrorCode::RemoteFileError, e)
+                    .with_stage("helper_sync")
+                    .with_detail("session_id", session_id.as_str())
+            })?;
+            if sync.failed.contains(&name) {
+                return Err(ToolError::new(
+                    ErrorCode::RemoteFileError,
+                    format!("Helper '{}' could not be uploaded", name),
+                )
+                .with_stage("helper_sync")
+                .with_detail("session_id", session_id.as_str()));
+            }
+        }
+
+        let command = library.command(&name, &args.unwrap_or_default());
+        let started = start_command(
+            session_id,
+            command,
+            timeout_secs,
+            None,
+            priority,
+            sandbox,
+            Some(format!("helper:{}", name)),
+            None,
+            None,
+            output_limit(None, None)?,
+            None,
+            None,
+            None,
+            OutputFormat::Text,
+            agent_id.as_deref(),
+        )
+        .await?;
+        wait_for_result(started, wait_timeout_secs, false)
+            .await
+            .map(StructuredContent)
+    }
+
+    /// Wait for several async commands to finish.
+    ///
+    /// Blocks until **all** listed commands (default) or **any** of them leave the
+    /// `running` state, or until `wait_timeout_secs` expires. Commands may belong
+    /// to different sessions, which makes this the join point for parallel work
+    /// started with ssh_execute.
+    ///
+    /// Returns the status and exit code of every listed command; fetch the output
+    /// with ssh_get_command_output.
+    async fn ssh_wait_commands(
+        &self,
+        /// Command IDs returned from ssh_execute
+        command_ids: Vec<String>,
+        /// Wait for "all" commands (default) or "any" command to finish
+        wait_for: Option<String>,
+        /// Max seconds to wait (default: 30, max: 300)
+        wait_timeout_secs: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshWaitCommandsResponse>, ToolError> {
+        let wait_any = match wait_for.as_deref().unwrap_or("all") {
+            "all" => false,
+            "any" => true,
+            other => {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("wait_for must be \"all\" or \"any\", got \"{}\"", other),
+                )
+                .with_detail("wait_for", other));
+            }
+        };
+        if command_ids.is_empty() {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                "command_ids must list at least one command",
+            ));
+        }
+        let wait_timeout = Duration::from_secs(wait_timeout_secs.unwrap_or(30).min(300));
+
+        let mut commands = Vec::with_capacity(command_ids.len());
+        for command_id in &command_ids {
+            let command = COMMAND_STORAGE
+                .get(command_id)
+                .ok_or_else(|| ToolError::command_not_found(command_id))?;
+            authorize_owning_session(
+                &command.info.session_id,
+                agent_id.as_deref(),
+                SessionAccess::Read,
+            )
+            .await?;
+            commands.push(command);
+        }
+
+        let waiters = commands
+            .iter()
+            .map(|cmd| Box::pin(wait_until_finished(cmd.status_rx.clone())));
+        if wait_any {
+            let _ = tokio::time::timeout(wait_timeout, select_all(waiters)).await;
+        } else {
+            let _ = tokio::time::timeout(wait_timeout, join_all(waiters)).await;
+        }
+
+        let mut statuses = Vec::with_capacity(commands.len());
+        for cmd in &commands {
+            let status = *cmd.status_rx.borrow();
+            statuses.push(CommandWaitStatus {
+                command_id: cmd.info.command_id.clone(),
+                session_id: cmd.info.session_id.clone(),
+                status,
+                exit_code: *cmd.exit_code.lock().await,
+                timed_out: cmd.timed_out.load(Ordering::SeqCst),
+            });
+        }
+
+        let finished = statuses
+            .iter()
+            .filter(|entry| entry.status != AsyncCommandStatus::Running)
+            .count();
+        let done = if wait_any {
+            finished > 0
+        } else {
+            finished == statuses.len()
+        };
+
+        Ok(StructuredContent(SshWaitCommandsResponse {
+            running: statuses.len() - finished,
+            finished,
+            done,
+            commands: statuses,
+        }))
+    }
+
+    /// Pick the best connected session in a region and/or datacenter.
+    ///
+    /// Sessions are tagged with `region`/`datacenter` in ssh_connect. Among
+    /// the sessions the caller may run commands on and that match every given
+    /// field (case-insensitive), the one not known to be unhealthy with the
+    /// lowest smoothed RTT wins. Fails with `session_not_found` when nothing
+    /// matches.
+    ///
+    /// ssh_execute_dag nodes accept the same `affinity` instead of a session_id.
+    async fn ssh_select_session(
+        &self,
+        /// Region to match (e.g., "eu-west-1")
+        region: Option<String>,
+        /// Datacenter to match (e.g., "dub-2")
+        datacenter: Option<String>,
+        /// Calling agent's ID; only sessions it may run commands on are considered
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshSelectSessionResponse>, ToolError> {
+        let affinity = resolve_affinity(region, datacenter)?;
+        let ranked = select_sessions(&affinity, agent_id.as_deref())?;
+        let session = ranked[0].clone();
+        let alternatives: Vec<String> = ranked[1..]
+            .iter()
+            .map(|info| info.session_id.clone())
+            .collect();
+        let message = format!(
+            "Selected session {} ({}) for {}; {} other match(es)",
+            session.session_id,
+            session.host,
+            affinity,
+            alternatives.len()
+        );
+
+        Ok(StructuredContent(SshSelectSessionResponse {
+            session_id: session.session_id.clone(),
+            session,
+            alternatives,
+            message,
+        }))
+    }
+
+    /// Run commands across sessions in dependency order (a DAG) in one call.
+    ///
+    /// Each node has an `id`, a `session_id`, a `command` and optional
+    /// `depends_on` ids. Instead of `session_id` a node may give an `affinity`
+    /// (`region`/`datacenter`); it runs on the session ssh_select_session
+    /// would pick. A node starts once all its dependencies succeeded
+    /// (exit code 0); independent nodes run in parallel, up to `concurrency`.
+    /// Use it for multi-host orchestration, e.g. build on one host, then deploy
+    /// to several, then smoke-test.
+    ///
+    /// Nodes whose dependency failed are skipped. With `fail_fast=true`
+    /// (default) the first failure also skips every node not yet started.
+    /// Blocks until every node has finished or been skipped; each node is bounded
+    /// by its own `timeout_secs`. `nice`, `ionice` and `sandbox` apply to every
+    /// node, as in ssh_execute.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_execute_dag(
+        &self,
+        /// Commands with their dependencies (max 100 nodes)
+        nodes: Vec<DagNodeSpec>,
+        /// Nodes run in parallel (default: 4, max: 32)
+        concurrency: Option<usize>,
+        /// Skip all not-yet-started nodes after the first failure (default: true)
+        fail_fast: Option<bool>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+        /// CPU priority via `nice -n` (-20 highest to 19 lowest)
+        nice: Option<i32>,
+        /// I/O priority class via `ionice`: "idle", "best-effort" or "realtime"
+        ionice: Option<String>,
+        /// Resource limits on the remote host (see ssh_execute)
+        sandbox: Option<SandboxLimits>,
+    ) -> Result<StructuredContent<SshExecuteDagResponse>, ToolError> {
+        let start = std::time::Instant::now();
+        let plan = plan_dag(&nodes).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let concurrency = resolve_dag_concurrency(concurrency);
+        let fail_fast = fail_fast.unwrap_or(true);
+        let priority = resolve_priority(nice, ionice.as_deref())?;
+        // Nodes run under the operator's sandbox limits, if any
+        let sandbox = resolve_sandbox(sandbox)?;
+
+        // Resolve and check every session up front so a bad id fails the call
+        // before anything runs
+        let node_sessions = nodes
+            .iter()
+            .map(|node| resolve_node_session(node, agent_id.as_deref()))
+            .collect::<Result<Vec<String>, ToolError>>()?;
+        for (node, session_id) in nodes.iter().zip(&node_sessions) {
+            enforce_command_policy(
+                "ssh_execute_dag",
+                session_id,
+                &node.command,
+                agent_id.as_deref(),
+            )
+            .map_err(|e| e.with_detail("node", node.id.as_str()))?;
+        }
+        let mut handles = HashMap::new();
+        let mut owners = HashMap::new();
+        for session_id in &node_sessions {
+            if handles.contains_key(session_id) {
+                continue;
+            }
+            let session_ref = get_session(session_id).await?;
+            authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+            handles.insert(session_id.clone(), session_ref.handle.clone());
+            owners.insert(session_id.clone(), session_ref.info.agent_id.clone());
+        }
+
+        let ids: Vec<String> = nodes.iter().map(|node| node.id.clone()).collect();
+        let runs = run_dag(&plan, &ids, concurrency, fail_fast, |index| {
+            let node = &nodes[index];
+            let session_id = &node_sessions[index];
+            let handle = handles[session_id].clone();
+            let timeout = resolve_command_timeout(node.timeout_secs);
+            let billed = billed_agent(agent_id.as_deref(), owners[session_id].as_deref());
+            async move {
+                USAGE.record_command(billed);
+                SESSION_STORAGE.record_activity(session_id, SessionActivity::CommandExecuted);
+                let started = std::time::Instant::now();
+                let remote_command = sandbox.wrap(&priority.wrap(&node.command));
+                let result = execute_ssh_command(&handle, &remote_command, timeout).await;
+                if let Ok(response) = &result {
+                    let output = response.stdout.len() + response.stderr.len();
+                    USAGE.record_output(billed, output);
+                    SESSION_STORAGE.record_activity(session_id, SessionActivity::Output(output));
+                }
+                let event = AuditEvent::new("ssh_execute_dag")
+                    .agent(Some(billed))
+                    .session_id(session_id)
+                    .target(node.command.as_str());
+                audit::record(match &result {
+                    Ok(_) => event,
+                    Err(e) => event.failed(e),
+                });
+                let elapsed_ms = round_ms(started.elapsed().as_secs_f64() * 1000.0);
+                match result {
+                    Ok(response) => (
+                        response.exit_code == 0 && !response.timed_out,
+                        (Some(response), None, elapsed_ms),
+                    ),
+                    Err(e) => (false, (None, Some(e), elapsed_ms)),
+                }
+            }
+        })
+        .await;
+
+        let results: Vec<DagNodeResult> = nodes
+            .iter()
+            .zip(node_sessions)
+            .zip(runs)
+            .map(|((node, session_id), run)| {
+                let mut result = DagNodeResult {
+                    id: node.id.clone(),
+                    session_id,
+                    status: DagNodeStatus::Skipped,
+                    exit_code: None,
+                    stdout: String::new(),
+                    stderr: String::new(),
+                    timed_out: false,
+                    error: None,
+                    elapsed_ms: None,
+                };
+                match run {
+                    NodeRun::Skipped { reason } => result.error = Some(reason),
+                    NodeRun::Ran {
+                        ok,
+                        value: (response, error, elapsed_ms),
+                    } => {
+                        result.status = if ok {
+                            DagNodeStatus::Succeeded
+                        } else {
+                            DagNodeStatus::Failed
+                        };
+                        result.elapsed_ms = Some(elapsed_ms);
+                        result.error = error;
+                        if let Some(response) = response {
+                            result.timed_out = response.timed_out;
+                            result.exit_code = (!response.timed_out).then_some(response.exit_code);
+                            result.stdout = response.stdout;
+                            result.stderr = response.stderr;
+                            if response.timed_out {
+                                result.error = Some("Command timed out".to_string());
+                            }
+                        }
+                    }
+                }
+                result
+            })
+            .collect();
+
+        let count = |status| results.iter().filter(|node| node.status == status).count();
+        let (succeeded, failed, skipped) = (
+            count(DagNodeStatus::Succeeded),
+            count(DagNodeStatus::Failed),
+            count(DagNodeStatus::Skipped),
+        );
+        info!(
+            "DAG finished: {} succeeded, {} failed, {} skipped",
+            succeeded, failed, skipped
+        );
+
+        Ok(StructuredContent(SshExecuteDagResponse {
+            success: succeeded == results.len(),
+            nodes: results,
+            succeeded,
+            failed,
+            skipped,
+            elapsed_ms: round_ms(start.elapsed().as_secs_f64() * 1000.0),
+        }))
+    }
+
+    /// Run commands on many sessions concurrently and return every result.
+    ///
+    /// **Recommended for:** Fleet-wide checks and changes (`uptime`, `df -h`,
+    /// `systemctl reload nginx`) instead of one ssh_execute_sync per host.
+    ///
+    /// Give `targets` as `{session_id, command}` pairs, or one `command` with
+    /// `session_ids`; a target without its own command uses `command`. Each
+    /// session may appear once, and results are keyed by session ID.
+    ///
+    /// Targets are independent: one that cannot run (unknown session, access
+    /// denied, command refused by policy) is `rejected` and the others still
+    /// run. Blocks until every target has finished; each command is bounded by
+    /// `timeout_secs`, else its session's command timeout. `nice`, `ionice`
+    /// and `sandbox` apply to every target, as in ssh_execute.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_execute_batch(
+        &self,
+        /// `{session_id, command}` pairs (max 100 targets in total)
+        targets: Option<Vec<BatchTarget>>,
+        /// Sessions that all run `command`
+        session_ids: Option<Vec<String>>,
+        /// Shell command for `session_ids` and for targets without their own
+        command: Option<String>,
+        /// Command timeout in seconds (default: 180, env: SSH_COMMAND_TIMEOUT)
+        timeout_secs: Option<u64>,
+        /// Targets run in parallel (default: 8, max: 32)
+        concurrency: Option<usize>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+        /// CPU priority via `nice -n` (-20 highest to 19 lowest)
+        nice: Option<i32>,
+        /// I/O priority class via `ionice`: "idle", "best-effort" or "realtime"
+        ionice: Option<String>,
+        /// Resource limits on the remote host (see ssh_execute)
+        sandbox: Option<SandboxLimits>,
+    ) -> Result<StructuredContent<SshExecuteBatchResponse>, ToolError> {
+        let start = std::time::Instant::now();
+        let targets = resolve_batch_targets(
+            targets.unwrap_or_default(),
+            session_ids.unwrap_or_default(),
+            command.as_deref(),
+        )
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let concurrency = resolve_batch_concurrency(concurrency);
+        let priority = resolve_priority(nice, ionice.as_deref())?;
+        // Targets run under the operator's sandbox limits, if any
+        let sandbox = resolve_sandbox(sandbox)?;
+
+        // Check every target before any runs; managed hosts connect in parallel
+        let prepared = join_all(targets.iter().map(|(session_id, command)| {
+            prepare_batch_target(session_id, command, agent_id.as_deref())
+        }))
+        .await;
+
+        let plan = DagPlan {
+            deps: vec![Vec::new(); targets.len()],
+        };
+        let ids: Vec<String> = targets
+            .iter()
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        let runs = run_dag(&plan, &ids, concurrency, false, |index| {
+            let (session_id, command) = &targets[index];
+            let prepared = &prepared[index];
+            let (priority, sandbox) = (&priority, &sandbox);
+            let agent_id = agent_id.as_deref();
+            async move {
+                let mut result = BatchTargetResult {
+                    command: command.clone(),
+                    status: BatchTargetStatus::Rejected,
+                    exit_code: None,
+                    stdout: String::new(),
+                    stderr: String::new(),
+                    timed_out: false,
+                    error: None,
+                    elapsed_ms: None,
+                };
+                let session_ref = match prepared {
+                    Ok(session_ref) => session_ref,
+                    Err(e) => {
+                        result.error = Some(e.message.clone());
+                        return (false, result);
+                    }
+                };
+                let billed = billed_agent(agent_id, session_ref.info.agent_id.as_deref());
+                USAGE.record_command(billed);
+                SESSION_STORAGE.record_activity(session_id, SessionActivity::CommandExecuted);
+                let timeout =
+                    resolve_command_timeout(timeout_secs.or(session_ref.info.command_timeout_secs));
+                let started = std::time::Instant::now();
+                let remote_command = sandbox.wrap(&priority.wrap(command));
+                let outcome =
+                    execute_ssh_command(&session_ref.handle, &remote_command, timeout).await;
+                let event = AuditEvent::new("ssh_execute_batch")
+                    .agent(Some(billed))
+                    .session_id(session_id)
+                    .target(command.as_str());
+                audit::record(match &outcome {
+                    Ok(_) => event,
+                    Err(e) => event.failed(e),
+                });
+                result.elapsed_ms = Some(round_ms(started.elapsed().as_secs_f64() * 1000.0));
+                result.status = BatchTargetStatus::Failed;
+                match outcome {
+                    Ok(response) => {
+                        let output = response.stdout.len() + response.stderr.len();
+                        USAGE.record_output(billed, output);
+                        SESSION_STORAGE
+                            .record_activity(session_id, SessionActivity::Output(output));
+                        if response.exit_code == 0 && !response.timed_out {
+                            result.status = BatchTargetStatus::Succeeded;
+                        }
+                        result.timed_out = response.timed_out;
+                        result.exit_code = (!response.timed_out).then_some(response.exit_code);
+                        result.stdout = response.stdout;
+                        result.stderr = response.stderr;
+                        if response.timed_out {
+                            result.error = Some("Command timed out".to_string());
+                        }
+                    }
+                    Err(e) => result.error = Some(e),
+                }
+                (result.status == BatchTargetStatus::Succeeded, result)
+            }
+        })
+        .await;
+
+        let results: BTreeMap<String, BatchTargetResult> = targets
+            .into_iter()
+            .zip(runs)
+            .map(|((session_id, command), run)| {
+                let result = match run {
+                    NodeRun::Ran { value, .. } => value,
+                    // Targets have no dependencies and never fail fast
+                    NodeRun::Skipped { reason } => BatchTargetResult {
+                        command,
+                        status: BatchTargetStatus::Rejected,
+                        exit_code: None,
+                        stdout: String::new(),
+                        stderr: String::new(),
+                        timed_out: false,
+                        error: Some(reason),
+                        elapsed_ms: None,
+                    },
+                };
+                (session_id, result)
+            })
+            .collect();
+
+        let count = |status| {
+            results
+                .values()
+                .filter(|result| result.status == status)
+                .count()
+        };
+        let (succeeded, failed, rejected) = (
+            count(BatchTargetStatus::Succeeded),
+            count(BatchTargetStatus::Failed),
+            count(BatchTargetStatus::Rejected),
+        );
+        info!(
+            "Batch finished: {} succeeded, {} failed, {} rejected",
+            succeeded, failed, rejected
+        );
+
+        Ok(StructuredContent(SshExecuteBatchResponse {
+            success: succeeded == results.len(),
+            results,
+            succeeded,
+            failed,
+            rejected,
+            elapsed_ms: round_ms(start.elapsed().as_secs_f64() * 1000.0),
         }))
     }
 
-    /// List all async commands, optionally filtered by session or status.
+    /// List all async commands, optionally filtered by session, status or label.
     ///
     /// Useful for monitoring multiple concurrent operations or checking
     /// which commands are still running before disconnecting a session.
+    /// `label` matches commands whose ssh_execute label contains the text
+    /// (case-insensitive), e.g. "deploy" or "step 2".
+    ///
+    /// Only commands of sessions `agent_id` may use are listed (all sessions
+    /// when `agent_id` is omitted and the client is not bound to an agent).
     async fn ssh_list_commands(
         &self,
         /// Filter by session ID
         session_id: Option<String>,
         /// Filter by status: "running", "completed", "cancelled", "failed"
         status: Option<String>,
-    ) -> StructuredContent<SshListCommandsResponse> {
+        /// Filter by label substring (case-insensitive)
+        label: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshListCommandsResponse>, ToolError> {
+        if let Some(session_id) = &session_id {
+            authorize_owning_session(session_id, agent_id.as_deref(), SessionAccess::Read).await?;
+        }
         let status_filter: Option<AsyncCommandStatus> = status.and_then(|s| match s.as_str() {
             "running" => Some(AsyncCommandStatus::Running),
             "completed" => Some(AsyncCommandStatus::Completed),
@@ -660,13 +3104,32 @@ impl McpSSHCommands {
         });
 
         // Use storage trait method for filtered listing (LSP compliance)
-        let filtered = COMMAND_STORAGE.list_filtered(session_id.as_deref(), status_filter);
+        let label = label
+            .as_deref()
+            .map(str::trim)
+            .filter(|query| !query.is_empty());
+        let filtered: Vec<AsyncCommandInfo> = COMMAND_STORAGE
+            .list_filtered(session_id.as_deref(), status_filter, label)
+            .into_iter()
+            .filter(|cmd| {
+                SESSION_STORAGE
+                    .get(&cmd.session_id)
+                    .is_some_and(|session_ref| {
+                        check_session_access(
+                            &session_ref.info,
+                            agent_id.as_deref(),
+                            SessionAccess::Read,
+                        )
+                        .is_ok()
+                    })
+            })
+            .collect();
 
         let count = filtered.len();
-        StructuredContent(SshListCommandsResponse {
+        Ok(StructuredContent(SshListCommandsResponse {
             commands: filtered,
             count,
-        })
+        }))
     }
 
     /// Cancel a running async command.
@@ -680,30 +3143,33 @@ impl McpSSHCommands {
         &self,
         /// Command ID to cancel
         command_id: String,
-    ) -> Result<StructuredContent<SshCancelCommandResponse>, String> {
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCancelCommandResponse>, ToolError> {
         // Get command using storage abstraction
-        let (cancel_token, output, status_rx) = COMMAND_STORAGE
-            .get_direct(&command_id)
-            .map(|cmd| {
-                let current_status = *cmd.status_rx.borrow();
-                (
-                    current_status,
-                    cmd.cancel_token.clone(),
-                    cmd.output.clone(),
-                    cmd.status_rx.clone(),
-                )
-            })
-            .ok_or_else(|| format!("No async command with ID: {}", command_id))
-            .and_then(|(current_status, cancel_token, output, status_rx)| {
-                if current_status != AsyncCommandStatus::Running {
-                    Err(format!(
-                        "Command is not running (status: {})",
-                        current_status
-                    ))
-                } else {
-                    Ok((cancel_token, output, status_rx))
-                }
-            })?;
+        let cmd = COMMAND_STORAGE
+            .get(&command_id)
+            .ok_or_else(|| ToolError::command_not_found(&command_id))?;
+        authorize_owning_session(
+            &cmd.info.session_id,
+            agent_id.as_deref(),
+            SessionAccess::Write,
+        )
+        .await?;
+        if !cmd.is_active().await {
+            let current_status = *cmd.status_rx.borrow();
+            return Err(ToolError::new(
+                ErrorCode::InvalidState,
+                format!("Command is not running (status: {})", current_status),
+            )
+            .with_detail("command_id", command_id.as_str())
+            .with_detail("status", current_status.to_string()));
+        }
+        let (cancel_token, output, status_rx) = (
+            cmd.cancel_token.clone(),
+            cmd.output.clone(),
+            cmd.status_rx.clone(),
+        );
 
         // Signal cancellation
         cancel_token.cancel();
@@ -736,68 +3202,188 @@ impl McpSSHCommands {
         }))
     }
 
-    /// Disconnect ALL sessions for a specific agent.
-    ///
-    /// Use this for cleanup when an agent is done. This will:
-    /// - Cancel all running commands for the agent's sessions
-    /// - Disconnect all sessions owned by the agent
-    /// - Other agents' sessions are NOT affected
+    /// Send a signal (INT, TERM, KILL, HUP, ...) to a running async command.
     ///
-    /// **Required identifier:**
-    /// - `agent_id`: The agent identifier from ssh_connect
-    async fn ssh_disconnect_agent(
+    /// Unlike ssh_cancel_command, which closes the channel, this lets the
+    /// command react: TERM asks it to shut down cleanly, INT interrupts it like
+    /// Ctrl+C, HUP asks daemons to reload. The command keeps running until it
+    /// exits; poll with ssh_get_command_output. Servers that do not implement
+    /// SSH signal requests ignore them, so fall back to ssh_cancel_command when
+    /// `status` stays `running`.
+    async fn ssh_command_signal(
         &self,
-        /// The agent identifier to disconnect all sessions for
-        agent_id: String,
-    ) -> Result<StructuredContent<AgentDisconnectResponse>, String> {
-        info!("Disconnecting all sessions for agent: {}", agent_id);
+        /// Command ID returned from ssh_execute
+        command_id: String,
+        /// Signal name: INT, TERM, KILL, HUP, QUIT, USR1, ABRT, ALRM or PIPE (`SIG` prefix optional)
+        signal: String,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCommandSignalResponse>, ToolError> {
+        let (sig, signal) =
+            parse_signal(&signal).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let cmd = COMMAND_STORAGE
+            .get(&command_id)
+            .ok_or_else(|| ToolError::command_not_found(&command_id))?;
+        let session_ref = authorize_owning_session(
+            &cmd.info.session_id,
+            agent_id.as_deref(),
+            SessionAccess::Write,
+        )
+        .await?;
+        let (signal_tx, status_rx, exit_code) = (
+            cmd.signal_tx.clone(),
+            cmd.status_rx.clone(),
+            cmd.exit_code.clone(),
+        );
 
-        // Get and remove all session IDs for this agent atomically
-        let session_ids = SESSION_STORAGE.remove_agent_sessions(&agent_id);
+        let current_status = *status_rx.borrow();
+        if !cmd.is_active().await {
+            return Err(ToolError::new(
+                ErrorCode::InvalidState,
+                format!("Command is not running (status: {})", current_status),
+            )
+            .with_detail("command_id", command_id.as_str())
+            .with_detail("status", current_status.to_string()));
+        }
 
-        if session_ids.is_empty() {
+        audit::record(
+            AuditEvent::new("ssh_command_signal")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!("command {} (SIG{})", command_id, signal)),
+        );
+
+        signal::send(&signal_tx, sig).await.map_err(|e| {
+            ToolError::channel(e)
+                .with_stage("signal")
+                .with_detail("command_id", command_id.as_str())
+        })?;
+        info!("Sent SIG{} to async command {}", signal, command_id);
+
+        // Give the command a moment to exit so the status reflects the signal
+        let _ = tokio::time::timeout(
+            SIGNAL_SETTLE_TIMEOUT,
+            wait_until_finished(status_rx.clone()),
+        )
+        .await;
+        let status = *status_rx.borrow();
+        let message = if status == AsyncCommandStatus::Running {
+            format!(
+                "Sent SIG{} to command '{}'; it is still running. Poll with ssh_get_command_output, or stop it with ssh_cancel_command if the server ignores signals.",
+                signal, command_id
+            )
+        } else {
+            format!(
+                "Sent SIG{} to command '{}'; it is now {}",
+                signal, command_id, status
+            )
+        };
+
+        Ok(StructuredContent(SshCommandSignalResponse {
+            command_id,
+            signal: signal.to_string(),
+            status,
+            exit_code: *exit_code.lock().await,
+            message,
+        }))
+    }
+
+    /// Cancel every running async command on a session.
+    ///
+    /// Use this instead of one ssh_cancel_command per command_id when a batch of
+    /// work on a session must be aborted. The session itself stays connected and
+    /// cancelled commands keep their partial output for ssh_get_command_output.
+    async fn ssh_cancel_session_commands(
+        &self,
+        /// Session ID whose running commands should be cancelled
+        session_id: String,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCancelSessionCommandsResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+
+        let mut command_ids = Vec::new();
+        let mut waiters = Vec::new();
+        for command_id in COMMAND_STORAGE.list_by_session(&session_id) {
+            let Some(cmd) = COMMAND_STORAGE.get(&command_id) else {
+                continue;
+            };
+            if *cmd.status_rx.borrow() != AsyncCommandStatus::Running {
+                continue;
+            }
+            cmd.cancel_token.cancel();
+            waiters.push(wait_until_finished(cmd.status_rx.clone()));
+            command_ids.push(command_id);
+        }
+
+        // Wait briefly for cancellation to take effect
+        let _ = tokio::time::timeout(Duration::from_secs(2), join_all(waiters)).await;
+
+        let cancelled = command_ids.len();
+        info!(
+            "Cancelled {} async commands on session {}",
+            cancelled, session_id
+        );
+
+        Ok(StructuredContent(SshCancelSessionCommandsResponse {
+            message: format!(
+                "Cancelled {} running command(s) on session '{}'",
+                cancelled, session_id
+            ),
+            session_id,
+            cancelled,
+            command_ids,
+        }))
+    }
+
+    /// Disconnect ALL sessions for a specific agent.
+    ///
+    /// Use this for cleanup when an agent is done. This will:
+    /// - Cancel all running commands for the agent's sessions
+    /// - Disconnect all sessions owned by the agent
+    /// - Other agents' sessions are NOT affected
+    ///
+    /// **Required identifier:**
+    /// - `agent_id`: The agent identifier from ssh_connect
+    async fn ssh_disconnect_agent(
+        &self,
+        /// The agent identifier to disconnect all sessions for
+        agent_id: String,
+    ) -> Result<StructuredContent<AgentDisconnectResponse>, ToolError> {
+        info!("Disconnecting all sessions for agent: {}", agent_id);
+
+        // Get and remove all session IDs for this agent atomically
+        let session_ids = SESSION_STORAGE.remove_agent_sessions(&agent_id);
+        audit::record(
+            AuditEvent::new("ssh_disconnect_agent")
+                .agent(Some(&agent_id))
+                .target(session_ids.join(",")),
+        );
+
+        if session_ids.is_empty() {
             let message = AgentDisconnectMessageBuilder::new(&agent_id)
                 .with_sessions_disconnected(0)
                 .with_commands_cancelled(0)
+                .with_template(server_config().messages.agent_disconnect.as_deref())
                 .build();
             return Ok(StructuredContent(AgentDisconnectResponse {
                 agent_id: agent_id.clone(),
                 sessions_disconnected: 0,
                 commands_cancelled: 0,
+                shells_closed: 0,
+                forwards_closed: 0,
                 message,
             }));
         }
 
-        let mut total_commands_cancelled = 0;
-
-        let mut total_shells_closed = 0;
+        let mut total = SessionTeardown::default();
 
         // Process each session
         for session_id in &session_ids {
-            // Close all interactive shells for this session
-            let shell_ids = SHELL_STORAGE.list_by_session(session_id);
-            for shell_id in &shell_ids {
-                if let Some(shell) = SHELL_STORAGE.unregister(shell_id) {
-                    shell.cancel_token.cancel();
-                    let writer = shell.channel_writer.lock().await;
-                    let _ = writer.close().await;
-                }
-            }
-            total_shells_closed += shell_ids.len();
-
-            // Cancel all async commands for this session
-            let command_ids = COMMAND_STORAGE.list_by_session(session_id);
-            for cmd_id in &command_ids {
-                if let Some(cmd_ref) = COMMAND_STORAGE.get_ref(cmd_id) {
-                    cmd_ref.running.cancel_token.cancel();
-                }
-            }
-            total_commands_cancelled += command_ids.len();
-
-            // Remove cancelled commands
-            for cmd_id in command_ids {
-                COMMAND_STORAGE.unregister(&cmd_id);
-            }
+            let teardown = tear_down_session(session_id).await;
+            total.shells += teardown.shells;
+            total.commands += teardown.commands;
+            total.forwards += teardown.forwards;
 
             // Disconnect the session
             if let Some(session_ref) = SESSION_STORAGE.remove(session_id)
@@ -813,19 +3399,102 @@ impl McpSSHCommands {
         let sessions_disconnected = session_ids.len();
 
         info!(
-            "Disconnected {} sessions, cancelled {} commands, and closed {} shells for agent {}",
-            sessions_disconnected, total_commands_cancelled, total_shells_closed, agent_id
+            "Disconnected {} sessions, cancelled {} commands, closed {} shells and {} forwards for agent {}",
+            sessions_disconnected, total.commands, total.shells, total.forwards, agent_id
         );
 
         let message = AgentDisconnectMessageBuilder::new(&agent_id)
             .with_sessions_disconnected(sessions_disconnected)
-            .with_commands_cancelled(total_commands_cancelled)
+            .with_commands_cancelled(total.commands)
+            .with_shells_closed(total.shells)
+            .with_forwards_closed(total.forwards)
+            .with_template(server_config().messages.agent_disconnect.as_deref())
             .build();
 
         Ok(StructuredContent(AgentDisconnectResponse {
             agent_id: agent_id.clone(),
             sessions_disconnected,
-            commands_cancelled: total_commands_cancelled,
+            commands_cancelled: total.commands,
+            shells_closed: total.shells,
+            forwards_closed: total.forwards,
+            message,
+        }))
+    }
+
+    /// Claim exclusive use of a session for a critical sequence.
+    ///
+    /// While the lease is active, ssh_execute, ssh_execute_sync, ssh_shell_open,
+    /// ssh_forward, ssh_cancel_session_commands and ssh_disconnect fail for every
+    /// other caller with a `session_locked` error naming the holder and expiry.
+    /// Pass your `agent_id` to those tools to use the locked session yourself.
+    ///
+    /// Calling again before expiry renews the lease. Release it with
+    /// ssh_unlock_session; an unreleased lease lapses after `ttl_secs`.
+    async fn ssh_lock_session(
+        &self,
+        /// Session ID to lock
+        session_id: String,
+        /// Agent claiming the lease
+        agent_id: String,
+        /// Lease duration in seconds (default: 300, max: 3600)
+        ttl_secs: Option<u64>,
+    ) -> Result<StructuredContent<SshLockSessionResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, Some(&agent_id), SessionAccess::Write)?;
+        let agent_id = acting_agent(Some(&agent_id)).unwrap_or(agent_id);
+
+        let ttl = resolve_lease_ttl(ttl_secs);
+        let (lease, renewed) = SESSION_LEASES
+            .acquire(&session_id, &agent_id, ttl, chrono::Utc::now())
+            .map_err(|held| session_locked(&held))?;
+
+        info!(
+            "Agent {} {} lease on session {} until {}",
+            agent_id,
+            if renewed { "renewed" } else { "acquired" },
+            session_id,
+            lease.expires_at
+        );
+
+        Ok(StructuredContent(SshLockSessionResponse {
+            message: format!(
+                "Session '{}' locked by agent '{}' until {}. Release it with ssh_unlock_session.",
+                session_id, agent_id, lease.expires_at
+            ),
+            lease,
+            renewed,
+        }))
+    }
+
+    /// Release a lease taken with ssh_lock_session.
+    ///
+    /// Only the holder can release an active lease. Unlocking a session that is
+    /// not locked (or whose lease already expired) succeeds with `released: false`.
+    async fn ssh_unlock_session(
+        &self,
+        /// Session ID to unlock
+        session_id: String,
+        /// Agent holding the lease
+        agent_id: String,
+    ) -> Result<StructuredContent<SshUnlockSessionResponse>, ToolError> {
+        let agent_id = acting_agent(Some(&agent_id)).unwrap_or(agent_id);
+        let released = SESSION_LEASES
+            .release(&session_id, &agent_id, chrono::Utc::now())
+            .map_err(|held| session_locked(&held))?;
+
+        let message = if released {
+            info!(
+                "Agent {} released lease on session {}",
+                agent_id, session_id
+            );
+            format!("Session '{}' unlocked", session_id)
+        } else {
+            format!("Session '{}' was not locked", session_id)
+        };
+
+        Ok(StructuredContent(SshUnlockSessionResponse {
+            session_id,
+            released,
             message,
         }))
     }
@@ -841,9 +3510,14 @@ impl McpSSHCommands {
     /// - Multi-step workflows requiring persistent shell state
     /// - Commands requiring terminal interaction
     ///
-    /// For Serial Over LAN (SOL) / IPMI / OOB access, use `term="vt100"` with `cols=80`, `rows=24`.
+    /// For Serial Over LAN (SOL) / IPMI / OOB access, use `term="vt100"` with `cols=80`, `rows=24`,
+    /// or ssh_open_console which also starts the vendor console command.
+    ///
+    /// **Working directory:** `cwd` starts the shell in that directory (a login
+    /// shell is exec'd there); a directory that cannot be entered fails the call.
     ///
     /// **Limits:** Up to 10 concurrent shells per session.
+    #[allow(clippy::too_many_arguments)]
     async fn ssh_shell_open(
         &self,
         /// Session ID returned from ssh_connect
@@ -854,188 +3528,2531 @@ impl McpSSHCommands {
         cols: Option<u32>,
         /// Terminal height in rows (default: 24)
         rows: Option<u32>,
-    ) -> Result<StructuredContent<SshShellOpenResponse>, String> {
-        let term = term.unwrap_or_else(|| "xterm".to_string());
-        let cols = cols.unwrap_or(80);
-        let rows = rows.unwrap_or(24);
-
-        // Check shell limit
-        let current_count = SHELL_STORAGE.count_by_session(&session_id);
-        if current_count >= MAX_SHELLS_PER_SESSION {
-            return Err(format!(
-                "Maximum shells per session reached ({}). Close existing shells first.",
-                MAX_SHELLS_PER_SESSION
-            ));
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+        /// Remote directory the shell starts in (quoted safely; a leading ~/ means the home directory)
+        cwd: Option<String>,
+    ) -> Result<StructuredContent<SshShellOpenResponse>, ToolError> {
+        if let Some(cwd) = &cwd {
+            validate_cwd(cwd).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
         }
+        open_shell(
+            "ssh_shell_open",
+            session_id,
+            term,
+            cols,
+            rows,
+            cwd,
+            agent_id.as_deref(),
+        )
+        .await
+        .map(StructuredContent)
+    }
 
-        // Get session handle and agent_id
-        let (handle_arc, agent_id) = SESSION_STORAGE
-            .get(&session_id)
-            .map(|s| (s.handle.clone(), s.info.agent_id.clone()))
-            .ok_or_else(|| format!("No active SSH session with ID: {}", session_id))?;
+    /// Send input (text, keystrokes, escape sequences) to an interactive shell.
+    ///
+    /// Sends raw input to the shell's PTY channel. Use this for:
+    /// - Typing commands (append `\n` for Enter)
+    /// - Sending control characters (`\x03` for Ctrl+C, `\x04` for Ctrl+D)
+    /// - Sending escape sequences (`\x1b[A` for arrow up)
+    ///
+    /// The input is recorded in the audit log; send su/sudo passwords with
+    /// ssh_shell_switch_user instead.
+    async fn ssh_shell_write(
+        &self,
+        /// Shell ID returned from ssh_shell_open
+        shell_id: String,
+        /// Input to send to the shell (text, control chars, escape sequences). Append \n for Enter.
+        input: String,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<Text<String>, ToolError> {
+        let (channel_writer, session_id) = SHELL_STORAGE
+            .get_direct(&shell_id)
+            .map(|shell| (shell.channel_writer.clone(), shell.info.session_id.clone()))
+            .ok_or_else(|| ToolError::shell_not_found(&shell_id))?;
+        let session_ref =
+            authorize_owning_session(&session_id, agent_id.as_deref(), SessionAccess::Write)
+                .await?;
+        read_only::check_tool(&session_ref.info, "ssh_shell_write")?;
+        enforce_tool_policy("ssh_shell_write", &session_ref.info, agent_id.as_deref())?;
 
-        // Open PTY channel with shell
-        let channel = open_pty_shell(&handle_arc, &term, cols, rows).await?;
+        // Passwords for su/sudo go through ssh_shell_switch_user, not here
+        audit::record(
+            AuditEvent::new("ssh_shell_write")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(audit::shell_input_target(
+                    &shell_id,
+                    &input,
+                    server_config().audit.redact_shell_input,
+                )),
+        );
 
-        let shell_id = Uuid::new_v4().to_string();
-        let opened_at = chrono::Utc::now().to_rfc3339();
+        let writer = channel_writer.lock().await;
+        writer.write(input.as_bytes()).await.map_err(|e| {
+            ToolError::channel(e)
+                .with_stage("write")
+                .with_detail("shell_id", shell_id.as_str())
+        })?;
 
-        let shell_info = ShellInfo {
-            shell_id: shell_id.clone(),
-            session_id: session_id.clone(),
-            term_type: term.clone(),
-            cols,
-            rows,
-            opened_at,
+        Ok(Text(format!(
+            "Sent {} bytes to shell '{}'",
+            input.len(),
+            shell_id
+        )))
+    }
+
+    /// Read accumulated output from an interactive shell.
+    ///
+    /// Returns all output buffered since the last read (when `clear=true`)
+    /// or all output since shell open (when `clear=false`).
+    ///
+    /// **Recommended workflow:**
+    /// 1. ssh_shell_write to send a command
+    /// 2. Wait briefly (shell needs time to produce output)
+    /// 3. ssh_shell_read with clear=true to get new output
+    ///
+    /// **Pagers:** when output ends in a `less`/`more` prompt (`--More--`,
+    /// `lines X-Y`, `:`, `(END)`), `pager_prompt` reports it. With
+    /// `pager="continue"` the read pages through (space) and quits at `(END)`;
+    /// `pager="quit"` sends `q` right away. Prefer `PAGER=cat` or `--no-pager`
+    /// when you control the command.
+    async fn ssh_shell_read(
+        &self,
+        /// Shell ID returned from ssh_shell_open
+        shell_id: String,
+        /// Clear the output buffer after reading (default: true). Set to false to peek without consuming.
+        clear: Option<bool>,
+        /// Pager prompt policy: "detect" (default, report only), "continue" (page to the end, then quit) or "quit"
+        pager: Option<String>,
+        /// Calling agent's ID; peeking needs a share, consuming output or paging a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshShellReadResponse>, ToolError> {
+        let clear = clear.unwrap_or(true);
+        let policy = PagerPolicy::parse(pager.as_deref())
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let access = if clear || policy != PagerPolicy::Detect {
+            SessionAccess::Write
+        } else {
+            SessionAccess::Read
         };
+        authorize_owning_session(&shell_session_id(&shell_id)?, agent_id.as_deref(), access)
+            .await?;
 
-        // Create shared state
-        let (status_tx, status_rx) = watch::channel(ShellStatus::Open);
-        let output = Arc::new(Mutex::new(Vec::with_capacity(4096)));
-        let cancel_token = CancellationToken::new();
-
-        // Split channel into independent read/write halves to avoid mutex contention.
-        // The reader exclusively owns the read half, while writes go through the write half.
-        let (read_half, write_half) = channel.split();
-        let writer = ChannelWriter::new(write_half);
-        let channel_writer = Arc::new(Mutex::new(writer));
-
-        // Spawn background reader task (owns read_half exclusively, no mutex needed)
-        let reader_output = output.clone();
-        let reader_cancel = cancel_token.clone();
-        let reader_status_tx = status_tx.clone();
-
-        tokio::spawn(async move {
-            shell_reader(read_half, reader_output, reader_cancel, reader_status_tx).await;
-        });
+        let (output_arc, channel_writer, status_rx) = SHELL_STORAGE
+            .get_direct(&shell_id)
+            .map(|shell| {
+                (
+                    shell.output.clone(),
+                    shell.channel_writer.clone(),
+                    shell.status_rx.clone(),
+                )
+            })
+            .ok_or_else(|| ToolError::shell_not_found(&shell_id))?;
 
-        // Store running shell
-        SHELL_STORAGE.register(
-            shell_id.clone(),
-            RunningShell {
-                info: shell_info,
-                cancel_token,
-                output,
-                channel_writer,
-                status_tx,
-                status_rx,
-            },
-        );
+        let mut data = read_shell_buffer(&output_arc, clear, Vec::new()).await;
+        let mut pager_keys_sent = 0;
+        let mut pager_prompt = detect_pager_prompt(&data);
+        while let Some(key) = pager_prompt.and_then(|prompt| policy.key_for(prompt)) {
+            if pager_keys_sent >= MAX_PAGER_KEYS || *status_rx.borrow() != ShellStatus::Open {
+                break;
+            }
+            channel_writer.lock().await.write(key).await.map_err(|e| {
+                ToolError::channel(e)
+                    .with_stage("pager")
+                    .with_detail("shell_id", shell_id.as_str())
+            })?;
+            pager_keys_sent += 1;
+            wait_for_shell_output(&output_arc, if clear { 0 } else { data.len() }).await;
+            data = read_shell_buffer(&output_arc, clear, data).await;
+            pager_prompt = detect_pager_prompt(&data);
+        }
 
-        info!(
-            "Opened interactive shell {} on session {} (term={}, {}x{})",
-            shell_id, session_id, term, cols, rows
+        let status = *status_rx.borrow();
+
+        Ok(StructuredContent(SshShellReadResponse {
+            shell_id,
+            data: String::from_utf8_lossy(&data).into_owned(),
+            status,
+            pager_prompt,
+            pager_keys_sent,
+        }))
+    }
+
+    /// Wait until a shell's output matches a pattern.
+    ///
+    /// Blocks until the unread output matches `pattern` (a regex, or a plain
+    /// string with `literal=true`), the shell closes or `timeout_secs` passes.
+    /// Returns the matched text, capture groups and the output before it.
+    /// Output through the end of the match is consumed (`consume=false` to
+    /// peek); later output stays buffered.
+    ///
+    /// **Recommended workflow:**
+    /// 1. ssh_shell_write to send a command
+    /// 2. ssh_shell_expect with the prompt (e.g. `pattern="$ ", literal=true`)
+    ///    or an expected line (e.g. `pattern="(?m)^Done in \\d+s"`)
+    ///
+    /// Patterns see the raw terminal output, including `\r\n` line ends and
+    /// ANSI color codes. On timeout nothing is consumed; `before` shows what
+    /// arrived so far.
+    async fn ssh_shell_expect(
+        &self,
+        /// Shell ID returned from ssh_shell_open
+        shell_id: String,
+        /// Regex to wait for (Rust regex syntax; (?m) for per-line ^/$, (?i) for case-insensitive)
+        pattern: String,
+        /// Match pattern as a plain string, e.g. a prompt like "$ " (default: false)
+        literal: Option<bool>,
+        /// Seconds to wait for a match (default: 30, max: 300)
+        timeout_secs: Option<u64>,
+        /// Remove the output through the match from the buffer (default: true)
+        consume: Option<bool>,
+        /// Calling agent's ID; peeking needs a share, consuming output a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshShellExpectResponse>, ToolError> {
+        let pattern = ExpectPattern::new(&pattern, literal.unwrap_or(false))
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let timeout = resolve_expect_timeout(timeout_secs);
+        let consume = consume.unwrap_or(true);
+        let access = if consume {
+            SessionAccess::Write
+        } else {
+            SessionAccess::Read
+        };
+        authorize_owning_session(&shell_session_id(&shell_id)?, agent_id.as_deref(), access)
+            .await?;
+
+        let (output_arc, status_rx) = SHELL_STORAGE
+            .get_direct(&shell_id)
+            .map(|shell| (shell.output.clone(), shell.status_rx.clone()))
+            .ok_or_else(|| ToolError::shell_not_found(&shell_id))?;
+
+        let started = tokio::time::Instant::now();
+        let outcome = expect(&output_arc, &status_rx, &pattern, timeout, consume).await;
+        let waited_ms = round_ms(started.elapsed().as_secs_f64() * 1000.0);
+        let status = *status_rx.borrow();
+
+        let (matched_text, groups) = match outcome.matched {
+            Some((text, groups)) => (Some(text), groups),
+            None => (None, Vec::new()),
+        };
+        let timed_out = matched_text.is_none() && !outcome.closed;
+        let message = match (matched_text.is_some(), outcome.closed) {
+            (true, _) => None,
+            (false, true) => Some(
+                "Shell closed before the pattern appeared; `before` holds its last output"
+                    .to_string(),
+            ),
+            (false, false) => Some(format!(
+                "Pattern did not appear within {}s; nothing was consumed. Check `before`, then call ssh_shell_expect again or answer the shell with ssh_shell_write.",
+                timeout.as_secs()
+            )),
+        };
+
+        Ok(StructuredContent(SshShellExpectResponse {
+            shell_id,
+            matched: matched_text.is_some(),
+            matched_text,
+            groups,
+            before: outcome.before,
+            status,
+            timed_out,
+            waited_ms,
+            message,
+        }))
+    }
+
+    /// Send a signal (INT, TERM, KILL, HUP, ...) to an interactive shell.
+    ///
+    /// The signal goes to the shell process the server started, not to the
+    /// program running in its foreground: to interrupt a command typed into
+    /// the shell, write `\x03` (Ctrl+C) with ssh_shell_write instead. Use this
+    /// to hang up (HUP) or kill (KILL) a shell that no longer reads input.
+    /// Servers that do not implement SSH signal requests ignore them.
+    async fn ssh_shell_signal(
+        &self,
+        /// Shell ID returned from ssh_shell_open
+        shell_id: String,
+        /// Signal name: INT, TERM, KILL, HUP, QUIT, USR1, ABRT, ALRM or PIPE (`SIG` prefix optional)
+        signal: String,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshShellSignalResponse>, ToolError> {
+        let (sig, signal) =
+            parse_signal(&signal).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let (channel_writer, session_id, status_rx) = SHELL_STORAGE
+            .get_direct(&shell_id)
+            .map(|shell| {
+                (
+                    shell.channel_writer.clone(),
+                    shell.info.session_id.clone(),
+                    shell.status_rx.clone(),
+                )
+            })
+            .ok_or_else(|| ToolError::shell_not_found(&shell_id))?;
+        let session_ref =
+            authorize_owning_session(&session_id, agent_id.as_deref(), SessionAccess::Write)
+                .await?;
+
+        audit::record(
+            AuditEvent::new("ssh_shell_signal")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!("shell {} (SIG{})", shell_id, signal)),
         );
 
-        let message = ShellOpenMessageBuilder::new(&shell_id, &session_id, &term, cols, rows)
-            .with_agent_id(agent_id.as_deref())
-            .build();
+        channel_writer.lock().await.signal(sig).await.map_err(|e| {
+            ToolError::channel(e)
+                .with_stage("signal")
+                .with_detail("shell_id", shell_id.as_str())
+        })?;
+        info!("Sent SIG{} to shell {}", signal, shell_id);
+
+        // Give the shell a moment to exit so the status reflects the signal
+        let mut rx = status_rx;
+        let _ = tokio::time::timeout(SIGNAL_SETTLE_TIMEOUT, async {
+            while *rx.borrow() == ShellStatus::Open {
+                if rx.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+        .await;
+        let status = *rx.borrow();
+        let message = match status {
+            ShellStatus::Open => format!(
+                "Sent SIG{} to shell '{}'; it is still open. Use ssh_shell_close to close it.",
+                signal, shell_id
+            ),
+            _ => format!(
+                "Sent SIG{} to shell '{}'; it is now {}",
+                signal, shell_id, status
+            ),
+        };
 
-        Ok(StructuredContent(SshShellOpenResponse {
+        Ok(StructuredContent(SshShellSignalResponse {
             shell_id,
-            session_id,
-            agent_id,
-            term_type: term,
+            signal: signal.to_string(),
+            status,
             message,
         }))
     }
 
-    /// Send input (text, keystrokes, escape sequences) to an interactive shell.
-    ///
-    /// Sends raw input to the shell's PTY channel. Use this for:
-    /// - Typing commands (append `\n` for Enter)
-    /// - Sending control characters (`\x03` for Ctrl+C, `\x04` for Ctrl+D)
-    /// - Sending escape sequences (`\x1b[A` for arrow up)
-    async fn ssh_shell_write(
-        &self,
-        /// Shell ID returned from ssh_shell_open
-        shell_id: String,
-        /// Input to send to the shell (text, control chars, escape sequences). Append \n for Enter.
-        input: String,
-    ) -> Result<Text<String>, String> {
-        let channel_writer = SHELL_STORAGE
-            .get_direct(&shell_id)
-            .map(|shell| shell.channel_writer.clone())
-            .ok_or_else(|| format!("No active shell with ID: {}", shell_id))?;
+    /// Close an interactive shell session.
+    ///
+    /// Stops the background reader and closes the PTY channel.
+    /// Any buffered output is discarded.
+    async fn ssh_shell_close(
+        &self,
+        /// Shell ID to close
+        shell_id: String,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshShellCloseResponse>, ToolError> {
+        authorize_owning_session(
+            &shell_session_id(&shell_id)?,
+            agent_id.as_deref(),
+            SessionAccess::Write,
+        )
+        .await?;
+        let shell = SHELL_STORAGE
+            .unregister(&shell_id)
+            .ok_or_else(|| ToolError::shell_not_found(&shell_id))?;
+
+        // Cancel the background reader
+        shell.cancel_token.cancel();
+
+        // Close the channel
+        let writer = shell.channel_writer.lock().await;
+        let _ = writer.close().await;
+
+        info!("Closed interactive shell: {}", shell_id);
+
+        Ok(StructuredContent(SshShellCloseResponse {
+            shell_id,
+            closed: true,
+            message: "Shell closed successfully".to_string(),
+        }))
+    }
+
+    /// Stream a shell's output to this client as it arrives.
+    ///
+    /// Instead of polling with ssh_shell_read, the server sends every new
+    /// chunk of output as a `notifications/message` notification (logger
+    /// `ssh_shell`) whose `data` holds `subscription_id`, `shell_id`, `seq` and
+    /// `output`. Streaming lasts until ssh_shell_unsubscribe or until the shell
+    /// closes; the final notification has `closed: true` and a `reason`.
+    ///
+    /// Output produced before subscribing is not replayed, and output keeps
+    /// accumulating in the shell buffer for ssh_shell_read. Over streamable
+    /// HTTP the notifications arrive on the session's `GET` event stream.
+    async fn ssh_shell_subscribe(
+        &self,
+        /// Shell ID returned from ssh_shell_open
+        shell_id: String,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshShellSubscribeResponse>, ToolError> {
+        let sink = notify::current_client().ok_or_else(|| {
+            ToolError::new(
+                ErrorCode::InvalidState,
+                "This transport cannot deliver notifications; poll with ssh_shell_read instead",
+            )
+        })?;
+        let (output_rx, status_rx, session_id) = SHELL_STORAGE
+            .get_direct(&shell_id)
+            .map(|shell| {
+                (
+                    shell.output_tx.subscribe(),
+                    shell.status_rx.clone(),
+                    shell.info.session_id.clone(),
+                )
+            })
+            .ok_or_else(|| ToolError::shell_not_found(&shell_id))?;
+        let session_ref =
+            authorize_owning_session(&session_id, agent_id.as_deref(), SessionAccess::Read).await?;
+        if *status_rx.borrow() != ShellStatus::Open {
+            return Err(ToolError::new(
+                ErrorCode::InvalidState,
+                format!("Shell {} is closed", shell_id),
+            )
+            .with_detail("shell_id", shell_id.as_str()));
+        }
+        if subscription::count_for_shell(&shell_id) >= subscription::MAX_SUBSCRIPTIONS_PER_SHELL {
+            return Err(ToolError::new(
+                ErrorCode::LimitExceeded,
+                format!(
+                    "Shell {} already has {} subscriptions; unsubscribe one first",
+                    shell_id,
+                    subscription::MAX_SUBSCRIPTIONS_PER_SHELL
+                ),
+            )
+            .with_detail("shell_id", shell_id.as_str()));
+        }
+
+        let subscription_id = subscription::subscribe(&shell_id, output_rx, status_rx, sink);
+        audit::record(
+            AuditEvent::new("ssh_shell_subscribe")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!(
+                    "shell {} (subscription {})",
+                    shell_id, subscription_id
+                )),
+        );
+        info!(
+            "Subscription {} streams output of shell {}",
+            subscription_id, shell_id
+        );
+
+        Ok(StructuredContent(SshShellSubscribeResponse {
+            message: format!(
+                "Streaming output of shell {} as {} notifications (logger \"{}\")",
+                shell_id,
+                subscription::SHELL_OUTPUT_METHOD,
+                subscription::SHELL_OUTPUT_LOGGER
+            ),
+            subscription_id,
+            shell_id,
+            notification_method: subscription::SHELL_OUTPUT_METHOD.to_string(),
+            logger: subscription::SHELL_OUTPUT_LOGGER.to_string(),
+        }))
+    }
+
+    /// Stop streaming shell output started with ssh_shell_subscribe.
+    ///
+    /// A final notification with `closed: true` and `reason: "unsubscribed"`
+    /// marks the end of the stream. The shell itself stays open.
+    async fn ssh_shell_unsubscribe(
+        &self,
+        /// Subscription ID returned from ssh_shell_subscribe
+        subscription_id: String,
+    ) -> Result<StructuredContent<SshShellUnsubscribeResponse>, ToolError> {
+        let shell_id = subscription::unsubscribe(&subscription_id).ok_or_else(|| {
+            ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "No active subscription with ID: {} (it ends by itself when the shell closes)",
+                    subscription_id
+                ),
+            )
+            .with_detail("subscription_id", subscription_id.as_str())
+        })?;
+        info!(
+            "Subscription {} on shell {} stopped",
+            subscription_id, shell_id
+        );
+
+        Ok(StructuredContent(SshShellUnsubscribeResponse {
+            message: format!("Stopped streaming output of shell {}", shell_id),
+            subscription_id,
+            shell_id,
+            unsubscribed: true,
+        }))
+    }
+
+    /// Become another user inside an open shell (`sudo -i -u` or `su -`).
+    ///
+    /// Types the switch command, answers the password prompt once with
+    /// `password` (never echoed, logged or audited), then verifies the result
+    /// with `whoami`. On success the shell runs as `user` and later
+    /// ssh_shell_write input executes as that user; `effective_user` is
+    /// recorded on the shell. A rejected password or a sudoers/su error is
+    /// cancelled with Ctrl+C and reported as an error.
+    ///
+    /// Type `exit` with ssh_shell_write to return to the previous user; that
+    /// step is not tracked.
+    async fn ssh_shell_switch_user(
+        &self,
+        /// Shell ID returned from ssh_shell_open
+        shell_id: String,
+        /// Target user name
+        user: String,
+        /// "sudo" (default, asks for your own password) or "su" (asks for the target user's password)
+        method: Option<String>,
+        /// Password for the prompt, if one appears. Omit for passwordless sudo.
+        password: Option<String>,
+        /// Seconds allowed for the whole switch (default: 20, max: 120)
+        timeout_secs: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshShellSwitchUserResponse>, ToolError> {
+        let user = validate_user(&user)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?
+            .to_string();
+        let method = SwitchMethod::parse(method.as_deref())
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let timeout = timeout_secs
+            .map(|secs| Duration::from_secs(secs.clamp(1, MAX_SWITCH_TIMEOUT_SECS)))
+            .unwrap_or(DEFAULT_SWITCH_TIMEOUT);
+        let session_ref = authorize_owning_session(
+            &shell_session_id(&shell_id)?,
+            agent_id.as_deref(),
+            SessionAccess::Write,
+        )
+        .await?;
+        read_only::check_tool(&session_ref.info, "ssh_shell_switch_user")?;
+        enforce_tool_policy(
+            "ssh_shell_switch_user",
+            &session_ref.info,
+            agent_id.as_deref(),
+        )?;
+
+        switch_shell_user(&shell_id, &user, method, password.as_deref(), timeout)
+            .await
+            .map(StructuredContent)
+    }
+
+    /// Open a Serial-over-LAN / BMC serial console.
+    ///
+    /// Opens a shell with console defaults (`vt100`, 80x24) and starts the
+    /// vendor console command from a preset:
+    ///
+    /// | console | command | exit |
+    /// |---------|---------|------|
+    /// | `ipmitool` (default) | `ipmitool sol activate` | `~.` |
+    /// | `idrac` | `console com2` | Ctrl+\\ |
+    /// | `ilo` | `vsp` | ESC ( |
+    /// | `xcc` | `console 1` | ESC ( |
+    /// | `smash` | `start /system1/sol1` | ESC ( |
+    /// | `raw` | none (already a console) | - |
+    ///
+    /// Pass `command` to override the preset command, e.g.
+    /// `ipmitool -I lanplus -H bmc01 -U admin -E sol activate`.
+    ///
+    /// Read output with ssh_shell_read, type with ssh_shell_write, and send the
+    /// returned `exit_sequence` to leave the console before ssh_shell_close.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_open_console(
+        &self,
+        /// Session ID returned from ssh_connect (host with ipmitool, or the BMC itself)
+        session_id: String,
+        /// Console preset: "ipmitool" (default), "idrac", "ilo", "xcc", "smash" or "raw"
+        console: Option<String>,
+        /// Command that attaches the console, overriding the preset command
+        command: Option<String>,
+        /// Terminal type (default: "vt100")
+        term: Option<String>,
+        /// Terminal width in columns (default: 80)
+        cols: Option<u32>,
+        /// Terminal height in rows (default: 24)
+        rows: Option<u32>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshOpenConsoleResponse>, ToolError> {
+        let preset = console_preset(console.as_deref())
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let command = command.or_else(|| preset.command.map(str::to_string));
+
+        let shell = open_shell(
+            "ssh_open_console",
+            session_id,
+            Some(term.unwrap_or_else(|| CONSOLE_TERM.to_string())),
+            Some(cols.unwrap_or(CONSOLE_COLS)),
+            Some(rows.unwrap_or(CONSOLE_ROWS)),
+            None,
+            agent_id.as_deref(),
+        )
+        .await?;
+
+        if let Some(ref command) = command {
+            let channel_writer = SHELL_STORAGE
+                .get_direct(&shell.shell_id)
+                .map(|shell| shell.channel_writer.clone())
+                .ok_or_else(|| ToolError::shell_not_found(&shell.shell_id))?;
+            let writer = channel_writer.lock().await;
+            writer
+                .write(format!("{}\n", command).as_bytes())
+                .await
+                .map_err(|e| {
+                    ToolError::channel(e)
+                        .with_stage("console")
+                        .with_detail("shell_id", shell.shell_id.as_str())
+                })?;
+        }
+
+        info!(
+            "Opened {} console {} on session {}",
+            preset.name, shell.shell_id, shell.session_id
+        );
+
+        Ok(StructuredContent(SshOpenConsoleResponse {
+            message: format!(
+                "Console '{}' opened ({}). Read with ssh_shell_read, type with ssh_shell_write; leave the console with {} before ssh_shell_close.",
+                shell.shell_id, preset.name, preset.exit_hint
+            ),
+            shell_id: shell.shell_id,
+            session_id: shell.session_id,
+            console: preset.name.to_string(),
+            command,
+            term_type: shell.term_type,
+            exit_sequence: preset.exit_sequence.map(str::to_string),
+            exit_hint: preset.exit_hint.to_string(),
+        }))
+    }
+
+    /// Attach an interactive shell to a named tmux (or screen) session on the remote host.
+    ///
+    /// Creates the multiplexer session if it does not exist, otherwise attaches to
+    /// it. The returned `shell_id` works with ssh_shell_write and ssh_shell_read.
+    ///
+    /// **Persistence:** ssh_shell_close, a dropped connection or a server restart
+    /// only detach the client. The remote session keeps running; attach again
+    /// with the same `name` (from any new SSH session) to continue.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_tmux_attach(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Multiplexer session name (letters, digits, '-' and '_')
+        name: String,
+        /// Multiplexer to use: "tmux" (default) or "screen"
+        backend: Option<String>,
+        /// Terminal type (default: "xterm")
+        term: Option<String>,
+        /// Terminal width in columns (default: 80)
+        cols: Option<u32>,
+        /// Terminal height in rows (default: 24)
+        rows: Option<u32>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshTmuxAttachResponse>, ToolError> {
+        let backend = Multiplexer::from_param(backend.as_deref())
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        validate_session_name(&name).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+
+        let shell = open_shell(
+            "ssh_tmux_attach",
+            session_id,
+            term,
+            cols,
+            rows,
+            None,
+            agent_id.as_deref(),
+        )
+        .await?;
+        let channel_writer = SHELL_STORAGE
+            .get_direct(&shell.shell_id)
+            .map(|shell| shell.channel_writer.clone())
+            .ok_or_else(|| ToolError::shell_not_found(&shell.shell_id))?;
+
+        let writer = channel_writer.lock().await;
+        writer
+            .write(backend.attach_input(&name).as_bytes())
+            .await
+            .map_err(|e| {
+                ToolError::channel(e)
+                    .with_stage("attach")
+                    .with_detail("shell_id", shell.shell_id.as_str())
+            })?;
+
+        info!(
+            "Attached shell {} to {} session '{}' on session {}",
+            shell.shell_id, backend, name, shell.session_id
+        );
+
+        Ok(StructuredContent(SshTmuxAttachResponse {
+            message: format!(
+                "Shell '{}' attached to {} session '{}'. Use ssh_shell_write/ssh_shell_read; ssh_shell_close detaches and leaves the {} session running.",
+                shell.shell_id, backend, name, backend
+            ),
+            shell_id: shell.shell_id,
+            session_id: shell.session_id,
+            name,
+            backend: backend.to_string(),
+        }))
+    }
+
+    /// List tmux (or screen) sessions of the remote user.
+    ///
+    /// Use this to find sessions that survived a disconnect before re-attaching
+    /// with ssh_tmux_attach.
+    async fn ssh_tmux_list(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Multiplexer to query: "tmux" (default) or "screen"
+        backend: Option<String>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshTmuxListResponse>, ToolError> {
+        let backend = Multiplexer::from_param(backend.as_deref())
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let (response, reconnected) = execute_on_session(
+            &session_ref,
+            backend.list_command(),
+            MULTIPLEXER_LIST_TIMEOUT,
+        )
+        .await
+        .map_err(|e| {
+            ToolError::channel(e)
+                .with_stage("list_multiplexer_sessions")
+                .with_detail("session_id", session_id.as_str())
+        })?;
+
+        let sessions = backend.parse_sessions(&response.stdout);
+        let count = sessions.len();
+
+        Ok(StructuredContent(SshTmuxListResponse {
+            session_id,
+            backend: backend.to_string(),
+            sessions,
+            count,
+            reconnected,
+        }))
+    }
+    /// Create a symbolic link on the remote host.
+    ///
+    /// `target` is stored as given: relative targets resolve from the link's
+    /// directory. Fails if `link_path` exists unless `force` is set.
+    async fn ssh_symlink(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Path the link points to (absolute, or relative to the link's directory)
+        target: String,
+        /// Path of the link to create
+        link_path: String,
+        /// Replace an existing file or link at link_path (default: false)
+        force: Option<bool>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshSymlinkResponse>, ToolError> {
+        let session_ref = get_session(&session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+        read_only::check_tool(&session_ref.info, "ssh_symlink")?;
+        enforce_tool_policy("ssh_symlink", &session_ref.info, agent_id.as_deref())?;
+        audit::record(
+            AuditEvent::new("ssh_symlink")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!("{} -> {}", link_path, target)),
+        );
+
+        let command = symlink_command(&target, &link_path, force.unwrap_or(false));
+        let (response, reconnected) = execute_on_session(&session_ref, &command, SYMLINK_TIMEOUT)
+            .await
+            .map_err(|e| {
+                ToolError::channel(e)
+                    .with_stage("symlink")
+                    .with_detail("session_id", session_id.as_str())
+            })?;
+        if response.exit_code != 0 || response.timed_out {
+            return Err(
+                ToolError::new(ErrorCode::RemoteFileError, failure_message(&response))
+                    .with_detail("session_id", session_id.as_str())
+                    .with_detail("path", link_path.as_str()),
+            );
+        }
+
+        info!(
+            "Created symlink {} -> {} on session {}",
+            link_path, target, session_id
+        );
+
+        Ok(StructuredContent(SshSymlinkResponse {
+            message: format!("Created symlink {} -> {}", link_path, target),
+            session_id,
+            link_path,
+            target,
+            reconnected,
+        }))
+    }
+
+    /// Read the target of a symbolic link on the remote host.
+    ///
+    /// By default the stored target is returned unchanged (it may be relative
+    /// or dangling). With `follow`, the whole link chain is resolved to a
+    /// canonical absolute path.
+    async fn ssh_readlink(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Path of the link to read
+        path: String,
+        /// Resolve the whole link chain to a canonical path (default: false)
+        follow: Option<bool>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshReadlinkResponse>, ToolError> {
+        let follow = follow.unwrap_or(false);
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let command = readlink_command(&path, follow);
+        let (response, reconnected) = execute_on_session(&session_ref, &command, SYMLINK_TIMEOUT)
+            .await
+            .map_err(|e| {
+                ToolError::channel(e)
+                    .with_stage("readlink")
+                    .with_detail("session_id", session_id.as_str())
+            })?;
+        let target = match parse_link_target(&response.stdout) {
+            Some(target) if response.exit_code == 0 && !response.timed_out => target,
+            _ => {
+                return Err(
+                    ToolError::new(ErrorCode::RemoteFileError, failure_message(&response))
+                        .with_detail("session_id", session_id.as_str())
+                        .with_detail("path", path.as_str()),
+                );
+            }
+        };
+
+        Ok(StructuredContent(SshReadlinkResponse {
+            session_id,
+            path,
+            target,
+            follow,
+            reconnected,
+        }))
+    }
+
+    /// Expand a glob pattern on the remote host.
+    ///
+    /// Supports `*`, `?` and `[...]` in any path component (POSIX sh rules;
+    /// no `**`). Returns existing matching paths, at most `max_matches`; check
+    /// `truncated` before assuming the list is complete.
+    async fn ssh_glob(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Glob pattern (e.g., "/var/log/app-*.log")
+        pattern: String,
+        /// Maximum number of paths to return (default: 1000, max: 10000)
+        max_matches: Option<usize>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshGlobResponse>, ToolError> {
+        let limit = resolve_max_matches(max_matches);
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let command = glob_command(&pattern, limit);
+        let (response, reconnected) = execute_on_session(&session_ref, &command, GLOB_TIMEOUT)
+            .await
+            .map_err(|e| {
+                ToolError::channel(e)
+                    .with_stage("glob")
+                    .with_detail("session_id", session_id.as_str())
+            })?;
+        if response.timed_out {
+            return Err(
+                ToolError::new(ErrorCode::RemoteFileError, failure_message(&response))
+                    .with_detail("session_id", session_id.as_str())
+                    .with_detail("path", pattern.as_str()),
+            );
+        }
+
+        let (matches, truncated) = parse_matches(&response.stdout, limit);
+        Ok(StructuredContent(SshGlobResponse {
+            session_id,
+            pattern,
+            count: matches.len(),
+            matches,
+            truncated,
+            reconnected,
+        }))
+    }
+
+    /// List a remote tree with sizes and checksums computed on the host.
+    ///
+    /// Returns every file, directory and symbolic link under `path` down to
+    /// `depth` levels, sorted by path relative to `path`. File checksums use
+    /// sha256sum (or `shasum -a 256`), falling back to POSIX cksum; check
+    /// `algorithm` before comparing manifests from different hosts. Links are
+    /// not followed. Nothing is downloaded, so two calls (e.g. before and
+    /// after a deploy, or on two hosts) are a cheap drift check.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_manifest(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Remote file or directory to list (e.g., "/opt/app")
+        path: String,
+        /// Levels below path to list; 0 lists only path itself (default: 10, max: 64)
+        depth: Option<usize>,
+        /// Maximum number of entries to return (default: 2000, max: 20000)
+        max_entries: Option<usize>,
+        /// Timeout in seconds for hashing the whole tree (default: 180, env SSH_COMMAND_TIMEOUT)
+        timeout_secs: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshManifestResponse>, ToolError> {
+        let depth = resolve_manifest_depth(depth);
+        let limit = resolve_manifest_entries(max_entries);
+        let timeout = resolve_command_timeout(timeout_secs);
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let command = manifest_command(&path, depth, limit);
+        let (response, reconnected) = execute_on_session(&session_ref, &command, timeout)
+            .await
+            .map_err(|e| {
+                ToolError::channel(e)
+                    .with_stage("manifest")
+                    .with_detail("session_id", session_id.as_str())
+            })?;
+        if response.exit_code != 0 || response.timed_out {
+            return Err(
+                ToolError::new(ErrorCode::RemoteFileError, failure_message(&response))
+                    .with_detail("session_id", session_id.as_str())
+                    .with_detail("path", path.as_str()),
+            );
+        }
+
+        let manifest = parse_manifest(&response.stdout, &path, limit);
+        let files: Vec<&ManifestEntry> = manifest
+            .entries
+            .iter()
+            .filter(|entry| entry.kind == ManifestEntryKind::File)
+            .collect();
+        Ok(StructuredContent(SshManifestResponse {
+            session_id,
+            path,
+            depth,
+            algorithm: manifest.algorithm,
+            files: files.len(),
+            total_bytes: files.iter().filter_map(|entry| entry.size).sum(),
+            entries: manifest.entries,
+            truncated: manifest.truncated,
+            reconnected,
+        }))
+    }
+
+    /// List a remote directory with typed entries.
+    ///
+    /// Reads the directory through the SFTP subsystem and returns each entry's
+    /// name, kind (file, directory, symlink, other), size, mtime, permissions
+    /// and owner ids - no `ls -la` parsing needed. Symbolic links are not
+    /// followed; their target is reported. Entries are sorted by name; dot files
+    /// are included unless `include_hidden=false`. Use ssh_manifest for
+    /// recursive listings with checksums.
+    async fn ssh_list_dir(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Remote directory to list; relative paths start at the login directory (e.g., "/var/log")
+        path: String,
+        /// Include entries whose name starts with "." (default: true)
+        include_hidden: Option<bool>,
+        /// Maximum number of entries to return (default: 1000, max: 10000)
+        max_entries: Option<usize>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshListDirResponse>, ToolError> {
+        let limit = resolve_list_dir_entries(max_entries);
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+
+        let listing = list_dir(
+            &session_ref.handle,
+            &path,
+            include_hidden.unwrap_or(true),
+            limit,
+        )
+        .await
+        .map_err(|e| {
+            ToolError::new(ErrorCode::RemoteFileError, e)
+                .with_stage("sftp")
+                .with_detail("session_id", session_id.as_str())
+                .with_detail("path", path.as_str())
+        })?;
+
+        Ok(StructuredContent(SshListDirResponse {
+            session_id,
+            path: listing.path,
+            count: listing.entries.len(),
+            entries: listing.entries,
+            truncated: listing.truncated,
+        }))
+    }
+
+    /// Upload a local directory tree to the remote host.
+    ///
+    /// Recreates `local_path` under `remote_path` (created if missing): directories
+    /// first, then files over up to `concurrency` parallel channels. Symbolic links
+    /// are recreated as links unless `follow_symlinks=true`. Existing remote files
+    /// are overwritten.
+    ///
+    /// `exclude` takes `*`/`?` wildcard patterns: a pattern with `/` matches the
+    /// path relative to `local_path` (e.g. `build/*`), any other pattern matches
+    /// entry names at any depth (e.g. `node_modules`, `*.pyc`).
+    ///
+    /// Each file gets its own result; check `failed` before assuming the tree is
+    /// complete. Limited to 10000 entries per call.
+    ///
+    /// `local_path` must be inside the directory the operator configured as
+    /// `[transfer] local_root`; without one the tool is refused.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_upload_dir(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Local directory to upload, inside the server's [transfer] local_root (relative paths start there)
+        local_path: String,
+        /// Remote directory to recreate the tree in
+        remote_path: String,
+        /// Wildcard patterns of entries to skip (e.g., ["target", "*.log", "build/*"])
+        exclude: Option<Vec<String>>,
+        /// Files uploaded in parallel (default: 4, max: 16)
+        concurrency: Option<usize>,
+        /// Copy Unix permission bits to the remote files and directories (default: true)
+        preserve_permissions: Option<bool>,
+        /// Upload what symbolic links point to instead of recreating the links, for targets inside local_path (default: false)
+        follow_symlinks: Option<bool>,
+        /// Bandwidth cap in bytes per second, shared by all files (min 1024; default: unlimited)
+        rate_limit_bytes_per_sec: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshUploadDirResponse>, ToolError> {
+        let start = std::time::Instant::now();
+        let concurrency = resolve_upload_concurrency(concurrency);
+        let preserve_permissions = preserve_permissions.unwrap_or(true);
+        let follow_symlinks = follow_symlinks.unwrap_or(false);
+        let exclude = exclude.unwrap_or_default();
+        let bandwidth = resolve_rate_limit(rate_limit_bytes_per_sec)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+
+        let session_ref = get_session(&session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+        read_only::check_tool(&session_ref.info, "ssh_upload_dir")?;
+        enforce_tool_policy("ssh_upload_dir", &session_ref.info, agent_id.as_deref())?;
+        let local_root =
+            confine_local_path(server_config().transfer.local_root.as_deref(), &local_path)
+                .map_err(|e| ToolError::new(ErrorCode::AccessDenied, e))?;
+        let local_path = local_root.display().to_string();
+        audit::record(
+            AuditEvent::new("ssh_upload_dir")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!("{} -> {}", local_path, remote_path)),
+        );
+
+        let root_metadata = tokio::fs::metadata(&local_root).await.map_err(|e| {
+            ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!("Cannot read local directory {}: {}", local_path, e),
+            )
+        })?;
+        if !root_metadata.is_dir() {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!("{} is not a directory", local_path),
+            ));
+        }
+
+        let walk_root = local_root.clone();
+        let mut entries = tokio::task::spawn_blocking(move || {
+            walk_local_dir(&walk_root, &walk_root, &exclude, follow_symlinks)
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Directory walk failed: {}", e)))
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        if !preserve_permissions {
+            entries.iter_mut().for_each(|entry| entry.mode = None);
+        }
+        let root_mode = preserve_permissions
+            .then(|| local_mode(&root_metadata))
+            .flatten();
+
+        let directories =
+            create_remote_dirs(&session_ref.handle, &remote_path, root_mode, &entries)
+                .await
+                .map_err(|e| {
+                    ToolError::new(ErrorCode::RemoteFileError, e)
+                        .with_stage("mkdir")
+                        .with_detail("session_id", session_id.as_str())
+                        .with_detail("path", remote_path.as_str())
+                })?;
+
+        let files = upload_entries(
+            &session_ref.handle,
+            &session_ref.traffic,
+            &local_root,
+            &remote_path,
+            entries,
+            concurrency,
+            bandwidth.as_ref(),
+        )
+        .await;
+
+        let failed = files
+            .iter()
+            .filter(|file| file.status == TransferStatus::Failed)
+            .count();
+        let bytes = files
+            .iter()
+            .filter(|file| file.status == TransferStatus::Uploaded)
+            .filter_map(|file| file.size)
+            .sum();
+        info!(
+            "Uploaded {} to {}:{} ({} files, {} failed)",
+            local_path,
+            session_id,
+            remote_path,
+            files.len() - failed,
+            failed
+        );
+
+        Ok(StructuredContent(SshUploadDirResponse {
+            session_id,
+            local_path,
+            remote_path,
+            directories,
+            uploaded: files.len() - failed,
+            failed,
+            files,
+            bytes,
+            elapsed_ms: round_ms(start.elapsed().as_secs_f64() * 1000.0),
+        }))
+    }
+
+    /// Archive remote paths and download them as one `.tar.gz` file.
+    ///
+    /// Runs `tar -czf -` on the remote host over the given files and directories,
+    /// streams the archive to `local_path` on the MCP server, and optionally
+    /// extracts it into `extract_to`. Use it to grab all logs or configs for an
+    /// incident in one call.
+    ///
+    /// Missing paths and files that changed while being read (busy logs) do not
+    /// fail the call: the rest is archived, `complete` is false and `warnings`
+    /// lists what happened. The archive is removed if it grows past `max_bytes`
+    /// or the download exceeds `timeout_secs`.
+    ///
+    /// `local_path` and `extract_to` must be inside the directory the operator
+    /// configured as `[transfer] local_root`; without one the tool is refused.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_collect(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Remote files and directories to archive (e.g., ["/var/log/app", "/etc/app.conf"])
+        paths: Vec<String>,
+        /// Local file to write the .tar.gz archive to, inside the server's [transfer] local_root (relative paths start there; parent directories are created)
+        local_path: String,
+        /// Local directory inside [transfer] local_root to extract the archive into (optional)
+        extract_to: Option<String>,
+        /// Largest archive accepted, in bytes (default: 1073741824 = 1 GiB)
+        max_bytes: Option<u64>,
+        /// Time allowed to build and download the archive (default: 300)
+        timeout_secs: Option<u64>,
+        /// Bandwidth cap in bytes per second, of the download (min 1024; default: unlimited)
+        rate_limit_bytes_per_sec: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCollectResponse>, ToolError> {
+        let start = std::time::Instant::now();
+        if paths.is_empty() || paths.iter().any(|path| path.is_empty()) {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                "paths must list at least one non-empty remote path",
+            ));
+        }
+        let max_bytes = max_bytes.unwrap_or(DEFAULT_COLLECT_MAX_BYTES).max(1);
+        let timeout = timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_COLLECT_TIMEOUT);
+        let bandwidth = resolve_rate_limit(rate_limit_bytes_per_sec)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+
+        let transfer_root = server_config().transfer.local_root.as_deref();
+        let archive = confine_local_path(transfer_root, &local_path)
+            .map_err(|e| ToolError::new(ErrorCode::AccessDenied, e))?;
+        let extract_to = extract_to
+            .map(|dir| confine_local_path(transfer_root, &dir))
+            .transpose()
+            .map_err(|e| ToolError::new(ErrorCode::AccessDenied, e))?;
+        let local_path = archive.display().to_string();
+
+        let session_ref = get_session(&session_id).await?;
+        check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+        audit::record(
+            AuditEvent::new("ssh_collect")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(format!("{} -> {}", paths.join(" "), local_path)),
+        );
+
+        let download = download_command_output(
+            &session_ref.handle,
+            &collect_command(&paths),
+            &archive,
+            max_bytes,
+            timeout,
+            bandwidth.as_deref(),
+        )
+        .await
+        .map_err(|e| {
+            match e {
+            DownloadError::TooLarge(limit) => ToolError::new(
+                ErrorCode::LimitExceeded,
+                format!(
+                    "Archive exceeds max_bytes ({} bytes); collect fewer paths or raise the limit",
+                    limit
+                ),
+            )
+            .with_detail("limit", limit),
+            DownloadError::Failed(message) => ToolError::new(ErrorCode::RemoteFileError, message)
+                .with_stage("collect"),
+        }
+        .with_detail("session_id", session_id.as_str())
+        })?;
+
+        let mut warnings = collect_warnings(&download.stderr);
+        if download.exit_code != 0 && download.bytes == 0 {
+            let _ = tokio::fs::remove_file(&archive).await;
+            let message = warnings
+                .first()
+                .cloned()
+                .unwrap_or_else(|| format!("Remote tar exited with code {}", download.exit_code));
+            return Err(ToolError::new(ErrorCode::RemoteFileError, message)
+                .with_detail("session_id", session_id.as_str()));
+        }
+
+        let mut extracted_to = None;
+        if let Some(dir) = extract_to {
+            match extract_archive(&archive, &dir).await {
+                Ok(()) => extracted_to = Some(dir.display().to_string()),
+                Err(e) => warnings.push(e),
+            }
+        }
+
+        info!(
+            "Collected {} path(s) from {} into {} ({} bytes)",
+            paths.len(),
+            session_id,
+            local_path,
+            download.bytes
+        );
+
+        Ok(StructuredContent(SshCollectResponse {
+            session_id,
+            paths,
+            archive_path: local_path,
+            bytes: download.bytes,
+            extracted_to,
+            complete: download.exit_code == 0,
+            exit_code: download.exit_code,
+            warnings,
+            elapsed_ms: round_ms(start.elapsed().as_secs_f64() * 1000.0),
+        }))
+    }
+
+    /// Write text content to a remote file, replacing it atomically.
+    ///
+    /// Use instead of `echo`/`cat <<EOF`/`sed -i` in ssh_execute: the content
+    /// is sent as-is (no shell quoting), written to a temporary file next to
+    /// `path` and renamed over it only once complete, so readers never see a
+    /// half-written file and a failed write leaves the old one intact.
+    ///
+    /// An existing file keeps its permissions (and owner, where permitted)
+    /// unless `mode` is given. `backup=true` copies the previous version to
+    /// `<path>.bak.<UTC timestamp>` first. At most 8 MiB.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_write_file(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Remote file path
+        path: String,
+        /// Full new content of the file
+        content: String,
+        /// Octal permission bits, e.g. "644" (default: keep the existing file's, or the umask for a new one)
+        mode: Option<String>,
+        /// Copy the previous version to <path>.bak.<timestamp> first (default: false)
+        backup: Option<bool>,
+        /// Create missing parent directories (default: false)
+        create_parents: Option<bool>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshWriteFileResponse>, ToolError> {
+        check_path(&path).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let mode = mode
+            .as_deref()
+            .map(parse_mode)
+            .transpose()
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        if content.len() > MAX_EDIT_FILE_BYTES {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "content is {} bytes; at most {} can be written",
+                    content.len(),
+                    MAX_EDIT_FILE_BYTES
+                ),
+            ));
+        }
+
+        let session_ref = get_session(&session_id).await?;
+        authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+        read_only::check_tool(&session_ref.info, "ssh_write_file")?;
+        enforce_tool_policy("ssh_write_file", &session_ref.info, agent_id.as_deref())?;
+        audit::record(
+            AuditEvent::new("ssh_write_file")
+                .session(&session_ref.info, agent_id.as_deref())
+                .target(path.as_str()),
+        );
+
+        let options = WriteOptions {
+            mode,
+            backup: backup.unwrap_or(false),
+            create_parents: create_parents.unwrap_or(false),
+        };
+        let written = write_file(
+            &session_ref.handle,
+            &session_ref.traffic,
+            &path,
+            content.as_bytes(),
+            options,
+        )
+        .await
+        .map_err(|e| {
+            ToolError::new(ErrorCode::RemoteFileError, e)
+                .with_stage("write")
+                .with_detail("session_id", session_id.as_str())
+                .with_detail("path", path.as_str())
+        })?;
+
+        info!(
+            "Wrote {} ({} bytes) on session {}",
+            path, written.bytes, session_id
+        );
+
+        let message = format!(
+            "{} {} ({} bytes){}",
+            if written.created {
+                "Created"
+            } else {
+                "Replaced"
+            },
+            path,
+            written.bytes,
+            written
+                .backup_path
+                .as_ref()
+                .map(|backup| format!("; previous version saved as {}", backup))
+                .unwrap_or_default()
+        );
+        Ok(StructuredContent(SshWriteFileResponse {
+            session_id,
+            path,
+            bytes: written.bytes,
+            sha256: written.sha256,
+            created: written.created,
+            backup_path: written.backup_path,
+            message,
+        }))
+    }
+
+    /// Apply a unified diff to a remote file.
+    ///
+    /// Takes the output of `diff -u` or `git diff` for one file. The file is
+    /// read, patched on the MCP server (the host needs no `patch` binary)
+    /// and written back atomically like ssh_write_file. Every hunk's context
+    /// and removed lines must match exactly; hunks whose line numbers are off
+    /// are found nearby (`shifted_hunks`). If any hunk does not apply,
+    /// nothing is written and the error names the hunk.
+    ///
+    /// A diff from `/dev/null` creates the file. `dry_run=true` only checks
+    /// that the patch applies.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_patch_file(
+        &self,
+        /// Session ID returned from ssh_connect
+        session_id: String,
+        /// Remote file path
+        path: String,
+        /// Unified diff for this file (@@ hunks; ---/+++ headers optional)
+        diff: String,
+        /// Copy the previous version to <path>.bak.<timestamp> first (default: false)
+        backup: Option<bool>,
+        /// Check that the patch applies without writing (default: false)
+        dry_run: Option<bool>,
+        /// Calling agent's ID; sessions owned by another agent require a full share
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshPatchFileResponse>, ToolError> {
+        check_path(&path).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let patch =
+            parse_unified_diff(&diff).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+        let dry_run = dry_run.unwrap_or(false);
+
+        let session_ref = get_session(&session_id).await?;
+        if dry_run {
+            check_session_access(&session_ref.info, agent_id.as_deref(), SessionAccess::Read)?;
+        } else {
+            authorize_session_write(&session_ref.info, agent_id.as_deref())?;
+            read_only::check_tool(&session_ref.info, "ssh_patch_file")?;
+            enforce_tool_policy("ssh_patch_file", &session_ref.info, agent_id.as_deref())?;
+            audit::record(
+                AuditEvent::new("ssh_patch_file")
+                    .session(&session_ref.info, agent_id.as_deref())
+                    .target(path.as_str()),
+            );
+        }
+
+        let file_error = |stage: &str, message: String| {
+            ToolError::new(ErrorCode::RemoteFileError, message)
+                .with_stage(stage)
+                .with_detail("session_id", session_id.as_str())
+                .with_detail("path", path.as_str())
+        };
+        let original = match read_file(&session_ref.handle, &path)
+            .await
+            .map_err(|e| file_error("read", e))?
+        {
+            Some(content) if patch.creates && !content.is_empty() => {
+                return Err(file_error(
+                    "read",
+                    format!("The diff creates {} but the file already exists", path),
+                ));
+            }
+            Some(content) => String::from_utf8(content)
+                .map_err(|_| file_error("read", format!("{} is not UTF-8 text", path)))?,
+            None if patch.creates => String::new(),
+            None => return Err(file_error("read", format!("{}: No such file", path))),
+        };
+        let patched = apply_patch(&original, &patch).map_err(|e| file_error("apply", e))?;
+
+        let written = if dry_run {
+            let content = patched.text.as_bytes();
+            Written {
+                bytes: content.len() as u64,
+                sha256: sha256_hex(content),
+                created: patch.creates,
+                backup_path: None,
+            }
+        } else {
+            let options = WriteOptions {
+                backup: backup.unwrap_or(false),
+                ..Default::default()
+            };
+            write_file(
+                &session_ref.handle,
+                &session_ref.traffic,
+                &path,
+                patched.text.as_bytes(),
+                options,
+            )
+            .await
+            .map_err(|e| file_error("write", e))?
+        };
+
+        let hunks = patch.hunks.len();
+        let message = format!(
+            "{} {} hunk(s) to {} (+{} -{} lines){}",
+            if dry_run { "Would apply" } else { "Applied" },
+            hunks,
+            path,
+            patched.added,
+            patched.removed,
+            written
+                .backup_path
+                .as_ref()
+                .map(|backup| format!("; previous version saved as {}", backup))
+                .unwrap_or_default()
+        );
+        if !dry_run {
+            info!("Patched {} on session {}: {}", path, session_id, message);
+        }
+
+        Ok(StructuredContent(SshPatchFileResponse {
+            session_id,
+            path,
+            hunks,
+            shifted_hunks: patched.shifted,
+            lines_added: patched.added,
+            lines_removed: patched.removed,
+            bytes: written.bytes,
+            sha256: written.sha256,
+            created: written.created,
+            backup_path: written.backup_path,
+            dry_run,
+            message,
+        }))
+    }
+
+    /// Copy a file from one remote host to another through the MCP server.
+    ///
+    /// Streams `cat` on `session_a` into `cat >` on `session_b` over two
+    /// bridged channels: the file is never stored on the MCP server or the
+    /// client, and the hosts do not need to reach each other. Both sessions may
+    /// be the same to copy within one host.
+    ///
+    /// `path_b` must be a file path (not a directory) whose parent exists. The
+    /// file is written under a temporary name and renamed over `path_b` only
+    /// when the whole file arrived, so a failed copy leaves an existing
+    /// `path_b` untouched. Returns the byte count and the sha256 of what was
+    /// sent.
+    #[allow(clippy::too_many_arguments)]
+    async fn ssh_copy_between(
+        &self,
+        /// Session to read the file from
+        session_a: String,
+        /// Remote file to copy on session_a
+        path_a: String,
+        /// Session to write the file to (may equal session_a)
+        session_b: String,
+        /// Destination file path on session_b; replaced if it exists
+        path_b: String,
+        /// Time allowed for the whole copy (default: 300)
+        timeout_secs: Option<u64>,
+        /// Bandwidth cap in bytes per second, of the copy (min 1024; default: unlimited)
+        rate_limit_bytes_per_sec: Option<u64>,
+        /// Calling agent's ID; sessions owned by another agent must be shared with it
+        agent_id: Option<String>,
+    ) -> Result<StructuredContent<SshCopyBetweenResponse>, ToolError> {
+        let start = std::time::Instant::now();
+        if path_a.is_empty() || path_b.is_empty() {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                "path_a and path_b must be non-empty",
+            ));
+        }
+        if path_b.ends_with('/') {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                "path_b must name a file, not a directory",
+            ));
+        }
+        let timeout = timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_RELAY_TIMEOUT);
+        let bandwidth = resolve_rate_limit(rate_limit_bytes_per_sec)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?;
+
+        let source = get_session(&session_a).await?;
+        check_session_access(&source.info, agent_id.as_deref(), SessionAccess::Read)?;
+        let dest = get_session(&session_b).await?;
+        authorize_session_write(&dest.info, agent_id.as_deref())?;
+        read_only::check_tool(&dest.info, "ssh_copy_between")?;
+        enforce_tool_policy("ssh_copy_between", &dest.info, agent_id.as_deref())?;
+        audit::record(
+            AuditEvent::new("ssh_copy_between")
+                .session(&dest.info, agent_id.as_deref())
+                .target(format!("{}:{} -> {}", session_a, path_a, path_b)),
+        );
+
+        let relay = relay_file(
+            &source.handle,
+            &path_a,
+            &dest.handle,
+            &dest.traffic,
+            &path_b,
+            timeout,
+            bandwidth.as_deref(),
+        )
+        .await
+        .map_err(|e| {
+            let (error, session_id) = match e {
+                RelayError::Source(message) => (
+                    ToolError::new(ErrorCode::RemoteFileError, message).with_stage("read"),
+                    &session_a,
+                ),
+                RelayError::Destination(message) => (
+                    ToolError::new(ErrorCode::RemoteFileError, message).with_stage("write"),
+                    &session_b,
+                ),
+                RelayError::TimedOut(timeout) => (
+                    ToolError::new(
+                        ErrorCode::RemoteFileError,
+                        format!(
+                            "Copy did not finish within {}s; raise timeout_secs for large files",
+                            timeout.as_secs()
+                        ),
+                    )
+                    .with_stage("copy"),
+                    &session_b,
+                ),
+            };
+            error.with_detail("session_id", session_id.as_str())
+        })?;
+
+        info!(
+            "Copied {}:{} to {}:{} ({} bytes)",
+            session_a, path_a, session_b, path_b, relay.bytes
+        );
+
+        Ok(StructuredContent(SshCopyBetweenResponse {
+            session_a,
+            path_a,
+            session_b,
+            path_b,
+            bytes: relay.bytes,
+            sha256: relay.sha256,
+            elapsed_ms: round_ms(start.elapsed().as_secs_f64() * 1000.0),
+        }))
+    }
+}
+
+impl McpSSHCommands {
+    /// Create the tools over the process-wide storage.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Look up a session, connecting a managed host on first use of its name.
+async fn get_session(session_id: &str) -> Result<SessionRef, ToolError> {
+    if let Some(session_ref) = SESSION_STORAGE.get(session_id) {
+        return Ok(session_ref);
+    }
+    ensure_managed_session(session_id).await?;
+    SESSION_STORAGE
+        .get(session_id)
+        .ok_or_else(|| ToolError::session_not_found(session_id))
+}
+
+/// Run `command` on a session; when it fails because the connection of an
+/// `auto_reconnect` session is lost, reconnect and run it once more.
+///
+/// The flag tells whether the session was reconnected.
+async fn execute_on_session(
+    session_ref: &SessionRef,
+    command: &str,
+    timeout: Duration,
+) -> Result<(SshCommandResponse, bool), String> {
+    let session_id = &session_ref.info.session_id;
+    let error = match execute_ssh_command(&session_ref.handle, command, timeout).await {
+        Ok(response) => return Ok((response, false)),
+        Err(e) => e,
+    };
+    if !reconnect::auto_reconnect(session_id) || !connection_lost(&session_ref.handle).await {
+        return Err(error);
+    }
+    let revived = reconnect::revive(session_id, session_ref.clone()).await?;
+    execute_ssh_command(&revived.handle, command, timeout)
+        .await
+        .map(|response| (response, true))
+}
+
+/// Reconnect an `auto_reconnect` session whose connection has closed before
+/// work is started on it.
+async fn revive_if_closed(session_ref: SessionRef) -> Result<(SessionRef, bool), ToolError> {
+    let session_id = session_ref.info.session_id.clone();
+    if !session_ref.handle.is_closed() || !reconnect::auto_reconnect(&session_id) {
+        return Ok((session_ref, false));
+    }
+    reconnect::revive(&session_id, session_ref)
+        .await
+        .map(|revived| (revived, true))
+        .map_err(|e| {
+            ToolError::connection(e)
+                .with_stage("reconnect")
+                .with_detail("session_id", session_id)
+        })
+}
+
+/// Check that the caller may run work on a session: its sharing mode and any active lease.
+fn authorize_session_write(info: &SessionInfo, caller: Option<&str>) -> Result<(), ToolError> {
+    check_session_access(info, caller, SessionAccess::Write)?;
+    let caller = acting_agent(caller);
+    SESSION_LEASES.check(&info.session_id, caller.as_deref(), chrono::Utc::now())
+}
+
+/// Check that the caller may use the session a command or shell runs on.
+///
+/// Write access also honours the session's lease, like [`authorize_session_write`].
+async fn authorize_owning_session(
+    session_id: &str,
+    caller: Option<&str>,
+    access: SessionAccess,
+) -> Result<SessionRef, ToolError> {
+    let session_ref = get_session(session_id).await?;
+    match access {
+        SessionAccess::Read => check_session_access(&session_ref.info, caller, access)?,
+        SessionAccess::Write => authorize_session_write(&session_ref.info, caller)?,
+    }
+    Ok(session_ref)
+}
+
+/// The session running async command `command_id`.
+fn command_session_id(command_id: &str) -> Result<String, ToolError> {
+    COMMAND_STORAGE
+        .get(command_id)
+        .map(|cmd| cmd.info.session_id.clone())
+        .ok_or_else(|| ToolError::command_not_found(command_id))
+}
+
+/// The session interactive shell `shell_id` was opened on.
+fn shell_session_id(shell_id: &str) -> Result<String, ToolError> {
+    SHELL_STORAGE
+        .get_direct(shell_id)
+        .map(|shell| shell.info.session_id.clone())
+        .ok_or_else(|| ToolError::shell_not_found(shell_id))
+}
+
+/// Validate the nice/ionice tool parameters.
+fn resolve_priority(nice: Option<i32>, ionice: Option<&str>) -> Result<Priority, ToolError> {
+    Priority::from_params(nice, ionice).map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))
+}
+
+/// Build a session affinity from tool parameters; at least one field is required.
+fn resolve_affinity(
+    region: Option<String>,
+    datacenter: Option<String>,
+) -> Result<SessionAffinity, ToolError> {
+    let affinity = SessionAffinity {
+        region: normalize_affinity_label("region", region)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?,
+        datacenter: normalize_affinity_label("datacenter", datacenter)
+            .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))?,
+    };
+    if affinity.is_empty() {
+        return Err(ToolError::new(
+            ErrorCode::InvalidArgument,
+            "affinity needs a region and/or a datacenter",
+        ));
+    }
+    Ok(affinity)
+}
+
+/// Sessions `caller` may run commands on that match `affinity`, best first.
+/// Never empty: no match is a `session_not_found` error.
+fn select_sessions(
+    affinity: &SessionAffinity,
+    caller: Option<&str>,
+) -> Result<Vec<SessionInfo>, ToolError> {
+    let candidates: Vec<SessionInfo> = SESSION_STORAGE
+        .session_ids()
+        .into_iter()
+        .filter_map(|session_id| SESSION_STORAGE.get(&session_id))
+        .map(|session_ref| session_ref.info.clone())
+        .filter(|info| check_session_access(info, caller, SessionAccess::Write).is_ok())
+        .collect();
+    let ranked: Vec<SessionInfo> = rank_sessions(&candidates, affinity)
+        .into_iter()
+        .cloned()
+        .collect();
+    if ranked.is_empty() {
+        let mut error = ToolError::new(
+            ErrorCode::SessionNotFound,
+            format!("No connected session matches {}", affinity),
+        );
+        if let Some(region) = &affinity.region {
+            error = error.with_detail("region", region.as_str());
+        }
+        if let Some(datacenter) = &affinity.datacenter {
+            error = error.with_detail("datacenter", datacenter.as_str());
+        }
+        return Err(error);
+    }
+    Ok(ranked)
+}
+
+/// Session a DAG node runs on: its `session_id`, or the best match for its `affinity`.
+/// The session of an ssh_execute_batch target, once the caller may run
+/// `command` on it.
+async fn prepare_batch_target(
+    session_id: &str,
+    command: &str,
+    caller: Option<&str>,
+) -> Result<SessionRef, ToolError> {
+    let session_ref = get_session(session_id).await?;
+    authorize_session_write(&session_ref.info, caller)?;
+    enforce_command_policy("ssh_execute_batch", session_id, command, caller)?;
+    Ok(session_ref)
+}
+
+fn resolve_node_session(node: &DagNodeSpec, caller: Option<&str>) -> Result<String, ToolError> {
+    match (&node.session_id, &node.affinity) {
+        (Some(session_id), None) => Ok(session_id.clone()),
+        (None, Some(affinity)) => {
+            let affinity = resolve_affinity(affinity.region.clone(), affinity.datacenter.clone())?;
+            Ok(select_sessions(&affinity, caller)?[0].session_id.clone())
+        }
+        _ => Err(ToolError::new(
+            ErrorCode::InvalidArgument,
+            format!("DAG node '{}' needs either session_id or affinity", node.id),
+        )
+        .with_detail("node", node.id.as_str())),
+    }
+}
+
+/// Resolve the `max_output_bytes` and `output_overflow` parameters.
+fn output_limit(
+    max_bytes: Option<u64>,
+    overflow: Option<&str>,
+) -> Result<Option<OutputLimit>, ToolError> {
+    resolve_output_limit(max_bytes, overflow)
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))
+}
+
+/// Parse an `output_encoding` parameter; `None` when it is not given.
+fn output_format(encoding: Option<&str>) -> Result<Option<OutputFormat>, ToolError> {
+    encoding
+        .map(OutputFormat::from_name)
+        .transpose()
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))
+}
+
+/// Combine requested sandbox limits with the operator's `[sandbox]` ceiling.
+fn resolve_sandbox(requested: Option<SandboxLimits>) -> Result<Sandbox, ToolError> {
+    Sandbox::resolve(requested, &server_config().sandbox)
+        .map_err(|e| ToolError::new(ErrorCode::InvalidArgument, e))
+}
+
+/// Open a PTY shell on a session and register it in shell storage.
+async fn open_shell(
+    tool: &str,
+    session_id: String,
+    term: Option<String>,
+    cols: Option<u32>,
+    rows: Option<u32>,
+    cwd: Option<String>,
+    caller_agent_id: Option<&str>,
+) -> Result<SshShellOpenResponse, ToolError> {
+    let term = term.unwrap_or_else(|| "xterm".to_string());
+    let cols = cols.unwrap_or(80);
+    let rows = rows.unwrap_or(24);
+
+    // Check shell limit
+    let current_count = SHELL_STORAGE.count_by_session(&session_id);
+    if current_count >= MAX_SHELLS_PER_SESSION {
+        return Err(ToolError::new(
+            ErrorCode::LimitExceeded,
+            format!(
+                "Maximum shells per session reached ({}). Close existing shells first.",
+                MAX_SHELLS_PER_SESSION
+            ),
+        )
+        .with_detail("session_id", session_id));
+    }
+
+    // Get session handle and owning agent_id
+    let session_ref = get_session(&session_id).await?;
+    authorize_session_write(&session_ref.info, caller_agent_id)?;
+    enforce_tool_policy(tool, &session_ref.info, caller_agent_id)?;
+    audit::record(AuditEvent::new("ssh_shell_open").session(&session_ref.info, caller_agent_id));
+    let (handle_arc, agent_id, forward_agent) = (
+        session_ref.handle.clone(),
+        session_ref.info.agent_id.clone(),
+        session_ref.info.agent_forwarding,
+    );
+
+    // A shell exits at once in a missing directory; check it first for a clear error
+    if let Some(cwd) = &cwd {
+        let check = execute_ssh_command(
+            &handle_arc,
+            &cd_command(cwd, "true"),
+            resolve_channel_open_timeout(),
+        )
+        .await
+        .map_err(|e| ToolError::channel(e).with_stage("open_shell"))?;
+        if check.exit_code != 0 {
+            return Err(ToolError::new(
+                ErrorCode::InvalidArgument,
+                format!("Cannot enter directory '{}': {}", cwd, check.stderr.trim()),
+            )
+            .with_detail("session_id", session_id.as_str()));
+        }
+    }
+
+    // Open PTY channel with shell
+    let channel = open_pty_shell(
+        &handle_arc,
+        &term,
+        cols,
+        rows,
+        cwd.as_deref(),
+        forward_agent,
+    )
+    .await
+    .map_err(|e| {
+        ToolError::channel(e)
+            .with_stage("open_shell")
+            .with_detail("session_id", session_id.as_str())
+    })?;
+
+    let shell_id = Uuid::new_v4().to_string();
+    let opened_at = chrono::Utc::now().to_rfc3339();
+
+    let shell_info = ShellInfo {
+        shell_id: shell_id.clone(),
+        session_id: session_id.clone(),
+        term_type: term.clone(),
+        cols,
+        rows,
+        opened_at,
+        effective_user: None,
+    };
+
+    // Create shared state
+    let (status_tx, status_rx) = watch::channel(ShellStatus::Open);
+    let output = Arc::new(Mutex::new(Vec::with_capacity(4096)));
+    let (output_tx, _) = broadcast::channel(subscription::SHELL_OUTPUT_BROADCAST);
+    let cancel_token = CancellationToken::new();
+
+    // Split channel into independent read/write halves to avoid mutex contention.
+    // The reader exclusively owns the read half, while writes go through the write half.
+    let (read_half, write_half) = channel.split();
+    let writer = ChannelWriter::new(write_half, session_ref.traffic.clone());
+    let channel_writer = Arc::new(Mutex::new(writer));
+
+    // Spawn background reader task (owns read_half exclusively, no mutex needed)
+    let reader_output = output.clone();
+    let reader_cancel = cancel_token.clone();
+    let reader_status_tx = status_tx.clone();
+    let reader_output_tx = output_tx.clone();
+
+    USAGE.shell_opened(
+        billed_agent(caller_agent_id, agent_id.as_deref()),
+        &shell_id,
+    );
+    SESSION_STORAGE.record_activity(&session_id, SessionActivity::ShellOpened);
+    let reader_shell_id = shell_id.clone();
+
+    tokio::spawn(async move {
+        shell_reader(
+            read_half,
+            reader_output,
+            reader_output_tx,
+            reader_cancel,
+            reader_status_tx,
+        )
+        .await;
+        USAGE.shell_closed(&reader_shell_id);
+    });
+
+    // Store running shell
+    SHELL_STORAGE.register(
+        shell_id.clone(),
+        RunningShell {
+            info: shell_info,
+            cancel_token,
+            output,
+            channel_writer,
+            status_tx,
+            status_rx,
+            output_tx,
+        },
+    );
+
+    info!(
+        "Opened interactive shell {} on session {} (term={}, {}x{})",
+        shell_id, session_id, term, cols, rows
+    );
+
+    let message = ShellOpenMessageBuilder::new(&shell_id, &session_id, &term, cols, rows)
+        .with_agent_id(agent_id.as_deref())
+        .with_template(server_config().messages.shell_open.as_deref())
+        .build();
+
+    Ok(SshShellOpenResponse {
+        shell_id,
+        session_id,
+        agent_id,
+        term_type: term,
+        message,
+    })
+}
+
+/// Refuse `command` when the operator's command policy blocks it, or when it
+/// would change the host of a read-only session; refusals are audited.
+///
+/// Agent-scoped rules apply to the caller, or to the session owner when the
+/// call has no `agent_id`.
+fn enforce_command_policy(
+    action: &str,
+    session_id: &str,
+    command: &str,
+    caller_agent_id: Option<&str>,
+) -> Result<(), ToolError> {
+    let info = SESSION_STORAGE
+        .get(session_id)
+        .map(|session_ref| session_ref.info);
+    let owner = info.as_ref().and_then(|info| info.agent_id.as_deref());
+    let agent = caller_agent_id.or(owner);
+    policy::check_command(command, agent)
+        .and_then(|()| match &info {
+            Some(info) => read_only::check_command(info, command),
+            None => Ok(()),
+        })
+        .inspect_err(|e| {
+            audit::record(
+                AuditEvent::new(action)
+                    .agent(caller_agent_id)
+                    .session_id(session_id)
+                    .target(command)
+                    .failed(e),
+            );
+        })
+}
+
+/// Refuse `tool` when the command policy limits the caller, or the session
+/// owner when the call has no `agent_id`, to an allowlist that the tool's shell
+/// input or file writes would bypass.
+fn enforce_tool_policy(
+    tool: &str,
+    info: &SessionInfo,
+    caller_agent_id: Option<&str>,
+) -> Result<(), ToolError> {
+    policy::check_tool(tool, caller_agent_id.or(info.agent_id.as_deref()))
+}
+
+/// Start an async command on a session and register it in command storage.
+#[allow(clippy::too_many_arguments)]
+async fn start_command(
+    session_id: String,
+    command: String,
+    timeout_secs: Option<u64>,
+    pty: Option<bool>,
+    priority: Priority,
+    sandbox: Sandbox,
+    label: Option<String>,
+    callback: Option<(String, HttpTarget)>,
+    stream: Option<NotificationSink>,
+    output_limit: Option<OutputLimit>,
+    env: Option<BTreeMap<String, String>>,
+    cwd: Option<String>,
+    patterns: Option<ExitPatterns>,
+    output_format: OutputFormat,
+    caller_agent_id: Option<&str>,
+) -> Result<SshExecuteResponse, ToolError> {
+    enforce_command_policy("ssh_execute", &session_id, &command, caller_agent_id)?;
+
+    // Check session limit (sync O(1) lookup)
+    let current_count = COMMAND_STORAGE.count_by_session(&session_id);
+    if current_count >= MAX_ASYNC_COMMANDS_PER_SESSION {
+        return Err(ToolError::new(
+            ErrorCode::LimitExceeded,
+            format!(
+                "Maximum async commands per session reached ({}). Cancel or wait for existing commands to complete.",
+                MAX_ASYNC_COMMANDS_PER_SESSION
+            ),
+        )
+        .with_detail("session_id", session_id));
+    }
+
+    // Get session handle and owning agent_id using storage abstraction
+    let session_ref = get_session(&session_id).await?;
+    authorize_session_write(&session_ref.info, caller_agent_id)?;
+    let timeout = resolve_command_timeout(timeout_secs.or(session_ref.info.command_timeout_secs));
+    audit::record(
+        AuditEvent::new("ssh_execute")
+            .session(&session_ref.info, caller_agent_id)
+            .target(command.as_str()),
+    );
+    let (session_ref, reconnected) = revive_if_closed(session_ref).await?;
+    let (handle_arc, agent_id, forward_agent) = (
+        session_ref.handle.clone(),
+        session_ref.info.agent_id.clone(),
+        session_ref.info.agent_forwarding,
+    );
+
+    let command_id = Uuid::new_v4().to_string();
+    let finished_event = AuditEvent::new("command_finished")
+        .session(&session_ref.info, caller_agent_id)
+        .command_id(&command_id)
+        .target(command.as_str());
+    let started_at = chrono::Utc::now().to_rfc3339();
+
+    // Create shared state with pre-allocated buffers
+    let (status_tx, status_rx) = watch::channel(AsyncCommandStatus::Running);
+    let output = Arc::new(Mutex::new(
+        OutputBuffer::with_capacity(4096, 1024).with_limit(output_limit),
+    ));
+    let exit_code = Arc::new(Mutex::new(None));
+    let error = Arc::new(Mutex::new(None));
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let pattern_match = Arc::new(Mutex::new(None));
+    let cancel_token = CancellationToken::new();
+    let (signal_tx, signals) = signal_channel();
+
+    // Create command info
+    let cmd_info = AsyncCommandInfo {
+        command_id: command_id.clone(),
+        session_id: session_id.clone(),
+        command: command.clone(),
+        status: AsyncCommandStatus::Running,
+        started_at: started_at.clone(),
+        label: label.clone(),
+    };
+
+    // Store running command using storage abstraction
+    COMMAND_STORAGE.register(
+        command_id.clone(),
+        RunningCommand {
+            info: cmd_info,
+            cancel_token: cancel_token.clone(),
+            signal_tx,
+            status_rx,
+            status_tx: status_tx.clone(),
+            output: output.clone(),
+            exit_code: exit_code.clone(),
+            error: error.clone(),
+            timed_out: timed_out.clone(),
+            pattern_match: pattern_match.clone(),
+            output_format,
+        },
+    );
+
+    match &label {
+        Some(label) => info!(
+            "Starting async command {} [{}] on session {}: {}",
+            command_id, label, session_id, command
+        ),
+        None => info!(
+            "Starting async command {} on session {}: {}",
+            command_id, session_id, command
+        ),
+    }
+
+    let streaming = stream.is_some();
+    if let Some(sink) = stream {
+        command_stream::stream(
+            command_id.clone(),
+            output.clone(),
+            status_tx.subscribe(),
+            exit_code.clone(),
+            sink,
+        );
+    }
+
+    // Only the executed command line carries the directory change and the
+    // priority and sandbox wrappers; the directory change runs innermost so
+    // it also holds inside `sh -c` and systemd-run wrappers
+    let command_line = match &cwd {
+        Some(cwd) => cd_command(cwd, &command),
+        None => command.clone(),
+    };
+    let remote_command = sandbox.wrap(&priority.wrap(&command_line));
+    let env = session_env::command_env(&session_id, env);
+
+    let billed = billed_agent(caller_agent_id, agent_id.as_deref()).to_string();
+    USAGE.record_command(&billed);
+    SESSION_STORAGE.record_activity(&session_id, SessionActivity::CommandExecuted);
+    let usage_output = output.clone();
+    let callback_url = callback.as_ref().map(|(url, _)| url.clone());
+    let (task_command_id, task_session_id) = (command_id.clone(), session_id.clone());
+    let (task_exit_code, task_error, task_timed_out) =
+        (exit_code.clone(), error.clone(), timed_out.clone());
+    let task_status = status_tx.subscribe();
+
+    // Spawn background task (with or without PTY); output bytes are charged on completion
+    let pty = pty.unwrap_or(false);
+    tokio::spawn(async move {
+        if pty {
+            execute_ssh_command_async_pty(
+                handle_arc,
+                remote_command,
+                env,
+                forward_agent,
+                timeout,
+                output,
+                status_tx,
+                cancel_token,
+                signals,
+                exit_code,
+                error,
+                timed_out,
+                patterns,
+                pattern_match,
+            )
+            .await;
+        } else {
+            execute_ssh_command_async(
+                handle_arc,
+                remote_command,
+                env,
+                forward_agent,
+                timeout,
+                output,
+                status_tx,
+                cancel_token,
+                signals,
+                exit_code,
+                error,
+                timed_out,
+                patterns,
+                pattern_match,
+            )
+            .await;
+        }
+        let collected = usage_output.lock().await.total_received();
+        USAGE.record_output(&billed, collected);
+        SESSION_STORAGE.record_activity(&task_session_id, SessionActivity::Output(collected));
+        let status = *task_status.borrow();
+        audit::record(finish_event(
+            finished_event,
+            status,
+            *task_exit_code.lock().await,
+            task_error.lock().await.clone(),
+            task_timed_out.load(Ordering::SeqCst),
+        ));
+        if let Some((url, target)) = callback {
+            match command_output(task_command_id, None, false, false, None).await {
+                Ok(final_output) => {
+                    let config = &server_config().callbacks;
+                    deliver_callback(&target, &url, &task_session_id, final_output, config).await;
+                }
+                Err(e) => warn!("No output to deliver to {}: {}", url, e.message),
+            }
+        }
+    });
+
+    let message = ExecuteMessageBuilder::new(&command_id, &session_id, &command)
+        .with_agent_id(agent_id.as_deref())
+        .with_template(server_config().messages.execute.as_deref())
+        .build();
+
+    Ok(SshExecuteResponse {
+        command_id,
+        session_id,
+        agent_id,
+        command,
+        label,
+        callback_url,
+        streaming,
+        started_at,
+        message,
+        reconnected,
+    })
+}
+
+/// Complete the `command_finished` audit event of a command that ended; a
+/// command that failed, timed out or was cancelled is recorded as a failure.
+fn finish_event(
+    event: AuditEvent,
+    status: AsyncCommandStatus,
+    exit_code: Option<i32>,
+    error: Option<String>,
+    timed_out: bool,
+) -> AuditEvent {
+    let event = event.exit_code(exit_code);
+    match (status, error) {
+        (_, Some(error)) => event.failed(error),
+        _ if timed_out => event.failed("timed out"),
+        (AsyncCommandStatus::Completed, None) => event,
+        (status, None) => event.failed(status),
+    }
+}
+
+/// Wait up to `wait_timeout_secs` for a started command and build the sync response.
+///
+/// Finished commands are unregistered; a command still running stays pollable.
+async fn wait_for_result(
+    started: SshExecuteResponse,
+    wait_timeout_secs: u64,
+    compress: bool,
+) -> Result<SshExecuteSyncResponse, ToolError> {
+    let output = command_output(
+        started.command_id.clone(),
+        Some(Duration::from_secs(wait_timeout_secs)),
+        false,
+        compress,
+        None,
+    )
+    .await?;
+
+    let message = if output.status == AsyncCommandStatus::Running {
+        format!(
+            "Command still running after {}s. Poll with ssh_get_command_output(command_id='{}', wait=true) or stop it with ssh_cancel_command.",
+            wait_timeout_secs, started.command_id
+        )
+    } else {
+        COMMAND_STORAGE.unregister(&started.command_id);
+        match output.exit_code {
+            Some(code) => format!("Command {} with exit code {}", output.status, code),
+            None => format!("Command {}", output.status),
+        }
+    };
+
+    Ok(SshExecuteSyncResponse {
+        command_id: started.command_id,
+        session_id: started.session_id,
+        agent_id: started.agent_id,
+        command: started.command,
+        status: output.status,
+        stdout: output.stdout,
+        stderr: output.stderr,
+        stdout_encoding: output.stdout_encoding,
+        stderr_encoding: output.stderr_encoding,
+        exit_code: output.exit_code,
+        error: output.error,
+        timed_out: output.timed_out,
+        privilege_drop: None,
+        message,
+        reconnected: started.reconnected,
+    })
+}
+
+/// Run the privilege-drop check on a session and audit its outcome; lingering
+/// processes are recorded as a failed check.
+async fn check_privilege_drop(
+    session_ref: &SessionRef,
+    command: Option<&str>,
+    caller_agent_id: Option<&str>,
+) -> Result<PrivilegeDropReport, ToolError> {
+    let event = AuditEvent::new("privilege_drop").session(&session_ref.info, caller_agent_id);
+    let event = match command {
+        Some(command) => event.target(command),
+        None => event,
+    };
+    let report = match verify_privilege_drop(&session_ref.handle, command).await {
+        Ok(report) => report,
+        Err(e) => {
+            audit::record(event.failed(&e));
+            return Err(ToolError::channel(e)
+                .with_stage("verify_privilege_drop")
+                .with_detail("session_id", session_ref.info.session_id.as_str()));
+        }
+    };
+    if report.status == PrivilegeDropStatus::Lingering {
+        audit::record(event.failed(report.summary()));
+    } else {
+        audit::record(event);
+    }
+    Ok(report)
+}
+
+/// Read the status and output of a command, optionally waiting for it to finish.
+/// Read a command's state, first waiting up to `wait_timeout` for it to finish.
+///
+/// With `renew`, a window in which the command produced output is followed by
+/// another one, up to `MAX_RENEWED_WAIT` in total. With `compress`, large
+/// output fields are gzip+base64 encoded. `format` overrides the command's
+/// own `output_encoding`.
+async fn command_output(
+    command_id: String,
+    wait_timeout: Option<Duration>,
+    renew: bool,
+    compress: bool,
+    format: Option<OutputFormat>,
+) -> Result<SshAsyncOutputResponse, ToolError> {
+    // Get command using storage abstraction
+    let (status_rx, output, exit_code, error, timed_out, pattern_match, command_format) =
+        COMMAND_STORAGE
+            .get(&command_id)
+            .map(|cmd| {
+                (
+                    cmd.status_rx.clone(),
+                    cmd.output.clone(),
+                    cmd.exit_code.clone(),
+                    cmd.error.clone(),
+                    cmd.timed_out.clone(),
+                    cmd.pattern_match.clone(),
+                    cmd.output_format,
+                )
+            })
+            .ok_or_else(|| ToolError::command_not_found(&command_id))?;
 
-        let writer = channel_writer.lock().await;
-        writer.write(input.as_bytes()).await?;
+    // Optionally wait for completion
+    let mut wait_progress = None;
+    if let Some(wait_timeout) = wait_timeout {
+        let started = tokio::time::Instant::now();
+        let hard_deadline = started
+            + if renew {
+                MAX_RENEWED_WAIT
+            } else {
+                wait_timeout
+            };
+        let initial_len = output.lock().await.total_received();
+        let mut seen_len = initial_len;
+        let mut renewals = 0u32;
+        loop {
+            let deadline = (tokio::time::Instant::now() + wait_timeout).min(hard_deadline);
+            let finished =
+                tokio::time::timeout_at(deadline, wait_until_finished(status_rx.clone())).await;
+            if finished.is_ok() || !renew || deadline >= hard_deadline {
+                break;
+            }
+            let current_len = output.lock().await.total_received();
+            if current_len == seen_len {
+                break;
+            }
+            seen_len = current_len;
+            renewals += 1;
+        }
 
-        Ok(Text(format!(
-            "Sent {} bytes to shell '{}'",
-            input.len(),
-            shell_id
-        )))
+        let waited = started.elapsed();
+        let expired = *status_rx.borrow() == AsyncCommandStatus::Running;
+        let new_output_bytes = output.lock().await.total_received() - initial_len;
+        wait_progress = Some(WaitProgress {
+            waited_ms: round_ms(waited.as_secs_f64() * 1000.0),
+            expired,
+            renewals,
+            new_output_bytes,
+            message: expired.then(|| {
+                format!(
+                    "Command is still running after waiting {}s ({} new output bytes). It has not finished; \
+                     call ssh_get_command_output(command_id='{}', wait=true) again to keep waiting{}.",
+                    waited.as_secs(),
+                    new_output_bytes,
+                    command_id,
+                    if renew {
+                        ""
+                    } else {
+                        ", with renew_wait=true to follow a command that keeps logging"
+                    }
+                )
+            }),
+        });
     }
 
-    /// Read accumulated output from an interactive shell.
-    ///
-    /// Returns all output buffered since the last read (when `clear=true`)
-    /// or all output since shell open (when `clear=false`).
-    ///
-    /// **Recommended workflow:**
-    /// 1. ssh_shell_write to send a command
-    /// 2. Wait briefly (shell needs time to produce output)
-    /// 3. ssh_shell_read with clear=true to get new output
-    async fn ssh_shell_read(
-        &self,
-        /// Shell ID returned from ssh_shell_open
-        shell_id: String,
-        /// Clear the output buffer after reading (default: true). Set to false to peek without consuming.
-        clear: Option<bool>,
-    ) -> Result<StructuredContent<SshShellReadResponse>, String> {
-        let clear = clear.unwrap_or(true);
+    // Get current state
+    let status = *status_rx.borrow();
+    let output_buf = output.lock().await;
+    let exit_code_val = *exit_code.lock().await;
+    let error_val = error.lock().await.clone();
+    let timed_out_val = timed_out.load(Ordering::SeqCst);
 
-        let (output_arc, status_rx) = SHELL_STORAGE
-            .get_direct(&shell_id)
-            .map(|shell| (shell.output.clone(), shell.status_rx.clone()))
-            .ok_or_else(|| format!("No active shell with ID: {}", shell_id))?;
+    let format = format.unwrap_or(command_format);
+    let (stdout, stdout_encoding) = encode_output(&output_buf.stdout, compress, format);
+    let (stderr, stderr_encoding) = encode_output(&output_buf.stderr, compress, format);
 
-        let data = if clear {
-            let mut buf = output_arc.lock().await;
-            let data = std::mem::take(&mut *buf);
-            String::from_utf8_lossy(&data).into_owned()
-        } else {
-            let buf = output_arc.lock().await;
-            String::from_utf8_lossy(&buf).into_owned()
-        };
+    Ok(SshAsyncOutputResponse {
+        command_id,
+        status,
+        stdout,
+        stderr,
+        stdout_encoding,
+        stderr_encoding,
+        stdout_is_truncated_utf8: is_truncated_utf8(&output_buf.stdout, stdout_encoding),
+        stderr_is_truncated_utf8: is_truncated_utf8(&output_buf.stderr, stderr_encoding),
+        exit_code: exit_code_val,
+        error: error_val,
+        timed_out: timed_out_val,
+        dropped_bytes: output_buf.dropped_bytes(),
+        wait: wait_progress,
+        matched_pattern: pattern_match.lock().await.clone(),
+    })
+}
 
-        let status = *status_rx.borrow();
+/// Resources released together with a session.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SessionTeardown {
+    shells: usize,
+    commands: usize,
+    /// Local and reverse port forwards
+    forwards: usize,
+}
 
-        Ok(StructuredContent(SshShellReadResponse {
-            shell_id,
-            data,
-            status,
-        }))
+impl SessionTeardown {
+    /// Suffix for the ssh_disconnect confirmation; empty when nothing was running.
+    pub(crate) fn summary(&self) -> String {
+        if *self == Self::default() {
+            return String::new();
+        }
+        format!(
+            " (closed {} shell(s), cancelled {} command(s), closed {} forward(s))",
+            self.shells, self.commands, self.forwards
+        )
     }
+}
 
-    /// Close an interactive shell session.
-    ///
-    /// Stops the background reader and closes the PTY channel.
-    /// Any buffered output is discarded.
-    async fn ssh_shell_close(
-        &self,
-        /// Shell ID to close
-        shell_id: String,
-    ) -> Result<StructuredContent<SshShellCloseResponse>, String> {
-        let shell = SHELL_STORAGE
-            .unregister(&shell_id)
-            .ok_or_else(|| format!("No active shell with ID: {}", shell_id))?;
+/// Close the shells, async commands and port forwards of `session_id` and
+/// drop its lease, before the session itself is removed.
+pub(crate) async fn tear_down_session(session_id: &str) -> SessionTeardown {
+    let shell_ids = SHELL_STORAGE.list_by_session(session_id);
+    if !shell_ids.is_empty() {
+        info!(
+            "Closing {} interactive shells for session {}",
+            shell_ids.len(),
+            session_id
+        );
+    }
+    for shell_id in &shell_ids {
+        if let Some(shell) = SHELL_STORAGE.unregister(shell_id) {
+            shell.cancel_token.cancel();
+            let writer = shell.channel_writer.lock().await;
+            let _ = writer.close().await;
+        }
+    }
 
-        // Cancel the background reader
-        shell.cancel_token.cancel();
+    // Cancel, then remove, every async command of the session
+    let command_ids = COMMAND_STORAGE.list_by_session(session_id);
+    if !command_ids.is_empty() {
+        info!(
+            "Cancelling {} async commands for session {}",
+            command_ids.len(),
+            session_id
+        );
+    }
+    for cmd_id in &command_ids {
+        if let Some(cmd_ref) = COMMAND_STORAGE.get_ref(cmd_id) {
+            cmd_ref.running.cancel_token.cancel();
+        }
+    }
+    for cmd_id in &command_ids {
+        COMMAND_STORAGE.unregister(cmd_id);
+    }
 
-        // Close the channel
-        let writer = shell.channel_writer.lock().await;
-        let _ = writer.close().await;
+    // Stop every local forward listener of the session, releasing its port
+    let forward_ids = FORWARD_STORAGE.list_by_session(session_id);
+    for forward_id in &forward_ids {
+        if let Some(forward) = FORWARD_STORAGE.unregister(forward_id) {
+            forward.cancel_token.cancel();
+        }
+    }
+    let local_forwards = forward_ids.len();
+    // Remote listeners die with the connection; the table only needs dropping
+    let reverse_forwards = REVERSE_FORWARDS.detach(session_id);
+    if local_forwards + reverse_forwards > 0 {
+        info!(
+            "Closed {} local and {} reverse forwards for session {}",
+            local_forwards, reverse_forwards, session_id
+        );
+    }
+    SESSION_LEASES.remove(session_id);
+    forget_helper_sync(session_id);
+    reconnect::forget(session_id);
+    session_env::forget(session_id);
 
-        info!("Closed interactive shell: {}", shell_id);
+    SessionTeardown {
+        shells: shell_ids.len(),
+        commands: command_ids.len(),
+        forwards: local_forwards + reverse_forwards,
+    }
+}
 
-        Ok(StructuredContent(SshShellCloseResponse {
-            shell_id,
-            closed: true,
-            message: "Shell closed successfully".to_string(),
-        }))
+/// Resolve once the command status leaves `Running` (or its sender is dropped).
+async fn wait_until_finished(mut status_rx: watch::Receiver<AsyncCommandStatus>) {
+    loop {
+        if *status_rx.borrow() != AsyncCommandStatus::Running {
+            break;
+        }
+        if status_rx.changed().await.is_err() {
+            break;
+        }
     }
 }
 
@@ -1046,6 +6063,7 @@ impl McpSSHCommands {
 async fn shell_reader(
     mut read_half: russh::ChannelReadHalf,
     output: Arc<Mutex<Vec<u8>>>,
+    output_tx: broadcast::Sender<Vec<u8>>,
     cancel_token: CancellationToken,
     status_tx: watch::Sender<ShellStatus>,
 ) {
@@ -1061,13 +6079,10 @@ async fn shell_reader(
 
             msg = read_half.wait() => {
                 match msg {
-                    Some(ChannelMsg::Data { data }) => {
-                        let mut buf = output.lock().await;
-                        buf.extend_from_slice(&data);
-                    }
-                    Some(ChannelMsg::ExtendedData { data, .. }) => {
-                        let mut buf = output.lock().await;
-                        buf.extend_from_slice(&data);
+                    Some(ChannelMsg::Data { data }) | Some(ChannelMsg::ExtendedData { data, .. }) => {
+                        output.lock().await.extend_from_slice(&data);
+                        // No subscribers is not an error
+                        let _ = output_tx.send(data.to_vec());
                     }
                     Some(ChannelMsg::Eof) | Some(ChannelMsg::Close) | None => {
                         break;
@@ -1080,3 +6095,589 @@ async fn shell_reader(
 
     let _ = status_tx.send(ShellStatus::Closed);
 }
+
+/// Read a shell's buffer: drain it onto `acc` (`clear`) or copy all of it.
+async fn read_shell_buffer(output: &Mutex<Vec<u8>>, clear: bool, mut acc: Vec<u8>) -> Vec<u8> {
+    let mut buf = output.lock().await;
+    if clear {
+        acc.extend_from_slice(&std::mem::take(&mut *buf));
+        acc
+    } else {
+        buf.clone()
+    }
+}
+
+/// Type the switch command into `shell_id` and drive it to a verified user.
+async fn switch_shell_user(
+    shell_id: &str,
+    user: &str,
+    method: SwitchMethod,
+    password: Option<&str>,
+    timeout: Duration,
+) -> Result<SshShellSwitchUserResponse, ToolError> {
+    let (output, channel_writer, status_rx, session_id, previous_user) = SHELL_STORAGE
+        .get_direct(shell_id)
+        .map(|shell| {
+            (
+                shell.output.clone(),
+                shell.channel_writer.clone(),
+                shell.status_rx.clone(),
+                shell.info.session_id.clone(),
+                shell.info.effective_user.clone(),
+            )
+        })
+        .ok_or_else(|| ToolError::shell_not_found(shell_id))?;
+    if *status_rx.borrow() != ShellStatus::Open {
+        return Err(ToolError::new(ErrorCode::InvalidState, "Shell is closed")
+            .with_detail("shell_id", shell_id));
+    }
+    // The password is never part of the audit record
+    audit::record(
+        AuditEvent::new("ssh_shell_switch_user")
+            .session_id(&session_id)
+            .target(format!(
+                "shell {} -> {} ({})",
+                shell_id,
+                user,
+                method.as_str()
+            )),
+    );
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let write = |input: String| {
+        let channel_writer = channel_writer.clone();
+        async move {
+            channel_writer
+                .lock()
+                .await
+                .write(input.as_bytes())
+                .await
+                .map_err(|e| {
+                    ToolError::channel(e)
+                        .with_stage("switch_user")
+                        .with_detail("shell_id", shell_id)
+                })
+        }
+    };
+    // Output since `start`; a concurrent ssh_shell_read may have cleared the buffer
+    let new_output = |start: usize| {
+        let output = output.clone();
+        async move {
+            let buf = output.lock().await;
+            buf[start.min(buf.len())..].to_vec()
+        }
+    };
+    let abort = |error: ToolError| async {
+        let _ = write("\x03".to_string()).await;
+        Err(error
+            .with_detail("shell_id", shell_id)
+            .with_detail("user", user))
+    };
+
+    let mut start = output.lock().await.len();
+    write(method.command(user)).await?;
+    let mut password_sent = false;
+    let mut seen = 0;
+    let mut last_change = tokio::time::Instant::now();
+    loop {
+        if tokio::time::Instant::now() >= deadline {
+            return abort(ToolError::new(
+                ErrorCode::InvalidState,
+                format!("Timed out switching to user '{}'", user),
+            ))
+            .await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let data = new_output(start).await;
+        if let Some(failure) = detect_failure(&data) {
+            return abort(ToolError::new(
+                ErrorCode::AccessDenied,
+                format!("Switching to user '{}' failed: {}", user, failure),
+            ))
+            .await;
+        }
+        if detect_password_prompt(&data) {
+            let Some(password) = password.filter(|_| !password_sent) else {
+                let message = if password_sent {
+                    format!("Password rejected switching to user '{}'", user)
+                } else {
+                    format!(
+                        "{} asked for a password switching to user '{}'; pass `password`",
+                        method.as_str(),
+                        user
+                    )
+                };
+                return abort(ToolError::new(ErrorCode::AuthenticationFailed, message)).await;
+            };
+            start += data.len();
+            write(format!("{}\n", password)).await?;
+            password_sent = true;
+            seen = 0;
+            last_change = tokio::time::Instant::now();
+            continue;
+        }
+        if data.len() != seen {
+            seen = data.len();
+            last_change = tokio::time::Instant::now();
+        } else if seen > 0 && last_change.elapsed() >= SWITCH_QUIET_PERIOD {
+            break;
+        }
+    }
+
+    // The login shell is ready; ask it who it is
+    let start = output.lock().await.len();
+    write(VERIFY_COMMAND.to_string()).await?;
+    let verified = loop {
+        if tokio::time::Instant::now() >= deadline {
+            return Err(ToolError::new(
+                ErrorCode::InvalidState,
+                format!("Could not verify the switch to user '{}'", user),
+            )
+            .with_detail("shell_id", shell_id));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if let Some(verified) = parse_verified_user(&new_output(start).await) {
+            break verified;
+        }
+    };
+    if verified != user {
+        return Err(ToolError::new(
+            ErrorCode::AccessDenied,
+            format!(
+                "Shell runs as '{}' after switching to user '{}'",
+                verified, user
+            ),
+        )
+        .with_detail("shell_id", shell_id)
+        .with_detail("effective_user", verified));
+    }
+
+    SHELL_STORAGE.set_effective_user(shell_id, Some(verified.clone()));
+    info!(
+        "Shell {} switched to user {} via {}",
+        shell_id,
+        verified,
+        method.as_str()
+    );
+    Ok(SshShellSwitchUserResponse {
+        shell_id: shell_id.to_string(),
+        message: format!(
+            "Shell '{}' now runs as '{}'. Input sent with ssh_shell_write executes as this user.",
+            shell_id, verified
+        ),
+        effective_user: verified,
+        previous_user,
+        method: method.as_str().to_string(),
+        password_sent,
+    })
+}
+
+/// Wait for a pager to redraw: until the buffer grows past `baseline` and then
+/// stays quiet for [`PAGER_QUIET_PERIOD`], or [`PAGER_SETTLE_TIMEOUT`] passes.
+async fn wait_for_shell_output(output: &Mutex<Vec<u8>>, baseline: usize) {
+    let deadline = tokio::time::Instant::now() + PAGER_SETTLE_TIMEOUT;
+    let mut last_len = baseline;
+    let mut last_change = None;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let len = output.lock().await.len();
+        if len != last_len {
+            last_len = len;
+            last_change = Some(tokio::time::Instant::now());
+        } else if last_change.is_some_and(|at| at.elapsed() >= PAGER_QUIET_PERIOD) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::access::with_bound_agent;
+    use crate::mcp::test_server::{connect, start_server};
+    use crate::mcp::types::ShareMode;
+
+    /// Info of a session owned by `owner`.
+    fn session_info(session_id: &str, owner: &str, shared: Option<ShareMode>) -> SessionInfo {
+        SessionInfo {
+            session_id: session_id.to_string(),
+            name: None,
+            agent_id: Some(owner.to_string()),
+            shared,
+            host: "127.0.0.1:22".to_string(),
+            username: "deploy".to_string(),
+            connected_at: "2024-01-01T00:00:00Z".to_string(),
+            default_timeout_secs: 30,
+            retry_attempts: 0,
+            compression_enabled: false,
+            compression_level: None,
+            keepalive_interval_secs: 30,
+            keepalive_max: 3,
+            algorithms: None,
+            last_health_check: None,
+            healthy: None,
+            health_history: Vec::new(),
+            flapping: false,
+            identity: None,
+            rtt_ms: None,
+            rtt_avg_ms: None,
+            region: None,
+            datacenter: None,
+            jump_hosts: Vec::new(),
+            rebooting: false,
+            read_only: false,
+            agent_forwarding: false,
+            profile: None,
+            command_timeout_secs: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            stats: Default::default(),
+            labels: Default::default(),
+        }
+    }
+
+    /// A connected session owned by `owner`, with one running command and
+    /// one open shell; returns the session, command and shell IDs.
+    async fn owned_session(owner: &str, shared: Option<ShareMode>) -> (String, String, String) {
+        let (_, address) = start_server().await;
+        let traffic = Arc::new(ByteCounters::default());
+        let handle = Arc::new(
+            connect(
+                &address,
+                &[],
+                &Arc::new(ReverseForwards::default()),
+                &traffic,
+            )
+            .await,
+        );
+        let session_id = Uuid::new_v4().to_string();
+        SESSION_STORAGE.insert(
+            session_id.clone(),
+            session_info(&session_id, owner, shared),
+            handle.clone(),
+            traffic.clone(),
+        );
+
+        let command_id = Uuid::new_v4().to_string();
+        let (status_tx, status_rx) = watch::channel(AsyncCommandStatus::Running);
+        let (signal_tx, _) = signal_channel();
+        COMMAND_STORAGE.register(
+            command_id.clone(),
+            RunningCommand {
+                info: AsyncCommandInfo {
+                    command_id: command_id.clone(),
+                    session_id: session_id.clone(),
+                    command: "make deploy".to_string(),
+                    status: AsyncCommandStatus::Running,
+                    started_at: chrono::Utc::now().to_rfc3339(),
+                    label: None,
+                },
+                cancel_token: CancellationToken::new(),
+                signal_tx,
+                status_rx,
+                status_tx,
+                output: Arc::new(Mutex::new(OutputBuffer::default())),
+                exit_code: Arc::new(Mutex::new(None)),
+                error: Arc::new(Mutex::new(None)),
+                timed_out: Arc::new(AtomicBool::new(false)),
+                pattern_match: Arc::new(Mutex::new(None)),
+                output_format: OutputFormat::Text,
+            },
+        );
+
+        let shell_id = Uuid::new_v4().to_string();
+        let (_, write_half) = handle.channel_open_session().await.unwrap().split();
+        let (status_tx, status_rx) = watch::channel(ShellStatus::Open);
+        let (output_tx, _) = broadcast::channel(1);
+        SHELL_STORAGE.register(
+            shell_id.clone(),
+            RunningShell {
+                info: ShellInfo {
+                    shell_id: shell_id.clone(),
+                    session_id: session_id.clone(),
+                    term_type: "xterm".to_string(),
+                    cols: 80,
+                    rows: 24,
+                    opened_at: chrono::Utc::now().to_rfc3339(),
+                    effective_user: None,
+                },
+                cancel_token: CancellationToken::new(),
+                output: Arc::new(Mutex::new(b"$ ".to_vec())),
+                channel_writer: Arc::new(Mutex::new(ChannelWriter::new(write_half, traffic))),
+                status_tx,
+                status_rx,
+                output_tx,
+            },
+        );
+        (session_id, command_id, shell_id)
+    }
+
+    fn tools() -> McpSSHCommands {
+        McpSSHCommands::new()
+    }
+
+    fn assert_denied<T>(result: Result<T, ToolError>, what: &str) {
+        match result {
+            Err(error) => assert_eq!(error.code, ErrorCode::AccessDenied, "{}", what),
+            Ok(_) => panic!("{} was allowed", what),
+        }
+    }
+
+    mod ownership {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_bound_client_cannot_reach_other_agents_commands() {
+            let (session_id, command_id, _) = owned_session("deploy", None).await;
+            let tools = tools();
+
+            with_bound_agent("ci".to_string(), async {
+                let listed = tools
+                    .ssh_list_commands(None, None, None, None)
+                    .await
+                    .unwrap();
+                assert!(
+                    listed
+                        .0
+                        .commands
+                        .iter()
+                        .all(|cmd| cmd.command_id != command_id)
+                );
+                assert_denied(
+                    tools
+                        .ssh_list_commands(Some(session_id.clone()), None, None, None)
+                        .await,
+                    "ssh_list_commands",
+                );
+                assert_denied(
+                    tools
+                        .ssh_get_command_output(
+                            command_id.clone(),
+                            None,
+                            None,
+                            None,
+                            None,
+                            None,
+                            None,
+                        )
+                        .await,
+                    "ssh_get_command_output",
+                );
+                assert_denied(
+                    tools
+                        .ssh_wait_commands(vec![command_id.clone()], None, Some(0), None)
+                        .await,
+                    "ssh_wait_commands",
+                );
+                assert_denied(
+                    tools
+                        .ssh_tail_read(command_id.clone(), None, None, None, None)
+                        .await,
+                    "ssh_tail_read",
+                );
+                assert_denied(
+                    tools.ssh_cancel_command(command_id.clone(), None).await,
+                    "ssh_cancel_command",
+                );
+                assert_denied(
+                    tools
+                        .ssh_command_signal(command_id.clone(), "TERM".to_string(), None)
+                        .await,
+                    "ssh_command_signal",
+                );
+            })
+            .await;
+
+            // The owner still sees and controls its command
+            let cmd = COMMAND_STORAGE.get(&command_id).unwrap();
+            assert!(!cmd.cancel_token.is_cancelled());
+            with_bound_agent("deploy".to_string(), async {
+                let listed = tools
+                    .ssh_list_commands(None, None, None, None)
+                    .await
+                    .unwrap();
+                assert!(
+                    listed
+                        .0
+                        .commands
+                        .iter()
+                        .any(|cmd| cmd.command_id == command_id)
+                );
+            })
+            .await;
+            tear_down_session(&session_id).await;
+        }
+
+        #[tokio::test]
+        async fn test_bound_client_cannot_use_other_agents_shells() {
+            let (session_id, _, shell_id) = owned_session("deploy", None).await;
+            let tools = tools();
+
+            with_bound_agent("ci".to_string(), async {
+                assert_denied(
+                    tools
+                        .ssh_shell_write(shell_id.clone(), "id\n".to_string(), None)
+                        .await,
+                    "ssh_shell_write",
+                );
+                assert_denied(
+                    tools
+                        .ssh_shell_read(shell_id.clone(), Some(false), None, None)
+                        .await,
+                    "ssh_shell_read",
+                );
+                assert_denied(
+                    tools
+                        .ssh_shell_expect(
+                            shell_id.clone(),
+                            "$ ".to_string(),
+                            Some(true),
+                            Some(1),
+                            Some(false),
+                            None,
+                        )
+                        .await,
+                    "ssh_shell_expect",
+                );
+                assert_denied(
+                    tools
+                        .ssh_shell_signal(shell_id.clone(), "HUP".to_string(), None)
+                        .await,
+                    "ssh_shell_signal",
+                );
+                assert_denied(
+                    tools
+                        .ssh_shell_switch_user(
+                            shell_id.clone(),
+                            "root".to_string(),
+                            None,
+                            None,
+                            Some(1),
+                            None,
+                        )
+                        .await,
+                    "ssh_shell_switch_user",
+                );
+                assert_denied(
+                    tools.ssh_shell_close(shell_id.clone(), None).await,
+                    "ssh_shell_close",
+                );
+            })
+            .await;
+            assert!(SHELL_STORAGE.get_direct(&shell_id).is_some());
+
+            // A read-only share lets others peek, not type or consume
+            let (shared_id, _, shared_shell) =
+                owned_session("deploy", Some(ShareMode::ReadOnly)).await;
+            with_bound_agent("ci".to_string(), async {
+                let read = tools
+                    .ssh_shell_read(shared_shell.clone(), Some(false), None, None)
+                    .await
+                    .unwrap();
+                assert_eq!(read.0.data, "$ ");
+                assert_denied(
+                    tools
+                        .ssh_shell_read(shared_shell.clone(), Some(true), None, None)
+                        .await,
+                    "consuming ssh_shell_read",
+                );
+                assert_denied(
+                    tools
+                        .ssh_shell_write(shared_shell.clone(), "id\n".to_string(), None)
+                        .await,
+                    "ssh_shell_write on a read-only share",
+                );
+            })
+            .await;
+            tear_down_session(&session_id).await;
+            tear_down_session(&shared_id).await;
+        }
+    }
+
+    mod lease {
+        use super::*;
+
+        fn assert_locked<T>(result: Result<T, ToolError>, what: &str) {
+            match result {
+                Err(error) => assert_eq!(error.code, ErrorCode::SessionLocked, "{}", what),
+                Ok(_) => panic!("{} ran on a locked session", what),
+            }
+        }
+
+        #[tokio::test]
+        async fn test_lease_blocks_shell_and_command_control() {
+            let (session_id, command_id, shell_id) =
+                owned_session("deploy", Some(ShareMode::Full)).await;
+            let tools = tools();
+
+            // The holder is the bound agent, whatever agent_id it passes
+            with_bound_agent("deploy".to_string(), async {
+                tools
+                    .ssh_lock_session(session_id.clone(), "ops".to_string(), Some(60))
+                    .await
+                    .unwrap();
+            })
+            .await;
+            assert_eq!(
+                SESSION_LEASES
+                    .active(&session_id, chrono::Utc::now())
+                    .unwrap()
+                    .holder_agent_id,
+                "deploy"
+            );
+
+            with_bound_agent("ops".to_string(), async {
+                assert_locked(
+                    tools
+                        .ssh_shell_write(
+                            shell_id.clone(),
+                            "id\n".to_string(),
+                            Some("deploy".to_string()),
+                        )
+                        .await,
+                    "ssh_shell_write",
+                );
+                assert_locked(
+                    tools
+                        .ssh_cancel_command(command_id.clone(), Some("deploy".to_string()))
+                        .await,
+                    "ssh_cancel_command",
+                );
+                assert_locked(
+                    tools
+                        .ssh_command_signal(command_id.clone(), "TERM".to_string(), None)
+                        .await,
+                    "ssh_command_signal",
+                );
+                assert_locked(
+                    tools.ssh_shell_close(shell_id.clone(), None).await,
+                    "ssh_shell_close",
+                );
+            })
+            .await;
+            assert!(SHELL_STORAGE.get_direct(&shell_id).is_some());
+            assert!(
+                !COMMAND_STORAGE
+                    .get(&command_id)
+                    .unwrap()
+                    .cancel_token
+                    .is_cancelled()
+            );
+
+            // The holder keeps using the session without repeating its agent_id
+            with_bound_agent("deploy".to_string(), async {
+                tools
+                    .ssh_shell_write(shell_id.clone(), "id\n".to_string(), None)
+                    .await
+                    .unwrap();
+                tools
+                    .ssh_cancel_command(command_id.clone(), None)
+                    .await
+                    .unwrap();
+                tools.ssh_shell_close(shell_id.clone(), None).await.unwrap();
+            })
+            .await;
+            tear_down_session(&session_id).await;
+        }
+    }
+}
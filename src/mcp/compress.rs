@@ -0,0 +1,176 @@
+//! Compressed output fields for large command results.
+//!
+//! `ssh_get_command_output(compress=true)` and `ssh_execute_sync(compress=true)`
+//! send a `stdout` or `stderr` larger than [`COMPRESS_THRESHOLD`] as gzip
+//! compressed, base64 encoded text and declare it with `stdout_encoding` /
+//! `stderr_encoding: "gzip+base64"`. Logs and listings typically shrink 5-10x,
+//! which keeps very large results below HTTP body limits of proxies and
+//! clients. The raw bytes are compressed, so non-UTF-8 output survives intact.
+//!
+//! A field is only encoded when that makes it smaller; otherwise it stays plain
+//! text and its encoding is omitted.
+//!
+//! Plain text is decoded lossily, so binary output (tarballs, images) is
+//! mangled. `output_encoding: "base64"` on `ssh_execute` or
+//! `ssh_get_command_output` sends the raw bytes base64 encoded instead,
+//! declared as `"base64"` (or `"gzip+base64"` when `compress` also applies).
+//! Plain text fields that were not valid UTF-8 are flagged with
+//! `stdout_is_truncated_utf8` / `stderr_is_truncated_utf8`.
+
+use std::io::Write;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use super::types::OutputEncoding;
+
+/// Output fields above this size are compressed when the caller asks for it
+pub(crate) const COMPRESS_THRESHOLD: usize = 64 * 1024;
+
+/// How output fields are sent (`output_encoding` parameter).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// UTF-8 text, invalid sequences replaced
+    #[default]
+    Text,
+    /// Raw bytes, base64 encoded
+    Base64,
+}
+
+impl OutputFormat {
+    /// Parse an `output_encoding` parameter: "text" or "base64".
+    pub(crate) fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "text" => Ok(Self::Text),
+            "base64" => Ok(Self::Base64),
+            _ => Err(format!(
+                "Invalid output_encoding '{}': expected \"text\" or \"base64\"",
+                name
+            )),
+        }
+    }
+}
+
+/// Text for an output field and its encoding (`None` for plain text).
+pub(crate) fn encode_output(
+    bytes: &[u8],
+    compress: bool,
+    format: OutputFormat,
+) -> (String, Option<OutputEncoding>) {
+    let plain = || match format {
+        OutputFormat::Text => (String::from_utf8_lossy(bytes).into_owned(), None),
+        OutputFormat::Base64 => (STANDARD.encode(bytes), Some(OutputEncoding::Base64)),
+    };
+    if !compress || bytes.len() <= COMPRESS_THRESHOLD {
+        return plain();
+    }
+    match gzip(bytes) {
+        Ok(compressed)
+            if base64::encoded_len(compressed.len(), true).is_some_and(|len| len < bytes.len()) =>
+        {
+            (
+                STANDARD.encode(compressed),
+                Some(OutputEncoding::GzipBase64),
+            )
+        }
+        _ => plain(),
+    }
+}
+
+/// Whether an output field sent as plain text lost bytes to UTF-8
+/// replacement: binary output, or a character cut at the buffer edge.
+pub(crate) fn is_truncated_utf8(bytes: &[u8], encoding: Option<OutputEncoding>) -> bool {
+    encoding.is_none() && std::str::from_utf8(bytes).is_err()
+}
+
+fn gzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn decode(text: &str) -> Vec<u8> {
+        let compressed = STANDARD.decode(text).unwrap();
+        let mut bytes = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_small_or_unrequested_stays_plain() {
+        assert_eq!(
+            encode_output(b"ok\n", true, OutputFormat::Text),
+            ("ok\n".to_string(), None)
+        );
+        let large = vec![b'a'; COMPRESS_THRESHOLD * 2];
+        let (text, encoding) = encode_output(&large, false, OutputFormat::Text);
+        assert_eq!(text.len(), large.len());
+        assert_eq!(encoding, None);
+    }
+
+    #[test]
+    fn test_large_output_roundtrips() {
+        let mut log = Vec::new();
+        for i in 0..5000 {
+            log.extend_from_slice(format!("line {} status=ok\n", i).as_bytes());
+        }
+        log.push(0xff);
+        let (text, encoding) = encode_output(&log, true, OutputFormat::Text);
+        assert_eq!(encoding, Some(OutputEncoding::GzipBase64));
+        assert!(text.len() < log.len() / 4);
+        assert_eq!(decode(&text), log);
+    }
+
+    #[test]
+    fn test_incompressible_output_stays_plain() {
+        // xorshift noise does not compress; base64 would make it larger
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let noise: Vec<u8> = (0..COMPRESS_THRESHOLD * 2)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        assert_eq!(encode_output(&noise, true, OutputFormat::Text).1, None);
+        assert_eq!(
+            encode_output(&noise, true, OutputFormat::Base64),
+            (STANDARD.encode(&noise), Some(OutputEncoding::Base64))
+        );
+    }
+
+    #[test]
+    fn test_base64_keeps_binary_output() {
+        let binary = [0x1f, 0x8b, 0x08, 0x00, 0xff, b'\n'];
+        let (text, encoding) = encode_output(&binary, false, OutputFormat::Base64);
+        assert_eq!(encoding, Some(OutputEncoding::Base64));
+        assert_eq!(STANDARD.decode(text).unwrap(), binary);
+        assert!(!is_truncated_utf8(&binary, encoding));
+
+        let (text, encoding) = encode_output(&binary, false, OutputFormat::Text);
+        assert!(text.contains('\u{fffd}'));
+        assert!(is_truncated_utf8(&binary, encoding));
+
+        // "é" cut after its first byte
+        assert!(is_truncated_utf8(&"café".as_bytes()[..4], None));
+        assert!(!is_truncated_utf8("café".as_bytes(), None));
+    }
+
+    #[test]
+    fn test_output_format_from_name() {
+        assert_eq!(OutputFormat::from_name("text"), Ok(OutputFormat::Text));
+        assert_eq!(OutputFormat::from_name("base64"), Ok(OutputFormat::Base64));
+        assert!(OutputFormat::from_name("hex").is_err());
+    }
+}
@@ -0,0 +1,1506 @@
+//! Server configuration file support.
+//!
+//! Settings that do not fit a single environment variable (message templates,
+//! tool exposure) are read from an optional TOML file. The file path is taken
+//! from the `--config` command-line flag, then the `SSH_MCP_CONFIG` environment
+//! variable; when neither is given the server runs with built-in defaults.
+//!
+//! The file also holds defaults for settings that have an environment variable
+//! (listener port, TLS files, timeouts). Those are layered: a tool parameter
+//! wins over the environment variable, which wins over the file, which wins
+//! over the built-in default. The file is validated when it is loaded, and the
+//! startup self-test refuses to serve with a file that does not load.
+//!
+//! # Example
+//!
+//! ```toml
+//! [server]
+//! port = 8443
+//! tls_cert = "/etc/ssh-mcp/tls/fullchain.pem"
+//! tls_key = "/etc/ssh-mcp/tls/privkey.pem"
+//!
+//! [timeouts]
+//! connect_secs = 15
+//! command_secs = 600
+//!
+//! [messages]
+//! execute = """
+//! {message}
+//!
+//! Reminder: open a change ticket before running sudo on {session_id}.
+//! """
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::mcp::types::SandboxLimits;
+
+/// Environment variable name for the server configuration file path
+pub(crate) const CONFIG_FILE_ENV_VAR: &str = "SSH_MCP_CONFIG";
+
+/// Process-wide server configuration, loaded on first access.
+static SERVER_CONFIG: OnceCell<ServerConfig> = OnceCell::new();
+
+/// Configuration file given on the command line (`--config`).
+static CONFIG_FILE_ARG: OnceCell<String> = OnceCell::new();
+
+/// Top-level server configuration file contents.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// HTTP listener of the `ssh-mcp` binary.
+    pub server: HttpServerConfig,
+    /// Default timeouts, below their environment variables.
+    pub timeouts: TimeoutsConfig,
+    /// Overrides for the human-readable messages attached to tool responses.
+    pub messages: MessageTemplates,
+    /// Which tools are exposed to clients.
+    pub tools: ToolsConfig,
+    /// Concurrent session caps per remote host.
+    pub host_limits: HostLimitsConfig,
+    /// Audit event export to syslog or HTTP collectors.
+    pub audit: AuditConfig,
+    /// Resource limits applied to every executed command; per-call limits can
+    /// only tighten them.
+    pub sandbox: SandboxLimits,
+    /// Hosts the server connects to itself and keeps connected.
+    pub managed_hosts: Vec<ManagedHostConfig>,
+    /// Where `ssh_execute` result webhooks may be sent.
+    pub callbacks: CallbacksConfig,
+    /// Vetted helper scripts mirrored to every session.
+    pub helpers: HelpersConfig,
+    /// Startup configuration self-test.
+    pub self_test: SelfTestConfig,
+    /// Commands refused before they reach a host.
+    pub policy: PolicyConfig,
+    /// Background session health monitor.
+    pub health: HealthConfig,
+    /// Session state file read back after a restart.
+    pub persistence: PersistenceConfig,
+    /// Where sessions and async commands are stored.
+    pub storage: StorageConfig,
+    /// Idle connections kept for reuse by `ssh_connect`.
+    pub connection_pool: ConnectionPoolConfig,
+    /// Background removal of dead and idle sessions.
+    pub reaper: ReaperConfig,
+    /// Named credentials `ssh_connect` takes as `credential_ref`, by name.
+    pub credentials: BTreeMap<String, CredentialConfig>,
+    /// HashiCorp Vault server for `source = "vault"` credentials.
+    pub vault: VaultConfig,
+    /// Named connection targets `ssh_connect` takes as `profile`.
+    pub profiles: ProfilesConfig,
+    /// Tokens the HTTP transports require from clients.
+    pub auth: AuthConfig,
+    /// Local directory the directory transfer tools are confined to.
+    pub transfer: TransferConfig,
+    /// Prompts `ssh_connect` may send to the user through the MCP client.
+    pub elicitation: ElicitationConfig,
+}
+
+/// HTTP listener settings (env `MCP_PORT`, `MCP_TLS_CERT`, `MCP_TLS_KEY` override).
+///
+/// ```toml
+/// [server]
+/// port = 8443
+/// tls_cert = "/etc/ssh-mcp/tls/fullchain.pem"
+/// tls_key = "/etc/ssh-mcp/tls/privkey.pem"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HttpServerConfig {
+    /// Port the MCP endpoints listen on (default: 8000).
+    pub port: Option<u16>,
+    /// PEM certificate chain; serves HTTPS together with `tls_key`.
+    pub tls_cert: Option<String>,
+    /// PEM private key of `tls_cert`.
+    pub tls_key: Option<String>,
+}
+
+/// Default timeouts (see `config` module); each environment variable overrides
+/// its entry.
+///
+/// ```toml
+/// [timeouts]
+/// connect_secs = 15
+/// command_secs = 600
+/// channel_idle_secs = 0
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+    /// `ssh_connect` timeout (env `SSH_CONNECT_TIMEOUT`).
+    pub connect_secs: Option<u64>,
+    /// Command execution timeout (env `SSH_COMMAND_TIMEOUT`).
+    pub command_secs: Option<u64>,
+    /// Idle time before a session is considered inactive (env `SSH_INACTIVITY_TIMEOUT`).
+    pub inactivity_secs: Option<u64>,
+    /// Wait for the server to open a channel (env `SSH_CHANNEL_OPEN_TIMEOUT`).
+    pub channel_open_secs: Option<u64>,
+    /// Hung-channel watchdog threshold; 0 disables it (env `SSH_CHANNEL_IDLE_TIMEOUT`).
+    pub channel_idle_secs: Option<u64>,
+}
+
+/// Message template overrides for the response builders.
+///
+/// Each template replaces the corresponding builder output. Placeholders of
+/// the form `{name}` are substituted with the builder's values; `{message}`
+/// expands to the default message, so templates can append guidance instead
+/// of rewriting the whole text. Unknown placeholders are left untouched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MessageTemplates {
+    /// `ssh_connect` for a new connection.
+    /// Placeholders: `session_id`, `username`, `host`, `agent_id`, `name`, `retry_attempts`.
+    pub connect: Option<String>,
+    /// `ssh_connect` when an existing session is reused. Same placeholders as `connect`.
+    pub connect_reused: Option<String>,
+    /// `ssh_execute`. Placeholders: `command_id`, `session_id`, `agent_id`, `command`.
+    pub execute: Option<String>,
+    /// `ssh_disconnect_agent`.
+    /// Placeholders: `agent_id`, `sessions_disconnected`, `commands_cancelled`,
+    /// `shells_closed`, `forwards_closed`.
+    pub agent_disconnect: Option<String>,
+    /// `ssh_shell_open`.
+    /// Placeholders: `shell_id`, `session_id`, `agent_id`, `term`, `cols`, `rows`.
+    pub shell_open: Option<String>,
+}
+
+/// Tool exposure settings (see `exposure` module).
+///
+/// Entries are tool names (`ssh_execute`) or group names (`shell`).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ToolsConfig {
+    /// Allowlist of tools/groups; `None` exposes every tool.
+    pub enabled: Option<Vec<String>>,
+    /// Tools/groups to hide, applied after `enabled`.
+    pub disabled: Vec<String>,
+    /// Prefix prepended to every exposed tool name (env `SSH_MCP_TOOL_PREFIX` overrides).
+    pub prefix: Option<String>,
+}
+
+/// Per-host session caps (see `host_limit` module).
+///
+/// ```toml
+/// [host_limits]
+/// max_sessions = 8
+///
+/// [host_limits.hosts]
+/// "bmc-rack1.example.com" = 1
+/// "10.0.0.5" = 2
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HostLimitsConfig {
+    /// Cap for every host without an override (env `SSH_MAX_SESSIONS_PER_HOST` overrides).
+    pub max_sessions: Option<usize>,
+    /// Caps for individual hosts, keyed by host name or IP without the port.
+    pub hosts: HashMap<String, usize>,
+}
+
+impl HostLimitsConfig {
+    /// Cap for a host: its override, then `default` (env), then `max_sessions`.
+    ///
+    /// A cap of 0 means unlimited.
+    pub fn limit_for(&self, host: &str, default: Option<usize>) -> Option<usize> {
+        let limit = self
+            .hosts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            .map(|(_, limit)| *limit)
+            .or(default)
+            .or(self.max_sessions)?;
+        (limit > 0).then_some(limit)
+    }
+}
+
+/// Command result webhooks (see `webhook` module).
+///
+/// Callbacks are refused until the operator lists the hosts they may reach:
+///
+/// ```toml
+/// [callbacks]
+/// allowed_hosts = ["orchestrator.internal", "10.0.0.9"]
+/// headers = { Authorization = "Bearer 0000-1111" }
+/// max_output_bytes = 65536
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CallbacksConfig {
+    /// Host names or IPs (without port) callback URLs may point to; `"*"` allows any.
+    pub allowed_hosts: Vec<String>,
+    /// Extra HTTP headers sent with every callback.
+    pub headers: HashMap<String, String>,
+    /// Delivery retries after the first attempt.
+    pub max_retries: usize,
+    /// Bytes of stdout and of stderr (each, the last ones) sent in a callback; 0 sends all.
+    pub max_output_bytes: usize,
+}
+
+impl Default for CallbacksConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            headers: HashMap::new(),
+            max_retries: 3,
+            max_output_bytes: 64 * 1024,
+        }
+    }
+}
+
+impl CallbacksConfig {
+    /// Whether callbacks may be sent to `host`.
+    pub fn allows(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(host))
+    }
+}
+
+/// Helper script library (see `helpers` module).
+///
+/// ```toml
+/// [helpers]
+/// dir = "/etc/ssh-mcp/helpers"
+/// remote_dir = ".ssh-mcp/helpers"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HelpersConfig {
+    /// Local directory holding the scripts; helpers are disabled when unset.
+    pub dir: Option<String>,
+    /// Remote directory, relative to the login directory unless absolute.
+    pub remote_dir: String,
+    /// Upload changed scripts right after every connect (default: true).
+    pub sync_on_connect: bool,
+}
+
+impl Default for HelpersConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            remote_dir: ".ssh-mcp/helpers".to_string(),
+            sync_on_connect: true,
+        }
+    }
+}
+
+/// Startup configuration self-test (see `self_test` module).
+///
+/// ```toml
+/// [self_test]
+/// canary = "bastion"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SelfTestConfig {
+    /// Managed host dialed once at startup and by `ssh_check_config(dial_canary=true)`.
+    pub canary: Option<String>,
+}
+
+/// Command policy (see `policy` module).
+///
+/// ```toml
+/// [policy]
+/// file = "/etc/ssh-mcp/policy.toml"
+///
+/// [[policy.deny]]
+/// pattern = "systemctl restart *"
+/// reason = "service restarts go through the restart-service helper"
+/// suggestions = ["ssh_run_helper name=\"restart-service\""]
+///
+/// [[policy.allow]]
+/// pattern = "^(df|uptime|journalctl)( |$)"
+/// syntax = "regex"
+/// agents = ["triage-bot"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// TOML file with more `[[deny]]` and `[[allow]]` rules, read at startup.
+    pub file: Option<String>,
+    /// Commands that are refused, checked in order.
+    pub deny: Vec<CommandRule>,
+    /// Commands an agent is limited to once any of these rules applies to it.
+    pub allow: Vec<CommandRule>,
+}
+
+/// How a rule's `pattern` is matched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternSyntax {
+    /// `*` and `?` wildcards matched against the whole command
+    #[default]
+    Glob,
+    /// Regular expression searched in the command; anchor with `^` and `$`
+    Regex,
+}
+
+/// One command policy rule.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandRule {
+    /// Pattern matched against the command, see `syntax`.
+    pub pattern: String,
+    /// `glob` (default) or `regex`.
+    #[serde(default)]
+    pub syntax: PatternSyntax,
+    /// Agents the rule applies to; empty (default) applies it to every call.
+    #[serde(default)]
+    pub agents: Vec<String>,
+    /// Why the command is refused; returned to the agent.
+    pub reason: Option<String>,
+    /// What the agent should do instead; returned to the agent.
+    #[serde(default)]
+    pub suggestions: Vec<String>,
+}
+
+/// Background session health monitor (see `health` module).
+///
+/// ```toml
+/// [health]
+/// interval_secs = 60
+/// method = "keepalive"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Seconds between background checks of every session; 0 (default) disables the monitor.
+    pub interval_secs: u64,
+    /// How a session is probed, in the background and by ssh_list_sessions.
+    pub method: HealthMethod,
+    /// Command run by `method = "command"`; the session is healthy when it exits 0.
+    pub command: String,
+    /// Seconds allowed for one probe.
+    pub timeout_secs: u64,
+    /// Sessions probed at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 0,
+            method: HealthMethod::Command,
+            command: "echo 1".to_string(),
+            timeout_secs: 5,
+            concurrency: 16,
+        }
+    }
+}
+
+/// Session state kept across server restarts (see `storage::persistent`).
+///
+/// ```toml
+/// [persistence]
+/// path = "/var/lib/ssh-mcp/state.json"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PersistenceConfig {
+    /// State file; unset (default) keeps session state in memory only.
+    pub path: Option<String>,
+    /// Seconds a session lost in a restart is still reported.
+    pub retention_secs: u64,
+    /// Most recent async commands recorded in the file.
+    pub max_commands: usize,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            retention_secs: 86400,
+            max_commands: 1000,
+        }
+    }
+}
+
+/// Idle connection pool reused by `ssh_connect` (see `pool` module).
+///
+/// ```toml
+/// [connection_pool]
+/// enabled = true
+/// idle_secs = 120
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConnectionPoolConfig {
+    /// Park connections on `ssh_disconnect` instead of closing them; off by default.
+    pub enabled: bool,
+    /// Idle connections kept per agent, host, user and credential.
+    pub max_idle_per_key: usize,
+    /// Idle connections kept in total.
+    pub max_idle: usize,
+    /// Seconds an idle connection is kept before it is closed.
+    pub idle_secs: u64,
+}
+
+impl Default for ConnectionPoolConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_idle_per_key: 2,
+            max_idle: 16,
+            idle_secs: 300,
+        }
+    }
+}
+
+/// Background reaper of dead and idle sessions (see `reaper` module).
+///
+/// ```toml
+/// [reaper]
+/// interval_secs = 30
+/// idle_secs = 1800
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReaperConfig {
+    /// Seconds between sweeps; `0` turns the reaper off.
+    pub interval_secs: u64,
+    /// Seconds without activity before a session is disconnected; `0` (the
+    /// default) only removes sessions whose connection is already closed.
+    pub idle_secs: u64,
+}
+
+impl Default for ReaperConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            idle_secs: 0,
+        }
+    }
+}
+
+/// Local side of `ssh_upload_dir` and `ssh_collect` (see `transfer` module).
+///
+/// Both tools are refused until the operator names the directory their local
+/// paths must stay in:
+///
+/// ```toml
+/// [transfer]
+/// local_root = "/var/lib/ssh-mcp/transfer"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TransferConfig {
+    /// Existing directory that local paths resolve against and may not leave.
+    pub local_root: Option<String>,
+}
+
+/// User prompts sent through MCP elicitation (see `elicit` module).
+///
+/// ```toml
+/// [elicitation]
+/// password_prompt = true
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ElicitationConfig {
+    /// Ask the user for the SSH password when agent authentication fails; off
+    /// by default, as the MCP specification forbids requesting sensitive data
+    /// through form elicitation. Prefer `credential_ref`.
+    pub password_prompt: bool,
+}
+
+/// A named credential for `ssh_connect(credential_ref=...)` (see `credentials` module).
+///
+/// ```toml
+/// [credentials.prod-db]
+/// username = "postgres"
+/// password_env = "PROD_DB_PASSWORD"
+/// hosts = ["db1.internal", "db2.internal"]
+///
+/// [credentials.bastion]
+/// source = "vault"
+/// path = "secret/data/ssh/bastion"
+/// hosts = ["bastion.example.com"]
+/// agents = ["deploy-bot"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CredentialConfig {
+    /// Where the secrets come from (default: env).
+    #[serde(default)]
+    pub source: CredentialSource,
+    /// Hosts (names or IPs without port) the credential may be sent to, as the
+    /// target or a jump host; required.
+    pub hosts: Vec<String>,
+    /// Agents allowed to use the credential; empty (default) allows every agent.
+    #[serde(default)]
+    pub agents: Vec<String>,
+    /// SSH username; a Vault secret's `username` is used when unset.
+    pub username: Option<String>,
+    /// Private key file; a Vault secret's `key_path` is used when unset.
+    pub key_path: Option<String>,
+    /// Environment variable holding the password (`source = "env"`).
+    pub password_env: Option<String>,
+    /// Environment variable holding the private key content (`source = "env"`).
+    pub key_data_env: Option<String>,
+    /// Secret path below `/v1/` (`source = "vault"`), e.g. `secret/data/ssh/prod-db`
+    /// for a KV v2 engine mounted at `secret`.
+    pub path: Option<String>,
+}
+
+impl CredentialConfig {
+    /// Whether the credential may be sent to `host`.
+    pub fn allows_host(&self, host: &str) -> bool {
+        self.hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+
+    /// Whether `agent_id` may use the credential.
+    pub fn allows_agent(&self, agent_id: Option<&str>) -> bool {
+        self.agents.is_empty()
+            || agent_id.is_some_and(|agent_id| self.agents.iter().any(|agent| agent == agent_id))
+    }
+}
+
+/// Origin of a named credential's secrets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialSource {
+    /// Environment variables of the server process
+    #[default]
+    Env,
+    /// A HashiCorp Vault KV secret
+    Vault,
+}
+
+/// HashiCorp Vault server for `source = "vault"` credentials.
+///
+/// ```toml
+/// [vault]
+/// address = "http://127.0.0.1:8200"
+/// token_file = "/run/vault-agent/token"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VaultConfig {
+    /// `http://` address of Vault or a local Vault Agent (default: `VAULT_ADDR`).
+    pub address: Option<String>,
+    /// File holding the Vault token (default: `VAULT_TOKEN`; none for an agent
+    /// that injects its own token).
+    pub token_file: Option<String>,
+    /// Enterprise namespace (default: `VAULT_NAMESPACE`).
+    pub namespace: Option<String>,
+}
+
+/// Tokens required by the HTTP transports (see `transport::token_auth`).
+///
+/// ```toml
+/// [[auth.tokens]]
+/// name = "ci"
+/// token_env = "MCP_TOKEN_CI"
+/// agent_id = "ci-runner"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Accepted tokens, in addition to `MCP_AUTH_TOKEN`.
+    pub tokens: Vec<AuthTokenConfig>,
+}
+
+/// One accepted HTTP token.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthTokenConfig {
+    /// Label used in logs and self-test findings.
+    pub name: String,
+    /// Environment variable holding the token.
+    pub token_env: String,
+    /// Agent the token is bound to: its calls act as this `agent_id` and only
+    /// reach the agent's own sessions and shared ones.
+    pub agent_id: Option<String>,
+}
+
+/// Named connection targets (see `profiles` module).
+///
+/// ```toml
+/// [profiles]
+/// file = "/etc/ssh-mcp/profiles.toml"
+///
+/// [profiles.targets.staging-web]
+/// host = "10.0.1.20"
+/// username = "deploy"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfilesConfig {
+    /// TOML file whose top-level tables are further profiles, read once at startup.
+    pub file: Option<String>,
+    /// Profiles declared inline, by name.
+    pub targets: BTreeMap<String, ProfileConfig>,
+}
+
+/// One named connection target.
+///
+/// Holds no secrets: authentication comes from `key_path`, a `credential_ref`
+/// or the SSH agent.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// Host name or IP address.
+    pub host: String,
+    /// SSH port (default: 22).
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    /// Private key file on the server.
+    pub key_path: Option<String>,
+    /// Named credential (`[credentials.<name>]`) to authenticate with.
+    pub credential_ref: Option<String>,
+    /// Bastions as `[user@]host[:port]`, first hop first.
+    pub jump_hosts: Vec<String>,
+    /// Default ssh_connect timeout.
+    pub connect_timeout_secs: Option<u64>,
+    /// Default timeout of commands run on the session.
+    pub command_timeout_secs: Option<u64>,
+    pub region: Option<String>,
+    pub datacenter: Option<String>,
+    /// Shown by `ssh_list_profiles`.
+    pub description: Option<String>,
+}
+
+/// Session and async command storage (see `storage::backend`).
+///
+/// ```toml
+/// [storage]
+/// backend = "redis"
+///
+/// [storage.redis]
+/// url = "redis://redis.internal:6379/0"
+/// advertise_url = "http://ssh-mcp-1.internal:8000"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Backend holding sessions and async commands; `SSH_STORAGE_BACKEND` overrides it.
+    pub backend: StorageBackend,
+    /// Shared registry used by `backend = "redis"`.
+    pub redis: RedisStorageConfig,
+}
+
+/// Redis registry shared by the replicas of a deployment (`redis` feature).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RedisStorageConfig {
+    /// Connection URL, e.g. `redis://host:6379/0`.
+    pub url: Option<String>,
+    /// Prefix of every key written by the server.
+    pub key_prefix: String,
+    /// Name of this replica; defaults to `HOSTNAME`, else a random id.
+    pub replica_id: Option<String>,
+    /// URL other replicas give clients to reach this one.
+    pub advertise_url: Option<String>,
+    /// Seconds an entry outlives its replica when it stops refreshing it.
+    pub ttl_secs: u64,
+}
+
+impl Default for RedisStorageConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            key_prefix: "ssh-mcp".to_string(),
+            replica_id: None,
+            advertise_url: None,
+            ttl_secs: 30,
+        }
+    }
+}
+
+/// Storage backend for sessions and async commands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// In-process maps; everything is gone when the server stops
+    #[default]
+    Memory,
+    /// In-process maps mirrored to a Redis registry shared by all replicas
+    Redis,
+}
+
+impl StorageBackend {
+    /// Parse a backend name as written in `SSH_STORAGE_BACKEND`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "redis" => Ok(Self::Redis),
+            other => Err(format!(
+                "Unknown storage backend '{}'. Use 'memory' or 'redis'",
+                other
+            )),
+        }
+    }
+
+    /// Name used in the configuration file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StorageBackend::Memory => "memory",
+            StorageBackend::Redis => "redis",
+        }
+    }
+}
+
+/// How a session health check probes the connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthMethod {
+    /// SSH keepalive request; no channel or remote process
+    Keepalive,
+    /// Run `command` in an exec channel
+    #[default]
+    Command,
+}
+
+impl HealthMethod {
+    /// Name used in the configuration file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthMethod::Keepalive => "keepalive",
+            HealthMethod::Command => "command",
+        }
+    }
+}
+
+/// Audit event export (see `audit` and `siem` modules).
+///
+/// ```toml
+/// [audit]
+/// redact_shell_input = true
+///
+/// [[audit.exporters]]
+/// kind = "syslog"
+/// address = "siem.example.com:514"
+/// transport = "tcp"
+/// format = "cef"
+///
+/// [[audit.exporters]]
+/// kind = "http"
+/// url = "http://collector.internal:8080/ingest"
+/// headers = { Authorization = "Splunk 0000-1111" }
+/// batch_size = 200
+///
+/// [[audit.exporters]]
+/// kind = "file"
+/// path = "/var/log/ssh-mcp/audit.jsonl"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Destinations every audit event is sent to.
+    pub exporters: Vec<AuditExporterConfig>,
+    /// Record only the size of `ssh_shell_write` input instead of the text.
+    pub redact_shell_input: bool,
+}
+
+/// Where an audit exporter delivers events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditExporterKind {
+    /// RFC 5424 syslog over UDP or TCP
+    Syslog,
+    /// HTTP POST of each batch
+    Http,
+    /// Lines appended to a local file
+    File,
+}
+
+/// Syslog transport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogTransport {
+    /// One datagram per event
+    #[default]
+    Udp,
+    /// Octet-counted frames (RFC 6587)
+    Tcp,
+}
+
+/// Event encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditFormat {
+    /// One JSON object per event (HTTP batches are JSON arrays)
+    #[default]
+    Json,
+    /// ArcSight Common Event Format
+    Cef,
+}
+
+/// One audit exporter.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditExporterConfig {
+    /// `syslog`, `http` or `file`.
+    pub kind: AuditExporterKind,
+    /// Syslog collector `host:port` (syslog only).
+    pub address: Option<String>,
+    /// Syslog transport (default: udp).
+    #[serde(default)]
+    pub transport: SyslogTransport,
+    /// Collector URL, `http://` only (http only).
+    pub url: Option<String>,
+    /// Extra request headers, e.g. an auth token (http only).
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Log file events are appended to, one per line (file only).
+    pub path: Option<String>,
+    /// Event encoding (default: json).
+    #[serde(default)]
+    pub format: AuditFormat,
+    /// Events per delivery (default: 100).
+    #[serde(default = "default_audit_batch_size")]
+    pub batch_size: usize,
+    /// Longest wait before a partial batch is sent, in milliseconds (default: 1000).
+    #[serde(default = "default_audit_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Delivery retries with exponential backoff before a batch is dropped (default: 5).
+    #[serde(default = "default_audit_max_retries")]
+    pub max_retries: usize,
+    /// Events buffered while the collector is slow or down (default: 10000).
+    #[serde(default = "default_audit_queue_size")]
+    pub queue_size: usize,
+}
+
+/// A host the server connects to on its own (see `managed` module).
+///
+/// ```toml
+/// [[managed_hosts]]
+/// name = "web1"
+/// address = "10.0.0.5:22"
+/// username = "deploy"
+/// key_path = "/etc/ssh-mcp/deploy_key"
+/// region = "eu-west-1"
+/// pool_size = 2
+///
+/// [[managed_hosts]]
+/// name = "bmc-rack1"
+/// address = "bmc-rack1.example.com"
+/// username = "admin"
+/// password_env = "BMC_RACK1_PASSWORD"
+/// connect = "lazy"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManagedHostConfig {
+    /// Session ID agents use for this host (`[A-Za-z0-9._-]`).
+    pub name: String,
+    /// SSH server `host:port` (port defaults to 22).
+    pub address: String,
+    /// SSH username.
+    pub username: String,
+    /// Private key file for key authentication.
+    pub key_path: Option<String>,
+    /// Environment variable holding the password, so secrets stay out of the file.
+    pub password_env: Option<String>,
+    /// When to connect (default: startup).
+    #[serde(default)]
+    pub connect: ManagedConnect,
+    /// zlib compression (default: `SSH_COMPRESSION`, else true).
+    pub compress: Option<bool>,
+    /// Spare connections kept dialed for fast reconnects and `ssh_connect` (default: 0, max 8).
+    #[serde(default)]
+    pub pool_size: usize,
+    /// Region tag for affinity-based selection.
+    pub region: Option<String>,
+    /// Datacenter tag for affinity-based selection.
+    pub datacenter: Option<String>,
+}
+
+/// When a managed host is connected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManagedConnect {
+    /// When the server starts; reconnected whenever it drops
+    #[default]
+    Startup,
+    /// On first use; reconnected whenever it drops afterwards
+    Lazy,
+}
+
+fn default_audit_batch_size() -> usize {
+    100
+}
+
+fn default_audit_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_audit_max_retries() -> usize {
+    5
+}
+
+fn default_audit_queue_size() -> usize {
+    10_000
+}
+
+impl ServerConfig {
+    /// Parse and validate a configuration from TOML text.
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let config: Self =
+            toml::from_str(content).map_err(|e| format!("Invalid configuration: {}", e))?;
+        config
+            .validate()
+            .map_err(|e| format!("Invalid configuration: {}", e))?;
+        Ok(config)
+    }
+
+    /// Check values the TOML types alone cannot rule out; every problem is
+    /// reported, separated by `; `.
+    fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if self.server.port == Some(0) {
+            problems.push("[server] port must be between 1 and 65535".to_string());
+        }
+        if self.server.tls_cert.is_some() != self.server.tls_key.is_some() {
+            problems.push("[server] tls_cert and tls_key must be set together".to_string());
+        }
+        let timeouts = [
+            ("connect_secs", self.timeouts.connect_secs),
+            ("command_secs", self.timeouts.command_secs),
+            ("inactivity_secs", self.timeouts.inactivity_secs),
+            ("channel_open_secs", self.timeouts.channel_open_secs),
+        ];
+        for (name, value) in timeouts {
+            if value == Some(0) {
+                problems.push(format!("[timeouts] {} must be at least 1", name));
+            }
+        }
+        if self
+            .policy
+            .file
+            .as_deref()
+            .is_some_and(|file| file.trim().is_empty())
+        {
+            problems.push("[policy] file must not be empty".to_string());
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems.join("; ")),
+        }
+    }
+
+    /// Read and parse a configuration file.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+        Self::from_toml(&content).map_err(|e| format!("{} ({})", e, path.display()))
+    }
+
+    /// Load the configuration file (`--config`, then `SSH_MCP_CONFIG`),
+    /// falling back to defaults.
+    fn load() -> Self {
+        let Some(path) = config_file_path() else {
+            return Self::default();
+        };
+
+        match Self::from_file(Path::new(&path)) {
+            Ok(config) => {
+                info!("Loaded server configuration from {}", path);
+                config
+            }
+            Err(e) => {
+                warn!("{}; using built-in defaults", e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Use `path` as the configuration file, ahead of `SSH_MCP_CONFIG`.
+///
+/// Must be called before the configuration is first read.
+pub(crate) fn set_config_file(path: String) -> Result<(), String> {
+    if SERVER_CONFIG.get().is_some() {
+        return Err("The server configuration was already loaded".to_string());
+    }
+    CONFIG_FILE_ARG
+        .set(path)
+        .map_err(|_| "The configuration file was already set".to_string())
+}
+
+/// Path of the configuration file: `--config`, then `SSH_MCP_CONFIG`.
+pub(crate) fn config_file_path() -> Option<String> {
+    CONFIG_FILE_ARG
+        .get()
+        .cloned()
+        .or_else(|| env::var(CONFIG_FILE_ENV_VAR).ok())
+}
+
+/// Get the process-wide server configuration.
+///
+/// The first call loads the file named by `--config` or `SSH_MCP_CONFIG`;
+/// later calls return the cached value.
+pub(crate) fn server_config() -> &'static ServerConfig {
+    SERVER_CONFIG.get_or_init(ServerConfig::load)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn test_empty_config_uses_defaults() {
+            let config = ServerConfig::from_toml("").unwrap();
+            assert!(config.messages.connect.is_none());
+            assert!(config.messages.execute.is_none());
+        }
+
+        #[test]
+        fn test_parses_message_templates() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [messages]
+                connect = "Connected {session_id}"
+                shell_open = "{message}\nBe careful"
+                "#,
+            )
+            .unwrap();
+
+            assert_eq!(
+                config.messages.connect.as_deref(),
+                Some("Connected {session_id}")
+            );
+            assert_eq!(
+                config.messages.shell_open.as_deref(),
+                Some("{message}\nBe careful")
+            );
+            assert!(config.messages.connect_reused.is_none());
+        }
+
+        #[test]
+        fn test_parses_server_and_timeouts() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [server]
+                port = 8443
+                tls_cert = "cert.pem"
+                tls_key = "key.pem"
+
+                [timeouts]
+                connect_secs = 15
+                channel_idle_secs = 0
+                "#,
+            )
+            .unwrap();
+
+            assert_eq!(config.server.port, Some(8443));
+            assert_eq!(config.server.tls_key.as_deref(), Some("key.pem"));
+            assert_eq!(config.timeouts.connect_secs, Some(15));
+            assert_eq!(config.timeouts.channel_idle_secs, Some(0));
+            assert_eq!(config.timeouts.command_secs, None);
+        }
+
+        #[test]
+        fn test_validation_reports_every_problem() {
+            let err = ServerConfig::from_toml(
+                r#"
+                [server]
+                port = 0
+                tls_cert = "cert.pem"
+
+                [timeouts]
+                command_secs = 0
+                "#,
+            )
+            .unwrap_err();
+
+            assert!(err.contains("[server] port must be between 1 and 65535"));
+            assert!(err.contains("tls_cert and tls_key must be set together"));
+            assert!(err.contains("[timeouts] command_secs must be at least 1"));
+
+            // Out-of-range values are caught by the TOML types, with their location
+            let err = ServerConfig::from_toml("[server]\nport = 70000\n").unwrap_err();
+            assert!(err.contains("line 2"), "{}", err);
+        }
+
+        #[test]
+        fn test_parses_tools_section() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [tools]
+                enabled = ["session", "execute"]
+                disabled = ["ssh_cancel_command"]
+                prefix = "prod_"
+                "#,
+            )
+            .unwrap();
+
+            assert_eq!(
+                config.tools.enabled,
+                Some(vec!["session".to_string(), "execute".to_string()])
+            );
+            assert_eq!(config.tools.disabled, vec!["ssh_cancel_command"]);
+            assert_eq!(config.tools.prefix.as_deref(), Some("prod_"));
+        }
+
+        #[test]
+        fn test_parses_host_limits_section() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [host_limits]
+                max_sessions = 8
+
+                [host_limits.hosts]
+                "bmc-rack1" = 1
+                "#,
+            )
+            .unwrap();
+
+            assert_eq!(config.host_limits.max_sessions, Some(8));
+            assert_eq!(config.host_limits.hosts["bmc-rack1"], 1);
+        }
+
+        #[test]
+        fn test_parses_audit_exporters() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [audit]
+                redact_shell_input = true
+
+                [[audit.exporters]]
+                kind = "syslog"
+                address = "siem:514"
+                transport = "tcp"
+                format = "cef"
+
+                [[audit.exporters]]
+                kind = "http"
+                url = "http://collector:8080/ingest"
+                headers = { Authorization = "Bearer t" }
+                batch_size = 10
+                "#,
+            )
+            .unwrap();
+
+            let exporters = &config.audit.exporters;
+            assert_eq!(exporters.len(), 2);
+            assert_eq!(exporters[0].kind, AuditExporterKind::Syslog);
+            assert_eq!(exporters[0].transport, SyslogTransport::Tcp);
+            assert_eq!(exporters[0].format, AuditFormat::Cef);
+            assert_eq!(exporters[0].batch_size, 100);
+            assert_eq!(exporters[1].kind, AuditExporterKind::Http);
+            assert_eq!(exporters[1].format, AuditFormat::Json);
+            assert_eq!(exporters[1].headers["Authorization"], "Bearer t");
+            assert_eq!(exporters[1].batch_size, 10);
+            assert_eq!(exporters[1].max_retries, 5);
+            assert!(config.audit.redact_shell_input);
+            assert!(!ServerConfig::default().audit.redact_shell_input);
+        }
+
+        #[test]
+        fn test_parses_sandbox_section() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [sandbox]
+                wall_secs = 3600
+                max_open_files = 4096
+                "#,
+            )
+            .unwrap();
+
+            assert_eq!(config.sandbox.wall_secs, Some(3600));
+            assert_eq!(config.sandbox.max_open_files, Some(4096));
+            assert_eq!(config.sandbox.cpu_secs, None);
+        }
+
+        #[test]
+        fn test_parses_managed_hosts() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [[managed_hosts]]
+                name = "web1"
+                address = "10.0.0.5:22"
+                username = "deploy"
+                key_path = "/etc/ssh-mcp/deploy_key"
+                pool_size = 2
+
+                [[managed_hosts]]
+                name = "bmc"
+                address = "bmc.example.com"
+                username = "admin"
+                password_env = "BMC_PASSWORD"
+                connect = "lazy"
+                "#,
+            )
+            .unwrap();
+
+            let hosts = &config.managed_hosts;
+            assert_eq!(hosts.len(), 2);
+            assert_eq!(hosts[0].connect, ManagedConnect::Startup);
+            assert_eq!(
+                hosts[0].key_path.as_deref(),
+                Some("/etc/ssh-mcp/deploy_key")
+            );
+            assert_eq!(hosts[0].pool_size, 2);
+            assert_eq!(hosts[1].connect, ManagedConnect::Lazy);
+            assert_eq!(hosts[1].pool_size, 0);
+            assert_eq!(hosts[1].password_env.as_deref(), Some("BMC_PASSWORD"));
+        }
+
+        #[test]
+        fn test_helpers_defaults() {
+            let config =
+                ServerConfig::from_toml("[helpers]\ndir = \"/etc/ssh-mcp/helpers\"").unwrap();
+            assert_eq!(config.helpers.dir.as_deref(), Some("/etc/ssh-mcp/helpers"));
+            assert_eq!(config.helpers.remote_dir, ".ssh-mcp/helpers");
+            assert!(config.helpers.sync_on_connect);
+            assert!(ServerConfig::default().helpers.dir.is_none());
+        }
+
+        #[test]
+        fn test_parses_health_section() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [health]
+                interval_secs = 60
+                method = "keepalive"
+                "#,
+            )
+            .unwrap();
+            assert_eq!(config.health.interval_secs, 60);
+            assert_eq!(config.health.method, HealthMethod::Keepalive);
+            assert_eq!(config.health.command, "echo 1");
+
+            // Off by default; ssh_list_sessions keeps probing on every call
+            let defaults = ServerConfig::default().health;
+            assert_eq!(defaults.interval_secs, 0);
+            assert_eq!(defaults.method, HealthMethod::Command);
+        }
+
+        #[test]
+        fn test_parses_persistence_section() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [persistence]
+                path = "/var/lib/ssh-mcp/state.json"
+                retention_secs = 3600
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                config.persistence.path.as_deref(),
+                Some("/var/lib/ssh-mcp/state.json")
+            );
+            assert_eq!(config.persistence.retention_secs, 3600);
+            assert_eq!(config.persistence.max_commands, 1000);
+
+            assert!(ServerConfig::default().persistence.path.is_none());
+        }
+
+        #[test]
+        fn test_parses_storage_section() {
+            let config = ServerConfig::from_toml("[storage]\nbackend = \"memory\"").unwrap();
+            assert_eq!(config.storage.backend, StorageBackend::Memory);
+            assert!(ServerConfig::from_toml("[storage]\nbackend = \"sled\"").is_err());
+
+            let config = ServerConfig::from_toml(
+                r#"
+                [storage]
+                backend = "redis"
+
+                [storage.redis]
+                url = "redis://redis.internal:6379/0"
+                replica_id = "mcp-1"
+                "#,
+            )
+            .unwrap();
+            assert_eq!(config.storage.backend, StorageBackend::Redis);
+            let redis = &config.storage.redis;
+            assert_eq!(redis.url.as_deref(), Some("redis://redis.internal:6379/0"));
+            assert_eq!(redis.replica_id.as_deref(), Some("mcp-1"));
+            assert_eq!(redis.key_prefix, "ssh-mcp");
+            assert_eq!(redis.ttl_secs, 30);
+
+            assert_eq!(
+                StorageBackend::parse(" Memory "),
+                Ok(StorageBackend::Memory)
+            );
+            assert_eq!(StorageBackend::parse("REDIS"), Ok(StorageBackend::Redis));
+            assert!(StorageBackend::parse("sqlite").is_err());
+        }
+
+        #[test]
+        fn test_parses_connection_pool_section() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [connection_pool]
+                enabled = true
+                idle_secs = 120
+                "#,
+            )
+            .unwrap();
+            let pool = &config.connection_pool;
+            assert!(pool.enabled);
+            assert_eq!(pool.idle_secs, 120);
+            assert_eq!(pool.max_idle_per_key, 2);
+            assert_eq!(pool.max_idle, 16);
+
+            assert!(!ServerConfig::default().connection_pool.enabled);
+        }
+
+        #[test]
+        fn test_parses_reaper_section() {
+            let config = ServerConfig::from_toml("[reaper]\nidle_secs = 1800").unwrap();
+            assert_eq!(config.reaper.idle_secs, 1800);
+            assert_eq!(config.reaper.interval_secs, 60);
+
+            assert_eq!(ServerConfig::default().reaper.idle_secs, 0);
+        }
+
+        #[test]
+        fn test_password_prompt_is_opt_in() {
+            assert!(!ServerConfig::default().elicitation.password_prompt);
+            let config = ServerConfig::from_toml("[elicitation]\npassword_prompt = true").unwrap();
+            assert!(config.elicitation.password_prompt);
+        }
+
+        #[test]
+        fn test_parses_credentials() {
+            let config = ServerConfig::from_toml(
+                "[credentials.prod-db]\nusername = \"postgres\"\npassword_env = \"PROD_DB_PASSWORD\"\n\
+                 hosts = [\"db1.internal\"]\n\
+                 [credentials.bastion]\nsource = \"vault\"\npath = \"secret/data/ssh/bastion\"\n\
+                 hosts = [\"bastion\"]\nagents = [\"deploy-bot\"]\n\
+                 [vault]\naddress = \"http://127.0.0.1:8200\"",
+            )
+            .unwrap();
+            let prod = &config.credentials["prod-db"];
+            assert_eq!(prod.source, CredentialSource::Env);
+            assert_eq!(prod.password_env.as_deref(), Some("PROD_DB_PASSWORD"));
+            let bastion = &config.credentials["bastion"];
+            assert_eq!(bastion.source, CredentialSource::Vault);
+            assert_eq!(bastion.path.as_deref(), Some("secret/data/ssh/bastion"));
+            assert_eq!(prod.hosts, vec!["db1.internal"]);
+            assert!(prod.agents.is_empty());
+            assert_eq!(bastion.agents, vec!["deploy-bot"]);
+            assert_eq!(
+                config.vault.address.as_deref(),
+                Some("http://127.0.0.1:8200")
+            );
+
+            // Secrets never go in the file itself
+            assert!(
+                ServerConfig::from_toml("[credentials.x]\nhosts = []\npassword = \"hunter2\"")
+                    .is_err()
+            );
+            // A credential is always tied to hosts
+            assert!(ServerConfig::from_toml("[credentials.x]\npassword_env = \"X\"").is_err());
+        }
+
+        #[test]
+        fn test_parses_auth_tokens() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [[auth.tokens]]
+                name = "ci"
+                token_env = "MCP_TOKEN_CI"
+                agent_id = "ci-runner"
+
+                [[auth.tokens]]
+                name = "ops"
+                token_env = "MCP_TOKEN_OPS"
+                "#,
+            )
+            .unwrap();
+            let tokens = &config.auth.tokens;
+            assert_eq!(tokens.len(), 2);
+            assert_eq!(tokens[0].token_env, "MCP_TOKEN_CI");
+            assert_eq!(tokens[0].agent_id.as_deref(), Some("ci-runner"));
+            assert!(tokens[1].agent_id.is_none());
+
+            // Tokens themselves never go in the file
+            assert!(ServerConfig::from_toml("[[auth.tokens]]\ntoken = \"s3cret\"").is_err());
+        }
+
+        #[test]
+        fn test_parses_profiles() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [profiles]
+                file = "/etc/ssh-mcp/profiles.toml"
+
+                [profiles.targets.staging-web]
+                host = "10.0.1.20"
+                port = 2222
+                username = "deploy"
+                jump_hosts = ["ops@bastion.example.com"]
+                command_timeout_secs = 600
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                config.profiles.file.as_deref(),
+                Some("/etc/ssh-mcp/profiles.toml")
+            );
+            let profile = &config.profiles.targets["staging-web"];
+            assert_eq!(profile.host, "10.0.1.20");
+            assert_eq!(profile.port, Some(2222));
+            assert_eq!(profile.jump_hosts, vec!["ops@bastion.example.com"]);
+            assert_eq!(profile.command_timeout_secs, Some(600));
+            assert!(profile.connect_timeout_secs.is_none());
+
+            assert!(
+                ServerConfig::from_toml("[profiles.targets.x]\nhost = \"h\"\npassword = \"p\"")
+                    .is_err()
+            );
+        }
+
+        #[test]
+        fn test_invalid_toml_is_error() {
+            let result = ServerConfig::from_toml("[messages\nconnect = 1");
+            assert!(result.is_err());
+            assert!(result.unwrap_err().contains("Invalid configuration"));
+        }
+
+        #[test]
+        fn test_wrong_type_is_error() {
+            let result = ServerConfig::from_toml("[messages]\nconnect = 42");
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn test_missing_file_is_error() {
+            let result = ServerConfig::from_file(Path::new("/nonexistent/ssh-mcp.toml"));
+            assert!(result.is_err());
+            assert!(result.unwrap_err().contains("Failed to read config file"));
+        }
+    }
+
+    mod host_limits {
+        use super::*;
+
+        fn limits() -> HostLimitsConfig {
+            HostLimitsConfig {
+                max_sessions: Some(8),
+                hosts: HashMap::from([("BMC-1".to_string(), 1), ("lab".to_string(), 0)]),
+            }
+        }
+
+        #[test]
+        fn test_host_override_wins() {
+            assert_eq!(limits().limit_for("bmc-1", Some(4)), Some(1));
+        }
+
+        #[test]
+        fn test_env_default_before_file_default() {
+            assert_eq!(limits().limit_for("web", Some(4)), Some(4));
+            assert_eq!(limits().limit_for("web", None), Some(8));
+        }
+
+        #[test]
+        fn test_zero_is_unlimited() {
+            assert_eq!(limits().limit_for("lab", Some(4)), None);
+            assert_eq!(HostLimitsConfig::default().limit_for("web", None), None);
+        }
+    }
+}
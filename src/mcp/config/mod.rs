@@ -0,0 +1,1475 @@
+//! Configuration resolution for SSH MCP server.
+//!
+//! This module handles configuration values with a three-tier priority system:
+//!
+//! 1. **Parameter** - Explicitly provided function parameter (highest priority)
+//! 2. **Environment Variable** - Value from environment variable
+//! 3. **Default** - Built-in default value (lowest priority)
+//!
+//! Timeouts, the listener port and the TLS files can also be set in the
+//! configuration file (`[timeouts]`, `[server]`), between the environment
+//! variable and the built-in default.
+//!
+//! # Environment Variables
+//!
+//! | Variable | Default | Description |
+//! |----------|---------|-------------|
+//! | `SSH_CONNECT_TIMEOUT` | 30s | Connection timeout in seconds |
+//! | `SSH_COMMAND_TIMEOUT` | 180s | Command execution timeout in seconds |
+//! | `SSH_MAX_RETRIES` | 3 | Maximum retry attempts |
+//! | `SSH_RETRY_DELAY_MS` | 1000ms | Initial retry delay in milliseconds |
+//! | `SSH_INACTIVITY_TIMEOUT` | 300s | Session inactivity timeout in seconds |
+//! | `SSH_KEEPALIVE_INTERVAL` | 30s | Seconds between keepalives while the server is silent (0 disables) |
+//! | `SSH_KEEPALIVE_MAX` | 3 | Unanswered keepalives before the connection is dropped (0: never) |
+//! | `SSH_COMPRESSION` | true | Enable zlib compression |
+//! | `SSH_COMPRESSION_LEVEL` | - | zlib level 0-1 (0 disables compression) |
+//! | `SSH_IDENTITY_PROBE` | true | Run the post-connect identity probe (`id`, `hostname`) |
+//! | `SSH_AUTO_RECONNECT` | false | Default of `ssh_connect`'s `auto_reconnect` |
+//! | `SSH_READ_ONLY` | false | Make every session read-only and hide the write tools |
+//! | `SSH_CHANNEL_OPEN_TIMEOUT` | 15s | Timeout for opening a channel and each setup request (pty, exec, shell) |
+//! | `SSH_CHANNEL_IDLE_TIMEOUT` | 300s | Silence after which an async command's connection is probed (0 disables) |
+//! | `SSH_MAX_OUTPUT_BYTES` | 64 MiB | Output kept per stream of an async command (0: unlimited) |
+//! | `SSH_OUTPUT_OVERFLOW` | ring | Past the cap keep the newest (`ring`) or first (`head`) output |
+//! | `SSH_MAX_SESSIONS` | - | Concurrent sessions allowed in total (0: unlimited) |
+//! | `SSH_MAX_SESSIONS_PER_AGENT` | - | Concurrent sessions allowed per agent_id (0: unlimited) |
+//! | `SSH_MAX_SESSIONS_PER_HOST` | - | Concurrent sessions allowed per remote host (0: unlimited) |
+//! | `SSH_STRICT_HOST_KEY_CHECKING` | accept-new | Host key checking mode (see `known_hosts`) |
+//! | `SSH_KNOWN_HOSTS` | ~/.ssh/known_hosts | known_hosts file used to verify host keys |
+//! | `SSH_MCP_CONFIG` | - | Path to the TOML server configuration file (`--config` overrides) |
+//! | `SSH_MCP_TOOL_PREFIX` | - | Prefix prepended to every exposed tool name |
+//! | `SSH_CONFIG_FILE` | ~/.ssh/config | OpenSSH client config read with `use_ssh_config` |
+//!
+//! Settings that are not simple scalars live in the server configuration file;
+//! see [`file`]. Host aliases from the OpenSSH client configuration are
+//! resolved by [`ssh_config`].
+
+mod file;
+pub(crate) mod ssh_config;
+
+#[cfg(feature = "redis")]
+pub(crate) use file::RedisStorageConfig;
+pub(crate) use file::{
+    AuditExporterConfig, AuditExporterKind, AuditFormat, AuthConfig, AuthTokenConfig,
+    CONFIG_FILE_ENV_VAR, CallbacksConfig, CommandRule, ConnectionPoolConfig, CredentialConfig,
+    CredentialSource, HealthConfig, HealthMethod, HelpersConfig, HostLimitsConfig, ManagedConnect,
+    ManagedHostConfig, PatternSyntax, PersistenceConfig, PolicyConfig, ProfileConfig,
+    ProfilesConfig, ReaperConfig, ServerConfig, StorageBackend, StorageConfig, SyslogTransport,
+    ToolsConfig, VaultConfig, config_file_path, server_config, set_config_file,
+};
+
+use std::env;
+use std::time::Duration;
+
+use super::async_command::{OutputLimit, OutputOverflow};
+
+/// Default SSH connection timeout
+pub(crate) const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default SSH command execution timeout
+pub(crate) const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Default maximum retry attempts for SSH connection
+pub(crate) const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default retry delay
+pub(crate) const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(1000);
+
+/// Default session inactivity timeout (separate from connect timeout)
+pub(crate) const DEFAULT_INACTIVITY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default interval between SSH keepalives while the server is silent
+pub(crate) const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default number of unanswered keepalives before the connection is dropped
+pub(crate) const DEFAULT_KEEPALIVE_MAX: u32 = 3;
+
+/// Default timeout for opening a channel and each channel setup request
+pub(crate) const DEFAULT_CHANNEL_OPEN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Default silence on an async command channel before the watchdog probes the server
+pub(crate) const DEFAULT_CHANNEL_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default output kept per stream of an async command
+pub(crate) const DEFAULT_MAX_OUTPUT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Maximum retry delay cap (10 seconds)
+pub(crate) const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Environment variable name for SSH connection timeout
+pub(crate) const CONNECT_TIMEOUT_ENV_VAR: &str = "SSH_CONNECT_TIMEOUT";
+
+/// Environment variable name for SSH command execution timeout
+pub(crate) const COMMAND_TIMEOUT_ENV_VAR: &str = "SSH_COMMAND_TIMEOUT";
+
+/// Environment variable name for SSH max retries
+pub(crate) const MAX_RETRIES_ENV_VAR: &str = "SSH_MAX_RETRIES";
+
+/// Environment variable name for SSH retry delay in milliseconds
+pub(crate) const RETRY_DELAY_MS_ENV_VAR: &str = "SSH_RETRY_DELAY_MS";
+
+/// Environment variable name for SSH session inactivity timeout
+pub(crate) const INACTIVITY_TIMEOUT_ENV_VAR: &str = "SSH_INACTIVITY_TIMEOUT";
+
+/// Environment variable name for the SSH keepalive interval in seconds
+pub(crate) const KEEPALIVE_INTERVAL_ENV_VAR: &str = "SSH_KEEPALIVE_INTERVAL";
+
+/// Environment variable name for the unanswered keepalive limit
+pub(crate) const KEEPALIVE_MAX_ENV_VAR: &str = "SSH_KEEPALIVE_MAX";
+
+/// Environment variable name for SSH compression
+pub(crate) const COMPRESSION_ENV_VAR: &str = "SSH_COMPRESSION";
+
+/// Environment variable name for the zlib compression level
+pub(crate) const COMPRESSION_LEVEL_ENV_VAR: &str = "SSH_COMPRESSION_LEVEL";
+
+/// Highest zlib compression level: the SSH transport only compresses at
+/// [`TRANSPORT_ZLIB_LEVEL`](super::client::TRANSPORT_ZLIB_LEVEL)
+pub(crate) const MAX_COMPRESSION_LEVEL: u32 = 1;
+
+/// Environment variable name for the post-connect identity probe toggle
+pub(crate) const IDENTITY_PROBE_ENV_VAR: &str = "SSH_IDENTITY_PROBE";
+
+/// Environment variable name for the default of `ssh_connect`'s `auto_reconnect`
+pub(crate) const AUTO_RECONNECT_ENV_VAR: &str = "SSH_AUTO_RECONNECT";
+
+/// Environment variable name for the server-wide read-only mode
+pub(crate) const READ_ONLY_ENV_VAR: &str = "SSH_READ_ONLY";
+
+/// Environment variable name for the channel open timeout
+pub(crate) const CHANNEL_OPEN_TIMEOUT_ENV_VAR: &str = "SSH_CHANNEL_OPEN_TIMEOUT";
+
+/// Environment variable name for the hung-channel watchdog threshold
+pub(crate) const CHANNEL_IDLE_TIMEOUT_ENV_VAR: &str = "SSH_CHANNEL_IDLE_TIMEOUT";
+
+/// Environment variable name for the per-stream output cap of async commands
+pub(crate) const MAX_OUTPUT_BYTES_ENV_VAR: &str = "SSH_MAX_OUTPUT_BYTES";
+
+/// Environment variable name for what happens to output past the cap
+pub(crate) const OUTPUT_OVERFLOW_ENV_VAR: &str = "SSH_OUTPUT_OVERFLOW";
+
+/// Environment variable name for the cap on concurrent sessions in total
+pub(crate) const MAX_SESSIONS_ENV_VAR: &str = "SSH_MAX_SESSIONS";
+
+/// Environment variable name for the cap on concurrent sessions per agent
+pub(crate) const MAX_SESSIONS_PER_AGENT_ENV_VAR: &str = "SSH_MAX_SESSIONS_PER_AGENT";
+
+/// Environment variable name for the per-host session cap
+pub(crate) const MAX_SESSIONS_PER_HOST_ENV_VAR: &str = "SSH_MAX_SESSIONS_PER_HOST";
+
+/// Environment variable name for the exposed tool name prefix
+pub(crate) const TOOL_PREFIX_ENV_VAR: &str = "SSH_MCP_TOOL_PREFIX";
+
+/// Environment variable name for the session and command storage backend
+pub(crate) const STORAGE_BACKEND_ENV_VAR: &str = "SSH_STORAGE_BACKEND";
+
+/// Resolve the connection timeout value with priority: parameter -> env var ->
+/// config file -> default
+pub(crate) fn resolve_connect_timeout(timeout_param: Option<u64>) -> Duration {
+    // Priority 1: Use parameter if provided
+    if let Some(timeout) = timeout_param {
+        return Duration::from_secs(timeout);
+    }
+
+    // Priority 2: Use environment variable if set
+    if let Ok(env_timeout) = env::var(CONNECT_TIMEOUT_ENV_VAR)
+        && let Ok(timeout) = env_timeout.parse::<u64>()
+    {
+        return Duration::from_secs(timeout);
+    }
+
+    // Priority 3: [timeouts] in the config file, then the default value
+    server_config()
+        .timeouts
+        .connect_secs
+        .map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from_secs)
+}
+
+/// Resolve the command execution timeout value with priority: parameter ->
+/// env var -> config file -> default
+pub(crate) fn resolve_command_timeout(timeout_param: Option<u64>) -> Duration {
+    // Priority 1: Use parameter if provided
+    if let Some(timeout) = timeout_param {
+        return Duration::from_secs(timeout);
+    }
+
+    // Priority 2: Use environment variable if set
+    if let Ok(env_timeout) = env::var(COMMAND_TIMEOUT_ENV_VAR)
+        && let Ok(timeout) = env_timeout.parse::<u64>()
+    {
+        return Duration::from_secs(timeout);
+    }
+
+    // Priority 3: [timeouts] in the config file, then the default value
+    server_config()
+        .timeouts
+        .command_secs
+        .map_or(DEFAULT_COMMAND_TIMEOUT, Duration::from_secs)
+}
+
+/// Resolve the max retries value with priority: parameter -> env var -> default
+pub(crate) fn resolve_max_retries(max_retries_param: Option<u32>) -> u32 {
+    // Priority 1: Use parameter if provided
+    if let Some(max_retries) = max_retries_param {
+        return max_retries;
+    }
+
+    // Priority 2: Use environment variable if set
+    if let Ok(env_retries) = env::var(MAX_RETRIES_ENV_VAR)
+        && let Ok(retries) = env_retries.parse::<u32>()
+    {
+        return retries;
+    }
+
+    // Priority 3: Default value
+    DEFAULT_MAX_RETRIES
+}
+
+/// Resolve the retry delay value with priority: parameter -> env var -> default
+pub(crate) fn resolve_retry_delay(retry_delay_param: Option<u64>) -> Duration {
+    // Priority 1: Use parameter if provided (milliseconds)
+    if let Some(delay) = retry_delay_param {
+        return Duration::from_millis(delay);
+    }
+
+    // Priority 2: Use environment variable if set (milliseconds)
+    if let Ok(env_delay) = env::var(RETRY_DELAY_MS_ENV_VAR)
+        && let Ok(delay) = env_delay.parse::<u64>()
+    {
+        return Duration::from_millis(delay);
+    }
+
+    // Priority 3: Default value
+    DEFAULT_RETRY_DELAY
+}
+
+/// Resolve the inactivity timeout with priority: env var -> config file ->
+/// default (300s)
+pub(crate) fn resolve_inactivity_timeout() -> Duration {
+    if let Ok(env_timeout) = env::var(INACTIVITY_TIMEOUT_ENV_VAR)
+        && let Ok(timeout) = env_timeout.parse::<u64>()
+    {
+        return Duration::from_secs(timeout);
+    }
+
+    server_config()
+        .timeouts
+        .inactivity_secs
+        .map_or(DEFAULT_INACTIVITY_TIMEOUT, Duration::from_secs)
+}
+
+/// SSH keepalive settings of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Keepalive {
+    /// Seconds between keepalives while the server is silent (0: disabled)
+    pub interval_secs: u64,
+    /// Unanswered keepalives after which the connection is dropped (0: never)
+    pub max: u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self {
+            interval_secs: DEFAULT_KEEPALIVE_INTERVAL.as_secs(),
+            max: DEFAULT_KEEPALIVE_MAX,
+        }
+    }
+}
+
+impl Keepalive {
+    /// Interval handed to russh; `None` disables keepalives.
+    pub(crate) fn interval(self) -> Option<Duration> {
+        (self.interval_secs > 0).then(|| Duration::from_secs(self.interval_secs))
+    }
+}
+
+/// Resolve the keepalive settings with priority: parameters -> env vars -> default (30s, 3)
+pub(crate) fn resolve_keepalive(interval_param: Option<u64>, max_param: Option<u32>) -> Keepalive {
+    let default = Keepalive::default();
+    let interval_secs = interval_param
+        .or_else(|| {
+            env::var(KEEPALIVE_INTERVAL_ENV_VAR)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+        })
+        .unwrap_or(default.interval_secs);
+    let max = max_param
+        .or_else(|| {
+            env::var(KEEPALIVE_MAX_ENV_VAR)
+                .ok()
+                .and_then(|value| value.parse::<u32>().ok())
+        })
+        .unwrap_or(default.max);
+
+    Keepalive { interval_secs, max }
+}
+
+/// Resolve the channel open timeout with priority: env var -> config file ->
+/// default (15s)
+pub(crate) fn resolve_channel_open_timeout() -> Duration {
+    if let Ok(env_timeout) = env::var(CHANNEL_OPEN_TIMEOUT_ENV_VAR)
+        && let Ok(timeout) = env_timeout.parse::<u64>()
+        && timeout > 0
+    {
+        return Duration::from_secs(timeout);
+    }
+
+    server_config()
+        .timeouts
+        .channel_open_secs
+        .map_or(DEFAULT_CHANNEL_OPEN_TIMEOUT, Duration::from_secs)
+}
+
+/// Resolve the hung-channel watchdog threshold with priority: env var ->
+/// config file -> default (300s)
+///
+/// Returns `None` when the watchdog is disabled (`0`).
+pub(crate) fn resolve_channel_idle_timeout() -> Option<Duration> {
+    let secs = env::var(CHANNEL_IDLE_TIMEOUT_ENV_VAR)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .or(server_config().timeouts.channel_idle_secs)
+        .unwrap_or(DEFAULT_CHANNEL_IDLE_TIMEOUT.as_secs());
+
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Resolve the output cap of an async command with priority: parameters -> env
+/// vars -> default (64 MiB per stream, `ring`)
+///
+/// Returns `Ok(None)` when output is unlimited (`0`). An unknown overflow
+/// parameter is an error; an unknown env value falls back to `ring`.
+pub(crate) fn resolve_output_limit(
+    max_bytes_param: Option<u64>,
+    overflow_param: Option<&str>,
+) -> Result<Option<OutputLimit>, String> {
+    let overflow = match overflow_param {
+        Some(value) => OutputOverflow::parse(value)?,
+        None => env::var(OUTPUT_OVERFLOW_ENV_VAR)
+            .ok()
+            .and_then(|value| OutputOverflow::parse(&value).ok())
+            .unwrap_or_default(),
+    };
+    let max_bytes = max_bytes_param
+        .or_else(|| {
+            env::var(MAX_OUTPUT_BYTES_ENV_VAR)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+        })
+        .unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+
+    Ok((max_bytes > 0).then(|| OutputLimit {
+        max_bytes: usize::try_from(max_bytes).unwrap_or(usize::MAX),
+        overflow,
+    }))
+}
+
+/// Resolve the compression setting with priority: parameter -> env var -> default (true)
+pub(crate) fn resolve_compression(compress_param: Option<bool>) -> bool {
+    // Priority 1: Use parameter if provided
+    if let Some(compress) = compress_param {
+        return compress;
+    }
+
+    // Priority 2: Use environment variable if set
+    if let Ok(env_compress) = env::var(COMPRESSION_ENV_VAR) {
+        return env_compress.eq_ignore_ascii_case("true") || env_compress == "1";
+    }
+
+    // Priority 3: Default value (enabled)
+    true
+}
+
+/// Resolve the zlib compression level with priority: parameter -> env var -> none
+///
+/// Environment levels above [`MAX_COMPRESSION_LEVEL`] are clamped; `ssh_connect`
+/// rejects such a parameter before calling this. `Some(0)` disables compression.
+pub(crate) fn resolve_compression_level(level_param: Option<u32>) -> Option<u32> {
+    // Priority 1: Use parameter if provided
+    // Priority 2: Use environment variable if set and valid
+    let level = level_param.or_else(|| {
+        env::var(COMPRESSION_LEVEL_ENV_VAR)
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+    })?;
+
+    Some(level.min(MAX_COMPRESSION_LEVEL))
+}
+
+/// Resolve whether to run the post-connect identity probe: env var -> default (true)
+pub(crate) fn resolve_identity_probe() -> bool {
+    match env::var(IDENTITY_PROBE_ENV_VAR) {
+        Ok(value) => !(value.eq_ignore_ascii_case("false") || value == "0"),
+        Err(_) => true,
+    }
+}
+
+/// Resolve whether a session reconnects by itself with priority: parameter -> env var -> disabled
+pub(crate) fn resolve_auto_reconnect(param: Option<bool>) -> bool {
+    if let Some(enabled) = param {
+        return enabled;
+    }
+    match env::var(AUTO_RECONNECT_ENV_VAR) {
+        Ok(value) => value.eq_ignore_ascii_case("true") || value == "1",
+        Err(_) => false,
+    }
+}
+
+/// Resolve whether a session is read-only
+///
+/// True when `ssh_connect` asks for it or `SSH_READ_ONLY` is set; a session
+/// cannot opt out of the server-wide setting.
+pub(crate) fn resolve_read_only(param: Option<bool>) -> bool {
+    param == Some(true)
+        || env::var(READ_ONLY_ENV_VAR)
+            .is_ok_and(|value| value.eq_ignore_ascii_case("true") || value == "1")
+}
+
+/// Resolve the cap on concurrent sessions in total from `SSH_MAX_SESSIONS`
+///
+/// Returns `None` when unset, invalid or 0 (unlimited).
+pub(crate) fn resolve_max_sessions() -> Option<usize> {
+    session_quota(MAX_SESSIONS_ENV_VAR)
+}
+
+/// Resolve the cap on concurrent sessions per agent from `SSH_MAX_SESSIONS_PER_AGENT`
+///
+/// Returns `None` when unset, invalid or 0 (unlimited).
+pub(crate) fn resolve_max_sessions_per_agent() -> Option<usize> {
+    session_quota(MAX_SESSIONS_PER_AGENT_ENV_VAR)
+}
+
+fn session_quota(env_var: &str) -> Option<usize> {
+    env::var(env_var)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|limit| *limit > 0)
+}
+
+/// Resolve the session cap for a host with priority: per-host config -> env var -> config default
+///
+/// Returns `None` when the host is unlimited.
+pub(crate) fn resolve_max_sessions_per_host(
+    host: &str,
+    limits: &HostLimitsConfig,
+) -> Option<usize> {
+    let env_limit = env::var(MAX_SESSIONS_PER_HOST_ENV_VAR)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok());
+    limits.limit_for(host, env_limit)
+}
+
+/// Resolve the storage backend with priority: env var -> config file -> default (memory)
+///
+/// An unknown env value falls back to the config file; the self-test reports it.
+pub(crate) fn resolve_storage_backend(config_backend: StorageBackend) -> StorageBackend {
+    env::var(STORAGE_BACKEND_ENV_VAR)
+        .ok()
+        .and_then(|value| StorageBackend::parse(&value).ok())
+        .unwrap_or(config_backend)
+}
+
+/// Resolve the tool name prefix with priority: env var -> config file -> none
+///
+/// Prefixes containing characters outside `[A-Za-z0-9_-]` are rejected so the
+/// resulting tool names stay valid MCP identifiers.
+pub(crate) fn resolve_tool_prefix(config_prefix: Option<&str>) -> String {
+    let prefix = env::var(TOOL_PREFIX_ENV_VAR)
+        .ok()
+        .or_else(|| config_prefix.map(str::to_string))
+        .unwrap_or_default();
+
+    if prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        prefix
+    } else {
+        tracing::warn!("Ignoring invalid tool name prefix: {:?}", prefix);
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    // Use a mutex to serialize env var tests to avoid race conditions
+    // SAFETY: Tests are serialized via ENV_TEST_MUTEX to prevent data races
+    static ENV_TEST_MUTEX: once_cell::sync::Lazy<StdMutex<()>> =
+        once_cell::sync::Lazy::new(|| StdMutex::new(()));
+
+    /// Helper to set an environment variable safely within tests.
+    /// SAFETY: Must be called while holding ENV_TEST_MUTEX to prevent data races.
+    unsafe fn set_env(key: &str, value: &str) {
+        // SAFETY: Caller ensures ENV_TEST_MUTEX is held
+        unsafe { env::set_var(key, value) };
+    }
+
+    /// Helper to remove an environment variable safely within tests.
+    /// SAFETY: Must be called while holding ENV_TEST_MUTEX to prevent data races.
+    unsafe fn remove_env(key: &str) {
+        // SAFETY: Caller ensures ENV_TEST_MUTEX is held
+        unsafe { env::remove_var(key) };
+    }
+
+    mod config_resolution {
+        use super::*;
+
+        mod connect_timeout {
+            use super::*;
+
+            #[test]
+            fn test_uses_param_when_provided() {
+                let result = resolve_connect_timeout(Some(60));
+                assert_eq!(result, Duration::from_secs(60));
+            }
+
+            #[test]
+            fn test_param_takes_priority_over_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(CONNECT_TIMEOUT_ENV_VAR, "120");
+                }
+                let result = resolve_connect_timeout(Some(45));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CONNECT_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, Duration::from_secs(45));
+            }
+
+            #[test]
+            fn test_uses_env_var_when_no_param() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(CONNECT_TIMEOUT_ENV_VAR, "90");
+                }
+                let result = resolve_connect_timeout(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CONNECT_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, Duration::from_secs(90));
+            }
+
+            #[test]
+            fn test_uses_default_when_no_param_or_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CONNECT_TIMEOUT_ENV_VAR);
+                }
+                let result = resolve_connect_timeout(None);
+                assert_eq!(result, DEFAULT_CONNECT_TIMEOUT);
+            }
+
+            #[test]
+            fn test_ignores_invalid_env_var() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(CONNECT_TIMEOUT_ENV_VAR, "invalid");
+                }
+                let result = resolve_connect_timeout(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CONNECT_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, DEFAULT_CONNECT_TIMEOUT);
+            }
+
+            #[test]
+            fn test_ignores_negative_env_var() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(CONNECT_TIMEOUT_ENV_VAR, "-10");
+                }
+                let result = resolve_connect_timeout(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CONNECT_TIMEOUT_ENV_VAR);
+                }
+                // Parsing fails for negative u64, so default is used
+                assert_eq!(result, DEFAULT_CONNECT_TIMEOUT);
+            }
+        }
+
+        mod command_timeout {
+            use super::*;
+
+            #[test]
+            fn test_uses_param_when_provided() {
+                let result = resolve_command_timeout(Some(120));
+                assert_eq!(result, Duration::from_secs(120));
+            }
+
+            #[test]
+            fn test_param_takes_priority_over_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMMAND_TIMEOUT_ENV_VAR, "300");
+                }
+                let result = resolve_command_timeout(Some(60));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMMAND_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, Duration::from_secs(60));
+            }
+
+            #[test]
+            fn test_uses_env_var_when_no_param() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMMAND_TIMEOUT_ENV_VAR, "240");
+                }
+                let result = resolve_command_timeout(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMMAND_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, Duration::from_secs(240));
+            }
+
+            #[test]
+            fn test_uses_default_when_no_param_or_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMMAND_TIMEOUT_ENV_VAR);
+                }
+                let result = resolve_command_timeout(None);
+                assert_eq!(result, DEFAULT_COMMAND_TIMEOUT);
+            }
+
+            #[test]
+            fn test_ignores_invalid_env_var() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMMAND_TIMEOUT_ENV_VAR, "not_a_number");
+                }
+                let result = resolve_command_timeout(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMMAND_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, DEFAULT_COMMAND_TIMEOUT);
+            }
+        }
+
+        mod max_retries {
+            use super::*;
+
+            #[test]
+            fn test_uses_param_when_provided() {
+                let result = resolve_max_retries(Some(5));
+                assert_eq!(result, 5);
+            }
+
+            #[test]
+            fn test_param_takes_priority_over_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_RETRIES_ENV_VAR, "10");
+                }
+                let result = resolve_max_retries(Some(2));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_RETRIES_ENV_VAR);
+                }
+                assert_eq!(result, 2);
+            }
+
+            #[test]
+            fn test_uses_env_var_when_no_param() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_RETRIES_ENV_VAR, "7");
+                }
+                let result = resolve_max_retries(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_RETRIES_ENV_VAR);
+                }
+                assert_eq!(result, 7);
+            }
+
+            #[test]
+            fn test_uses_default_when_no_param_or_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_RETRIES_ENV_VAR);
+                }
+                let result = resolve_max_retries(None);
+                assert_eq!(result, DEFAULT_MAX_RETRIES);
+            }
+
+            #[test]
+            fn test_zero_retries_is_valid() {
+                let result = resolve_max_retries(Some(0));
+                assert_eq!(result, 0);
+            }
+
+            #[test]
+            fn test_ignores_invalid_env_var() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_RETRIES_ENV_VAR, "abc");
+                }
+                let result = resolve_max_retries(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_RETRIES_ENV_VAR);
+                }
+                assert_eq!(result, DEFAULT_MAX_RETRIES);
+            }
+        }
+
+        mod retry_delay {
+            use super::*;
+
+            #[test]
+            fn test_uses_param_when_provided() {
+                let result = resolve_retry_delay(Some(2000));
+                assert_eq!(result, Duration::from_millis(2000));
+            }
+
+            #[test]
+            fn test_param_takes_priority_over_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(RETRY_DELAY_MS_ENV_VAR, "5000");
+                }
+                let result = resolve_retry_delay(Some(500));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(RETRY_DELAY_MS_ENV_VAR);
+                }
+                assert_eq!(result, Duration::from_millis(500));
+            }
+
+            #[test]
+            fn test_uses_env_var_when_no_param() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(RETRY_DELAY_MS_ENV_VAR, "3000");
+                }
+                let result = resolve_retry_delay(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(RETRY_DELAY_MS_ENV_VAR);
+                }
+                assert_eq!(result, Duration::from_millis(3000));
+            }
+
+            #[test]
+            fn test_uses_default_when_no_param_or_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(RETRY_DELAY_MS_ENV_VAR);
+                }
+                let result = resolve_retry_delay(None);
+                assert_eq!(result, DEFAULT_RETRY_DELAY);
+            }
+
+            #[test]
+            fn test_ignores_invalid_env_var() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(RETRY_DELAY_MS_ENV_VAR, "xyz");
+                }
+                let result = resolve_retry_delay(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(RETRY_DELAY_MS_ENV_VAR);
+                }
+                assert_eq!(result, DEFAULT_RETRY_DELAY);
+            }
+        }
+
+        mod inactivity_timeout {
+            use super::*;
+
+            #[test]
+            fn test_uses_default_when_no_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(INACTIVITY_TIMEOUT_ENV_VAR);
+                }
+                let result = resolve_inactivity_timeout();
+                assert_eq!(result, DEFAULT_INACTIVITY_TIMEOUT);
+            }
+
+            #[test]
+            fn test_default_is_300_seconds() {
+                assert_eq!(DEFAULT_INACTIVITY_TIMEOUT, Duration::from_secs(300));
+            }
+
+            #[test]
+            fn test_uses_env_var() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(INACTIVITY_TIMEOUT_ENV_VAR, "600");
+                }
+                let result = resolve_inactivity_timeout();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(INACTIVITY_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, Duration::from_secs(600));
+            }
+
+            #[test]
+            fn test_ignores_invalid_env_var() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(INACTIVITY_TIMEOUT_ENV_VAR, "invalid");
+                }
+                let result = resolve_inactivity_timeout();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(INACTIVITY_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(result, DEFAULT_INACTIVITY_TIMEOUT);
+            }
+        }
+
+        mod compression {
+            use super::*;
+
+            #[test]
+            fn test_uses_param_true_when_provided() {
+                let result = resolve_compression(Some(true));
+                assert!(result);
+            }
+
+            #[test]
+            fn test_uses_param_false_when_provided() {
+                let result = resolve_compression(Some(false));
+                assert!(!result);
+            }
+
+            #[test]
+            fn test_param_takes_priority_over_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "true");
+                }
+                let result = resolve_compression(Some(false));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                assert!(!result);
+            }
+
+            #[test]
+            fn test_env_var_true_lowercase() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "true");
+                }
+                let result = resolve_compression(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                assert!(result);
+            }
+
+            #[test]
+            fn test_env_var_true_uppercase() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "TRUE");
+                }
+                let result = resolve_compression(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                assert!(result);
+            }
+
+            #[test]
+            fn test_env_var_true_mixed_case() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "TrUe");
+                }
+                let result = resolve_compression(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                assert!(result);
+            }
+
+            #[test]
+            fn test_env_var_one() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "1");
+                }
+                let result = resolve_compression(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                assert!(result);
+            }
+
+            #[test]
+            fn test_env_var_false_lowercase() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "false");
+                }
+                let result = resolve_compression(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                assert!(!result);
+            }
+
+            #[test]
+            fn test_env_var_zero() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "0");
+                }
+                let result = resolve_compression(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                assert!(!result);
+            }
+
+            #[test]
+            fn test_env_var_random_value_is_false() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_ENV_VAR, "yes");
+                }
+                let result = resolve_compression(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                // "yes" is not "true" or "1", so it's false
+                assert!(!result);
+            }
+
+            #[test]
+            fn test_default_is_true() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_ENV_VAR);
+                }
+                let result = resolve_compression(None);
+                assert!(result);
+            }
+        }
+
+        mod compression_level {
+            use super::*;
+
+            #[test]
+            fn test_uses_param_when_provided() {
+                assert_eq!(resolve_compression_level(Some(0)), Some(0));
+            }
+
+            #[test]
+            fn test_clamps_to_max() {
+                assert_eq!(
+                    resolve_compression_level(Some(42)),
+                    Some(MAX_COMPRESSION_LEVEL)
+                );
+            }
+
+            #[test]
+            fn test_env_fallback() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_LEVEL_ENV_VAR, "0");
+                }
+                let from_env = resolve_compression_level(None);
+                let from_param = resolve_compression_level(Some(1));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_LEVEL_ENV_VAR);
+                }
+                assert_eq!(from_env, Some(0));
+                assert_eq!(from_param, Some(1));
+            }
+
+            #[test]
+            fn test_invalid_env_is_ignored() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(COMPRESSION_LEVEL_ENV_VAR, "fast");
+                }
+                let result = resolve_compression_level(None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(COMPRESSION_LEVEL_ENV_VAR);
+                }
+                assert_eq!(result, None);
+            }
+        }
+
+        mod keepalive {
+            use super::*;
+
+            #[test]
+            fn test_params_take_priority_over_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(KEEPALIVE_INTERVAL_ENV_VAR, "10");
+                    set_env(KEEPALIVE_MAX_ENV_VAR, "8");
+                }
+                let from_env = resolve_keepalive(None, None);
+                let from_params = resolve_keepalive(Some(0), Some(1));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(KEEPALIVE_INTERVAL_ENV_VAR);
+                    remove_env(KEEPALIVE_MAX_ENV_VAR);
+                }
+                assert_eq!(from_env.interval(), Some(Duration::from_secs(10)));
+                assert_eq!(from_env.max, 8);
+                assert_eq!(from_params.interval(), None);
+                assert_eq!(from_params.max, 1);
+            }
+
+            #[test]
+            fn test_defaults_and_invalid_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(KEEPALIVE_INTERVAL_ENV_VAR, "often");
+                    remove_env(KEEPALIVE_MAX_ENV_VAR);
+                }
+                let result = resolve_keepalive(None, None);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(KEEPALIVE_INTERVAL_ENV_VAR);
+                }
+                assert_eq!(result.interval(), Some(DEFAULT_KEEPALIVE_INTERVAL));
+                assert_eq!(result.max, DEFAULT_KEEPALIVE_MAX);
+            }
+        }
+
+        mod identity_probe {
+            use super::*;
+
+            #[test]
+            fn test_default_enabled() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(IDENTITY_PROBE_ENV_VAR);
+                }
+                assert!(resolve_identity_probe());
+            }
+
+            #[test]
+            fn test_env_disables() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                for value in ["false", "FALSE", "0"] {
+                    // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                    unsafe {
+                        set_env(IDENTITY_PROBE_ENV_VAR, value);
+                    }
+                    let result = resolve_identity_probe();
+                    // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                    unsafe {
+                        remove_env(IDENTITY_PROBE_ENV_VAR);
+                    }
+                    assert!(!result, "{} should disable the probe", value);
+                }
+            }
+
+            #[test]
+            fn test_env_true_keeps_enabled() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(IDENTITY_PROBE_ENV_VAR, "true");
+                }
+                let result = resolve_identity_probe();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(IDENTITY_PROBE_ENV_VAR);
+                }
+                assert!(result);
+            }
+        }
+
+        mod auto_reconnect {
+            use super::*;
+
+            #[test]
+            fn test_default_disabled() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(AUTO_RECONNECT_ENV_VAR);
+                }
+                assert!(!resolve_auto_reconnect(None));
+                assert!(resolve_auto_reconnect(Some(true)));
+            }
+
+            #[test]
+            fn test_env_enables_unless_param_disables() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(AUTO_RECONNECT_ENV_VAR, "1");
+                }
+                let (default, explicit) = (
+                    resolve_auto_reconnect(None),
+                    resolve_auto_reconnect(Some(false)),
+                );
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(AUTO_RECONNECT_ENV_VAR);
+                }
+                assert!(default);
+                assert!(!explicit);
+            }
+        }
+
+        mod read_only {
+            use super::*;
+
+            #[test]
+            fn test_default_disabled() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(READ_ONLY_ENV_VAR);
+                }
+                assert!(!resolve_read_only(None));
+                assert!(resolve_read_only(Some(true)));
+            }
+
+            #[test]
+            fn test_env_cannot_be_lifted_per_session() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(READ_ONLY_ENV_VAR, "true");
+                }
+                let (default, explicit) = (resolve_read_only(None), resolve_read_only(Some(false)));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(READ_ONLY_ENV_VAR);
+                }
+                assert!(default);
+                assert!(explicit);
+            }
+        }
+
+        mod channel_timeouts {
+            use super::*;
+
+            #[test]
+            fn test_defaults() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CHANNEL_OPEN_TIMEOUT_ENV_VAR);
+                    remove_env(CHANNEL_IDLE_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(resolve_channel_open_timeout(), DEFAULT_CHANNEL_OPEN_TIMEOUT);
+                assert_eq!(
+                    resolve_channel_idle_timeout(),
+                    Some(DEFAULT_CHANNEL_IDLE_TIMEOUT)
+                );
+            }
+
+            #[test]
+            fn test_env_overrides() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(CHANNEL_OPEN_TIMEOUT_ENV_VAR, "5");
+                    set_env(CHANNEL_IDLE_TIMEOUT_ENV_VAR, "60");
+                }
+                let open = resolve_channel_open_timeout();
+                let idle = resolve_channel_idle_timeout();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CHANNEL_OPEN_TIMEOUT_ENV_VAR);
+                    remove_env(CHANNEL_IDLE_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(open, Duration::from_secs(5));
+                assert_eq!(idle, Some(Duration::from_secs(60)));
+            }
+
+            #[test]
+            fn test_zero_disables_watchdog_but_not_open_timeout() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(CHANNEL_OPEN_TIMEOUT_ENV_VAR, "0");
+                    set_env(CHANNEL_IDLE_TIMEOUT_ENV_VAR, "0");
+                }
+                let open = resolve_channel_open_timeout();
+                let idle = resolve_channel_idle_timeout();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(CHANNEL_OPEN_TIMEOUT_ENV_VAR);
+                    remove_env(CHANNEL_IDLE_TIMEOUT_ENV_VAR);
+                }
+                assert_eq!(open, DEFAULT_CHANNEL_OPEN_TIMEOUT);
+                assert_eq!(idle, None);
+            }
+        }
+
+        mod output_limit {
+            use super::*;
+
+            #[test]
+            fn test_default_is_ring_of_64_mib() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_OUTPUT_BYTES_ENV_VAR);
+                    remove_env(OUTPUT_OVERFLOW_ENV_VAR);
+                }
+                let limit = resolve_output_limit(None, None).unwrap().unwrap();
+                assert_eq!(limit.max_bytes as u64, DEFAULT_MAX_OUTPUT_BYTES);
+                assert_eq!(limit.overflow, OutputOverflow::Ring);
+            }
+
+            #[test]
+            fn test_params_take_priority_over_env() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_OUTPUT_BYTES_ENV_VAR, "1024");
+                    set_env(OUTPUT_OVERFLOW_ENV_VAR, "head");
+                }
+                let from_env = resolve_output_limit(None, None);
+                let from_params = resolve_output_limit(Some(2048), Some("ring"));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_OUTPUT_BYTES_ENV_VAR);
+                    remove_env(OUTPUT_OVERFLOW_ENV_VAR);
+                }
+                assert_eq!(
+                    from_env,
+                    Ok(Some(OutputLimit {
+                        max_bytes: 1024,
+                        overflow: OutputOverflow::Head
+                    }))
+                );
+                assert_eq!(
+                    from_params,
+                    Ok(Some(OutputLimit {
+                        max_bytes: 2048,
+                        overflow: OutputOverflow::Ring
+                    }))
+                );
+            }
+
+            #[test]
+            fn test_zero_is_unlimited_and_bad_mode_rejected() {
+                assert_eq!(resolve_output_limit(Some(0), Some("head")), Ok(None));
+                assert!(resolve_output_limit(Some(10), Some("middle")).is_err());
+            }
+        }
+
+        mod max_sessions_per_host {
+            use super::*;
+
+            #[test]
+            fn test_unlimited_by_default() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_SESSIONS_PER_HOST_ENV_VAR);
+                }
+                let result = resolve_max_sessions_per_host("web", &HostLimitsConfig::default());
+                assert_eq!(result, None);
+            }
+
+            #[test]
+            fn test_env_applies_to_every_host() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_SESSIONS_PER_HOST_ENV_VAR, "3");
+                }
+                let result = resolve_max_sessions_per_host("web", &HostLimitsConfig::default());
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_SESSIONS_PER_HOST_ENV_VAR);
+                }
+                assert_eq!(result, Some(3));
+            }
+
+            #[test]
+            fn test_invalid_env_is_ignored() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_SESSIONS_PER_HOST_ENV_VAR, "many");
+                }
+                let result = resolve_max_sessions_per_host("web", &HostLimitsConfig::default());
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_SESSIONS_PER_HOST_ENV_VAR);
+                }
+                assert_eq!(result, None);
+            }
+        }
+
+        mod session_quotas {
+            use super::*;
+
+            #[test]
+            fn test_unlimited_by_default() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_SESSIONS_ENV_VAR);
+                    remove_env(MAX_SESSIONS_PER_AGENT_ENV_VAR);
+                }
+                assert_eq!(resolve_max_sessions(), None);
+                assert_eq!(resolve_max_sessions_per_agent(), None);
+            }
+
+            #[test]
+            fn test_env_values() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_SESSIONS_ENV_VAR, "200");
+                    set_env(MAX_SESSIONS_PER_AGENT_ENV_VAR, "0");
+                }
+                let (total, per_agent) = (resolve_max_sessions(), resolve_max_sessions_per_agent());
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(MAX_SESSIONS_ENV_VAR, "lots");
+                }
+                let invalid = resolve_max_sessions();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(MAX_SESSIONS_ENV_VAR);
+                    remove_env(MAX_SESSIONS_PER_AGENT_ENV_VAR);
+                }
+                assert_eq!(total, Some(200));
+                assert_eq!(per_agent, None);
+                assert_eq!(invalid, None);
+            }
+        }
+
+        mod tool_prefix {
+            use super::*;
+
+            #[test]
+            fn test_default_is_empty() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(TOOL_PREFIX_ENV_VAR);
+                }
+                assert_eq!(resolve_tool_prefix(None), "");
+            }
+
+            #[test]
+            fn test_uses_config_value() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(TOOL_PREFIX_ENV_VAR);
+                }
+                assert_eq!(resolve_tool_prefix(Some("prod")), "prod");
+            }
+
+            #[test]
+            fn test_env_takes_priority_over_config() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(TOOL_PREFIX_ENV_VAR, "staging_");
+                }
+                let result = resolve_tool_prefix(Some("prod"));
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(TOOL_PREFIX_ENV_VAR);
+                }
+                assert_eq!(result, "staging_");
+            }
+
+            #[test]
+            fn test_invalid_prefix_is_ignored() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(TOOL_PREFIX_ENV_VAR);
+                }
+                assert_eq!(resolve_tool_prefix(Some("prod ssh.")), "");
+            }
+        }
+
+        mod storage_backend {
+            use super::*;
+
+            #[test]
+            fn test_env_value_and_fallback() {
+                let _guard = ENV_TEST_MUTEX.lock().unwrap();
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(STORAGE_BACKEND_ENV_VAR, "Memory");
+                }
+                let parsed = resolve_storage_backend(StorageBackend::default());
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    set_env(STORAGE_BACKEND_ENV_VAR, "sled");
+                }
+                let unknown = resolve_storage_backend(StorageBackend::Memory);
+                // SAFETY: Holding ENV_TEST_MUTEX, no concurrent env access
+                unsafe {
+                    remove_env(STORAGE_BACKEND_ENV_VAR);
+                }
+                assert_eq!(parsed, StorageBackend::Memory);
+                assert_eq!(unknown, StorageBackend::Memory);
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! OpenSSH client configuration (`~/.ssh/config`) lookup.
+//!
+//! With `use_ssh_config=true`, `ssh_connect` treats the host part of its
+//! address as a `Host` alias and resolves it like `ssh` does:
+//!
+//! - `HostName` replaces the alias (`%h` expands to the alias)
+//! - `Port` applies unless the address names a port
+//! - `User` applies unless `username` is given
+//! - `IdentityFile` is used when neither `key_path` nor `key_data` is given
+//!   (the first listed file that exists)
+//! - `ProxyJump` fills `jump_hosts` when none are given; every hop is looked
+//!   up as an alias too
+//!
+//! The file is `SSH_CONFIG_FILE`, else `~/.ssh/config`. As in OpenSSH the
+//! first value obtained for an option wins, so specific `Host` blocks belong
+//! before `Host *`. `Match` blocks (other than `Match all`) and `Include` are
+//! not evaluated; other options are ignored.
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::mcp::transfer::wildcard_match;
+use crate::mcp::types::JumpHost;
+
+/// ssh_config file used by `use_ssh_config` (default: `~/.ssh/config`)
+pub(crate) const SSH_CONFIG_ENV_VAR: &str = "SSH_CONFIG_FILE";
+
+/// Options resolved for one host alias.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SshConfigHost {
+    pub host_name: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub identity_files: Vec<String>,
+    pub proxy_jump: Option<String>,
+}
+
+/// One `Host` (or `Match all`) block.
+#[derive(Debug)]
+struct Block {
+    /// Lower-cased patterns with their negation flag; empty for the leading
+    /// global block
+    patterns: Vec<(String, bool)>,
+    options: Vec<(String, String)>,
+}
+
+impl Block {
+    fn matches(&self, alias: &str) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+        // Host patterns match case-insensitively, like OpenSSH
+        let alias = alias.to_ascii_lowercase();
+        let mut matched = false;
+        for (pattern, negated) in &self.patterns {
+            if wildcard_match(pattern, &alias) {
+                if *negated {
+                    return false;
+                }
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+/// Parsed ssh_config file; the default has no hosts.
+#[derive(Debug, Default)]
+pub(crate) struct SshConfig {
+    blocks: Vec<Block>,
+}
+
+/// Split a line into keyword and value (`Key value` or `Key=value`).
+fn split_option(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let (keyword, rest) = line.split_at(end);
+    let value = rest
+        .trim_start()
+        .strip_prefix('=')
+        .unwrap_or(rest)
+        .trim()
+        .trim_matches('"');
+    (!value.is_empty()).then(|| (keyword.to_ascii_lowercase(), value.to_string()))
+}
+
+/// Expand a leading `~/` to the home directory.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), env::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => path.to_string(),
+    }
+}
+
+impl SshConfig {
+    /// Parse ssh_config text.
+    pub(crate) fn parse(text: &str) -> Self {
+        let mut blocks = vec![Block {
+            patterns: Vec::new(),
+            options: Vec::new(),
+        }];
+        // Options of a `Match` block we cannot evaluate are skipped
+        let mut skipping = false;
+        for line in text.lines() {
+            let Some((keyword, value)) = split_option(line) else {
+                continue;
+            };
+            match keyword.as_str() {
+                "host" => {
+                    skipping = false;
+                    let patterns = value
+                        .to_ascii_lowercase()
+                        .split_whitespace()
+                        .map(|pattern| match pattern.strip_prefix('!') {
+                            Some(negated) => (negated.to_string(), true),
+                            None => (pattern.to_string(), false),
+                        })
+                        .collect();
+                    blocks.push(Block {
+                        patterns,
+                        options: Vec::new(),
+                    });
+                }
+                "match" => {
+                    skipping = !value.eq_ignore_ascii_case("all");
+                    if !skipping {
+                        blocks.push(Block {
+                            patterns: vec![("*".to_string(), false)],
+                            options: Vec::new(),
+                        });
+                    }
+                }
+                _ if skipping => {}
+                _ => {
+                    if let Some(block) = blocks.last_mut() {
+                        block.options.push((keyword, value));
+                    }
+                }
+            }
+        }
+        Self { blocks }
+    }
+
+    /// Load [`resolve_ssh_config_path`].
+    pub(crate) fn load() -> Result<Self, String> {
+        let path = resolve_ssh_config_path()
+            .ok_or_else(|| "No ssh_config file: HOME is not set".to_string())?;
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read ssh_config {}: {}", path.display(), e))?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Options that apply to `alias`; the first value of each option wins.
+    pub(crate) fn lookup(&self, alias: &str) -> SshConfigHost {
+        let mut host = SshConfigHost::default();
+        for block in self.blocks.iter().filter(|block| block.matches(alias)) {
+            for (keyword, value) in &block.options {
+                match keyword.as_str() {
+                    "hostname" if host.host_name.is_none() => {
+                        host.host_name = Some(value.replace("%h", alias));
+                    }
+                    "port" if host.port.is_none() => host.port = value.parse().ok(),
+                    "user" if host.user.is_none() => host.user = Some(value.clone()),
+                    "identityfile" => {
+                        let path = expand_home(value);
+                        if !host.identity_files.contains(&path) {
+                            host.identity_files.push(path);
+                        }
+                    }
+                    "proxyjump" if host.proxy_jump.is_none() => {
+                        host.proxy_jump = Some(value.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        host
+    }
+
+    /// Resolve an `ssh_connect` address (`alias` or `alias:port`) to
+    /// `host:port`, with the alias' options.
+    pub(crate) fn resolve_address(&self, address: &str) -> (String, SshConfigHost) {
+        let (alias, port) = split_alias(address);
+        let host = self.lookup(alias);
+        let host_name = host.host_name.as_deref().unwrap_or(alias);
+        let port = port.or(host.port).unwrap_or(22);
+        (join_host_port(host_name, port), host)
+    }
+
+    /// Jump hosts of a `ProxyJump` value (`[user@]host[:port],...`; `none`
+    /// for none), each resolved as an alias.
+    pub(crate) fn jump_hosts(&self, proxy_jump: &str) -> Vec<JumpHost> {
+        if proxy_jump.eq_ignore_ascii_case("none") {
+            return Vec::new();
+        }
+        proxy_jump
+            .split(',')
+            .map(str::trim)
+            .filter(|hop| !hop.is_empty())
+            .map(|hop| {
+                let (user, address) = match hop.rsplit_once('@') {
+                    Some((user, address)) => (Some(user.to_string()), address),
+                    None => (None, hop),
+                };
+                let (address, options) = self.resolve_address(address);
+                JumpHost {
+                    address,
+                    username: user.or(options.user.clone()),
+                    password: None,
+                    key_path: existing_identity(&options),
+                    key_data: None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Split `alias[:port]` (IPv6 literals in brackets) into alias and port.
+fn split_alias(address: &str) -> (&str, Option<u16>) {
+    if let Some(rest) = address.strip_prefix('[')
+        && let Some((host, tail)) = rest.split_once(']')
+    {
+        return (host, tail.strip_prefix(':').and_then(|p| p.parse().ok()));
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => (host, port.parse().ok()),
+        _ => (address, None),
+    }
+}
+
+fn join_host_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// The first `IdentityFile` of `host` that exists.
+pub(crate) fn existing_identity(host: &SshConfigHost) -> Option<String> {
+    host.identity_files
+        .iter()
+        .find(|path| std::path::Path::new(path).is_file())
+        .cloned()
+}
+
+/// Resolve the ssh_config path: `SSH_CONFIG_FILE`, else `~/.ssh/config`.
+pub(crate) fn resolve_ssh_config_path() -> Option<PathBuf> {
+    env::var_os(SSH_CONFIG_ENV_VAR)
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".ssh").join("config")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+# Global defaults
+ServerAliveInterval 30
+
+Host web-* !web-legacy
+    HostName %h.internal.example.com
+    User deploy
+    IdentityFile /nonexistent/web_key
+
+Host db
+    HostName=10.0.2.15
+    Port 2222
+    ProxyJump ops@bastion,jump2:2200
+
+Host bastion
+    HostName bastion.example.com
+    User ops
+
+Match host secret exec \"true\"
+    User nobody
+
+Host *
+    User fallback
+    Port 22
+";
+
+    #[test]
+    fn test_host_patterns_ignore_case() {
+        let config = SshConfig::parse("Host WEB-?1 *.Example.*\n    User deploy\n");
+        assert_eq!(config.lookup("web-01").user.as_deref(), Some("deploy"));
+        assert_eq!(
+            config.lookup("A.EXAMPLE.com").user.as_deref(),
+            Some("deploy")
+        );
+        assert_eq!(config.lookup("web-02").user, None);
+        assert_eq!(config.lookup("db").user, None);
+    }
+
+    #[test]
+    fn test_first_value_wins_and_negation() {
+        let config = SshConfig::parse(CONFIG);
+        let web = config.lookup("web-01");
+        assert_eq!(
+            web.host_name.as_deref(),
+            Some("web-01.internal.example.com")
+        );
+        assert_eq!(web.user.as_deref(), Some("deploy"));
+        assert_eq!(web.identity_files, vec!["/nonexistent/web_key"]);
+
+        let legacy = config.lookup("web-legacy");
+        assert_eq!(legacy.host_name, None);
+        assert_eq!(legacy.user.as_deref(), Some("fallback"));
+    }
+
+    #[test]
+    fn test_match_blocks_are_skipped() {
+        let config = SshConfig::parse(CONFIG);
+        assert_eq!(config.lookup("secret").user.as_deref(), Some("fallback"));
+    }
+
+    #[test]
+    fn test_resolve_address() {
+        let config = SshConfig::parse(CONFIG);
+        assert_eq!(config.resolve_address("db").0, "10.0.2.15:2222");
+        assert_eq!(config.resolve_address("db:2200").0, "10.0.2.15:2200");
+        assert_eq!(config.resolve_address("unlisted").0, "unlisted:22");
+        assert_eq!(
+            SshConfig::parse("Host v6\n HostName ::1\n")
+                .resolve_address("v6")
+                .0,
+            "[::1]:22"
+        );
+    }
+
+    #[test]
+    fn test_proxy_jump_hops_are_resolved() {
+        let config = SshConfig::parse(CONFIG);
+        let db = config.lookup("db");
+        let hops = config.jump_hosts(db.proxy_jump.as_deref().unwrap());
+        assert_eq!(hops.len(), 2);
+        assert_eq!(hops[0].address, "bastion.example.com:22");
+        assert_eq!(hops[0].username.as_deref(), Some("ops"));
+        assert_eq!(hops[1].address, "jump2:2200");
+        assert_eq!(hops[1].username.as_deref(), Some("fallback"));
+        assert!(config.jump_hosts("none").is_empty());
+    }
+
+    #[test]
+    fn test_missing_identity_files_are_skipped() {
+        let host = SshConfigHost {
+            identity_files: vec!["/nonexistent/a".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(existing_identity(&host), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Serial-over-LAN / BMC console presets.
+//!
+//! `ssh_open_console` opens a shell with serial-console friendly defaults
+//! (`vt100`, 80x24) and starts the vendor console command, so agents do not
+//! have to assemble the SOL flow by hand. Each preset also carries the key
+//! sequence that leaves the console without killing the SSH session.
+
+/// Console preset used when none is requested
+pub(crate) const DEFAULT_CONSOLE: &str = "ipmitool";
+
+/// Terminal type used for serial consoles
+pub(crate) const CONSOLE_TERM: &str = "vt100";
+
+/// Serial console width
+pub(crate) const CONSOLE_COLS: u32 = 80;
+
+/// Serial console height
+pub(crate) const CONSOLE_ROWS: u32 = 24;
+
+/// How to start and leave a vendor console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ConsolePreset {
+    /// Preset name accepted by the `console` parameter
+    pub name: &'static str,
+    /// Command that attaches to the serial console (`None`: already a console)
+    pub command: Option<&'static str>,
+    /// Input that leaves the console, ready for `ssh_shell_write`
+    pub exit_sequence: Option<&'static str>,
+    /// Human-readable form of the exit sequence
+    pub exit_hint: &'static str,
+}
+
+/// Known console presets.
+pub(crate) const CONSOLE_PRESETS: &[ConsolePreset] = &[
+    // Host with ipmitool and a local BMC (or a jump host, with a custom command)
+    ConsolePreset {
+        name: "ipmitool",
+        command: Some("ipmitool sol activate"),
+        exit_sequence: Some("\n~."),
+        exit_hint: "~. at the start of a line",
+    },
+    // Dell iDRAC SSH CLI
+    ConsolePreset {
+        name: "idrac",
+        command: Some("console com2"),
+        exit_sequence: Some("\x1c"),
+        exit_hint: "Ctrl+\\",
+    },
+    // HPE iLO SSH CLI (virtual serial port)
+    ConsolePreset {
+        name: "ilo",
+        command: Some("vsp"),
+        exit_sequence: Some("\x1b("),
+        exit_hint: "ESC (",
+    },
+    // Lenovo XClarity Controller SSH CLI
+    ConsolePreset {
+        name: "xcc",
+        command: Some("console 1"),
+        exit_sequence: Some("\x1b("),
+        exit_hint: "ESC (",
+    },
+    // SMASH-CLP BMCs (Supermicro and others)
+    ConsolePreset {
+        name: "smash",
+        command: Some("start /system1/sol1"),
+        exit_sequence: Some("\x1b("),
+        exit_hint: "ESC (",
+    },
+    // Console servers and hosts whose login shell already is the serial console
+    ConsolePreset {
+        name: "raw",
+        command: None,
+        exit_sequence: None,
+        exit_hint: "close the shell with ssh_shell_close",
+    },
+];
+
+/// Look up a console preset by name (default: ipmitool).
+pub(crate) fn console_preset(name: Option<&str>) -> Result<&'static ConsolePreset, String> {
+    let name = name.unwrap_or(DEFAULT_CONSOLE);
+    CONSOLE_PRESETS
+        .iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = CONSOLE_PRESETS.iter().map(|preset| preset.name).collect();
+            format!(
+                "console must be one of {}, got \"{}\"",
+                names.join(", "),
+                name
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod presets {
+        use super::*;
+
+        #[test]
+        fn test_default_is_ipmitool() {
+            let preset = console_preset(None).unwrap();
+            assert_eq!(preset.name, "ipmitool");
+            assert_eq!(preset.command, Some("ipmitool sol activate"));
+            assert_eq!(preset.exit_sequence, Some("\n~."));
+        }
+
+        #[test]
+        fn test_vendor_lookup() {
+            assert_eq!(
+                console_preset(Some("idrac")).unwrap().command,
+                Some("console com2")
+            );
+            assert_eq!(console_preset(Some("ilo")).unwrap().command, Some("vsp"));
+        }
+
+        #[test]
+        fn test_raw_has_no_command() {
+            let preset = console_preset(Some("raw")).unwrap();
+            assert!(preset.command.is_none());
+            assert!(preset.exit_sequence.is_none());
+        }
+
+        #[test]
+        fn test_unknown_preset_lists_choices() {
+            let err = console_preset(Some("drac5")).unwrap_err();
+            assert!(err.contains("ipmitool, idrac, ilo, xcc, smash, raw"));
+        }
+
+        #[test]
+        fn test_names_are_unique() {
+            for (i, preset) in CONSOLE_PRESETS.iter().enumerate() {
+                assert!(
+                    CONSOLE_PRESETS[i + 1..]
+                        .iter()
+                        .all(|other| other.name != preset.name)
+                );
+            }
+        }
+    }
+}
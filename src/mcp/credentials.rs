@@ -0,0 +1,422 @@
+//! Named credentials resolved on the server for `ssh_connect(credential_ref=...)`.
+//!
+//! Operators declare credentials under `[credentials.<name>]` so agents pass a
+//! name instead of passwords or keys, and secrets never travel through the
+//! conversation:
+//!
+//! ```toml
+//! # Secrets from environment variables of the server process
+//! [credentials.prod-db]
+//! username = "postgres"
+//! password_env = "PROD_DB_PASSWORD"
+//! hosts = ["db1.internal", "db2.internal"]
+//!
+//! # Secrets from HashiCorp Vault, read on every connect
+//! [credentials.bastion]
+//! source = "vault"
+//! path = "secret/data/ssh/bastion"
+//! hosts = ["bastion.example.com"]
+//! agents = ["deploy-bot"]
+//!
+//! [vault]
+//! address = "http://127.0.0.1:8200"
+//! ```
+//!
+//! Every credential names the `hosts` it may be sent to: password
+//! authentication hands the secret to whatever sshd answers, so a connection
+//! whose target or any jump host is not listed is refused before the secrets
+//! are read. `agents`, when given, further limits which agents may use it.
+//!
+//! A Vault secret (KV v1, or KV v2 with its `data.data` nesting) may hold
+//! `username`, `password`, `private_key` (key content) and `key_path` fields.
+//! There is no HTTPS client here: only `http://` addresses on the loopback
+//! interface are accepted, since the token and secrets travel in clear. Vault
+//! credentials therefore require a Vault Agent (or proxy) sidecar on the same
+//! host, which handles authentication and TLS to the Vault servers.
+//! The token comes from `token_file`, else `VAULT_TOKEN`, and is optional so an
+//! agent can inject its own.
+
+use std::env;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::config::{CredentialConfig, CredentialSource, VaultConfig, server_config};
+use super::error::{ErrorCode, ToolError};
+use super::host_limit::host_key;
+use super::siem::{HttpTarget, get_http, parse_http_url};
+
+/// Environment variable name for the Vault address
+pub(crate) const VAULT_ADDR_ENV_VAR: &str = "VAULT_ADDR";
+
+/// Environment variable name for the Vault token
+pub(crate) const VAULT_TOKEN_ENV_VAR: &str = "VAULT_TOKEN";
+
+/// Environment variable name for the Vault Enterprise namespace
+pub(crate) const VAULT_NAMESPACE_ENV_VAR: &str = "VAULT_NAMESPACE";
+
+/// Timeout for one Vault request
+const VAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Secrets of a named credential.
+///
+/// Not `Debug`: it carries secrets.
+#[derive(Clone, Default)]
+pub(crate) struct Credential {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub key_path: Option<String>,
+    pub key_data: Option<String>,
+}
+
+/// Fields read from a Vault secret; others are ignored.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct VaultSecret {
+    username: Option<String>,
+    password: Option<String>,
+    private_key: Option<String>,
+    key_path: Option<String>,
+}
+
+/// Names of the configured credentials, sorted.
+pub(crate) fn credential_names() -> Vec<String> {
+    server_config().credentials.keys().cloned().collect()
+}
+
+/// Fetch the secrets of the credential called `name` for a connection by
+/// `agent_id` to `targets` (the address and every jump host).
+pub(crate) async fn resolve_credential(
+    name: &str,
+    agent_id: Option<&str>,
+    targets: &[&str],
+) -> Result<Credential, ToolError> {
+    let config = server_config();
+    let Some(entry) = config.credentials.get(name) else {
+        return Err(ToolError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unknown credential_ref '{}'", name),
+        )
+        .with_detail("credential_ref", name)
+        .with_detail("available", credential_names()));
+    };
+    check_scope(name, entry, agent_id, targets)?;
+    let credential = match entry.source {
+        CredentialSource::Env => from_env(entry),
+        CredentialSource::Vault => from_vault(entry, &config.vault).await,
+    };
+    credential.map_err(|e| {
+        ToolError::new(
+            ErrorCode::InvalidState,
+            format!("Credential '{}' is unavailable: {}", name, e),
+        )
+        .with_detail("credential_ref", name)
+    })
+}
+
+/// Refuse a credential for an agent or host it is not configured for.
+fn check_scope(
+    name: &str,
+    entry: &CredentialConfig,
+    agent_id: Option<&str>,
+    targets: &[&str],
+) -> Result<(), ToolError> {
+    if !entry.allows_agent(agent_id) {
+        return Err(ToolError::new(
+            ErrorCode::AccessDenied,
+            format!(
+                "Credential '{}' is not available to agent '{}'",
+                name,
+                agent_id.unwrap_or("")
+            ),
+        )
+        .with_detail("credential_ref", name));
+    }
+    for target in targets {
+        let host = host_key(target);
+        if !entry.allows_host(&host) {
+            return Err(ToolError::new(
+                ErrorCode::AccessDenied,
+                format!(
+                    "Credential '{}' may not be sent to '{}': the host is not in its hosts list",
+                    name, host
+                ),
+            )
+            .with_detail("credential_ref", name)
+            .with_detail("host", host));
+        }
+    }
+    Ok(())
+}
+
+/// Read an optional environment variable named by the configuration.
+fn read_env(var: Option<&str>) -> Result<Option<String>, String> {
+    var.map(|var| env::var(var).map_err(|_| format!("environment variable {} is not set", var)))
+        .transpose()
+}
+
+fn from_env(entry: &CredentialConfig) -> Result<Credential, String> {
+    Ok(Credential {
+        username: entry.username.clone(),
+        password: read_env(entry.password_env.as_deref())?,
+        key_path: entry.key_path.clone(),
+        key_data: read_env(entry.key_data_env.as_deref())?,
+    })
+}
+
+/// The Vault token: `token_file`, else `VAULT_TOKEN`.
+fn vault_token(vault: &VaultConfig) -> Result<Option<String>, String> {
+    match &vault.token_file {
+        Some(path) => std::fs::read_to_string(path)
+            .map(|token| Some(token.trim().to_string()))
+            .map_err(|e| format!("cannot read Vault token file {}: {}", path, e)),
+        None => Ok(env::var(VAULT_TOKEN_ENV_VAR).ok()),
+    }
+}
+
+async fn from_vault(entry: &CredentialConfig, vault: &VaultConfig) -> Result<Credential, String> {
+    let path = entry
+        .path
+        .as_deref()
+        .ok_or("vault credentials need a 'path'")?;
+    let address = vault
+        .address
+        .clone()
+        .or_else(|| env::var(VAULT_ADDR_ENV_VAR).ok())
+        .ok_or("no Vault address: set [vault] address or VAULT_ADDR")?;
+    let mut target = parse_vault_url(&format!(
+        "{}/v1/{}",
+        address.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    ))?;
+    if let Some(token) = vault_token(vault)? {
+        target.headers.insert("X-Vault-Token".to_string(), token);
+    }
+    if let Some(namespace) = vault
+        .namespace
+        .clone()
+        .or_else(|| env::var(VAULT_NAMESPACE_ENV_VAR).ok())
+    {
+        target
+            .headers
+            .insert("X-Vault-Namespace".to_string(), namespace);
+    }
+
+    let body = tokio::time::timeout(VAULT_TIMEOUT, get_http(&target))
+        .await
+        .map_err(|_| format!("Vault did not answer within {}s", VAULT_TIMEOUT.as_secs()))?
+        .map_err(|e| format!("Vault request for {} failed: {}", path, e))?;
+    let secret = parse_secret(&body)?;
+    Ok(Credential {
+        username: entry.username.clone().or(secret.username),
+        password: secret.password,
+        key_path: entry.key_path.clone().or(secret.key_path),
+        key_data: secret.private_key,
+    })
+}
+
+/// Parse a Vault URL, refusing hosts other than the loopback interface.
+pub(crate) fn parse_vault_url(url: &str) -> Result<HttpTarget, String> {
+    if url.starts_with("https://") {
+        return Err(format!(
+            "Vault address '{}' uses HTTPS, which is not supported: run a local Vault Agent sidecar and point the address at its http:// listener on loopback",
+            url
+        ));
+    }
+    let target = parse_http_url(url)?;
+    let host = host_key(&target.address);
+    let loopback = host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    if !loopback {
+        return Err(format!(
+            "Vault address '{}' is not on the loopback interface: point it at a local Vault Agent",
+            url
+        ));
+    }
+    Ok(target)
+}
+
+/// Extract the secret of a Vault read response.
+fn parse_secret(body: &str) -> Result<VaultSecret, String> {
+    let response: Value =
+        serde_json::from_str(body).map_err(|e| format!("invalid Vault response: {}", e))?;
+    let data = &response["data"];
+    // KV v2 nests the secret under data.data, next to data.metadata
+    let data = match (data.get("data"), data.get("metadata")) {
+        (Some(inner), Some(_)) => inner,
+        _ => data,
+    };
+    if !data.is_object() {
+        return Err("Vault response has no secret data".to_string());
+    }
+    // The values are secrets; keep them out of the error message
+    serde_json::from_value(data.clone())
+        .map_err(|_| "Vault secret fields must be strings".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kv_v1_and_v2() {
+        let v1 = parse_secret(r#"{"data":{"username":"root","password":"p","ttl":60}}"#).unwrap();
+        assert_eq!(v1.username.as_deref(), Some("root"));
+        assert_eq!(v1.password.as_deref(), Some("p"));
+
+        let v2 = parse_secret(
+            r#"{"data":{"data":{"private_key":"-----BEGIN"},"metadata":{"version":3}}}"#,
+        )
+        .unwrap();
+        assert_eq!(v2.private_key.as_deref(), Some("-----BEGIN"));
+        assert!(v2.password.is_none());
+
+        assert!(parse_secret(r#"{"errors":[]}"#).is_err());
+        let error = parse_secret(r#"{"data":{"password":12345}}"#)
+            .err()
+            .unwrap();
+        assert!(!error.contains("12345"));
+    }
+
+    #[test]
+    fn test_scope_allows_listed_hosts_and_agents() {
+        let entry = CredentialConfig {
+            hosts: vec!["db1.internal".to_string(), "10.0.0.9".to_string()],
+            agents: vec!["dba-bot".to_string()],
+            ..Default::default()
+        };
+        assert!(check_scope("prod-db", &entry, Some("dba-bot"), &["DB1.internal:22"]).is_ok());
+        assert!(
+            check_scope(
+                "prod-db",
+                &entry,
+                Some("dba-bot"),
+                &["db1.internal:22", "10.0.0.9:2222"]
+            )
+            .is_ok()
+        );
+
+        let any_agent = CredentialConfig {
+            hosts: vec!["db1.internal".to_string()],
+            ..Default::default()
+        };
+        assert!(check_scope("prod-db", &any_agent, None, &["db1.internal"]).is_ok());
+    }
+
+    #[test]
+    fn test_scope_refuses_other_hosts_and_agents() {
+        let entry = CredentialConfig {
+            hosts: vec!["db1.internal".to_string()],
+            agents: vec!["dba-bot".to_string()],
+            ..Default::default()
+        };
+        let error = check_scope("prod-db", &entry, Some("dba-bot"), &["attacker:22"])
+            .err()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::AccessDenied);
+        assert!(error.message.contains("attacker"));
+
+        // Every jump host must be listed as well
+        assert!(
+            check_scope(
+                "prod-db",
+                &entry,
+                Some("dba-bot"),
+                &["db1.internal:22", "jump.attacker:22"]
+            )
+            .is_err()
+        );
+        assert!(check_scope("prod-db", &entry, Some("other"), &["db1.internal:22"]).is_err());
+        assert!(check_scope("prod-db", &entry, None, &["db1.internal:22"]).is_err());
+
+        // No hosts allows none
+        let unscoped = CredentialConfig::default();
+        assert!(check_scope("prod-db", &unscoped, None, &["db1.internal:22"]).is_err());
+    }
+
+    #[test]
+    fn test_env_credential_reads_named_variables() {
+        let var = format!("SSH_MCP_TEST_CRED_{}", uuid::Uuid::new_v4().simple());
+        let entry = CredentialConfig {
+            username: Some("postgres".to_string()),
+            password_env: Some(var.clone()),
+            ..Default::default()
+        };
+        assert!(from_env(&entry).err().unwrap().contains(&var));
+
+        // SAFETY: The variable name is unique to this test
+        unsafe {
+            env::set_var(&var, "secret");
+        }
+        let credential = from_env(&entry).unwrap();
+        // SAFETY: The variable name is unique to this test
+        unsafe {
+            env::remove_var(&var);
+        }
+        assert_eq!(credential.username.as_deref(), Some("postgres"));
+        assert_eq!(credential.password.as_deref(), Some("secret"));
+        assert!(credential.key_data.is_none());
+    }
+
+    #[test]
+    fn test_vault_address_must_be_loopback() {
+        for url in [
+            "http://127.0.0.1:8200/v1/secret",
+            "http://localhost:8200/v1/secret",
+            "http://[::1]:8200/v1/secret",
+        ] {
+            assert!(parse_vault_url(url).is_ok(), "{}", url);
+        }
+        for url in [
+            "http://vault.example.com:8200/v1/secret",
+            "http://10.0.0.5:8200/v1/secret",
+            "https://127.0.0.1:8200/v1/secret",
+        ] {
+            assert!(parse_vault_url(url).is_err(), "{}", url);
+        }
+        let https = parse_vault_url("https://vault.example.com:8200/v1/secret").unwrap_err();
+        assert!(https.contains("Vault Agent sidecar"), "{}", https);
+    }
+
+    #[tokio::test]
+    async fn test_vault_credential() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let vault = VaultConfig {
+            address: Some(format!("http://{}/", listener.local_addr().unwrap())),
+            ..Default::default()
+        };
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let read = socket.read(&mut buf).await.unwrap();
+            let body = r#"{"data":{"data":{"username":"ops","password":"pw"},"metadata":{}}}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..read]).to_string()
+        });
+        let entry = CredentialConfig {
+            source: CredentialSource::Vault,
+            key_path: Some("/etc/ssh-mcp/ops_key".to_string()),
+            path: Some("secret/data/ssh/ops".to_string()),
+            ..Default::default()
+        };
+
+        let credential = from_vault(&entry, &vault).await.unwrap();
+        assert_eq!(credential.username.as_deref(), Some("ops"));
+        assert_eq!(credential.password.as_deref(), Some("pw"));
+        assert_eq!(credential.key_path.as_deref(), Some("/etc/ssh-mcp/ops_key"));
+        assert!(
+            server
+                .await
+                .unwrap()
+                .starts_with("GET /v1/secret/data/ssh/ops HTTP/1.0\r\n")
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Dependency-graph command execution.
+//!
+//! `ssh_execute_dag` takes a set of commands, each naming the nodes it depends
+//! on, and runs them in dependency order: a node starts once every dependency
+//! succeeded, nodes without a path between them run in parallel up to the
+//! concurrency limit, and nodes may target different sessions (hosts). This
+//! turns a multi-host deployment ("build on ci, then deploy to web-1 and web-2,
+//! then smoke-test from lb") into a single tool call.
+//!
+//! # Failure Handling
+//!
+//! A node whose dependency failed or was skipped is skipped itself, with the
+//! reason recorded. With `fail_fast` (the default), the first failure also
+//! stops every node that has not started yet; nodes already running are left
+//! to finish so their results are not lost.
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use super::types::DagNodeSpec;
+
+/// Largest number of nodes in one graph
+pub(crate) const MAX_DAG_NODES: usize = 100;
+
+/// Nodes run in parallel when `concurrency` is not given
+pub(crate) const DEFAULT_DAG_CONCURRENCY: usize = 4;
+
+/// Largest accepted `concurrency`
+pub(crate) const MAX_DAG_CONCURRENCY: usize = 32;
+
+/// Resolve the node concurrency, clamped to 1..=`MAX_DAG_CONCURRENCY`.
+pub(crate) fn resolve_dag_concurrency(concurrency: Option<usize>) -> usize {
+    concurrency
+        .unwrap_or(DEFAULT_DAG_CONCURRENCY)
+        .clamp(1, MAX_DAG_CONCURRENCY)
+}
+
+/// Dependencies of each node as indices into the node list.
+#[derive(Debug, PartialEq)]
+pub(crate) struct DagPlan {
+    pub deps: Vec<Vec<usize>>,
+}
+
+/// Check node ids and dependencies and resolve them to indices.
+///
+/// Rejects empty or duplicate ids, unknown or self dependencies, cycles, and
+/// graphs larger than [`MAX_DAG_NODES`].
+pub(crate) fn plan_dag(nodes: &[DagNodeSpec]) -> Result<DagPlan, String> {
+    if nodes.is_empty() {
+        return Err("nodes must contain at least one command".to_string());
+    }
+    if nodes.len() > MAX_DAG_NODES {
+        return Err(format!(
+            "Too many nodes ({}); at most {} are allowed",
+            nodes.len(),
+            MAX_DAG_NODES
+        ));
+    }
+
+    let mut index_of = HashMap::with_capacity(nodes.len());
+    for (index, node) in nodes.iter().enumerate() {
+        if node.id.trim().is_empty() {
+            return Err(format!("Node {} has an empty id", index));
+        }
+        if index_of.insert(node.id.as_str(), index).is_some() {
+            return Err(format!("Duplicate node id '{}'", node.id));
+        }
+    }
+
+    let mut deps = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let mut node_deps = Vec::with_capacity(node.depends_on.len());
+        for dep in &node.depends_on {
+            if dep == &node.id {
+                return Err(format!("Node '{}' depends on itself", node.id));
+            }
+            let Some(&dep_index) = index_of.get(dep.as_str()) else {
+                return Err(format!(
+                    "Node '{}' depends on unknown node '{}'",
+                    node.id, dep
+                ));
+            };
+            if !node_deps.contains(&dep_index) {
+                node_deps.push(dep_index);
+            }
+        }
+        deps.push(node_deps);
+    }
+
+    if let Some(index) = find_cycle(&deps) {
+        return Err(format!(
+            "Dependency cycle involving node '{}'",
+            nodes[index].id
+        ));
+    }
+    Ok(DagPlan { deps })
+}
+
+/// Return a node on a cycle, if any (Kahn's algorithm leaves cycles unvisited).
+fn find_cycle(deps: &[Vec<usize>]) -> Option<usize> {
+    let mut remaining: Vec<usize> = deps.iter().map(Vec::len).collect();
+    let mut dependents = vec![Vec::new(); deps.len()];
+    for (node, node_deps) in deps.iter().enumerate() {
+        for &dep in node_deps {
+            dependents[dep].push(node);
+        }
+    }
+
+    let mut ready: Vec<usize> = (0..deps.len()).filter(|&n| remaining[n] == 0).collect();
+    let mut visited = 0;
+    while let Some(node) = ready.pop() {
+        visited += 1;
+        for &dependent in &dependents[node] {
+            remaining[dependent] -= 1;
+            if remaining[dependent] == 0 {
+                ready.push(dependent);
+            }
+        }
+    }
+    if visited == deps.len() {
+        return None;
+    }
+    (0..deps.len()).find(|&n| remaining[n] > 0)
+}
+
+/// What happened to one node.
+#[derive(Debug, PartialEq)]
+pub(crate) enum NodeRun<T> {
+    /// The node ran; `ok` is whether it succeeded
+    Ran { ok: bool, value: T },
+    /// The node never started
+    Skipped { reason: String },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// Run every node of `plan` with `run`, at most `concurrency` at a time.
+///
+/// `run` returns whether the node succeeded along with its result. Ready
+/// nodes start in list order. Results are returned in list order.
+pub(crate) async fn run_dag<T, F, Fut>(
+    plan: &DagPlan,
+    ids: &[String],
+    concurrency: usize,
+    fail_fast: bool,
+    run: F,
+) -> Vec<NodeRun<T>>
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = (bool, T)>,
+{
+    let count = plan.deps.len();
+    let mut states = vec![State::Pending; count];
+    let mut results: Vec<Option<NodeRun<T>>> = (0..count).map(|_| None).collect();
+    let mut running = FuturesUnordered::new();
+    let mut halted = false;
+
+    loop {
+        // Skip nodes that can no longer run (repeated so skips propagate down
+        // chains in any list order), then start whatever is ready.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in 0..count {
+                if states[node] != State::Pending {
+                    continue;
+                }
+                let blocked = plan.deps[node]
+                    .iter()
+                    .find(|&&dep| matches!(states[dep], State::Failed | State::Skipped));
+                let reason = match blocked {
+                    Some(&dep) => Some(format!(
+                        "Dependency '{}' {}",
+                        ids[dep],
+                        if states[dep] == State::Failed {
+                            "failed"
+                        } else {
+                            "was skipped"
+                        }
+                    )),
+                    None if halted => {
+                        Some("Not started because another node failed (fail_fast)".to_string())
+                    }
+                    None => None,
+                };
+                if let Some(reason) = reason {
+                    states[node] = State::Skipped;
+                    results[node] = Some(NodeRun::Skipped { reason });
+                    changed = true;
+                }
+            }
+        }
+        for node in 0..count {
+            if running.len() >= concurrency {
+                break;
+            }
+            if states[node] == State::Pending
+                && plan.deps[node]
+                    .iter()
+                    .all(|&dep| states[dep] == State::Succeeded)
+            {
+                states[node] = State::Running;
+                let future = run(node);
+                running.push(async move { (node, future.await) });
+            }
+        }
+
+        let Some((node, (ok, value))) = running.next().await else {
+            break;
+        };
+        states[node] = if ok { State::Succeeded } else { State::Failed };
+        results[node] = Some(NodeRun::Ran { ok, value });
+        if !ok && fail_fast {
+            halted = true;
+        }
+    }
+
+    results
+        .into_iter()
+        .map(|result| {
+            result.unwrap_or_else(|| NodeRun::Skipped {
+                reason: "Not started".to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, deps: &[&str]) -> DagNodeSpec {
+        DagNodeSpec {
+            id: id.to_string(),
+            session_id: Some("s".to_string()),
+            affinity: None,
+            command: format!("echo {}", id),
+            depends_on: deps.iter().map(|dep| dep.to_string()).collect(),
+            timeout_secs: None,
+        }
+    }
+
+    fn ids(nodes: &[DagNodeSpec]) -> Vec<String> {
+        nodes.iter().map(|node| node.id.clone()).collect()
+    }
+
+    mod planning {
+        use super::*;
+
+        #[test]
+        fn test_resolves_dependencies() {
+            let nodes = vec![node("build", &[]), node("deploy", &["build", "build"])];
+            assert_eq!(
+                plan_dag(&nodes).unwrap(),
+                DagPlan {
+                    deps: vec![vec![], vec![0]]
+                }
+            );
+        }
+
+        #[test]
+        fn test_rejects_invalid_graphs() {
+            assert!(plan_dag(&[]).is_err());
+            assert!(plan_dag(&[node("a", &[]), node("a", &[])]).is_err());
+            assert!(plan_dag(&[node("a", &["missing"])]).is_err());
+            assert!(plan_dag(&[node("a", &["a"])]).is_err());
+            assert!(plan_dag(&[node(" ", &[])]).is_err());
+        }
+
+        #[test]
+        fn test_rejects_cycle() {
+            let nodes = vec![node("a", &["c"]), node("b", &["a"]), node("c", &["b"])];
+            let error = plan_dag(&nodes).unwrap_err();
+            assert!(error.contains("cycle"));
+        }
+
+        #[test]
+        fn test_concurrency_clamped() {
+            assert_eq!(resolve_dag_concurrency(None), DEFAULT_DAG_CONCURRENCY);
+            assert_eq!(resolve_dag_concurrency(Some(0)), 1);
+            assert_eq!(resolve_dag_concurrency(Some(1000)), MAX_DAG_CONCURRENCY);
+        }
+    }
+
+    mod scheduling {
+        use super::*;
+        use std::sync::Mutex;
+
+        #[tokio::test]
+        async fn test_runs_in_dependency_order() {
+            let nodes = vec![
+                node("deploy", &["build"]),
+                node("build", &[]),
+                node("smoke", &["deploy"]),
+            ];
+            let plan = plan_dag(&nodes).unwrap();
+            let order = Mutex::new(Vec::new());
+
+            let results = run_dag(&plan, &ids(&nodes), 4, true, |index| {
+                order.lock().unwrap().push(index);
+                async move { (true, index) }
+            })
+            .await;
+
+            assert_eq!(*order.lock().unwrap(), vec![1, 0, 2]);
+            assert_eq!(results[0], NodeRun::Ran { ok: true, value: 0 });
+        }
+
+        #[tokio::test]
+        async fn test_failure_skips_dependents_only() {
+            let nodes = vec![
+                node("build", &[]),
+                node("deploy", &["build"]),
+                node("lint", &[]),
+            ];
+            let plan = plan_dag(&nodes).unwrap();
+
+            let results = run_dag(&plan, &ids(&nodes), 1, false, |index| async move {
+                (index != 0, ())
+            })
+            .await;
+
+            assert_eq!(
+                results[0],
+                NodeRun::Ran {
+                    ok: false,
+                    value: ()
+                }
+            );
+            assert_eq!(
+                results[1],
+                NodeRun::Skipped {
+                    reason: "Dependency 'build' failed".to_string()
+                }
+            );
+            assert_eq!(
+                results[2],
+                NodeRun::Ran {
+                    ok: true,
+                    value: ()
+                }
+            );
+        }
+
+        #[tokio::test]
+        async fn test_skips_propagate_against_list_order() {
+            let nodes = vec![
+                node("smoke", &["deploy"]),
+                node("deploy", &["build"]),
+                node("build", &[]),
+            ];
+            let plan = plan_dag(&nodes).unwrap();
+
+            let results = run_dag(&plan, &ids(&nodes), 4, false, |_| async { (false, ()) }).await;
+
+            assert_eq!(
+                results[0],
+                NodeRun::Skipped {
+                    reason: "Dependency 'deploy' was skipped".to_string()
+                }
+            );
+        }
+
+        #[tokio::test]
+        async fn test_fail_fast_stops_pending_nodes() {
+            let nodes = vec![node("build", &[]), node("lint", &[])];
+            let plan = plan_dag(&nodes).unwrap();
+
+            let results = run_dag(&plan, &ids(&nodes), 1, true, |index| async move {
+                (index != 0, ())
+            })
+            .await;
+
+            assert!(matches!(results[1], NodeRun::Skipped { .. }));
+        }
+
+        #[tokio::test]
+        async fn test_concurrency_limit() {
+            let nodes = vec![node("a", &[]), node("b", &[]), node("c", &[])];
+            let plan = plan_dag(&nodes).unwrap();
+            let active = Mutex::new((0usize, 0usize));
+
+            run_dag(&plan, &ids(&nodes), 2, true, |_| {
+                let active = &active;
+                async move {
+                    {
+                        let mut guard = active.lock().unwrap();
+                        guard.0 += 1;
+                        guard.1 = guard.1.max(guard.0);
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                    active.lock().unwrap().0 -= 1;
+                    (true, ())
+                }
+            })
+            .await;
+
+            assert_eq!(active.lock().unwrap().1, 2);
+        }
+    }
+}
@@ -0,0 +1,604 @@
+//! Remote file editing (`ssh_write_file`, `ssh_patch_file`).
+//!
+//! Config edits through `echo >` or `sed -i` one-liners break on quoting,
+//! leave half-written files behind when the connection drops, and give no way
+//! back. These tools send the content over an exec channel into a temporary
+//! file next to the target (so no quoting is involved) and only `mv` it over
+//! the target once it is complete; a failed write removes the temporary file
+//! and leaves the target untouched.
+//!
+//! A replaced file keeps its permission bits and, where the session may set
+//! it, its owner. With `backup`, the previous version is copied to
+//! `<path>.bak.<UTC timestamp>` first.
+//!
+//! # Patches
+//!
+//! `ssh_patch_file` reads the file, applies a unified diff here and writes the
+//! result back the same way, so the remote host needs no `patch` binary. Each
+//! hunk must match exactly (context and removed lines); a hunk whose line
+//! numbers are off is searched for nearby, nearest first, as `patch` does
+//! without fuzz. If any hunk does not apply, nothing is written.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use russh::{ChannelMsg, client};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::client::{channel_step, execute_ssh_command};
+use super::command_wrap::shell_quote;
+use super::config::resolve_channel_open_timeout;
+use super::session::SshClientHandler;
+use super::symlink::failure_message;
+use super::traffic::ByteCounters;
+use super::transfer::{relay_temp_path, upload_bytes};
+
+/// Largest file written or patched
+pub(crate) const MAX_EDIT_FILE_BYTES: usize = 8 * 1024 * 1024;
+
+/// Time allowed for each remote step (read, parent creation, install)
+const EDIT_STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Exit code of [`read_command`] when the file does not exist
+const MISSING_FILE_EXIT: u32 = 66;
+
+/// Parse an octal permission mode such as `644` or `0o600`.
+pub(crate) fn parse_mode(mode: &str) -> Result<u32, String> {
+    let digits = mode.trim().trim_start_matches("0o");
+    match u32::from_str_radix(digits, 8) {
+        Ok(bits) if bits <= 0o7777 && !digits.is_empty() => Ok(bits),
+        _ => Err(format!(
+            "Invalid mode '{}'; use octal permission bits such as \"644\"",
+            mode
+        )),
+    }
+}
+
+/// Reject paths that cannot name a regular file.
+pub(crate) fn check_path(path: &str) -> Result<(), String> {
+    if path.trim().is_empty() {
+        return Err("path must not be empty".to_string());
+    }
+    if path.ends_with('/') {
+        return Err(format!("path '{}' names a directory", path));
+    }
+    Ok(())
+}
+
+/// Name the previous version of `path` is copied to.
+pub(crate) fn backup_path(path: &str, now: DateTime<Utc>) -> String {
+    format!("{}.bak.{}", path, now.format("%Y%m%d%H%M%S"))
+}
+
+/// Command reading `path`, exiting with [`MISSING_FILE_EXIT`] when it does not exist.
+fn read_command(path: &str) -> String {
+    let quoted = shell_quote(path);
+    format!(
+        "[ -e {} ] || exit {}; cat -- {}",
+        quoted, MISSING_FILE_EXIT, quoted
+    )
+}
+
+/// Command moving the written `temp` over `path`.
+///
+/// An existing target is copied to `backup` first and its mode (and owner,
+/// where permitted) carried over, unless `mode` is given. Prints `created` or
+/// `replaced`; on failure `temp` is removed.
+pub(crate) fn install_command(
+    path: &str,
+    temp: &str,
+    backup: Option<&str>,
+    mode: Option<u32>,
+) -> String {
+    let mut existing = Vec::new();
+    if let Some(backup) = backup {
+        existing.push(format!("cp -p -- \"$p\" {}", shell_quote(backup)));
+    }
+    if mode.is_none() {
+        existing.push(
+            "m=$(stat -c %a -- \"$p\" 2>/dev/null || stat -f %Lp \"$p\") && chmod \"$m\" \"$t\""
+                .to_string(),
+        );
+    }
+    existing.push(
+        "{ chown \"$(stat -c %u:%g -- \"$p\" 2>/dev/null || stat -f %u:%g \"$p\")\" \"$t\" 2>/dev/null || true; }"
+            .to_string(),
+    );
+    let chmod = mode
+        .map(|mode| format!("chmod {:o} \"$t\" && ", mode))
+        .unwrap_or_default();
+    format!(
+        "t={}; p={}; if [ -e \"$p\" ]; then echo replaced && {}; else echo created; fi && {}mv -f -- \"$t\" \"$p\" || {{ rm -f -- \"$t\"; exit 1; }}",
+        shell_quote(temp),
+        shell_quote(path),
+        existing.join(" && "),
+        chmod
+    )
+}
+
+/// How [`write_file`] writes.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct WriteOptions {
+    /// Permission bits; an existing file's are kept when `None`
+    pub mode: Option<u32>,
+    pub backup: bool,
+    /// Create missing parent directories
+    pub create_parents: bool,
+}
+
+/// A file written on the remote host.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Written {
+    pub bytes: u64,
+    /// Hex sha256 of the content
+    pub sha256: String,
+    /// Whether the file did not exist before
+    pub created: bool,
+    pub backup_path: Option<String>,
+}
+
+/// Hex sha256 of `content`.
+pub(crate) fn sha256_hex(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Replace `path` with `content` through a temporary file.
+pub(crate) async fn write_file(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    traffic: &ByteCounters,
+    path: &str,
+    content: &[u8],
+    options: WriteOptions,
+) -> Result<Written, String> {
+    if options.create_parents {
+        let mkdir = format!("mkdir -p -- \"$(dirname -- {})\"", shell_quote(path));
+        let response = execute_ssh_command(handle, &mkdir, EDIT_STEP_TIMEOUT).await?;
+        if response.timed_out || response.exit_code != 0 {
+            return Err(failure_message(&response));
+        }
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+    let temp = relay_temp_path(path, &token[..12]);
+    if let Err(e) = upload_bytes(handle, &temp, content).await {
+        let cleanup = format!("rm -f -- {}", shell_quote(&temp));
+        let _ = execute_ssh_command(handle, &cleanup, EDIT_STEP_TIMEOUT).await;
+        return Err(e);
+    }
+    traffic.record_sent(content.len());
+
+    let backup = options.backup.then(|| backup_path(path, Utc::now()));
+    let install = install_command(path, &temp, backup.as_deref(), options.mode);
+    let response = execute_ssh_command(handle, &install, EDIT_STEP_TIMEOUT).await?;
+    if response.timed_out || response.exit_code != 0 {
+        return Err(failure_message(&response));
+    }
+    let created = response.stdout.trim() == "created";
+    Ok(Written {
+        bytes: content.len() as u64,
+        sha256: sha256_hex(content),
+        created,
+        backup_path: backup.filter(|_| !created),
+    })
+}
+
+/// Read `path` from the remote host; `None` when it does not exist.
+pub(crate) async fn read_file(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    path: &str,
+) -> Result<Option<Vec<u8>>, String> {
+    let open_timeout = resolve_channel_open_timeout();
+    let mut channel =
+        channel_step("open channel", open_timeout, handle.channel_open_session()).await?;
+    channel_step(
+        "execute command",
+        open_timeout,
+        channel.exec(true, read_command(path)),
+    )
+    .await?;
+
+    let mut content = Vec::new();
+    let mut stderr = Vec::new();
+    let mut exit_code: Option<u32> = None;
+    let finished = tokio::time::timeout(EDIT_STEP_TIMEOUT, async {
+        loop {
+            match channel.wait().await {
+                Some(ChannelMsg::Data { data }) => {
+                    content.extend_from_slice(&data);
+                    if content.len() > MAX_EDIT_FILE_BYTES {
+                        return Err(format!(
+                            "{} is larger than {} bytes",
+                            path, MAX_EDIT_FILE_BYTES
+                        ));
+                    }
+                }
+                Some(ChannelMsg::ExtendedData { data, ext: 1 }) => {
+                    stderr.extend_from_slice(&data);
+                }
+                Some(ChannelMsg::ExitStatus { exit_status }) => {
+                    exit_code = Some(exit_status);
+                }
+                Some(ChannelMsg::Eof) if exit_code.is_some() => break,
+                Some(ChannelMsg::Close) | None => break,
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    })
+    .await;
+    let _ = channel.close().await;
+
+    match finished {
+        Err(_) => Err(format!(
+            "Reading {} did not finish within {}s",
+            path,
+            EDIT_STEP_TIMEOUT.as_secs()
+        )),
+        Ok(Err(e)) => Err(e),
+        Ok(Ok(())) => match exit_code {
+            Some(0) => Ok(Some(content)),
+            Some(MISSING_FILE_EXIT) => Ok(None),
+            code => {
+                let stderr = String::from_utf8_lossy(&stderr);
+                Err(stderr
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| match code {
+                        Some(code) => format!("Remote read exited with code {}", code),
+                        None => "Channel closed before the remote read finished".to_string(),
+                    }))
+            }
+        },
+    }
+}
+
+/// One `@@` hunk of a unified diff.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Hunk {
+    /// 1-based first line in the original (0 when inserting at the top)
+    pub old_start: usize,
+    /// Context and removed lines, in order
+    pub old_lines: Vec<String>,
+    /// Context and added lines, in order
+    pub new_lines: Vec<String>,
+    pub added: usize,
+    pub removed: usize,
+    /// `\ No newline at end of file` after an original line
+    pub old_no_newline: bool,
+    /// `\ No newline at end of file` after a new line
+    pub new_no_newline: bool,
+}
+
+/// A parsed single-file unified diff.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Patch {
+    /// The diff creates the file (`--- /dev/null`)
+    pub creates: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Parse `@@ -a,b +c,d @@` into `(a, b, d)`.
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let ranges = line.strip_prefix("@@ -")?;
+    let (old, rest) = ranges.split_once(" +")?;
+    let (new, _) = rest.split_once(" @@")?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(old)?;
+    let (_, new_count) = range(new)?;
+    Some((old_start, old_count, new_count))
+}
+
+/// Parse a unified diff of one file (as produced by `diff -u` or `git diff`).
+pub(crate) fn parse_unified_diff(diff: &str) -> Result<Patch, String> {
+    let mut patch = Patch::default();
+    let mut files = 0;
+    // Lines still expected in the current hunk (original, new)
+    let mut left = (0usize, 0usize);
+    let mut last_kind = ' ';
+
+    for line in diff.lines() {
+        if line.starts_with("\\ ") {
+            let Some(hunk) = patch.hunks.last_mut() else {
+                continue;
+            };
+            match last_kind {
+                '-' => hunk.old_no_newline = true,
+                '+' => hunk.new_no_newline = true,
+                _ => {
+                    hunk.old_no_newline = true;
+                    hunk.new_no_newline = true;
+                }
+            }
+            continue;
+        }
+
+        if left != (0, 0) {
+            let hunk = patch.hunks.last_mut().expect("counts are set by a hunk");
+            let (kind, text) = match line.chars().next() {
+                None => (' ', ""),
+                Some(kind @ (' ' | '-' | '+')) => (kind, &line[1..]),
+                Some(_) => {
+                    return Err(format!(
+                        "Unexpected line in hunk {}: '{}'",
+                        patch.hunks.len(),
+                        line
+                    ));
+                }
+            };
+            let (old, new) = match kind {
+                ' ' => (true, true),
+                '-' => (true, false),
+                _ => (false, true),
+            };
+            if (old && left.0 == 0) || (new && left.1 == 0) {
+                return Err(format!(
+                    "Hunk {} has more lines than its @@ header counts",
+                    patch.hunks.len()
+                ));
+            }
+            if old {
+                hunk.old_lines.push(text.to_string());
+                left.0 -= 1;
+            }
+            if new {
+                hunk.new_lines.push(text.to_string());
+                left.1 -= 1;
+            }
+            match kind {
+                '-' => hunk.removed += 1,
+                '+' => hunk.added += 1,
+                _ => {}
+            }
+            last_kind = kind;
+            continue;
+        }
+
+        if line.starts_with("@@") {
+            let (old_start, old_count, new_count) =
+                parse_hunk_header(line).ok_or_else(|| format!("Invalid hunk header '{}'", line))?;
+            patch.hunks.push(Hunk {
+                old_start,
+                ..Default::default()
+            });
+            left = (old_count, new_count);
+        } else if let Some(old) = line.strip_prefix("--- ") {
+            patch.creates = old.trim() == "/dev/null" || old.starts_with("/dev/null\t");
+        } else if line.starts_with("+++ ") {
+            files += 1;
+            if files > 1 {
+                return Err(
+                    "The diff changes more than one file; patch one file per call".to_string(),
+                );
+            }
+        } else if !patch.hunks.is_empty() && matches!(line.chars().next(), Some('+' | '-')) {
+            return Err(format!(
+                "Hunk {} has more lines than its @@ header counts",
+                patch.hunks.len()
+            ));
+        }
+    }
+
+    if left != (0, 0) {
+        return Err(format!(
+            "Hunk {} ends early: {} original and {} new line(s) missing",
+            patch.hunks.len(),
+            left.0,
+            left.1
+        ));
+    }
+    if patch.hunks.is_empty() {
+        return Err("The diff contains no @@ hunks".to_string());
+    }
+    Ok(patch)
+}
+
+/// Result of applying a patch.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Patched {
+    pub text: String,
+    pub added: usize,
+    pub removed: usize,
+    /// Hunks applied away from the line their header names
+    pub shifted: usize,
+}
+
+/// Find `needle` in `lines` at or after `from`, nearest to `expected` first.
+fn find_hunk(lines: &[&str], needle: &[String], from: usize, expected: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(needle.len())?;
+    if from > last {
+        return None;
+    }
+    let expected = expected.clamp(from, last);
+    let matches = |at: usize| {
+        lines[at..at + needle.len()]
+            .iter()
+            .zip(needle)
+            .all(|(line, wanted)| *line == wanted.trim_end_matches('\r'))
+    };
+    (0..=(last - from)).find_map(|distance| {
+        let after = expected + distance;
+        if after <= last && matches(after) {
+            return Some(after);
+        }
+        let before = expected.checked_sub(distance)?;
+        (before >= from && distance > 0 && matches(before)).then_some(before)
+    })
+}
+
+/// Apply `patch` to `original`, keeping its line endings.
+pub(crate) fn apply_patch(original: &str, patch: &Patch) -> Result<Patched, String> {
+    let eol = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let lines: Vec<&str> = original
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .collect();
+    let mut trailing_newline = original.is_empty() || original.ends_with('\n');
+
+    let mut out: Vec<&str> = Vec::with_capacity(lines.len());
+    let mut pos = 0;
+    let mut offset: isize = 0;
+    let mut patched = Patched {
+        text: String::new(),
+        added: 0,
+        removed: 0,
+        shifted: 0,
+    };
+    for (index, hunk) in patch.hunks.iter().enumerate() {
+        // A hunk without original lines inserts after line `old_start`
+        let header_at = if hunk.old_lines.is_empty() {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = (header_at as isize + offset).max(0) as usize;
+        let at = if hunk.old_lines.is_empty() {
+            Some(expected.clamp(pos, lines.len()))
+        } else {
+            find_hunk(&lines, &hunk.old_lines, pos, expected)
+        }
+        .ok_or_else(|| {
+            format!(
+                "Hunk {} (@@ -{},{}) does not apply: its context and removed lines were not found after line {}",
+                index + 1,
+                hunk.old_start,
+                hunk.old_lines.len(),
+                pos
+            )
+        })?;
+
+        if at != header_at {
+            patched.shifted += 1;
+        }
+        offset = at as isize - header_at as isize;
+        out.extend_from_slice(&lines[pos..at]);
+        out.extend(
+            hunk.new_lines
+                .iter()
+                .map(|line| line.trim_end_matches('\r')),
+        );
+        pos = at + hunk.old_lines.len();
+        patched.added += hunk.added;
+        patched.removed += hunk.removed;
+        if hunk.new_no_newline {
+            trailing_newline = false;
+        } else if hunk.old_no_newline {
+            trailing_newline = true;
+        }
+    }
+    out.extend_from_slice(&lines[pos..]);
+
+    patched.text = out.join(eol);
+    if trailing_newline && !out.is_empty() {
+        patched.text.push_str(eol);
+    }
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NGINX: &str =
+        "server {\n    listen 80;\n    server_name example.com;\n    root /var/www;\n}\n";
+
+    #[test]
+    fn test_install_command() {
+        assert_eq!(
+            install_command(
+                "/etc/app.conf",
+                "/etc/app.conf.ssh-mcp-1",
+                None,
+                Some(0o600)
+            ),
+            "t='/etc/app.conf.ssh-mcp-1'; p='/etc/app.conf'; if [ -e \"$p\" ]; then echo replaced && \
+             { chown \"$(stat -c %u:%g -- \"$p\" 2>/dev/null || stat -f %u:%g \"$p\")\" \"$t\" 2>/dev/null || true; }; \
+             else echo created; fi && chmod 600 \"$t\" && mv -f -- \"$t\" \"$p\" || { rm -f -- \"$t\"; exit 1; }"
+        );
+        let command = install_command("/etc/a b", "/etc/a b.tmp", Some("/etc/a b.bak.1"), None);
+        assert!(command.contains("cp -p -- \"$p\" '/etc/a b.bak.1' && m=$(stat"));
+        assert_eq!(parse_mode("0644"), Ok(0o644));
+        assert_eq!(parse_mode("0o600"), Ok(0o600));
+        assert!(parse_mode("999").is_err());
+        assert!(parse_mode("").is_err());
+        assert!(check_path("/etc/").is_err());
+    }
+
+    #[test]
+    fn test_apply_with_offset() {
+        let patch = parse_unified_diff(
+            "--- a/site.conf\n+++ b/site.conf\n@@ -1,3 +1,4 @@\n server {\n-    listen 80;\n+    listen 443 ssl;\n+    http2 on;\n     server_name example.com;\n",
+        )
+        .unwrap();
+        assert!(!patch.creates);
+        assert_eq!(patch.hunks[0].added, 2);
+
+        let patched = apply_patch(NGINX, &patch).unwrap();
+        assert_eq!(
+            patched.text,
+            "server {\n    listen 443 ssl;\n    http2 on;\n    server_name example.com;\n    root /var/www;\n}\n"
+        );
+        assert_eq!((patched.added, patched.removed, patched.shifted), (2, 1, 0));
+
+        // The same hunk still applies when lines were added above it
+        let shifted = apply_patch(&format!("# managed\n\n{}", NGINX), &patch).unwrap();
+        assert_eq!(shifted.shifted, 1);
+        assert!(
+            shifted
+                .text
+                .starts_with("# managed\n\nserver {\n    listen 443 ssl;\n")
+        );
+
+        // CRLF files keep their line endings
+        let crlf = apply_patch(&NGINX.replace('\n', "\r\n"), &patch).unwrap();
+        assert!(crlf.text.contains("    http2 on;\r\n"));
+    }
+
+    #[test]
+    fn test_rejects_mismatch_and_bad_diffs() {
+        let patch =
+            parse_unified_diff("@@ -2 +2 @@\n-    listen 8080;\n+    listen 80;\n").unwrap();
+        assert!(
+            apply_patch(NGINX, &patch)
+                .unwrap_err()
+                .contains("does not apply")
+        );
+        assert!(
+            parse_unified_diff("@@ -1,2 +1,2 @@\n a\n")
+                .unwrap_err()
+                .contains("ends early")
+        );
+        assert!(parse_unified_diff("just text").is_err());
+        assert!(
+            parse_unified_diff("--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n--- a/y\n+++ b/y\n")
+                .unwrap_err()
+                .contains("more than one file")
+        );
+    }
+
+    #[test]
+    fn test_create_and_newline_markers() {
+        let patch = parse_unified_diff(
+            "--- /dev/null\n+++ b/motd\n@@ -0,0 +1,2 @@\n+hello\n+world\n\\ No newline at end of file\n",
+        )
+        .unwrap();
+        assert!(patch.creates);
+        assert_eq!(apply_patch("", &patch).unwrap().text, "hello\nworld");
+
+        let patch =
+            parse_unified_diff("@@ -1 +1 @@\n-old\n\\ No newline at end of file\n+new\n").unwrap();
+        assert_eq!(apply_patch("old", &patch).unwrap().text, "new\n");
+    }
+}
@@ -0,0 +1,148 @@
+//! Interactive password prompt for `ssh_connect` (MCP elicitation).
+//!
+//! When `ssh_connect` is given no credentials, only the local SSH agent is
+//! tried. If that fails on the target, the operator enabled
+//! `[elicitation] password_prompt` and the client declared the `elicitation`
+//! capability, the user is asked for the password with an
+//! `elicitation/create` request and the connection is dialed once more with
+//! it. Otherwise, or when the user declines, the original authentication
+//! error is returned, pointing to `credential_ref`.
+//!
+//! The prompt is off by default: the MCP specification forbids requesting
+//! sensitive information such as passwords through form elicitation, and the
+//! startup self-test warns when it is enabled. The password travels through
+//! the MCP client like any tool argument would, but it is typed by the user
+//! rather than generated by the model, so it does not enter the conversation.
+
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use super::config::server_config;
+use super::error::is_auth_error;
+use super::notify::current_client;
+
+/// How long the user has to answer the prompt
+pub(crate) const ELICITATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Whether a failed connect without credentials was refused by the target's
+/// authentication, which a password may fix. Failures of a jump host, the
+/// network or the host key are not.
+pub(crate) fn agent_auth_failed(error: &str) -> bool {
+    !error.contains("Jump host ")
+        && (is_auth_error(error)
+            || error.contains("authentication rejected")
+            || error.contains("SSH agent"))
+}
+
+/// Parameters of the `elicitation/create` request for `username@address`.
+fn password_request(username: &str, address: &str) -> Value {
+    json!({
+        "message": format!(
+            "SSH agent authentication to {}@{} failed. Enter the password to connect.",
+            username, address
+        ),
+        "requestedSchema": {
+            "type": "object",
+            "properties": {
+                "password": {
+                    "type": "string",
+                    "title": "Password",
+                    "description": format!("Password of {} on {}", username, address),
+                },
+            },
+            "required": ["password"],
+        },
+    })
+}
+
+/// Hint appended to an agent authentication failure that was not prompted for.
+pub(crate) const CREDENTIAL_REF_HINT: &str = "pass credential_ref to use a server-side credential (see ssh_capabilities policies.credential_refs) instead of a password";
+
+/// The password of an accepted elicitation result.
+fn accepted_password(result: &Value) -> Option<String> {
+    if result.get("action").and_then(Value::as_str) != Some("accept") {
+        return None;
+    }
+    result["content"]["password"]
+        .as_str()
+        .filter(|password| !password.is_empty())
+        .map(str::to_string)
+}
+
+/// Ask the client of the current request for the password of
+/// `username@address`; `None` if it cannot be asked or gives none.
+pub(crate) async fn ask_password(username: &str, address: &str) -> Option<String> {
+    if !server_config().elicitation.password_prompt {
+        return None;
+    }
+    let client = current_client().filter(|client| client.supports_elicitation())?;
+    info!(
+        "Asking the client for the password of {}@{}",
+        username, address
+    );
+    match client
+        .request(
+            "elicitation/create",
+            password_request(username, address),
+            ELICITATION_TIMEOUT,
+        )
+        .await
+    {
+        Ok(result) => accepted_password(&result),
+        Err(e) => {
+            warn!("Password prompt for {}@{} failed: {}", username, address, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_auth_failed() {
+        assert!(agent_auth_failed(
+            "SSH connection failed after 1 attempt(s). Last error: Agent authentication failed: no identities accepted"
+        ));
+        assert!(agent_auth_failed("No identities found in SSH agent"));
+        assert!(agent_auth_failed("agent authentication rejected"));
+        assert!(!agent_auth_failed(
+            "Jump host 1 (ops@bastion:22): Agent authentication failed: no identities accepted"
+        ));
+        assert!(!agent_auth_failed("Connection timed out after 30s"));
+    }
+
+    #[test]
+    fn test_password_request() {
+        let params = password_request("deploy", "10.0.0.5:22");
+        assert!(
+            params["message"]
+                .as_str()
+                .unwrap()
+                .contains("deploy@10.0.0.5:22")
+        );
+        assert_eq!(params["requestedSchema"]["required"], json!(["password"]));
+        assert_eq!(
+            params["requestedSchema"]["properties"]["password"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn test_accepted_password() {
+        let accepted = json!({"action": "accept", "content": {"password": "s3cret"}});
+        assert_eq!(accepted_password(&accepted), Some("s3cret".to_string()));
+        assert_eq!(accepted_password(&json!({"action": "decline"})), None);
+        assert_eq!(accepted_password(&json!({"action": "cancel"})), None);
+        let empty = json!({"action": "accept", "content": {"password": ""}});
+        assert_eq!(accepted_password(&empty), None);
+    }
+
+    #[tokio::test]
+    async fn test_ask_password_without_client() {
+        assert_eq!(ask_password("deploy", "10.0.0.5:22").await, None);
+    }
+}
@@ -1,8 +1,12 @@
-//! Error classification for SSH connection retry logic.
+//! Error classification for SSH connection retry logic and structured tool errors.
 //!
 //! This module provides error classification to determine which errors are transient
 //! and worth retrying versus permanent failures that should fail immediately.
 //!
+//! Tool failures are reported to MCP clients as [`ToolError`], a JSON payload with a
+//! machine-readable `code`, the human-readable `message`, a `retryable` hint derived
+//! from the same classification, and optional `details` (session_id, stage, ...).
+//!
 //! # Classification Strategy
 //!
 //! Errors are classified into three categories:
@@ -46,6 +50,15 @@
 //! assert!(is_retryable_error("SSH connection timeout"));
 //! ```
 
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::storage::persistent::{lost_command, lost_session};
+use super::types::LostSession;
+
 /// Authentication error patterns that indicate permanent failures.
 ///
 /// These errors will never succeed by retrying and should fail immediately
@@ -62,6 +75,9 @@ const AUTH_ERRORS: &[&str] = &[
     "all authentication methods failed",
 ];
 
+/// Host key rejections (see `known_hosts`): the server's key will not change on retry.
+const HOST_KEY_ERRORS: &[&str] = &["host key verification failed"];
+
 /// Connection error patterns that indicate transient failures.
 ///
 /// These errors may resolve on retry due to temporary network conditions,
@@ -138,11 +154,13 @@ const RETRYABLE_ERRORS: &[&str] = &[
 pub(crate) fn is_retryable_error(error: &str) -> bool {
     let error_lower = error.to_lowercase();
 
-    // Authentication failures are NOT retryable (checked first for priority)
-    for auth_err in AUTH_ERRORS {
-        if error_lower.contains(auth_err) {
-            return false;
-        }
+    // Authentication failures and rejected host keys are NOT retryable (checked first for priority)
+    if is_auth_error(&error_lower)
+        || HOST_KEY_ERRORS
+            .iter()
+            .any(|host_key_err| error_lower.contains(host_key_err))
+    {
+        return false;
     }
 
     // Connection errors ARE retryable
@@ -159,6 +177,260 @@ pub(crate) fn is_retryable_error(error: &str) -> bool {
         || error_lower.contains("connect")
 }
 
+/// Returns `true` if the error message matches a known authentication failure pattern.
+pub(crate) fn is_auth_error(error: &str) -> bool {
+    let error_lower = error.to_lowercase();
+    AUTH_ERRORS
+        .iter()
+        .any(|auth_err| error_lower.contains(auth_err))
+}
+
+/// Machine-readable error classes returned in [`ToolError::code`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// No session exists with the given session_id
+    SessionNotFound,
+    /// No async command exists with the given command_id
+    CommandNotFound,
+    /// The session was open when the server last stopped; reconnect with ssh_connect
+    SessionLost,
+    /// The async command was running when the server last stopped; its output is gone
+    CommandLost,
+    /// Another ssh-mcp replica holds the session or command; route the call there
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    WrongReplica,
+    /// No interactive shell exists with the given shell_id
+    ShellNotFound,
+    /// A per-session limit (commands, shells) was reached
+    LimitExceeded,
+    /// The target exists but is not in a state that allows the operation
+    InvalidState,
+    /// A tool argument is empty or has an unsupported value
+    InvalidArgument,
+    /// A file operation on the remote host failed (missing path, permission denied, ...)
+    RemoteFileError,
+    /// The session belongs to another agent and is not shared with the caller
+    AccessDenied,
+    /// Another agent holds a lease on the session
+    SessionLocked,
+    /// Credentials were rejected by the server
+    AuthenticationFailed,
+    /// The TCP connection or SSH handshake failed
+    ConnectionFailed,
+    /// Opening or using an SSH channel failed on an established session
+    ChannelError,
+    /// The operator's command policy refuses the command; see `suggestions`
+    PolicyViolation,
+    /// The tool requires a feature this build does not include
+    #[cfg_attr(feature = "port_forward", allow(dead_code))]
+    FeatureDisabled,
+}
+
+impl ErrorCode {
+    /// Whether an error of this class can succeed if the same call is repeated.
+    fn default_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::LimitExceeded | ErrorCode::ConnectionFailed | ErrorCode::SessionLocked
+        )
+    }
+}
+
+/// Structured error returned by MCP tools.
+///
+/// Serialized as JSON in the tool result text so client-side agents can branch on
+/// `code` and `retryable` instead of matching message strings:
+///
+/// ```json
+/// {"code":"session_not_found","message":"No active SSH session with ID: abc","retryable":false,"details":{"session_id":"abc"}}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolError {
+    /// Error class
+    pub code: ErrorCode,
+    /// Human-readable description
+    pub message: String,
+    /// Whether repeating the same call may succeed
+    pub retryable: bool,
+    /// Additional context such as session_id, command_id or stage
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub details: Map<String, Value>,
+}
+
+impl ToolError {
+    /// Create an error with the default retryable hint for its code.
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retryable: code.default_retryable(),
+            details: Map::new(),
+        }
+    }
+
+    /// Error for an unknown session_id; `session_lost` when it was lost in a
+    /// server restart.
+    pub fn session_not_found(session_id: &str) -> Self {
+        if let Some(lost) = lost_session(session_id) {
+            return Self::session_lost(&lost);
+        }
+        #[cfg(feature = "redis")]
+        if let Some(owner) = super::storage::redis::session_owner(session_id) {
+            return Self::wrong_replica(
+                "Session",
+                session_id,
+                &owner.replica,
+                owner.replica_url.as_deref(),
+            )
+            .with_detail("session_id", session_id);
+        }
+        Self::new(
+            ErrorCode::SessionNotFound,
+            format!("No active SSH session with ID: {}", session_id),
+        )
+        .with_detail("session_id", session_id)
+    }
+
+    /// Error for a session that did not survive a server restart, with the
+    /// parameters to reconnect with.
+    pub fn session_lost(lost: &LostSession) -> Self {
+        let reconnect = match &lost.reconnect {
+            Some(hint) => format!(
+                "Reconnect with ssh_connect(address=\"{}\", username=\"{}\") and supply the {} again, then use the new session_id.",
+                hint.address, hint.username, hint.auth
+            ),
+            None => "Reconnect with ssh_connect and use the new session_id.".to_string(),
+        };
+        Self::new(
+            ErrorCode::SessionLost,
+            format!(
+                "Session {} ({}@{}) was lost when the MCP server restarted (last seen {}). {}",
+                lost.session_id, lost.username, lost.host, lost.last_seen_at, reconnect
+            ),
+        )
+        .with_detail("session_id", lost.session_id.as_str())
+        .with_detail(
+            "lost_session",
+            serde_json::to_value(lost).unwrap_or(Value::Null),
+        )
+    }
+
+    /// Error for an unknown command_id; `command_lost` when it was lost in a
+    /// server restart.
+    pub fn command_not_found(command_id: &str) -> Self {
+        #[cfg(feature = "redis")]
+        if let Some(owner) = super::storage::redis::command_owner(command_id) {
+            return Self::wrong_replica(
+                "Command",
+                command_id,
+                &owner.replica,
+                owner.replica_url.as_deref(),
+            )
+            .with_detail("command_id", command_id);
+        }
+        if let Some(lost) = lost_command(command_id) {
+            return Self::new(
+                ErrorCode::CommandLost,
+                format!(
+                    "Command {} on session {} was lost when the MCP server restarted (status {}, last seen {}); its output is gone. Reconnect and run it again if needed.",
+                    lost.command_id, lost.session_id, lost.status, lost.last_seen_at
+                ),
+            )
+            .with_detail("command_id", command_id)
+            .with_detail(
+                "lost_command",
+                serde_json::to_value(&lost).unwrap_or(Value::Null),
+            );
+        }
+        Self::new(
+            ErrorCode::CommandNotFound,
+            format!("No async command with ID: {}", command_id),
+        )
+        .with_detail("command_id", command_id)
+    }
+
+    /// Error for a session or command held by another replica sharing the
+    /// Redis registry, naming the replica to route the call to.
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    pub fn wrong_replica(kind: &str, id: &str, replica: &str, replica_url: Option<&str>) -> Self {
+        let target = match replica_url {
+            Some(url) => format!("{} ({})", replica, url),
+            None => replica.to_string(),
+        };
+        let mut error = Self::new(
+            ErrorCode::WrongReplica,
+            format!(
+                "{} {} is held by ssh-mcp replica {}; send the call to that replica.",
+                kind, id, target
+            ),
+        )
+        .with_detail("replica", replica);
+        if let Some(url) = replica_url {
+            error = error.with_detail("replica_url", url);
+        }
+        error
+    }
+
+    /// Error for an unknown shell_id.
+    pub fn shell_not_found(shell_id: &str) -> Self {
+        Self::new(
+            ErrorCode::ShellNotFound,
+            format!("No active shell with ID: {}", shell_id),
+        )
+        .with_detail("shell_id", shell_id)
+    }
+
+    /// Classify a connection failure message as authentication or connection error.
+    ///
+    /// The retryable hint follows [`is_retryable_error`].
+    pub fn connection(message: impl Into<String>) -> Self {
+        let message = message.into();
+        let code = if is_auth_error(&message) {
+            ErrorCode::AuthenticationFailed
+        } else {
+            ErrorCode::ConnectionFailed
+        };
+        let retryable = is_retryable_error(&message);
+        Self::new(code, message).with_retryable(retryable)
+    }
+
+    /// Error raised while opening or using a channel on an established session.
+    pub fn channel(message: impl Into<String>) -> Self {
+        let message = message.into();
+        let retryable = is_retryable_error(&message);
+        Self::new(ErrorCode::ChannelError, message).with_retryable(retryable)
+    }
+
+    /// Attach a detail value.
+    pub fn with_detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Record the stage of the operation that failed (e.g. "connect", "open_channel").
+    pub fn with_stage(self, stage: &str) -> Self {
+        self.with_detail("stage", stage)
+    }
+
+    /// Override the retryable hint.
+    pub fn with_retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_string(self) {
+            Ok(json) => f.write_str(&json),
+            Err(_) => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for ToolError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,6 +496,15 @@ mod tests {
         fn test_all_auth_methods_failed() {
             assert!(!is_retryable_error("All authentication methods failed"));
         }
+
+        #[test]
+        fn test_host_key_rejected() {
+            assert!(!is_retryable_error(
+                "SSH connection failed after 1 attempt(s). Last error: Host key verification \
+                 failed for web1 (ssh-ed25519 SHA256:abc): host is not in known_hosts; add it \
+                 or connect with strict_host_key_checking=\"accept-new\""
+            ));
+        }
     }
 
     mod connection_errors_retryable {
@@ -356,4 +637,135 @@ mod tests {
             ));
         }
     }
+
+    mod tool_error {
+        use super::*;
+
+        fn to_json(error: &ToolError) -> Value {
+            serde_json::from_str(&error.to_string()).unwrap()
+        }
+
+        #[test]
+        fn test_display_is_json() {
+            let error = ToolError::new(ErrorCode::InvalidState, "Command is not running");
+            let json = to_json(&error);
+
+            assert_eq!(json["code"], "invalid_state");
+            assert_eq!(json["message"], "Command is not running");
+            assert_eq!(json["retryable"], false);
+            assert!(json.get("details").is_none());
+        }
+
+        #[test]
+        fn test_session_not_found_details() {
+            let json = to_json(&ToolError::session_not_found("sess-1"));
+
+            assert_eq!(json["code"], "session_not_found");
+            assert_eq!(json["message"], "No active SSH session with ID: sess-1");
+            assert_eq!(json["details"]["session_id"], "sess-1");
+        }
+
+        #[test]
+        fn test_command_and_shell_not_found() {
+            let command = to_json(&ToolError::command_not_found("cmd-1"));
+            assert_eq!(command["code"], "command_not_found");
+            assert_eq!(command["details"]["command_id"], "cmd-1");
+
+            let shell = to_json(&ToolError::shell_not_found("sh-1"));
+            assert_eq!(shell["code"], "shell_not_found");
+            assert_eq!(shell["details"]["shell_id"], "sh-1");
+        }
+
+        #[test]
+        fn test_session_lost_carries_reconnect_hint() {
+            let lost = LostSession {
+                session_id: "sess-1".to_string(),
+                name: None,
+                agent_id: None,
+                host: "db1:22".to_string(),
+                username: "deploy".to_string(),
+                connected_at: "2026-10-01T10:00:00+00:00".to_string(),
+                last_seen_at: "2026-10-01T11:00:00+00:00".to_string(),
+                reconnect: Some(crate::mcp::types::ReconnectHint {
+                    address: "db1".to_string(),
+                    username: "deploy".to_string(),
+                    auth: "agent".to_string(),
+                    credential_ref: None,
+                    key_path: None,
+                    jump_hosts: Vec::new(),
+                    compress: false,
+                }),
+            };
+            let json = to_json(&ToolError::session_lost(&lost));
+
+            assert_eq!(json["code"], "session_lost");
+            assert_eq!(json["retryable"], false);
+            assert_eq!(json["details"]["session_id"], "sess-1");
+            assert_eq!(
+                json["details"]["lost_session"]["reconnect"]["address"],
+                "db1"
+            );
+            assert!(
+                json["message"]
+                    .as_str()
+                    .unwrap()
+                    .contains("ssh_connect(address=\"db1\", username=\"deploy\")")
+            );
+        }
+
+        #[test]
+        fn test_wrong_replica_names_owner() {
+            let json = to_json(
+                &ToolError::wrong_replica("Session", "sess-1", "mcp-2", Some("http://mcp-2:8000"))
+                    .with_detail("session_id", "sess-1"),
+            );
+
+            assert_eq!(json["code"], "wrong_replica");
+            assert_eq!(json["retryable"], false);
+            assert_eq!(json["details"]["replica"], "mcp-2");
+            assert_eq!(json["details"]["replica_url"], "http://mcp-2:8000");
+            assert_eq!(
+                json["message"],
+                "Session sess-1 is held by ssh-mcp replica mcp-2 (http://mcp-2:8000); send the call to that replica."
+            );
+        }
+
+        #[test]
+        fn test_limit_exceeded_is_retryable() {
+            let error = ToolError::new(ErrorCode::LimitExceeded, "too many");
+            assert!(error.retryable);
+        }
+
+        #[test]
+        fn test_connection_classifies_auth_failure() {
+            let error = ToolError::connection("Authentication failed for user root");
+            assert_eq!(error.code, ErrorCode::AuthenticationFailed);
+            assert!(!error.retryable);
+        }
+
+        #[test]
+        fn test_connection_classifies_network_failure() {
+            let error = ToolError::connection("Connection refused");
+            assert_eq!(error.code, ErrorCode::ConnectionFailed);
+            assert!(error.retryable);
+        }
+
+        #[test]
+        fn test_with_stage_and_retryable_override() {
+            let error = ToolError::channel("Failed to open channel: closed")
+                .with_stage("open_channel")
+                .with_retryable(false);
+            let json = to_json(&error);
+
+            assert_eq!(json["code"], "channel_error");
+            assert_eq!(json["details"]["stage"], "open_channel");
+            assert_eq!(json["retryable"], false);
+        }
+
+        #[test]
+        fn test_is_auth_error() {
+            assert!(is_auth_error("Permission denied (publickey)"));
+            assert!(!is_auth_error("Connection refused"));
+        }
+    }
 }
@@ -0,0 +1,185 @@
+//! Settling async commands on an output pattern (`success_pattern`,
+//! `failure_pattern` of `ssh_execute`).
+//!
+//! Some commands never exit on their own when they are done: a service
+//! started in the foreground prints "ready" and keeps serving. With a
+//! `success_pattern` the command is marked `completed` as soon as its output
+//! matches, so `ssh_get_command_output(wait=true)` returns right then; a
+//! `failure_pattern` marks it `failed` the same way. The failure pattern is
+//! checked first, so output matching both counts as a failure.
+//!
+//! With `terminate_on_match` (the default) the command is then sent SIGTERM
+//! and its channel closed. Otherwise it keeps running and its output keeps
+//! being collected; it can still be cancelled or signalled, and its exit code
+//! is recorded when it exits.
+//!
+//! Patterns use `regex` crate syntax, as in `ssh_shell_expect`, and are
+//! matched against stdout and stderr separately. The last [`MATCH_WINDOW`]
+//! bytes of each stream are kept, so a match may span chunks of output.
+
+use super::expect::ExpectPattern;
+use super::types::{PatternMatch, PatternOutcome};
+
+/// Output kept per stream for matches spanning chunks
+pub(crate) const MATCH_WINDOW: usize = 4096;
+
+/// Longest matched text reported in a [`PatternMatch`]
+const MAX_MATCH_TEXT: usize = 1024;
+
+/// The patterns an async command is settled on.
+#[derive(Debug)]
+pub(crate) struct ExitPatterns {
+    success: Option<ExpectPattern>,
+    failure: Option<ExpectPattern>,
+    terminate: bool,
+}
+
+impl ExitPatterns {
+    /// Compile the `ssh_execute` parameters; `None` when neither pattern is given.
+    pub(crate) fn resolve(
+        success_pattern: Option<&str>,
+        failure_pattern: Option<&str>,
+        terminate_on_match: Option<bool>,
+    ) -> Result<Option<Self>, String> {
+        let compile = |name: &str, pattern: Option<&str>| {
+            pattern
+                .map(|pattern| {
+                    ExpectPattern::new(pattern, false).map_err(|e| format!("{}: {}", name, e))
+                })
+                .transpose()
+        };
+        let success = compile("success_pattern", success_pattern)?;
+        let failure = compile("failure_pattern", failure_pattern)?;
+        if success.is_none() && failure.is_none() {
+            if terminate_on_match.is_some() {
+                return Err(
+                    "terminate_on_match needs a success_pattern or failure_pattern".to_string(),
+                );
+            }
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            success,
+            failure,
+            terminate: terminate_on_match.unwrap_or(true),
+        }))
+    }
+
+    /// Start watching a command's output.
+    pub(crate) fn watch(self) -> PatternWatch {
+        PatternWatch {
+            patterns: self,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        }
+    }
+}
+
+/// Recent output of a command, checked against its [`ExitPatterns`].
+#[derive(Debug)]
+pub(crate) struct PatternWatch {
+    patterns: ExitPatterns,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl PatternWatch {
+    /// Add a chunk of output and report the first match, if any.
+    pub(crate) fn feed(&mut self, stderr: bool, data: &[u8]) -> Option<PatternMatch> {
+        let (window, stream) = match stderr {
+            true => (&mut self.stderr, "stderr"),
+            false => (&mut self.stdout, "stdout"),
+        };
+        window.extend_from_slice(data);
+
+        let candidates = [
+            (PatternOutcome::Failure, &self.patterns.failure),
+            (PatternOutcome::Success, &self.patterns.success),
+        ];
+        for (outcome, pattern) in candidates {
+            let Some(found) = pattern.as_ref().and_then(|pattern| pattern.find(window)) else {
+                continue;
+            };
+            let text: String = String::from_utf8_lossy(&window[found.start..found.end])
+                .chars()
+                .take(MAX_MATCH_TEXT)
+                .collect();
+            return Some(PatternMatch {
+                outcome,
+                text,
+                stream: stream.to_string(),
+                terminated: self.patterns.terminate,
+                running: !self.patterns.terminate,
+            });
+        }
+
+        // Nothing matched; only the tail can still be part of a later match
+        if window.len() > MATCH_WINDOW {
+            window.drain(..window.len() - MATCH_WINDOW);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(success: Option<&str>, failure: Option<&str>) -> PatternWatch {
+        ExitPatterns::resolve(success, failure, None)
+            .unwrap()
+            .unwrap()
+            .watch()
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert!(ExitPatterns::resolve(None, None, None).unwrap().is_none());
+        assert!(ExitPatterns::resolve(None, None, Some(false)).is_err());
+        assert!(ExitPatterns::resolve(Some("("), None, None).is_err());
+        assert!(ExitPatterns::resolve(None, Some(""), None).is_err());
+
+        let mut watch = ExitPatterns::resolve(None, Some("error"), Some(false))
+            .unwrap()
+            .unwrap()
+            .watch();
+        let found = watch.feed(false, b"error\n").unwrap();
+        assert!(!found.terminated);
+        assert!(found.running);
+    }
+
+    #[test]
+    fn test_success_match() {
+        let mut watch = watch(Some(r"listening on port \d+"), None);
+        assert_eq!(watch.feed(false, b"starting up\n"), None);
+        let found = watch.feed(false, b"listening on port 8080\n").unwrap();
+        assert_eq!(found.outcome, PatternOutcome::Success);
+        assert_eq!(found.text, "listening on port 8080");
+        assert_eq!(found.stream, "stdout");
+        assert!(found.terminated);
+        assert!(!found.running);
+    }
+
+    #[test]
+    fn test_failure_checked_first_and_streams_apart() {
+        let mut both = watch(Some("done"), Some("(?i)error"));
+        let found = both.feed(true, b"ERROR: done badly\n").unwrap();
+        assert_eq!(found.outcome, PatternOutcome::Failure);
+        assert_eq!(found.stream, "stderr");
+
+        // A match cannot be assembled from two streams
+        let mut split = watch(Some("ready"), None);
+        assert_eq!(split.feed(false, b"rea"), None);
+        assert_eq!(split.feed(true, b"dy"), None);
+        assert!(split.feed(false, b"dy").is_some());
+    }
+
+    #[test]
+    fn test_window_keeps_tail() {
+        let mut watch = watch(Some("ready"), None);
+        assert_eq!(watch.feed(false, &vec![b'x'; 3 * MATCH_WINDOW]), None);
+        assert_eq!(watch.stdout.len(), MATCH_WINDOW);
+        assert_eq!(watch.feed(false, b"re"), None);
+        assert!(watch.feed(false, b"ady").is_some());
+    }
+}
@@ -0,0 +1,220 @@
+//! Waiting for shell output to match a pattern (`ssh_shell_expect`).
+//!
+//! Instead of writing a command, sleeping and reading, an agent sends input
+//! with `ssh_shell_write` and then blocks in `ssh_shell_expect` until the
+//! shell's unread output matches a regular expression or, with
+//! `literal=true`, a plain string such as a prompt. Output up to the end of
+//! the match is consumed like a `ssh_shell_read`; whatever follows stays
+//! buffered for the next call.
+//!
+//! Patterns use `regex` crate syntax and run against the raw PTY output,
+//! including `\r\n` line ends and any ANSI escape sequences. `(?m)` makes `^`
+//! and `$` match at line boundaries.
+
+use std::time::Duration;
+
+use regex::bytes::{Regex, RegexBuilder};
+use tokio::sync::{Mutex, watch};
+
+use super::types::ShellStatus;
+
+/// Default time to wait for a match
+pub(crate) const DEFAULT_EXPECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest wait for a match
+pub(crate) const MAX_EXPECT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest accepted pattern
+const MAX_PATTERN_LEN: usize = 1024;
+
+/// Compiled size limit, so a pathological pattern is rejected up front
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// How often the buffer is checked for new output
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Resolve the wait: parameter (capped at [`MAX_EXPECT_TIMEOUT`]) -> default.
+pub(crate) fn resolve_expect_timeout(timeout_secs: Option<u64>) -> Duration {
+    timeout_secs
+        .map(|secs| Duration::from_secs(secs).min(MAX_EXPECT_TIMEOUT))
+        .unwrap_or(DEFAULT_EXPECT_TIMEOUT)
+}
+
+/// A pattern to wait for.
+#[derive(Debug)]
+pub(crate) struct ExpectPattern(Regex);
+
+/// Where a pattern matched in the output, with its capture groups.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ExpectMatch {
+    pub start: usize,
+    pub end: usize,
+    pub groups: Vec<Option<String>>,
+}
+
+impl ExpectPattern {
+    /// Compile `pattern` as a regex, or as a plain string when `literal`.
+    pub(crate) fn new(pattern: &str, literal: bool) -> Result<Self, String> {
+        if pattern.is_empty() {
+            return Err("pattern must not be empty".to_string());
+        }
+        if pattern.len() > MAX_PATTERN_LEN {
+            return Err(format!("pattern is longer than {} bytes", MAX_PATTERN_LEN));
+        }
+        let source = if literal {
+            regex::escape(pattern)
+        } else {
+            pattern.to_string()
+        };
+        RegexBuilder::new(&source)
+            .size_limit(PATTERN_SIZE_LIMIT)
+            .build()
+            .map(Self)
+            .map_err(|e| format!("Invalid pattern: {}", e))
+    }
+
+    /// The first match in `output`.
+    pub(crate) fn find(&self, output: &[u8]) -> Option<ExpectMatch> {
+        let captures = self.0.captures(output)?;
+        let whole = captures.get(0)?;
+        Some(ExpectMatch {
+            start: whole.start(),
+            end: whole.end(),
+            groups: captures
+                .iter()
+                .skip(1)
+                .map(|group| group.map(|g| String::from_utf8_lossy(g.as_bytes()).into_owned()))
+                .collect(),
+        })
+    }
+}
+
+/// Result of waiting for a pattern.
+#[derive(Debug)]
+pub(crate) struct ExpectOutcome {
+    /// Matched text and capture groups; `None` when the wait ended without a match
+    pub matched: Option<(String, Vec<Option<String>>)>,
+    /// Output before the match, or all unread output when nothing matched
+    pub before: String,
+    /// The shell closed before a match
+    pub closed: bool,
+}
+
+/// Wait until `output` matches `pattern`, the shell closes or `timeout`
+/// passes.
+///
+/// On a match the output through its end is removed from the buffer when
+/// `consume`; otherwise the buffer is left alone.
+pub(crate) async fn expect(
+    output: &Mutex<Vec<u8>>,
+    status: &watch::Receiver<ShellStatus>,
+    pattern: &ExpectPattern,
+    timeout: Duration,
+    consume: bool,
+) -> ExpectOutcome {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut checked_len = None;
+    loop {
+        // Read the status first so output that arrived before the close is checked
+        let closed = *status.borrow() != ShellStatus::Open;
+        {
+            let mut buf = output.lock().await;
+            // A concurrent ssh_shell_read may have drained the buffer; search it all
+            if checked_len != Some(buf.len()) {
+                checked_len = Some(buf.len());
+                if let Some(found) = pattern.find(&buf) {
+                    let before = String::from_utf8_lossy(&buf[..found.start]).into_owned();
+                    let text = String::from_utf8_lossy(&buf[found.start..found.end]).into_owned();
+                    if consume {
+                        buf.drain(..found.end);
+                    }
+                    return ExpectOutcome {
+                        matched: Some((text, found.groups)),
+                        before,
+                        closed: false,
+                    };
+                }
+            }
+            if closed || tokio::time::Instant::now() >= deadline {
+                return ExpectOutcome {
+                    matched: None,
+                    before: String::from_utf8_lossy(&buf).into_owned(),
+                    closed,
+                };
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_regex_with_groups() {
+        let pattern = ExpectPattern::new(r"exit=(\d+)(x)?", false).unwrap();
+        let found = pattern.find(b"building\r\nexit=0\r\n$ ").unwrap();
+        assert_eq!((found.start, found.end), (10, 16));
+        assert_eq!(found.groups, vec![Some("0".to_string()), None]);
+    }
+
+    #[test]
+    fn test_literal_escapes_metacharacters() {
+        let pattern = ExpectPattern::new("[sudo] password for", true).unwrap();
+        assert!(pattern.find(b"[sudo] password for deploy: ").is_some());
+        assert!(pattern.find(b"s password for").is_none());
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(ExpectPattern::new("", false).is_err());
+        assert!(ExpectPattern::new("(unclosed", false).is_err());
+        assert!(ExpectPattern::new(&"a".repeat(MAX_PATTERN_LEN + 1), true).is_err());
+    }
+
+    #[test]
+    fn test_timeout_is_capped() {
+        assert_eq!(resolve_expect_timeout(None), DEFAULT_EXPECT_TIMEOUT);
+        assert_eq!(resolve_expect_timeout(Some(5)), Duration::from_secs(5));
+        assert_eq!(resolve_expect_timeout(Some(86400)), MAX_EXPECT_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_output_and_consumes_through_match() {
+        let output = Arc::new(Mutex::new(b"$ make\r\n".to_vec()));
+        let (_status_tx, status_rx) = watch::channel(ShellStatus::Open);
+        let writer = output.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            writer.lock().await.extend_from_slice(b"done\r\n$ leftover");
+        });
+
+        let pattern = ExpectPattern::new("$ ", true).unwrap();
+        // The prompt already in the buffer matches at once
+        let first = expect(&output, &status_rx, &pattern, Duration::from_secs(5), true).await;
+        assert_eq!(first.matched.unwrap().0, "$ ");
+        assert_eq!(first.before, "");
+
+        let second = expect(&output, &status_rx, &pattern, Duration::from_secs(5), true).await;
+        assert_eq!(second.before, "make\r\ndone\r\n");
+        assert_eq!(*output.lock().await, b"leftover");
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_close_leave_buffer() {
+        let output = Mutex::new(b"no prompt yet".to_vec());
+        let (status_tx, status_rx) = watch::channel(ShellStatus::Open);
+        let pattern = ExpectPattern::new("#", true).unwrap();
+
+        let timed_out = expect(&output, &status_rx, &pattern, Duration::ZERO, true).await;
+        assert!(timed_out.matched.is_none() && !timed_out.closed);
+        assert_eq!(timed_out.before, "no prompt yet");
+
+        status_tx.send(ShellStatus::Closed).unwrap();
+        let closed = expect(&output, &status_rx, &pattern, Duration::from_secs(5), true).await;
+        assert!(closed.closed);
+        assert_eq!(*output.lock().await, b"no prompt yet");
+    }
+}
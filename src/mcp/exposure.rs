@@ -0,0 +1,420 @@
+//! Config-driven tool exposure.
+//!
+//! Operators can ship a restricted flavor of the server (read-only, execute-only,
+//! no interactive shells, ...) without rebuilding with different feature flags.
+//! The `[tools]` section of the server configuration file lists tool names or
+//! group names:
+//!
+//! ```toml
+//! [tools]
+//! # Expose only these tools/groups (default: everything)
+//! enabled = ["session", "execute"]
+//! # Hide these tools/groups (applied after `enabled`)
+//! disabled = ["ssh_cancel_command"]
+//! ```
+//!
+//! Disabled tools are omitted from `tools/list` and calls to them are rejected
+//! with a JSON-RPC "method not found" error. With `SSH_READ_ONLY=true` the
+//! write tools of [`WRITE_TOOLS`] are disabled as well.
+//!
+//! A tool name prefix (`prefix = "prod_"` or `SSH_MCP_TOOL_PREFIX`) lets several
+//! instances attach to one MCP client without name collisions: `ssh_connect` is
+//! exposed as `prod_ssh_connect`, and tool descriptions that mention other tools
+//! are rewritten to the prefixed names.
+
+use std::collections::HashSet;
+
+use poem_mcpserver::tool::Tools;
+use serde_json::Value;
+use tracing::warn;
+
+use super::commands::McpSSHCommands;
+use super::config::ToolsConfig;
+use super::read_only::WRITE_TOOLS;
+
+/// Named groups of tools that can be enabled or disabled together.
+pub const TOOL_GROUPS: &[(&str, &[&str])] = &[
+    (
+        "session",
+        &[
+            "ssh_connect",
+            "ssh_connect_many",
+            "ssh_disconnect",
+            "ssh_list_sessions",
+            "ssh_update_session",
+            "ssh_select_session",
+            "ssh_capabilities",
+            "ssh_check_config",
+            "ssh_list_managed_hosts",
+            "ssh_list_profiles",
+            "ssh_usage",
+            "ssh_get_audit_log",
+            "ssh_disconnect_agent",
+            "ssh_lock_session",
+            "ssh_unlock_session",
+        ],
+    ),
+    (
+        "execute",
+        &[
+            "ssh_execute",
+            "ssh_execute_sync",
+            "ssh_execute_parsed",
+            "ssh_run_helper",
+            "ssh_get_command_output",
+            "ssh_wait_commands",
+            "ssh_tail_file",
+            "ssh_tail_read",
+            "ssh_execute_dag",
+            "ssh_execute_batch",
+            "ssh_check_privileges",
+            "ssh_gather_facts",
+            "ssh_verify_privilege_drop",
+            "ssh_reboot_and_wait",
+            "ssh_list_commands",
+            "ssh_cancel_command",
+            "ssh_command_signal",
+            "ssh_cancel_session_commands",
+        ],
+    ),
+    (
+        "shell",
+        &[
+            "ssh_shell_open",
+            "ssh_shell_write",
+            "ssh_shell_read",
+            "ssh_shell_expect",
+            "ssh_shell_signal",
+            "ssh_shell_subscribe",
+            "ssh_shell_unsubscribe",
+            "ssh_shell_close",
+            "ssh_shell_switch_user",
+            "ssh_open_console",
+            "ssh_tmux_attach",
+            "ssh_tmux_list",
+        ],
+    ),
+    (
+        "forward",
+        &[
+            "ssh_forward",
+            "ssh_cancel_forward",
+            "ssh_list_forwards",
+            "ssh_reverse_forward",
+            "ssh_cancel_reverse_forward",
+            "ssh_list_reverse_forwards",
+            "ssh_check_port",
+        ],
+    ),
+    (
+        "transfer",
+        &[
+            "ssh_symlink",
+            "ssh_readlink",
+            "ssh_glob",
+            "ssh_list_dir",
+            "ssh_manifest",
+            "ssh_upload_dir",
+            "ssh_collect",
+            "ssh_copy_between",
+            "ssh_write_file",
+            "ssh_patch_file",
+        ],
+    ),
+];
+
+/// Resolved set of tools hidden from clients and the exposed name prefix.
+#[derive(Debug, Clone, Default)]
+pub struct ToolExposure {
+    disabled: HashSet<&'static str>,
+    prefix: String,
+}
+
+impl ToolExposure {
+    /// Resolve the `[tools]` configuration against the tools this build provides.
+    pub fn from_config(config: &ToolsConfig) -> Self {
+        let all_tools: Vec<&'static str> = McpSSHCommands::list()
+            .into_iter()
+            .map(|tool| tool.name)
+            .collect();
+
+        let enabled: HashSet<&'static str> = match &config.enabled {
+            Some(entries) => entries
+                .iter()
+                .flat_map(|entry| expand_entry(entry, &all_tools))
+                .collect(),
+            None => all_tools.iter().copied().collect(),
+        };
+
+        let mut disabled: HashSet<&'static str> = all_tools
+            .iter()
+            .copied()
+            .filter(|name| !enabled.contains(name))
+            .collect();
+        disabled.extend(
+            config
+                .disabled
+                .iter()
+                .flat_map(|entry| expand_entry(entry, &all_tools)),
+        );
+
+        Self {
+            disabled,
+            prefix: String::new(),
+        }
+    }
+
+    /// Hide the write tools when the server is read-only (`SSH_READ_ONLY`).
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        if read_only {
+            self.disabled.extend(WRITE_TOOLS);
+        }
+        self
+    }
+
+    /// Set the prefix prepended to every exposed tool name.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Whether a tool may be listed and called.
+    pub fn is_enabled(&self, tool_name: &str) -> bool {
+        !self.disabled.contains(tool_name)
+    }
+
+    /// Exposed names (prefix applied) of the enabled tools, in registration order.
+    pub fn exposed_tools(&self) -> Vec<String> {
+        McpSSHCommands::list()
+            .into_iter()
+            .filter(|tool| self.is_enabled(tool.name))
+            .map(|tool| format!("{}{}", self.prefix, tool.name))
+            .collect()
+    }
+
+    /// Names of the disabled tools.
+    pub fn disabled(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.disabled.iter().copied()
+    }
+
+    /// Map an exposed (possibly prefixed) tool name back to the internal name.
+    ///
+    /// Returns `None` when the name lacks the configured prefix.
+    pub fn internal_name<'a>(&self, exposed_name: &'a str) -> Option<&'a str> {
+        exposed_name.strip_prefix(self.prefix.as_str())
+    }
+
+    /// Apply the prefix to tool names and tool mentions in a `tools/list` result.
+    pub fn prefix_tools_list(&self, result: &mut Value) {
+        if self.prefix.is_empty() {
+            return;
+        }
+        let Some(tools) = result.get_mut("tools").and_then(Value::as_array_mut) else {
+            return;
+        };
+
+        for tool in tools {
+            let Some(object) = tool.as_object_mut() else {
+                continue;
+            };
+            for field in ["name", "description"] {
+                if let Some(Value::String(text)) = object.get_mut(field) {
+                    *text = self.prefix_tool_mentions(text);
+                }
+            }
+        }
+    }
+
+    /// Prefix every whole-word mention of a known tool name in `text`.
+    fn prefix_tool_mentions(&self, text: &str) -> String {
+        let known: HashSet<&'static str> = McpSSHCommands::list()
+            .into_iter()
+            .map(|tool| tool.name)
+            .collect();
+
+        let mut output = String::with_capacity(text.len());
+        let mut word = String::new();
+        for c in text.chars().chain(std::iter::once('\0')) {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                word.push(c);
+                continue;
+            }
+            if known.contains(word.as_str()) {
+                output.push_str(&self.prefix);
+            }
+            output.push_str(&word);
+            word.clear();
+            if c != '\0' {
+                output.push(c);
+            }
+        }
+        output
+    }
+}
+
+/// Expand a tool or group name into the matching tools of this build.
+fn expand_entry(entry: &str, all_tools: &[&'static str]) -> Vec<&'static str> {
+    if let Some((_, members)) = TOOL_GROUPS.iter().find(|(group, _)| *group == entry) {
+        return all_tools
+            .iter()
+            .copied()
+            .filter(|name| members.contains(name))
+            .collect();
+    }
+
+    match all_tools.iter().find(|name| **name == entry) {
+        Some(name) => vec![*name],
+        None => {
+            warn!(
+                "Unknown tool or tool group in [tools] configuration: {}",
+                entry
+            );
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(enabled: Option<&[&str]>, disabled: &[&str]) -> ToolsConfig {
+        ToolsConfig {
+            enabled: enabled.map(|e| e.iter().map(|s| s.to_string()).collect()),
+            disabled: disabled.iter().map(|s| s.to_string()).collect(),
+            prefix: None,
+        }
+    }
+
+    mod groups {
+        use super::*;
+
+        #[test]
+        fn test_every_tool_belongs_to_a_group() {
+            for tool in McpSSHCommands::list() {
+                assert!(
+                    TOOL_GROUPS
+                        .iter()
+                        .any(|(_, members)| members.contains(&tool.name)),
+                    "{} is not in any tool group",
+                    tool.name
+                );
+            }
+        }
+    }
+
+    mod exposure {
+        use super::*;
+
+        #[test]
+        fn test_default_enables_everything() {
+            let exposure = ToolExposure::from_config(&ToolsConfig::default());
+            assert_eq!(exposure.disabled().count(), 0);
+            assert!(exposure.is_enabled("ssh_execute"));
+        }
+
+        #[test]
+        fn test_exposed_tools_apply_prefix_and_skip_disabled() {
+            let exposure =
+                ToolExposure::from_config(&config(None, &["ssh_execute"])).with_prefix("prod_");
+            let tools = exposure.exposed_tools();
+            assert!(tools.contains(&"prod_ssh_connect".to_string()));
+            assert!(!tools.iter().any(|tool| tool.ends_with("ssh_execute")));
+        }
+
+        #[test]
+        fn test_disable_group() {
+            let exposure = ToolExposure::from_config(&config(None, &["shell"]));
+            assert!(!exposure.is_enabled("ssh_shell_open"));
+            assert!(!exposure.is_enabled("ssh_shell_write"));
+            assert!(exposure.is_enabled("ssh_execute"));
+        }
+
+        #[test]
+        fn test_enable_allowlist() {
+            let exposure =
+                ToolExposure::from_config(&config(Some(&["session", "ssh_execute"]), &[]));
+            assert!(exposure.is_enabled("ssh_connect"));
+            assert!(exposure.is_enabled("ssh_execute"));
+            assert!(!exposure.is_enabled("ssh_get_command_output"));
+            assert!(!exposure.is_enabled("ssh_shell_open"));
+        }
+
+        #[test]
+        fn test_read_only_hides_write_tools() {
+            let exposure = ToolExposure::from_config(&ToolsConfig::default()).with_read_only(true);
+            assert!(!exposure.is_enabled("ssh_shell_write"));
+            assert!(!exposure.is_enabled("ssh_upload_dir"));
+            assert!(exposure.is_enabled("ssh_execute"));
+            assert_eq!(exposure.disabled().count(), WRITE_TOOLS.len());
+        }
+
+        #[test]
+        fn test_disabled_applies_after_enabled() {
+            let exposure =
+                ToolExposure::from_config(&config(Some(&["execute"]), &["ssh_cancel_command"]));
+            assert!(exposure.is_enabled("ssh_execute"));
+            assert!(!exposure.is_enabled("ssh_cancel_command"));
+        }
+
+        #[test]
+        fn test_unknown_entries_are_ignored() {
+            let exposure = ToolExposure::from_config(&config(None, &["no_such_tool"]));
+            assert_eq!(exposure.disabled().count(), 0);
+        }
+
+        #[test]
+        fn test_empty_allowlist_disables_everything() {
+            let exposure = ToolExposure::from_config(&config(Some(&[]), &[]));
+            assert_eq!(exposure.disabled().count(), McpSSHCommands::list().len());
+        }
+    }
+
+    mod prefix {
+        use super::*;
+        use serde_json::json;
+
+        #[test]
+        fn test_internal_name_strips_prefix() {
+            let exposure = ToolExposure::default().with_prefix("prod_");
+            assert_eq!(
+                exposure.internal_name("prod_ssh_connect"),
+                Some("ssh_connect")
+            );
+            assert_eq!(exposure.internal_name("ssh_connect"), None);
+        }
+
+        #[test]
+        fn test_no_prefix_is_identity() {
+            let exposure = ToolExposure::default();
+            assert_eq!(exposure.internal_name("ssh_connect"), Some("ssh_connect"));
+        }
+
+        #[test]
+        fn test_prefix_tools_list_renames_and_rewrites_mentions() {
+            let exposure = ToolExposure::default().with_prefix("prodssh_");
+            let mut result = json!({
+                "tools": [{
+                    "name": "ssh_execute",
+                    "description": "Poll with ssh_get_command_output, not ssh_get_command_output_x."
+                }]
+            });
+
+            exposure.prefix_tools_list(&mut result);
+
+            assert_eq!(result["tools"][0]["name"], "prodssh_ssh_execute");
+            assert_eq!(
+                result["tools"][0]["description"],
+                "Poll with prodssh_ssh_get_command_output, not ssh_get_command_output_x."
+            );
+        }
+
+        #[test]
+        fn test_empty_prefix_leaves_list_untouched() {
+            let exposure = ToolExposure::default();
+            let mut result = json!({"tools": [{"name": "ssh_execute", "description": "d"}]});
+            exposure.prefix_tools_list(&mut result);
+            assert_eq!(result["tools"][0]["name"], "ssh_execute");
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Host facts probe (`ssh_gather_facts`).
+//!
+//! One command collects what agents otherwise piece together from a dozen
+//! calls and per-distro output formats: OS release, kernel, CPUs, load,
+//! memory, filesystems, uptime, package manager, init system and
+//! virtualization. The probe only reads, so it also runs on read-only
+//! sessions.
+//!
+//! Each probe prints a `@@<section>` marker followed by its raw output, and
+//! every probe tolerates missing tools, so a fact the host cannot provide is
+//! simply absent. Linux is covered fully from `/proc` and `/etc/os-release`;
+//! other Unix systems (macOS, the BSDs) get what `uname`, `sysctl`, `df` and
+//! `sw_vers` report.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::client;
+
+use super::client::execute_ssh_command;
+use super::session::SshClientHandler;
+use super::types::{CpuFacts, DiskFacts, HostFacts, KernelFacts, MemoryFacts, OsFacts};
+
+/// Upper bound for the probe; `df` can hang on a dead network mount.
+pub(crate) const FACTS_PROBE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Probe printing every section behind its `@@<name>` marker.
+pub(crate) const FACTS_PROBE_COMMAND: &str = "echo '@@hostname'; hostname 2>/dev/null || uname -n; \
+     echo '@@os'; cat /etc/os-release 2>/dev/null || cat /usr/lib/os-release 2>/dev/null \
+     || sw_vers 2>/dev/null; \
+     echo '@@uname'; uname -s; uname -r; uname -m; \
+     echo '@@cpus'; getconf _NPROCESSORS_ONLN 2>/dev/null || nproc 2>/dev/null \
+     || sysctl -n hw.ncpu 2>/dev/null; \
+     echo '@@cpu_model'; grep -m1 -E '^(model name|Hardware|cpu model|Model)' /proc/cpuinfo 2>/dev/null \
+     || sysctl -n machdep.cpu.brand_string hw.model 2>/dev/null; \
+     echo '@@loadavg'; cat /proc/loadavg 2>/dev/null || sysctl -n vm.loadavg 2>/dev/null; \
+     echo '@@meminfo'; grep -E '^(MemTotal|MemAvailable|SwapTotal|SwapFree):' /proc/meminfo 2>/dev/null \
+     || { m=$(sysctl -n hw.memsize hw.physmem 2>/dev/null | head -n1) && [ -n \"$m\" ] \
+     && echo \"MemTotal: $((m / 1024)) kB\"; }; \
+     echo '@@uptime'; cat /proc/uptime 2>/dev/null; \
+     echo '@@df'; LC_ALL=C df -P -k 2>/dev/null; \
+     echo '@@pkg'; for pm in apt-get dnf yum zypper apk pacman emerge xbps-install brew pkg; do \
+     command -v $pm >/dev/null 2>&1 && echo $pm; done; \
+     echo '@@init'; cat /proc/1/comm 2>/dev/null || ps -o comm= -p 1 2>/dev/null; \
+     echo '@@virt'; systemd-detect-virt 2>/dev/null || { [ -f /.dockerenv ] && echo docker; }; \
+     true";
+
+/// Filesystems that hold no persistent data
+const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "tmpfs", "devtmpfs", "udev", "overlay", "shm", "none", "devfs", "efivarfs", "cgroup",
+];
+
+/// Mount points of kernel and runtime filesystems
+const PSEUDO_MOUNTS: &[&str] = &["/dev", "/proc", "/sys", "/run", "/snap", "/System/Volumes"];
+
+/// Split probe output into its sections.
+fn sections(stdout: &str) -> HashMap<&str, Vec<&str>> {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current = None;
+    for line in stdout.lines() {
+        if let Some(name) = line.strip_prefix("@@") {
+            current = Some(name.trim());
+            sections.entry(name.trim()).or_default();
+        } else if let Some(name) = current
+            && !line.trim().is_empty()
+        {
+            sections.entry(name).or_default().push(line.trim_end());
+        }
+    }
+    sections
+}
+
+/// Parse the probe output.
+pub(crate) fn parse_facts(stdout: &str) -> HostFacts {
+    let sections = sections(stdout);
+    let section = |name: &str| sections.get(name).map(Vec::as_slice).unwrap_or_default();
+    let first = |name: &str| {
+        section(name)
+            .first()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+    };
+
+    let uname = section("uname");
+    let uname_field = |index: usize| uname.get(index).map(|field| field.trim().to_string());
+    HostFacts {
+        hostname: first("hostname"),
+        os: parse_os(section("os")),
+        kernel: KernelFacts {
+            name: uname_field(0),
+            release: uname_field(1),
+            arch: uname_field(2),
+        },
+        cpu: CpuFacts {
+            count: first("cpus").and_then(|count| count.parse().ok()),
+            model: first("cpu_model").map(|line| match line.split_once(':') {
+                Some((_, model)) => model.trim().to_string(),
+                None => line,
+            }),
+            load_average: first("loadavg")
+                .map(|line| {
+                    line.trim_matches(|c| c == '{' || c == '}' || c == ' ')
+                        .split_whitespace()
+                        .take(3)
+                        .map_while(|value| value.parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        },
+        memory: parse_meminfo(section("meminfo")),
+        disks: parse_df(section("df")),
+        uptime_secs: first("uptime")
+            .and_then(|line| line.split_whitespace().next()?.parse::<f64>().ok())
+            .map(|secs| secs as u64),
+        package_manager: first("pkg").map(|pm| match pm.as_str() {
+            "apt-get" => "apt".to_string(),
+            "xbps-install" => "xbps".to_string(),
+            _ => pm,
+        }),
+        init_system: first("init").map(|init| {
+            // ps may report the full path, e.g. /sbin/launchd
+            init.rsplit('/').next().unwrap_or(&init).to_string()
+        }),
+        virtualization: first("virt").filter(|virt| virt != "none"),
+    }
+}
+
+/// Parse `/etc/os-release` (`KEY=value`, optionally quoted) or `sw_vers` output.
+fn parse_os(lines: &[&str]) -> OsFacts {
+    let mut values = HashMap::new();
+    for line in lines {
+        if let Some((key, value)) = line.split_once('=') {
+            values.insert(
+                key.trim(),
+                value.trim().trim_matches(['"', '\'']).to_string(),
+            );
+        } else if let Some((key, value)) = line.split_once(':') {
+            values.insert(key.trim(), value.trim().to_string());
+        }
+    }
+    if let Some(product) = values.get("ProductName") {
+        let version = values.get("ProductVersion").cloned();
+        return OsFacts {
+            id: Some("macos".to_string()),
+            id_like: Vec::new(),
+            name: Some(match &version {
+                Some(version) => format!("{} {}", product, version),
+                None => product.clone(),
+            }),
+            version,
+        };
+    }
+    OsFacts {
+        id: values.get("ID").cloned(),
+        id_like: values
+            .get("ID_LIKE")
+            .map(|like| like.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+        name: values
+            .get("PRETTY_NAME")
+            .or_else(|| values.get("NAME"))
+            .cloned(),
+        version: values.get("VERSION_ID").cloned(),
+    }
+}
+
+/// Parse `/proc/meminfo` lines (`MemTotal:  16314216 kB`).
+fn parse_meminfo(lines: &[&str]) -> MemoryFacts {
+    let mut values = HashMap::new();
+    for line in lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut parts = value.split_whitespace();
+        let Some(amount) = parts.next().and_then(|amount| amount.parse::<u64>().ok()) else {
+            continue;
+        };
+        let bytes = match parts.next() {
+            Some("kB") => amount * 1024,
+            _ => amount,
+        };
+        values.insert(key.trim(), bytes);
+    }
+    MemoryFacts {
+        total_bytes: values.get("MemTotal").copied(),
+        available_bytes: values.get("MemAvailable").copied(),
+        swap_total_bytes: values.get("SwapTotal").copied(),
+        swap_free_bytes: values.get("SwapFree").copied(),
+    }
+}
+
+/// Parse `df -P -k` output, leaving out pseudo filesystems.
+fn parse_df(lines: &[&str]) -> Vec<DiskFacts> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let kib = |index: usize| fields[index].parse::<u64>().ok().map(|kib| kib * 1024);
+            // Header and wrapped lines do not parse
+            let (total_bytes, used_bytes, available_bytes) = (kib(1)?, kib(2)?, kib(3)?);
+            // Mount points may contain spaces
+            let mount = fields[5..].join(" ");
+            let filesystem = fields[0].to_string();
+            let pseudo = PSEUDO_FILESYSTEMS.contains(&filesystem.as_str())
+                || filesystem.starts_with("map ")
+                || PSEUDO_MOUNTS
+                    .iter()
+                    .any(|prefix| mount == *prefix || mount.starts_with(&format!("{}/", prefix)));
+            if pseudo || total_bytes == 0 {
+                return None;
+            }
+            Some(DiskFacts {
+                mount,
+                filesystem,
+                total_bytes,
+                used_bytes,
+                available_bytes,
+                used_percent: fields[4].trim_end_matches('%').parse().ok(),
+            })
+        })
+        .collect()
+}
+
+/// Names of the facts the host did not provide.
+pub(crate) fn unavailable_facts(facts: &HostFacts) -> Vec<String> {
+    [
+        ("os", facts.os.id.is_none() && facts.os.name.is_none()),
+        ("kernel", facts.kernel.release.is_none()),
+        ("cpu", facts.cpu.count.is_none()),
+        ("memory", facts.memory.total_bytes.is_none()),
+        ("disks", facts.disks.is_empty()),
+        ("uptime", facts.uptime_secs.is_none()),
+        ("package_manager", facts.package_manager.is_none()),
+    ]
+    .into_iter()
+    .filter(|(_, missing)| *missing)
+    .map(|(name, _)| name.to_string())
+    .collect()
+}
+
+/// One-line summary, e.g. `Ubuntu 24.04.1 LTS, Linux 6.8.0-45-generic x86_64, 8 CPU(s), 15.6 GiB memory`.
+pub(crate) fn facts_summary(facts: &HostFacts) -> String {
+    let mut parts = Vec::new();
+    if let Some(name) = facts.os.name.as_ref().or(facts.os.id.as_ref()) {
+        parts.push(name.clone());
+    }
+    let kernel: Vec<&str> = [
+        &facts.kernel.name,
+        &facts.kernel.release,
+        &facts.kernel.arch,
+    ]
+    .into_iter()
+    .filter_map(|field| field.as_deref())
+    .collect();
+    if !kernel.is_empty() {
+        parts.push(kernel.join(" "));
+    }
+    if let Some(count) = facts.cpu.count {
+        parts.push(format!("{} CPU(s)", count));
+    }
+    if let Some(total) = facts.memory.total_bytes {
+        parts.push(format!(
+            "{:.1} GiB memory",
+            total as f64 / (1024.0 * 1024.0 * 1024.0)
+        ));
+    }
+    if parts.is_empty() {
+        "No facts could be gathered".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Run the facts probe on a session.
+pub(crate) async fn probe_facts(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+) -> Result<HostFacts, String> {
+    let response = execute_ssh_command(handle, FACTS_PROBE_COMMAND, FACTS_PROBE_TIMEOUT).await?;
+    if response.timed_out {
+        return Err(format!(
+            "Facts probe timed out after {}s",
+            FACTS_PROBE_TIMEOUT.as_secs()
+        ));
+    }
+    Ok(parse_facts(&response.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UBUNTU: &str = "@@hostname
+web-1
+@@os
+PRETTY_NAME=\"Ubuntu 24.04.1 LTS\"
+NAME=\"Ubuntu\"
+VERSION_ID=\"24.04\"
+ID=ubuntu
+ID_LIKE=debian
+@@uname
+Linux
+6.8.0-45-generic
+x86_64
+@@cpus
+8
+@@cpu_model
+model name\t: Intel(R) Xeon(R) Gold 6230 CPU @ 2.10GHz
+@@loadavg
+0.42 0.35 0.30 1/512 12345
+@@meminfo
+MemTotal:       16314216 kB
+MemAvailable:   12001000 kB
+SwapTotal:             0 kB
+SwapFree:              0 kB
+@@uptime
+273632.51 2153210.02
+@@df
+Filesystem     1024-blocks     Used Available Capacity Mounted on
+tmpfs              1631424     1620   1629804       1% /run
+/dev/sda1         40581564 20290782  20290782      50% /
+/dev/sdb1        103081248  1024000 102057248       1% /srv/data files
+/dev/loop3           65536    65536         0     100% /snap/core22/1621
+@@pkg
+apt-get
+@@init
+systemd
+@@virt
+kvm
+";
+
+    #[test]
+    fn test_parse_linux() {
+        let facts = parse_facts(UBUNTU);
+        assert_eq!(facts.hostname.as_deref(), Some("web-1"));
+        assert_eq!(
+            facts.os,
+            OsFacts {
+                id: Some("ubuntu".to_string()),
+                id_like: vec!["debian".to_string()],
+                name: Some("Ubuntu 24.04.1 LTS".to_string()),
+                version: Some("24.04".to_string()),
+            }
+        );
+        assert_eq!(facts.kernel.arch.as_deref(), Some("x86_64"));
+        assert_eq!(facts.cpu.count, Some(8));
+        assert_eq!(
+            facts.cpu.model.as_deref(),
+            Some("Intel(R) Xeon(R) Gold 6230 CPU @ 2.10GHz")
+        );
+        assert_eq!(facts.cpu.load_average, vec![0.42, 0.35, 0.30]);
+        assert_eq!(facts.memory.total_bytes, Some(16314216 * 1024));
+        assert_eq!(facts.memory.swap_total_bytes, Some(0));
+        assert_eq!(
+            facts
+                .disks
+                .iter()
+                .map(|disk| disk.mount.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/", "/srv/data files"]
+        );
+        assert_eq!(facts.disks[0].used_percent, Some(50));
+        assert_eq!(facts.disks[0].total_bytes, 40581564 * 1024);
+        assert_eq!(facts.uptime_secs, Some(273632));
+        assert_eq!(facts.package_manager.as_deref(), Some("apt"));
+        assert_eq!(facts.init_system.as_deref(), Some("systemd"));
+        assert_eq!(facts.virtualization.as_deref(), Some("kvm"));
+        assert!(unavailable_facts(&facts).is_empty());
+        assert_eq!(
+            facts_summary(&facts),
+            "Ubuntu 24.04.1 LTS, Linux 6.8.0-45-generic x86_64, 8 CPU(s), 15.6 GiB memory"
+        );
+    }
+
+    #[test]
+    fn test_parse_macos_and_missing_sections() {
+        let facts = parse_facts(
+            "@@hostname\nmac\n@@os\nProductName:\tmacOS\nProductVersion:\t14.6.1\nBuildVersion:\t23G93\n\
+             @@uname\nDarwin\n23.6.0\narm64\n@@cpus\n10\n@@loadavg\n{ 1.52 1.61 1.70 }\n\
+             @@meminfo\nMemTotal: 16777216 kB\n@@uptime\n@@pkg\nbrew\n@@init\n/sbin/launchd\n@@virt\nnone\n",
+        );
+        assert_eq!(facts.os.id.as_deref(), Some("macos"));
+        assert_eq!(facts.os.name.as_deref(), Some("macOS 14.6.1"));
+        assert_eq!(facts.cpu.load_average, vec![1.52, 1.61, 1.70]);
+        assert_eq!(facts.memory.total_bytes, Some(16 * 1024 * 1024 * 1024));
+        assert_eq!(facts.init_system.as_deref(), Some("launchd"));
+        assert!(facts.virtualization.is_none());
+        assert_eq!(unavailable_facts(&facts), vec!["disks", "uptime"]);
+
+        let empty = parse_facts("");
+        assert_eq!(empty, HostFacts::default());
+        assert_eq!(facts_summary(&empty), "No facts could be gathered");
+    }
+}
@@ -19,18 +19,41 @@
 //!    with `tokio::select!` to handle both directions concurrently until either
 //!    side closes the connection.
 //!
+//! # Multi-hop Sessions
+//!
+//! Channels are always opened on the session's own handle, so the destination
+//! is resolved and dialled by the last SSH server of the connection. A session
+//! whose transport is itself carried over another SSH connection (a bastion
+//! hop) therefore needs no extra plumbing here: only the handle of the final
+//! hop may be passed in, never an intermediate one.
+//!
+//! # Lifecycle
+//!
+//! Listeners are registered in `FORWARD_STORAGE` under a forward ID, so
+//! `ssh_list_forwards` can enumerate them and `ssh_cancel_forward` can stop
+//! one. Cancelling a forward, or disconnecting its session, stops the listener
+//! and its open connections, releasing the local port.
+//!
 //! # Feature Gate
 //!
 //! This module is only compiled when the `port_forward` feature is enabled.
 
-use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use russh::client;
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error};
+use uuid::Uuid;
 
+use super::client::channel_step;
+use super::config::resolve_channel_open_timeout;
+use super::rate_limit::{Bandwidth, Throttled};
 use super::session::SshClientHandler;
+use super::storage::{FORWARD_STORAGE, ForwardStorage, RunningForward};
+use super::traffic::{ByteCounters, CountingStream};
+use super::types::ForwardInfo;
 
 /// Sets up port forwarding from a local port to a remote destination via SSH.
 ///
@@ -39,20 +62,27 @@ use super::session::SshClientHandler;
 ///
 /// # Arguments
 ///
+/// * `session_id` - Session the listener is registered under in `FORWARD_STORAGE`
 /// * `handle_arc` - Arc-wrapped mutex containing the SSH client handle
+/// * `traffic` - Byte counters of the session; forwarded writes count as sent
+/// * `bandwidth` - Rate limit shared by all forwarded connections, if any
 /// * `local_port` - The local port to listen on (binds to 127.0.0.1)
 /// * `remote_address` - The remote host to forward connections to
 /// * `remote_port` - The remote port to forward connections to
 ///
 /// # Returns
 ///
-/// Returns the actual bound socket address on success, or an error message on failure.
+/// Returns the registered forward, with the actual bound local address, on
+/// success, or an error message on failure.
 pub(crate) async fn setup_port_forwarding(
+    session_id: &str,
     handle_arc: Arc<client::Handle<SshClientHandler>>,
+    traffic: Arc<ByteCounters>,
+    bandwidth: Option<Arc<Bandwidth>>,
     local_port: u16,
     remote_address: &str,
     remote_port: u16,
-) -> Result<SocketAddr, String> {
+) -> Result<ForwardInfo, String> {
     // Create a TCP listener for the local port
     let listener_addr = format!("127.0.0.1:{}", local_port);
     let listener = TcpListener::bind(&listener_addr)
@@ -64,31 +94,63 @@ pub(crate) async fn setup_port_forwarding(
         .map_err(|e| format!("Failed to get local address: {}", e))?;
 
     let remote_addr_clone = remote_address.to_string();
+    let token = CancellationToken::new();
+    let connections = Arc::new(AtomicU64::new(0));
+    let info = ForwardInfo {
+        forward_id: Uuid::new_v4().to_string(),
+        session_id: session_id.to_string(),
+        local_address: local_addr.to_string(),
+        remote_address: format!("{}:{}", remote_address, remote_port),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        connections: 0,
+        rate_limit_bytes_per_sec: bandwidth.as_ref().map(|limit| limit.bytes_per_sec()),
+    };
+    FORWARD_STORAGE.register(
+        info.forward_id.clone(),
+        RunningForward {
+            info: info.clone(),
+            cancel_token: token.clone(),
+            connections: connections.clone(),
+        },
+    );
 
     // Spawn an async task to handle port forwarding connections
+    let forward_id = info.forward_id.clone();
     tokio::spawn(async move {
         debug!("Port forwarding active on {}", local_addr);
 
         loop {
-            match listener.accept().await {
+            let accepted = tokio::select! {
+                _ = token.cancelled() => break,
+                accepted = listener.accept() => accepted,
+            };
+            match accepted {
                 Ok((local_stream, client_addr)) => {
                     debug!("New connection from {} to forwarded port", client_addr);
+                    connections.fetch_add(1, Ordering::Relaxed);
 
                     // Clone handle arc for this connection
                     let handle_arc = handle_arc.clone();
+                    let traffic = traffic.clone();
+                    let bandwidth = bandwidth.clone();
                     let remote_host = remote_addr_clone.clone();
+                    let token = token.clone();
 
                     // Spawn a task for each connection
                     tokio::spawn(async move {
-                        if let Err(e) = handle_port_forward_connection(
+                        let forwarded = handle_port_forward_connection(
                             handle_arc,
+                            traffic,
+                            bandwidth,
                             local_stream,
                             &remote_host,
                             remote_port,
-                        )
-                        .await
-                        {
-                            debug!("Port forwarding connection error: {}", e);
+                        );
+                        tokio::select! {
+                            _ = token.cancelled() => {}
+                            result = forwarded => if let Err(e) = result {
+                                debug!("Port forwarding connection error: {}", e);
+                            }
                         }
                     });
                 }
@@ -98,9 +160,11 @@ pub(crate) async fn setup_port_forwarding(
                 }
             }
         }
+        // A failed listener is gone; a cancelled one was already unregistered
+        FORWARD_STORAGE.unregister(&forward_id);
     });
 
-    Ok(local_addr)
+    Ok(info)
 }
 
 /// Handles a single port forwarding connection using async I/O.
@@ -110,23 +174,30 @@ pub(crate) async fn setup_port_forwarding(
 /// SSH channel.
 async fn handle_port_forward_connection(
     handle_arc: Arc<client::Handle<SshClientHandler>>,
+    traffic: Arc<ByteCounters>,
+    bandwidth: Option<Arc<Bandwidth>>,
     local_stream: tokio::net::TcpStream,
     remote_host: &str,
     remote_port: u16,
 ) -> Result<(), String> {
     // Open a direct-tcpip channel to the remote destination
-    let channel = handle_arc
-        .channel_open_direct_tcpip(
+    let channel = channel_step(
+        "open direct-tcpip channel",
+        resolve_channel_open_timeout(),
+        handle_arc.channel_open_direct_tcpip(
             remote_host,
             remote_port as u32,
             "127.0.0.1",
             0, // Local originator port (not significant for direct-tcpip)
-        )
-        .await
-        .map_err(|e| format!("Failed to open direct-tcpip channel: {}", e))?;
+        ),
+    )
+    .await?;
 
-    // Convert channel to stream for bidirectional I/O
-    let channel_stream = channel.into_stream();
+    // Convert channel to stream for bidirectional I/O; writes count as sent
+    let channel_stream = Throttled::new(
+        CountingStream::new(channel.into_stream(), traffic),
+        bandwidth,
+    );
 
     // Split both streams for bidirectional forwarding
     let (mut local_read, mut local_write) = tokio::io::split(local_stream);
@@ -153,3 +224,93 @@ async fn handle_port_forward_connection(
     debug!("Port forwarding connection closed");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::mcp::reverse::ReverseForwards;
+    use crate::mcp::test_server::{TEST_PASSWORD, connect, start_server};
+    use crate::mcp::types::JumpHost;
+
+    /// Start a TCP server echoing what it reads.
+    async fn start_echo() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        port
+    }
+
+    async fn round_trip(address: &str, payload: &[u8]) -> Vec<u8> {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(payload).await.unwrap();
+        let mut echoed = vec![0; payload.len()];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut echoed))
+            .await
+            .unwrap()
+            .unwrap();
+        echoed
+    }
+
+    #[tokio::test]
+    async fn test_forwards_over_jump_chain() {
+        let (bastion, bastion_address) = start_server().await;
+        let (target, target_address) = start_server().await;
+        let echo_port = start_echo().await;
+
+        let reverse = Arc::new(ReverseForwards::default());
+        let traffic = Arc::new(ByteCounters::default());
+        let jump = JumpHost {
+            address: bastion_address,
+            username: None,
+            password: Some(TEST_PASSWORD.to_string()),
+            key_path: None,
+            key_data: None,
+        };
+        let handle = Arc::new(connect(&target_address, &[jump], &reverse, &traffic).await);
+        // The target was reached through the bastion
+        assert_eq!(*bastion.dialled.lock().unwrap(), vec![target_address]);
+
+        // Local forward: dialled by the target, not the bastion
+        let forward = setup_port_forwarding(
+            "jump-chain",
+            handle.clone(),
+            traffic.clone(),
+            None,
+            0,
+            "127.0.0.1",
+            echo_port,
+        )
+        .await
+        .unwrap();
+        assert_eq!(round_trip(&forward.local_address, b"local").await, b"local");
+        assert_eq!(
+            *target.dialled.lock().unwrap(),
+            vec![format!("127.0.0.1:{}", echo_port)]
+        );
+        assert_eq!(bastion.dialled.lock().unwrap().len(), 1);
+        if let Some(running) = FORWARD_STORAGE.unregister(&forward.forward_id) {
+            running.cancel_token.cancel();
+        }
+
+        // Reverse forward: the listener is opened on the target
+        let tunnel = reverse
+            .open(&handle, "127.0.0.1", 0, "127.0.0.1", echo_port, None)
+            .await
+            .unwrap();
+        assert_ne!(tunnel.remote_port, 0);
+        let remote = format!("127.0.0.1:{}", tunnel.remote_port);
+        assert_eq!(round_trip(&remote, b"reverse").await, b"reverse");
+    }
+}
@@ -0,0 +1,108 @@
+//! Remote glob expansion.
+//!
+//! `ssh_glob` expands a pattern such as `/var/log/app-*.log` with the remote
+//! shell's pathname expansion, so agents get the matching file set in one call
+//! instead of listing directories and filtering themselves.
+//!
+//! The pattern is only subject to pathname expansion: it is assigned to a
+//! variable and expanded unquoted with an empty `IFS`, which performs neither
+//! word splitting nor command substitution. Patterns follow POSIX `sh` rules
+//! (`*`, `?`, `[...]`; no `**` or brace expansion).
+
+use super::command_wrap::shell_quote;
+
+/// Matches returned when `max_matches` is not given
+pub(crate) const DEFAULT_GLOB_MATCHES: usize = 1000;
+
+/// Largest accepted `max_matches`
+pub(crate) const MAX_GLOB_MATCHES: usize = 10_000;
+
+/// Resolve the match limit, clamped to 1..=`MAX_GLOB_MATCHES`.
+pub(crate) fn resolve_max_matches(max_matches: Option<usize>) -> usize {
+    max_matches
+        .unwrap_or(DEFAULT_GLOB_MATCHES)
+        .clamp(1, MAX_GLOB_MATCHES)
+}
+
+/// Command printing up to `limit + 1` paths matching `pattern`, one per line.
+///
+/// The extra line tells [`parse_matches`] that the result was truncated. A
+/// pattern that matches nothing prints nothing (the literal pattern is dropped
+/// unless such a path exists).
+pub(crate) fn glob_command(pattern: &str, limit: usize) -> String {
+    format!(
+        "p={}; IFS=''; for f in $p; do \
+         if [ -e \"$f\" ] || [ -L \"$f\" ]; then printf '%s\\n' \"$f\"; fi; \
+         done | head -n {}",
+        shell_quote(pattern),
+        limit + 1
+    )
+}
+
+/// Split the command output into at most `limit` paths and a truncation flag.
+pub(crate) fn parse_matches(stdout: &str, limit: usize) -> (Vec<String>, bool) {
+    let mut matches: Vec<String> = stdout
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    let truncated = matches.len() > limit;
+    matches.truncate(limit);
+    (matches, truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod limit {
+        use super::*;
+
+        #[test]
+        fn test_default_and_clamping() {
+            assert_eq!(resolve_max_matches(None), DEFAULT_GLOB_MATCHES);
+            assert_eq!(resolve_max_matches(Some(0)), 1);
+            assert_eq!(resolve_max_matches(Some(1_000_000)), MAX_GLOB_MATCHES);
+        }
+    }
+
+    mod command {
+        use super::*;
+
+        #[test]
+        fn test_pattern_is_quoted_and_limited() {
+            let command = glob_command("/var/log/app-*.log", 50);
+            assert!(command.starts_with("p='/var/log/app-*.log'; IFS='';"));
+            assert!(command.ends_with("| head -n 51"));
+        }
+
+        #[test]
+        fn test_substitution_stays_literal() {
+            let command = glob_command("/tmp/$(reboot)*", 10);
+            assert!(command.starts_with("p='/tmp/$(reboot)*';"));
+        }
+    }
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn test_within_limit() {
+            let (matches, truncated) = parse_matches("/a.log\n/b.log\n", 10);
+            assert_eq!(matches, vec!["/a.log", "/b.log"]);
+            assert!(!truncated);
+        }
+
+        #[test]
+        fn test_truncated() {
+            let (matches, truncated) = parse_matches("/a\n/b\n/c\n", 2);
+            assert_eq!(matches, vec!["/a", "/b"]);
+            assert!(truncated);
+        }
+
+        #[test]
+        fn test_no_matches() {
+            assert_eq!(parse_matches("", 10), (vec![], false));
+        }
+    }
+}
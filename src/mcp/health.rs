@@ -0,0 +1,219 @@
+//! Session health checks and the background health monitor.
+//!
+//! A health check probes a session with the `[health]` method:
+//!
+//! - `keepalive`: an SSH keepalive request. No channel is opened and nothing
+//!   runs on the host, so it is cheap enough for large fleets.
+//! - `command` (default): runs `command` (default `echo 1`) in an exec
+//!   channel; the session is healthy when it exits 0 in time. This also
+//!   catches hosts that still answer keepalives but can no longer start
+//!   processes (full process table, hung login shell).
+//!
+//! The result is recorded on the session (`healthy`, `last_health_check`,
+//! `health_history`, RTT samples) and sessions whose connection is closed are
+//! removed.
+//!
+//! With `interval_secs > 0` a monitor checks every session in the background
+//! on that interval, at most `concurrency` at a time. `ssh_list_sessions` then
+//! answers from the recorded results instead of probing every session on each
+//! call; `last_health_check` tells how fresh they are. Managed hosts are left
+//! to their own supervisor (see `managed`).
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use futures::StreamExt;
+use futures::stream;
+use russh::client::Handle;
+use tracing::{info, warn};
+
+use super::client::{RTT_PROBE_TIMEOUT, execute_ssh_command, measure_rtt};
+use super::config::{HealthConfig, HealthMethod, server_config};
+use super::lease::SESSION_LEASES;
+use super::managed::find_managed_host;
+use super::reconnect;
+use super::session::SshClientHandler;
+use super::storage::SESSION_STORAGE;
+
+/// Shortest accepted monitor interval
+pub(crate) const MIN_MONITOR_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest accepted probe timeout
+const MAX_PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest accepted `concurrency`
+const MAX_CONCURRENCY: usize = 256;
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Background check interval, or `None` when the monitor is off.
+pub(crate) fn monitor_interval(config: &HealthConfig) -> Option<Duration> {
+    (config.interval_secs > 0)
+        .then(|| Duration::from_secs(config.interval_secs).max(MIN_MONITOR_INTERVAL))
+}
+
+fn probe_timeout(config: &HealthConfig) -> Duration {
+    Duration::from_secs(config.timeout_secs.max(1)).min(MAX_PROBE_TIMEOUT)
+}
+
+fn concurrency(config: &HealthConfig) -> usize {
+    config.concurrency.clamp(1, MAX_CONCURRENCY)
+}
+
+/// One-line description of the monitor for `ssh_capabilities`.
+pub(crate) fn describe_monitor(config: &HealthConfig) -> Option<String> {
+    monitor_interval(config).map(|interval| {
+        format!(
+            "sessions health-checked every {}s ({})",
+            interval.as_secs(),
+            config.method.as_str()
+        )
+    })
+}
+
+/// Probe a connection; returns whether it is healthy and the measured RTT.
+async fn probe(
+    handle: &Arc<Handle<SshClientHandler>>,
+    config: &HealthConfig,
+) -> (bool, Option<Duration>) {
+    let timeout = probe_timeout(config);
+    match config.method {
+        HealthMethod::Keepalive => {
+            let rtt = measure_rtt(handle, timeout).await;
+            (rtt.is_some(), rtt)
+        }
+        HealthMethod::Command => {
+            let result = execute_ssh_command(handle, &config.command, timeout).await;
+            let healthy = matches!(result, Ok(ref response) if !response.timed_out && response.exit_code == 0);
+            let rtt = if healthy {
+                measure_rtt(handle, RTT_PROBE_TIMEOUT).await
+            } else {
+                None
+            };
+            (healthy, rtt)
+        }
+    }
+}
+
+/// Check one session and record the result; a closed session is removed.
+async fn check_session(session_id: &str, config: &HealthConfig) {
+    // A rebooting host is expected to be down; ssh_reboot_and_wait reconnects it
+    let Some(handle) = SESSION_STORAGE
+        .get(session_id)
+        .filter(|session_ref| !session_ref.info.rebooting)
+        .map(|session_ref| session_ref.handle.clone())
+    else {
+        return;
+    };
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let (healthy, rtt) = probe(&handle, config).await;
+    if handle.is_closed() {
+        warn!("Removing dead session {} from storage", session_id);
+        SESSION_LEASES.remove(session_id);
+        SESSION_STORAGE.remove(session_id);
+        reconnect::forget(session_id);
+        return;
+    }
+
+    if healthy && let Some(rtt) = rtt {
+        SESSION_STORAGE.record_rtt(session_id, rtt);
+    }
+    SESSION_STORAGE.update_health(session_id, now, healthy);
+    let flapping = SESSION_STORAGE
+        .get(session_id)
+        .is_some_and(|session_ref| session_ref.info.flapping);
+    if flapping {
+        warn!(
+            "Session {} is flapping between healthy and failed",
+            session_id
+        );
+    }
+}
+
+/// Check `session_ids`, at most `concurrency` at a time.
+pub(crate) async fn check_sessions(session_ids: Vec<String>, config: &HealthConfig) {
+    stream::iter(session_ids)
+        .for_each_concurrent(concurrency(config), |session_id| async move {
+            check_session(&session_id, config).await;
+        })
+        .await;
+}
+
+/// Start the background monitor when `[health] interval_secs` is set; later calls do nothing.
+///
+/// Needs a Tokio runtime; called when an MCP handler is created.
+pub(crate) fn start() {
+    let config = &server_config().health;
+    let Some(interval) = monitor_interval(config) else {
+        return;
+    };
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if tokio::runtime::Handle::try_current().is_err() {
+        warn!("No async runtime; background health checks are off");
+        return;
+    }
+
+    info!(
+        "Health-checking sessions every {}s ({})",
+        interval.as_secs(),
+        config.method.as_str()
+    );
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let session_ids = SESSION_STORAGE
+                .session_ids()
+                .into_iter()
+                .filter(|session_id| find_managed_host(session_id).is_none())
+                .collect();
+            check_sessions(session_ids, config).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::config::ServerConfig;
+
+    fn health(toml: &str) -> HealthConfig {
+        ServerConfig::from_toml(toml).unwrap().health
+    }
+
+    #[test]
+    fn test_monitor_off_by_default() {
+        let config = HealthConfig::default();
+        assert_eq!(monitor_interval(&config), None);
+        assert_eq!(describe_monitor(&config), None);
+    }
+
+    #[test]
+    fn test_interval_has_a_floor() {
+        let config = health("[health]\ninterval_secs = 1");
+        assert_eq!(monitor_interval(&config), Some(MIN_MONITOR_INTERVAL));
+
+        let config = health("[health]\ninterval_secs = 120\nmethod = \"keepalive\"");
+        assert_eq!(monitor_interval(&config), Some(Duration::from_secs(120)));
+        assert_eq!(
+            describe_monitor(&config).as_deref(),
+            Some("sessions health-checked every 120s (keepalive)")
+        );
+    }
+
+    #[test]
+    fn test_timeout_and_concurrency_are_clamped() {
+        let config = health("[health]\ntimeout_secs = 0\nconcurrency = 0");
+        assert_eq!(probe_timeout(&config), Duration::from_secs(1));
+        assert_eq!(concurrency(&config), 1);
+
+        let config = health("[health]\ntimeout_secs = 3600\nconcurrency = 100000");
+        assert_eq!(probe_timeout(&config), MAX_PROBE_TIMEOUT);
+        assert_eq!(concurrency(&config), MAX_CONCURRENCY);
+    }
+}
@@ -0,0 +1,413 @@
+//! Operator-vetted helper scripts (`ssh_run_helper`).
+//!
+//! Operators point `[helpers] dir` at a local directory of reviewed scripts.
+//! Every regular file directly in it whose name matches `[A-Za-z0-9._-]` (and
+//! does not start with `.`) is a helper. The library is mirrored to
+//! `remote_dir` (default `~/.ssh-mcp/helpers`, mode 0755):
+//!
+//! 1. on connect, in the background, unless `sync_on_connect = false`
+//! 2. before `ssh_run_helper` whenever the local library changed since the
+//!    session was last synced
+//!
+//! A sync compares local sha256 checksums with `sha256sum` on the remote host
+//! and only uploads scripts that are missing or differ, so edits to a helper
+//! reach every session on its next use. Agents can only run names from the
+//! library; arguments are passed shell-quoted.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use russh::client;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use super::audit::{self, AuditEvent};
+use super::client::execute_ssh_command;
+use super::command_wrap::shell_quote;
+use super::config::{HelpersConfig, server_config};
+use super::session::SshClientHandler;
+use super::symlink::failure_message;
+use super::traffic::ByteCounters;
+use super::transfer::{
+    DEFAULT_UPLOAD_CONCURRENCY, LocalEntry, LocalEntryKind, create_remote_dirs, remote_path,
+    upload_entries,
+};
+use super::types::TransferStatus;
+
+/// Largest accepted library
+const MAX_HELPERS: usize = 256;
+
+/// Permission bits of uploaded helpers
+const HELPER_MODE: u32 = 0o755;
+
+/// Permission bits of the remote helper directory
+const HELPER_DIR_MODE: u32 = 0o700;
+
+/// Timeout for the remote checksum listing
+const CHECKSUM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Library fingerprint each session was last synced with, keyed by session ID.
+static SYNCED: Lazy<DashMap<String, String>> = Lazy::new(DashMap::new);
+
+/// One script of the local library.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Helper {
+    pub name: String,
+    /// Hex sha256 of the script
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// The scripts found in `[helpers] dir`, sorted by name.
+#[derive(Debug, Clone)]
+pub(crate) struct HelperLibrary {
+    pub dir: PathBuf,
+    pub remote_dir: String,
+    pub helpers: Vec<Helper>,
+}
+
+/// Outcome of mirroring the library to one session.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct HelperSync {
+    pub uploaded: usize,
+    pub unchanged: usize,
+    pub failed: Vec<String>,
+}
+
+impl HelperLibrary {
+    /// Read the library configured in `config`; `Ok(None)` when none is configured.
+    pub(crate) fn load(config: &HelpersConfig) -> Result<Option<Self>, String> {
+        let Some(dir) = config.dir.as_deref() else {
+            return Ok(None);
+        };
+        let dir = PathBuf::from(dir);
+        let helpers = scan_dir(&dir)?;
+        Ok(Some(Self {
+            dir,
+            remote_dir: config.remote_dir.trim_end_matches('/').to_string(),
+            helpers,
+        }))
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Helper> {
+        self.helpers.iter().find(|helper| helper.name == name)
+    }
+
+    /// Names and checksums of every helper; changes whenever a script does.
+    fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.remote_dir.as_bytes());
+        for helper in &self.helpers {
+            hasher.update(helper.name.as_bytes());
+            hasher.update(helper.sha256.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Command running `name` with `args` from the remote helper directory.
+    pub(crate) fn command(&self, name: &str, args: &[String]) -> String {
+        let mut parts = vec![shell_quote(&remote_path(&self.remote_dir, name))];
+        parts.extend(args.iter().map(|arg| shell_quote(arg)));
+        parts.join(" ")
+    }
+}
+
+/// Whether `name` may name a helper: `[A-Za-z0-9._-]`, not starting with `.`.
+pub(crate) fn is_helper_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+fn scan_dir(dir: &Path) -> Result<Vec<Helper>, String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read helper directory {}: {}", dir.display(), e))?;
+    let mut helpers = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Follows symlinks, so a linked script counts like a copied one
+        let is_file = fs::metadata(entry.path()).is_ok_and(|meta| meta.is_file());
+        if !is_file || !is_helper_name(&name) {
+            continue;
+        }
+        if helpers.len() == MAX_HELPERS {
+            return Err(format!(
+                "Helper directory {} holds more than {} scripts",
+                dir.display(),
+                MAX_HELPERS
+            ));
+        }
+        let content =
+            fs::read(entry.path()).map_err(|e| format!("Failed to read helper {}: {}", name, e))?;
+        helpers.push(Helper {
+            name,
+            sha256: format!("{:x}", Sha256::digest(&content)),
+            size: content.len() as u64,
+        });
+    }
+    helpers.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(helpers)
+}
+
+/// Command printing `sha256sum` lines for every helper present remotely.
+pub(crate) fn checksum_command(library: &HelperLibrary) -> String {
+    let names: Vec<String> = library
+        .helpers
+        .iter()
+        .map(|helper| shell_quote(&helper.name))
+        .collect();
+    format!(
+        "cd -- {} 2>/dev/null && sha256sum -- {} 2>/dev/null; true",
+        shell_quote(&library.remote_dir),
+        names.join(" ")
+    )
+}
+
+/// Parse `sha256sum` output into name -> checksum.
+pub(crate) fn parse_checksums(stdout: &str) -> HashMap<String, String> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let (sum, name) = line.split_once(' ')?;
+            let name = name.trim_start_matches([' ', '*']);
+            (sum.len() == 64 && sum.bytes().all(|b| b.is_ascii_hexdigit()))
+                .then(|| (name.to_string(), sum.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+/// Helpers whose remote copy is missing or differs.
+pub(crate) fn stale_helpers<'a>(
+    library: &'a HelperLibrary,
+    remote: &HashMap<String, String>,
+) -> Vec<&'a Helper> {
+    library
+        .helpers
+        .iter()
+        .filter(|helper| remote.get(&helper.name) != Some(&helper.sha256))
+        .collect()
+}
+
+/// Mirror `library` to the session, uploading only changed scripts.
+pub(crate) async fn sync_helpers(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    traffic: &ByteCounters,
+    session_id: &str,
+    library: &HelperLibrary,
+) -> Result<HelperSync, String> {
+    let fingerprint = library.fingerprint();
+    if library.helpers.is_empty() {
+        SYNCED.insert(session_id.to_string(), fingerprint);
+        return Ok(HelperSync::default());
+    }
+
+    create_remote_dirs(handle, &library.remote_dir, Some(HELPER_DIR_MODE), &[]).await?;
+    let response =
+        execute_ssh_command(handle, &checksum_command(library), CHECKSUM_TIMEOUT).await?;
+    if response.timed_out {
+        return Err(failure_message(&response));
+    }
+    let remote = parse_checksums(&response.stdout);
+    let stale: Vec<LocalEntry> = stale_helpers(library, &remote)
+        .into_iter()
+        .map(|helper| LocalEntry {
+            rel_path: helper.name.clone(),
+            kind: LocalEntryKind::File { size: helper.size },
+            mode: Some(HELPER_MODE),
+        })
+        .collect();
+
+    let mut sync = HelperSync {
+        unchanged: library.helpers.len() - stale.len(),
+        ..HelperSync::default()
+    };
+    let results = upload_entries(
+        handle,
+        traffic,
+        &library.dir,
+        &library.remote_dir,
+        stale,
+        DEFAULT_UPLOAD_CONCURRENCY,
+        None,
+    )
+    .await;
+    for result in results {
+        if result.status == TransferStatus::Failed {
+            warn!(
+                "Helper {} failed to upload to session {}: {}",
+                result.path,
+                session_id,
+                result.error.unwrap_or_default()
+            );
+            sync.failed.push(result.path);
+        } else {
+            sync.uploaded += 1;
+        }
+    }
+
+    let event = AuditEvent::new("helpers_sync")
+        .session_id(session_id)
+        .target(format!(
+            "{} uploaded, {} unchanged",
+            sync.uploaded, sync.unchanged
+        ));
+    if sync.failed.is_empty() {
+        SYNCED.insert(session_id.to_string(), fingerprint);
+        audit::record(event);
+    } else {
+        audit::record(event.failed(format!("failed: {}", sync.failed.join(", "))));
+    }
+    info!(
+        "Synced helpers to session {}: {} uploaded, {} unchanged, {} failed",
+        session_id,
+        sync.uploaded,
+        sync.unchanged,
+        sync.failed.len()
+    );
+    Ok(sync)
+}
+
+/// Whether the session was last synced with this exact library.
+pub(crate) fn is_synced(session_id: &str, library: &HelperLibrary) -> bool {
+    SYNCED
+        .get(session_id)
+        .is_some_and(|synced| *synced == library.fingerprint())
+}
+
+/// Forget the sync state of a closed session.
+pub(crate) fn forget_session(session_id: &str) {
+    SYNCED.remove(session_id);
+}
+
+/// Sync the configured library to a new session in the background.
+pub(crate) fn sync_on_connect(
+    handle: Arc<client::Handle<SshClientHandler>>,
+    traffic: Arc<ByteCounters>,
+    session_id: String,
+) {
+    let config = &server_config().helpers;
+    if config.dir.is_none() || !config.sync_on_connect {
+        return;
+    }
+    tokio::spawn(async move {
+        let result = match HelperLibrary::load(&server_config().helpers) {
+            Ok(Some(library)) => sync_helpers(&handle, &traffic, &session_id, &library).await,
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Helper sync for session {} failed: {}", session_id, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(helpers: &[(&str, &str)]) -> HelperLibrary {
+        HelperLibrary {
+            dir: PathBuf::from("/etc/ssh-mcp/helpers"),
+            remote_dir: ".ssh-mcp/helpers".to_string(),
+            helpers: helpers
+                .iter()
+                .map(|(name, sha256)| Helper {
+                    name: name.to_string(),
+                    sha256: sha256.to_string(),
+                    size: 10,
+                })
+                .collect(),
+        }
+    }
+
+    mod library {
+        use super::*;
+
+        #[test]
+        fn test_helper_names() {
+            assert!(is_helper_name("disk-report.sh"));
+            assert!(is_helper_name("rotate_logs"));
+            assert!(!is_helper_name(".hidden"));
+            assert!(!is_helper_name("../etc/passwd"));
+            assert!(!is_helper_name("a b"));
+        }
+
+        #[test]
+        fn test_scan_dir_hashes_scripts() {
+            let dir = std::env::temp_dir().join(format!("helpers-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(dir.join("nested")).unwrap();
+            fs::write(dir.join("b.sh"), "echo b\n").unwrap();
+            fs::write(dir.join("a.sh"), "").unwrap();
+            fs::write(dir.join(".swp"), "x").unwrap();
+            let helpers = scan_dir(&dir).unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+
+            let names: Vec<&str> = helpers.iter().map(|h| h.name.as_str()).collect();
+            assert_eq!(names, vec!["a.sh", "b.sh"]);
+            assert_eq!(
+                helpers[0].sha256,
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+            );
+            assert_eq!(helpers[1].size, 7);
+        }
+
+        #[test]
+        fn test_command_quotes_arguments() {
+            let library = library(&[("disk-report.sh", "")]);
+            assert_eq!(
+                library.command("disk-report.sh", &["/var".to_string(), "a'b".to_string()]),
+                "'.ssh-mcp/helpers/disk-report.sh' '/var' 'a'\\''b'"
+            );
+        }
+
+        #[test]
+        fn test_fingerprint_tracks_content() {
+            let before = library(&[("a.sh", "11")]).fingerprint();
+            assert_eq!(before, library(&[("a.sh", "11")]).fingerprint());
+            assert_ne!(before, library(&[("a.sh", "22")]).fingerprint());
+        }
+    }
+
+    mod checksums {
+        use super::*;
+
+        #[test]
+        fn test_only_changed_helpers_are_stale() {
+            let sum = |c: char| c.to_string().repeat(64);
+            let library = library(&[
+                ("a.sh", &sum('a')),
+                ("b.sh", &sum('b')),
+                ("c.sh", &sum('c')),
+            ]);
+            let stdout = format!(
+                "{}  a.sh\n{} *b.sh\nsha256sum: c.sh: No such file\n",
+                sum('a'),
+                sum('f')
+            );
+            let remote = parse_checksums(&stdout);
+            assert_eq!(remote.len(), 2);
+            let stale: Vec<&str> = stale_helpers(&library, &remote)
+                .iter()
+                .map(|helper| helper.name.as_str())
+                .collect();
+            assert_eq!(stale, vec!["b.sh", "c.sh"]);
+        }
+
+        #[test]
+        fn test_checksum_command() {
+            let library = library(&[("a.sh", ""), ("b.sh", "")]);
+            assert_eq!(
+                checksum_command(&library),
+                "cd -- '.ssh-mcp/helpers' 2>/dev/null && sha256sum -- 'a.sh' 'b.sh' 2>/dev/null; true"
+            );
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Per-host session caps.
+//!
+//! Small appliances and network devices (BMCs, switches, PDUs) often misbehave
+//! when many SSH connections are open at once. An operator can cap concurrent
+//! sessions per remote host with `SSH_MAX_SESSIONS_PER_HOST` or the
+//! `[host_limits]` section of the configuration file; the cap applies across
+//! all agents, independently of any per-agent quota.
+//!
+//! `ssh_connect` reserves a slot before dialling, so concurrent connects to the
+//! same host cannot overshoot the cap while their handshakes are in flight.
+//! Spare connections of managed host pools count as sessions.
+
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use once_cell::sync::Lazy;
+
+use super::client::parse_address;
+use super::config::{resolve_max_sessions_per_host, server_config};
+use super::error::{ErrorCode, ToolError};
+use super::managed::pooled_on_host;
+use super::pool::idle_on_host;
+use super::storage::SESSION_STORAGE;
+
+/// Global table of in-flight connection attempts keyed by host.
+pub(crate) static HOST_SLOTS: Lazy<HostSlots> = Lazy::new(HostSlots::new);
+
+/// Normalize a `host:port` address to the key caps are counted under.
+///
+/// The port is dropped (one appliance, one cap) and the host is lower-cased;
+/// brackets around IPv6 literals are removed.
+pub(crate) fn host_key(address: &str) -> String {
+    let host = parse_address(address)
+        .map(|(host, _)| host)
+        .unwrap_or_else(|_| address.to_string());
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase()
+}
+
+/// Connection attempts in flight per host.
+pub(crate) struct HostSlots {
+    pending: DashMap<String, usize>,
+}
+
+impl HostSlots {
+    /// Create an empty slot table.
+    pub fn new() -> Self {
+        Self {
+            pending: DashMap::new(),
+        }
+    }
+
+    /// Reserve a slot on `host` given its `active` sessions and its `limit`.
+    ///
+    /// Returns the number of sessions in use (active plus pending) when the
+    /// host is full. The slot is released when the reservation is dropped, by
+    /// which time a successful connect has registered its session.
+    pub fn reserve(
+        &self,
+        host: &str,
+        active: usize,
+        limit: usize,
+    ) -> Result<HostReservation<'_>, usize> {
+        match self.pending.entry(host.to_string()) {
+            Entry::Occupied(mut entry) => {
+                let in_use = active + *entry.get();
+                if in_use >= limit {
+                    return Err(in_use);
+                }
+                *entry.get_mut() += 1;
+            }
+            Entry::Vacant(entry) => {
+                if active >= limit {
+                    return Err(active);
+                }
+                entry.insert(1);
+            }
+        }
+
+        Ok(HostReservation {
+            slots: self,
+            host: host.to_string(),
+        })
+    }
+
+    fn release(&self, host: &str) {
+        if let Entry::Occupied(mut entry) = self.pending.entry(host.to_string()) {
+            if *entry.get() <= 1 {
+                entry.remove();
+            } else {
+                *entry.get_mut() -= 1;
+            }
+        }
+    }
+}
+
+impl Default for HostSlots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reserved connection slot, released on drop.
+pub(crate) struct HostReservation<'a> {
+    slots: &'a HostSlots,
+    host: String,
+}
+
+impl Drop for HostReservation<'_> {
+    fn drop(&mut self) {
+        self.slots.release(&self.host);
+    }
+}
+
+/// Reserve a connection slot on the target host when it has a session cap.
+pub(crate) fn reserve_host_slot(
+    address: &str,
+) -> Result<Option<HostReservation<'static>>, ToolError> {
+    let host = host_key(address);
+    let Some(limit) = resolve_max_sessions_per_host(&host, &server_config().host_limits) else {
+        return Ok(None);
+    };
+    let active = SESSION_STORAGE
+        .list()
+        .iter()
+        .filter(|info| host_key(&info.host) == host)
+        .count()
+        + pooled_on_host(&host)
+        + idle_on_host(&host);
+
+    HOST_SLOTS
+        .reserve(&host, active, limit)
+        .map(Some)
+        .map_err(|in_use| {
+            ToolError::new(
+                ErrorCode::LimitExceeded,
+                format!(
+                    "Maximum sessions for host {} reached ({}/{}). Reuse or disconnect an existing session first.",
+                    host, in_use, limit
+                ),
+            )
+            .with_detail("host", host.as_str())
+            .with_detail("limit", limit)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod keys {
+        use super::*;
+
+        #[test]
+        fn test_port_is_ignored() {
+            assert_eq!(host_key("BMC-1.example.com:22"), "bmc-1.example.com");
+            assert_eq!(host_key("bmc-1.example.com:2222"), "bmc-1.example.com");
+            assert_eq!(host_key("10.0.0.5"), "10.0.0.5");
+        }
+
+        #[test]
+        fn test_ipv6_brackets_removed() {
+            assert_eq!(host_key("[2001:DB8::1]:22"), "2001:db8::1");
+        }
+    }
+
+    mod slots {
+        use super::*;
+
+        #[test]
+        fn test_reserve_up_to_limit() {
+            let slots = HostSlots::new();
+            let first = slots.reserve("bmc", 0, 2).unwrap();
+            let _second = slots.reserve("bmc", 0, 2).unwrap();
+            assert_eq!(slots.reserve("bmc", 0, 2).err(), Some(2));
+            drop(first);
+            assert!(slots.reserve("bmc", 0, 2).is_ok());
+        }
+
+        #[test]
+        fn test_active_sessions_count() {
+            let slots = HostSlots::new();
+            assert_eq!(slots.reserve("bmc", 1, 1).err(), Some(1));
+            assert!(slots.reserve("other", 1, 2).is_ok());
+        }
+
+        #[test]
+        fn test_drop_releases_slot() {
+            let slots = HostSlots::new();
+            {
+                let _slot = slots.reserve("bmc", 0, 1).unwrap();
+                assert!(slots.pending.contains_key("bmc"));
+            }
+            assert!(!slots.pending.contains_key("bmc"));
+        }
+    }
+}
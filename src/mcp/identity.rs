@@ -0,0 +1,126 @@
+//! Post-connect identity probe.
+//!
+//! Right after authentication the server runs a lightweight probe (`id`,
+//! `hostname`) on the new session and stores the result in
+//! [`SessionInfo::identity`](super::types::SessionInfo), so agents immediately
+//! know which effective user and privileges they landed with.
+//!
+//! The probe is best effort: hosts without a POSIX shell (network appliances,
+//! BMCs) simply get no identity. Disable it with `SSH_IDENTITY_PROBE=false`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::client;
+use tracing::{debug, warn};
+
+use super::client::execute_ssh_command;
+use super::session::SshClientHandler;
+use super::types::RemoteIdentity;
+
+/// Maximum time the identity probe may take
+pub(crate) const IDENTITY_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prints exactly four lines (user, uid, groups, hostname); failing tools yield empty lines.
+const IDENTITY_PROBE_COMMAND: &str = r#"printf '%s\n' "$(id -un 2>/dev/null || whoami 2>/dev/null)" "$(id -u 2>/dev/null)" "$(id -Gn 2>/dev/null)" "$(hostname 2>/dev/null || uname -n 2>/dev/null)""#;
+
+/// Parse the probe output. Returns `None` when no user name was reported.
+pub(crate) fn parse_identity(stdout: &str) -> Option<RemoteIdentity> {
+    let mut lines = stdout.lines().map(str::trim);
+
+    let user = lines.next().filter(|line| !line.is_empty())?.to_string();
+    let uid = lines.next().and_then(|line| line.parse::<u32>().ok());
+    let groups = lines
+        .next()
+        .map(|line| line.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    let hostname = lines
+        .next()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string);
+
+    Some(RemoteIdentity {
+        is_root: uid == Some(0) || (uid.is_none() && user == "root"),
+        user,
+        uid,
+        groups,
+        hostname,
+    })
+}
+
+/// Run the identity probe on an authenticated session.
+pub(crate) async fn probe_identity(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+) -> Option<RemoteIdentity> {
+    match execute_ssh_command(handle, IDENTITY_PROBE_COMMAND, IDENTITY_PROBE_TIMEOUT).await {
+        Ok(response) if !response.timed_out && response.exit_code == 0 => {
+            let identity = parse_identity(&response.stdout);
+            debug!("Identity probe result: {:?}", identity);
+            identity
+        }
+        Ok(response) => {
+            debug!(
+                "Identity probe unavailable (exit_code={}, timed_out={})",
+                response.exit_code, response.timed_out
+            );
+            None
+        }
+        Err(e) => {
+            warn!("Identity probe failed: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn test_regular_user() {
+            let identity = parse_identity("deploy\n1001\ndeploy docker wheel\nweb-01\n").unwrap();
+            assert_eq!(identity.user, "deploy");
+            assert_eq!(identity.uid, Some(1001));
+            assert_eq!(identity.groups, vec!["deploy", "docker", "wheel"]);
+            assert_eq!(identity.hostname.as_deref(), Some("web-01"));
+            assert!(!identity.is_root);
+        }
+
+        #[test]
+        fn test_root_by_uid() {
+            let identity = parse_identity("toor\n0\nroot\ndb-01\n").unwrap();
+            assert!(identity.is_root);
+        }
+
+        #[test]
+        fn test_root_by_name_without_uid() {
+            let identity = parse_identity("root\n\n\nbmc\n").unwrap();
+            assert_eq!(identity.uid, None);
+            assert!(identity.groups.is_empty());
+            assert!(identity.is_root);
+        }
+
+        #[test]
+        fn test_missing_hostname() {
+            let identity = parse_identity("alice\n1000\nalice\n\n").unwrap();
+            assert_eq!(identity.hostname, None);
+        }
+
+        #[test]
+        fn test_empty_output_is_none() {
+            assert!(parse_identity("").is_none());
+            assert!(parse_identity("\n\n\n\n").is_none());
+        }
+
+        #[test]
+        fn test_crlf_line_endings() {
+            let identity = parse_identity("bob\r\n1002\r\nbob\r\nhost\r\n").unwrap();
+            assert_eq!(identity.user, "bob");
+            assert_eq!(identity.uid, Some(1002));
+            assert_eq!(identity.hostname.as_deref(), Some("host"));
+        }
+    }
+}
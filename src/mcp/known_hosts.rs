@@ -0,0 +1,453 @@
+//! Server host key verification against an OpenSSH known_hosts file.
+//!
+//! Every connection checks the server's host key against `~/.ssh/known_hosts`,
+//! or the file named by `SSH_KNOWN_HOSTS`. Plain and hashed (`|1|...`) host
+//! entries are understood; hosts on a port other than 22 are looked up as
+//! `[host]:port`, like OpenSSH.
+//!
+//! The server-wide mode comes from `SSH_STRICT_HOST_KEY_CHECKING`, else
+//! `accept-new`; `ssh_connect(strict_host_key_checking=...)` can only make it
+//! stricter (`no` < `accept-new` < `yes`), never turn verification off:
+//!
+//! | Mode | Known key | Unknown host | Changed key |
+//! |------|-----------|--------------|-------------|
+//! | `yes` | accept | reject | reject |
+//! | `accept-new` | accept | record and accept | reject |
+//! | `no` | accept | accept | accept |
+//!
+//! A rejected key fails the connect with a non-retryable
+//! `Host key verification failed ...` error naming the key's fingerprint.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+use russh::keys::known_hosts::{known_host_keys_path, learn_known_hosts_path};
+use russh::keys::{HashAlg, PublicKey};
+use tracing::{info, warn};
+
+/// Environment variable name for the known_hosts file path
+pub(crate) const KNOWN_HOSTS_ENV_VAR: &str = "SSH_KNOWN_HOSTS";
+
+/// Environment variable name for the default host key checking mode
+pub(crate) const STRICT_HOST_KEY_CHECKING_ENV_VAR: &str = "SSH_STRICT_HOST_KEY_CHECKING";
+
+/// Serializes lookups and appends so concurrent first connects to one host
+/// record its key once.
+static KNOWN_HOSTS_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// How unknown and changed host keys are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum HostKeyChecking {
+    /// Only hosts already in known_hosts are accepted
+    Yes,
+    /// Unknown hosts are recorded; changed keys are rejected
+    #[default]
+    AcceptNew,
+    /// Host keys are not verified
+    No,
+}
+
+impl HostKeyChecking {
+    /// Parse `yes`, `accept-new` or `no` (case-insensitive).
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "yes" => Ok(Self::Yes),
+            "accept-new" => Ok(Self::AcceptNew),
+            "no" => Ok(Self::No),
+            _ => Err(format!(
+                "Invalid strict_host_key_checking '{}': use \"yes\", \"accept-new\" or \"no\"",
+                value
+            )),
+        }
+    }
+
+    /// Rank of the mode, higher is stricter.
+    fn strictness(self) -> u8 {
+        match self {
+            Self::No => 0,
+            Self::AcceptNew => 1,
+            Self::Yes => 2,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Yes => "yes",
+            Self::AcceptNew => "accept-new",
+            Self::No => "no",
+        }
+    }
+}
+
+/// Resolve the checking mode: the env var (or the default), tightened by the parameter
+pub(crate) fn resolve_host_key_checking(mode: Option<HostKeyChecking>) -> HostKeyChecking {
+    let server = env::var(STRICT_HOST_KEY_CHECKING_ENV_VAR)
+        .ok()
+        .and_then(|value| {
+            HostKeyChecking::parse(&value)
+                .inspect_err(|e| warn!("{}; using accept-new", e))
+                .ok()
+        })
+        .unwrap_or_default();
+    tighten(server, mode)
+}
+
+/// `server`, or `mode` when it is stricter; a call cannot loosen the server mode.
+fn tighten(server: HostKeyChecking, mode: Option<HostKeyChecking>) -> HostKeyChecking {
+    match mode {
+        Some(mode) if mode.strictness() > server.strictness() => mode,
+        Some(mode) if mode != server => {
+            warn!(
+                "strict_host_key_checking={} is looser than the server's {}; using {}",
+                mode.as_str(),
+                server.as_str(),
+                server.as_str()
+            );
+            server
+        }
+        _ => server,
+    }
+}
+
+/// Resolve the known_hosts path: `SSH_KNOWN_HOSTS`, else `~/.ssh/known_hosts`.
+pub(crate) fn resolve_known_hosts_path() -> Option<PathBuf> {
+    env::var_os(KNOWN_HOSTS_ENV_VAR)
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".ssh").join("known_hosts")))
+}
+
+/// Outcome of an accepted host key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HostKeyStatus {
+    /// The key matches a known_hosts entry
+    Known,
+    /// The host was unknown and its key was recorded
+    Learned,
+    /// The key was accepted without verification
+    Unchecked,
+}
+
+/// `host` or `[host]:port`, as written in known_hosts.
+fn host_label(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+/// Check `key` for `host:port` against the known_hosts file at `path`.
+///
+/// Returns why the key is rejected as the error.
+pub(crate) fn verify_host_key(
+    mode: HostKeyChecking,
+    path: Option<&Path>,
+    host: &str,
+    port: u16,
+    key: &PublicKey,
+) -> Result<HostKeyStatus, String> {
+    if mode == HostKeyChecking::No {
+        return Ok(HostKeyStatus::Unchecked);
+    }
+    let label = host_label(host, port);
+    let fingerprint = key.fingerprint(HashAlg::Sha256);
+    let failed = |reason: String| {
+        format!(
+            "Host key verification failed for {} ({} {}): {}",
+            label,
+            key.algorithm().as_str(),
+            fingerprint,
+            reason
+        )
+    };
+    let Some(path) = path else {
+        return match mode {
+            HostKeyChecking::Yes => Err(failed(format!(
+                "no known_hosts file (set {})",
+                KNOWN_HOSTS_ENV_VAR
+            ))),
+            _ => {
+                warn!(
+                    "No known_hosts file for {}; host key accepted unrecorded",
+                    label
+                );
+                Ok(HostKeyStatus::Unchecked)
+            }
+        };
+    };
+
+    let _guard = KNOWN_HOSTS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let entries = known_host_keys_path(host, port, path)
+        .map_err(|e| failed(format!("cannot read {}: {}", path.display(), e)))?;
+    // Line numbers from russh skip comment lines, so they are not reported
+    let recorded: Vec<&PublicKey> = entries
+        .iter()
+        .map(|(_, recorded)| recorded)
+        .filter(|recorded| recorded.algorithm() == key.algorithm())
+        .collect();
+    if recorded.contains(&key) {
+        return Ok(HostKeyStatus::Known);
+    }
+    if !recorded.is_empty() {
+        return Err(failed(format!(
+            "the key differs from the one recorded in {}; if the host was reinstalled, \
+             remove the old entry with `ssh-keygen -R '{}' -f '{}'`",
+            path.display(),
+            label,
+            path.display()
+        )));
+    }
+
+    match mode {
+        HostKeyChecking::Yes => Err(failed(format!(
+            "host is not in {}; add it or connect with strict_host_key_checking=\"accept-new\"",
+            path.display()
+        ))),
+        _ => {
+            match learn_known_hosts_path(host, port, key, path) {
+                Ok(()) => info!(
+                    "Recorded new host key for {} ({}) in {}",
+                    label,
+                    fingerprint,
+                    path.display()
+                ),
+                Err(e) => warn!(
+                    "Could not record host key for {} in {}: {}",
+                    label,
+                    path.display(),
+                    e
+                ),
+            }
+            Ok(HostKeyStatus::Learned)
+        }
+    }
+}
+
+/// Host key check run by a connection's handler during the handshake.
+///
+/// Clones share the rejection reason, so the connecting code can report it
+/// after russh fails the handshake with a generic error.
+#[derive(Debug, Clone)]
+pub(crate) struct HostKeyCheck {
+    host: String,
+    port: u16,
+    mode: HostKeyChecking,
+    path: Option<PathBuf>,
+    rejection: Arc<Mutex<Option<String>>>,
+}
+
+impl HostKeyCheck {
+    /// Check `host:port` in `mode` against the resolved known_hosts file.
+    pub(crate) fn new(host: &str, port: u16, mode: HostKeyChecking) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            mode,
+            path: resolve_known_hosts_path(),
+            rejection: Arc::default(),
+        }
+    }
+
+    /// Whether to accept the server's key; a rejection is kept for [`Self::rejection`].
+    pub(crate) fn accept(&self, key: &PublicKey) -> bool {
+        match verify_host_key(self.mode, self.path.as_deref(), &self.host, self.port, key) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("{}", e);
+                *self.rejection.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+                false
+            }
+        }
+    }
+
+    /// Why the server's key was rejected, if it was.
+    pub(crate) fn rejection(&self) -> Option<String> {
+        self.rejection
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIE91yV8SGiY98j+Xcz4t9gmk6w4vXGn85ypCCgv5PvaD";
+    const KEY_B: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIHgi1RqXMw4/VqT2mMNvXQQ+peepdJMqlCv0M8O9ynu9";
+
+    fn key(base64: &str) -> PublicKey {
+        russh::keys::parse_public_key_base64(base64).unwrap()
+    }
+
+    /// A known_hosts file in a fresh temp directory with `content`.
+    fn known_hosts(content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("known-hosts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("known_hosts");
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    mod modes {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(HostKeyChecking::parse("yes"), Ok(HostKeyChecking::Yes));
+            assert_eq!(
+                HostKeyChecking::parse("Accept-New"),
+                Ok(HostKeyChecking::AcceptNew)
+            );
+            assert_eq!(HostKeyChecking::parse("no"), Ok(HostKeyChecking::No));
+            assert!(HostKeyChecking::parse("ask").is_err());
+            assert_eq!(HostKeyChecking::default().as_str(), "accept-new");
+        }
+
+        #[test]
+        fn test_parameter_only_tightens() {
+            assert_eq!(
+                tighten(HostKeyChecking::Yes, Some(HostKeyChecking::No)),
+                HostKeyChecking::Yes
+            );
+            assert_eq!(
+                tighten(HostKeyChecking::Yes, Some(HostKeyChecking::AcceptNew)),
+                HostKeyChecking::Yes
+            );
+            assert_eq!(
+                tighten(HostKeyChecking::AcceptNew, Some(HostKeyChecking::No)),
+                HostKeyChecking::AcceptNew
+            );
+            assert_eq!(
+                tighten(HostKeyChecking::AcceptNew, Some(HostKeyChecking::Yes)),
+                HostKeyChecking::Yes
+            );
+            assert_eq!(
+                tighten(HostKeyChecking::No, Some(HostKeyChecking::AcceptNew)),
+                HostKeyChecking::AcceptNew
+            );
+            assert_eq!(tighten(HostKeyChecking::No, None), HostKeyChecking::No);
+        }
+    }
+
+    mod verification {
+        use super::*;
+
+        #[test]
+        fn test_known_key_is_accepted() {
+            let path = known_hosts(&format!("web1 ssh-ed25519 {}\n", KEY_A));
+            for mode in [HostKeyChecking::Yes, HostKeyChecking::AcceptNew] {
+                assert_eq!(
+                    verify_host_key(mode, Some(&path), "web1", 22, &key(KEY_A)),
+                    Ok(HostKeyStatus::Known)
+                );
+            }
+        }
+
+        #[test]
+        fn test_changed_key_is_rejected() {
+            let path = known_hosts(&format!("# fleet\nweb1,10.0.0.5 ssh-ed25519 {}\n", KEY_A));
+            let error = verify_host_key(
+                HostKeyChecking::AcceptNew,
+                Some(&path),
+                "10.0.0.5",
+                22,
+                &key(KEY_B),
+            )
+            .unwrap_err();
+            assert!(
+                error.starts_with("Host key verification failed for 10.0.0.5 (ssh-ed25519 SHA256:"),
+                "{}",
+                error
+            );
+            assert!(error.contains("ssh-keygen -R '10.0.0.5'"), "{}", error);
+            assert!(!crate::mcp::error::is_retryable_error(&error));
+        }
+
+        #[test]
+        fn test_unknown_host() {
+            let path = known_hosts(&format!("web1 ssh-ed25519 {}\n", KEY_A));
+            let error = verify_host_key(HostKeyChecking::Yes, Some(&path), "web2", 22, &key(KEY_B))
+                .unwrap_err();
+            assert!(error.contains("host is not in"), "{}", error);
+
+            assert_eq!(
+                verify_host_key(
+                    HostKeyChecking::AcceptNew,
+                    Some(&path),
+                    "web2",
+                    2222,
+                    &key(KEY_B)
+                ),
+                Ok(HostKeyStatus::Learned)
+            );
+            let content = std::fs::read_to_string(&path).unwrap();
+            assert!(content.ends_with(&format!("[web2]:2222 ssh-ed25519 {}\n", KEY_B)));
+            assert_eq!(
+                verify_host_key(HostKeyChecking::Yes, Some(&path), "web2", 2222, &key(KEY_B)),
+                Ok(HostKeyStatus::Known)
+            );
+            // The entry is port-specific
+            assert!(
+                verify_host_key(HostKeyChecking::Yes, Some(&path), "web2", 22, &key(KEY_B))
+                    .is_err()
+            );
+        }
+
+        #[test]
+        fn test_missing_file() {
+            let path = env::temp_dir()
+                .join(format!("known-hosts-{}", uuid::Uuid::new_v4()))
+                .join("known_hosts");
+            assert!(
+                verify_host_key(HostKeyChecking::Yes, Some(&path), "web1", 22, &key(KEY_A))
+                    .is_err()
+            );
+            // accept-new creates the file
+            assert_eq!(
+                verify_host_key(
+                    HostKeyChecking::AcceptNew,
+                    Some(&path),
+                    "web1",
+                    22,
+                    &key(KEY_A)
+                ),
+                Ok(HostKeyStatus::Learned)
+            );
+            assert!(path.exists());
+        }
+
+        #[test]
+        fn test_no_skips_verification() {
+            let path = known_hosts(&format!("web1 ssh-ed25519 {}\n", KEY_A));
+            assert_eq!(
+                verify_host_key(HostKeyChecking::No, Some(&path), "web1", 22, &key(KEY_B)),
+                Ok(HostKeyStatus::Unchecked)
+            );
+            assert_eq!(
+                verify_host_key(HostKeyChecking::No, None, "web1", 22, &key(KEY_B)),
+                Ok(HostKeyStatus::Unchecked)
+            );
+            assert!(verify_host_key(HostKeyChecking::Yes, None, "web1", 22, &key(KEY_B)).is_err());
+        }
+
+        #[test]
+        fn test_check_keeps_rejection() {
+            let path = known_hosts(&format!("web1 ssh-ed25519 {}\n", KEY_A));
+            let check = HostKeyCheck {
+                host: "web1".to_string(),
+                port: 22,
+                mode: HostKeyChecking::Yes,
+                path: Some(path),
+                rejection: Arc::default(),
+            };
+            let handler_copy = check.clone();
+            assert!(handler_copy.accept(&key(KEY_A)));
+            assert_eq!(check.rejection(), None);
+            assert!(!handler_copy.accept(&key(KEY_B)));
+            assert!(check.rejection().unwrap().contains("web1"));
+        }
+    }
+}
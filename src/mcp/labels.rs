@@ -0,0 +1,222 @@
+//! Session labels and label selectors.
+//!
+//! `ssh_connect` attaches arbitrary `key: value` labels to a session, e.g.
+//! `{"env": "prod", "role": "db"}`, and `ssh_update_session` changes them.
+//! `ssh_list_sessions` filters on them with a selector of comma-separated
+//! requirements, all of which must hold:
+//!
+//! - `key=value` (or `key==value`): the label is set to `value`
+//! - `key!=value`: the label is missing or set to something else
+//! - `key`: the label is set, to anything
+//! - `!key`: the label is not set
+//!
+//! Keys and values are compared exactly (case-sensitive).
+
+use std::collections::BTreeMap;
+
+/// Most labels one session can carry
+pub(crate) const MAX_LABELS: usize = 32;
+
+/// Longest accepted label key
+pub(crate) const MAX_LABEL_KEY_LEN: usize = 63;
+
+/// Longest accepted label value
+pub(crate) const MAX_LABEL_VALUE_LEN: usize = 256;
+
+/// Check a label key: letters, digits, `-`, `_`, `.` and `/`.
+fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("label keys must not be empty".to_string());
+    }
+    if key.len() > MAX_LABEL_KEY_LEN {
+        return Err(format!(
+            "label key '{}' is longer than {} characters",
+            key, MAX_LABEL_KEY_LEN
+        ));
+    }
+    if let Some(c) = key
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '-' | '_' | '.' | '/'))
+    {
+        return Err(format!(
+            "label key '{}' contains '{}'; use letters, digits, '-', '_', '.' and '/'",
+            key, c
+        ));
+    }
+    Ok(())
+}
+
+/// Check the labels a session would end up with.
+pub(crate) fn validate_labels(labels: &BTreeMap<String, String>) -> Result<(), String> {
+    if labels.len() > MAX_LABELS {
+        return Err(format!(
+            "a session can have at most {} labels, got {}",
+            MAX_LABELS,
+            labels.len()
+        ));
+    }
+    for (key, value) in labels {
+        validate_key(key)?;
+        if value.chars().count() > MAX_LABEL_VALUE_LEN {
+            return Err(format!(
+                "value of label '{}' is longer than {} characters",
+                key, MAX_LABEL_VALUE_LEN
+            ));
+        }
+        if value.contains(',') {
+            return Err(format!(
+                "value of label '{}' contains ',', which selectors cannot match",
+                key
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// One requirement of a [`LabelSelector`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    Exists(String),
+    Missing(String),
+}
+
+impl Requirement {
+    fn parse(term: &str) -> Result<Self, String> {
+        let requirement = if let Some((key, value)) = term.split_once("!=") {
+            Self::NotEquals(key.trim().to_string(), value.trim().to_string())
+        } else if let Some((key, value)) = term.split_once("==").or_else(|| term.split_once('=')) {
+            Self::Equals(key.trim().to_string(), value.trim().to_string())
+        } else if let Some(key) = term.strip_prefix('!') {
+            Self::Missing(key.trim().to_string())
+        } else {
+            Self::Exists(term.to_string())
+        };
+        validate_key(requirement.key())
+            .map_err(|e| format!("invalid selector '{}': {}", term, e))?;
+        Ok(requirement)
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            Self::Equals(key, _)
+            | Self::NotEquals(key, _)
+            | Self::Exists(key)
+            | Self::Missing(key) => key,
+        }
+    }
+
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            Self::Equals(key, value) => labels.get(key) == Some(value),
+            Self::NotEquals(key, value) => labels.get(key) != Some(value),
+            Self::Exists(key) => labels.contains_key(key),
+            Self::Missing(key) => !labels.contains_key(key),
+        }
+    }
+}
+
+/// Parsed `labels` argument of `ssh_list_sessions`, e.g. `env=prod,role=db`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LabelSelector {
+    requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    /// Parse a selector; blank terms are ignored, so an empty selector matches everything.
+    pub(crate) fn parse(selector: &str) -> Result<Self, String> {
+        let requirements = selector
+            .split(',')
+            .map(str::trim)
+            .filter(|term| !term.is_empty())
+            .map(Requirement::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { requirements })
+    }
+
+    /// Whether `labels` satisfy every requirement.
+    pub(crate) fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements
+            .iter()
+            .all(|requirement| requirement.matches(labels))
+    }
+}
+
+/// Apply an `ssh_update_session` change to `labels`: `set` is merged in
+/// (replacing equal keys), then every key in `remove` is dropped.
+pub(crate) fn update_labels(
+    labels: &BTreeMap<String, String>,
+    set: BTreeMap<String, String>,
+    remove: &[String],
+) -> Result<BTreeMap<String, String>, String> {
+    let mut updated = labels.clone();
+    updated.extend(set);
+    for key in remove {
+        updated.remove(key);
+    }
+    validate_labels(&updated)?;
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_labels() {
+        assert!(validate_labels(&labels(&[("env", "prod"), ("team/owner", "db-ops")])).is_ok());
+        assert!(validate_labels(&labels(&[("", "x")])).is_err());
+        assert!(validate_labels(&labels(&[("env name", "prod")])).is_err());
+        assert!(validate_labels(&labels(&[("env", "prod,staging")])).is_err());
+        assert!(validate_labels(&labels(&[("k", &"v".repeat(MAX_LABEL_VALUE_LEN + 1))])).is_err());
+        let many: BTreeMap<String, String> = (0..=MAX_LABELS)
+            .map(|i| (format!("k{}", i), String::new()))
+            .collect();
+        assert!(validate_labels(&many).is_err());
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let session = labels(&[("env", "prod"), ("role", "db")]);
+        let matches = |selector: &str| LabelSelector::parse(selector).unwrap().matches(&session);
+
+        assert!(matches("env=prod,role=db"));
+        assert!(matches(" env == prod , role "));
+        assert!(matches("env!=staging,!canary"));
+        assert!(matches(""));
+        assert!(!matches("env=staging"));
+        assert!(!matches("env=prod,role=web"));
+        assert!(!matches("zone"));
+        assert!(!matches("!role"));
+        // Values are compared exactly
+        assert!(!matches("env=Prod"));
+    }
+
+    #[test]
+    fn test_selector_rejects_bad_keys() {
+        assert!(LabelSelector::parse("=prod").is_err());
+        assert!(LabelSelector::parse("env name=prod").is_err());
+        assert!(LabelSelector::parse("!").is_err());
+    }
+
+    #[test]
+    fn test_update_labels() {
+        let current = labels(&[("env", "staging"), ("role", "db")]);
+        let updated = update_labels(
+            &current,
+            labels(&[("env", "prod"), ("zone", "a")]),
+            &["role".to_string()],
+        )
+        .unwrap();
+        assert_eq!(updated, labels(&[("env", "prod"), ("zone", "a")]));
+        assert!(update_labels(&current, labels(&[("bad key", "x")]), &[]).is_err());
+    }
+}
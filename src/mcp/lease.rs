@@ -0,0 +1,347 @@
+//! Session leases.
+//!
+//! An agent can claim exclusive use of a session for a critical sequence with
+//! `ssh_lock_session`. While the lease is active, tools that run work on the
+//! session (execute, shells, forwards, cancel, disconnect) fail for every other
+//! caller with `session_locked` instead of silently interleaving commands.
+//! A client bound to an agent always locks and passes the lease as that agent.
+//!
+//! Leases expire after their TTL unless renewed by locking again, so a crashed
+//! agent cannot hold a session forever. Expired leases are dropped lazily.
+
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use once_cell::sync::Lazy;
+
+use super::error::{ErrorCode, ToolError};
+use super::types::SessionLease;
+
+/// Lease duration when `ttl_secs` is not given
+pub(crate) const DEFAULT_LEASE_TTL_SECS: u64 = 300;
+
+/// Longest lease an agent may take in one call
+pub(crate) const MAX_LEASE_TTL_SECS: u64 = 3600;
+
+/// Global lease table keyed by session_id.
+pub(crate) static SESSION_LEASES: Lazy<LeaseTable> = Lazy::new(LeaseTable::new);
+
+/// Resolve the requested lease TTL, clamped to 1..=`MAX_LEASE_TTL_SECS`.
+pub(crate) fn resolve_lease_ttl(ttl_secs: Option<u64>) -> Duration {
+    Duration::from_secs(
+        ttl_secs
+            .unwrap_or(DEFAULT_LEASE_TTL_SECS)
+            .clamp(1, MAX_LEASE_TTL_SECS),
+    )
+}
+
+/// Error returned to callers blocked by another agent's lease.
+pub(crate) fn session_locked(lease: &SessionLease) -> ToolError {
+    ToolError::new(
+        ErrorCode::SessionLocked,
+        format!(
+            "Session {} is locked by agent '{}' until {}",
+            lease.session_id, lease.holder_agent_id, lease.expires_at
+        ),
+    )
+    .with_detail("session_id", lease.session_id.as_str())
+    .with_detail("holder_agent_id", lease.holder_agent_id.as_str())
+    .with_detail("expires_at", lease.expires_at.as_str())
+}
+
+#[derive(Debug, Clone)]
+struct Lease {
+    holder: String,
+    acquired_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl Lease {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at > now
+    }
+
+    fn to_info(&self, session_id: &str) -> SessionLease {
+        SessionLease {
+            session_id: session_id.to_string(),
+            holder_agent_id: self.holder.clone(),
+            acquired_at: self.acquired_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            expires_at: self.expires_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+}
+
+/// Active leases by session_id.
+pub(crate) struct LeaseTable {
+    leases: DashMap<String, Lease>,
+}
+
+impl LeaseTable {
+    /// Create an empty lease table.
+    pub fn new() -> Self {
+        Self {
+            leases: DashMap::new(),
+        }
+    }
+
+    /// Acquire or renew the lease on a session.
+    ///
+    /// Returns the caller's lease and whether an existing lease was renewed, or
+    /// the conflicting lease when another agent holds the session.
+    pub fn acquire(
+        &self,
+        session_id: &str,
+        holder: &str,
+        ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<(SessionLease, bool), SessionLease> {
+        let expires_at = now + ttl;
+        match self.leases.entry(session_id.to_string()) {
+            Entry::Occupied(mut entry) => {
+                let current = entry.get();
+                if current.is_active(now) && current.holder != holder {
+                    return Err(current.to_info(session_id));
+                }
+                let renewed = current.is_active(now);
+                let acquired_at = if renewed { current.acquired_at } else { now };
+                let lease = Lease {
+                    holder: holder.to_string(),
+                    acquired_at,
+                    expires_at,
+                };
+                let info = lease.to_info(session_id);
+                entry.insert(lease);
+                Ok((info, renewed))
+            }
+            Entry::Vacant(entry) => {
+                let lease = Lease {
+                    holder: holder.to_string(),
+                    acquired_at: now,
+                    expires_at,
+                };
+                let info = lease.to_info(session_id);
+                entry.insert(lease);
+                Ok((info, false))
+            }
+        }
+    }
+
+    /// Release the caller's lease.
+    ///
+    /// Returns `Ok(true)` when an active lease was released, `Ok(false)` when the
+    /// session was not locked, or the conflicting lease held by another agent.
+    pub fn release(
+        &self,
+        session_id: &str,
+        holder: &str,
+        now: DateTime<Utc>,
+    ) -> Result<bool, SessionLease> {
+        match self.leases.entry(session_id.to_string()) {
+            Entry::Occupied(entry) => {
+                let current = entry.get();
+                if !current.is_active(now) {
+                    entry.remove();
+                    Ok(false)
+                } else if current.holder == holder {
+                    entry.remove();
+                    Ok(true)
+                } else {
+                    Err(current.to_info(session_id))
+                }
+            }
+            Entry::Vacant(_) => Ok(false),
+        }
+    }
+
+    /// Get the active lease on a session, if any.
+    pub fn active(&self, session_id: &str, now: DateTime<Utc>) -> Option<SessionLease> {
+        self.leases
+            .get(session_id)
+            .filter(|lease| lease.is_active(now))
+            .map(|lease| lease.to_info(session_id))
+    }
+
+    /// Check that `caller` may run work on the session.
+    ///
+    /// Fails with `session_locked` when another agent (or an anonymous caller)
+    /// would interleave with an active lease.
+    pub fn check(
+        &self,
+        session_id: &str,
+        caller: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<(), ToolError> {
+        let Some(lease) = self.active(session_id, now) else {
+            return Ok(());
+        };
+        if caller == Some(lease.holder_agent_id.as_str()) {
+            return Ok(());
+        }
+
+        Err(session_locked(&lease))
+    }
+
+    /// Drop any lease on a session (used when the session is closed).
+    pub fn remove(&self, session_id: &str) {
+        self.leases.remove(session_id);
+    }
+}
+
+impl Default for LeaseTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    mod ttl {
+        use super::*;
+
+        #[test]
+        fn test_default_and_clamping() {
+            assert_eq!(resolve_lease_ttl(None).as_secs(), DEFAULT_LEASE_TTL_SECS);
+            assert_eq!(resolve_lease_ttl(Some(0)).as_secs(), 1);
+            assert_eq!(resolve_lease_ttl(Some(86400)).as_secs(), MAX_LEASE_TTL_SECS);
+        }
+    }
+
+    mod acquire {
+        use super::*;
+
+        #[test]
+        fn test_acquire_free_session() {
+            let table = LeaseTable::new();
+            let (lease, renewed) = table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            assert!(!renewed);
+            assert_eq!(lease.holder_agent_id, "deploy");
+            assert_eq!(
+                lease.expires_at,
+                at(60).to_rfc3339_opts(SecondsFormat::Secs, true)
+            );
+        }
+
+        #[test]
+        fn test_holder_renews() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            let (lease, renewed) = table.acquire("s1", "deploy", TTL, at(30)).unwrap();
+            assert!(renewed);
+            assert_eq!(
+                lease.acquired_at,
+                at(0).to_rfc3339_opts(SecondsFormat::Secs, true)
+            );
+            assert_eq!(
+                lease.expires_at,
+                at(90).to_rfc3339_opts(SecondsFormat::Secs, true)
+            );
+        }
+
+        #[test]
+        fn test_other_agent_is_rejected() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            let conflict = table.acquire("s1", "monitor", TTL, at(10)).unwrap_err();
+            assert_eq!(conflict.holder_agent_id, "deploy");
+        }
+
+        #[test]
+        fn test_expired_lease_can_be_taken() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            let (lease, renewed) = table.acquire("s1", "monitor", TTL, at(61)).unwrap();
+            assert!(!renewed);
+            assert_eq!(lease.holder_agent_id, "monitor");
+        }
+    }
+
+    mod release {
+        use super::*;
+
+        #[test]
+        fn test_holder_releases() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            assert_eq!(table.release("s1", "deploy", at(1)), Ok(true));
+            assert!(table.active("s1", at(1)).is_none());
+        }
+
+        #[test]
+        fn test_release_unlocked_session() {
+            let table = LeaseTable::new();
+            assert_eq!(table.release("s1", "deploy", at(0)), Ok(false));
+        }
+
+        #[test]
+        fn test_other_agent_cannot_release() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            assert!(table.release("s1", "monitor", at(1)).is_err());
+            assert!(table.active("s1", at(1)).is_some());
+        }
+
+        #[test]
+        fn test_release_expired_lease() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            assert_eq!(table.release("s1", "deploy", at(120)), Ok(false));
+        }
+    }
+
+    mod check {
+        use super::*;
+
+        #[test]
+        fn test_unlocked_session_allows_everyone() {
+            let table = LeaseTable::new();
+            assert!(table.check("s1", None, at(0)).is_ok());
+            assert!(table.check("s1", Some("monitor"), at(0)).is_ok());
+        }
+
+        #[test]
+        fn test_holder_is_allowed() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            assert!(table.check("s1", Some("deploy"), at(1)).is_ok());
+        }
+
+        #[test]
+        fn test_others_get_locked_error() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+
+            for caller in [Some("monitor"), None] {
+                let err = table.check("s1", caller, at(1)).unwrap_err();
+                assert_eq!(err.code, ErrorCode::SessionLocked);
+                assert!(err.retryable);
+                assert!(err.message.contains("locked by agent 'deploy' until"));
+                assert_eq!(err.details["holder_agent_id"], "deploy");
+            }
+        }
+
+        #[test]
+        fn test_lease_lapses_after_ttl() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            assert!(table.check("s1", Some("monitor"), at(60)).is_ok());
+        }
+
+        #[test]
+        fn test_remove_drops_lease() {
+            let table = LeaseTable::new();
+            table.acquire("s1", "deploy", TTL, at(0)).unwrap();
+            table.remove("s1");
+            assert!(table.check("s1", Some("monitor"), at(1)).is_ok());
+        }
+    }
+}
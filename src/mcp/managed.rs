@@ -0,0 +1,756 @@
+//! Config-declared managed hosts.
+//!
+//! Operators list their standard fleet in `[[managed_hosts]]`. The server
+//! connects to each host itself and registers the session under the host's
+//! `name`, so agents skip `ssh_connect` and pass e.g. `session_id="web1"` to
+//! any tool.
+//!
+//! - `connect = "startup"` hosts are connected when the server starts
+//! - `connect = "lazy"` hosts are connected on the first use of their name
+//!
+//! Once a host is wanted (startup, or first used), a supervisor pings its
+//! session every [`HEALTH_CHECK_INTERVAL`] and reconnects it when it is gone,
+//! including after an agent called `ssh_disconnect` on it. Managed sessions
+//! never idle out and are shared with every agent (`share_mode = "full"`).
+//!
+//! A host with `pool_size > 0` also keeps that many spare connections dialed
+//! ahead of time. The same supervisor tick pings the spares, drops dead ones
+//! and dials replacements. A spare is used instead of a fresh handshake:
+//!
+//! - when the managed session itself has to be reconnected
+//! - when `ssh_connect` targets the host's address and username with default
+//!   compression and without `persistent`; the caller gets its own session
+//!
+//! Spares count toward the host's session cap.
+//!
+//! The session's `health_history` survives reconnects, so a host whose
+//! connection keeps dropping shows up as `flapping` in `ssh_list_sessions`.
+
+use std::collections::HashSet;
+use std::env;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use futures::future::join_all;
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use super::accounting::billed_agent;
+use super::algorithms::AlgorithmPreferences;
+use super::audit::{self, AuditEvent};
+use super::client::{
+    RTT_PROBE_TIMEOUT, TRANSPORT_ZLIB_LEVEL, connect_to_ssh_with_retry, measure_rtt, parse_address,
+};
+use super::config::{
+    ManagedConnect, ManagedHostConfig, resolve_compression, resolve_connect_timeout,
+    resolve_identity_probe, resolve_inactivity_timeout, resolve_keepalive, resolve_max_retries,
+    resolve_read_only, resolve_retry_delay, server_config,
+};
+use super::error::ToolError;
+use super::helpers::sync_on_connect;
+use super::host_limit::{host_key, reserve_host_slot};
+use super::identity::probe_identity;
+use super::known_hosts::resolve_host_key_checking;
+use super::reverse::{REVERSE_FORWARDS, ReverseForwards, restore_tunnels};
+use super::session::SshClientHandler;
+use super::storage::{SESSION_STORAGE, SessionRef};
+use super::traffic::ByteCounters;
+use super::types::{HealthCheck, ManagedHostInfo, SessionInfo, ShareMode};
+
+/// Time between health checks of connected managed sessions
+pub(crate) const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Longest accepted managed host name
+const MAX_NAME_LEN: usize = 64;
+
+/// Largest accepted `pool_size`
+pub(crate) const MAX_POOL_SIZE: usize = 8;
+
+/// Process-wide managed host state.
+pub(crate) static MANAGED_HOSTS: Lazy<ManagedHosts> = Lazy::new(ManagedHosts::new);
+
+/// A pre-dialed connection waiting in a managed host's pool.
+pub(crate) struct WarmSession {
+    pub handle: Arc<russh::client::Handle<SshClientHandler>>,
+    /// Reverse forward registry of the connection's client handler
+    pub reverse: Arc<ReverseForwards>,
+    /// Byte counters of the connection
+    pub traffic: Arc<ByteCounters>,
+    dialed_at: Instant,
+}
+
+/// Connection state of one managed host.
+#[derive(Default)]
+struct ManagedState {
+    /// Serializes connects of the host (supervisor and lazy first use)
+    connecting: Arc<Mutex<()>>,
+    /// Supervised: startup hosts always, lazy hosts after their first use
+    wanted: bool,
+    connects: u64,
+    connected_at: Option<String>,
+    last_error: Option<String>,
+    /// Spare connections, oldest first
+    pool: Vec<WarmSession>,
+    /// Spares handed out or promoted since startup
+    pool_hits: u64,
+    /// Health checks of the replaced session, carried to the next one
+    health_history: Vec<HealthCheck>,
+}
+
+/// Connection state of every managed host, keyed by name.
+pub(crate) struct ManagedHosts {
+    states: DashMap<String, ManagedState>,
+    started: AtomicBool,
+}
+
+impl ManagedHosts {
+    fn new() -> Self {
+        Self {
+            states: DashMap::new(),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    fn connect_lock(&self, name: &str) -> Arc<Mutex<()>> {
+        self.states
+            .entry(name.to_string())
+            .or_default()
+            .connecting
+            .clone()
+    }
+
+    fn mark_wanted(&self, name: &str) {
+        self.states.entry(name.to_string()).or_default().wanted = true;
+    }
+
+    fn is_wanted(&self, name: &str) -> bool {
+        self.states.get(name).is_some_and(|state| state.wanted)
+    }
+
+    fn record_connected(&self, name: &str, connected_at: String) {
+        let mut state = self.states.entry(name.to_string()).or_default();
+        state.connects += 1;
+        state.connected_at = Some(connected_at);
+        state.last_error = None;
+    }
+
+    fn record_error(&self, name: &str, error: String) {
+        self.states.entry(name.to_string()).or_default().last_error = Some(error);
+    }
+
+    /// Remove the session of `name`, keeping its health history for the next one.
+    fn retire_session(&self, name: &str) -> Option<SessionRef> {
+        let session_ref = SESSION_STORAGE.remove(name)?;
+        self.states
+            .entry(name.to_string())
+            .or_default()
+            .health_history = session_ref.info.health_history.clone();
+        Some(session_ref)
+    }
+
+    fn take_health_history(&self, name: &str) -> Vec<HealthCheck> {
+        self.states
+            .get_mut(name)
+            .map(|mut state| std::mem::take(&mut state.health_history))
+            .unwrap_or_default()
+    }
+
+    /// Take the oldest open spare of `name`, discarding closed ones on the way.
+    fn take_warm(&self, name: &str) -> Option<WarmSession> {
+        let mut state = self.states.get_mut(name)?;
+        while !state.pool.is_empty() {
+            let warm = state.pool.remove(0);
+            if !warm.handle.is_closed() {
+                state.pool_hits += 1;
+                return Some(warm);
+            }
+        }
+        None
+    }
+
+    fn put_warm(&self, name: &str, warm: WarmSession) {
+        self.states
+            .entry(name.to_string())
+            .or_default()
+            .pool
+            .push(warm);
+    }
+
+    /// Remove every spare of `name`, e.g. to health-check them without holding the map.
+    fn drain_warm(&self, name: &str) -> Vec<WarmSession> {
+        self.states
+            .get_mut(name)
+            .map(|mut state| std::mem::take(&mut state.pool))
+            .unwrap_or_default()
+    }
+
+    fn warm_count(&self, name: &str) -> usize {
+        self.states.get(name).map_or(0, |state| state.pool.len())
+    }
+
+    /// Status of `host` for ssh_list_managed_hosts.
+    fn info(&self, host: &ManagedHostConfig) -> ManagedHostInfo {
+        let connected = SESSION_STORAGE
+            .get(&host.name)
+            .is_some_and(|session_ref| !session_ref.handle.is_closed());
+        let state = self.states.get(&host.name);
+        ManagedHostInfo {
+            name: host.name.clone(),
+            host: host.address.clone(),
+            username: host.username.clone(),
+            connect: match host.connect {
+                ManagedConnect::Startup => "startup".to_string(),
+                ManagedConnect::Lazy => "lazy".to_string(),
+            },
+            connected,
+            connected_at: state
+                .as_ref()
+                .and_then(|state| state.connected_at.clone())
+                .filter(|_| connected),
+            reconnects: state
+                .as_ref()
+                .map_or(0, |state| state.connects.saturating_sub(1)),
+            last_error: state.as_ref().and_then(|state| state.last_error.clone()),
+            pool_size: pool_size(host),
+            warm: state.as_ref().map_or(0, |state| state.pool.len()),
+            pool_hits: state.map_or(0, |state| state.pool_hits),
+            region: host.region.clone(),
+            datacenter: host.datacenter.clone(),
+        }
+    }
+}
+
+/// Whether `name` can serve as a session ID: `[A-Za-z0-9._-]`, at most 64 characters.
+pub(crate) fn is_valid_managed_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Entries with a valid, unique name; the first of duplicate names wins.
+fn usable_hosts(hosts: &[ManagedHostConfig]) -> Vec<&ManagedHostConfig> {
+    let mut seen = HashSet::new();
+    hosts
+        .iter()
+        .filter(|host| is_valid_managed_name(&host.name) && seen.insert(host.name.as_str()))
+        .collect()
+}
+
+/// Spare connections kept for `host`, clamped to [`MAX_POOL_SIZE`].
+fn pool_size(host: &ManagedHostConfig) -> usize {
+    host.pool_size.min(MAX_POOL_SIZE)
+}
+
+/// Whether `address` names the same host and port as `host.address`.
+fn same_endpoint(host: &ManagedHostConfig, address: &str) -> bool {
+    let port = |address: &str| parse_address(address).map(|(_, port)| port).ok();
+    host_key(&host.address) == host_key(address) && port(&host.address) == port(address)
+}
+
+/// Spare connections held across all managed hosts.
+pub(crate) fn pooled_total() -> usize {
+    managed_hosts()
+        .into_iter()
+        .map(|host| MANAGED_HOSTS.warm_count(&host.name))
+        .sum()
+}
+
+/// Spare connections held for `key` (see [`host_key`]) across all managed hosts.
+pub(crate) fn pooled_on_host(key: &str) -> usize {
+    managed_hosts()
+        .into_iter()
+        .filter(|host| host_key(&host.address) == key)
+        .map(|host| MANAGED_HOSTS.warm_count(&host.name))
+        .sum()
+}
+
+/// Take a spare connection for an `ssh_connect` to `username@address`.
+///
+/// Only connects asking for the host's compression setting and without
+/// `persistent` match, since spares are dialed with both.
+pub(crate) fn take_pooled_session(
+    address: &str,
+    username: &str,
+    compress: bool,
+    persistent: bool,
+) -> Option<WarmSession> {
+    if persistent {
+        return None;
+    }
+    let host = managed_hosts().into_iter().find(|host| {
+        pool_size(host) > 0
+            && host.username == username
+            && resolve_compression(host.compress) == compress
+            && same_endpoint(host, address)
+    })?;
+    let warm = MANAGED_HOSTS.take_warm(&host.name)?;
+    info!(
+        "Using warm connection of managed host {} ({}s old) for {}@{}",
+        host.name,
+        warm.dialed_at.elapsed().as_secs(),
+        username,
+        address
+    );
+    Some(warm)
+}
+
+/// Managed hosts from the server configuration.
+pub(crate) fn managed_hosts() -> Vec<&'static ManagedHostConfig> {
+    usable_hosts(&server_config().managed_hosts)
+}
+
+/// Look up a managed host by name.
+pub(crate) fn find_managed_host(name: &str) -> Option<&'static ManagedHostConfig> {
+    managed_hosts().into_iter().find(|host| host.name == name)
+}
+
+/// Status of every managed host, in configuration order.
+pub(crate) fn managed_host_infos() -> Vec<ManagedHostInfo> {
+    managed_hosts()
+        .into_iter()
+        .map(|host| MANAGED_HOSTS.info(host))
+        .collect()
+}
+
+/// Connect the managed host named `session_id` if it is one and not connected.
+///
+/// Returns `Ok(false)` when no managed host has that name.
+pub(crate) async fn ensure_managed_session(session_id: &str) -> Result<bool, ToolError> {
+    let Some(host) = find_managed_host(session_id) else {
+        return Ok(false);
+    };
+    connect_managed(host).await?;
+    Ok(true)
+}
+
+/// Connect `host` unless its session is open; concurrent callers wait for one connect.
+pub(crate) async fn connect_managed(host: &ManagedHostConfig) -> Result<(), ToolError> {
+    MANAGED_HOSTS.mark_wanted(&host.name);
+    let lock = MANAGED_HOSTS.connect_lock(&host.name);
+    let _connecting = lock.lock().await;
+
+    if let Some(session_ref) = SESSION_STORAGE.get(&host.name) {
+        if !session_ref.handle.is_closed() {
+            return Ok(());
+        }
+        warn!("Managed session {} is closed, reconnecting", host.name);
+        MANAGED_HOSTS.retire_session(&host.name);
+    }
+
+    match open_session(host).await {
+        Ok(connected_at) => {
+            MANAGED_HOSTS.record_connected(&host.name, connected_at);
+            Ok(())
+        }
+        Err(e) => {
+            warn!(
+                "Managed host {} failed to connect: {}",
+                host.name, e.message
+            );
+            MANAGED_HOSTS.record_error(&host.name, e.message.clone());
+            Err(e)
+        }
+    }
+}
+
+/// Dial and authenticate `host`; returns the connection and the retries it took.
+///
+/// `persistent` connections never idle out; spares are dialed without it and
+/// kept alive by the supervisor's pings.
+async fn dial(host: &ManagedHostConfig, persistent: bool) -> Result<(WarmSession, u32), String> {
+    let password = match &host.password_env {
+        Some(var) => Some(env::var(var).map_err(|_| {
+            format!(
+                "Managed host {}: password variable {} is not set",
+                host.name, var
+            )
+        })?),
+        None => None,
+    };
+    let reverse = Arc::new(ReverseForwards::default());
+    let traffic = Arc::new(ByteCounters::default());
+    let (handle, retry_attempts) = connect_to_ssh_with_retry(
+        &host.address,
+        &host.username,
+        password.as_deref(),
+        host.key_path.as_deref(),
+        None,
+        resolve_connect_timeout(None),
+        resolve_inactivity_timeout(),
+        resolve_max_retries(None),
+        resolve_retry_delay(None),
+        resolve_compression(host.compress),
+        persistent,
+        resolve_keepalive(None, None),
+        &AlgorithmPreferences::default(),
+        resolve_host_key_checking(None),
+        &[],
+        false,
+        &reverse,
+        &traffic,
+    )
+    .await?;
+    let warm = WarmSession {
+        handle: Arc::new(handle),
+        reverse,
+        traffic,
+        dialed_at: Instant::now(),
+    };
+    Ok((warm, retry_attempts))
+}
+
+/// Dial `host` once and hang up again; the startup canary check.
+pub(crate) async fn probe(host: &ManagedHostConfig) -> Result<(), String> {
+    let (warm, _) = dial(host, false).await?;
+    let _ = warm
+        .handle
+        .disconnect(russh::Disconnect::ByApplication, "Canary check", "en")
+        .await;
+    Ok(())
+}
+
+/// Register a session for `host` under `host.name`, promoting a spare when one
+/// is open and dialing otherwise; returns the connect time.
+async fn open_session(host: &ManagedHostConfig) -> Result<String, ToolError> {
+    let compress = resolve_compression(host.compress);
+    let timeout = resolve_connect_timeout(None);
+    let keepalive = resolve_keepalive(None, None);
+    let carried_reverse = REVERSE_FORWARDS.take_for_reconnect(&host.name, None);
+
+    let (warm, retry_attempts) = match MANAGED_HOSTS.take_warm(&host.name) {
+        Some(warm) => {
+            info!("Promoting warm connection of managed host {}", host.name);
+            (warm, 0)
+        }
+        None => {
+            let reserved = reserve_host_slot(&host.address);
+            let dialed = match reserved {
+                Ok(_host_slot) => dial(host, true)
+                    .await
+                    .map_err(|e| ToolError::connection(e).with_stage("connect")),
+                Err(e) => Err(e),
+            };
+            match dialed {
+                Ok(dialed) => dialed,
+                Err(e) => {
+                    audit::record(
+                        AuditEvent::new("managed_connect")
+                            .host(&host.address, &host.username)
+                            .session_id(&host.name)
+                            .failed(&e.message),
+                    );
+                    REVERSE_FORWARDS.park(&host.name, None, carried_reverse);
+                    return Err(e.with_detail("host", host.address.as_str()));
+                }
+            }
+        }
+    };
+
+    let WarmSession {
+        handle,
+        reverse,
+        traffic,
+        ..
+    } = warm;
+    let identity = if resolve_identity_probe() {
+        probe_identity(&handle).await
+    } else {
+        None
+    };
+    let connected_at = chrono::Utc::now().to_rfc3339();
+    let mut session_info = SessionInfo {
+        session_id: host.name.clone(),
+        name: Some(host.name.clone()),
+        agent_id: None,
+        shared: Some(ShareMode::Full),
+        host: host.address.clone(),
+        username: host.username.clone(),
+        connected_at: connected_at.clone(),
+        default_timeout_secs: timeout.as_secs(),
+        retry_attempts,
+        compression_enabled: compress,
+        compression_level: compress.then_some(TRANSPORT_ZLIB_LEVEL),
+        keepalive_interval_secs: keepalive.interval_secs,
+        keepalive_max: keepalive.max,
+        algorithms: None,
+        last_health_check: None,
+        healthy: None,
+        health_history: Vec::new(),
+        flapping: false,
+        identity,
+        rtt_ms: None,
+        rtt_avg_ms: None,
+        region: host.region.clone(),
+        datacenter: host.datacenter.clone(),
+        jump_hosts: Vec::new(),
+        rebooting: false,
+        read_only: resolve_read_only(None),
+        agent_forwarding: false,
+        profile: None,
+        command_timeout_secs: None,
+        bytes_sent: 0,
+        bytes_received: 0,
+        stats: Default::default(),
+        labels: Default::default(),
+    };
+    for check in MANAGED_HOSTS.take_health_history(&host.name) {
+        session_info.record_health(check.at, check.healthy);
+    }
+    if let Some(rtt) = measure_rtt(&handle, RTT_PROBE_TIMEOUT).await {
+        session_info.record_rtt(rtt);
+    }
+    restore_tunnels(&handle, &reverse, carried_reverse).await;
+
+    sync_on_connect(handle.clone(), traffic.clone(), host.name.clone());
+    traffic.bill_to(billed_agent(None, None));
+    SESSION_STORAGE.insert(host.name.clone(), session_info, handle, traffic);
+    REVERSE_FORWARDS.attach(&host.name, None, reverse);
+    audit::record(
+        AuditEvent::new("managed_connect")
+            .host(&host.address, &host.username)
+            .session_id(&host.name),
+    );
+    info!(
+        "Managed host {} connected ({}@{})",
+        host.name, host.username, host.address
+    );
+    Ok(connected_at)
+}
+
+/// Ping a wanted host's session and reconnect it when it is gone or unresponsive.
+async fn check_host(host: &ManagedHostConfig) {
+    if let Some(session_ref) = SESSION_STORAGE.get(&host.name) {
+        let now = chrono::Utc::now().to_rfc3339();
+        if !session_ref.handle.is_closed()
+            && let Some(rtt) = measure_rtt(&session_ref.handle, RTT_PROBE_TIMEOUT).await
+        {
+            SESSION_STORAGE.update_health(&host.name, now, true);
+            SESSION_STORAGE.record_rtt(&host.name, rtt);
+            return;
+        }
+        SESSION_STORAGE.update_health(&host.name, now, false);
+        // A session that stopped answering pings is replaced, not waited for
+        if let Some(session_ref) = MANAGED_HOSTS.retire_session(&host.name) {
+            let _ = session_ref
+                .handle
+                .disconnect(
+                    russh::Disconnect::ByApplication,
+                    "Health check failed",
+                    "en",
+                )
+                .await;
+        }
+    }
+    // Errors are recorded in the host state and retried on the next tick
+    let _ = connect_managed(host).await;
+    refill_pool(host).await;
+}
+
+/// Ping `host`'s spares, drop the dead ones and dial up to its `pool_size`.
+async fn refill_pool(host: &ManagedHostConfig) {
+    let target = pool_size(host);
+    let spares = MANAGED_HOSTS.drain_warm(&host.name);
+    if target == 0 && spares.is_empty() {
+        return;
+    }
+    let checks = spares.into_iter().map(|warm| async move {
+        let alive = !warm.handle.is_closed()
+            && measure_rtt(&warm.handle, RTT_PROBE_TIMEOUT).await.is_some();
+        (warm, alive)
+    });
+    for (warm, alive) in join_all(checks).await {
+        if alive && MANAGED_HOSTS.warm_count(&host.name) < target {
+            MANAGED_HOSTS.put_warm(&host.name, warm);
+        } else {
+            let _ = warm
+                .handle
+                .disconnect(russh::Disconnect::ByApplication, "Pool spare dropped", "en")
+                .await;
+        }
+    }
+
+    while MANAGED_HOSTS.warm_count(&host.name) < target {
+        // A full host keeps its slots for agents rather than spares
+        let Ok(_host_slot) = reserve_host_slot(&host.address) else {
+            break;
+        };
+        match dial(host, false).await {
+            Ok((warm, _)) => MANAGED_HOSTS.put_warm(&host.name, warm),
+            Err(e) => {
+                warn!("Managed host {} failed to dial a spare: {}", host.name, e);
+                break;
+            }
+        }
+    }
+}
+
+/// Connect startup hosts and supervise managed sessions; later calls do nothing.
+///
+/// Needs a Tokio runtime; called when an MCP handler is created.
+pub(crate) fn start() {
+    let hosts = managed_hosts();
+    if hosts.is_empty() || MANAGED_HOSTS.started.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let skipped = server_config().managed_hosts.len() - hosts.len();
+    if skipped > 0 {
+        warn!(
+            "Ignoring {} managed host(s) with an invalid or duplicate name",
+            skipped
+        );
+    }
+    if tokio::runtime::Handle::try_current().is_err() {
+        warn!("No async runtime; managed hosts connect on first use only");
+        return;
+    }
+
+    for host in &hosts {
+        if host.connect == ManagedConnect::Startup {
+            MANAGED_HOSTS.mark_wanted(&host.name);
+        }
+    }
+    info!("Supervising {} managed host(s)", hosts.len());
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            // The first tick completes immediately, connecting startup hosts
+            interval.tick().await;
+            let wanted = hosts
+                .iter()
+                .copied()
+                .filter(|host| MANAGED_HOSTS.is_wanted(&host.name));
+            join_all(wanted.map(check_host)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(name: &str, connect: ManagedConnect) -> ManagedHostConfig {
+        ManagedHostConfig {
+            name: name.to_string(),
+            address: format!("{}.example.com:22", name),
+            username: "deploy".to_string(),
+            key_path: None,
+            password_env: None,
+            connect,
+            compress: None,
+            pool_size: 0,
+            region: None,
+            datacenter: None,
+        }
+    }
+
+    mod names {
+        use super::*;
+
+        #[test]
+        fn test_valid_names() {
+            assert!(is_valid_managed_name("web1"));
+            assert!(is_valid_managed_name("db-primary.eu_1"));
+            assert!(!is_valid_managed_name(""));
+            assert!(!is_valid_managed_name("web 1"));
+            assert!(!is_valid_managed_name("web/1"));
+            assert!(!is_valid_managed_name(&"a".repeat(65)));
+        }
+
+        #[test]
+        fn test_invalid_and_duplicate_names_are_skipped() {
+            let hosts = vec![
+                host("web1", ManagedConnect::Startup),
+                host("bad name", ManagedConnect::Startup),
+                host("web1", ManagedConnect::Lazy),
+                host("bmc", ManagedConnect::Lazy),
+            ];
+            let usable: Vec<(&str, ManagedConnect)> = usable_hosts(&hosts)
+                .iter()
+                .map(|host| (host.name.as_str(), host.connect))
+                .collect();
+            assert_eq!(
+                usable,
+                vec![
+                    ("web1", ManagedConnect::Startup),
+                    ("bmc", ManagedConnect::Lazy)
+                ]
+            );
+        }
+    }
+
+    mod pool {
+        use super::*;
+
+        #[test]
+        fn test_pool_size_is_clamped() {
+            let mut config = host("web1", ManagedConnect::Startup);
+            config.pool_size = 3;
+            assert_eq!(pool_size(&config), 3);
+            config.pool_size = 100;
+            assert_eq!(pool_size(&config), MAX_POOL_SIZE);
+        }
+
+        #[test]
+        fn test_same_endpoint() {
+            let config = host("web1", ManagedConnect::Startup);
+            assert!(same_endpoint(&config, "WEB1.example.com:22"));
+            assert!(same_endpoint(&config, "web1.example.com"));
+            assert!(!same_endpoint(&config, "web1.example.com:2222"));
+            assert!(!same_endpoint(&config, "web2.example.com:22"));
+        }
+
+        #[test]
+        fn test_persistent_connects_never_use_the_pool() {
+            assert!(take_pooled_session("web1.example.com:22", "deploy", true, true).is_none());
+        }
+
+        #[test]
+        fn test_empty_pool() {
+            let hosts = ManagedHosts::new();
+            assert!(hosts.take_warm("managed-test-cold").is_none());
+            assert!(hosts.drain_warm("managed-test-cold").is_empty());
+            assert_eq!(hosts.warm_count("managed-test-cold"), 0);
+        }
+    }
+
+    mod state {
+        use super::*;
+
+        #[test]
+        fn test_never_connected_host() {
+            let hosts = ManagedHosts::new();
+            let info = hosts.info(&host("managed-test-idle", ManagedConnect::Lazy));
+            assert!(!info.connected);
+            assert_eq!(info.connect, "lazy");
+            assert_eq!(info.reconnects, 0);
+            assert!(info.last_error.is_none());
+            assert_eq!((info.pool_size, info.warm, info.pool_hits), (0, 0, 0));
+            assert!(!hosts.is_wanted("managed-test-idle"));
+        }
+
+        #[test]
+        fn test_reconnects_and_errors() {
+            let hosts = ManagedHosts::new();
+            let config = host("managed-test-flaky", ManagedConnect::Startup);
+            hosts.mark_wanted(&config.name);
+            hosts.record_connected(&config.name, "2024-01-01T00:00:00Z".to_string());
+            hosts.record_error(&config.name, "Connection refused".to_string());
+
+            let info = hosts.info(&config);
+            assert!(hosts.is_wanted(&config.name));
+            assert_eq!(info.reconnects, 0);
+            assert_eq!(info.last_error.as_deref(), Some("Connection refused"));
+
+            hosts.record_connected(&config.name, "2024-01-01T00:05:00Z".to_string());
+            let info = hosts.info(&config);
+            assert_eq!(info.reconnects, 1);
+            assert!(info.last_error.is_none());
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Remote file manifests.
+//!
+//! `ssh_manifest` lists a remote tree with sizes and checksums computed on the
+//! host, so agents can detect drift or verify a deploy without downloading
+//! anything. The listing runs through one exec channel with `find`, `wc` and
+//! the first checksum tool available:
+//!
+//! 1. `sha256sum` (GNU coreutils, BusyBox)
+//! 2. `shasum -a 256` (macOS, Perl)
+//! 3. `cksum` (POSIX CRC, always present but not cryptographic)
+//!
+//! Symbolic links are reported with their stored target and never followed.
+//! Paths containing tabs or newlines are not supported.
+
+use super::command_wrap::shell_quote;
+use super::types::{ManifestEntry, ManifestEntryKind};
+
+/// Levels below the root listed when `depth` is not given
+pub(crate) const DEFAULT_MANIFEST_DEPTH: usize = 10;
+
+/// Largest accepted `depth`
+pub(crate) const MAX_MANIFEST_DEPTH: usize = 64;
+
+/// Entries returned when `max_entries` is not given
+pub(crate) const DEFAULT_MANIFEST_ENTRIES: usize = 2000;
+
+/// Largest accepted `max_entries`
+pub(crate) const MAX_MANIFEST_ENTRIES: usize = 20_000;
+
+/// Resolve the listing depth, clamped to 0..=`MAX_MANIFEST_DEPTH`.
+pub(crate) fn resolve_manifest_depth(depth: Option<usize>) -> usize {
+    depth
+        .unwrap_or(DEFAULT_MANIFEST_DEPTH)
+        .min(MAX_MANIFEST_DEPTH)
+}
+
+/// Resolve the entry limit, clamped to 1..=`MAX_MANIFEST_ENTRIES`.
+pub(crate) fn resolve_manifest_entries(max_entries: Option<usize>) -> usize {
+    max_entries
+        .unwrap_or(DEFAULT_MANIFEST_ENTRIES)
+        .clamp(1, MAX_MANIFEST_ENTRIES)
+}
+
+/// Command printing the checksum algorithm, then up to `limit + 1` entries of
+/// the tree at `path`, one tab-separated line each:
+///
+/// ```text
+/// algorithm<TAB>sha256
+/// f<TAB>size<TAB>checksum<TAB>path
+/// d<TAB><TAB><TAB>path
+/// l<TAB><TAB><TAB>path<TAB>target
+/// ```
+///
+/// The extra entry tells [`parse_manifest`] that the listing was truncated.
+/// A missing root exits 1 with an error on stderr; unreadable subdirectories
+/// are skipped.
+pub(crate) fn manifest_command(path: &str, depth: usize, limit: usize) -> String {
+    format!(
+        "p={}; \
+         if [ ! -e \"$p\" ] && [ ! -L \"$p\" ]; then echo \"$p: No such file or directory\" >&2; exit 1; fi; \
+         if command -v sha256sum >/dev/null 2>&1; then a=sha256; h='sha256sum'; \
+         elif command -v shasum >/dev/null 2>&1; then a=sha256; h='shasum -a 256'; \
+         elif command -v cksum >/dev/null 2>&1; then a=cksum; h='cksum'; \
+         else a=none; h=''; fi; \
+         printf 'algorithm\\t%s\\n' \"$a\"; \
+         find \"$p\" -maxdepth {} 2>/dev/null | head -n {} | while IFS= read -r f; do \
+         if [ -L \"$f\" ]; then printf 'l\\t\\t\\t%s\\t%s\\n' \"$f\" \"$(readlink -- \"$f\")\"; \
+         elif [ -d \"$f\" ]; then printf 'd\\t\\t\\t%s\\n' \"$f\"; \
+         elif [ -f \"$f\" ]; then s=$(wc -c < \"$f\" 2>/dev/null | tr -d ' '); c=''; \
+         if [ -n \"$h\" ]; then c=$($h < \"$f\" 2>/dev/null | cut -d ' ' -f 1); fi; \
+         printf 'f\\t%s\\t%s\\t%s\\n' \"$s\" \"$c\" \"$f\"; \
+         else printf 'o\\t\\t\\t%s\\n' \"$f\"; fi; \
+         done",
+        shell_quote(path),
+        depth,
+        limit + 1
+    )
+}
+
+/// Parsed output of [`manifest_command`].
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Manifest {
+    /// `sha256` or `cksum`; `None` when the host has no checksum tool
+    pub algorithm: Option<String>,
+    /// Entries sorted by path, at most `limit`
+    pub entries: Vec<ManifestEntry>,
+    /// More entries existed than `limit`
+    pub truncated: bool,
+}
+
+/// Parse the listing of the tree rooted at `root`, keeping at most `limit` entries.
+pub(crate) fn parse_manifest(stdout: &str, root: &str, limit: usize) -> Manifest {
+    let mut manifest = Manifest::default();
+    for line in stdout.lines() {
+        let mut fields = line.splitn(5, '\t');
+        let (Some(tag), Some(size), Some(checksum), Some(path)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            if let Some(algorithm) = line.strip_prefix("algorithm\t") {
+                manifest.algorithm = (algorithm != "none").then(|| algorithm.to_string());
+            }
+            continue;
+        };
+        let kind = match tag {
+            "f" => ManifestEntryKind::File,
+            "d" => ManifestEntryKind::Directory,
+            "l" => ManifestEntryKind::Symlink,
+            "o" => ManifestEntryKind::Other,
+            _ => continue,
+        };
+        let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        manifest.entries.push(ManifestEntry {
+            path: relative_path(root, path),
+            kind,
+            size: size.parse().ok(),
+            checksum: non_empty(checksum),
+            target: fields.next().and_then(non_empty),
+        });
+    }
+
+    manifest.truncated = manifest.entries.len() > limit;
+    manifest.entries.truncate(limit);
+    manifest.entries.sort_by(|a, b| a.path.cmp(&b.path));
+    manifest
+}
+
+/// `path` relative to `root` as printed by `find`; the root itself is ".".
+fn relative_path(root: &str, path: &str) -> String {
+    let root = root.trim_end_matches('/');
+    let relative = match path.strip_prefix(root) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.trim_start_matches('/'),
+        _ => return path.to_string(),
+    };
+    if relative.is_empty() {
+        ".".to_string()
+    } else {
+        relative.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod limits {
+        use super::*;
+
+        #[test]
+        fn test_defaults_and_clamping() {
+            assert_eq!(resolve_manifest_depth(None), DEFAULT_MANIFEST_DEPTH);
+            assert_eq!(resolve_manifest_depth(Some(0)), 0);
+            assert_eq!(resolve_manifest_depth(Some(1000)), MAX_MANIFEST_DEPTH);
+            assert_eq!(resolve_manifest_entries(None), DEFAULT_MANIFEST_ENTRIES);
+            assert_eq!(resolve_manifest_entries(Some(0)), 1);
+            assert_eq!(
+                resolve_manifest_entries(Some(1_000_000)),
+                MAX_MANIFEST_ENTRIES
+            );
+        }
+    }
+
+    mod command {
+        use super::*;
+
+        #[test]
+        fn test_path_is_quoted_and_limited() {
+            let command = manifest_command("/srv/app $(reboot)", 3, 100);
+            assert!(command.starts_with("p='/srv/app $(reboot)';"));
+            assert!(command.contains("-maxdepth 3"));
+            assert!(command.contains("head -n 101"));
+        }
+
+        #[test]
+        fn test_prefers_sha256_over_cksum() {
+            let command = manifest_command("/srv", 1, 10);
+            let sha = command.find("sha256sum").unwrap();
+            let crc = command.find("cksum").unwrap();
+            assert!(sha < crc);
+        }
+    }
+
+    mod parsing {
+        use super::*;
+
+        const OUTPUT: &str = "algorithm\tsha256\n\
+            d\t\t\t/srv/app\n\
+            f\t12\tabc123\t/srv/app/run.sh\n\
+            l\t\t\t/srv/app/current\treleases/2\n\
+            f\t\t\t/srv/app/secret.key\n\
+            d\t\t\t/srv/app/releases\n";
+
+        #[test]
+        fn test_entries_sorted_and_relative() {
+            let manifest = parse_manifest(OUTPUT, "/srv/app/", 100);
+            assert_eq!(manifest.algorithm.as_deref(), Some("sha256"));
+            assert!(!manifest.truncated);
+            let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+            assert_eq!(
+                paths,
+                vec![".", "current", "releases", "run.sh", "secret.key"]
+            );
+
+            let run = &manifest.entries[3];
+            assert_eq!(run.kind, ManifestEntryKind::File);
+            assert_eq!(run.size, Some(12));
+            assert_eq!(run.checksum.as_deref(), Some("abc123"));
+
+            let link = &manifest.entries[1];
+            assert_eq!(link.kind, ManifestEntryKind::Symlink);
+            assert_eq!(link.target.as_deref(), Some("releases/2"));
+            assert_eq!(link.size, None);
+        }
+
+        #[test]
+        fn test_unreadable_file_has_no_checksum() {
+            let manifest = parse_manifest(OUTPUT, "/srv/app", 100);
+            let secret = &manifest.entries[4];
+            assert_eq!(secret.size, None);
+            assert_eq!(secret.checksum, None);
+        }
+
+        #[test]
+        fn test_truncated() {
+            let manifest = parse_manifest(OUTPUT, "/srv/app", 2);
+            assert!(manifest.truncated);
+            assert_eq!(manifest.entries.len(), 2);
+        }
+
+        #[test]
+        fn test_no_checksum_tool() {
+            let manifest =
+                parse_manifest("algorithm\tnone\nf\t5\t\t/etc/hosts\n", "/etc/hosts", 10);
+            assert_eq!(manifest.algorithm, None);
+            assert_eq!(manifest.entries[0].path, ".");
+            assert_eq!(manifest.entries[0].checksum, None);
+        }
+
+        #[test]
+        fn test_root_slash() {
+            assert_eq!(relative_path("/", "/"), ".");
+            assert_eq!(relative_path("/", "/etc"), "etc");
+            assert_eq!(
+                relative_path("/srv/app", "/srv/application"),
+                "/srv/application"
+            );
+        }
+    }
+}
@@ -25,6 +25,7 @@ pub struct ConnectMessageBuilder {
     retry_attempts: u32,
     persistent: bool,
     reused: bool,
+    template: Option<String>,
 }
 
 impl ConnectMessageBuilder {
@@ -43,6 +44,7 @@ impl ConnectMessageBuilder {
             retry_attempts: 0,
             persistent: false,
             reused: false,
+            template: None,
         }
     }
 
@@ -76,8 +78,29 @@ impl ConnectMessageBuilder {
         self
     }
 
+    /// Override the default message with a template (see `MessageTemplates`).
+    pub fn with_template(mut self, template: Option<impl Into<String>>) -> Self {
+        self.template = template.map(Into::into);
+        self
+    }
+
     /// Build the message string.
     pub fn build(&self) -> String {
+        apply_template(
+            self.template.as_deref(),
+            self.default_message(),
+            &[
+                ("session_id", &self.session_id),
+                ("username", &self.username),
+                ("host", &self.host),
+                ("agent_id", self.agent_id.as_deref().unwrap_or("")),
+                ("name", self.name.as_deref().unwrap_or("")),
+                ("retry_attempts", &self.retry_attempts.to_string()),
+            ],
+        )
+    }
+
+    fn default_message(&self) -> String {
         let header = if self.reused {
             "SESSION REUSED"
         } else {
@@ -131,6 +154,7 @@ pub struct ExecuteMessageBuilder {
     session_id: String,
     command: String,
     agent_id: Option<String>,
+    template: Option<String>,
 }
 
 impl ExecuteMessageBuilder {
@@ -145,6 +169,7 @@ impl ExecuteMessageBuilder {
             session_id: session_id.into(),
             command: command.into(),
             agent_id: None,
+            template: None,
         }
     }
 
@@ -154,8 +179,27 @@ impl ExecuteMessageBuilder {
         self
     }
 
+    /// Override the default message with a template (see `MessageTemplates`).
+    pub fn with_template(mut self, template: Option<impl Into<String>>) -> Self {
+        self.template = template.map(Into::into);
+        self
+    }
+
     /// Build the message string.
     pub fn build(&self) -> String {
+        apply_template(
+            self.template.as_deref(),
+            self.default_message(),
+            &[
+                ("command_id", &self.command_id),
+                ("session_id", &self.session_id),
+                ("agent_id", self.agent_id.as_deref().unwrap_or("")),
+                ("command", &self.command),
+            ],
+        )
+    }
+
+    fn default_message(&self) -> String {
         let mut lines = vec![
             "COMMAND STARTED. REMEMBER THESE IDENTIFIERS:".to_string(),
             format!("• command_id: '{}'", self.command_id),
@@ -192,12 +236,16 @@ impl ExecuteMessageBuilder {
 /// let message = AgentDisconnectMessageBuilder::new("my-agent")
 ///     .with_sessions_disconnected(3)
 ///     .with_commands_cancelled(5)
+///     .with_forwards_closed(1)
 ///     .build();
 /// ```
 pub struct AgentDisconnectMessageBuilder {
     agent_id: String,
     sessions_disconnected: usize,
     commands_cancelled: usize,
+    shells_closed: usize,
+    forwards_closed: usize,
+    template: Option<String>,
 }
 
 impl AgentDisconnectMessageBuilder {
@@ -207,6 +255,9 @@ impl AgentDisconnectMessageBuilder {
             agent_id: agent_id.into(),
             sessions_disconnected: 0,
             commands_cancelled: 0,
+            shells_closed: 0,
+            forwards_closed: 0,
+            template: None,
         }
     }
 
@@ -222,13 +273,50 @@ impl AgentDisconnectMessageBuilder {
         self
     }
 
+    /// Set the number of interactive shells closed.
+    pub fn with_shells_closed(mut self, count: usize) -> Self {
+        self.shells_closed = count;
+        self
+    }
+
+    /// Set the number of port forwards (local and reverse) closed.
+    pub fn with_forwards_closed(mut self, count: usize) -> Self {
+        self.forwards_closed = count;
+        self
+    }
+
+    /// Override the default message with a template (see `MessageTemplates`).
+    pub fn with_template(mut self, template: Option<impl Into<String>>) -> Self {
+        self.template = template.map(Into::into);
+        self
+    }
+
     /// Build the message string.
     pub fn build(&self) -> String {
+        apply_template(
+            self.template.as_deref(),
+            self.default_message(),
+            &[
+                ("agent_id", &self.agent_id),
+                (
+                    "sessions_disconnected",
+                    &self.sessions_disconnected.to_string(),
+                ),
+                ("commands_cancelled", &self.commands_cancelled.to_string()),
+                ("shells_closed", &self.shells_closed.to_string()),
+                ("forwards_closed", &self.forwards_closed.to_string()),
+            ],
+        )
+    }
+
+    fn default_message(&self) -> String {
         let mut lines = vec![
             "AGENT CLEANUP COMPLETE. SUMMARY:".to_string(),
             format!("• agent_id: '{}'", self.agent_id),
             format!("• sessions_disconnected: {}", self.sessions_disconnected),
             format!("• commands_cancelled: {}", self.commands_cancelled),
+            format!("• shells_closed: {}", self.shells_closed),
+            format!("• forwards_closed: {}", self.forwards_closed),
             String::new(), // empty line
         ];
 
@@ -236,7 +324,7 @@ impl AgentDisconnectMessageBuilder {
             lines.push(format!("No sessions found for agent '{}'.", self.agent_id));
         } else {
             lines.push(format!(
-                "All sessions and commands for agent '{}' have been terminated.",
+                "All sessions, commands, shells and forwards for agent '{}' have been terminated.",
                 self.agent_id
             ));
         }
@@ -261,6 +349,7 @@ pub struct ShellOpenMessageBuilder {
     term: String,
     cols: u32,
     rows: u32,
+    template: Option<String>,
 }
 
 impl ShellOpenMessageBuilder {
@@ -279,6 +368,7 @@ impl ShellOpenMessageBuilder {
             term: term.into(),
             cols,
             rows,
+            template: None,
         }
     }
 
@@ -288,8 +378,29 @@ impl ShellOpenMessageBuilder {
         self
     }
 
+    /// Override the default message with a template (see `MessageTemplates`).
+    pub fn with_template(mut self, template: Option<impl Into<String>>) -> Self {
+        self.template = template.map(Into::into);
+        self
+    }
+
     /// Build the message string.
     pub fn build(&self) -> String {
+        apply_template(
+            self.template.as_deref(),
+            self.default_message(),
+            &[
+                ("shell_id", &self.shell_id),
+                ("session_id", &self.session_id),
+                ("agent_id", self.agent_id.as_deref().unwrap_or("")),
+                ("term", &self.term),
+                ("cols", &self.cols.to_string()),
+                ("rows", &self.rows.to_string()),
+            ],
+        )
+    }
+
+    fn default_message(&self) -> String {
         let mut lines = vec!["INTERACTIVE SHELL OPENED. REMEMBER THESE IDENTIFIERS:".to_string()];
 
         if let Some(ref aid) = self.agent_id {
@@ -320,6 +431,23 @@ impl ShellOpenMessageBuilder {
     }
 }
 
+/// Render a message template, or return the default message when no template is set.
+///
+/// `{message}` expands to the default message; every `(name, value)` pair
+/// replaces `{name}`. Unknown placeholders are left as-is.
+fn apply_template(template: Option<&str>, message: String, vars: &[(&str, &str)]) -> String {
+    let Some(template) = template else {
+        return message;
+    };
+
+    let mut rendered = template.to_string();
+    for (name, value) in vars {
+        rendered = rendered.replace(&format!("{{{}}}", name), value);
+    }
+    // Substitute the default message last so its contents are never re-expanded
+    rendered.replace("{message}", &message)
+}
+
 /// Truncate a command string for display purposes.
 fn truncate_command(command: &str, max_len: usize) -> String {
     if command.len() > max_len {
@@ -631,6 +759,18 @@ mod tests {
             assert!(message.contains("No sessions found"));
         }
 
+        #[test]
+        fn test_shells_and_forwards() {
+            let message = AgentDisconnectMessageBuilder::new("my-agent")
+                .with_sessions_disconnected(1)
+                .with_shells_closed(2)
+                .with_forwards_closed(3)
+                .build();
+
+            assert!(message.contains("shells_closed: 2"));
+            assert!(message.contains("forwards_closed: 3"));
+        }
+
         #[test]
         fn test_from_string_type() {
             let agent_id = String::from("my-agent-123");
@@ -780,6 +920,99 @@ mod tests {
         }
     }
 
+    mod templates {
+        use super::*;
+
+        #[test]
+        fn test_no_template_keeps_default() {
+            let message = ConnectMessageBuilder::new("sess-123", "user", "host:22")
+                .with_template(None::<String>)
+                .build();
+
+            assert!(message.contains("SSH CONNECTION ESTABLISHED"));
+        }
+
+        #[test]
+        fn test_connect_placeholders() {
+            let message = ConnectMessageBuilder::new("sess-123", "user", "host:22")
+                .with_agent_id(Some("my-agent"))
+                .with_template(Some("{username}@{host} is {session_id} for {agent_id}"))
+                .build();
+
+            assert_eq!(message, "user@host:22 is sess-123 for my-agent");
+        }
+
+        #[test]
+        fn test_message_placeholder_appends_guidance() {
+            let message = ExecuteMessageBuilder::new("cmd-1", "sess-1", "sudo reboot")
+                .with_template(Some("{message}\n\nOpen a ticket before running sudo."))
+                .build();
+
+            assert!(message.starts_with("COMMAND STARTED"));
+            assert!(message.contains("command_id: 'cmd-1'"));
+            assert!(message.ends_with("Open a ticket before running sudo."));
+        }
+
+        #[test]
+        fn test_execute_command_placeholder_is_not_truncated() {
+            let long_command = "a".repeat(100);
+            let message = ExecuteMessageBuilder::new("cmd-1", "sess-1", long_command.clone())
+                .with_template(Some("ran {command}"))
+                .build();
+
+            assert_eq!(message, format!("ran {}", long_command));
+        }
+
+        #[test]
+        fn test_agent_disconnect_placeholders() {
+            let message = AgentDisconnectMessageBuilder::new("agent-1")
+                .with_sessions_disconnected(2)
+                .with_commands_cancelled(5)
+                .with_template(Some(
+                    "{agent_id}: {sessions_disconnected}/{commands_cancelled}",
+                ))
+                .build();
+
+            assert_eq!(message, "agent-1: 2/5");
+        }
+
+        #[test]
+        fn test_shell_open_placeholders() {
+            let message = ShellOpenMessageBuilder::new("sh-1", "sess-1", "vt100", 80, 24)
+                .with_template(Some("{shell_id} {session_id} {term} {cols}x{rows}"))
+                .build();
+
+            assert_eq!(message, "sh-1 sess-1 vt100 80x24");
+        }
+
+        #[test]
+        fn test_missing_optional_values_render_empty() {
+            let message = ConnectMessageBuilder::new("sess-123", "user", "host:22")
+                .with_template(Some("[{agent_id}][{name}]"))
+                .build();
+
+            assert_eq!(message, "[][]");
+        }
+
+        #[test]
+        fn test_unknown_placeholder_left_intact() {
+            let message = ConnectMessageBuilder::new("sess-123", "user", "host:22")
+                .with_template(Some("{session_id} {ticket_url}"))
+                .build();
+
+            assert_eq!(message, "sess-123 {ticket_url}");
+        }
+
+        #[test]
+        fn test_default_message_not_reexpanded() {
+            let message = ExecuteMessageBuilder::new("cmd-1", "sess-1", "echo {session_id}")
+                .with_template(Some("{message}"))
+                .build();
+
+            assert!(message.contains("command: 'echo {session_id}'"));
+        }
+    }
+
     mod truncate_command {
         use super::*;
 
@@ -10,31 +10,156 @@
 //! - [`config`]: Configuration resolution with environment variable support
 //! - [`error`]: Error classification for retry logic
 //! - [`session`]: `SshClientHandler` for russh callbacks
+//! - [`health`]: Session health checks and the background health monitor (`[health]`)
+//! - [`known_hosts`]: Server host key verification (`strict_host_key_checking`)
+//! - [`agent_forward`]: Local ssh-agent forwarding to commands and shells (`agent_forwarding`)
+//! - [`algorithms`]: Cipher, key exchange and host key algorithm preferences and negotiated algorithms
 //! - [`client`]: SSH connection and command execution logic
 //! - [`async_command`]: Async command tracking and state management
+//! - [`affinity`]: Region/datacenter session affinity (`ssh_select_session`)
+//! - [`labels`]: Session labels and label selectors (`ssh_update_session`, `ssh_list_sessions`)
+//! - [`capabilities`]: Feature, limit and policy report (`ssh_capabilities`)
+//! - [`dag`]: Dependency-graph scheduling for `ssh_execute_dag`
+//! - [`batch`]: Target resolution for fleet-wide `ssh_execute_batch` and `ssh_connect_many`
+//! - [`command_stream`]: Live async command output as MCP notifications (`stream_output`)
+//! - [`command_wrap`]: Remote command wrappers (nice/ionice priority, sandbox limits)
+//! - [`identity`]: Post-connect identity probe (effective user, uid, groups)
+//! - [`accounting`]: Per-agent usage counters (`ssh_usage`)
+//! - [`audit`]: Audit events recorded by state-changing tools
+//! - [`siem`]: Audit export to syslog / HTTP collectors (CEF or JSON)
+//! - [`access`]: Session ownership and cross-agent sharing checks
+//! - [`host_limit`]: Per-host concurrent session caps
+//! - [`quota`]: Global and per-agent session quotas (`SSH_MAX_SESSIONS`, `SSH_MAX_SESSIONS_PER_AGENT`)
+//! - [`lease`]: Exclusive session leases (`ssh_lock_session`)
+//! - [`self_test`]: Configuration self-test run at startup (`--check-config`, `ssh_check_config`)
+//! - [`sftp`]: SFTP subsystem sessions and typed directory listings (`ssh_list_dir`)
 //! - [`shell`]: Interactive PTY shell session management
+//! - [`notify`]: Server-to-client notification and request queues per MCP client
+//! - [`elicit`]: Password prompt via MCP elicitation when agent authentication fails
+//! - [`subscription`]: Live shell output streamed as notifications (`ssh_shell_subscribe`)
+//! - [`compress`]: gzip+base64 encoding of large output fields (`compress=true`)
+//! - [`console`]: Serial-over-LAN / BMC console presets
+//! - [`credentials`]: Named credentials from the environment or HashiCorp Vault (`credential_ref`)
+//! - [`tmux`]: tmux/screen attach commands and session listing
+//! - [`policy`]: Command deny rules with reasons and suggested alternatives
+//! - [`read_only`]: Read-only sessions: mutating command classifier and write tool refusal (`SSH_READ_ONLY`)
+//! - [`privilege_drop`]: `sudo -k` and lingering privileged process check after sudo operations (`ssh_verify_privilege_drop`)
+//! - [`privileges`]: Remote sudo capability probe (`ssh_check_privileges`)
+//! - [`facts`]: Typed host facts probe: OS, kernel, CPU, memory, disks (`ssh_gather_facts`)
+//! - [`profiles`]: Named connection targets defined on the server (`ssh_connect` `profile`)
+//! - [`expect`]: Waiting for shell output to match a pattern (`ssh_shell_expect`)
+//! - [`exit_pattern`]: Settling async commands on an output pattern (`success_pattern`, `failure_pattern`)
+//! - [`output_parser`]: Structured parsing of `df`, `ps`, `ss` and `systemctl` output (`ssh_execute_parsed`)
+//! - [`signal`]: Signals for shells and async commands (`ssh_shell_signal`, `ssh_command_signal`)
+//! - [`pager`]: Pager prompt detection and auto-paging for `ssh_shell_read`
+//! - [`port_check`]: Remote TCP reachability checks (`ssh_check_port`)
+//! - [`glob`]: Remote glob expansion (`ssh_glob`)
+//! - [`tail`]: File following with incremental, server-side filtered reads (`ssh_tail_file`, `ssh_tail_read`)
+//! - [`helpers`]: Vetted helper script library synced to sessions (`ssh_run_helper`)
+//! - [`managed`]: Config-declared hosts connected and supervised by the server
+//! - [`reconnect`]: Connection parameters kept for dialing a session's host again
+//! - [`pool`]: Opt-in idle connection pool reused by `ssh_connect` (`[connection_pool]`)
+//! - [`reaper`]: Background removal of dead and idle sessions (`[reaper]`)
+//! - [`session_env`]: Environment variables for remote commands (`env`), with an `export` fallback
+//! - [`reboot`]: Host reboot and reconnect under the same session (`ssh_reboot_and_wait`)
+//! - [`manifest`]: Remote tree listing with sizes and checksums (`ssh_manifest`)
+//! - [`symlink`]: Remote symbolic link commands (`ssh_symlink`, `ssh_readlink`)
+//! - [`edit`]: Atomic remote file writes and unified diff patches (`ssh_write_file`, `ssh_patch_file`)
+//! - [`traffic`]: Per-session byte counters (`bytes_sent` / `bytes_received`)
+//! - [`rate_limit`]: Bandwidth limits of transfers and forwards (`rate_limit_bytes_per_sec`)
+//! - [`transfer`]: Directory upload, archive download and host-to-host relay over exec channels
+//! - [`webhook`]: Command result callbacks (`ssh_execute` with `callback_url`)
+//! - [`user_switch`]: `sudo`/`su` user switching inside shells (`ssh_shell_switch_user`)
 //! - [`forward`]: Port forwarding implementation (feature-gated)
+//! - [`reverse`]: Reverse forwards (remote listeners) and their per-session registry
 //! - [`commands`]: `McpSSHCommands` MCP tool implementations
+//! - [`exposure`]: Config-driven tool enable/disable by name or group
+//! - [`annotations`]: MCP tool safety hints (`readOnlyHint`, `destructiveHint`, ...)
+//! - [`transport`]: stdio and streamable HTTP transports
 //!
 //! ## SOLID Architecture Modules
 //!
 //! - [`storage`]: Storage traits (`SessionStorage`, `CommandStorage`) with DashMap implementations
-//! - [`auth`]: Authentication strategies (`PasswordAuth`, `KeyAuth`, `AgentAuth`, `AuthChain`)
+//! - [`auth`]: Authentication strategies (`PasswordAuth`, `KeyAuth`, `InlineKeyAuth`, `AgentAuth`, `AuthChain`)
 //! - [`message`]: Message builders for LLM-friendly responses
 
+pub(crate) mod access;
+pub(crate) mod accounting;
+pub(crate) mod affinity;
+pub(crate) mod agent_forward;
+pub(crate) mod algorithms;
+pub mod annotations;
 pub(crate) mod async_command;
+pub(crate) mod audit;
 pub mod auth;
+pub(crate) mod batch;
+pub(crate) mod capabilities;
 pub(crate) mod client;
+pub(crate) mod command_stream;
+pub(crate) mod command_wrap;
 pub mod commands;
+pub(crate) mod compress;
 pub(crate) mod config;
+pub(crate) mod console;
+pub(crate) mod credentials;
+pub(crate) mod dag;
+pub(crate) mod edit;
+pub(crate) mod elicit;
 pub(crate) mod error;
+pub(crate) mod exit_pattern;
+pub(crate) mod expect;
+pub(crate) mod exposure;
+pub(crate) mod facts;
 #[cfg(feature = "port_forward")]
 pub(crate) mod forward;
+pub(crate) mod glob;
+pub(crate) mod health;
+pub(crate) mod helpers;
+pub(crate) mod host_limit;
+pub(crate) mod identity;
+pub(crate) mod known_hosts;
+pub(crate) mod labels;
+pub(crate) mod lease;
+pub(crate) mod managed;
+pub(crate) mod manifest;
 pub mod message;
+pub(crate) mod notify;
+pub(crate) mod output_parser;
+pub(crate) mod pager;
+pub(crate) mod policy;
+pub(crate) mod pool;
+pub(crate) mod port_check;
+pub(crate) mod privilege_drop;
+pub(crate) mod privileges;
+pub(crate) mod profiles;
+pub(crate) mod quota;
+pub(crate) mod rate_limit;
+pub(crate) mod read_only;
+pub(crate) mod reaper;
+pub(crate) mod reboot;
+pub(crate) mod reconnect;
+#[cfg_attr(not(feature = "port_forward"), allow(dead_code))]
+pub(crate) mod reverse;
 pub mod schema;
+pub mod self_test;
 pub mod session;
+pub(crate) mod session_env;
+pub(crate) mod sftp;
 pub(crate) mod shell;
+pub(crate) mod siem;
+pub(crate) mod signal;
 pub mod storage;
+pub(crate) mod subscription;
+pub(crate) mod symlink;
+pub(crate) mod tail;
+#[cfg(test)]
+pub(crate) mod test_server;
+pub(crate) mod tmux;
+pub mod traffic;
+pub(crate) mod transfer;
+pub mod transport;
 pub mod types;
+pub(crate) mod user_switch;
+pub(crate) mod webhook;
 
 pub use commands::McpSSHCommands;
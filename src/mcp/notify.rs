@@ -0,0 +1,259 @@
+//! Server-to-client notifications.
+//!
+//! Tools run inside one client's request, but some of them keep talking to
+//! that client afterwards (live shell output). While a request is handled the
+//! transport installs the client's [`NotificationSink`]; a tool captures it
+//! with [`current_client`] and queues JSON-RPC notifications on it later.
+//!
+//! The transport drains the matching receiver: stdio writes notifications to
+//! stdout between responses, streamable HTTP sends them on the session's
+//! `GET` event stream. The queue is bounded; when a client does not drain it,
+//! further notifications are dropped rather than buffered without limit.
+//!
+//! The same queue carries requests to the client (`elicitation/create`):
+//! [`NotificationSink::request`] waits for the answer, which the transport
+//! hands back through [`NotificationSink::intercept`] before the rest of the
+//! message reaches the MCP server.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use poem_mcpserver::protocol::JSON_RPC_VERSION;
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, oneshot};
+
+/// Notifications queued per client before new ones are dropped
+pub(crate) const NOTIFICATION_QUEUE: usize = 1024;
+
+tokio::task_local! {
+    static CLIENT: NotificationSink;
+}
+
+/// Why a notification was not queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NotifyError {
+    /// The client's queue is full; this notification was dropped
+    Full,
+    /// The client is gone
+    Closed,
+}
+
+/// Requests sent to one client and what it declared it can answer.
+#[derive(Debug, Default)]
+struct ClientRequests {
+    next_id: AtomicU64,
+    /// Requests waiting for their response, by JSON-RPC id
+    pending: DashMap<String, oneshot::Sender<Value>>,
+    /// The client declared the `elicitation` capability in `initialize`
+    elicitation: AtomicBool,
+}
+
+/// Queue of notifications for one MCP client.
+#[derive(Debug, Clone)]
+pub(crate) struct NotificationSink {
+    tx: mpsc::Sender<Value>,
+    requests: Arc<ClientRequests>,
+}
+
+impl NotificationSink {
+    /// Create a sink and the receiver its transport drains.
+    pub(crate) fn channel() -> (Self, mpsc::Receiver<Value>) {
+        let (tx, rx) = mpsc::channel(NOTIFICATION_QUEUE);
+        let requests = Arc::new(ClientRequests::default());
+        (Self { tx, requests }, rx)
+    }
+
+    /// Queue a `method` notification with `params`.
+    pub(crate) fn notify(&self, method: &str, params: Value) -> Result<(), NotifyError> {
+        let notification = json!({
+            "jsonrpc": JSON_RPC_VERSION,
+            "method": method,
+            "params": params,
+        });
+        self.tx.try_send(notification).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => NotifyError::Full,
+            mpsc::error::TrySendError::Closed(_) => NotifyError::Closed,
+        })
+    }
+
+    /// Whether the client can be asked for input with `elicitation/create`.
+    pub(crate) fn supports_elicitation(&self) -> bool {
+        self.requests.elicitation.load(Ordering::Relaxed)
+    }
+
+    /// Send a `method` request to the client and wait up to `timeout` for
+    /// its result.
+    pub(crate) async fn request(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, String> {
+        let id = format!(
+            "ssh-mcp-{}",
+            self.requests.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        let (tx, rx) = oneshot::channel();
+        self.requests.pending.insert(id.clone(), tx);
+        let request = json!({
+            "jsonrpc": JSON_RPC_VERSION,
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        if let Err(e) = self.tx.try_send(request) {
+            self.requests.pending.remove(&id);
+            return Err(match e {
+                mpsc::error::TrySendError::Full(_) => "client queue is full".to_string(),
+                mpsc::error::TrySendError::Closed(_) => "client is gone".to_string(),
+            });
+        }
+
+        let response = match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => return Err("client is gone".to_string()),
+            Err(_) => {
+                self.requests.pending.remove(&id);
+                return Err(format!("no answer within {:?}", timeout));
+            }
+        };
+        match response.get("error") {
+            Some(error) => Err(error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("request failed")
+                .to_string()),
+            None => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    /// Take the responses to [`request`](Self::request)s out of an incoming
+    /// message (or batch) and note the capabilities of an `initialize`;
+    /// returns what is left for the MCP server, if anything.
+    pub(crate) fn intercept(&self, message: Value) -> Option<Value> {
+        match message {
+            Value::Array(messages) => {
+                let rest: Vec<Value> = messages
+                    .into_iter()
+                    .filter_map(|message| self.intercept_one(message))
+                    .collect();
+                (!rest.is_empty()).then_some(Value::Array(rest))
+            }
+            message => self.intercept_one(message),
+        }
+    }
+
+    fn intercept_one(&self, message: Value) -> Option<Value> {
+        if message.get("method").and_then(Value::as_str) == Some("initialize") {
+            let elicitation = message["params"]["capabilities"]
+                .get("elicitation")
+                .is_some();
+            self.requests
+                .elicitation
+                .store(elicitation, Ordering::Relaxed);
+        }
+        let is_response = message.get("method").is_none()
+            && (message.get("result").is_some() || message.get("error").is_some());
+        if !is_response {
+            return Some(message);
+        }
+        // Answers to unknown or expired requests are dropped too
+        if let Some(id) = message.get("id").and_then(Value::as_str)
+            && let Some((_, tx)) = self.requests.pending.remove(id)
+        {
+            let _ = tx.send(message);
+        }
+        None
+    }
+}
+
+/// Run `future` (a request) on behalf of the client owning `sink`.
+pub(crate) async fn with_client<F: Future>(sink: NotificationSink, future: F) -> F::Output {
+    CLIENT.scope(sink, future).await
+}
+
+/// The sink of the client whose request is being handled, if any.
+pub(crate) fn current_client() -> Option<NotificationSink> {
+    CLIENT.try_with(NotificationSink::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_client_scope() {
+        assert!(current_client().is_none());
+
+        let (sink, mut rx) = NotificationSink::channel();
+        let captured = with_client(sink, async { current_client() }).await;
+        captured
+            .unwrap()
+            .notify("notifications/message", json!({"level": "info"}))
+            .unwrap();
+
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification["jsonrpc"], "2.0");
+        assert_eq!(notification["method"], "notifications/message");
+        assert_eq!(notification["params"]["level"], "info");
+        assert!(notification.get("id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_full_and_closed() {
+        let (sink, rx) = NotificationSink::channel();
+        for _ in 0..NOTIFICATION_QUEUE {
+            sink.notify("n", json!({})).unwrap();
+        }
+        assert_eq!(sink.notify("n", json!({})), Err(NotifyError::Full));
+        drop(rx);
+        assert_eq!(sink.notify("n", json!({})), Err(NotifyError::Closed));
+    }
+
+    #[tokio::test]
+    async fn test_request_round_trip() {
+        let (sink, mut rx) = NotificationSink::channel();
+        let initialize = json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"capabilities": {"elicitation": {}}},
+        });
+        assert_eq!(sink.intercept(initialize.clone()), Some(initialize));
+        assert!(sink.supports_elicitation());
+
+        let client = sink.clone();
+        tokio::spawn(async move {
+            let request = rx.recv().await.unwrap();
+            assert_eq!(request["method"], "elicitation/create");
+            let response = json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": {"action": "decline"},
+            });
+            let call = json!({"jsonrpc": "2.0", "id": 2, "method": "ping"});
+            // The response is taken out of the batch, the request is left
+            let rest = client.intercept(json!([response, call.clone()]));
+            assert_eq!(rest, Some(json!([call])));
+        });
+
+        let result = sink
+            .request("elicitation/create", json!({}), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(result["action"], "decline");
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let (sink, _rx) = NotificationSink::channel();
+        assert!(!sink.supports_elicitation());
+        let error = sink
+            .request("elicitation/create", json!({}), Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert!(error.contains("no answer"));
+        assert!(sink.requests.pending.is_empty());
+    }
+}
@@ -0,0 +1,482 @@
+//! Structured parsing of common command outputs (`ssh_execute_parsed`).
+//!
+//! Tables such as `df -h` or `ps aux` cost many tokens as raw text and are
+//! easy to misread by column. `ssh_execute_parsed` runs the command of a
+//! known format and returns its rows as typed JSON instead of stdout:
+//!
+//! - `df`: `df -hP` filesystems ([`DfRow`])
+//! - `ps`: `ps aux` processes ([`PsRow`])
+//! - `ss`: `ss -tlnp` sockets with their owning processes ([`SsRow`])
+//! - `systemctl`: `systemctl list-units` units ([`SystemctlRow`])
+//!
+//! A caller-supplied command must print the same format (e.g. `df -h /var`,
+//! `sudo ss -tulnp`). Default commands run with `LC_ALL=C`, so numbers are
+//! not localized. Lines that do not fit the format are returned as
+//! `unparsed_lines` rather than failing the call.
+
+use super::types::{
+    AsyncCommandStatus, DfRow, PsRow, SocketProcess, SsRow, SshExecuteParsedResponse,
+    SshExecuteSyncResponse, SystemctlRow,
+};
+
+/// Unrecognized lines reported at most
+pub(crate) const MAX_UNPARSED_LINES: usize = 20;
+
+/// A known output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputParser {
+    Df,
+    Ps,
+    Ss,
+    Systemctl,
+}
+
+impl OutputParser {
+    const ALL: [Self; 4] = [Self::Df, Self::Ps, Self::Ss, Self::Systemctl];
+
+    /// Look up a `parser` argument.
+    pub(crate) fn from_name(name: &str) -> Result<Self, String> {
+        let name = name.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|parser| parser.name() == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = Self::ALL.iter().map(|parser| parser.name()).collect();
+                format!("Unknown parser '{}'. Available: {}", name, known.join(", "))
+            })
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Df => "df",
+            Self::Ps => "ps",
+            Self::Ss => "ss",
+            Self::Systemctl => "systemctl",
+        }
+    }
+
+    /// Command run when the caller gives none.
+    pub(crate) fn default_command(self) -> &'static str {
+        match self {
+            Self::Df => "LC_ALL=C df -hP",
+            Self::Ps => "LC_ALL=C ps aux",
+            Self::Ss => "LC_ALL=C ss -tlnp",
+            Self::Systemctl => "LC_ALL=C systemctl list-units --no-pager --plain --no-legend",
+        }
+    }
+
+    /// What the parsed rows are called in messages.
+    fn rows_name(self) -> &'static str {
+        match self {
+            Self::Df => "filesystem(s)",
+            Self::Ps => "process(es)",
+            Self::Ss => "socket(s)",
+            Self::Systemctl => "unit(s)",
+        }
+    }
+}
+
+/// Lines no parser rule matched, capped at [`MAX_UNPARSED_LINES`].
+#[derive(Debug, Default)]
+struct Unparsed(Vec<String>);
+
+impl Unparsed {
+    fn push(&mut self, line: &str) {
+        if self.0.len() < MAX_UNPARSED_LINES {
+            self.0.push(line.to_string());
+        }
+    }
+}
+
+/// Split off the first `n` whitespace-separated fields of `line`; the rest
+/// (with its inner spacing kept) is returned trimmed. `None` when the line
+/// has fewer fields.
+fn split_fields(line: &str, n: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(n);
+    let mut rest = line.trim_start();
+    for _ in 0..n {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Some((fields, rest.trim_end()))
+}
+
+/// Parse `df -h` / `df -hP` output. Without `-P`, a long filesystem name
+/// wrapped onto a line of its own is joined with the next line.
+fn parse_df(stdout: &str) -> (Vec<DfRow>, Unparsed) {
+    let mut rows = Vec::new();
+    let mut unparsed = Unparsed::default();
+    // Columns before the mount point; macOS adds inode columns
+    let mut columns = 5;
+    let mut wrapped: Option<String> = None;
+
+    for line in stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if line.starts_with("Filesystem") {
+            // "Mounted on" is two words
+            columns = line.split_whitespace().count().saturating_sub(2).max(5);
+            continue;
+        }
+        let line = match wrapped.take() {
+            Some(filesystem) => format!("{} {}", filesystem, line),
+            None => line.to_string(),
+        };
+        match split_fields(&line, columns) {
+            Some((fields, mount)) if !mount.is_empty() => rows.push(DfRow {
+                filesystem: fields[0].to_string(),
+                size: fields[1].to_string(),
+                used: fields[2].to_string(),
+                available: fields[3].to_string(),
+                used_percent: fields[4].trim_end_matches('%').parse().ok(),
+                mounted_on: mount.to_string(),
+            }),
+            _ if line.split_whitespace().count() == 1 => wrapped = Some(line),
+            _ => unparsed.push(&line),
+        }
+    }
+    if let Some(filesystem) = wrapped {
+        unparsed.push(&filesystem);
+    }
+    (rows, unparsed)
+}
+
+/// One `ps aux` line.
+fn ps_row(line: &str) -> Option<PsRow> {
+    let (fields, command) = split_fields(line, 10)?;
+    Some(PsRow {
+        user: fields[0].to_string(),
+        pid: fields[1].parse().ok()?,
+        cpu_percent: fields[2].parse().ok()?,
+        mem_percent: fields[3].parse().ok()?,
+        vsz_kb: fields[4].parse().ok()?,
+        rss_kb: fields[5].parse().ok()?,
+        tty: fields[6].to_string(),
+        stat: fields[7].to_string(),
+        start: fields[8].to_string(),
+        time: fields[9].to_string(),
+        command: command.to_string(),
+    })
+}
+
+/// Parse `ps aux` output (Linux procps and BSD/macOS).
+fn parse_ps(stdout: &str) -> (Vec<PsRow>, Unparsed) {
+    let mut rows = Vec::new();
+    let mut unparsed = Unparsed::default();
+    for line in stdout.lines().filter(|line| !line.trim().is_empty()) {
+        if line.trim_start().starts_with("USER ") {
+            continue;
+        }
+        match ps_row(line) {
+            Some(row) => rows.push(row),
+            None => unparsed.push(line),
+        }
+    }
+    (rows, unparsed)
+}
+
+/// Owning processes from the `users:(("sshd",pid=812,fd=3),...)` column.
+fn socket_processes(column: &str) -> Vec<SocketProcess> {
+    column
+        .split("(\"")
+        .skip(1)
+        .filter_map(|entry| {
+            let (name, rest) = entry.split_once('"')?;
+            let pid = rest
+                .split([',', ')'])
+                .find_map(|field| field.strip_prefix("pid="))
+                .and_then(|pid| pid.parse().ok());
+            Some(SocketProcess {
+                name: name.to_string(),
+                pid,
+            })
+        })
+        .collect()
+}
+
+/// One `ss` line; the Netid column is recognized by its lowercase value
+/// (`tcp`, `udp`), as states are uppercase.
+fn ss_row(line: &str) -> Option<SsRow> {
+    let has_netid = line
+        .split_whitespace()
+        .next()?
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    let (fields, process) = split_fields(line, if has_netid { 6 } else { 5 })?;
+    let (netid, fields) = match has_netid {
+        true => (Some(fields[0].to_string()), &fields[1..]),
+        false => (None, &fields[..]),
+    };
+    let (local_address, local_port) = fields[3].rsplit_once(':')?;
+    Some(SsRow {
+        netid,
+        state: fields[0].to_string(),
+        recv_q: fields[1].parse().ok()?,
+        send_q: fields[2].parse().ok()?,
+        local_address: local_address.to_string(),
+        local_port: local_port.parse().ok(),
+        peer: fields[4].to_string(),
+        processes: socket_processes(process),
+    })
+}
+
+/// Parse `ss -tlnp` output, with or without a Netid column (`ss -tulnp`).
+fn parse_ss(stdout: &str) -> (Vec<SsRow>, Unparsed) {
+    let mut rows = Vec::new();
+    let mut unparsed = Unparsed::default();
+    for line in stdout.lines().filter(|line| !line.trim().is_empty()) {
+        let first = line.split_whitespace().next().unwrap_or_default();
+        if first == "State" || first == "Netid" {
+            continue;
+        }
+        match ss_row(line) {
+            Some(row) => rows.push(row),
+            None => unparsed.push(line),
+        }
+    }
+    (rows, unparsed)
+}
+
+/// Parse `systemctl list-units` output. The failed-unit marker is dropped,
+/// and the legend after the first blank line is ignored.
+fn parse_systemctl(stdout: &str) -> (Vec<SystemctlRow>, Unparsed) {
+    let mut rows = Vec::new();
+    let mut unparsed = Unparsed::default();
+    for line in stdout.lines() {
+        let line = line.trim_start_matches(['●', '*', ' ', '\t']).trim_end();
+        if line.is_empty() {
+            if rows.is_empty() {
+                continue;
+            }
+            break;
+        }
+        if line.starts_with("UNIT ") {
+            continue;
+        }
+        match split_fields(line, 4) {
+            Some((fields, description)) => rows.push(SystemctlRow {
+                unit: fields[0].to_string(),
+                load: fields[1].to_string(),
+                active: fields[2].to_string(),
+                sub: fields[3].to_string(),
+                description: description.to_string(),
+            }),
+            None => unparsed.push(line),
+        }
+    }
+    (rows, unparsed)
+}
+
+/// Build the ssh_execute_parsed response from a finished (or still running)
+/// ssh_execute_sync result; output is only parsed once the command ended.
+pub(crate) fn parsed_response(
+    parser: OutputParser,
+    result: SshExecuteSyncResponse,
+) -> SshExecuteParsedResponse {
+    let mut response = SshExecuteParsedResponse {
+        command_id: result.command_id,
+        session_id: result.session_id,
+        command: result.command,
+        parser: parser.name().to_string(),
+        status: result.status,
+        exit_code: result.exit_code,
+        filesystems: None,
+        processes: None,
+        sockets: None,
+        units: None,
+        count: 0,
+        unparsed_lines: Vec::new(),
+        stderr: result.stderr,
+        error: result.error,
+        timed_out: result.timed_out,
+        message: result.message,
+        reconnected: result.reconnected,
+    };
+    if response.status == AsyncCommandStatus::Running {
+        return response;
+    }
+
+    let unparsed = match parser {
+        OutputParser::Df => {
+            let (rows, unparsed) = parse_df(&result.stdout);
+            response.count = rows.len();
+            response.filesystems = Some(rows);
+            unparsed
+        }
+        OutputParser::Ps => {
+            let (rows, unparsed) = parse_ps(&result.stdout);
+            response.count = rows.len();
+            response.processes = Some(rows);
+            unparsed
+        }
+        OutputParser::Ss => {
+            let (rows, unparsed) = parse_ss(&result.stdout);
+            response.count = rows.len();
+            response.sockets = Some(rows);
+            unparsed
+        }
+        OutputParser::Systemctl => {
+            let (rows, unparsed) = parse_systemctl(&result.stdout);
+            response.count = rows.len();
+            response.units = Some(rows);
+            unparsed
+        }
+    };
+    response.unparsed_lines = unparsed.0;
+    response.message = format!(
+        "{}; parsed {} {}",
+        response.message,
+        response.count,
+        parser.rows_name()
+    );
+    if !response.unparsed_lines.is_empty() {
+        response
+            .message
+            .push_str(", some lines not recognized (see unparsed_lines)");
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(OutputParser::from_name("df"), Ok(OutputParser::Df));
+        assert_eq!(
+            OutputParser::from_name(" Systemctl "),
+            Ok(OutputParser::Systemctl)
+        );
+        let err = OutputParser::from_name("netstat").unwrap_err();
+        assert!(err.contains("df, ps, ss, systemctl"));
+    }
+
+    #[test]
+    fn test_parse_df() {
+        let stdout = "\
+Filesystem      Size  Used Avail Use% Mounted on
+/dev/sda1        20G  5.1G   14G  28% /
+tmpfs           2.0G     0  2.0G   0% /dev/shm
+/dev/mapper/vg0-very--long--logical--volume
+                 99G   10G   84G  11% /srv/my data
+";
+        let (rows, unparsed) = parse_df(stdout);
+        assert!(unparsed.0.is_empty());
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].filesystem, "/dev/sda1");
+        assert_eq!(rows[0].size, "20G");
+        assert_eq!(rows[0].used_percent, Some(28));
+        assert_eq!(rows[0].mounted_on, "/");
+        assert_eq!(
+            rows[2].filesystem,
+            "/dev/mapper/vg0-very--long--logical--volume"
+        );
+        assert_eq!(rows[2].mounted_on, "/srv/my data");
+
+        // macOS prints inode columns before the mount point
+        let stdout = "\
+Filesystem     Size   Used  Avail Capacity iused ifree %iused  Mounted on
+/dev/disk3s1  460Gi  220Gi  200Gi    53%  1.2M  2.1G    0%   /System/Volumes/Data
+";
+        let (rows, _) = parse_df(stdout);
+        assert_eq!(rows[0].used_percent, Some(53));
+        assert_eq!(rows[0].mounted_on, "/System/Volumes/Data");
+    }
+
+    #[test]
+    fn test_parse_ps() {
+        let stdout = "\
+USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND
+root           1  0.0  0.1 167744 11520 ?        Ss   Jan01   0:05 /sbin/init splash
+www-data    4242 12.5  2.3 512000 94000 ?        S    10:31   1:02 nginx: worker process
+garbage line
+";
+        let (rows, unparsed) = parse_ps(stdout);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].pid, 1);
+        assert_eq!(rows[0].command, "/sbin/init splash");
+        assert_eq!(rows[1].user, "www-data");
+        assert_eq!(rows[1].cpu_percent, 12.5);
+        assert_eq!(rows[1].rss_kb, 94000);
+        assert_eq!(rows[1].command, "nginx: worker process");
+        assert_eq!(unparsed.0, vec!["garbage line"]);
+    }
+
+    #[test]
+    fn test_parse_ss() {
+        let stdout = r#"State  Recv-Q Send-Q Local Address:Port  Peer Address:Port Process
+LISTEN 0      128          0.0.0.0:22         0.0.0.0:*     users:(("sshd",pid=812,fd=3))
+LISTEN 0      4096   127.0.0.53%lo:53         0.0.0.0:*     users:(("systemd-resolve",pid=601,fd=14),("helper",pid=602,fd=3))
+LISTEN 0      511             [::]:80            [::]:*
+"#;
+        let (rows, unparsed) = parse_ss(stdout);
+        assert!(unparsed.0.is_empty());
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].netid, None);
+        assert_eq!(rows[0].state, "LISTEN");
+        assert_eq!(rows[0].local_address, "0.0.0.0");
+        assert_eq!(rows[0].local_port, Some(22));
+        assert_eq!(
+            rows[0].processes,
+            vec![SocketProcess {
+                name: "sshd".to_string(),
+                pid: Some(812)
+            }]
+        );
+        assert_eq!(rows[1].local_address, "127.0.0.53%lo");
+        assert_eq!(rows[1].processes.len(), 2);
+        assert_eq!(rows[2].local_address, "[::]");
+        assert_eq!(rows[2].local_port, Some(80));
+        assert!(rows[2].processes.is_empty());
+
+        let stdout = "\
+Netid State  Recv-Q Send-Q Local Address:Port Peer Address:Port Process
+udp   UNCONN 0      0            0.0.0.0:68        0.0.0.0:*
+tcp   LISTEN 0      128                *:8080            *:*
+";
+        let (rows, _) = parse_ss(stdout);
+        assert_eq!(rows[0].netid.as_deref(), Some("udp"));
+        assert_eq!(rows[0].state, "UNCONN");
+        assert_eq!(rows[1].local_address, "*");
+        assert_eq!(rows[1].local_port, Some(8080));
+    }
+
+    #[test]
+    fn test_parse_systemctl() {
+        let stdout = "\
+  UNIT                     LOAD   ACTIVE SUB     DESCRIPTION
+  cron.service             loaded active running Regular background program processing daemon
+● nginx.service            loaded failed failed  A high performance web server
+  ghost.service            not-found inactive dead ghost.service
+
+LOAD   = Reflects whether the unit definition was properly loaded.
+3 loaded units listed.
+";
+        let (rows, unparsed) = parse_systemctl(stdout);
+        assert!(unparsed.0.is_empty());
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].unit, "cron.service");
+        assert_eq!(rows[0].sub, "running");
+        assert_eq!(
+            rows[0].description,
+            "Regular background program processing daemon"
+        );
+        assert_eq!(rows[1].unit, "nginx.service");
+        assert_eq!(rows[1].active, "failed");
+        assert_eq!(rows[2].load, "not-found");
+    }
+
+    #[test]
+    fn test_unparsed_is_capped() {
+        let stdout = "x\n".repeat(MAX_UNPARSED_LINES * 2);
+        let (_, unparsed) = parse_ps(&stdout);
+        assert_eq!(unparsed.0.len(), MAX_UNPARSED_LINES);
+    }
+}
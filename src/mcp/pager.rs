@@ -0,0 +1,199 @@
+//! Pager prompt detection for interactive shells.
+//!
+//! Commands such as `man`, `git log` or `systemctl status` page their output
+//! through `less` or `more` when attached to a PTY. The pager then waits for a
+//! key and an agent reading the shell sees no further output. `ssh_shell_read`
+//! looks at the last line of the buffer for a pager prompt, reports it, and
+//! can answer it according to a [`PagerPolicy`]:
+//!
+//! | policy | `--More--` / `lines X-Y` / `:` | `(END)` |
+//! |--------|-------------------------------|---------|
+//! | `detect` (default) | report only | report only |
+//! | `continue` | space (next page) | `q` |
+//! | `quit` | `q` | `q` |
+//!
+//! Detection ignores ANSI escape sequences, since `less` highlights its prompt
+//! with standout mode.
+
+use std::time::Duration;
+
+use super::types::PagerPrompt;
+
+/// Maximum pager keys sent during one read, so an endless stream (e.g.
+/// `less +F`) cannot keep a read going forever
+pub(crate) const MAX_PAGER_KEYS: u32 = 200;
+
+/// How long to wait for the pager to redraw after a key
+pub(crate) const PAGER_SETTLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Quiet period that ends the wait once new output has arrived
+pub(crate) const PAGER_QUIET_PERIOD: Duration = Duration::from_millis(150);
+
+/// How `ssh_shell_read` answers a pager prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PagerPolicy {
+    /// Report the prompt without sending anything
+    Detect,
+    /// Page through to the end, then quit
+    Continue,
+    /// Quit the pager immediately
+    Quit,
+}
+
+impl PagerPolicy {
+    /// Parse the `pager` tool parameter (default: `detect`).
+    pub(crate) fn parse(name: Option<&str>) -> Result<Self, String> {
+        match name.map(str::trim) {
+            None | Some("") | Some("detect") => Ok(Self::Detect),
+            Some("continue") => Ok(Self::Continue),
+            Some("quit") => Ok(Self::Quit),
+            Some(other) => Err(format!(
+                "Unknown pager policy '{}'. Use 'detect', 'continue' or 'quit'",
+                other
+            )),
+        }
+    }
+
+    /// Key to send for a prompt, if any.
+    pub(crate) fn key_for(self, prompt: PagerPrompt) -> Option<&'static [u8]> {
+        match (self, prompt) {
+            (Self::Detect, _) => None,
+            (Self::Continue, PagerPrompt::More | PagerPrompt::Less) => Some(b" "),
+            (Self::Continue, PagerPrompt::End) | (Self::Quit, _) => Some(b"q"),
+        }
+    }
+}
+
+/// Detect a pager prompt on the last line of shell output.
+pub(crate) fn detect_pager_prompt(output: &[u8]) -> Option<PagerPrompt> {
+    // Prompts are short; only the tail needs decoding.
+    let tail = &output[output.len().saturating_sub(512)..];
+    let text = strip_ansi(&String::from_utf8_lossy(tail));
+    let line = text
+        .rsplit(['\n', '\r'])
+        .find(|line| !line.trim().is_empty())?
+        .trim();
+    // Only the very last line counts; older prompts were already answered.
+    if !text.trim_end_matches([' ', '\t']).ends_with(line) {
+        return None;
+    }
+
+    if line.ends_with("(END)") {
+        Some(PagerPrompt::End)
+    } else if line.contains("--More--") || line.contains("-- More --") {
+        Some(PagerPrompt::More)
+    } else if line == ":" || is_less_lines_prompt(line) {
+        Some(PagerPrompt::Less)
+    } else {
+        None
+    }
+}
+
+/// `less -M` / systemd style prompt: `lines 1-24/120 20%`.
+fn is_less_lines_prompt(line: &str) -> bool {
+    let Some(rest) = line
+        .find("lines ")
+        .map(|start| &line[start + "lines ".len()..])
+    else {
+        return false;
+    };
+    let range: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '-')
+        .collect();
+    matches!(range.split_once('-'), Some((from, to)) if !from.is_empty() && !to.is_empty())
+}
+
+/// Remove ANSI CSI sequences (`ESC [ ... letter`) and two-byte escapes.
+pub(crate) fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod detection {
+        use super::*;
+
+        #[test]
+        fn test_more_prompt() {
+            assert_eq!(
+                detect_pager_prompt(b"line 1\nline 2\n\x1b[7m--More--(42%)\x1b[27m"),
+                Some(PagerPrompt::More)
+            );
+        }
+
+        #[test]
+        fn test_less_prompts() {
+            assert_eq!(
+                detect_pager_prompt(b"text\n\x1b[7mlines 1-24/120 20%\x1b[27m"),
+                Some(PagerPrompt::Less)
+            );
+            assert_eq!(detect_pager_prompt(b"text\n:"), Some(PagerPrompt::Less));
+            assert_eq!(
+                detect_pager_prompt(b"text\n\x1b[7m(END)\x1b[27m"),
+                Some(PagerPrompt::End)
+            );
+            assert_eq!(
+                detect_pager_prompt(b"text\nlines 100-120/120 (END)"),
+                Some(PagerPrompt::End)
+            );
+        }
+
+        #[test]
+        fn test_answered_prompt_is_ignored() {
+            assert_eq!(detect_pager_prompt(b"--More--(10%)\rmore text\n$ "), None);
+            assert_eq!(detect_pager_prompt(b"user@host:~$ "), None);
+            assert_eq!(detect_pager_prompt(b"copied 3 lines 1 file\n"), None);
+            assert_eq!(detect_pager_prompt(b""), None);
+        }
+    }
+
+    mod policy {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(PagerPolicy::parse(None).unwrap(), PagerPolicy::Detect);
+            assert_eq!(
+                PagerPolicy::parse(Some("continue")).unwrap(),
+                PagerPolicy::Continue
+            );
+            assert_eq!(PagerPolicy::parse(Some("quit")).unwrap(), PagerPolicy::Quit);
+            assert!(PagerPolicy::parse(Some("page")).is_err());
+        }
+
+        #[test]
+        fn test_keys() {
+            assert_eq!(PagerPolicy::Detect.key_for(PagerPrompt::More), None);
+            assert_eq!(
+                PagerPolicy::Continue.key_for(PagerPrompt::Less),
+                Some(&b" "[..])
+            );
+            assert_eq!(
+                PagerPolicy::Continue.key_for(PagerPrompt::End),
+                Some(&b"q"[..])
+            );
+            assert_eq!(
+                PagerPolicy::Quit.key_for(PagerPrompt::More),
+                Some(&b"q"[..])
+            );
+        }
+    }
+}
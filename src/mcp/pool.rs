@@ -0,0 +1,395 @@
+//! Opt-in pool of idle connections reused by `ssh_connect` (`[connection_pool]`).
+//!
+//! With the pool enabled, `ssh_disconnect` parks a session's connection
+//! instead of closing it, once its shells, commands and forwards are gone. A
+//! later `ssh_connect` by the same agent to the same `username@address` with
+//! the same credentials takes it back after a keepalive ping, skipping the TCP
+//! connect, key exchange and authentication.
+//!
+//! Connections are keyed by agent, address, username, a SHA-256 fingerprint of
+//! the credential, compression, keepalive settings and host key checking mode;
+//! the credential itself is not kept here. Sessions with jump hosts or reverse forwards, and
+//! `persistent`, `agent_forwarding` or custom algorithm ones, are closed as
+//! before.
+//!
+//! `max_idle_per_key` and `max_idle` bound the parked connections (the oldest
+//! is closed to make room) and a background task closes those parked longer
+//! than `idle_secs`. Parked connections still count against per-host session
+//! caps.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use russh::Disconnect;
+use russh::client::Handle;
+use tracing::{info, warn};
+
+use super::client::{RTT_PROBE_TIMEOUT, measure_rtt};
+use super::config::{ConnectionPoolConfig, server_config};
+use super::host_limit::host_key;
+use super::reconnect::{self, ConnectParams};
+use super::reverse::{REVERSE_FORWARDS, ReverseForwards};
+use super::session::SshClientHandler;
+use super::traffic::ByteCounters;
+
+/// Longest time between idle eviction sweeps
+const MAX_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Parked connections by pool key, oldest first.
+static POOL: Lazy<DashMap<String, Vec<IdleConnection>>> = Lazy::new(DashMap::new);
+
+/// A connection parked by `ssh_disconnect`.
+pub(crate) struct IdleConnection {
+    pub handle: Arc<Handle<SshClientHandler>>,
+    /// Reverse forward registry of the connection's client handler
+    pub reverse: Arc<ReverseForwards>,
+    /// Byte counters of the connection, billed to the same agent
+    pub traffic: Arc<ByteCounters>,
+    /// Host the connection counts against (see [`host_key`])
+    host: String,
+    /// Agent that parked it, whose session quota it counts against
+    agent_id: Option<String>,
+    parked_at: Instant,
+}
+
+/// What the pool limits need to know about a parked entry.
+trait Parked {
+    fn parked_at(&self) -> Instant;
+    fn is_closed(&self) -> bool;
+}
+
+impl Parked for IdleConnection {
+    fn parked_at(&self) -> Instant {
+        self.parked_at
+    }
+
+    fn is_closed(&self) -> bool {
+        self.handle.is_closed()
+    }
+}
+
+/// Whether `ssh_disconnect` parks connections for reuse.
+pub(crate) fn enabled() -> bool {
+    server_config().connection_pool.enabled
+}
+
+/// Pool key for connections opened by `agent_id` with `params`.
+pub(crate) fn pool_key(agent_id: Option<&str>, params: &ConnectParams) -> String {
+    let fingerprint = params.credential_fingerprint();
+    format!(
+        "{}|{}@{}|{}|compress={}|keepalive={}/{}|{}",
+        agent_id.unwrap_or(""),
+        params.username,
+        params.address,
+        &fingerprint[..16],
+        params.compress,
+        params.keepalive.interval_secs,
+        params.keepalive.max,
+        params.host_key_checking.as_str()
+    )
+}
+
+/// Whether a connection made with `params` may be pooled at all.
+pub(crate) fn poolable(params: &ConnectParams) -> bool {
+    enabled()
+        && params.jump_hosts.is_empty()
+        && !params.persistent
+        && !params.agent_forwarding
+        && params.algorithms.is_default()
+}
+
+/// Pool key and reverse forward registry of `session_id` when its connection
+/// can be parked on disconnect.
+///
+/// Must be called before the session is torn down, which forgets both.
+pub(crate) fn parking_spot(
+    session_id: &str,
+    agent_id: Option<&str>,
+) -> Option<(String, Arc<ReverseForwards>)> {
+    let params = reconnect::connect_params(session_id).filter(poolable)?;
+    let reverse = REVERSE_FORWARDS.get(session_id)?;
+    if !reverse.tunnels().is_empty() {
+        return None;
+    }
+    Some((pool_key(agent_id, &params), reverse))
+}
+
+/// Park a connection under `key`, closing the oldest ones past the limits.
+pub(crate) async fn park(
+    key: String,
+    address: &str,
+    agent_id: Option<String>,
+    handle: Arc<Handle<SshClientHandler>>,
+    reverse: Arc<ReverseForwards>,
+    traffic: Arc<ByteCounters>,
+) {
+    let config = &server_config().connection_pool;
+    let idle = IdleConnection {
+        handle,
+        reverse,
+        traffic,
+        host: host_key(address),
+        agent_id,
+        parked_at: Instant::now(),
+    };
+    let evicted = insert(&POOL, key, idle, config);
+    close_all(evicted, "Pool limit reached").await;
+}
+
+/// Take back the newest live connection parked under `key`.
+pub(crate) async fn take(key: &str) -> Option<IdleConnection> {
+    loop {
+        let idle = POOL.get_mut(key).and_then(|mut parked| parked.pop())?;
+        POOL.remove_if(key, |_, parked| parked.is_empty());
+        if !idle.handle.is_closed() && measure_rtt(&idle.handle, RTT_PROBE_TIMEOUT).await.is_some()
+        {
+            info!(
+                "Reusing pooled connection ({}s idle)",
+                idle.parked_at.elapsed().as_secs()
+            );
+            return Some(idle);
+        }
+        close(idle, "Pooled connection failed its check").await;
+    }
+}
+
+/// Parked connections counting against `host` (see [`host_key`]).
+pub(crate) fn idle_on_host(host: &str) -> usize {
+    POOL.iter()
+        .map(|parked| parked.iter().filter(|idle| idle.host == host).count())
+        .sum()
+}
+
+/// Owners of the parked connections, one entry per connection.
+pub(crate) fn idle_owners() -> Vec<Option<String>> {
+    POOL.iter()
+        .flat_map(|parked| {
+            parked
+                .iter()
+                .map(|idle| idle.agent_id.clone())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Add `idle` to `pool`, returning the connections removed to respect the limits.
+fn insert<T: Parked>(
+    pool: &DashMap<String, Vec<T>>,
+    key: String,
+    idle: T,
+    config: &ConnectionPoolConfig,
+) -> Vec<T> {
+    let mut evicted = Vec::new();
+    {
+        let mut parked = pool.entry(key).or_default();
+        parked.push(idle);
+        let excess = parked.len().saturating_sub(config.max_idle_per_key.max(1));
+        evicted.extend(parked.drain(..excess));
+    }
+    loop {
+        let total: usize = pool.iter().map(|parked| parked.len()).sum();
+        if total <= config.max_idle {
+            break;
+        }
+        let oldest = pool
+            .iter()
+            .filter_map(|parked| {
+                parked
+                    .first()
+                    .map(|idle| (parked.key().clone(), idle.parked_at()))
+            })
+            .min_by_key(|(_, parked_at)| *parked_at);
+        let Some((key, _)) = oldest else {
+            break;
+        };
+        if let Some(mut parked) = pool.get_mut(&key)
+            && !parked.is_empty()
+        {
+            evicted.push(parked.remove(0));
+        }
+        pool.remove_if(&key, |_, parked| parked.is_empty());
+    }
+    evicted
+}
+
+/// Remove the connections parked longer than `idle` or already closed.
+fn expire<T: Parked>(pool: &DashMap<String, Vec<T>>, idle: Duration) -> Vec<T> {
+    let mut expired = Vec::new();
+    for mut parked in pool.iter_mut() {
+        let (keep, stale): (Vec<_>, Vec<_>) = std::mem::take(&mut *parked)
+            .into_iter()
+            .partition(|conn| conn.parked_at().elapsed() < idle && !conn.is_closed());
+        *parked = keep;
+        expired.extend(stale);
+    }
+    pool.retain(|_, parked| !parked.is_empty());
+    expired
+}
+
+async fn close(idle: IdleConnection, reason: &str) {
+    if let Err(e) = idle
+        .handle
+        .disconnect(Disconnect::ByApplication, reason, "en")
+        .await
+    {
+        warn!("Error closing pooled connection: {}", e);
+    }
+}
+
+async fn close_all(connections: Vec<IdleConnection>, reason: &str) {
+    for idle in connections {
+        close(idle, reason).await;
+    }
+}
+
+/// Start the idle eviction task when the pool is enabled.
+///
+/// Runs once per process; later calls do nothing.
+pub(crate) fn start() {
+    let config = &server_config().connection_pool;
+    if !config.enabled || STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if tokio::runtime::Handle::try_current().is_err() {
+        warn!("No async runtime; idle pooled connections are not evicted");
+        return;
+    }
+
+    let idle = Duration::from_secs(config.idle_secs);
+    info!(
+        "Pooling idle connections for {}s (at most {} per key, {} in total)",
+        config.idle_secs, config.max_idle_per_key, config.max_idle
+    );
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(idle.clamp(Duration::from_secs(1), MAX_SWEEP_INTERVAL));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            close_all(expire(&POOL, idle), "Pooled connection idle").await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::config::Keepalive;
+    use crate::mcp::known_hosts::HostKeyChecking;
+
+    struct Entry {
+        id: u32,
+        parked_at: Instant,
+        closed: bool,
+    }
+
+    impl Parked for Entry {
+        fn parked_at(&self) -> Instant {
+            self.parked_at
+        }
+
+        fn is_closed(&self) -> bool {
+            self.closed
+        }
+    }
+
+    fn entry(id: u32, age_secs: u64) -> Entry {
+        Entry {
+            id,
+            parked_at: Instant::now() - Duration::from_secs(age_secs),
+            closed: false,
+        }
+    }
+
+    fn params(password: Option<&str>) -> ConnectParams {
+        ConnectParams {
+            address: "db1:22".to_string(),
+            username: "deploy".to_string(),
+            password: password.map(str::to_string),
+            key_path: None,
+            key_data: None,
+            timeout: Duration::from_secs(30),
+            inactivity_timeout: Duration::from_secs(300),
+            compress: true,
+            persistent: false,
+            keepalive: Keepalive::default(),
+            algorithms: Default::default(),
+            host_key_checking: HostKeyChecking::AcceptNew,
+            jump_hosts: Vec::new(),
+            auto_reconnect: false,
+            credential_ref: None,
+            agent_forwarding: false,
+            redacted: None,
+        }
+    }
+
+    #[test]
+    fn test_pool_key_separates_agents_and_credentials() {
+        let key = pool_key(Some("agent-a"), &params(Some("secret")));
+        assert_eq!(key, pool_key(Some("agent-a"), &params(Some("secret"))));
+        assert_ne!(key, pool_key(Some("agent-b"), &params(Some("secret"))));
+        assert_ne!(key, pool_key(Some("agent-a"), &params(Some("other"))));
+        assert_ne!(key, pool_key(Some("agent-a"), &params(None)));
+        let keepalive = Keepalive {
+            interval_secs: 5,
+            max: 10,
+        };
+        let tuned = ConnectParams {
+            keepalive,
+            ..params(Some("secret"))
+        };
+        assert_ne!(key, pool_key(Some("agent-a"), &tuned));
+        assert!(!key.contains("secret"));
+        assert!(key.contains("deploy@db1:22"));
+        // The copy kept after connecting parks under the same key
+        let kept = params(Some("secret")).without_secrets();
+        assert!(kept.password.is_none());
+        assert_eq!(key, pool_key(Some("agent-a"), &kept));
+    }
+
+    #[test]
+    fn test_insert_respects_limits() {
+        let config = ConnectionPoolConfig {
+            enabled: true,
+            max_idle_per_key: 2,
+            max_idle: 3,
+            idle_secs: 300,
+        };
+        let pool = DashMap::new();
+        assert!(insert(&pool, "a".to_string(), entry(1, 50), &config).is_empty());
+        assert!(insert(&pool, "a".to_string(), entry(2, 40), &config).is_empty());
+        // Third entry for the key pushes out its oldest
+        let evicted = insert(&pool, "a".to_string(), entry(3, 30), &config);
+        assert_eq!(evicted.iter().map(|e| e.id).collect::<Vec<_>>(), [1]);
+
+        assert!(insert(&pool, "b".to_string(), entry(4, 20), &config).is_empty());
+        // Fourth connection overall closes the oldest of any key
+        let evicted = insert(&pool, "b".to_string(), entry(5, 10), &config);
+        assert_eq!(evicted.iter().map(|e| e.id).collect::<Vec<_>>(), [2]);
+        assert_eq!(pool.get("a").unwrap().len(), 1);
+        assert_eq!(pool.get("b").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_expire_drops_idle_and_closed() {
+        let pool = DashMap::new();
+        let mut closed = entry(3, 0);
+        closed.closed = true;
+        pool.insert("a".to_string(), vec![entry(1, 600), entry(2, 10)]);
+        pool.insert("b".to_string(), vec![closed]);
+
+        let mut expired: Vec<u32> = expire(&pool, Duration::from_secs(300))
+            .iter()
+            .map(|e| e.id)
+            .collect();
+        expired.sort();
+        assert_eq!(expired, [1, 3]);
+        assert_eq!(pool.get("a").unwrap().len(), 1);
+        assert!(pool.get("b").is_none());
+    }
+}
@@ -0,0 +1,189 @@
+//! TCP reachability checks from the remote host's point of view.
+//!
+//! `ssh_check_port` first asks the SSH server to open a `direct-tcpip` channel
+//! to the target, the same mechanism `ssh_forward` uses:
+//!
+//! - channel opens: **open**
+//! - server reports a connect failure: **closed**
+//! - no answer within the timeout: **filtered**
+//!
+//! When the server forbids TCP forwarding (`AllowTcpForwarding no`), the check
+//! falls back to a probe command on the remote host (`nc -z`, or bash's
+//! `/dev/tcp` under `timeout`).
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use russh::{ChannelOpenFailure, client};
+use tracing::debug;
+
+use super::client::execute_ssh_command;
+use super::command_wrap::shell_quote;
+use super::session::SshClientHandler;
+use super::types::PortState;
+
+/// Default time allowed for a port check
+pub(crate) const DEFAULT_PORT_CHECK_TIMEOUT_SECS: u64 = 5;
+
+/// Longest port check timeout
+pub(crate) const MAX_PORT_CHECK_TIMEOUT_SECS: u64 = 60;
+
+/// Exit code of the probe when neither `nc` nor `bash` exists remotely
+const PROBE_UNAVAILABLE_EXIT: i32 = 127;
+
+/// Exit code of coreutils `timeout` when the command timed out
+const PROBE_TIMEOUT_EXIT: i32 = 124;
+
+/// Outcome of a port check.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PortCheck {
+    pub state: PortState,
+    /// `direct_tcpip` or `remote_probe`
+    pub method: &'static str,
+    /// Time until the state was known
+    pub elapsed: Duration,
+}
+
+/// Resolve the port check timeout, clamped to 1..=`MAX_PORT_CHECK_TIMEOUT_SECS`.
+pub(crate) fn resolve_port_check_timeout(timeout_secs: Option<u64>) -> Duration {
+    Duration::from_secs(
+        timeout_secs
+            .unwrap_or(DEFAULT_PORT_CHECK_TIMEOUT_SECS)
+            .clamp(1, MAX_PORT_CHECK_TIMEOUT_SECS),
+    )
+}
+
+/// Check whether `host:port` accepts TCP connections from the remote host.
+pub(crate) async fn check_port(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<PortCheck, String> {
+    let started = Instant::now();
+    let open = tokio::time::timeout(
+        timeout,
+        handle.channel_open_direct_tcpip(host, port as u32, "127.0.0.1", 0),
+    )
+    .await;
+
+    let state = match open {
+        Ok(Ok(channel)) => {
+            let _ = channel.close().await;
+            PortState::Open
+        }
+        Ok(Err(russh::Error::ChannelOpenFailure(ChannelOpenFailure::ConnectFailed))) => {
+            PortState::Closed
+        }
+        Ok(Err(russh::Error::ChannelOpenFailure(reason))) => {
+            debug!(
+                "direct-tcpip to {}:{} refused by server ({:?}), probing remotely",
+                host, port, reason
+            );
+            return remote_probe(handle, host, port, timeout).await;
+        }
+        Ok(Err(e)) => return Err(format!("Failed to open direct-tcpip channel: {}", e)),
+        Err(_) => PortState::Filtered,
+    };
+
+    Ok(PortCheck {
+        state,
+        method: "direct_tcpip",
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Probe `host:port` with a command on the remote host.
+async fn remote_probe(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<PortCheck, String> {
+    let started = Instant::now();
+    let command = probe_command(host, port, timeout.as_secs());
+    // Leave the remote `timeout` room to report before the channel deadline
+    let response = execute_ssh_command(handle, &command, timeout + Duration::from_secs(2)).await?;
+
+    let state = if response.timed_out {
+        PortState::Filtered
+    } else {
+        classify_probe_exit(response.exit_code).ok_or_else(|| {
+            "Port probe needs nc or bash on the remote host; enable AllowTcpForwarding instead"
+                .to_string()
+        })?
+    };
+
+    Ok(PortCheck {
+        state,
+        method: "remote_probe",
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Shell snippet that exits 0 when `host:port` accepts connections.
+pub(crate) fn probe_command(host: &str, port: u16, timeout_secs: u64) -> String {
+    format!(
+        "h={host}; p={port}; \
+         if command -v nc >/dev/null 2>&1; then nc -z -w {t} \"$h\" \"$p\"; \
+         elif command -v bash >/dev/null 2>&1 && command -v timeout >/dev/null 2>&1; then \
+         timeout {t} bash -c 'exec 3<>\"/dev/tcp/$0/$1\"' \"$h\" \"$p\"; \
+         else exit {unavailable}; fi",
+        host = shell_quote(host),
+        port = port,
+        t = timeout_secs,
+        unavailable = PROBE_UNAVAILABLE_EXIT,
+    )
+}
+
+/// Map the probe exit code to a port state (`None`: no probe tool available).
+pub(crate) fn classify_probe_exit(exit_code: i32) -> Option<PortState> {
+    match exit_code {
+        0 => Some(PortState::Open),
+        PROBE_TIMEOUT_EXIT => Some(PortState::Filtered),
+        PROBE_UNAVAILABLE_EXIT => None,
+        _ => Some(PortState::Closed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod timeout {
+        use super::*;
+
+        #[test]
+        fn test_default_and_clamping() {
+            assert_eq!(
+                resolve_port_check_timeout(None).as_secs(),
+                DEFAULT_PORT_CHECK_TIMEOUT_SECS
+            );
+            assert_eq!(resolve_port_check_timeout(Some(0)).as_secs(), 1);
+            assert_eq!(
+                resolve_port_check_timeout(Some(600)).as_secs(),
+                MAX_PORT_CHECK_TIMEOUT_SECS
+            );
+        }
+    }
+
+    mod probe {
+        use super::*;
+
+        #[test]
+        fn test_command_quotes_host() {
+            let command = probe_command("db'; rm -rf /", 5432, 3);
+            assert!(command.starts_with(r"h='db'\''; rm -rf /'; p=5432;"));
+            assert!(command.contains("nc -z -w 3"));
+            assert!(command.contains("timeout 3 bash"));
+        }
+
+        #[test]
+        fn test_exit_codes() {
+            assert_eq!(classify_probe_exit(0), Some(PortState::Open));
+            assert_eq!(classify_probe_exit(1), Some(PortState::Closed));
+            assert_eq!(classify_probe_exit(124), Some(PortState::Filtered));
+            assert_eq!(classify_probe_exit(127), None);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Privilege-drop verification after sudo operations.
+//!
+//! After an agent ran something through sudo, `ssh_verify_privilege_drop` (and
+//! `ssh_execute_sync` with `verify_privilege_drop=true`) checks that nothing
+//! privileged outlived it:
+//!
+//! - cached sudo credentials are invalidated with `sudo -k`, so later commands
+//!   cannot reuse the timestamp without a password
+//! - `sudo` processes started from the session user's processes that are still
+//!   running (a `sudo -i` shell left open in a console, `sudo sleep 600 &`)
+//! - root processes whose command line matches a command wrapped by sudo
+//!   (`sudo -b`, `sudo nohup ... &`), found with `pgrep -u 0 -f`
+//!
+//! Commands are split on shell separators and whitespace without a full shell
+//! parser: quotes are dropped, and substitutions or here-docs are not followed.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::client;
+
+use super::client::execute_ssh_command;
+use super::command_wrap::shell_quote;
+use super::session::SshClientHandler;
+use super::types::{LingeringProcess, PrivilegeDropReport, PrivilegeDropStatus};
+
+/// Upper bound for the check; it only runs `sudo -k`, `pgrep` and `ps`.
+pub(crate) const PRIVILEGE_DROP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// sudo options that take a value in the next argument
+const SUDO_VALUE_OPTIONS: &[&str] = &[
+    "-C", "-D", "-R", "-T", "-U", "-g", "-h", "-p", "-r", "-t", "-u",
+];
+
+/// Launchers sudo commonly runs; the wrapped program follows them
+const LAUNCHERS: &[&str] = &[
+    "nohup", "setsid", "nice", "ionice", "env", "exec", "command",
+];
+
+/// The commands run through sudo in `command`, without sudo's own options or
+/// launchers such as `nohup`. Bare `sudo -i` / `sudo -s` wrap nothing.
+pub(crate) fn wrapped_commands(command: &str) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    for segment in command.split([';', '&', '|', '\n', '(', ')']) {
+        let words: Vec<&str> = segment
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c| c == '\'' || c == '"'))
+            .filter(|word| !word.is_empty())
+            .collect();
+        let Some(sudo) = words
+            .iter()
+            .position(|word| word.rsplit('/').next() == Some("sudo"))
+        else {
+            continue;
+        };
+
+        let mut rest = &words[sudo + 1..];
+        while let Some(word) = rest.first() {
+            if *word == "--" {
+                rest = &rest[1..];
+                break;
+            }
+            if !word.starts_with('-') {
+                break;
+            }
+            let skip = if SUDO_VALUE_OPTIONS.contains(word) {
+                2
+            } else {
+                1
+            };
+            rest = &rest[skip.min(rest.len())..];
+        }
+        while let Some(word) = rest.first() {
+            let is_launcher = LAUNCHERS.contains(&word.rsplit('/').next().unwrap_or(word));
+            let is_argument =
+                word.starts_with('-') || word.contains('=') || word.parse::<i64>().is_ok();
+            if !(is_launcher || is_argument) {
+                break;
+            }
+            rest = &rest[1..];
+        }
+
+        if !rest.is_empty() {
+            let joined = rest.join(" ");
+            if !wrapped.contains(&joined) {
+                wrapped.push(joined);
+            }
+        }
+    }
+    wrapped
+}
+
+/// Escape `text` for use as a literal in a `pgrep` extended regular expression.
+fn regex_literal(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Script invalidating sudo credentials and listing what is still running.
+///
+/// Prints `uid=N`, then `sudo_k=N` (`sudo_k=missing` without sudo), then one
+/// `sudo <pid> <args>` line per sudo process whose parent belongs to the
+/// session user and one `match <pid> <args>` line per root process matching
+/// a wrapped command. Root sessions skip the process checks.
+pub(crate) fn privilege_drop_command(wrapped: &[String]) -> String {
+    let mut script = String::from(
+        "uid=$(id -u 2>/dev/null); echo \"uid=$uid\"; \
+         if command -v sudo >/dev/null 2>&1; then sudo -k 2>/dev/null; echo \"sudo_k=$?\"; \
+         else echo 'sudo_k=missing'; fi; \
+         if [ \"$uid\" != 0 ]; then \
+         for pid in $(pgrep -x sudo 2>/dev/null); do \
+         ppid=$(ps -o ppid= -p \"$pid\" 2>/dev/null | tr -d ' '); \
+         [ -n \"$ppid\" ] && [ \"$(ps -o uid= -p \"$ppid\" 2>/dev/null | tr -d ' ')\" = \"$uid\" ] && \
+         echo \"sudo $pid $(ps -o args= -p \"$pid\" 2>/dev/null)\"; \
+         done;",
+    );
+    for command in wrapped {
+        script.push_str(&format!(
+            " pgrep -a -u 0 -f -- {} 2>/dev/null | sed 's/^/match /';",
+            shell_quote(&regex_literal(command))
+        ));
+    }
+    script.push_str(" fi");
+    script
+}
+
+/// Parse the output of [`privilege_drop_command`].
+pub(crate) fn parse_privilege_drop(stdout: &str, searched: Vec<String>) -> PrivilegeDropReport {
+    let mut uid = None;
+    let mut credentials_invalidated = false;
+    let mut lingering: Vec<LingeringProcess> = Vec::new();
+    for line in stdout.lines() {
+        if let Some(value) = line.strip_prefix("uid=") {
+            uid = value.trim().parse::<u32>().ok();
+        } else if let Some(value) = line.strip_prefix("sudo_k=") {
+            credentials_invalidated = value.trim() == "0";
+        } else if let Some((kind, rest)) = line.split_once(' ') {
+            let sudo = match kind {
+                "sudo" => true,
+                "match" => false,
+                _ => continue,
+            };
+            let (pid, command) = rest.trim_start().split_once(' ').unwrap_or((rest, ""));
+            let Ok(pid) = pid.parse::<u32>() else {
+                continue;
+            };
+            if lingering.iter().all(|process| process.pid != pid) {
+                lingering.push(LingeringProcess {
+                    pid,
+                    command: command.trim().to_string(),
+                    sudo,
+                });
+            }
+        }
+    }
+
+    let status = if uid == Some(0) {
+        PrivilegeDropStatus::NotApplicable
+    } else if lingering.is_empty() {
+        PrivilegeDropStatus::Clean
+    } else {
+        PrivilegeDropStatus::Lingering
+    };
+    PrivilegeDropReport {
+        status,
+        credentials_invalidated,
+        searched,
+        lingering,
+    }
+}
+
+/// Invalidate sudo credentials on the session and report lingering privileged
+/// processes of `command` (the sudo command that was run, if known).
+pub(crate) async fn verify_privilege_drop(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    command: Option<&str>,
+) -> Result<PrivilegeDropReport, String> {
+    let wrapped = command.map(wrapped_commands).unwrap_or_default();
+    let response = execute_ssh_command(
+        handle,
+        &privilege_drop_command(&wrapped),
+        PRIVILEGE_DROP_TIMEOUT,
+    )
+    .await?;
+    if response.timed_out {
+        return Err(format!(
+            "Privilege-drop check timed out after {}s",
+            PRIVILEGE_DROP_TIMEOUT.as_secs()
+        ));
+    }
+    Ok(parse_privilege_drop(&response.stdout, wrapped))
+}
+
+impl PrivilegeDropReport {
+    /// One-line summary for tool messages.
+    pub fn summary(&self) -> String {
+        let credentials = if self.credentials_invalidated {
+            "sudo credentials invalidated"
+        } else {
+            "sudo credentials not invalidated"
+        };
+        match self.status {
+            PrivilegeDropStatus::Clean => {
+                format!("No lingering privileged processes; {}", credentials)
+            }
+            PrivilegeDropStatus::Lingering => format!(
+                "{} privileged process(es) still running ({}); {}",
+                self.lingering.len(),
+                self.lingering
+                    .iter()
+                    .map(|process| process.pid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                credentials
+            ),
+            PrivilegeDropStatus::NotApplicable => {
+                "Session user is root; there is no privilege to drop".to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod wrapped {
+        use super::*;
+
+        #[test]
+        fn test_options_and_launchers_are_skipped() {
+            assert_eq!(
+                wrapped_commands("sudo -n -u root nohup /opt/app/worker --once &"),
+                vec!["/opt/app/worker --once"]
+            );
+            assert_eq!(
+                wrapped_commands("sudo env FOO=1 nice -n 10 tar -czf /tmp/a.tgz /etc"),
+                vec!["tar -czf /tmp/a.tgz /etc"]
+            );
+        }
+
+        #[test]
+        fn test_each_segment_and_duplicates() {
+            assert_eq!(
+                wrapped_commands(
+                    "cd /srv && sudo systemctl restart nginx; echo ok | sudo tee /etc/motd; sudo systemctl restart nginx"
+                ),
+                vec!["systemctl restart nginx", "tee /etc/motd"]
+            );
+        }
+
+        #[test]
+        fn test_shell_and_no_sudo() {
+            assert!(wrapped_commands("sudo -i").is_empty());
+            assert!(wrapped_commands("ls -la /var/log").is_empty());
+            assert_eq!(
+                wrapped_commands("/usr/bin/sudo -- 'apt-get' upgrade -y"),
+                vec!["apt-get upgrade -y"]
+            );
+        }
+    }
+
+    mod script {
+        use super::*;
+
+        #[test]
+        fn test_patterns_are_escaped_and_quoted() {
+            let script = privilege_drop_command(&["tee /etc/app.conf".to_string()]);
+            assert!(script.contains("sudo -k"));
+            assert!(script.contains("pgrep -a -u 0 -f -- 'tee /etc/app\\.conf'"));
+            assert!(script.ends_with(" fi"));
+        }
+    }
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn test_clean() {
+            let report = parse_privilege_drop("uid=1000\nsudo_k=0\n", vec!["ls".to_string()]);
+            assert_eq!(report.status, PrivilegeDropStatus::Clean);
+            assert!(report.credentials_invalidated);
+            assert!(report.lingering.is_empty());
+            assert_eq!(report.searched, vec!["ls"]);
+        }
+
+        #[test]
+        fn test_lingering_deduplicated_by_pid() {
+            let report = parse_privilege_drop(
+                "uid=1000\nsudo_k=0\nsudo 4120 sudo sleep 600\nmatch 4120 sudo sleep 600\nmatch 4121 sleep 600\n",
+                vec!["sleep 600".to_string()],
+            );
+            assert_eq!(report.status, PrivilegeDropStatus::Lingering);
+            assert_eq!(report.lingering.len(), 2);
+            assert!(report.lingering[0].sudo);
+            assert_eq!(report.lingering[1].pid, 4121);
+            assert_eq!(report.lingering[1].command, "sleep 600");
+            assert!(!report.lingering[1].sudo);
+            assert!(report.summary().contains("4120, 4121"));
+        }
+
+        #[test]
+        fn test_root_and_missing_sudo() {
+            let report = parse_privilege_drop("uid=0\nsudo_k=missing\n", Vec::new());
+            assert_eq!(report.status, PrivilegeDropStatus::NotApplicable);
+            assert!(!report.credentials_invalidated);
+        }
+    }
+}
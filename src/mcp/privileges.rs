@@ -0,0 +1,338 @@
+//! Remote sudo capability probe.
+//!
+//! `ssh_check_privileges` runs `sudo -n -l` (non-interactive, so it never
+//! blocks on a password prompt) and turns its rule listing into structured
+//! capabilities. Agents learn up front whether privileged remediation is
+//! possible, and whether it needs a password, instead of discovering it
+//! halfway through a fix.
+//!
+//! `sudo -l` output is only parsed for the common sudoers format:
+//!
+//! ```text
+//! User deploy may run the following commands on web-1:
+//!     (root) NOPASSWD: /usr/bin/systemctl restart nginx, /usr/bin/journalctl
+//!     (ALL : ALL) ALL
+//! ```
+//!
+//! Rules from other sources (LDAP `Sudoers entry:` blocks) are not parsed; the
+//! access level is still reported.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::client;
+
+use super::client::execute_ssh_command;
+use super::session::SshClientHandler;
+use super::types::{SudoAccess, SudoRule};
+
+/// Upper bound for the probe; `sudo -n` never waits for input, but LDAP-backed
+/// sudoers lookups can be slow.
+pub(crate) const PRIVILEGE_PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Probe printing the uid, the `sudo -n -l` listing and sudo's exit status.
+///
+/// The first line is `uid=N`, the last `sudo_exit=N` (or `sudo_exit=missing`
+/// when sudo is not installed); everything in between is sudo's combined
+/// output in the C locale.
+pub(crate) const PRIVILEGE_PROBE_COMMAND: &str = "echo \"uid=$(id -u 2>/dev/null)\"; \
+     if command -v sudo >/dev/null 2>&1; then \
+     LC_ALL=C sudo -n -l 2>&1; echo \"sudo_exit=$?\"; \
+     else echo 'sudo_exit=missing'; fi";
+
+/// Tags that may prefix the command list of a rule
+const RULE_TAGS: &[&str] = &[
+    "NOPASSWD",
+    "PASSWD",
+    "SETENV",
+    "NOSETENV",
+    "EXEC",
+    "NOEXEC",
+    "LOG_INPUT",
+    "NOLOG_INPUT",
+    "LOG_OUTPUT",
+    "NOLOG_OUTPUT",
+    "MAIL",
+    "NOMAIL",
+    "FOLLOW",
+    "NOFOLLOW",
+    "INTERCEPT",
+    "NOINTERCEPT",
+];
+
+/// Parsed result of [`PRIVILEGE_PROBE_COMMAND`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PrivilegeReport {
+    pub uid: Option<u32>,
+    pub access: SudoAccess,
+    pub rules: Vec<SudoRule>,
+    /// First line of sudo's explanation when access was refused
+    pub detail: Option<String>,
+}
+
+impl PrivilegeReport {
+    /// Whether some rule allows every command as root without a password.
+    pub fn passwordless_root(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.nopasswd && rule.runs_as_root() && rule.allows_any_command())
+    }
+
+    /// Whether some rule allows every command as root.
+    pub fn full_root(&self) -> bool {
+        self.uid == Some(0)
+            || self
+                .rules
+                .iter()
+                .any(|rule| rule.runs_as_root() && rule.allows_any_command())
+    }
+}
+
+/// Parse the probe output.
+pub(crate) fn parse_privileges(stdout: &str) -> PrivilegeReport {
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    let uid = lines
+        .first()
+        .and_then(|line| line.strip_prefix("uid="))
+        .and_then(|uid| uid.trim().parse().ok());
+    if uid.is_some() {
+        lines.remove(0);
+    }
+    let exit = lines
+        .last()
+        .and_then(|line| line.strip_prefix("sudo_exit="))
+        .map(|exit| exit.trim().to_string());
+    if exit.is_some() {
+        lines.pop();
+    }
+
+    let rules = parse_rules(&lines);
+    let detail = lines
+        .iter()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .map(str::to_string);
+
+    let access = match exit.as_deref() {
+        Some("missing") => SudoAccess::Unavailable,
+        _ if uid == Some(0) => SudoAccess::Root,
+        Some("0") => SudoAccess::Allowed,
+        _ => {
+            let output = lines.join("\n");
+            if output.contains("password is required") {
+                SudoAccess::PasswordRequired
+            } else if output.contains("may not run sudo")
+                || output.contains("not in the sudoers file")
+                || output.contains("not allowed to execute")
+            {
+                SudoAccess::Denied
+            } else {
+                SudoAccess::Unknown
+            }
+        }
+    };
+
+    PrivilegeReport {
+        uid,
+        detail: (!matches!(access, SudoAccess::Allowed | SudoAccess::Root))
+            .then_some(detail)
+            .flatten(),
+        access,
+        rules,
+    }
+}
+
+/// Run the probe on the session and parse its output.
+pub(crate) async fn probe_privileges(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+) -> Result<PrivilegeReport, String> {
+    let response =
+        execute_ssh_command(handle, PRIVILEGE_PROBE_COMMAND, PRIVILEGE_PROBE_TIMEOUT).await?;
+    if response.timed_out {
+        return Err(format!(
+            "Privilege probe timed out after {}s",
+            PRIVILEGE_PROBE_TIMEOUT.as_secs()
+        ));
+    }
+    Ok(parse_privileges(&response.stdout))
+}
+
+/// Parse the indented rule lines following `User ... may run the following commands on ...:`.
+fn parse_rules(lines: &[&str]) -> Vec<SudoRule> {
+    let mut rules = Vec::new();
+    let mut in_rules = false;
+    for line in lines {
+        if line.contains("may run the following commands") {
+            in_rules = true;
+            continue;
+        }
+        if !in_rules {
+            continue;
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            // A new unindented block (e.g. another listing header) ends the rules.
+            in_rules = false;
+            continue;
+        }
+        rules.push(parse_rule(trimmed));
+    }
+    rules
+}
+
+/// Parse one rule such as `(root) NOPASSWD: /usr/bin/systemctl restart nginx`.
+fn parse_rule(rule: &str) -> SudoRule {
+    let (run_as, mut rest) = match rule
+        .strip_prefix('(')
+        .and_then(|inner| inner.split_once(')'))
+    {
+        Some((run_as, rest)) => (run_as.trim().to_string(), rest.trim_start()),
+        None => ("root".to_string(), rule),
+    };
+
+    let mut tags = Vec::new();
+    while let Some((tag, after)) = rest.split_once(':') {
+        let tag = tag.trim();
+        if !RULE_TAGS.contains(&tag) {
+            break;
+        }
+        tags.push(tag.to_string());
+        rest = after.trim_start();
+    }
+
+    let commands = rest
+        .split(", ")
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    SudoRule {
+        nopasswd: tags.iter().any(|tag| tag == "NOPASSWD"),
+        run_as,
+        tags,
+        commands,
+    }
+}
+
+impl SudoRule {
+    /// Whether the rule lets the user run commands as root.
+    pub fn runs_as_root(&self) -> bool {
+        let users = self.run_as.split(':').next().unwrap_or_default();
+        users
+            .split(',')
+            .map(str::trim)
+            .any(|user| user == "ALL" || user == "root")
+    }
+
+    /// Whether the rule allows any command.
+    pub fn allows_any_command(&self) -> bool {
+        self.commands.iter().any(|command| command == "ALL")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADMIN: &str = "uid=1000\n\
+        Matching Defaults entries for deploy on web-1:\n    env_reset, secure_path=/usr/sbin\\:/usr/bin\n\n\
+        User deploy may run the following commands on web-1:\n\
+        \x20   (root) NOPASSWD: /usr/bin/systemctl restart nginx, /usr/bin/journalctl\n\
+        \x20   (ALL : ALL) ALL\n\
+        sudo_exit=0\n";
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn test_rules_and_tags() {
+            let report = parse_privileges(ADMIN);
+            assert_eq!(report.uid, Some(1000));
+            assert_eq!(report.access, SudoAccess::Allowed);
+            assert_eq!(report.rules.len(), 2);
+            assert_eq!(report.rules[0].run_as, "root");
+            assert!(report.rules[0].nopasswd);
+            assert_eq!(
+                report.rules[0].commands,
+                vec!["/usr/bin/systemctl restart nginx", "/usr/bin/journalctl"]
+            );
+            assert_eq!(report.rules[1].run_as, "ALL : ALL");
+            assert!(report.full_root());
+            assert!(!report.passwordless_root());
+            assert_eq!(report.detail, None);
+        }
+
+        #[test]
+        fn test_passwordless_root() {
+            let report = parse_privileges(
+                "uid=1001\nUser ci may run the following commands on b:\n    (ALL) NOPASSWD: ALL\nsudo_exit=0\n",
+            );
+            assert!(report.passwordless_root());
+        }
+
+        #[test]
+        fn test_password_required() {
+            let report = parse_privileges("uid=1000\nsudo: a password is required\nsudo_exit=1\n");
+            assert_eq!(report.access, SudoAccess::PasswordRequired);
+            assert!(report.rules.is_empty());
+            assert_eq!(
+                report.detail.as_deref(),
+                Some("sudo: a password is required")
+            );
+        }
+
+        #[test]
+        fn test_denied() {
+            let report = parse_privileges(
+                "uid=1000\nSorry, user guest may not run sudo on web-1.\nsudo_exit=1\n",
+            );
+            assert_eq!(report.access, SudoAccess::Denied);
+            assert!(!report.full_root());
+        }
+
+        #[test]
+        fn test_missing_sudo_and_root() {
+            assert_eq!(
+                parse_privileges("uid=1000\nsudo_exit=missing\n").access,
+                SudoAccess::Unavailable
+            );
+            let root = parse_privileges("uid=0\nsudo_exit=missing\n");
+            assert_eq!(root.access, SudoAccess::Unavailable);
+            assert!(root.full_root());
+            assert_eq!(
+                parse_privileges("uid=0\nsudo_exit=1\n").access,
+                SudoAccess::Root
+            );
+        }
+    }
+
+    mod rules {
+        use super::*;
+
+        #[test]
+        fn test_rule_without_run_as_defaults_to_root() {
+            let rule = parse_rule("NOPASSWD: SETENV: /usr/local/bin/backup");
+            assert_eq!(rule.run_as, "root");
+            assert_eq!(rule.tags, vec!["NOPASSWD", "SETENV"]);
+            assert_eq!(rule.commands, vec!["/usr/local/bin/backup"]);
+        }
+
+        #[test]
+        fn test_run_as_other_user_is_not_root() {
+            let rule = parse_rule("(postgres) ALL");
+            assert!(!rule.runs_as_root());
+            assert!(rule.allows_any_command());
+        }
+
+        #[test]
+        fn test_command_arguments_with_colons_are_kept() {
+            let rule = parse_rule("(root) /usr/bin/chown app:app /srv/app");
+            assert!(rule.tags.is_empty());
+            assert_eq!(rule.commands, vec!["/usr/bin/chown app:app /srv/app"]);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Named connection targets defined on the server (`ssh_connect(profile=...)`).
+//!
+//! Operators describe hosts once, so agents connect by name instead of
+//! repeating addresses, users, keys and bastions. Profiles come from
+//! `[profiles.targets.<name>]` in the server configuration and from the TOML
+//! file named by `[profiles] file`, whose top-level tables are profiles:
+//!
+//! ```toml
+//! # /etc/ssh-mcp/profiles.toml
+//! [staging-web]
+//! host = "10.0.1.20"
+//! port = 2222
+//! username = "deploy"
+//! key_path = "/etc/ssh-mcp/keys/staging"
+//! jump_hosts = ["ops@bastion.example.com"]
+//! connect_timeout_secs = 10
+//! command_timeout_secs = 600
+//! ```
+//!
+//! The file is read once at startup. Profiles hold no secrets: they
+//! authenticate with `key_path`, a `credential_ref` (see `credentials`) or the
+//! SSH agent, and `ssh_list_profiles` shows which without the key path.
+//! Arguments given to `ssh_connect` override the profile's values.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+use tracing::{error, info};
+
+use super::config::ssh_config::SshConfig;
+use super::config::{ProfileConfig, ProfilesConfig, server_config};
+use super::error::{ErrorCode, ToolError};
+use super::types::{JumpHost, ProfileInfo};
+
+/// The server's profiles, loaded on first use.
+static PROFILES: Lazy<Result<BTreeMap<String, ProfileConfig>, String>> = Lazy::new(|| {
+    let profiles = load_profiles(&server_config().profiles);
+    match &profiles {
+        Ok(profiles) if !profiles.is_empty() => {
+            info!("Loaded {} connection profile(s)", profiles.len())
+        }
+        Ok(_) => {}
+        Err(e) => error!("Connection profiles invalid; none are available: {}", e),
+    }
+    profiles
+});
+
+/// Inline profiles of `config` followed by those of its profile file.
+pub(crate) fn load_profiles(
+    config: &ProfilesConfig,
+) -> Result<BTreeMap<String, ProfileConfig>, String> {
+    let mut profiles = config.targets.clone();
+    if let Some(path) = &config.file {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read profile file {}: {}", path, e))?;
+        let file: BTreeMap<String, ProfileConfig> =
+            toml::from_str(&text).map_err(|e| format!("profile file {}: {}", path, e))?;
+        for (name, profile) in file {
+            if profiles.contains_key(&name) {
+                return Err(format!(
+                    "profile '{}' is defined both inline and in {}",
+                    name, path
+                ));
+            }
+            profiles.insert(name, profile);
+        }
+    }
+    if let Some((name, _)) = profiles
+        .iter()
+        .find(|(_, profile)| profile.host.trim().is_empty())
+    {
+        return Err(format!("profile '{}' has no host", name));
+    }
+    Ok(profiles)
+}
+
+/// The loaded profiles, or an `invalid_state` error when they could not be loaded.
+pub(crate) fn profiles() -> Result<&'static BTreeMap<String, ProfileConfig>, ToolError> {
+    PROFILES.as_ref().map_err(|e| {
+        ToolError::new(
+            ErrorCode::InvalidState,
+            format!("Connection profiles could not be loaded: {}", e),
+        )
+    })
+}
+
+/// The profile called `name`.
+pub(crate) fn find_profile(name: &str) -> Result<&'static ProfileConfig, ToolError> {
+    let profiles = profiles()?;
+    profiles.get(name).ok_or_else(|| {
+        ToolError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unknown profile '{}'", name),
+        )
+        .with_detail("profile", name)
+        .with_detail("available", profiles.keys().cloned().collect::<Vec<_>>())
+    })
+}
+
+/// `host:port` address of a profile, with IPv6 literals in brackets.
+pub(crate) fn profile_address(profile: &ProfileConfig) -> String {
+    let host = profile.host.trim();
+    let port = profile.port.unwrap_or(22);
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Jump hosts of a profile, without credentials of their own.
+pub(crate) fn profile_jump_hosts(profile: &ProfileConfig) -> Vec<JumpHost> {
+    SshConfig::default().jump_hosts(&profile.jump_hosts.join(","))
+}
+
+/// What ssh_list_profiles shows of a profile.
+pub(crate) fn profile_info(name: &str, profile: &ProfileConfig) -> ProfileInfo {
+    let auth = if profile.credential_ref.is_some() {
+        "credential_ref"
+    } else if profile.key_path.is_some() {
+        "key_path"
+    } else {
+        "agent"
+    };
+    ProfileInfo {
+        name: name.to_string(),
+        address: profile_address(profile),
+        username: profile.username.clone(),
+        auth: auth.to_string(),
+        credential_ref: profile.credential_ref.clone(),
+        jump_hosts: profile_jump_hosts(profile)
+            .iter()
+            .map(|hop| hop.label(profile.username.as_deref().unwrap_or("")))
+            .collect(),
+        connect_timeout_secs: profile.connect_timeout_secs,
+        command_timeout_secs: profile.command_timeout_secs,
+        region: profile.region.clone(),
+        datacenter: profile.datacenter.clone(),
+        description: profile.description.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> ProfilesConfig {
+        crate::mcp::config::ServerConfig::from_toml(toml)
+            .unwrap()
+            .profiles
+    }
+
+    #[test]
+    fn test_file_profiles_follow_inline_ones() {
+        let path = std::env::temp_dir().join(format!("profiles-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "[staging-web]\nhost = \"10.0.1.20\"\nport = 2222\n\n[db]\nhost = \"fd00::5\"\n",
+        )
+        .unwrap();
+        let mut config = parse("[profiles.targets.bastion]\nhost = \"bastion.example.com\"");
+        config.file = Some(path.display().to_string());
+
+        let profiles = load_profiles(&config).unwrap();
+        assert_eq!(
+            profiles.keys().collect::<Vec<_>>(),
+            vec!["bastion", "db", "staging-web"]
+        );
+        assert_eq!(profile_address(&profiles["staging-web"]), "10.0.1.20:2222");
+        assert_eq!(profile_address(&profiles["db"]), "[fd00::5]:22");
+
+        config
+            .targets
+            .insert("db".to_string(), profiles["db"].clone());
+        assert!(load_profiles(&config).unwrap_err().contains("both inline"));
+
+        std::fs::write(&path, "[broken]\nhost = \"h\"\npassword = \"p\"\n").unwrap();
+        config.targets.clear();
+        assert!(load_profiles(&config).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(
+            load_profiles(&parse("[profiles.targets.x]\nport = 22"))
+                .unwrap_err()
+                .contains("no host")
+        );
+    }
+
+    #[test]
+    fn test_info_hides_key_path() {
+        let profile = ProfileConfig {
+            host: "10.0.1.20".to_string(),
+            username: Some("deploy".to_string()),
+            key_path: Some("/etc/ssh-mcp/keys/staging".to_string()),
+            jump_hosts: vec!["ops@bastion:2200".to_string(), "gw".to_string()],
+            command_timeout_secs: Some(600),
+            ..Default::default()
+        };
+        let info = profile_info("staging-web", &profile);
+        assert_eq!(info.auth, "key_path");
+        assert_eq!(info.jump_hosts, vec!["ops@bastion:2200", "deploy@gw:22"]);
+        assert_eq!(info.command_timeout_secs, Some(600));
+        let json = serde_json::to_string(&info).unwrap();
+        assert!(!json.contains("/etc/ssh-mcp/keys"));
+
+        let info = profile_info(
+            "db",
+            &ProfileConfig {
+                credential_ref: Some("prod-db".to_string()),
+                ..profile
+            },
+        );
+        assert_eq!(info.auth, "credential_ref");
+        assert_eq!(info.credential_ref.as_deref(), Some("prod-db"));
+    }
+}
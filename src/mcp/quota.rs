@@ -0,0 +1,148 @@
+//! Global and per-agent session quotas.
+//!
+//! Every SSH session holds a socket and a connection task; one misbehaving
+//! agent that connects in a loop can exhaust the server's file descriptors
+//! for everyone. `SSH_MAX_SESSIONS` caps the sessions of the whole server and
+//! `SSH_MAX_SESSIONS_PER_AGENT` those of each `agent_id`; calls without an
+//! agent_id share the [`ANONYMOUS_AGENT`] quota. Both are unlimited by default.
+//!
+//! The total counts every open connection: sessions, spares of managed host
+//! pools and connections parked in the idle pool. An agent's count covers its
+//! sessions and parked connections; managed hosts belong to the server and do
+//! not count against any agent. Like per-host caps, `ssh_connect` reserves its
+//! place before dialling, so concurrent connects cannot overshoot a quota.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+
+use super::accounting::{ANONYMOUS_AGENT, billed_agent};
+use super::config::{resolve_max_sessions, resolve_max_sessions_per_agent};
+use super::error::{ErrorCode, ToolError};
+use super::host_limit::{HostReservation, HostSlots};
+use super::managed::{find_managed_host, pooled_total};
+use super::pool::idle_owners;
+use super::storage::SESSION_STORAGE;
+
+/// Connects in flight, under [`ALL_SESSIONS`] and per agent.
+static QUOTA_SLOTS: Lazy<HostSlots> = Lazy::new(HostSlots::new);
+
+/// Slot key of the server-wide quota; agent keys are prefixed with `agent:`
+const ALL_SESSIONS: &str = "*";
+
+/// Places held in the session quotas while a connect is in flight.
+pub(crate) struct QuotaReservation {
+    _total: Option<HostReservation<'static>>,
+    _agent: Option<HostReservation<'static>>,
+}
+
+/// Open connections per agent; server-owned connections are left out.
+fn sessions_by_agent<'a>(owners: impl Iterator<Item = Option<&'a str>>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for owner in owners {
+        *counts
+            .entry(billed_agent(owner, None).to_string())
+            .or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Human-readable per-agent counts, largest first: `a=3, b=1`.
+fn describe_counts(counts: &BTreeMap<String, usize>) -> String {
+    let mut sorted: Vec<_> = counts.iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    sorted
+        .iter()
+        .map(|(agent, count)| format!("{}={}", agent, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Reserve a place for a new session of `agent_id` in the session quotas.
+pub(crate) fn reserve_session_quota(agent_id: Option<&str>) -> Result<QuotaReservation, ToolError> {
+    let (total_limit, agent_limit) = (resolve_max_sessions(), resolve_max_sessions_per_agent());
+    if total_limit.is_none() && agent_limit.is_none() {
+        return Ok(QuotaReservation {
+            _total: None,
+            _agent: None,
+        });
+    }
+
+    let sessions = SESSION_STORAGE.list();
+    let idle = idle_owners();
+    let total = sessions.len() + pooled_total() + idle.len();
+    let by_agent = sessions_by_agent(
+        sessions
+            .iter()
+            .filter(|info| find_managed_host(&info.session_id).is_none())
+            .map(|info| info.agent_id.as_deref())
+            .chain(idle.iter().map(Option::as_deref)),
+    );
+    let agent = billed_agent(agent_id, None);
+
+    let total_slot = match total_limit {
+        Some(limit) => Some(QUOTA_SLOTS.reserve(ALL_SESSIONS, total, limit).map_err(
+            |in_use| {
+                ToolError::new(
+                    ErrorCode::LimitExceeded,
+                    format!(
+                        "Maximum sessions for this server reached ({}/{}). Sessions by agent: {}. Disconnect unused sessions first.",
+                        in_use,
+                        limit,
+                        describe_counts(&by_agent)
+                    ),
+                )
+                .with_detail("limit", limit)
+                .with_detail("sessions", in_use)
+                .with_detail("sessions_by_agent", serde_json::json!(by_agent))
+            },
+        )?),
+        None => None,
+    };
+    let agent_slot = match agent_limit {
+        Some(limit) => {
+            let active = by_agent.get(agent).copied().unwrap_or(0);
+            Some(
+                QUOTA_SLOTS
+                    .reserve(&format!("agent:{}", agent), active, limit)
+                    .map_err(|in_use| {
+                        let whose = match agent_id {
+                            Some(_) => format!("agent {}", agent),
+                            None => format!("calls without agent_id ({})", ANONYMOUS_AGENT),
+                        };
+                        ToolError::new(
+                            ErrorCode::LimitExceeded,
+                            format!(
+                                "Maximum sessions for {} reached ({}/{}). Reuse or disconnect one of its sessions first (ssh_list_sessions, ssh_disconnect_agent).",
+                                whose, in_use, limit
+                            ),
+                        )
+                        .with_detail("agent_id", agent)
+                        .with_detail("limit", limit)
+                        .with_detail("sessions", in_use)
+                    })?,
+            )
+        }
+        None => None,
+    };
+
+    Ok(QuotaReservation {
+        _total: total_slot,
+        _agent: agent_slot,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_by_agent() {
+        let counts =
+            sessions_by_agent([Some("a"), None, Some("b"), Some("a"), None, Some("a")].into_iter());
+        assert_eq!(counts["a"], 3);
+        assert_eq!(counts["b"], 1);
+        assert_eq!(counts[ANONYMOUS_AGENT], 2);
+        assert_eq!(describe_counts(&counts), "a=3, anonymous=2, b=1");
+    }
+}
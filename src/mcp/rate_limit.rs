@@ -0,0 +1,217 @@
+//! Bandwidth limits for transfers and forwards (`rate_limit_bytes_per_sec`).
+//!
+//! A [`Bandwidth`] paces the bytes of one operation to at most its rate in
+//! each direction, averaged over time: every chunk books its transfer time on
+//! the limit's schedule, and the next chunk waits until that time has passed.
+//! Nothing is saved up while the operation is idle, so a burst never exceeds
+//! one chunk (an SSH packet or a read buffer). Holding back the reader of a
+//! channel also stops its window from being extended, so the remote sender
+//! slows down instead of data piling up in memory.
+//!
+//! One limit covers a whole operation: all files of an `ssh_upload_dir`, and
+//! all connections of a forward, share it.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, ready};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// Smallest accepted `rate_limit_bytes_per_sec`
+pub(crate) const MIN_RATE_LIMIT: u64 = 1024;
+
+/// Pacing schedule of one direction.
+#[derive(Debug)]
+pub(crate) struct RateLimit {
+    bytes_per_sec: u64,
+    /// When the bytes booked so far have been transferred
+    next: Mutex<Instant>,
+}
+
+impl RateLimit {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Book `bytes` moved at `now`; the time to wait before the next chunk.
+    fn reserve_at(&self, bytes: usize, now: Instant) -> Duration {
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let start = (*next).max(now);
+        *next = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        next.saturating_duration_since(now)
+    }
+
+    fn reserve(&self, bytes: usize) -> Duration {
+        self.reserve_at(bytes, Instant::now())
+    }
+
+    /// Book `bytes` just moved and wait until the rate allows more.
+    pub(crate) async fn pace(&self, bytes: usize) {
+        let delay = self.reserve(bytes);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Rate limit of one operation, applied separately to reads and writes.
+#[derive(Debug)]
+pub(crate) struct Bandwidth {
+    pub read: RateLimit,
+    pub write: RateLimit,
+}
+
+impl Bandwidth {
+    #[cfg_attr(not(feature = "port_forward"), allow(dead_code))]
+    pub(crate) fn bytes_per_sec(&self) -> u64 {
+        self.read.bytes_per_sec
+    }
+}
+
+/// Resolve `rate_limit_bytes_per_sec`; `None` means unlimited.
+pub(crate) fn resolve_rate_limit(
+    bytes_per_sec: Option<u64>,
+) -> Result<Option<Arc<Bandwidth>>, String> {
+    match bytes_per_sec {
+        None => Ok(None),
+        Some(rate) if rate < MIN_RATE_LIMIT => Err(format!(
+            "rate_limit_bytes_per_sec must be at least {}",
+            MIN_RATE_LIMIT
+        )),
+        Some(rate) => Ok(Some(Arc::new(Bandwidth {
+            read: RateLimit::new(rate),
+            write: RateLimit::new(rate),
+        }))),
+    }
+}
+
+/// A stream whose reads and writes are paced by a [`Bandwidth`]; without one
+/// it passes everything through.
+pub(crate) struct Throttled<S> {
+    inner: S,
+    bandwidth: Option<Arc<Bandwidth>>,
+    read_pause: Option<Pin<Box<Sleep>>>,
+    write_pause: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Throttled<S> {
+    pub(crate) fn new(inner: S, bandwidth: Option<Arc<Bandwidth>>) -> Self {
+        Self {
+            inner,
+            bandwidth,
+            read_pause: None,
+            write_pause: None,
+        }
+    }
+}
+
+/// Wait out `pause`, if any.
+fn poll_pause(pause: &mut Option<Pin<Box<Sleep>>>, cx: &mut Context<'_>) -> Poll<()> {
+    if let Some(sleep) = pause {
+        ready!(sleep.as_mut().poll(cx));
+        *pause = None;
+    }
+    Poll::Ready(())
+}
+
+/// The pause owed after moving `bytes` under `limit`.
+fn pause_after(limit: &RateLimit, bytes: usize) -> Option<Pin<Box<Sleep>>> {
+    let delay = limit.reserve(bytes);
+    (!delay.is_zero()).then(|| Box::pin(tokio::time::sleep(delay)))
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Throttled<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(poll_pause(&mut this.read_pause, cx));
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        if let Some(bandwidth) = &this.bandwidth {
+            this.read_pause = pause_after(&bandwidth.read, buf.filled().len() - before);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Throttled<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(poll_pause(&mut this.write_pause, cx));
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        if let Some(bandwidth) = &this.bandwidth {
+            this.write_pause = pause_after(&bandwidth.write, written);
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_resolve_rate_limit() {
+        assert!(resolve_rate_limit(None).unwrap().is_none());
+        assert!(resolve_rate_limit(Some(MIN_RATE_LIMIT - 1)).is_err());
+        let bandwidth = resolve_rate_limit(Some(1_048_576)).unwrap().unwrap();
+        assert_eq!(bandwidth.bytes_per_sec(), 1_048_576);
+    }
+
+    #[test]
+    fn test_reserve_paces_without_saving_up() {
+        let limit = RateLimit::new(1000);
+        let start = Instant::now();
+        assert_eq!(limit.reserve_at(500, start), Duration::from_millis(500));
+        // Booked back to back: the second chunk ends a second after the start
+        assert_eq!(limit.reserve_at(500, start), Duration::from_secs(1));
+        // After an idle period the schedule restarts from now
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limit.reserve_at(250, later), Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn test_throttled_stream_limits_writes() {
+        let bandwidth = resolve_rate_limit(Some(MIN_RATE_LIMIT)).unwrap();
+        let (near, mut far) = tokio::io::duplex(4096);
+        let mut stream = Throttled::new(near, bandwidth);
+
+        let start = Instant::now();
+        stream.write_all(&[0u8; 256]).await.unwrap();
+        stream.write_all(&[0u8; 256]).await.unwrap();
+        // The second write waited for the first quarter second
+        assert!(start.elapsed() >= Duration::from_millis(240));
+
+        let mut buf = [0u8; 512];
+        far.read_exact(&mut buf).await.unwrap();
+        // Unlimited streams pass through at once
+        let (near, _far) = tokio::io::duplex(4096);
+        let mut stream = Throttled::new(near, None);
+        let start = Instant::now();
+        stream.write_all(&[0u8; 2048]).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+}
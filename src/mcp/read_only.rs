@@ -0,0 +1,1063 @@
+//! Read-only mode: refuse commands and tools that change the remote host.
+//!
+//! A session is read-only when `ssh_connect` is called with `read_only=true`,
+//! or for every session when the server runs with `SSH_READ_ONLY=true`; the
+//! server-wide setting cannot be lifted per session, and it also hides the
+//! [`WRITE_TOOLS`] from clients.
+//!
+//! On a read-only session the write tools are refused and every command run by
+//! `ssh_execute`, `ssh_execute_sync`, an `ssh_execute_dag` node or an
+//! `ssh_execute_batch` target is classified before it starts. It is refused
+//! when any of its parts (pipeline stages, `;`/`&&`/`||` lists, `$(...)`
+//! substitutions, `sh -c` scripts):
+//!
+//! - redirects output into a file (`>`, `>>`, `&>`; `/dev/null` excepted);
+//! - runs a command that changes files, packages, services, processes, users,
+//!   mounts or the system, such as `rm`, `sed -i`, `apt install`,
+//!   `systemctl restart`, `kill` or `git push`;
+//! - runs an interpreter on a script or inline code, which cannot be inspected.
+//!
+//! Unknown commands are allowed, so the classifier guards against accidental
+//! changes rather than a determined caller: for hard guarantees connect with an
+//! account that has no write access.
+
+use super::config::resolve_read_only;
+use super::error::{ErrorCode, ToolError};
+use super::types::SessionInfo;
+
+/// Tools refused on read-only sessions and hidden under `SSH_READ_ONLY`.
+pub const WRITE_TOOLS: &[&str] = &[
+    "ssh_shell_write",
+    "ssh_shell_switch_user",
+    "ssh_upload_dir",
+    "ssh_copy_between",
+    "ssh_symlink",
+    "ssh_write_file",
+    "ssh_patch_file",
+    "ssh_run_helper",
+    "ssh_reboot_and_wait",
+];
+
+/// Whether commands and write tools are restricted on the session.
+pub(crate) fn is_read_only(info: &SessionInfo) -> bool {
+    info.read_only || resolve_read_only(None)
+}
+
+/// Refuse `tool` on a read-only session.
+pub(crate) fn check_tool(info: &SessionInfo, tool: &str) -> Result<(), ToolError> {
+    if !is_read_only(info) {
+        return Ok(());
+    }
+    Err(ToolError::new(
+        ErrorCode::PolicyViolation,
+        format!(
+            "Session {} is read-only; {} is disabled",
+            info.session_id, tool
+        ),
+    )
+    .with_detail("session_id", info.session_id.as_str())
+    .with_detail("tool", tool)
+    .with_detail("read_only", true))
+}
+
+/// Refuse `command` on a read-only session when it would change the host.
+pub(crate) fn check_command(info: &SessionInfo, command: &str) -> Result<(), ToolError> {
+    if !is_read_only(info) {
+        return Ok(());
+    }
+    match mutating_reason(command) {
+        None => Ok(()),
+        Some(reason) => Err(ToolError::new(
+            ErrorCode::PolicyViolation,
+            format!(
+                "Session {} is read-only; command {}",
+                info.session_id, reason
+            ),
+        )
+        .with_detail("command", command)
+        .with_detail("reason", reason)
+        .with_detail("read_only", true)),
+    }
+}
+
+/// Why `command` would change the host, or `None` when it only reads.
+pub(crate) fn mutating_reason(command: &str) -> Option<String> {
+    let parsed = parse(command);
+    if let Some(target) = parsed.writes.iter().find(|target| !is_sink(target)) {
+        return Some(format!("writes to '{}'", target));
+    }
+    parsed.segments.iter().find_map(|words| classify(words))
+}
+
+/// Redirection targets that discard or pass output through.
+fn is_sink(target: &str) -> bool {
+    matches!(
+        target,
+        "/dev/null" | "/dev/stdout" | "/dev/stderr" | "/dev/tty"
+    )
+}
+
+/// Simple commands of a command line and the files its output is redirected to.
+#[derive(Debug, Default)]
+struct Parsed {
+    segments: Vec<Vec<String>>,
+    writes: Vec<String>,
+}
+
+/// What the next completed word is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Word,
+    /// Target of an output redirection
+    Write,
+    /// Input file or here-document delimiter
+    Input,
+}
+
+struct Parser {
+    parsed: Parsed,
+    segment: Vec<String>,
+    word: String,
+    in_word: bool,
+    pending: Pending,
+}
+
+impl Parser {
+    fn end_word(&mut self) {
+        if !self.in_word {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        self.in_word = false;
+        match std::mem::replace(&mut self.pending, Pending::Word) {
+            Pending::Word => self.segment.push(word),
+            Pending::Write => self.parsed.writes.push(word),
+            Pending::Input => {}
+        }
+    }
+
+    fn end_segment(&mut self) {
+        self.end_word();
+        if !self.segment.is_empty() {
+            let segment = std::mem::take(&mut self.segment);
+            self.parsed.segments.push(segment);
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        self.word.push(c);
+        self.in_word = true;
+    }
+}
+
+/// Split `command` into simple commands with quotes removed, collecting
+/// redirection targets on the way. Substitutions become commands of their own.
+fn parse(command: &str) -> Parsed {
+    let mut parser = Parser {
+        parsed: Parsed::default(),
+        segment: Vec::new(),
+        word: String::new(),
+        in_word: false,
+        pending: Pending::Word,
+    };
+    // Quoting state outside each open `$(` or backtick substitution
+    let mut outer: Vec<bool> = Vec::new();
+    let mut backtick = false;
+    let (mut single, mut double) = (false, false);
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if single {
+            match c {
+                '\'' => single = false,
+                _ => parser.push(c),
+            }
+            continue;
+        }
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next()
+                    && next != '\n'
+                {
+                    parser.push(next);
+                }
+            }
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                parser.end_segment();
+                outer.push(double);
+                double = false;
+            }
+            '`' => {
+                parser.end_segment();
+                if backtick {
+                    double = outer.pop().unwrap_or(false);
+                } else {
+                    outer.push(double);
+                    double = false;
+                }
+                backtick = !backtick;
+            }
+            '"' => {
+                double = !double;
+                parser.in_word = true;
+            }
+            _ if double => parser.push(c),
+            '\'' => {
+                single = true;
+                parser.in_word = true;
+            }
+            ')' if !outer.is_empty() => {
+                parser.end_segment();
+                double = outer.pop().unwrap_or(false);
+            }
+            '>' => {
+                // A word of digits right before `>` is the redirected descriptor
+                if parser.in_word && parser.word.chars().all(|c| c.is_ascii_digit()) {
+                    parser.word.clear();
+                    parser.in_word = false;
+                }
+                parser.end_word();
+                if matches!(chars.peek(), Some('>' | '|')) {
+                    chars.next();
+                }
+                if chars.peek() == Some(&'&') {
+                    // Descriptor duplication such as 2>&1
+                    chars.next();
+                    while chars
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_digit() || *c == '-')
+                    {
+                        chars.next();
+                    }
+                } else {
+                    parser.pending = Pending::Write;
+                }
+            }
+            '&' if chars.peek() == Some(&'>') => {
+                parser.end_word();
+                chars.next();
+                if chars.peek() == Some(&'>') {
+                    chars.next();
+                }
+                parser.pending = Pending::Write;
+            }
+            '<' => {
+                parser.end_word();
+                while matches!(chars.peek(), Some('<' | '&' | '-')) {
+                    chars.next();
+                }
+                parser.pending = Pending::Input;
+            }
+            ';' | '&' | '|' | '\n' | '(' | ')' => parser.end_segment(),
+            c if c.is_whitespace() => parser.end_word(),
+            _ => parser.push(c),
+        }
+    }
+    parser.end_segment();
+    parser.parsed
+}
+
+/// Commands that change the host whatever their arguments.
+const ALWAYS_MUTATING: &[&str] = &[
+    "rm",
+    "rmdir",
+    "unlink",
+    "mv",
+    "cp",
+    "install",
+    "dd",
+    "shred",
+    "truncate",
+    "touch",
+    "mkdir",
+    "mknod",
+    "mkfifo",
+    "ln",
+    "chmod",
+    "chown",
+    "chgrp",
+    "chattr",
+    "setfacl",
+    "patch",
+    "rsync",
+    "scp",
+    "wget",
+    "fallocate",
+    "vi",
+    "vim",
+    "nvim",
+    "nano",
+    "emacs",
+    "ed",
+    "fdisk",
+    "sfdisk",
+    "parted",
+    "mkswap",
+    "swapon",
+    "swapoff",
+    "mount",
+    "umount",
+    "losetup",
+    "wipefs",
+    "reboot",
+    "shutdown",
+    "halt",
+    "poweroff",
+    "init",
+    "telinit",
+    "kexec",
+    "kill",
+    "killall",
+    "pkill",
+    "useradd",
+    "userdel",
+    "usermod",
+    "groupadd",
+    "groupdel",
+    "groupmod",
+    "adduser",
+    "deluser",
+    "passwd",
+    "chpasswd",
+    "chsh",
+    "visudo",
+    "modprobe",
+    "rmmod",
+    "insmod",
+    "update-alternatives",
+    "ldconfig",
+    "a2enmod",
+    "a2dismod",
+    "a2ensite",
+    "a2dissite",
+    "unzip",
+    "logrotate",
+];
+
+/// Interpreters whose script or inline code cannot be inspected.
+const INTERPRETERS: &[&str] = &[
+    "sh", "bash", "dash", "zsh", "ksh", "fish", "python", "python2", "python3", "perl", "ruby",
+    "node", "php", "lua", "awk", "gawk", "mawk", "nawk",
+];
+
+/// Compressors that replace the file they are given unless told to write to
+/// standard output, test or list.
+const COMPRESSORS: &[&str] = &[
+    "gzip", "gunzip", "xz", "unxz", "lzma", "unlzma", "bzip2", "bunzip2", "zstd", "unzstd",
+];
+
+/// Commands taking a command to run, with their options that take a value.
+const WRAPPERS: &[(&str, &[&str])] = &[
+    (
+        "sudo",
+        &["-u", "-g", "-h", "-p", "-C", "-D", "-r", "-t", "-U"],
+    ),
+    ("doas", &["-u", "-C"]),
+    ("env", &["-u", "-C", "-S"]),
+    ("nohup", &[]),
+    ("time", &["-f", "-o"]),
+    ("command", &[]),
+    ("exec", &["-a"]),
+    ("builtin", &[]),
+    ("nice", &["-n"]),
+    ("ionice", &["-c", "-n", "-p"]),
+    ("stdbuf", &["-i", "-o", "-e"]),
+    ("timeout", &["-s", "-k"]),
+    ("watch", &["-n"]),
+    ("xargs", &["-I", "-n", "-P", "-L", "-d", "-E", "-s", "-a"]),
+    ("chroot", &[]),
+];
+
+/// Package managers and their subcommands that change the system.
+const PACKAGE_MANAGERS: &[(&str, &[&str])] = &[
+    (
+        "apt",
+        &[
+            "install",
+            "remove",
+            "purge",
+            "upgrade",
+            "full-upgrade",
+            "dist-upgrade",
+            "update",
+            "autoremove",
+            "reinstall",
+            "edit-sources",
+        ],
+    ),
+    (
+        "apt-get",
+        &[
+            "install",
+            "remove",
+            "purge",
+            "upgrade",
+            "dist-upgrade",
+            "update",
+            "autoremove",
+            "build-dep",
+            "clean",
+            "autoclean",
+        ],
+    ),
+    (
+        "aptitude",
+        &[
+            "install",
+            "remove",
+            "purge",
+            "upgrade",
+            "safe-upgrade",
+            "full-upgrade",
+            "update",
+        ],
+    ),
+    (
+        "yum",
+        &[
+            "install",
+            "remove",
+            "erase",
+            "update",
+            "upgrade",
+            "downgrade",
+            "reinstall",
+            "autoremove",
+            "groupinstall",
+            "localinstall",
+        ],
+    ),
+    (
+        "dnf",
+        &[
+            "install",
+            "remove",
+            "erase",
+            "update",
+            "upgrade",
+            "downgrade",
+            "reinstall",
+            "autoremove",
+            "distro-sync",
+        ],
+    ),
+    (
+        "zypper",
+        &[
+            "install",
+            "in",
+            "remove",
+            "rm",
+            "update",
+            "up",
+            "dist-upgrade",
+            "dup",
+            "patch",
+        ],
+    ),
+    ("apk", &["add", "del", "upgrade", "update", "fix"]),
+    (
+        "brew",
+        &[
+            "install",
+            "uninstall",
+            "remove",
+            "upgrade",
+            "update",
+            "reinstall",
+            "link",
+            "unlink",
+        ],
+    ),
+    (
+        "snap",
+        &[
+            "install", "remove", "refresh", "revert", "enable", "disable",
+        ],
+    ),
+    ("flatpak", &["install", "uninstall", "update"]),
+    ("pip", &["install", "uninstall", "download"]),
+    ("pip3", &["install", "uninstall", "download"]),
+    (
+        "npm",
+        &[
+            "install",
+            "i",
+            "uninstall",
+            "remove",
+            "update",
+            "ci",
+            "link",
+            "publish",
+        ],
+    ),
+    ("yarn", &["add", "remove", "install", "upgrade", "publish"]),
+    ("gem", &["install", "uninstall", "update"]),
+    ("cargo", &["install", "uninstall", "build", "publish"]),
+];
+
+/// Subcommands of service managers that change the system.
+const SYSTEMCTL_WRITES: &[&str] = &[
+    "start",
+    "stop",
+    "restart",
+    "try-restart",
+    "reload",
+    "reload-or-restart",
+    "enable",
+    "disable",
+    "reenable",
+    "mask",
+    "unmask",
+    "kill",
+    "daemon-reload",
+    "daemon-reexec",
+    "isolate",
+    "set-property",
+    "edit",
+    "link",
+    "revert",
+    "preset",
+    "reboot",
+    "poweroff",
+    "halt",
+    "suspend",
+    "hibernate",
+    "set-default",
+    "reset-failed",
+];
+
+const GIT_WRITES: &[&str] = &[
+    "push",
+    "commit",
+    "reset",
+    "checkout",
+    "switch",
+    "restore",
+    "merge",
+    "rebase",
+    "pull",
+    "fetch",
+    "clean",
+    "stash",
+    "rm",
+    "mv",
+    "add",
+    "apply",
+    "am",
+    "cherry-pick",
+    "revert",
+    "init",
+    "clone",
+    "gc",
+    "prune",
+];
+
+/// Read-only subcommands of container tools; any other subcommand is a change.
+const CONTAINER_READS: &[&str] = &[
+    "ps", "images", "inspect", "logs", "version", "info", "stats", "top", "port", "history",
+    "events", "diff", "search",
+];
+
+const KUBECTL_READS: &[&str] = &[
+    "get",
+    "describe",
+    "logs",
+    "top",
+    "explain",
+    "version",
+    "api-resources",
+    "api-versions",
+    "cluster-info",
+    "auth",
+    "events",
+    "diff",
+];
+
+/// Options of git, kubectl and container tools that take a value.
+const GLOBAL_OPTIONS: &[&str] = &[
+    "-C",
+    "-c",
+    "-n",
+    "--namespace",
+    "--context",
+    "--kubeconfig",
+    "-H",
+    "--host",
+    "--git-dir",
+    "--work-tree",
+];
+
+/// Keywords and grouping words in front of a command.
+const KEYWORDS: &[&str] = &[
+    "{", "}", "!", "if", "then", "else", "elif", "do", "while", "until",
+];
+
+/// The subcommand of `args`: the first argument that is neither an option
+/// nor the value of one of [`GLOBAL_OPTIONS`].
+fn subcommand<'a>(args: &[&'a str]) -> Option<&'a str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if GLOBAL_OPTIONS.contains(arg) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Why a command run by `find -exec` (or `-execdir`, `-ok`) would change the host.
+fn find_exec_reason(args: &[&str]) -> Option<String> {
+    let mut rest = args;
+    while let Some(index) = rest
+        .iter()
+        .position(|arg| matches!(*arg, "-exec" | "-execdir" | "-ok" | "-okdir"))
+    {
+        let tail = &rest[index + 1..];
+        let end = tail
+            .iter()
+            .position(|arg| matches!(*arg, ";" | "+"))
+            .unwrap_or(tail.len());
+        let words: Vec<String> = tail[..end]
+            .iter()
+            .filter(|arg| **arg != "{}")
+            .map(|arg| arg.to_string())
+            .collect();
+        if let Some(reason) = classify(&words) {
+            return Some(reason);
+        }
+        rest = &tail[end..];
+    }
+    None
+}
+
+/// Why the simple command `words` would change the host.
+fn classify(words: &[String]) -> Option<String> {
+    let words = strip_prefixes(words);
+    let (program, args) = words.split_first()?;
+    let name = program.rsplit('/').next().unwrap_or(program);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let first = subcommand(&args);
+    let any = |flags: &[&str]| args.iter().any(|arg| flags.contains(arg));
+    let changes = |what: &str| Some(format!("'{}' {}", name, what));
+
+    if ALWAYS_MUTATING.contains(&name) || name.starts_with("mkfs") {
+        return changes("changes the host");
+    }
+    if let Some((_, writes)) = PACKAGE_MANAGERS
+        .iter()
+        .find(|(manager, _)| *manager == name)
+    {
+        return first
+            .filter(|sub| writes.contains(sub))
+            .and_then(|sub| changes(&format!("{} changes installed packages", sub)));
+    }
+    match name {
+        "sed" | "perl" if args.iter().any(|arg| is_in_place(arg)) => {
+            return changes("edits files in place");
+        }
+        "find" if any(&["-delete", "-fprint", "-fprint0", "-fprintf", "-fls"]) => {
+            return changes("deletes or writes files");
+        }
+        "find" => return find_exec_reason(&args),
+        "sh" | "bash" | "dash" | "zsh" | "ksh" | "su" => {
+            if let Some(index) = args.iter().position(|arg| is_short_flag(arg, 'c')) {
+                let script = args.get(index + 1)?;
+                return mutating_reason(script);
+            }
+        }
+        _ => {}
+    }
+    if INTERPRETERS.contains(&name) && !any(&["--version", "-V", "--help"]) {
+        return changes("runs a script that cannot be inspected");
+    }
+
+    let reason = match name {
+        "systemctl" | "service" | "rc-service" => args
+            .iter()
+            .any(|arg| SYSTEMCTL_WRITES.contains(arg))
+            .then_some("changes services"),
+        "git" => first
+            .filter(|sub| GIT_WRITES.contains(sub))
+            .map(|_| "changes a repository"),
+        "docker" | "podman" | "nerdctl" | "crictl" => first
+            .filter(|sub| !CONTAINER_READS.contains(sub))
+            .map(|_| "changes containers"),
+        "kubectl" | "oc" => first
+            .filter(|sub| !KUBECTL_READS.contains(sub))
+            .map(|_| "changes cluster resources"),
+        "pacman" => args
+            .iter()
+            .any(|arg| {
+                arg.starts_with("-R")
+                    || arg.starts_with("-U")
+                    || (arg.starts_with("-S") && !arg.contains(['s', 'i', 'l', 'g']))
+            })
+            .then_some("changes installed packages"),
+        "dpkg" => args
+            .iter()
+            .any(|arg| {
+                matches!(
+                    *arg,
+                    "-i" | "-r" | "-P" | "--install" | "--remove" | "--purge"
+                ) || matches!(*arg, "--configure" | "--unpack")
+            })
+            .then_some("changes installed packages"),
+        "rpm" => args
+            .iter()
+            .any(|arg| {
+                (arg.starts_with('-')
+                    && !arg.starts_with("--")
+                    && arg[1..].starts_with(['i', 'e', 'U', 'F']))
+                    || matches!(*arg, "--install" | "--erase" | "--upgrade" | "--freshen")
+            })
+            .then_some("changes installed packages"),
+        "tee" => args
+            .iter()
+            .any(|arg| !arg.starts_with('-') && !is_sink(arg))
+            .then_some("writes files"),
+        "curl" => any(&[
+            "-o",
+            "-O",
+            "--output",
+            "--remote-name",
+            "-T",
+            "--upload-file",
+            "-X",
+            "--request",
+            "-d",
+            "--data",
+            "--data-binary",
+            "-F",
+            "--form",
+        ])
+        .then_some("writes files or sends requests that may change state"),
+        "crontab" => (!any(&["-l"])).then_some("changes scheduled jobs"),
+        "sysctl" => args
+            .iter()
+            .any(|arg| *arg == "-w" || *arg == "-p" || arg.contains('='))
+            .then_some("changes kernel parameters"),
+        "iptables" | "ip6tables" => {
+            (!any(&["-L", "-S", "--list", "--list-rules"])).then_some("changes firewall rules")
+        }
+        "nft" => (first != Some("list")).then_some("changes firewall rules"),
+        "ufw" => (first != Some("status")).then_some("changes firewall rules"),
+        "firewall-cmd" => args
+            .iter()
+            .any(|arg| {
+                !["--list", "--get", "--query", "--state", "--zone"]
+                    .iter()
+                    .any(|read| arg.starts_with(read))
+            })
+            .then_some("changes firewall rules"),
+        "ip" => any(&[
+            "add", "del", "delete", "set", "change", "replace", "flush", "append",
+        ])
+        .then_some("changes network configuration"),
+        "hostnamectl" | "timedatectl" | "localectl" => first
+            .filter(|sub| sub.starts_with("set-"))
+            .map(|_| "changes system settings"),
+        "hostname" => first.map(|_| "changes the host name"),
+        "date" => any(&["-s", "--set"]).then_some("changes the clock"),
+        "journalctl" => args
+            .iter()
+            .any(|arg| arg.starts_with("--vacuum-") || *arg == "--rotate")
+            .then_some("removes or rotates journal files"),
+        "tar" => tar_writes(&args).then_some("extracts or writes archives"),
+        _ if COMPRESSORS.contains(&name) => {
+            compresses_in_place(&args).then_some("compresses or decompresses files in place")
+        }
+        _ => None,
+    };
+    reason.and_then(changes)
+}
+
+/// Whether a compressor given `args` replaces files rather than streaming,
+/// testing or listing them.
+fn compresses_in_place(args: &[&str]) -> bool {
+    let streams = args.iter().any(|arg| {
+        matches!(*arg, "--stdout" | "--to-stdout" | "--test" | "--list")
+            || ['c', 't', 'l'].iter().any(|flag| is_short_flag(arg, *flag))
+    });
+    !streams && args.iter().any(|arg| !arg.starts_with('-'))
+}
+
+/// Whether tar given `args` extracts files or writes an archive file.
+fn tar_writes(args: &[&str]) -> bool {
+    let mut extract = false;
+    let mut create = false;
+    let mut file = None;
+    for (index, arg) in args.iter().enumerate() {
+        let next = args.get(index + 1).copied();
+        if let Some(long) = arg.strip_prefix("--") {
+            match long.split_once('=') {
+                Some(("file", value)) => file = Some(value),
+                _ if long == "file" => file = next,
+                _ => {}
+            }
+            extract |= matches!(long, "extract" | "get");
+            create |= matches!(
+                long,
+                "create" | "append" | "update" | "delete" | "concatenate" | "catenate"
+            );
+        } else if arg.starts_with('-') || index == 0 {
+            // Bundled options, with or without a dash in the old style
+            let flags = arg.trim_start_matches('-');
+            if !flags.chars().all(|c| c.is_ascii_alphabetic()) {
+                continue;
+            }
+            extract |= flags.contains('x');
+            create |= flags.contains(['c', 'r', 'u', 'A']);
+            if flags.contains('f') {
+                file = next;
+            }
+        }
+    }
+    extract || (create && file.is_some_and(|file| file != "-" && !is_sink(file)))
+}
+
+/// Drop variable assignments and wrappers such as `sudo` or `xargs` in front of
+/// the command they run.
+fn strip_prefixes(mut words: &[String]) -> &[String] {
+    loop {
+        let Some((first, rest)) = words.split_first() else {
+            return words;
+        };
+        if is_assignment(first) || KEYWORDS.contains(&first.as_str()) {
+            words = rest;
+            continue;
+        }
+        let name = first.rsplit('/').next().unwrap_or(first);
+        let Some((wrapper, takes_value)) = WRAPPERS.iter().find(|(wrapper, _)| *wrapper == name)
+        else {
+            return words;
+        };
+        words = rest;
+        // `command -v rm` looks a command up instead of running it
+        if *wrapper == "command" && rest.first().is_some_and(|arg| arg == "-v" || arg == "-V") {
+            return &[];
+        }
+        while let Some((arg, rest)) = words.split_first() {
+            if arg == "--" {
+                words = rest;
+                break;
+            }
+            if arg.starts_with('-') {
+                words = if takes_value.contains(&arg.as_str()) {
+                    rest.get(1..).unwrap_or_default()
+                } else {
+                    rest
+                };
+            } else if *wrapper == "env" && is_assignment(arg) {
+                words = rest;
+            } else {
+                break;
+            }
+        }
+        // The duration of timeout and the new root of chroot
+        if matches!(*wrapper, "timeout" | "chroot") {
+            words = words.get(1..).unwrap_or_default();
+        }
+    }
+}
+
+/// Whether `word` is a `NAME=value` variable assignment.
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Whether `arg` is a short option group containing `flag` (`-c`, `-xc`).
+fn is_short_flag(arg: &str, flag: char) -> bool {
+    arg.len() > 1
+        && arg.starts_with('-')
+        && !arg.starts_with("--")
+        && arg[1..].chars().all(|c| c.is_ascii_alphabetic())
+        && arg.contains(flag)
+}
+
+/// Whether `arg` asks sed or perl to edit files in place (`-i`, `-i.bak`, `-ni`, `--in-place`).
+fn is_in_place(arg: &str) -> bool {
+    arg.starts_with("--in-place")
+        || (arg.starts_with('-')
+            && !arg.starts_with("--")
+            && arg[1..]
+                .split('.')
+                .next()
+                .is_some_and(|flags| flags.contains('i')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(read_only: bool) -> SessionInfo {
+        SessionInfo {
+            session_id: "s1".to_string(),
+            name: None,
+            agent_id: None,
+            shared: None,
+            host: "host:22".to_string(),
+            username: "user".to_string(),
+            connected_at: "2024-01-01T00:00:00Z".to_string(),
+            default_timeout_secs: 30,
+            retry_attempts: 0,
+            compression_enabled: true,
+            compression_level: None,
+            keepalive_interval_secs: 30,
+            keepalive_max: 3,
+            algorithms: None,
+            last_health_check: None,
+            healthy: None,
+            health_history: Vec::new(),
+            flapping: false,
+            identity: None,
+            rtt_ms: None,
+            rtt_avg_ms: None,
+            region: None,
+            datacenter: None,
+            jump_hosts: Vec::new(),
+            rebooting: false,
+            read_only,
+            agent_forwarding: false,
+            profile: None,
+            command_timeout_secs: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            stats: Default::default(),
+            labels: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_reading_commands_are_allowed() {
+        for command in [
+            "ls -la /var/log",
+            "cat /etc/os-release | grep -i version",
+            "journalctl -u nginx --since '1 hour ago' 2>&1 | tail -n 50",
+            "df -h && free -m; uptime",
+            "find /var/log -name '*.gz' -mtime +7 -exec ls -l {} \\;",
+            "cut -d' ' -f1 /var/log/nginx/access.log | sort | uniq -c",
+            "gzip -c /var/log/syslog.1 | wc -c",
+            "zstd -l /var/backups/db.zst",
+            "xz -dc /var/log/app.log.xz | tail",
+            "tar -tzf /var/backups/etc.tar.gz",
+            "tar -czf - /etc | wc -c",
+            "journalctl --disk-usage",
+            "if true; then { uptime; }; fi",
+            "git -C /srv/app status",
+            "ps aux > /dev/null 2>&1",
+            "sudo -u postgres psql -c 'select 1'",
+            "systemctl status nginx",
+            "apt list --installed",
+            "git log --oneline -n 5",
+            "docker ps -a",
+            "kubectl get pods -n default",
+            "echo 'rm -rf /' | wc -c",
+            "grep -r 'a > b' /etc/app.conf",
+            "rpm -qa",
+            "sed -n '1,10p' /etc/hosts",
+            "bash -c 'uptime; df -h'",
+            "crontab -l",
+        ] {
+            assert_eq!(mutating_reason(command), None, "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_mutating_commands_are_refused() {
+        for command in [
+            "rm -rf /tmp/x",
+            "sudo rm /etc/motd",
+            "/bin/mv a b",
+            "LANG=C sudo -u root -- chmod 600 /etc/shadow",
+            "sed -i 's/a/b/' /etc/app.conf",
+            "sed -i.bak 's/a/b/' /etc/app.conf",
+            "apt-get install -y nginx",
+            "sudo apt update",
+            "yum -y remove httpd",
+            "pip install requests",
+            "systemctl restart nginx",
+            "sudo service nginx stop",
+            "git push origin main",
+            "docker rm -f web",
+            "kubectl delete pod web",
+            "find /tmp -name '*.log' -delete",
+            "find /tmp -name '*.log' -exec rm {} +",
+            "echo x | sudo tee /etc/motd",
+            "git -C /srv/app push",
+            "su - root -c 'passwd -l bob'",
+            "ls | xargs rm",
+            "uptime; reboot",
+            "cat /etc/hosts && kill -9 1234",
+            "timeout 10 dd if=/dev/zero of=/tmp/f",
+            "nohup nice -n 10 shutdown -r now",
+            "echo $(rm -rf /tmp/x)",
+            "echo \"`touch /tmp/x`\"",
+            "bash -c 'uptime && rm -rf /tmp/x'",
+            "python3 script.py",
+            "curl -o /tmp/f https://example.com",
+            "rpm -ivh pkg.rpm",
+            "mkfs.ext4 /dev/sdb1",
+            "gzip /var/log/syslog.1",
+            "gunzip app.log.gz",
+            "xz -9 dump.sql",
+            "bzip2 -d data.bz2",
+            "zstd --rm backup.tar",
+            "tar -xzf release.tar.gz -C /opt",
+            "tar xf release.tar",
+            "tar --extract --file=release.tar",
+            "tar -czf /tmp/etc.tar.gz /etc",
+            "tar --create --file /tmp/etc.tar /etc",
+            "unzip release.zip",
+            "awk '{ system(\"rm -f \" $1) }' list.txt",
+            "awk '{ print > \"/etc/hosts\" }' hosts.new",
+            "gawk -f script.awk data.txt",
+            "journalctl --vacuum-time=2d",
+            "journalctl --rotate",
+            "logrotate -f /etc/logrotate.conf",
+        ] {
+            assert!(mutating_reason(command).is_some(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_redirections_into_files_are_refused() {
+        for command in [
+            "echo hi > /etc/motd",
+            "echo hi >> /tmp/log",
+            "uptime 2> /tmp/err",
+            "uptime &> /tmp/out",
+            "echo hi >/tmp/x",
+        ] {
+            let reason = mutating_reason(command).unwrap();
+            assert!(
+                reason.starts_with("writes to '/"),
+                "{}: {}",
+                command,
+                reason
+            );
+        }
+        // Quoted and discarded output are not writes
+        for command in ["echo 'a > b'", "uptime > /dev/null", "cmd 2>&1 >&2"] {
+            assert_eq!(mutating_reason(command), None, "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_read_only_session_refuses_commands_and_tools() {
+        let session = info(true);
+        let error = check_command(&session, "rm -f /tmp/x").unwrap_err();
+        assert_eq!(error.code, ErrorCode::PolicyViolation);
+        assert_eq!(error.details["read_only"], true);
+        assert_eq!(error.details["command"], "rm -f /tmp/x");
+        assert!(check_command(&session, "ls /tmp").is_ok());
+        let error = check_tool(&session, "ssh_shell_write").unwrap_err();
+        assert_eq!(error.details["tool"], "ssh_shell_write");
+    }
+}
@@ -0,0 +1,270 @@
+//! Background reaper of dead and idle sessions.
+//!
+//! Every `[reaper] interval_secs` (60 by default) the reaper scans the
+//! sessions of this process and disconnects:
+//!
+//! - sessions whose connection is closed, unless they reconnect on their own
+//!   (`auto_reconnect`) or their host is rebooting;
+//! - with `idle_secs > 0`, sessions without activity for that long. Activity
+//!   is channel data, a channel being opened or a command exiting; sessions
+//!   that are `persistent`, leased or running an async command are kept.
+//!
+//! A reaped session is torn down like `ssh_disconnect` does it: its shells
+//! are closed, its async commands cancelled and its forwards stopped. Managed
+//! hosts are left to their own supervisor (see `managed`).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use russh::Disconnect;
+use tracing::{info, warn};
+
+use super::audit::{self, AuditEvent};
+use super::commands::tear_down_session;
+use super::config::{ReaperConfig, server_config};
+use super::health::MIN_MONITOR_INTERVAL;
+use super::lease::SESSION_LEASES;
+use super::managed::find_managed_host;
+use super::reconnect;
+use super::storage::{COMMAND_STORAGE, SESSION_STORAGE};
+use super::types::AsyncCommandStatus;
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Why a session is reaped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reason {
+    /// The connection is closed
+    Dead,
+    /// No activity for longer than `idle_secs`
+    Idle,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Dead => "connection closed",
+            Self::Idle => "idle",
+        }
+    }
+}
+
+/// What the reaper knows about one session.
+#[derive(Debug, Default)]
+struct SessionState {
+    closed: bool,
+    rebooting: bool,
+    auto_reconnect: bool,
+    persistent: bool,
+    /// Leased, or running an async command
+    busy: bool,
+    idle_for: Duration,
+}
+
+/// Sweep interval, or `None` when the reaper is off.
+fn sweep_interval(config: &ReaperConfig) -> Option<Duration> {
+    (config.interval_secs > 0)
+        .then(|| Duration::from_secs(config.interval_secs).max(MIN_MONITOR_INTERVAL))
+}
+
+/// Inactivity after which a session is disconnected, or `None` when idle
+/// sessions are kept.
+fn idle_ttl(config: &ReaperConfig) -> Option<Duration> {
+    (config.idle_secs > 0).then(|| Duration::from_secs(config.idle_secs))
+}
+
+/// Whether a session in `state` is reaped, and why.
+fn verdict(state: &SessionState, idle_ttl: Option<Duration>) -> Option<Reason> {
+    if state.rebooting {
+        return None;
+    }
+    if state.closed {
+        return (!state.auto_reconnect).then_some(Reason::Dead);
+    }
+    let ttl = idle_ttl?;
+    (!state.persistent && !state.busy && state.idle_for >= ttl).then_some(Reason::Idle)
+}
+
+fn has_running_command(session_id: &str) -> bool {
+    COMMAND_STORAGE
+        .list_by_session(session_id)
+        .iter()
+        .filter_map(|cmd_id| COMMAND_STORAGE.get(cmd_id))
+        .any(|cmd| *cmd.status_rx.borrow() == AsyncCommandStatus::Running)
+}
+
+fn session_state(session_id: &str) -> Option<SessionState> {
+    let session_ref = SESSION_STORAGE.get(session_id)?;
+    let params = reconnect::connect_params(session_id);
+    Some(SessionState {
+        closed: session_ref.handle.is_closed(),
+        rebooting: session_ref.info.rebooting,
+        auto_reconnect: params.as_ref().is_some_and(|params| params.auto_reconnect),
+        persistent: params.as_ref().is_some_and(|params| params.persistent),
+        busy: SESSION_LEASES
+            .active(session_id, chrono::Utc::now())
+            .is_some()
+            || has_running_command(session_id),
+        idle_for: session_ref.traffic.idle_for(),
+    })
+}
+
+/// Tear down and disconnect `session_id`.
+async fn reap(session_id: &str, reason: Reason) {
+    let teardown = tear_down_session(session_id).await;
+    let Some(session_ref) = SESSION_STORAGE.remove(session_id) else {
+        return;
+    };
+    if let Some(ref agent_id) = session_ref.info.agent_id {
+        SESSION_STORAGE.unregister_agent(agent_id, session_id);
+    }
+    info!(
+        "Reaped session {} ({}){}",
+        session_id,
+        reason.as_str(),
+        teardown.summary()
+    );
+    audit::record(
+        AuditEvent::new("session_reaped")
+            .session(&session_ref.info, None)
+            .target(reason.as_str()),
+    );
+    if reason == Reason::Idle
+        && let Err(e) = session_ref
+            .handle
+            .disconnect(Disconnect::ByApplication, "Session idle", "en")
+            .await
+    {
+        warn!("Error during disconnect of session {}: {}", session_id, e);
+    }
+}
+
+/// Reap every session that is dead or idle beyond `idle_ttl`.
+async fn sweep(idle_ttl: Option<Duration>) {
+    for session_id in SESSION_STORAGE.session_ids() {
+        if find_managed_host(&session_id).is_some() {
+            continue;
+        }
+        let reason = session_state(&session_id).and_then(|state| verdict(&state, idle_ttl));
+        if let Some(reason) = reason {
+            reap(&session_id, reason).await;
+        }
+    }
+}
+
+/// Start the reaper unless `[reaper] interval_secs = 0`.
+///
+/// Runs once per process; later calls do nothing.
+pub(crate) fn start() {
+    let config = &server_config().reaper;
+    let Some(interval) = sweep_interval(config) else {
+        return;
+    };
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if tokio::runtime::Handle::try_current().is_err() {
+        warn!("No async runtime; dead and idle sessions are not reaped");
+        return;
+    }
+
+    let idle_ttl = idle_ttl(config);
+    match idle_ttl {
+        Some(ttl) => info!(
+            "Reaping dead sessions and sessions idle for {}s every {}s",
+            ttl.as_secs(),
+            interval.as_secs()
+        ),
+        None => info!("Reaping dead sessions every {}s", interval.as_secs()),
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            sweep(idle_ttl).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::config::ServerConfig;
+
+    const TTL: Option<Duration> = Some(Duration::from_secs(600));
+
+    fn reaper(toml: &str) -> ReaperConfig {
+        ServerConfig::from_toml(toml).unwrap().reaper
+    }
+
+    fn idle(secs: u64) -> SessionState {
+        SessionState {
+            idle_for: Duration::from_secs(secs),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_intervals() {
+        let config = ReaperConfig::default();
+        assert_eq!(sweep_interval(&config), Some(Duration::from_secs(60)));
+        assert_eq!(idle_ttl(&config), None);
+
+        let config = reaper("[reaper]\ninterval_secs = 1\nidle_secs = 900");
+        assert_eq!(sweep_interval(&config), Some(MIN_MONITOR_INTERVAL));
+        assert_eq!(idle_ttl(&config), Some(Duration::from_secs(900)));
+
+        assert_eq!(sweep_interval(&reaper("[reaper]\ninterval_secs = 0")), None);
+    }
+
+    #[test]
+    fn test_dead_sessions_are_reaped() {
+        let dead = SessionState {
+            closed: true,
+            persistent: true,
+            busy: true,
+            ..Default::default()
+        };
+        assert_eq!(verdict(&dead, None), Some(Reason::Dead));
+
+        // Revived on next use, or reconnected by ssh_reboot_and_wait
+        for state in [
+            SessionState {
+                auto_reconnect: true,
+                ..dead
+            },
+            SessionState {
+                closed: true,
+                rebooting: true,
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(verdict(&state, TTL), None);
+        }
+    }
+
+    #[test]
+    fn test_idle_sessions_are_reaped_past_the_ttl() {
+        assert_eq!(verdict(&idle(600), TTL), Some(Reason::Idle));
+        assert_eq!(verdict(&idle(599), TTL), None);
+        // Idle reaping is off without a TTL
+        assert_eq!(verdict(&idle(86_400), None), None);
+    }
+
+    #[test]
+    fn test_persistent_and_busy_sessions_are_kept() {
+        for state in [
+            SessionState {
+                persistent: true,
+                ..idle(3600)
+            },
+            SessionState {
+                busy: true,
+                ..idle(3600)
+            },
+        ] {
+            assert_eq!(verdict(&state, TTL), None);
+        }
+    }
+}
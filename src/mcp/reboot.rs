@@ -0,0 +1,467 @@
+//! Host reboots that wait for the host to come back (`ssh_reboot_and_wait`).
+//!
+//! The default reboot command starts `reboot` detached and one second late,
+//! so the exec channel still reports whether it was accepted before sshd goes
+//! away; sessions that are not root use `sudo -n`, so a password prompt fails
+//! the call instead of hanging it. While the host is down the session is
+//! marked `rebooting`, and health checks and `ssh_list_sessions` keep it
+//! instead of removing the closed connection.
+//!
+//! Once the old connection is closed (or stops answering keepalives), the host
+//! is dialed every [`POLL_INTERVAL`] until it accepts a connection or the
+//! timeout passes. Sessions opened with `ssh_connect` are dialed with their
+//! recorded parameters (see `reconnect`); unless the session has
+//! `auto_reconnect`, its secrets were not kept, so the caller passes fresh
+//! credentials or the session's `credential_ref` is looked up again before
+//! the reboot is sent. The new connection replaces the
+//! old one under the same session_id, with its reverse forwards re-requested;
+//! managed hosts reconnect from their configuration. Shells and async commands
+//! end with the old connection. The kernel boot id is read before and after,
+//! which tells a real reboot from a dropped connection.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::client::Handle;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use super::client::{RTT_PROBE_TIMEOUT, execute_ssh_command, measure_rtt};
+use super::config::ManagedHostConfig;
+use super::credentials::{Credential, resolve_credential};
+use super::error::{ErrorCode, ToolError};
+use super::managed::{connect_managed, find_managed_host};
+use super::reconnect::{ConnectParams, connect_params, replace_connection};
+use super::reverse::ReverseForwards;
+use super::session::SshClientHandler;
+use super::storage::{SESSION_STORAGE, SessionRef};
+use super::traffic::ByteCounters;
+use super::types::SshRebootAndWaitResponse;
+
+/// Default time allowed from sending the reboot to the host being back
+pub(crate) const DEFAULT_REBOOT_TIMEOUT_SECS: u64 = 600;
+
+/// Longest accepted reboot timeout
+pub(crate) const MAX_REBOOT_TIMEOUT_SECS: u64 = 3600;
+
+/// Shortest accepted reboot timeout
+const MIN_REBOOT_TIMEOUT_SECS: u64 = 30;
+
+/// Delay between connection attempts while the host is down
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time the reboot command gets to report that it was accepted
+const REBOOT_COMMAND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Kernel boot id; changes on every boot
+const BOOT_ID_COMMAND: &str = "cat /proc/sys/kernel/random/boot_id";
+
+const BOOT_ID_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolve the `timeout_secs` parameter, clamped to the accepted range.
+pub(crate) fn resolve_reboot_timeout(timeout_secs: Option<u64>) -> Duration {
+    Duration::from_secs(
+        timeout_secs
+            .unwrap_or(DEFAULT_REBOOT_TIMEOUT_SECS)
+            .clamp(MIN_REBOOT_TIMEOUT_SECS, MAX_REBOOT_TIMEOUT_SECS),
+    )
+}
+
+/// Default reboot command; `sudo -n` unless the session runs as root.
+pub(crate) fn reboot_command(is_root: bool) -> &'static str {
+    if is_root {
+        "nohup sh -c 'sleep 1; reboot' >/dev/null 2>&1 &"
+    } else {
+        "sudo -n true && { nohup sudo -n sh -c 'sleep 1; reboot' >/dev/null 2>&1 & }"
+    }
+}
+
+/// How a rebooted session is connected again.
+enum Redial {
+    Managed(&'static ManagedHostConfig),
+    Params(Box<ConnectParams>),
+}
+
+/// Credentials passed to ssh_reboot_and_wait for reconnecting.
+#[derive(Default)]
+pub(crate) struct FreshCredentials {
+    pub password: Option<String>,
+    pub key_path: Option<String>,
+    pub key_data: Option<String>,
+    pub credential_ref: Option<String>,
+}
+
+impl FreshCredentials {
+    fn is_empty(&self) -> bool {
+        self.password.is_none()
+            && self.key_path.is_none()
+            && self.key_data.is_none()
+            && self.credential_ref.is_none()
+    }
+}
+
+impl Redial {
+    /// How to reconnect `session_id`, with `fresh` credentials when given.
+    ///
+    /// Fails when the session's secrets were not kept and neither fresh
+    /// credentials nor a `credential_ref` to look up again are available.
+    async fn for_session(
+        session_id: &str,
+        fresh: FreshCredentials,
+        agent_id: Option<&str>,
+    ) -> Result<Self, ToolError> {
+        if let Some(host) = find_managed_host(session_id) {
+            return Ok(Self::Managed(host));
+        }
+        let params = connect_params(session_id).ok_or_else(|| {
+            ToolError::new(
+                ErrorCode::InvalidState,
+                format!(
+                    "The connection parameters of session {} are unknown, so it could not be reconnected after a reboot",
+                    session_id
+                ),
+            )
+        })?;
+        if fresh.is_empty() && !params.needs_credentials() {
+            return Ok(Self::Params(Box::new(params)));
+        }
+
+        let credential_ref = match fresh.credential_ref.as_deref() {
+            Some(name) => Some(name),
+            // A named credential is looked up again rather than kept
+            None if fresh.is_empty() => params.credential_ref.as_deref(),
+            None => None,
+        };
+        let credential = match credential_ref {
+            Some(name) => {
+                let targets: Vec<&str> = std::iter::once(params.address.as_str())
+                    .chain(params.jump_hosts.iter().map(|hop| hop.address.as_str()))
+                    .collect();
+                resolve_credential(name, agent_id, &targets).await?
+            }
+            None if fresh.is_empty() => {
+                return Err(ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "Session {} was connected without auto_reconnect, so its credentials were not kept; pass password, key_path, key_data or credential_ref to reconnect after the reboot",
+                        session_id
+                    ),
+                )
+                .with_detail("session_id", session_id));
+            }
+            None => Credential {
+                username: None,
+                password: fresh.password,
+                key_path: fresh.key_path,
+                key_data: fresh.key_data,
+            },
+        };
+        params
+            .with_credentials(
+                credential.password,
+                credential.key_path,
+                credential.key_data,
+            )
+            .map(|params| Self::Params(Box::new(params)))
+            .map_err(|e| {
+                ToolError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Session {} cannot be reconnected: {}", session_id, e),
+                )
+                .with_detail("session_id", session_id)
+            })
+    }
+
+    /// One connection attempt; a managed host registers its session itself.
+    async fn attempt(
+        &self,
+        session_id: &str,
+        reverse: &Arc<ReverseForwards>,
+        traffic: &Arc<ByteCounters>,
+    ) -> Result<Arc<Handle<SshClientHandler>>, String> {
+        match self {
+            Self::Managed(host) => {
+                connect_managed(host).await.map_err(|e| e.message)?;
+                SESSION_STORAGE
+                    .get(session_id)
+                    .map(|session_ref| session_ref.handle)
+                    .ok_or_else(|| format!("Managed session {} is not registered", session_id))
+            }
+            Self::Params(params) => params.dial(reverse, traffic).await.map(Arc::new),
+        }
+    }
+}
+
+async fn read_boot_id(handle: &Arc<Handle<SshClientHandler>>) -> Option<String> {
+    match execute_ssh_command(handle, BOOT_ID_COMMAND, BOOT_ID_TIMEOUT).await {
+        Ok(response) if !response.timed_out && response.exit_code == 0 => {
+            let boot_id = response.stdout.trim();
+            (!boot_id.is_empty()).then(|| boot_id.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Wait for the old connection to close or stop answering; false at the deadline.
+async fn wait_until_down(handle: &Arc<Handle<SshClientHandler>>, deadline: Instant) -> bool {
+    loop {
+        if handle.is_closed() || measure_rtt(handle, RTT_PROBE_TIMEOUT).await.is_none() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+fn secs(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1000.0).round() / 1000.0
+}
+
+/// Reboot the host of `session`, wait for it and reconnect under the same session_id.
+pub(crate) async fn reboot_and_wait(
+    session: SessionRef,
+    command: String,
+    timeout: Duration,
+    fresh: FreshCredentials,
+    agent_id: Option<&str>,
+) -> Result<SshRebootAndWaitResponse, ToolError> {
+    let session_id = session.info.session_id.clone();
+    let redial = Redial::for_session(&session_id, fresh, agent_id).await?;
+
+    let started = Instant::now();
+    let deadline = started + timeout;
+    let boot_id = read_boot_id(&session.handle).await;
+
+    SESSION_STORAGE.set_rebooting(&session_id, true);
+    let rebooted_at = chrono::Utc::now().to_rfc3339();
+    info!("Rebooting the host of session {}: {}", session_id, command);
+    match execute_ssh_command(&session.handle, &command, REBOOT_COMMAND_TIMEOUT).await {
+        Ok(response) if !response.timed_out && response.exit_code != 0 => {
+            SESSION_STORAGE.set_rebooting(&session_id, false);
+            let stderr = response.stderr.trim();
+            return Err(ToolError::new(
+                ErrorCode::InvalidState,
+                format!(
+                    "Reboot command exited with code {}{}",
+                    response.exit_code,
+                    if stderr.is_empty() {
+                        String::new()
+                    } else {
+                        format!(": {}", stderr)
+                    }
+                ),
+            )
+            .with_stage("reboot")
+            .with_detail("session_id", session_id));
+        }
+        Ok(_) => {}
+        // The connection may already be going away
+        Err(e) => debug!("Reboot command of session {} ended: {}", session_id, e),
+    }
+
+    if !wait_until_down(&session.handle, deadline).await {
+        SESSION_STORAGE.set_rebooting(&session_id, false);
+        return Err(ToolError::new(
+            ErrorCode::InvalidState,
+            format!(
+                "The host did not go down within {}s; the reboot may have been refused or delayed",
+                timeout.as_secs()
+            ),
+        )
+        .with_stage("reboot")
+        .with_detail("session_id", session_id));
+    }
+    let down_at = Instant::now();
+    info!("Host of session {} went down; waiting for it", session_id);
+
+    let reverse = Arc::new(ReverseForwards::default());
+    let mut attempts: u32 = 0;
+    let mut last_error = None;
+    let handle = loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            SESSION_STORAGE.set_rebooting(&session_id, false);
+            let mut message = format!(
+                "The host did not come back within {}s of the reboot",
+                timeout.as_secs()
+            );
+            if let Some(e) = last_error {
+                message.push_str(&format!("; last error: {}", e));
+            }
+            return Err(ToolError::new(ErrorCode::ConnectionFailed, message)
+                .with_stage("reconnect")
+                .with_detail("session_id", session_id));
+        }
+        attempts += 1;
+        match tokio::time::timeout(
+            remaining,
+            redial.attempt(&session_id, &reverse, &session.traffic),
+        )
+        .await
+        {
+            Ok(Ok(handle)) => break handle,
+            Ok(Err(e)) => {
+                debug!("Session {} is not back yet: {}", session_id, e);
+                last_error = Some(e);
+            }
+            Err(_) => {}
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        tokio::time::sleep(POLL_INTERVAL.min(remaining)).await;
+    };
+    let back_at = Instant::now();
+
+    let identity = match redial {
+        Redial::Params(_) => {
+            replace_connection(&session_id, session, handle.clone(), reverse)
+                .await
+                .info
+                .identity
+        }
+        Redial::Managed(_) => session.info.identity.clone(),
+    };
+
+    let boot_id_changed = match (boot_id, read_boot_id(&handle).await) {
+        (Some(before), Some(after)) => Some(before != after),
+        _ => None,
+    };
+    if boot_id_changed == Some(false) {
+        warn!(
+            "Session {} reconnected but the host's boot id did not change",
+            session_id
+        );
+    }
+
+    let downtime_secs = secs(back_at - down_at);
+    let message = match boot_id_changed {
+        Some(false) => format!(
+            "Session {} reconnected after {:.1}s, but the boot id is unchanged: the host may not have rebooted",
+            session_id, downtime_secs
+        ),
+        _ => format!(
+            "Host of session {} rebooted and is back after {:.1}s of downtime ({} connection attempt(s))",
+            session_id, downtime_secs, attempts
+        ),
+    };
+    info!("{}", message);
+
+    Ok(SshRebootAndWaitResponse {
+        session_id,
+        command,
+        rebooted_at,
+        back_at: chrono::Utc::now().to_rfc3339(),
+        downtime_secs,
+        total_secs: secs(back_at - started),
+        reconnect_attempts: attempts,
+        boot_id_changed,
+        identity,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::reconnect::{forget, remember};
+
+    #[test]
+    fn test_timeout_is_clamped() {
+        assert_eq!(
+            resolve_reboot_timeout(None),
+            Duration::from_secs(DEFAULT_REBOOT_TIMEOUT_SECS)
+        );
+        assert_eq!(resolve_reboot_timeout(Some(1)), Duration::from_secs(30));
+        assert_eq!(
+            resolve_reboot_timeout(Some(86400)),
+            Duration::from_secs(MAX_REBOOT_TIMEOUT_SECS)
+        );
+    }
+
+    #[test]
+    fn test_reboot_command_uses_sudo_unless_root() {
+        let root = reboot_command(true);
+        assert!(!root.contains("sudo"));
+        assert!(root.starts_with("nohup "));
+
+        let user = reboot_command(false);
+        assert!(user.starts_with("sudo -n true && "));
+        assert!(user.contains("sudo -n sh -c 'sleep 1; reboot'"));
+    }
+
+    fn params(password: Option<&str>, auto_reconnect: bool) -> ConnectParams {
+        ConnectParams {
+            address: "10.0.0.9:22".to_string(),
+            username: "deploy".to_string(),
+            password: password.map(str::to_string),
+            key_path: None,
+            key_data: None,
+            timeout: Duration::from_secs(30),
+            inactivity_timeout: Duration::from_secs(300),
+            compress: true,
+            persistent: false,
+            keepalive: Default::default(),
+            algorithms: Default::default(),
+            host_key_checking: Default::default(),
+            jump_hosts: Vec::new(),
+            auto_reconnect,
+            credential_ref: None,
+            agent_forwarding: false,
+            redacted: None,
+        }
+    }
+
+    fn redialed_password(redial: Redial) -> Option<String> {
+        match redial {
+            Redial::Params(params) => params.password,
+            Redial::Managed(_) => panic!("not a managed host"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_session_cannot_be_redialed() {
+        let error = Redial::for_session("reboot-test-unknown", Default::default(), None)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidState);
+    }
+
+    #[tokio::test]
+    async fn test_redial_needs_auto_reconnect_or_fresh_credentials() {
+        remember("reboot-test-kept", params(Some("hunter2"), true));
+        let redial = Redial::for_session("reboot-test-kept", Default::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(redialed_password(redial).as_deref(), Some("hunter2"));
+        forget("reboot-test-kept");
+
+        remember("reboot-test-dropped", params(Some("hunter2"), false));
+        let error = Redial::for_session("reboot-test-dropped", Default::default(), None)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+        assert!(error.message.contains("auto_reconnect"));
+
+        let fresh = FreshCredentials {
+            password: Some("rotated".to_string()),
+            ..Default::default()
+        };
+        let redial = Redial::for_session("reboot-test-dropped", fresh, None)
+            .await
+            .unwrap();
+        assert_eq!(redialed_password(redial).as_deref(), Some("rotated"));
+        forget("reboot-test-dropped");
+
+        // Agent authentication has nothing to drop
+        remember("reboot-test-agent", params(None, false));
+        assert!(
+            Redial::for_session("reboot-test-agent", Default::default(), None)
+                .await
+                .is_ok()
+        );
+        forget("reboot-test-agent");
+    }
+}
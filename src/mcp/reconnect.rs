@@ -0,0 +1,464 @@
+//! Connection parameters kept for dialing a session's host again.
+//!
+//! `ssh_connect` records how each session was opened (address, credentials,
+//! jump hosts, transport settings) so a tool that has to reconnect, such as
+//! `ssh_reboot_and_wait`, can do so without the caller passing credentials
+//! again. Entries only live in memory and are dropped when the session is torn
+//! down. Managed hosts are dialed from their configuration instead.
+//!
+//! Passwords and key contents (also of jump hosts) are only kept for sessions
+//! opened with `auto_reconnect`; other sessions keep a copy without them,
+//! enough for pool keys and reconnect hints, and dialing them again needs
+//! fresh credentials (see [`ConnectParams::with_credentials`]).
+//!
+//! Sessions opened with `auto_reconnect` are also dialed again when a tool
+//! finds their connection dead (see [`revive`]): the new connection replaces
+//! the old one under the same session_id and the tool retries its operation
+//! once. Shells, async commands and local forwards end with the old
+//! connection; reverse forwards are requested again.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use russh::client;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use super::algorithms::AlgorithmPreferences;
+use super::client::{RTT_PROBE_TIMEOUT, connect_to_ssh_with_retry, measure_rtt};
+use super::config::{Keepalive, resolve_identity_probe};
+use super::helpers::{forget_session as forget_helper_sync, sync_on_connect};
+use super::identity::probe_identity;
+use super::known_hosts::HostKeyChecking;
+use super::reverse::{REVERSE_FORWARDS, ReverseForwards, restore_tunnels};
+use super::session::SshClientHandler;
+use super::storage::{SESSION_STORAGE, SessionRef};
+use super::traffic::ByteCounters;
+use super::types::JumpHost;
+
+/// How a session was connected.
+#[derive(Clone)]
+pub(crate) struct ConnectParams {
+    pub address: String,
+    pub username: String,
+    pub password: Option<String>,
+    pub key_path: Option<String>,
+    pub key_data: Option<String>,
+    pub timeout: Duration,
+    pub inactivity_timeout: Duration,
+    pub compress: bool,
+    pub persistent: bool,
+    pub keepalive: Keepalive,
+    /// Requested cipher, key exchange and host key algorithms
+    pub algorithms: AlgorithmPreferences,
+    pub host_key_checking: HostKeyChecking,
+    pub jump_hosts: Vec<JumpHost>,
+    /// Dial again when a tool finds the connection dead (`auto_reconnect`)
+    pub auto_reconnect: bool,
+    /// Named credential the secrets came from (`credential_ref`)
+    pub credential_ref: Option<String>,
+    /// Serve agent channels of the connection (`agent_forwarding`)
+    pub agent_forwarding: bool,
+    /// Set once the secrets were dropped ([`ConnectParams::without_secrets`])
+    pub redacted: Option<Redacted>,
+}
+
+/// What [`ConnectParams::without_secrets`] removed.
+#[derive(Clone)]
+pub(crate) struct Redacted {
+    /// How the session authenticated, see [`ConnectParams::auth`]
+    pub auth: &'static str,
+    /// Credential fingerprint, see [`ConnectParams::credential_fingerprint`]
+    pub fingerprint: String,
+    /// Whether jump host secrets were dropped too
+    pub jump_hosts: bool,
+}
+
+impl ConnectParams {
+    /// Credential the session authenticated with: `credential_ref`,
+    /// `key_data`, `key_path`, `password` or `agent`.
+    pub(crate) fn auth(&self) -> &'static str {
+        if let Some(redacted) = &self.redacted {
+            redacted.auth
+        } else if self.credential_ref.is_some() {
+            "credential_ref"
+        } else if self.key_data.is_some() {
+            "key_data"
+        } else if self.key_path.is_some() {
+            "key_path"
+        } else if self.password.is_some() {
+            "password"
+        } else {
+            "agent"
+        }
+    }
+
+    /// Hex SHA-256 of the password, key path and key data, which tells
+    /// connections opened with different credentials apart.
+    pub(crate) fn credential_fingerprint(&self) -> String {
+        if let Some(redacted) = &self.redacted {
+            return redacted.fingerprint.clone();
+        }
+        let mut credential = Sha256::new();
+        for part in [&self.password, &self.key_path, &self.key_data] {
+            credential.update(part.as_deref().unwrap_or("").as_bytes());
+            credential.update([0]);
+        }
+        format!("{:x}", credential.finalize())
+    }
+
+    /// A copy without passwords and key contents of the target and its jump
+    /// hosts; key file paths are kept. Unchanged when there are none.
+    pub(crate) fn without_secrets(&self) -> Self {
+        let hop_secrets = self
+            .jump_hosts
+            .iter()
+            .any(|hop| hop.password.is_some() || hop.key_data.is_some());
+        if self.redacted.is_some()
+            || (self.password.is_none() && self.key_data.is_none() && !hop_secrets)
+        {
+            return self.clone();
+        }
+        let jump_hosts = self
+            .jump_hosts
+            .iter()
+            .map(|hop| JumpHost {
+                password: None,
+                key_data: None,
+                ..hop.clone()
+            })
+            .collect();
+        Self {
+            password: None,
+            key_data: None,
+            jump_hosts,
+            redacted: Some(Redacted {
+                auth: self.auth(),
+                fingerprint: self.credential_fingerprint(),
+                jump_hosts: hop_secrets,
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the secrets needed to dial again were dropped.
+    pub(crate) fn needs_credentials(&self) -> bool {
+        self.redacted.is_some()
+    }
+
+    /// These parameters with fresh credentials for the target.
+    ///
+    /// Fails when jump host secrets were dropped, as they cannot be supplied again.
+    pub(crate) fn with_credentials(
+        &self,
+        password: Option<String>,
+        key_path: Option<String>,
+        key_data: Option<String>,
+    ) -> Result<Self, String> {
+        if self
+            .redacted
+            .as_ref()
+            .is_some_and(|redacted| redacted.jump_hosts)
+        {
+            return Err(
+                "the jump host credentials were not kept; connect with auto_reconnect=true to reconnect through them"
+                    .to_string(),
+            );
+        }
+        Ok(Self {
+            password,
+            key_path,
+            key_data,
+            redacted: None,
+            ..self.clone()
+        })
+    }
+
+    /// Dial and authenticate once, without retries.
+    pub(crate) async fn dial(
+        &self,
+        reverse: &Arc<ReverseForwards>,
+        traffic: &Arc<ByteCounters>,
+    ) -> Result<client::Handle<SshClientHandler>, String> {
+        connect_to_ssh_with_retry(
+            &self.address,
+            &self.username,
+            self.password.as_deref(),
+            self.key_path.as_deref(),
+            self.key_data.as_deref(),
+            self.timeout,
+            self.inactivity_timeout,
+            0,
+            Duration::ZERO,
+            self.compress,
+            self.persistent,
+            self.keepalive,
+            &self.algorithms,
+            self.host_key_checking,
+            &self.jump_hosts,
+            self.agent_forwarding,
+            reverse,
+            traffic,
+        )
+        .await
+        .map(|(handle, _)| handle)
+    }
+}
+
+/// Parameters by session_id.
+static CONNECT_PARAMS: Lazy<DashMap<String, ConnectParams>> = Lazy::new(DashMap::new);
+
+/// Record how `session_id` was connected, without secrets unless it
+/// reconnects automatically.
+pub(crate) fn remember(session_id: &str, params: ConnectParams) {
+    let params = if params.auto_reconnect {
+        params
+    } else {
+        params.without_secrets()
+    };
+    CONNECT_PARAMS.insert(session_id.to_string(), params);
+}
+
+/// The parameters `session_id` was connected with, if recorded.
+pub(crate) fn connect_params(session_id: &str) -> Option<ConnectParams> {
+    CONNECT_PARAMS
+        .get(session_id)
+        .map(|entry| entry.value().clone())
+}
+
+/// Drop the parameters of a session that is gone.
+pub(crate) fn forget(session_id: &str) {
+    CONNECT_PARAMS.remove(session_id);
+    REVIVING.remove(session_id);
+}
+
+/// Serializes reconnects of the same session, by session_id.
+static REVIVING: Lazy<DashMap<String, Arc<Mutex<()>>>> = Lazy::new(DashMap::new);
+
+/// Whether `session_id` was connected with `auto_reconnect`.
+pub(crate) fn auto_reconnect(session_id: &str) -> bool {
+    CONNECT_PARAMS
+        .get(session_id)
+        .is_some_and(|entry| entry.auto_reconnect)
+}
+
+/// Whether a failed channel operation on `handle` was caused by the
+/// connection itself: it is closed or no longer answers a keepalive.
+pub(crate) async fn connection_lost(handle: &Arc<client::Handle<SshClientHandler>>) -> bool {
+    handle.is_closed() || measure_rtt(handle, RTT_PROBE_TIMEOUT).await.is_none()
+}
+
+/// Dial the host of an `auto_reconnect` session again and register the new
+/// connection under the same session_id.
+///
+/// `session` is the session as the caller found it; when another call has
+/// already replaced its connection, the current session is returned instead
+/// of dialing twice.
+pub(crate) async fn revive(session_id: &str, session: SessionRef) -> Result<SessionRef, String> {
+    let params = connect_params(session_id)
+        .filter(|params| params.auto_reconnect)
+        .ok_or_else(|| format!("Session {} does not reconnect automatically", session_id))?;
+    let lock = REVIVING
+        .entry(session_id.to_string())
+        .or_default()
+        .value()
+        .clone();
+    let _guard = lock.lock().await;
+    if let Some(current) = SESSION_STORAGE.get(session_id)
+        && !Arc::ptr_eq(&current.handle, &session.handle)
+        && !current.handle.is_closed()
+    {
+        return Ok(current);
+    }
+
+    info!(
+        "Connection of session {} was lost; reconnecting to {}",
+        session_id, params.address
+    );
+    let reverse = Arc::new(ReverseForwards::default());
+    let handle = params.dial(&reverse, &session.traffic).await.map_err(|e| {
+        warn!("Reconnecting session {} failed: {}", session_id, e);
+        format!("Connection lost and reconnecting failed: {}", e)
+    })?;
+    Ok(replace_connection(session_id, session, Arc::new(handle), reverse).await)
+}
+
+/// Register `handle` as the connection of `session_id` in place of the one
+/// in `session`, keeping its name, owner and byte counters.
+///
+/// The identity is probed again, the session's reverse forwards are
+/// requested on the new connection and helper scripts are synced again.
+pub(crate) async fn replace_connection(
+    session_id: &str,
+    session: SessionRef,
+    handle: Arc<client::Handle<SshClientHandler>>,
+    reverse: Arc<ReverseForwards>,
+) -> SessionRef {
+    let owner = session.info.agent_id.clone();
+    let mut info = session.info;
+    info.rebooting = false;
+    info.connected_at = chrono::Utc::now().to_rfc3339();
+    if resolve_identity_probe() {
+        info.identity = probe_identity(&handle).await;
+    }
+    info.record_health(info.connected_at.clone(), true);
+    if let Some(rtt) = measure_rtt(&handle, RTT_PROBE_TIMEOUT).await {
+        info.record_rtt(rtt);
+    }
+
+    let carried = REVERSE_FORWARDS.take_for_reconnect(session_id, owner.as_deref());
+    let restored = restore_tunnels(&handle, &reverse, carried).await.len();
+    if restored > 0 {
+        info!(
+            "Re-established {} reverse forward(s) of session {}",
+            restored, session_id
+        );
+    }
+    forget_helper_sync(session_id);
+    sync_on_connect(
+        handle.clone(),
+        session.traffic.clone(),
+        session_id.to_string(),
+    );
+    // The byte counters carry over, so the totals span the reconnect
+    SESSION_STORAGE.insert(
+        session_id.to_string(),
+        info.clone(),
+        handle.clone(),
+        session.traffic.clone(),
+    );
+    REVERSE_FORWARDS.attach(session_id, owner.as_deref(), reverse);
+    if let Some(ref aid) = owner {
+        SESSION_STORAGE.register_agent(aid, session_id);
+    }
+    SessionRef {
+        info,
+        handle,
+        traffic: session.traffic,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(address: &str) -> ConnectParams {
+        ConnectParams {
+            address: address.to_string(),
+            username: "deploy".to_string(),
+            password: None,
+            key_path: Some("~/.ssh/id_ed25519".to_string()),
+            key_data: None,
+            timeout: Duration::from_secs(30),
+            inactivity_timeout: Duration::from_secs(300),
+            compress: true,
+            persistent: false,
+            keepalive: Keepalive::default(),
+            algorithms: Default::default(),
+            host_key_checking: HostKeyChecking::default(),
+            jump_hosts: Vec::new(),
+            auto_reconnect: false,
+            credential_ref: None,
+            agent_forwarding: false,
+            redacted: None,
+        }
+    }
+
+    #[test]
+    fn test_remember_and_forget() {
+        assert!(connect_params("reconnect-test").is_none());
+        remember("reconnect-test", params("10.0.0.5:22"));
+        let stored = connect_params("reconnect-test").unwrap();
+        assert_eq!(stored.address, "10.0.0.5:22");
+        assert_eq!(stored.username, "deploy");
+
+        remember("reconnect-test", params("10.0.0.6:22"));
+        assert_eq!(
+            connect_params("reconnect-test").unwrap().address,
+            "10.0.0.6:22"
+        );
+        forget("reconnect-test");
+        assert!(connect_params("reconnect-test").is_none());
+    }
+
+    #[test]
+    fn test_auto_reconnect_is_opt_in() {
+        remember("reconnect-test-auto", params("10.0.0.7:22"));
+        assert!(!auto_reconnect("reconnect-test-auto"));
+        remember(
+            "reconnect-test-auto",
+            ConnectParams {
+                auto_reconnect: true,
+                ..params("10.0.0.7:22")
+            },
+        );
+        assert!(auto_reconnect("reconnect-test-auto"));
+        forget("reconnect-test-auto");
+        assert!(!auto_reconnect("reconnect-test-auto"));
+        assert!(!auto_reconnect("reconnect-test-unknown"));
+    }
+
+    #[test]
+    fn test_secrets_are_kept_only_for_auto_reconnect() {
+        let hop = JumpHost {
+            address: "bastion:22".to_string(),
+            username: None,
+            password: Some("hop-secret".to_string()),
+            key_path: None,
+            key_data: None,
+        };
+        let with_password = ConnectParams {
+            password: Some("hunter2".to_string()),
+            key_path: None,
+            jump_hosts: vec![hop],
+            ..params("10.0.0.8:22")
+        };
+        remember("reconnect-test-secrets", with_password.clone());
+        let stored = connect_params("reconnect-test-secrets").unwrap();
+        assert!(stored.password.is_none());
+        assert!(stored.jump_hosts[0].password.is_none());
+        assert!(stored.needs_credentials());
+        assert_eq!(stored.auth(), "password");
+        assert_eq!(
+            stored.credential_fingerprint(),
+            with_password.credential_fingerprint()
+        );
+        assert!(
+            stored
+                .with_credentials(Some("hunter2".to_string()), None, None)
+                .is_err()
+        );
+
+        remember(
+            "reconnect-test-secrets",
+            ConnectParams {
+                auto_reconnect: true,
+                ..with_password.clone()
+            },
+        );
+        let stored = connect_params("reconnect-test-secrets").unwrap();
+        assert_eq!(stored.password.as_deref(), Some("hunter2"));
+        assert!(!stored.needs_credentials());
+        forget("reconnect-test-secrets");
+
+        // Nothing to drop: the copy can still dial with its key file
+        let stored = params("10.0.0.8:22").without_secrets();
+        assert!(!stored.needs_credentials());
+        assert_eq!(stored.key_path.as_deref(), Some("~/.ssh/id_ed25519"));
+
+        let stripped = ConnectParams {
+            jump_hosts: Vec::new(),
+            ..with_password
+        }
+        .without_secrets();
+        let fresh = stripped
+            .with_credentials(Some("new-password".to_string()), None, None)
+            .unwrap();
+        assert_eq!(fresh.password.as_deref(), Some("new-password"));
+        assert_eq!(fresh.auth(), "password");
+        assert!(!fresh.needs_credentials());
+    }
+}
@@ -0,0 +1,600 @@
+//! Reverse port forwarding (remote listeners).
+//!
+//! `ssh_reverse_forward` asks the SSH server to listen on a remote port
+//! (`tcpip-forward`, RFC 4254 section 7.1). Every connection the server accepts
+//! arrives as a `forwarded-tcpip` channel and is bridged to a TCP target
+//! reachable from the MCP server, so a remote job can call back into a local
+//! service.
+//!
+//! # Sending Global Requests
+//!
+//! russh only sends `tcpip-forward` through `&mut Handle` or from inside a
+//! handler callback, and sessions share their handle through an `Arc`. Requests
+//! are therefore queued on the connection's [`ReverseForwards`] and sent from
+//! the next `channel_open_confirmation` callback; the caller opens (and closes)
+//! a session channel to trigger one.
+//!
+//! # Registry and Reconnects
+//!
+//! [`REVERSE_FORWARDS`] tracks the listeners of every session. The entry is
+//! kept when a dead session is dropped, so `ssh_connect` with the old
+//! `session_id` can request the same listeners (same bound port, same target)
+//! on the new connection; long-lived callback tunnels survive a network blip.
+//! `ssh_disconnect` removes the entry.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use russh::Channel;
+use russh::client::{self, Msg, Session};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+use super::client::channel_step;
+use super::config::resolve_channel_open_timeout;
+use super::rate_limit::{Bandwidth, Throttled, resolve_rate_limit};
+use super::session::SshClientHandler;
+use super::traffic::{ByteCounters, CountingStream};
+use super::types::ReverseForwardInfo;
+
+/// Remote bind address when none is given (loopback on the remote host)
+pub(crate) const DEFAULT_REMOTE_BIND_ADDRESS: &str = "localhost";
+
+/// Local target host when none is given
+pub(crate) const DEFAULT_LOCAL_HOST: &str = "127.0.0.1";
+
+/// Time allowed to connect to the local target of a forwarded connection
+const LOCAL_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reverse forwards by session_id.
+pub(crate) static REVERSE_FORWARDS: Lazy<ReverseRegistry> = Lazy::new(ReverseRegistry::new);
+
+/// A remote listener and where its connections go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ReverseTunnel {
+    /// Address the server listens on
+    pub bind_address: String,
+    /// Port the server listens on (as bound, never 0)
+    pub remote_port: u32,
+    /// Host the connections are bridged to, resolved on the MCP server
+    pub local_host: String,
+    pub local_port: u16,
+    pub created_at: String,
+    /// Bandwidth cap of the forwarded connections, in bytes per second
+    pub rate_limit: Option<u64>,
+}
+
+impl ReverseTunnel {
+    fn key(&self) -> (String, u32) {
+        (self.bind_address.clone(), self.remote_port)
+    }
+}
+
+enum GlobalRequest {
+    Forward {
+        address: String,
+        port: u32,
+        reply: oneshot::Sender<Option<u32>>,
+    },
+    Cancel {
+        address: String,
+        port: u32,
+        reply: oneshot::Sender<bool>,
+    },
+}
+
+/// Reverse forwards of one SSH connection, shared with its client handler.
+#[derive(Default)]
+pub(crate) struct ReverseForwards {
+    /// Requests waiting for the next handler callback
+    pending: Mutex<Vec<GlobalRequest>>,
+    /// Active listeners by (bind address, port)
+    tunnels: DashMap<(String, u32), ReverseTunnel>,
+    /// Forwarded connections accepted so far, by listener
+    connections: DashMap<(String, u32), u64>,
+    /// Rate limits shared by the connections of a listener
+    bandwidth: DashMap<(String, u32), Arc<Bandwidth>>,
+}
+
+impl ReverseForwards {
+    /// Send queued global requests. Called from handler callbacks.
+    pub(crate) fn flush(&self, session: &mut Session) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        for request in pending {
+            let sent = match request {
+                GlobalRequest::Forward {
+                    address,
+                    port,
+                    reply,
+                } => session.tcpip_forward(Some(reply), &address, port),
+                GlobalRequest::Cancel {
+                    address,
+                    port,
+                    reply,
+                } => session.cancel_tcpip_forward(Some(reply), &address, port),
+            };
+            if let Err(e) = sent {
+                warn!("Failed to send reverse forward request: {}", e);
+            }
+        }
+    }
+
+    /// Queue a request and open a throwaway channel so the handler sends it.
+    async fn send(
+        &self,
+        handle: &client::Handle<SshClientHandler>,
+        request: GlobalRequest,
+    ) -> Result<(), String> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(request);
+        let channel = channel_step(
+            "open session channel",
+            resolve_channel_open_timeout(),
+            handle.channel_open_session(),
+        )
+        .await?;
+        let _ = channel.close().await;
+        Ok(())
+    }
+
+    /// Ask the server to listen on `bind_address:remote_port` (0 = server picks)
+    /// and bridge its connections to `local_host:local_port`, at most
+    /// `rate_limit` bytes per second when given.
+    pub(crate) async fn open(
+        &self,
+        handle: &client::Handle<SshClientHandler>,
+        bind_address: &str,
+        remote_port: u32,
+        local_host: &str,
+        local_port: u16,
+        rate_limit: Option<u64>,
+    ) -> Result<ReverseTunnel, String> {
+        let bandwidth = resolve_rate_limit(rate_limit)?;
+        if let Some(existing) = self.find(Some(bind_address), remote_port) {
+            return Err(format!(
+                "Remote port {}:{} is already forwarded to {}:{}",
+                existing.bind_address,
+                existing.remote_port,
+                existing.local_host,
+                existing.local_port
+            ));
+        }
+
+        let (reply, answer) = oneshot::channel();
+        self.send(
+            handle,
+            GlobalRequest::Forward {
+                address: bind_address.to_string(),
+                port: remote_port,
+                reply,
+            },
+        )
+        .await?;
+        let bound = match tokio::time::timeout(resolve_channel_open_timeout(), answer).await {
+            Ok(Ok(Some(port))) => port,
+            Ok(Ok(None)) => {
+                return Err(format!(
+                    "Server refused to listen on {}:{} (check AllowTcpForwarding/GatewayPorts and whether the port is in use)",
+                    bind_address, remote_port
+                ));
+            }
+            Ok(Err(_)) => return Err("Connection closed before the server answered".to_string()),
+            Err(_) => {
+                return Err(format!(
+                    "Failed to request remote listener: timeout after {}s (server not responding)",
+                    resolve_channel_open_timeout().as_secs()
+                ));
+            }
+        };
+
+        // A reply for a specific port carries no port number
+        let tunnel = ReverseTunnel {
+            bind_address: bind_address.to_string(),
+            remote_port: if bound == 0 { remote_port } else { bound },
+            local_host: local_host.to_string(),
+            local_port,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            rate_limit,
+        };
+        if let Some(bandwidth) = bandwidth {
+            self.bandwidth.insert(tunnel.key(), bandwidth);
+        }
+        self.tunnels.insert(tunnel.key(), tunnel.clone());
+        Ok(tunnel)
+    }
+
+    /// Stop a listener. The tunnel is forgotten even if the server refuses,
+    /// since its connections would no longer be bridged anyway.
+    pub(crate) async fn close(
+        &self,
+        handle: &client::Handle<SshClientHandler>,
+        bind_address: Option<&str>,
+        remote_port: u32,
+    ) -> Result<ReverseTunnel, String> {
+        let tunnel = self.find(bind_address, remote_port).ok_or_else(|| {
+            format!(
+                "No reverse forward on {}:{}",
+                bind_address.unwrap_or("*"),
+                remote_port
+            )
+        })?;
+        self.tunnels.remove(&tunnel.key());
+        self.connections.remove(&tunnel.key());
+        self.bandwidth.remove(&tunnel.key());
+
+        let (reply, answer) = oneshot::channel();
+        self.send(
+            handle,
+            GlobalRequest::Cancel {
+                address: tunnel.bind_address.clone(),
+                port: tunnel.remote_port,
+                reply,
+            },
+        )
+        .await?;
+        match tokio::time::timeout(resolve_channel_open_timeout(), answer).await {
+            Ok(Ok(true)) => Ok(tunnel),
+            Ok(Ok(false)) => Err(format!(
+                "Server refused to cancel the listener on {}:{}",
+                tunnel.bind_address, tunnel.remote_port
+            )),
+            _ => Err("No answer from the server to cancel-tcpip-forward".to_string()),
+        }
+    }
+
+    /// Find a listener by port, and by bind address when given.
+    pub(crate) fn find(
+        &self,
+        bind_address: Option<&str>,
+        remote_port: u32,
+    ) -> Option<ReverseTunnel> {
+        match bind_address {
+            Some(address) => self
+                .tunnels
+                .get(&(address.to_string(), remote_port))
+                .map(|tunnel| tunnel.clone()),
+            None => self
+                .tunnels
+                .iter()
+                .find(|tunnel| tunnel.remote_port == remote_port)
+                .map(|tunnel| tunnel.clone()),
+        }
+    }
+
+    /// Active listeners, ordered by port.
+    pub(crate) fn tunnels(&self) -> Vec<ReverseTunnel> {
+        let mut tunnels: Vec<ReverseTunnel> =
+            self.tunnels.iter().map(|tunnel| tunnel.clone()).collect();
+        tunnels.sort_by(|a, b| {
+            (a.remote_port, &a.bind_address).cmp(&(b.remote_port, &b.bind_address))
+        });
+        tunnels
+    }
+
+    /// Connections bridged so far through a listener.
+    pub(crate) fn connection_count(&self, tunnel: &ReverseTunnel) -> u64 {
+        self.connections
+            .get(&tunnel.key())
+            .map(|count| *count)
+            .unwrap_or(0)
+    }
+
+    /// Bridge a `forwarded-tcpip` channel to its local target. Called from the
+    /// handler; channels for unknown listeners are closed.
+    pub(crate) fn accept(
+        &self,
+        channel: Channel<Msg>,
+        connected_address: &str,
+        connected_port: u32,
+        traffic: Arc<ByteCounters>,
+    ) {
+        // Servers echo the bind address as requested; fall back to the port
+        // alone for servers that report the resolved address instead.
+        let tunnel = self
+            .find(Some(connected_address), connected_port)
+            .or_else(|| self.find(None, connected_port));
+        let Some(tunnel) = tunnel else {
+            warn!(
+                "Closing forwarded connection for unknown listener {}:{}",
+                connected_address, connected_port
+            );
+            tokio::spawn(async move {
+                let _ = channel.close().await;
+            });
+            return;
+        };
+
+        *self.connections.entry(tunnel.key()).or_default() += 1;
+        let bandwidth = self.bandwidth.get(&tunnel.key()).map(|limit| limit.clone());
+        tokio::spawn(async move {
+            let bridged = bridge(
+                channel,
+                &tunnel.local_host,
+                tunnel.local_port,
+                traffic,
+                bandwidth,
+            );
+            if let Err(e) = bridged.await {
+                debug!("Reverse forward connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// Copy data between a forwarded channel and a new connection to the target.
+async fn bridge(
+    channel: Channel<Msg>,
+    local_host: &str,
+    local_port: u16,
+    traffic: Arc<ByteCounters>,
+    bandwidth: Option<Arc<Bandwidth>>,
+) -> Result<(), String> {
+    let local = match tokio::time::timeout(
+        LOCAL_CONNECT_TIMEOUT,
+        TcpStream::connect((local_host, local_port)),
+    )
+    .await
+    {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            let _ = channel.close().await;
+            return Err(format!(
+                "Failed to connect to {}:{}: {}",
+                local_host, local_port, e
+            ));
+        }
+        Err(_) => {
+            let _ = channel.close().await;
+            return Err(format!(
+                "Failed to connect to {}:{}: timeout",
+                local_host, local_port
+            ));
+        }
+    };
+
+    let mut remote = Throttled::new(
+        CountingStream::new(channel.into_stream(), traffic),
+        bandwidth,
+    );
+    let mut local = local;
+    tokio::io::copy_bidirectional(&mut local, &mut remote)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Reverse forward tables of all sessions.
+pub(crate) struct ReverseRegistry {
+    sessions: DashMap<String, (Option<String>, Arc<ReverseForwards>)>,
+}
+
+impl ReverseRegistry {
+    pub fn new() -> Self {
+        Self {
+            sessions: DashMap::new(),
+        }
+    }
+
+    /// Register the reverse forward table of a new session owned by `owner`.
+    pub fn attach(&self, session_id: &str, owner: Option<&str>, forwards: Arc<ReverseForwards>) {
+        self.sessions.insert(
+            session_id.to_string(),
+            (owner.map(str::to_string), forwards),
+        );
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<Arc<ReverseForwards>> {
+        self.sessions
+            .get(session_id)
+            .map(|entry| entry.value().1.clone())
+    }
+
+    /// Drop a session's table; returns how many listeners it had. Listeners
+    /// die with the SSH connection.
+    pub fn detach(&self, session_id: &str) -> usize {
+        self.sessions
+            .remove(session_id)
+            .map_or(0, |(_, (_, forwards))| forwards.tunnels.len())
+    }
+
+    /// Remove a session's table for a reconnect by `caller`, returning the
+    /// listeners to re-establish. Tables owned by another agent are left alone.
+    pub fn take_for_reconnect(&self, session_id: &str, caller: Option<&str>) -> Vec<ReverseTunnel> {
+        let owned = self
+            .sessions
+            .get(session_id)
+            .is_some_and(|entry| entry.value().0.as_deref() == caller);
+        if !owned {
+            return Vec::new();
+        }
+        self.sessions
+            .remove(session_id)
+            .map(|(_, (_, forwards))| forwards.tunnels())
+            .unwrap_or_default()
+    }
+
+    /// Keep `tunnels` of a session whose reconnect failed, so a later reconnect
+    /// can try again. Nothing listens until then.
+    pub fn park(&self, session_id: &str, owner: Option<&str>, tunnels: Vec<ReverseTunnel>) {
+        if tunnels.is_empty() {
+            return;
+        }
+        let forwards = ReverseForwards::default();
+        for tunnel in tunnels {
+            forwards.tunnels.insert(tunnel.key(), tunnel);
+        }
+        self.attach(session_id, owner, Arc::new(forwards));
+    }
+
+    /// Session ids with at least one listener, sorted.
+    pub fn session_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|entry| !entry.value().1.tunnels.is_empty())
+            .map(|entry| entry.key().clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+}
+
+/// Re-request `tunnels` on a new connection. Returns the listeners that came
+/// back; failures are logged.
+pub(crate) async fn restore_tunnels(
+    handle: &client::Handle<SshClientHandler>,
+    forwards: &ReverseForwards,
+    tunnels: Vec<ReverseTunnel>,
+) -> Vec<ReverseTunnel> {
+    let mut restored = Vec::with_capacity(tunnels.len());
+    for tunnel in tunnels {
+        match forwards
+            .open(
+                handle,
+                &tunnel.bind_address,
+                tunnel.remote_port,
+                &tunnel.local_host,
+                tunnel.local_port,
+                tunnel.rate_limit,
+            )
+            .await
+        {
+            Ok(tunnel) => restored.push(tunnel),
+            Err(e) => warn!(
+                "Could not re-establish reverse forward {}:{} -> {}:{}: {}",
+                tunnel.bind_address, tunnel.remote_port, tunnel.local_host, tunnel.local_port, e
+            ),
+        }
+    }
+    restored
+}
+
+/// Describe a listener of `session_id` for tool responses.
+pub(crate) fn forward_info(
+    session_id: &str,
+    forwards: &ReverseForwards,
+    tunnel: &ReverseTunnel,
+) -> ReverseForwardInfo {
+    ReverseForwardInfo {
+        session_id: session_id.to_string(),
+        remote_address: format!("{}:{}", tunnel.bind_address, tunnel.remote_port),
+        remote_port: tunnel.remote_port as u16,
+        local_address: format!("{}:{}", tunnel.local_host, tunnel.local_port),
+        created_at: tunnel.created_at.clone(),
+        connections: forwards.connection_count(tunnel),
+        rate_limit_bytes_per_sec: tunnel.rate_limit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tunnel(bind_address: &str, remote_port: u32) -> ReverseTunnel {
+        ReverseTunnel {
+            bind_address: bind_address.to_string(),
+            remote_port,
+            local_host: DEFAULT_LOCAL_HOST.to_string(),
+            local_port: 8080,
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            rate_limit: None,
+        }
+    }
+
+    fn forwards_with(tunnels: &[ReverseTunnel]) -> Arc<ReverseForwards> {
+        let forwards = Arc::new(ReverseForwards::default());
+        for tunnel in tunnels {
+            forwards.tunnels.insert(tunnel.key(), tunnel.clone());
+        }
+        forwards
+    }
+
+    mod lookup {
+        use super::*;
+
+        #[test]
+        fn test_find_by_address_and_port() {
+            let forwards = forwards_with(&[tunnel("localhost", 9000), tunnel("0.0.0.0", 9001)]);
+            assert_eq!(
+                forwards.find(Some("localhost"), 9000),
+                Some(tunnel("localhost", 9000))
+            );
+            assert_eq!(forwards.find(Some("0.0.0.0"), 9000), None);
+            assert_eq!(forwards.find(None, 9001), Some(tunnel("0.0.0.0", 9001)));
+            assert_eq!(forwards.find(None, 9002), None);
+        }
+
+        #[test]
+        fn test_tunnels_sorted_by_port() {
+            let forwards = forwards_with(&[tunnel("localhost", 9001), tunnel("localhost", 9000)]);
+            let ports: Vec<u32> = forwards.tunnels().iter().map(|t| t.remote_port).collect();
+            assert_eq!(ports, vec![9000, 9001]);
+        }
+
+        #[test]
+        fn test_forward_info() {
+            let forwards = forwards_with(&[tunnel("localhost", 9000)]);
+            forwards
+                .connections
+                .insert(tunnel("localhost", 9000).key(), 3);
+            let info = forward_info("s-1", &forwards, &tunnel("localhost", 9000));
+            assert_eq!(info.remote_address, "localhost:9000");
+            assert_eq!(info.local_address, "127.0.0.1:8080");
+            assert_eq!(info.connections, 3);
+        }
+    }
+
+    mod registry {
+        use super::*;
+
+        #[test]
+        fn test_take_for_reconnect_by_owner() {
+            let registry = ReverseRegistry::new();
+            registry.attach(
+                "s-1",
+                Some("agent-a"),
+                forwards_with(&[tunnel("localhost", 9000)]),
+            );
+
+            assert!(
+                registry
+                    .take_for_reconnect("s-1", Some("agent-b"))
+                    .is_empty()
+            );
+            assert!(registry.get("s-1").is_some());
+
+            let tunnels = registry.take_for_reconnect("s-1", Some("agent-a"));
+            assert_eq!(tunnels, vec![tunnel("localhost", 9000)]);
+            assert!(registry.get("s-1").is_none());
+        }
+
+        #[test]
+        fn test_session_ids_skip_empty_tables() {
+            let registry = ReverseRegistry::new();
+            registry.attach("s-2", None, forwards_with(&[tunnel("localhost", 9000)]));
+            registry.attach("s-1", None, forwards_with(&[]));
+            assert_eq!(registry.session_ids(), vec!["s-2"]);
+
+            registry.detach("s-2");
+            assert!(registry.session_ids().is_empty());
+        }
+
+        #[test]
+        fn test_park_after_failed_reconnect() {
+            let registry = ReverseRegistry::new();
+            registry.park("s-1", Some("agent-a"), Vec::new());
+            assert!(registry.get("s-1").is_none());
+
+            registry.park("s-1", Some("agent-a"), vec![tunnel("localhost", 9000)]);
+            assert_eq!(
+                registry.take_for_reconnect("s-1", Some("agent-a")),
+                vec![tunnel("localhost", 9000)]
+            );
+        }
+    }
+}
@@ -2,9 +2,41 @@
 //!
 //! Generates standard JSON Schema without Rust-specific formats like "uint"
 //! that LLMs may not understand correctly.
+//!
+//! Tool input schemas are generated by the `#[Tools]` macro from the plain
+//! parameter types, which cannot carry schema attributes. [`enrich_tools_list`]
+//! therefore refines them by parameter name, and by tool where limits differ,
+//! when `tools/list` is served: it adds
+//! enums (terminal types, status values), numeric ranges for ports, timeouts and
+//! terminal sizes, and examples, so schema-aware clients reject invalid calls
+//! before sending them.
 
 use schemars::Schema;
 use schemars::json_schema;
+use serde_json::{Value, json};
+
+use super::batch::MAX_BATCH_CONCURRENCY;
+use super::config::MAX_COMPRESSION_LEVEL;
+use super::console::CONSOLE_PRESETS;
+use super::dag::MAX_DAG_CONCURRENCY;
+use super::transfer::MAX_UPLOAD_CONCURRENCY;
+
+/// Terminal types offered in the `term` parameter schema.
+pub const TERM_TYPES: &[&str] = &[
+    "xterm",
+    "xterm-256color",
+    "vt100",
+    "vt220",
+    "ansi",
+    "linux",
+    "screen",
+    "screen-256color",
+    "tmux-256color",
+    "dumb",
+];
+
+/// Async command status values accepted by status filters.
+pub const COMMAND_STATUS_VALUES: &[&str] = &["running", "completed", "cancelled", "failed"];
 
 /// Unsigned integer schema: `{"type": "integer", "minimum": 0}`
 ///
@@ -16,6 +48,155 @@ pub fn uint(_generator: &mut schemars::SchemaGenerator) -> Schema {
     })
 }
 
+/// Optional unsigned integer schema: `{"type": ["integer", "null"], "minimum": 0}`
+pub fn optional_uint(_generator: &mut schemars::SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": ["integer", "null"],
+        "minimum": 0
+    })
+}
+
+/// RFC 3339 timestamp schema: `{"type": "string", "format": "date-time"}`
+///
+/// Use with `#[schemars(schema_with = "crate::mcp::schema::datetime")]` on timestamp fields.
+pub fn datetime(_generator: &mut schemars::SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "string",
+        "format": "date-time"
+    })
+}
+
+/// Optional RFC 3339 timestamp schema.
+pub fn optional_datetime(_generator: &mut schemars::SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": ["string", "null"],
+        "format": "date-time"
+    })
+}
+
+/// Largest `concurrency` accepted by `tool`, for tools that have the parameter.
+fn max_concurrency(tool: &str) -> Option<usize> {
+    match tool {
+        "ssh_execute_batch" | "ssh_connect_many" => Some(MAX_BATCH_CONCURRENCY),
+        "ssh_execute_dag" => Some(MAX_DAG_CONCURRENCY),
+        "ssh_upload_dir" => Some(MAX_UPLOAD_CONCURRENCY),
+        _ => None,
+    }
+}
+
+/// Schema refinements for an input parameter of `tool`, keyed by parameter name.
+fn param_refinement(tool: &str, param: &str) -> Option<Value> {
+    let refinement = match param {
+        "local_port" | "remote_port" | "port" => json!({
+            "minimum": 1,
+            "maximum": 65535,
+            "examples": [22, 8080, 5432]
+        }),
+        "timeout_secs" => json!({"minimum": 1, "maximum": 86400, "examples": [30, 180, 600]}),
+        "wait_timeout_secs" => json!({"minimum": 1, "maximum": 300, "examples": [30, 120]}),
+        "max_retries" => json!({"minimum": 0, "maximum": 20}),
+        "compression_level" => json!({"minimum": 0, "maximum": MAX_COMPRESSION_LEVEL}),
+        "keepalive_interval_secs" => json!({"minimum": 0, "maximum": 3600, "examples": [10, 30]}),
+        "keepalive_max" => json!({"minimum": 0, "maximum": 100}),
+        "retry_delay_ms" => json!({"minimum": 0, "maximum": 60000}),
+        "cols" => json!({"minimum": 20, "maximum": 1000}),
+        "rows" => json!({"minimum": 5, "maximum": 500}),
+        "term" => json!({"enum": TERM_TYPES}),
+        "status" => json!({"enum": COMMAND_STATUS_VALUES}),
+        "wait_for" => json!({"enum": ["all", "any"]}),
+        "nice" => json!({"minimum": -20, "maximum": 19, "examples": [10, 19]}),
+        "ionice" => json!({"enum": ["idle", "best-effort", "realtime"]}),
+        "ttl_secs" => json!({"minimum": 1, "maximum": 3600}),
+        "max_matches" => json!({"minimum": 1, "maximum": 10000}),
+        "concurrency" => match max_concurrency(tool) {
+            Some(max) => json!({"minimum": 1, "maximum": max}),
+            None => json!({"minimum": 1}),
+        },
+        "max_bytes" => json!({"minimum": 1}),
+        "rate_limit_bytes_per_sec" => json!({"minimum": 1024, "examples": [1048576, 10485760]}),
+        "label" => json!({"maxLength": 200}),
+        "nodes" => json!({"minItems": 1, "maxItems": 100}),
+        "paths" => json!({"minItems": 1, "maxItems": 500}),
+        "console" => json!({
+            "enum": CONSOLE_PRESETS.iter().map(|preset| preset.name).collect::<Vec<_>>()
+        }),
+        "backend" => json!({"enum": ["tmux", "screen"]}),
+        "share_mode" => json!({"enum": ["read_only", "full"]}),
+        "command_ids" => json!({"minItems": 1, "maxItems": 500}),
+        "address" => json!({
+            "minLength": 1,
+            "examples": ["192.168.1.10:22", "db.example.com", "[2001:db8::1]:2222"]
+        }),
+        "session_id" | "command_id" | "shell_id" | "agent_id" | "username" | "command" | "host"
+        | "path" | "link_path" | "target" | "pattern" | "local_path" | "remote_path"
+        | "extract_to" => {
+            json!({"minLength": 1})
+        }
+        _ => return None,
+    };
+    Some(refinement)
+}
+
+/// Refine the input schema of `tool` in place.
+///
+/// Removes Rust-specific `uint*` formats and merges the per-parameter
+/// refinements. Enum values get `null` added when the parameter is optional.
+pub fn enrich_input_schema(tool: &str, schema: &mut Value) {
+    let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) else {
+        return;
+    };
+
+    for (name, property) in properties.iter_mut() {
+        let Some(property) = property.as_object_mut() else {
+            continue;
+        };
+
+        if property
+            .get("format")
+            .and_then(Value::as_str)
+            .is_some_and(|format| format.starts_with("uint") || format.starts_with("int"))
+        {
+            property.remove("format");
+        }
+
+        let Some(Value::Object(refinement)) = param_refinement(tool, name) else {
+            continue;
+        };
+        let nullable = property
+            .get("type")
+            .and_then(Value::as_array)
+            .is_some_and(|types| types.iter().any(|t| t == "null"));
+
+        for (key, mut value) in refinement {
+            if key == "enum"
+                && nullable
+                && let Some(values) = value.as_array_mut()
+            {
+                values.push(Value::Null);
+            }
+            property.insert(key, value);
+        }
+    }
+}
+
+/// Refine the input schema of every tool in a `tools/list` result.
+pub fn enrich_tools_list(result: &mut Value) {
+    let Some(tools) = result.get_mut("tools").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    for tool in tools {
+        let name = tool
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        if let Some(schema) = tool.get_mut("inputSchema") {
+            enrich_input_schema(&name, schema);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,4 +296,138 @@ mod tests {
             json_str
         );
     }
+
+    mod input_schemas {
+        use super::*;
+        use crate::mcp::McpSSHCommands;
+        use poem_mcpserver::tool::Tools;
+
+        fn input_schema(tool_name: &str) -> Value {
+            let mut schema = McpSSHCommands::list()
+                .into_iter()
+                .find(|tool| tool.name == tool_name)
+                .map(|tool| tool.input_schema)
+                .expect("tool exists");
+            enrich_input_schema(tool_name, &mut schema);
+            schema
+        }
+
+        #[test]
+        fn test_no_uint_formats_remain() {
+            for tool in McpSSHCommands::list() {
+                let mut schema = tool.input_schema;
+                enrich_input_schema(tool.name, &mut schema);
+                let json_str = serde_json::to_string(&schema).unwrap();
+                assert!(
+                    !json_str.contains("\"uint"),
+                    "{} input schema still has uint format: {}",
+                    tool.name,
+                    json_str
+                );
+            }
+        }
+
+        #[test]
+        fn test_port_range() {
+            let schema = input_schema("ssh_forward");
+            let port = &schema["properties"]["local_port"];
+            assert_eq!(port["minimum"], 1);
+            assert_eq!(port["maximum"], 65535);
+        }
+
+        #[test]
+        fn test_compression_level_range() {
+            let schema = input_schema("ssh_connect");
+            let level = &schema["properties"]["compression_level"];
+            assert_eq!(level["minimum"], 0);
+            assert_eq!(level["maximum"], 1);
+        }
+
+        #[test]
+        fn test_optional_term_enum_allows_null() {
+            let schema = input_schema("ssh_shell_open");
+            let values = schema["properties"]["term"]["enum"].as_array().unwrap();
+            assert!(values.contains(&json!("vt100")));
+            assert!(values.contains(&Value::Null));
+        }
+
+        #[test]
+        fn test_status_enum_matches_command_status() {
+            use crate::mcp::types::AsyncCommandStatus;
+
+            let schema = input_schema("ssh_list_commands");
+            let values = schema["properties"]["status"]["enum"].as_array().unwrap();
+            for status in [
+                AsyncCommandStatus::Running,
+                AsyncCommandStatus::Completed,
+                AsyncCommandStatus::Cancelled,
+                AsyncCommandStatus::Failed,
+            ] {
+                assert!(values.contains(&serde_json::to_value(status).unwrap()));
+            }
+        }
+
+        #[test]
+        fn test_concurrency_bounds_match_each_tool() {
+            let limits = [
+                ("ssh_connect_many", MAX_BATCH_CONCURRENCY),
+                ("ssh_execute_batch", MAX_BATCH_CONCURRENCY),
+                ("ssh_execute_dag", MAX_DAG_CONCURRENCY),
+                ("ssh_upload_dir", MAX_UPLOAD_CONCURRENCY),
+            ];
+            for (tool, max) in limits {
+                let concurrency = &input_schema(tool)["properties"]["concurrency"];
+                assert_eq!(concurrency["minimum"], 1, "{}", tool);
+                assert_eq!(concurrency["maximum"], max, "{}", tool);
+            }
+
+            // Every tool taking concurrency has its limit listed
+            for tool in McpSSHCommands::list() {
+                if tool.input_schema["properties"].get("concurrency").is_some() {
+                    assert!(
+                        limits.iter().any(|(name, _)| *name == tool.name),
+                        "{} takes concurrency without a schema limit",
+                        tool.name
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_wait_timeout_bounds() {
+            let schema = input_schema("ssh_get_command_output");
+            assert_eq!(schema["properties"]["wait_timeout_secs"]["maximum"], 300);
+        }
+
+        #[test]
+        fn test_required_ids_non_empty() {
+            let schema = input_schema("ssh_execute");
+            assert_eq!(schema["properties"]["session_id"]["minLength"], 1);
+            assert_eq!(schema["properties"]["command"]["minLength"], 1);
+        }
+
+        #[test]
+        fn test_enrich_tools_list_ignores_other_results() {
+            let mut result = json!({"content": []});
+            enrich_tools_list(&mut result);
+            assert_eq!(result, json!({"content": []}));
+        }
+    }
+
+    mod datetime_schemas {
+        use schemars::SchemaGenerator;
+
+        #[test]
+        fn test_session_info_timestamps_are_date_time() {
+            use crate::mcp::types::SessionInfo;
+
+            let schema = SchemaGenerator::default().into_root_schema_for::<SessionInfo>();
+            let json = serde_json::to_value(&schema).unwrap();
+            assert_eq!(json["properties"]["connected_at"]["format"], "date-time");
+            assert_eq!(
+                json["properties"]["last_health_check"]["format"],
+                "date-time"
+            );
+        }
+    }
 }
@@ -0,0 +1,1347 @@
+//! Startup configuration self-test (`--check-config`, `ssh_check_config`).
+//!
+//! Most settings fall back to defaults when they are malformed: a typo in
+//! `SSH_COMMAND_TIMEOUT` is silently ignored, an unreadable `SSH_MCP_CONFIG`
+//! file is replaced by built-in defaults, and a bad managed host key only shows
+//! up when the host is first dialed. The self-test checks all of it up front:
+//!
+//! - environment variables parse as the expected type and range
+//! - the known_hosts file is readable (or can be created under `accept-new`)
+//! - the `SSH_CONFIG_FILE` ssh_config, when set, is readable
+//! - `MCP_TLS_CERT` and `MCP_TLS_KEY`, when set, name a PEM certificate
+//!   chain and private key
+//! - the `SSH_MCP_CONFIG` file is readable and valid
+//! - `[tools]` entries name existing tools or groups
+//! - managed hosts have valid names and addresses, loadable keys and set
+//!   password variables
+//! - audit exporters have a usable target
+//! - the helper library directory can be read
+//! - `[[policy.deny]]` and `[[policy.allow]]` rules have a pattern, and the
+//!   policy (file, regexes) loads
+//! - optionally, the `[self_test] canary` managed host accepts a connection
+//!
+//! Both binaries run it before serving and refuse to start when a check
+//! reports an error; `--check-config` prints the report and exits.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use poem_mcpserver::tool::Tools;
+use russh::keys;
+use tracing::{error, info, warn};
+
+use super::async_command::OutputOverflow;
+use super::client::parse_address;
+use super::commands::McpSSHCommands;
+use super::config::{
+    AUTO_RECONNECT_ENV_VAR, AuditExporterKind, AuthTokenConfig, CHANNEL_IDLE_TIMEOUT_ENV_VAR,
+    CHANNEL_OPEN_TIMEOUT_ENV_VAR, COMMAND_TIMEOUT_ENV_VAR, COMPRESSION_ENV_VAR,
+    COMPRESSION_LEVEL_ENV_VAR, CONFIG_FILE_ENV_VAR, CONNECT_TIMEOUT_ENV_VAR, CredentialConfig,
+    CredentialSource, HealthMethod, IDENTITY_PROBE_ENV_VAR, INACTIVITY_TIMEOUT_ENV_VAR,
+    MAX_COMPRESSION_LEVEL, MAX_OUTPUT_BYTES_ENV_VAR, MAX_RETRIES_ENV_VAR, MAX_SESSIONS_ENV_VAR,
+    MAX_SESSIONS_PER_AGENT_ENV_VAR, MAX_SESSIONS_PER_HOST_ENV_VAR, OUTPUT_OVERFLOW_ENV_VAR,
+    ProfileConfig, READ_ONLY_ENV_VAR, RETRY_DELAY_MS_ENV_VAR, STORAGE_BACKEND_ENV_VAR,
+    ServerConfig, StorageBackend, TOOL_PREFIX_ENV_VAR, VaultConfig, config_file_path,
+    resolve_storage_backend, server_config, set_config_file, ssh_config::SSH_CONFIG_ENV_VAR,
+};
+use super::credentials::{VAULT_ADDR_ENV_VAR, parse_vault_url};
+use super::exposure::TOOL_GROUPS;
+use super::helpers::HelperLibrary;
+use super::host_limit::host_key;
+use super::known_hosts::{
+    HostKeyChecking, KNOWN_HOSTS_ENV_VAR, STRICT_HOST_KEY_CHECKING_ENV_VAR,
+    resolve_host_key_checking, resolve_known_hosts_path,
+};
+use super::managed::{is_valid_managed_name, probe};
+use super::policy::Policy;
+use super::profiles::{load_profiles, profile_address};
+use super::siem::parse_http_url;
+use super::storage;
+use super::transport::tls::TlsFiles;
+use super::transport::{HttpTransports, MCP_TRANSPORT_ENV_VAR};
+use super::types::{CheckLevel, ConfigCheck, SshCheckConfigResponse};
+
+/// Port of the streamable HTTP server (`ssh-mcp` binary)
+pub const MCP_PORT_ENV_VAR: &str = "MCP_PORT";
+
+/// Port served when neither `MCP_PORT` nor `[server] port` is set
+pub const DEFAULT_MCP_PORT: u16 = 8000;
+
+/// Command-line flag that prints the report and exits
+pub const CHECK_CONFIG_FLAG: &str = "--check-config";
+
+/// Command-line flag naming the server configuration file (over `SSH_MCP_CONFIG`)
+pub const CONFIG_FLAG: &str = "--config";
+
+/// Apply `--config <path>` (or `--config=<path>`) from the command-line
+/// arguments, ahead of `SSH_MCP_CONFIG`.
+///
+/// Call before anything reads the configuration; the self-test then reports
+/// whether the file loads.
+pub fn apply_config_arg(args: &[String]) -> Result<(), String> {
+    match config_file_arg(args)? {
+        Some(path) => use_config_file(path),
+        None => Ok(()),
+    }
+}
+
+/// Use `path` as the server configuration file instead of `SSH_MCP_CONFIG`.
+///
+/// Call before anything reads the configuration.
+pub fn use_config_file(path: String) -> Result<(), String> {
+    set_config_file(path)
+}
+
+/// Value of the [`CONFIG_FLAG`] argument, if given.
+fn config_file_arg(args: &[String]) -> Result<Option<String>, String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == CONFIG_FLAG {
+            return match args.next() {
+                Some(path) if !path.starts_with("--") => Ok(Some(path.clone())),
+                _ => Err(format!("{} needs a file path", CONFIG_FLAG)),
+            };
+        }
+        if let Some(path) = arg
+            .strip_prefix(CONFIG_FLAG)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Ok(Some(path.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Port of the HTTP server: `MCP_PORT`, then `[server] port`, then 8000.
+pub fn mcp_port() -> u16 {
+    env::var(MCP_PORT_ENV_VAR)
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .filter(|port| *port != 0)
+        .or(server_config().server.port)
+        .unwrap_or(DEFAULT_MCP_PORT)
+}
+
+/// Expected shape of an environment variable's value.
+#[derive(Debug, Clone, Copy)]
+enum EnvKind {
+    /// Whole number up to `max`; `0` falls back to the default unless `zero_ok`
+    Number {
+        unit: &'static str,
+        max: u64,
+        zero_ok: bool,
+    },
+    /// Whole number clamped to `max`
+    Clamped { max: u64 },
+    /// `true`/`false`/`1`/`0`; anything else means `other`
+    Flag { other: bool },
+    /// Tool name prefix: `[A-Za-z0-9_-]*`
+    ToolPrefix,
+    /// `yes`, `accept-new` or `no`
+    HostKeyChecking,
+    /// `streamable_http`, `sse` or `both`
+    Transport,
+    /// `ring` or `head`
+    OutputOverflow,
+    /// `memory`
+    StorageBackend,
+}
+
+const fn number(unit: &'static str, max: u64) -> EnvKind {
+    EnvKind::Number {
+        unit,
+        max,
+        zero_ok: true,
+    }
+}
+
+const ENV_VARS: &[(&str, EnvKind)] = &[
+    (CONNECT_TIMEOUT_ENV_VAR, number("seconds", u64::MAX)),
+    (COMMAND_TIMEOUT_ENV_VAR, number("seconds", u64::MAX)),
+    (MAX_RETRIES_ENV_VAR, number("retries", u32::MAX as u64)),
+    (RETRY_DELAY_MS_ENV_VAR, number("milliseconds", u64::MAX)),
+    (INACTIVITY_TIMEOUT_ENV_VAR, number("seconds", u64::MAX)),
+    (
+        CHANNEL_OPEN_TIMEOUT_ENV_VAR,
+        EnvKind::Number {
+            unit: "seconds",
+            max: u64::MAX,
+            zero_ok: false,
+        },
+    ),
+    (CHANNEL_IDLE_TIMEOUT_ENV_VAR, number("seconds", u64::MAX)),
+    (COMPRESSION_ENV_VAR, EnvKind::Flag { other: false }),
+    (
+        COMPRESSION_LEVEL_ENV_VAR,
+        EnvKind::Clamped {
+            max: MAX_COMPRESSION_LEVEL as u64,
+        },
+    ),
+    (IDENTITY_PROBE_ENV_VAR, EnvKind::Flag { other: true }),
+    (AUTO_RECONNECT_ENV_VAR, EnvKind::Flag { other: false }),
+    (READ_ONLY_ENV_VAR, EnvKind::Flag { other: false }),
+    (MAX_SESSIONS_ENV_VAR, number("sessions", u64::MAX)),
+    (MAX_SESSIONS_PER_AGENT_ENV_VAR, number("sessions", u64::MAX)),
+    (MAX_SESSIONS_PER_HOST_ENV_VAR, number("sessions", u64::MAX)),
+    (TOOL_PREFIX_ENV_VAR, EnvKind::ToolPrefix),
+    (STRICT_HOST_KEY_CHECKING_ENV_VAR, EnvKind::HostKeyChecking),
+    (MCP_PORT_ENV_VAR, number("port", u16::MAX as u64)),
+    (MCP_TRANSPORT_ENV_VAR, EnvKind::Transport),
+    (MAX_OUTPUT_BYTES_ENV_VAR, number("bytes", u64::MAX)),
+    (OUTPUT_OVERFLOW_ENV_VAR, EnvKind::OutputOverflow),
+    (STORAGE_BACKEND_ENV_VAR, EnvKind::StorageBackend),
+];
+
+fn check(name: impl Into<String>, level: CheckLevel, message: impl Into<String>) -> ConfigCheck {
+    ConfigCheck {
+        name: name.into(),
+        level,
+        message: message.into(),
+    }
+}
+
+/// Check one environment variable value against its expected shape.
+fn check_env_value(var: &str, kind: EnvKind, value: &str) -> ConfigCheck {
+    let (level, message) = match kind {
+        EnvKind::Number { unit, max, zero_ok } => match value.parse::<u64>() {
+            Ok(n) if n > max => (
+                CheckLevel::Error,
+                format!("{} is above the maximum {}; ignored", n, max),
+            ),
+            Ok(0) if !zero_ok => (CheckLevel::Error, "0 is not allowed; ignored".to_string()),
+            Ok(n) => (CheckLevel::Ok, format!("{} {}", n, unit)),
+            Err(_) => (
+                CheckLevel::Error,
+                format!("'{}' is not a whole number of {}; ignored", value, unit),
+            ),
+        },
+        EnvKind::Clamped { max } => match value.parse::<u64>() {
+            Ok(n) if n > max => (
+                CheckLevel::Warning,
+                format!("{} is above the maximum; clamped to {}", n, max),
+            ),
+            Ok(n) => (CheckLevel::Ok, n.to_string()),
+            Err(_) => (
+                CheckLevel::Error,
+                format!("'{}' is not a whole number; ignored", value),
+            ),
+        },
+        EnvKind::Flag { other } => {
+            if ["true", "false", "1", "0"]
+                .iter()
+                .any(|flag| value.eq_ignore_ascii_case(flag))
+            {
+                (CheckLevel::Ok, value.to_string())
+            } else {
+                (
+                    CheckLevel::Warning,
+                    format!("'{}' is not true/false/1/0; treated as {}", value, other),
+                )
+            }
+        }
+        EnvKind::ToolPrefix => {
+            if value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                (CheckLevel::Ok, format!("'{}'", value))
+            } else {
+                (
+                    CheckLevel::Error,
+                    format!(
+                        "'{}' may only contain letters, digits, '_' and '-'; ignored",
+                        value
+                    ),
+                )
+            }
+        }
+        EnvKind::HostKeyChecking => match HostKeyChecking::parse(value) {
+            Ok(mode) => (CheckLevel::Ok, mode.as_str().to_string()),
+            Err(e) => (CheckLevel::Error, format!("{}; using accept-new", e)),
+        },
+        EnvKind::Transport => match HttpTransports::parse(value) {
+            Ok(transports) => (CheckLevel::Ok, transports.as_str().to_string()),
+            Err(e) => (CheckLevel::Error, format!("{}; serving both", e)),
+        },
+        EnvKind::OutputOverflow => match OutputOverflow::parse(value) {
+            Ok(_) => (CheckLevel::Ok, value.trim().to_ascii_lowercase()),
+            Err(e) => (CheckLevel::Error, format!("{}; using ring", e)),
+        },
+        EnvKind::StorageBackend => match StorageBackend::parse(value) {
+            Ok(backend) => (CheckLevel::Ok, backend.as_str().to_string()),
+            Err(e) => (
+                CheckLevel::Error,
+                format!("{}; using the [storage] backend", e),
+            ),
+        },
+    };
+    check(var, level, message)
+}
+
+/// Check every environment variable that is set.
+fn check_env() -> Vec<ConfigCheck> {
+    ENV_VARS
+        .iter()
+        .filter_map(|(var, kind)| {
+            env::var(var)
+                .ok()
+                .map(|value| check_env_value(var, *kind, &value))
+        })
+        .collect()
+}
+
+/// Check that the configuration file (`--config` or `SSH_MCP_CONFIG`), when
+/// given, loads and is valid.
+///
+/// The running server falls back to defaults for a broken file, so this is
+/// the only place its error is reported in full.
+fn check_config_file() -> ConfigCheck {
+    match config_file_path() {
+        None => check(
+            "config_file",
+            CheckLevel::Ok,
+            format!(
+                "Neither {} nor {} given; using built-in defaults",
+                CONFIG_FLAG, CONFIG_FILE_ENV_VAR
+            ),
+        ),
+        Some(path) => match ServerConfig::from_file(Path::new(&path)) {
+            Ok(_) => check("config_file", CheckLevel::Ok, format!("Loaded {}", path)),
+            Err(e) => check("config_file", CheckLevel::Error, e),
+        },
+    }
+}
+
+/// Check that an explicitly set `SSH_CONFIG_FILE` can be read.
+///
+/// Only `use_ssh_config` connects read it, so a broken file is a warning.
+fn check_ssh_config(path: Option<&Path>) -> Option<ConfigCheck> {
+    let path = path?;
+    Some(match fs::read_to_string(path) {
+        Ok(_) => check(
+            "ssh_config",
+            CheckLevel::Ok,
+            format!("Readable {}", path.display()),
+        ),
+        Err(e) => check(
+            "ssh_config",
+            CheckLevel::Warning,
+            format!(
+                "{} {} is not readable: {}",
+                SSH_CONFIG_ENV_VAR,
+                path.display(),
+                e
+            ),
+        ),
+    })
+}
+
+/// Check the TLS files of the HTTP listener, when TLS is configured.
+fn check_tls(files: Result<Option<TlsFiles>, String>) -> Option<ConfigCheck> {
+    let files = match files {
+        Ok(files) => files?,
+        Err(e) => return Some(check("tls", CheckLevel::Error, e)),
+    };
+    Some(match files.check() {
+        Ok(certs) => check(
+            "tls",
+            CheckLevel::Ok,
+            format!(
+                "HTTPS with {} ({} certificate(s)) and key {}",
+                files.cert, certs, files.key
+            ),
+        ),
+        Err(e) => check("tls", CheckLevel::Error, e),
+    })
+}
+
+/// Check that the known_hosts file can be used in the default checking mode.
+fn check_known_hosts(mode: HostKeyChecking, path: Option<&Path>) -> ConfigCheck {
+    let name = "known_hosts";
+    if mode == HostKeyChecking::No {
+        return check(
+            name,
+            CheckLevel::Warning,
+            "Host keys are not verified (strict_host_key_checking=no)",
+        );
+    }
+    let Some(path) = path else {
+        let level = match mode {
+            HostKeyChecking::Yes => CheckLevel::Error,
+            _ => CheckLevel::Warning,
+        };
+        return check(
+            name,
+            level,
+            format!(
+                "No home directory and {} not set; host keys cannot be checked",
+                KNOWN_HOSTS_ENV_VAR
+            ),
+        );
+    };
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            let entries = content
+                .lines()
+                .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                .count();
+            check(
+                name,
+                CheckLevel::Ok,
+                format!(
+                    "{} entries in {} ({})",
+                    entries,
+                    path.display(),
+                    mode.as_str()
+                ),
+            )
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match mode {
+            HostKeyChecking::Yes => check(
+                name,
+                CheckLevel::Error,
+                format!(
+                    "{} does not exist; every host would be rejected",
+                    path.display()
+                ),
+            ),
+            _ => check(
+                name,
+                CheckLevel::Ok,
+                format!("{} will be created on first connect", path.display()),
+            ),
+        },
+        Err(e) => check(
+            name,
+            CheckLevel::Error,
+            format!("Cannot read {}: {}", path.display(), e),
+        ),
+    }
+}
+
+/// Check the contents of a loaded configuration.
+fn check_server_config(config: &ServerConfig) -> Vec<ConfigCheck> {
+    let mut checks = Vec::new();
+
+    let tools: Vec<&str> = McpSSHCommands::list()
+        .into_iter()
+        .map(|tool| tool.name)
+        .collect();
+    let entries = config.tools.enabled.iter().flatten();
+    for entry in entries.chain(&config.tools.disabled) {
+        let known =
+            tools.contains(&entry.as_str()) || TOOL_GROUPS.iter().any(|(group, _)| group == entry);
+        if !known {
+            checks.push(check(
+                "tools",
+                CheckLevel::Warning,
+                format!(
+                    "Unknown tool or group '{}' (not in this build); ignored",
+                    entry
+                ),
+            ));
+        }
+    }
+
+    let mut seen = Vec::new();
+    for host in &config.managed_hosts {
+        let name = format!("managed_host:{}", host.name);
+        if !is_valid_managed_name(&host.name) {
+            checks.push(check(
+                name,
+                CheckLevel::Error,
+                "Invalid name: use 1-64 letters, digits, '.', '_' or '-'; host ignored",
+            ));
+            continue;
+        }
+        if seen.contains(&host.name) {
+            checks.push(check(
+                name,
+                CheckLevel::Error,
+                "Duplicate name; only the first entry is used",
+            ));
+            continue;
+        }
+        seen.push(host.name.clone());
+
+        let mut problems = Vec::new();
+        if let Err(e) = parse_address(&host.address) {
+            problems.push(format!("address '{}': {}", host.address, e));
+        }
+        if let Some(path) = &host.key_path
+            && let Err(e) = keys::load_secret_key(path, None)
+        {
+            problems.push(format!("cannot load key {}: {}", path, e));
+        }
+        if let Some(var) = &host.password_env
+            && env::var(var).is_err()
+        {
+            problems.push(format!("password variable {} is not set", var));
+        }
+        checks.push(if problems.is_empty() {
+            check(
+                name,
+                CheckLevel::Ok,
+                format!("{}@{}", host.username, host.address),
+            )
+        } else {
+            check(name, CheckLevel::Error, problems.join("; "))
+        });
+    }
+
+    for (index, exporter) in config.audit.exporters.iter().enumerate() {
+        let name = format!("audit_exporter:{}", index);
+        let target = match exporter.kind {
+            AuditExporterKind::Syslog => exporter
+                .address
+                .clone()
+                .ok_or_else(|| "syslog exporter requires 'address'".to_string()),
+            AuditExporterKind::Http => exporter
+                .url
+                .as_deref()
+                .ok_or_else(|| "http exporter requires 'url'".to_string())
+                .and_then(|url| parse_http_url(url).map(|_| url.to_string())),
+            AuditExporterKind::File => exporter
+                .path
+                .clone()
+                .ok_or_else(|| "file exporter requires 'path'".to_string()),
+        };
+        checks.push(match target {
+            Ok(target) => check(name, CheckLevel::Ok, target),
+            Err(e) => check(name, CheckLevel::Error, format!("{}; exporter disabled", e)),
+        });
+    }
+
+    match HelperLibrary::load(&config.helpers) {
+        Ok(None) => {}
+        Ok(Some(library)) => checks.push(check(
+            "helpers",
+            CheckLevel::Ok,
+            format!(
+                "{} helper(s) in {}",
+                library.helpers.len(),
+                library.dir.display()
+            ),
+        )),
+        Err(e) => checks.push(check("helpers", CheckLevel::Error, e)),
+    }
+
+    if config.elicitation.password_prompt {
+        checks.push(check(
+            "elicitation",
+            CheckLevel::Warning,
+            "password_prompt asks users for SSH passwords through form elicitation, which the MCP specification forbids for sensitive data; prefer credential_ref",
+        ));
+    }
+
+    if let Some(root) = &config.transfer.local_root {
+        checks.push(if Path::new(root).is_dir() {
+            check("transfer", CheckLevel::Ok, root)
+        } else {
+            check(
+                "transfer",
+                CheckLevel::Error,
+                format!(
+                    "local_root {} is not a directory; ssh_upload_dir and ssh_collect are refused",
+                    root
+                ),
+            )
+        });
+    }
+
+    let rules = [
+        ("deny", &config.policy.deny),
+        ("allow", &config.policy.allow),
+    ];
+    for (kind, rules) in rules {
+        for (index, rule) in rules.iter().enumerate() {
+            if rule.pattern.trim().is_empty() {
+                checks.push(check(
+                    format!("policy_{}:{}", kind, index),
+                    CheckLevel::Warning,
+                    "Empty pattern only matches an empty command; rule has no effect",
+                ));
+            }
+        }
+    }
+    if let Err(e) = Policy::load(&config.policy) {
+        checks.push(check(
+            "policy",
+            CheckLevel::Error,
+            format!("{}; every command is refused", e),
+        ));
+    }
+
+    for (name, credential) in &config.credentials {
+        checks.push(check_credential(name, credential, &config.vault));
+    }
+
+    match load_profiles(&config.profiles) {
+        Ok(profiles) => {
+            for (name, profile) in &profiles {
+                checks.push(check_profile(name, profile, config));
+            }
+        }
+        Err(e) => checks.push(check(
+            "profiles",
+            CheckLevel::Error,
+            format!("{}; no profile is available", e),
+        )),
+    }
+
+    for token in &config.auth.tokens {
+        checks.push(check_auth_token(token));
+    }
+
+    if config.health.method == HealthMethod::Command && config.health.command.trim().is_empty() {
+        checks.push(check(
+            "health",
+            CheckLevel::Error,
+            "method = \"command\" needs a non-empty command; use method = \"keepalive\" to skip running one",
+        ));
+    }
+
+    let backend = resolve_storage_backend(config.storage.backend);
+    if backend != StorageBackend::Memory {
+        checks.push(match storage::backend::check(backend, &config.storage) {
+            Ok(()) => check("storage", CheckLevel::Ok, backend.as_str()),
+            Err(e) => check(
+                "storage",
+                CheckLevel::Error,
+                format!("{}; sessions are kept in memory", e),
+            ),
+        });
+    }
+
+    if let Some(canary) = &config.self_test.canary
+        && !config.managed_hosts.iter().any(|host| &host.name == canary)
+    {
+        checks.push(check(
+            "canary",
+            CheckLevel::Error,
+            format!("'{}' is not a managed host", canary),
+        ));
+    }
+
+    checks
+}
+
+/// Check that a named credential can be resolved, without fetching Vault secrets.
+fn check_credential(name: &str, credential: &CredentialConfig, vault: &VaultConfig) -> ConfigCheck {
+    let mut problems = Vec::new();
+    if let Some(path) = &credential.key_path
+        && !Path::new(path).is_file()
+    {
+        problems.push(format!("key file {} does not exist", path));
+    }
+    if credential.hosts.is_empty() {
+        problems.push("no hosts: the credential cannot be used for any connection".to_string());
+    }
+    let source = match credential.source {
+        CredentialSource::Env => {
+            let vars = [&credential.password_env, &credential.key_data_env];
+            for var in vars.into_iter().flatten() {
+                if env::var(var).is_err() {
+                    problems.push(format!("variable {} is not set", var));
+                }
+            }
+            "environment".to_string()
+        }
+        CredentialSource::Vault => {
+            let address = vault
+                .address
+                .clone()
+                .or_else(|| env::var(VAULT_ADDR_ENV_VAR).ok());
+            match address {
+                Some(address) => {
+                    if let Err(e) = parse_vault_url(&address) {
+                        problems.push(format!("Vault address: {}", e));
+                    }
+                }
+                None => problems.push("no Vault address: set [vault] address or VAULT_ADDR".into()),
+            }
+            match &credential.path {
+                Some(path) => format!("vault {}", path),
+                None => {
+                    problems.push("vault credentials need a 'path'".to_string());
+                    "vault".to_string()
+                }
+            }
+        }
+    };
+    let name = format!("credential:{}", name);
+    if problems.is_empty() {
+        check(name, CheckLevel::Ok, source)
+    } else {
+        check(name, CheckLevel::Error, problems.join("; "))
+    }
+}
+
+/// Check that an HTTP token's variable is set, without revealing it.
+fn check_auth_token(token: &AuthTokenConfig) -> ConfigCheck {
+    let name = format!("auth_token:{}", token.name);
+    if token.token_env.is_empty() {
+        return check(name, CheckLevel::Error, "needs token_env");
+    }
+    if env::var(&token.token_env).map_or(true, |value| value.is_empty()) {
+        return check(
+            name,
+            CheckLevel::Error,
+            format!(
+                "variable {} is not set; the token is not accepted",
+                token.token_env
+            ),
+        );
+    }
+    match &token.agent_id {
+        Some(agent_id) => check(
+            name,
+            CheckLevel::Ok,
+            format!("bound to agent '{}'", agent_id),
+        ),
+        None => check(name, CheckLevel::Ok, "not bound to an agent"),
+    }
+}
+
+/// Check that a profile's key file and credential exist.
+fn check_profile(name: &str, profile: &ProfileConfig, config: &ServerConfig) -> ConfigCheck {
+    let mut problems = Vec::new();
+    if let Some(path) = &profile.key_path
+        && !Path::new(path).is_file()
+    {
+        problems.push(format!("key file {} does not exist", path));
+    }
+    if let Some(credential) = &profile.credential_ref {
+        match config.credentials.get(credential) {
+            None => problems.push(format!("unknown credential_ref '{}'", credential)),
+            Some(entry) if !entry.allows_host(&host_key(&profile.host)) => problems.push(format!(
+                "credential '{}' does not list host {}",
+                credential, profile.host
+            )),
+            Some(_) => {}
+        }
+    }
+    let name = format!("profile:{}", name);
+    if problems.is_empty() {
+        check(name, CheckLevel::Ok, profile_address(profile))
+    } else {
+        check(name, CheckLevel::Error, problems.join("; "))
+    }
+}
+
+/// Dial the configured canary host once.
+async fn check_canary(config: &ServerConfig) -> Option<ConfigCheck> {
+    let canary = config.self_test.canary.as_deref()?;
+    let host = config
+        .managed_hosts
+        .iter()
+        .find(|host| host.name == canary)?;
+    let started = Instant::now();
+    Some(match probe(host).await {
+        Ok(()) => check(
+            "canary",
+            CheckLevel::Ok,
+            format!(
+                "Connected to {} in {} ms",
+                canary,
+                started.elapsed().as_millis()
+            ),
+        ),
+        Err(e) => check("canary", CheckLevel::Error, e),
+    })
+}
+
+/// Summarize a list of checks.
+fn report(checks: Vec<ConfigCheck>) -> SshCheckConfigResponse {
+    let count = |level| checks.iter().filter(|check| check.level == level).count();
+    let errors = count(CheckLevel::Error);
+    let warnings = count(CheckLevel::Warning);
+    let message = if errors == 0 {
+        format!(
+            "Configuration OK: {} check(s), {} warning(s)",
+            checks.len(),
+            warnings
+        )
+    } else {
+        format!(
+            "Configuration invalid: {} error(s), {} warning(s)",
+            errors, warnings
+        )
+    };
+    SshCheckConfigResponse {
+        ok: errors == 0,
+        errors,
+        warnings,
+        checks,
+        message,
+    }
+}
+
+/// Run every check; dials the canary host when `dial_canary` is set.
+pub async fn check_config(dial_canary: bool) -> SshCheckConfigResponse {
+    let config = server_config();
+    let mut checks = check_env();
+    checks.push(check_config_file());
+    checks.push(check_known_hosts(
+        resolve_host_key_checking(None),
+        resolve_known_hosts_path().as_deref(),
+    ));
+    checks.extend(check_ssh_config(
+        env::var_os(SSH_CONFIG_ENV_VAR)
+            .map(PathBuf::from)
+            .as_deref(),
+    ));
+    checks.extend(check_tls(TlsFiles::resolve()));
+    checks.extend(check_server_config(config));
+    if dial_canary && let Some(canary) = check_canary(config).await {
+        checks.push(canary);
+    }
+    report(checks)
+}
+
+/// Render a report as one line per check, for `--check-config`.
+pub fn render_report(report: &SshCheckConfigResponse) -> String {
+    let mut out = String::new();
+    for check in &report.checks {
+        let level = match check.level {
+            CheckLevel::Ok => "ok",
+            CheckLevel::Warning => "warning",
+            CheckLevel::Error => "error",
+        };
+        out.push_str(&format!("[{}] {}: {}\n", level, check.name, check.message));
+    }
+    out.push_str(&report.message);
+    out
+}
+
+/// Run the self-test before serving, dialing the canary when one is configured.
+///
+/// Logs warnings and errors; fails when any check reports an error.
+pub async fn startup_self_test() -> Result<(), String> {
+    let report = check_config(true).await;
+    for check in &report.checks {
+        match check.level {
+            CheckLevel::Ok => {}
+            CheckLevel::Warning => warn!("Config check {}: {}", check.name, check.message),
+            CheckLevel::Error => error!("Config check {}: {}", check.name, check.message),
+        }
+    }
+    if report.ok {
+        info!("{}", report.message);
+        Ok(())
+    } else {
+        Err(format!(
+            "{} (run with {} for the full report)",
+            report.message, CHECK_CONFIG_FLAG
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::config::{AuditExporterConfig, ManagedHostConfig};
+
+    fn managed(name: &str, address: &str) -> ManagedHostConfig {
+        ManagedHostConfig {
+            name: name.to_string(),
+            address: address.to_string(),
+            username: "deploy".to_string(),
+            key_path: None,
+            password_env: None,
+            connect: Default::default(),
+            compress: None,
+            pool_size: 0,
+            region: None,
+            datacenter: None,
+        }
+    }
+
+    fn levels(checks: &[ConfigCheck]) -> Vec<(&str, CheckLevel)> {
+        checks
+            .iter()
+            .map(|check| (check.name.as_str(), check.level))
+            .collect()
+    }
+
+    mod env_values {
+        use super::*;
+
+        #[test]
+        fn test_numbers() {
+            let seconds = number("seconds", u64::MAX);
+            assert_eq!(check_env_value("T", seconds, "30").level, CheckLevel::Ok);
+            let bad = check_env_value("T", seconds, "30s");
+            assert_eq!(bad.level, CheckLevel::Error);
+            assert!(bad.message.contains("'30s'"), "{}", bad.message);
+            assert_eq!(check_env_value("T", seconds, "-1").level, CheckLevel::Error);
+            let port = number("port", u16::MAX as u64);
+            assert_eq!(check_env_value("P", port, "70000").level, CheckLevel::Error);
+        }
+
+        #[test]
+        fn test_zero_rejected_when_not_allowed() {
+            let kind = EnvKind::Number {
+                unit: "seconds",
+                max: u64::MAX,
+                zero_ok: false,
+            };
+            assert_eq!(check_env_value("T", kind, "0").level, CheckLevel::Error);
+            assert_eq!(check_env_value("T", kind, "5").level, CheckLevel::Ok);
+        }
+
+        #[test]
+        fn test_clamped_level_warns() {
+            let kind = EnvKind::Clamped { max: 9 };
+            assert_eq!(check_env_value("L", kind, "6").level, CheckLevel::Ok);
+            assert_eq!(check_env_value("L", kind, "12").level, CheckLevel::Warning);
+            assert_eq!(check_env_value("L", kind, "high").level, CheckLevel::Error);
+        }
+
+        #[test]
+        fn test_flags() {
+            let kind = EnvKind::Flag { other: false };
+            assert_eq!(check_env_value("F", kind, "TRUE").level, CheckLevel::Ok);
+            assert_eq!(check_env_value("F", kind, "0").level, CheckLevel::Ok);
+            let odd = check_env_value("F", kind, "yes");
+            assert_eq!(odd.level, CheckLevel::Warning);
+            assert!(odd.message.ends_with("treated as false"));
+        }
+
+        #[test]
+        fn test_host_key_checking() {
+            let kind = EnvKind::HostKeyChecking;
+            assert_eq!(check_env_value("S", kind, "yes").level, CheckLevel::Ok);
+            assert_eq!(check_env_value("S", kind, "ask").level, CheckLevel::Error);
+        }
+
+        #[test]
+        fn test_transport() {
+            let kind = EnvKind::Transport;
+            assert_eq!(check_env_value("T", kind, "sse").message, "sse");
+            assert_eq!(check_env_value("T", kind, "ws").level, CheckLevel::Error);
+        }
+
+        #[test]
+        fn test_output_overflow() {
+            let kind = EnvKind::OutputOverflow;
+            assert_eq!(check_env_value("O", kind, "Head").message, "head");
+            assert_eq!(check_env_value("O", kind, "drop").level, CheckLevel::Error);
+        }
+
+        #[test]
+        fn test_storage_backend() {
+            let kind = EnvKind::StorageBackend;
+            assert_eq!(check_env_value("S", kind, "MEMORY").message, "memory");
+            assert_eq!(check_env_value("S", kind, "sled").level, CheckLevel::Error);
+        }
+
+        #[test]
+        fn test_tool_prefix() {
+            let kind = EnvKind::ToolPrefix;
+            assert_eq!(check_env_value("X", kind, "prod_").level, CheckLevel::Ok);
+            assert_eq!(check_env_value("X", kind, "prod.").level, CheckLevel::Error);
+        }
+    }
+
+    mod server_config {
+        use super::*;
+
+        #[test]
+        fn test_default_config_has_no_findings() {
+            assert!(check_server_config(&ServerConfig::default()).is_empty());
+        }
+
+        #[test]
+        fn test_empty_policy_pattern_warns() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [[policy.deny]]
+                pattern = "reboot"
+
+                [[policy.deny]]
+                pattern = " "
+                "#,
+            )
+            .unwrap();
+            let checks = check_server_config(&config);
+            assert_eq!(
+                levels(&checks),
+                vec![("policy_deny:1", CheckLevel::Warning)]
+            );
+        }
+
+        #[test]
+        fn test_password_prompt_warns() {
+            let config = ServerConfig::from_toml("[elicitation]\npassword_prompt = true").unwrap();
+            assert_eq!(
+                levels(&check_server_config(&config)),
+                vec![("elicitation", CheckLevel::Warning)]
+            );
+        }
+
+        #[test]
+        fn test_invalid_policy_is_error() {
+            let config = ServerConfig::from_toml(
+                r#"
+                [[policy.allow]]
+                pattern = "[unclosed"
+                syntax = "regex"
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                levels(&check_server_config(&config)),
+                vec![("policy", CheckLevel::Error)]
+            );
+        }
+
+        #[test]
+        fn test_redis_storage_needs_url() {
+            let config = ServerConfig::from_toml("[storage]\nbackend = \"redis\"").unwrap();
+            assert_eq!(
+                levels(&check_server_config(&config)),
+                vec![("storage", CheckLevel::Error)]
+            );
+        }
+
+        #[test]
+        fn test_empty_health_command_is_error() {
+            let config =
+                ServerConfig::from_toml("[health]\ninterval_secs = 60\ncommand = \"\"").unwrap();
+            assert_eq!(
+                levels(&check_server_config(&config)),
+                vec![("health", CheckLevel::Error)]
+            );
+
+            let config = ServerConfig::from_toml(
+                "[health]\ninterval_secs = 60\nmethod = \"keepalive\"\ncommand = \"\"",
+            )
+            .unwrap();
+            assert!(check_server_config(&config).is_empty());
+        }
+
+        #[test]
+        fn test_unknown_tool_entries_warn() {
+            let mut config = ServerConfig::default();
+            config.tools.enabled = Some(vec!["session".to_string(), "ssh_nope".to_string()]);
+            config.tools.disabled = vec!["ssh_execute".to_string()];
+            let checks = check_server_config(&config);
+            assert_eq!(levels(&checks), vec![("tools", CheckLevel::Warning)]);
+            assert!(checks[0].message.contains("'ssh_nope'"));
+        }
+
+        #[test]
+        fn test_managed_hosts() {
+            let mut config = ServerConfig::default();
+            let mut keyed = managed("keyed", "10.0.0.2");
+            keyed.key_path = Some("/nonexistent/ssh-mcp-test-key".to_string());
+            let mut password = managed("bmc", "10.0.0.3:623");
+            password.password_env = Some("SSH_MCP_SELF_TEST_UNSET_PASSWORD".to_string());
+            config.managed_hosts = vec![
+                managed("web", "10.0.0.1:22"),
+                managed("web", "10.0.0.9"),
+                managed("bad name", "10.0.0.4"),
+                managed("port", "10.0.0.5:ssh"),
+                keyed,
+                password,
+            ];
+
+            let checks = check_server_config(&config);
+            assert_eq!(
+                levels(&checks),
+                vec![
+                    ("managed_host:web", CheckLevel::Ok),
+                    ("managed_host:web", CheckLevel::Error),
+                    ("managed_host:bad name", CheckLevel::Error),
+                    ("managed_host:port", CheckLevel::Error),
+                    ("managed_host:keyed", CheckLevel::Error),
+                    ("managed_host:bmc", CheckLevel::Error),
+                ]
+            );
+            assert!(checks[4].message.contains("/nonexistent/ssh-mcp-test-key"));
+            assert!(
+                checks[5]
+                    .message
+                    .contains("SSH_MCP_SELF_TEST_UNSET_PASSWORD")
+            );
+        }
+
+        #[test]
+        fn test_credentials() {
+            let config = ServerConfig::from_toml(
+                "[credentials.db]\npassword_env = \"SSH_MCP_SELF_TEST_UNSET_PASSWORD\"\n\
+                 hosts = [\"db1\"]\n\
+                 [credentials.ops]\nsource = \"vault\"\npath = \"secret/data/ops\"\n\
+                 hosts = [\"ops1\"]\n\
+                 [credentials.web]\nusername = \"deploy\"\nhosts = [\"web1\"]\n\
+                 [credentials.none]\nusername = \"deploy\"\nhosts = []\n\
+                 [vault]\naddress = \"http://127.0.0.1:8200\"",
+            )
+            .unwrap();
+            let checks = check_server_config(&config);
+            assert_eq!(
+                levels(&checks),
+                vec![
+                    ("credential:db", CheckLevel::Error),
+                    ("credential:none", CheckLevel::Error),
+                    ("credential:ops", CheckLevel::Ok),
+                    ("credential:web", CheckLevel::Ok),
+                ]
+            );
+            assert!(
+                checks[0]
+                    .message
+                    .contains("SSH_MCP_SELF_TEST_UNSET_PASSWORD")
+            );
+            assert!(checks[1].message.contains("no hosts"));
+
+            let mut config = config;
+            config.vault.address = Some("https://vault.example.com".to_string());
+            let checks = check_server_config(&config);
+            assert_eq!(checks[2].level, CheckLevel::Error);
+
+            // The token would cross the network in clear
+            config.vault.address = Some("http://vault.example.com:8200".to_string());
+            let checks = check_server_config(&config);
+            assert_eq!(checks[2].level, CheckLevel::Error);
+            assert!(checks[2].message.contains("loopback"));
+        }
+
+        #[test]
+        fn test_profiles() {
+            let mut config = ServerConfig::from_toml(
+                "[credentials.ops]\nusername = \"ops\"\nhosts = [\"10.0.0.6\"]\n\
+                 [profiles.targets.db]\nhost = \"10.0.0.5\"\ncredential_ref = \"dba\"\n\
+                 [profiles.targets.db2]\nhost = \"10.0.0.7\"\ncredential_ref = \"ops\"\n\
+                 [profiles.targets.web]\nhost = \"10.0.0.6\"\ncredential_ref = \"ops\"",
+            )
+            .unwrap();
+            let checks = check_server_config(&config);
+            assert_eq!(
+                levels(&checks),
+                vec![
+                    ("credential:ops", CheckLevel::Ok),
+                    ("profile:db", CheckLevel::Error),
+                    ("profile:db2", CheckLevel::Error),
+                    ("profile:web", CheckLevel::Ok),
+                ]
+            );
+            assert!(checks[1].message.contains("'dba'"));
+            assert!(checks[2].message.contains("10.0.0.7"));
+            assert_eq!(checks[3].message, "10.0.0.6:22");
+
+            config.profiles.file = Some("/nonexistent/ssh-mcp-profiles.toml".to_string());
+            assert_eq!(
+                levels(&check_server_config(&config)),
+                vec![
+                    ("credential:ops", CheckLevel::Ok),
+                    ("profiles", CheckLevel::Error),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_auth_tokens() {
+            let config = ServerConfig::from_toml(
+                "[[auth.tokens]]\nname = \"ci\"\ntoken_env = \"SSH_MCP_SELF_TEST_UNSET_TOKEN\"\n\
+                 [[auth.tokens]]\nname = \"broken\"",
+            )
+            .unwrap();
+            let checks = check_server_config(&config);
+            assert_eq!(
+                levels(&checks),
+                vec![
+                    ("auth_token:ci", CheckLevel::Error),
+                    ("auth_token:broken", CheckLevel::Error),
+                ]
+            );
+            assert!(checks[0].message.contains("SSH_MCP_SELF_TEST_UNSET_TOKEN"));
+        }
+
+        #[test]
+        fn test_audit_exporters() {
+            let mut config = ServerConfig::default();
+            let exporter = |toml: &str| -> AuditExporterConfig {
+                ServerConfig::from_toml(&format!("[[audit.exporters]]\n{}", toml))
+                    .unwrap()
+                    .audit
+                    .exporters
+                    .remove(0)
+            };
+            config.audit.exporters = vec![
+                exporter("kind = \"syslog\"\naddress = \"siem:514\""),
+                exporter("kind = \"syslog\""),
+                exporter("kind = \"http\"\nurl = \"https://collector/ingest\""),
+                exporter("kind = \"file\"\npath = \"/var/log/ssh-mcp/audit.jsonl\""),
+                exporter("kind = \"file\""),
+            ];
+            let checks = check_server_config(&config);
+            assert_eq!(
+                levels(&checks),
+                vec![
+                    ("audit_exporter:0", CheckLevel::Ok),
+                    ("audit_exporter:1", CheckLevel::Error),
+                    ("audit_exporter:2", CheckLevel::Error),
+                    ("audit_exporter:3", CheckLevel::Ok),
+                    ("audit_exporter:4", CheckLevel::Error),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_missing_helper_dir_and_unknown_canary() {
+            let mut config = ServerConfig::default();
+            config.helpers.dir = Some("/nonexistent/ssh-mcp-helpers".to_string());
+            config.transfer.local_root = Some("/nonexistent/ssh-mcp-transfer".to_string());
+            config.self_test.canary = Some("bastion".to_string());
+            let checks = check_server_config(&config);
+            assert_eq!(
+                levels(&checks),
+                vec![
+                    ("helpers", CheckLevel::Error),
+                    ("transfer", CheckLevel::Error),
+                    ("canary", CheckLevel::Error)
+                ]
+            );
+        }
+    }
+
+    mod known_hosts_file {
+        use super::*;
+
+        #[test]
+        fn test_modes() {
+            let dir = env::temp_dir().join(format!("self-test-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            let missing = dir.join("known_hosts");
+            assert_eq!(
+                check_known_hosts(HostKeyChecking::Yes, Some(&missing)).level,
+                CheckLevel::Error
+            );
+            assert_eq!(
+                check_known_hosts(HostKeyChecking::AcceptNew, Some(&missing)).level,
+                CheckLevel::Ok
+            );
+            assert_eq!(
+                check_known_hosts(HostKeyChecking::No, None).level,
+                CheckLevel::Warning
+            );
+            assert_eq!(
+                check_known_hosts(HostKeyChecking::Yes, None).level,
+                CheckLevel::Error
+            );
+
+            fs::write(&missing, "# fleet\nweb1 ssh-ed25519 AAAA\n\n").unwrap();
+            let found = check_known_hosts(HostKeyChecking::Yes, Some(&missing));
+            assert_eq!(found.level, CheckLevel::Ok);
+            assert!(
+                found.message.starts_with("1 entries in"),
+                "{}",
+                found.message
+            );
+        }
+    }
+
+    mod ssh_config_file {
+        use super::*;
+
+        #[test]
+        fn test_only_checked_when_set() {
+            assert!(check_ssh_config(None).is_none());
+            let missing = env::temp_dir().join(format!("ssh-config-{}", uuid::Uuid::new_v4()));
+            assert_eq!(
+                check_ssh_config(Some(&missing)).unwrap().level,
+                CheckLevel::Warning
+            );
+            fs::write(
+                &missing,
+                "Host web
+  HostName 10.0.0.5
+",
+            )
+            .unwrap();
+            assert_eq!(
+                check_ssh_config(Some(&missing)).unwrap().level,
+                CheckLevel::Ok
+            );
+            fs::remove_file(&missing).unwrap();
+        }
+    }
+
+    mod tls_files {
+        use super::*;
+
+        #[test]
+        fn test_only_checked_when_set() {
+            assert!(check_tls(Ok(None)).is_none());
+            assert_eq!(
+                check_tls(Err(
+                    "MCP_TLS_CERT and MCP_TLS_KEY must be set together".into()
+                ))
+                .unwrap()
+                .level,
+                CheckLevel::Error
+            );
+            let missing = env::temp_dir().join(format!("tls-{}", uuid::Uuid::new_v4()));
+            let files = TlsFiles {
+                cert: missing.display().to_string(),
+                key: missing.display().to_string(),
+            };
+            let found = check_tls(Ok(Some(files))).unwrap();
+            assert_eq!(found.level, CheckLevel::Error);
+            assert!(found.message.contains("cannot read certificate"));
+        }
+    }
+
+    mod reporting {
+        use super::*;
+
+        #[test]
+        fn test_counts_and_render() {
+            let result = report(vec![
+                check("a", CheckLevel::Ok, "fine"),
+                check("b", CheckLevel::Warning, "odd"),
+                check("c", CheckLevel::Error, "broken"),
+            ]);
+            assert!(!result.ok);
+            assert_eq!((result.errors, result.warnings), (1, 1));
+            let text = render_report(&result);
+            assert!(text.starts_with("[ok] a: fine\n[warning] b: odd\n[error] c: broken\n"));
+            assert!(text.ends_with("Configuration invalid: 1 error(s), 1 warning(s)"));
+        }
+
+        #[test]
+        fn test_no_errors_is_ok() {
+            let result = report(vec![check("b", CheckLevel::Warning, "odd")]);
+            assert!(result.ok);
+            assert_eq!(result.message, "Configuration OK: 1 check(s), 1 warning(s)");
+        }
+    }
+
+    mod arguments {
+        use super::*;
+
+        fn args(list: &[&str]) -> Vec<String> {
+            list.iter().map(|arg| arg.to_string()).collect()
+        }
+
+        #[test]
+        fn test_config_file_arg() {
+            assert_eq!(config_file_arg(&args(&["--check-config"])), Ok(None));
+            assert_eq!(
+                config_file_arg(&args(&["--config", "/etc/ssh-mcp.toml"])),
+                Ok(Some("/etc/ssh-mcp.toml".to_string()))
+            );
+            assert_eq!(
+                config_file_arg(&args(&["--check-config", "--config=ssh-mcp.toml"])),
+                Ok(Some("ssh-mcp.toml".to_string()))
+            );
+            assert!(config_file_arg(&args(&["--config"])).is_err());
+            assert!(config_file_arg(&args(&["--config", "--check-config"])).is_err());
+        }
+    }
+}
@@ -5,37 +5,162 @@
 //!
 //! # Architecture
 //!
-//! - `SshClientHandler`: A russh client handler that accepts all host keys (similar to
-//!   `StrictHostKeyChecking=no` in OpenSSH). In production environments, this should be
-//!   extended to verify against known_hosts.
+//! - `SshClientHandler`: A russh client handler that verifies the server's host key
+//!   against known_hosts (see the `known_hosts` module) and serves the connection's
+//!   reverse forwards (see the `reverse` module) and, when enabled, agent
+//!   channels (see the `agent_forward` module). A connection made through a
+//!   jump host owns the jump host's connection, so the chain closes with it.
 //!
 //! # Thread Safety
 //!
 //! The `client::Handle<SshClientHandler>` is wrapped in `Arc<>` in storage because it's not
 //! `Clone`, and we need to share it across multiple async operations (execute, forward, etc.).
 
-use russh::{client, keys};
+use std::sync::Arc;
 
-/// Client handler for russh that accepts all host keys.
-///
-/// This implementation accepts all server public keys without verification,
-/// similar to `StrictHostKeyChecking=no` in OpenSSH configuration.
-///
-/// # Security Note
+use russh::client::{Msg, Session};
+use russh::{Channel, ChannelId, Names, client, keys};
+
+use super::agent_forward;
+use super::algorithms;
+use super::known_hosts::HostKeyCheck;
+use super::reverse::ReverseForwards;
+use super::traffic::ByteCounters;
+
+/// Client handler for russh.
 ///
-/// In production environments, you should implement proper host key verification
-/// against a known_hosts file to prevent man-in-the-middle attacks.
-pub struct SshClientHandler;
+/// The server's host key is accepted or rejected by `host_key` according to
+/// its `StrictHostKeyChecking`-style mode; a rejection aborts the handshake.
+pub struct SshClientHandler {
+    reverse: Arc<ReverseForwards>,
+    traffic: Arc<ByteCounters>,
+    host_key: HostKeyCheck,
+    /// Pipe agent channels opened by the server to the local agent
+    agent_forwarding: bool,
+    /// Jump host connection this connection is tunneled through (kept alive
+    /// to keep the tunnel open)
+    #[allow(dead_code)]
+    jump: Option<client::Handle<SshClientHandler>>,
+}
+
+impl SshClientHandler {
+    /// Create a handler serving the given reverse forward table.
+    pub(crate) fn new(
+        reverse: Arc<ReverseForwards>,
+        traffic: Arc<ByteCounters>,
+        host_key: HostKeyCheck,
+    ) -> Self {
+        Self {
+            reverse,
+            traffic,
+            host_key,
+            agent_forwarding: false,
+            jump: None,
+        }
+    }
+
+    /// Serve the server's agent channels (`agent_forwarding`).
+    pub(crate) fn with_agent_forwarding(mut self, agent_forwarding: bool) -> Self {
+        self.agent_forwarding = agent_forwarding;
+        self
+    }
+
+    /// Keep the jump host connection carrying this connection alive with it.
+    pub(crate) fn with_jump(mut self, jump: Option<client::Handle<SshClientHandler>>) -> Self {
+        self.jump = jump;
+        self
+    }
+}
 
 impl client::Handler for SshClientHandler {
     type Error = russh::Error;
 
     async fn check_server_key(
         &mut self,
-        _server_public_key: &keys::PublicKey,
+        server_public_key: &keys::PublicKey,
     ) -> Result<bool, Self::Error> {
-        // Accept all host keys (similar to StrictHostKeyChecking=no)
-        // In production, you'd want to verify against known_hosts
-        Ok(true)
+        Ok(self.host_key.accept(server_public_key))
+    }
+
+    async fn kex_done(
+        &mut self,
+        _shared_secret: Option<&[u8]>,
+        names: &Names,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.traffic
+            .record_algorithms(algorithms::negotiated(names));
+        Ok(())
+    }
+
+    async fn channel_open_confirmation(
+        &mut self,
+        _id: ChannelId,
+        _max_packet_size: u32,
+        _window_size: u32,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        // Queued tcpip-forward requests can only be sent with the session at hand
+        self.reverse.flush(session);
+        self.traffic.touch();
+        Ok(())
+    }
+
+    async fn server_channel_open_forwarded_tcpip(
+        &mut self,
+        channel: Channel<Msg>,
+        connected_address: &str,
+        connected_port: u32,
+        _originator_address: &str,
+        _originator_port: u32,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.reverse.accept(
+            channel,
+            connected_address,
+            connected_port,
+            self.traffic.clone(),
+        );
+        Ok(())
+    }
+
+    async fn server_channel_open_agent_forward(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        agent_forward::serve(channel, self.agent_forwarding);
+        Ok(())
+    }
+
+    async fn exit_status(
+        &mut self,
+        _channel: ChannelId,
+        _exit_status: u32,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.traffic.touch();
+        Ok(())
+    }
+
+    async fn data(
+        &mut self,
+        _channel: ChannelId,
+        data: &[u8],
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.traffic.record_received(data.len());
+        Ok(())
+    }
+
+    async fn extended_data(
+        &mut self,
+        _channel: ChannelId,
+        _ext: u32,
+        data: &[u8],
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.traffic.record_received(data.len());
+        Ok(())
     }
 }
@@ -0,0 +1,201 @@
+//! Environment variables for remote commands (`env` on `ssh_connect` and
+//! `ssh_execute`).
+//!
+//! Variables given to `ssh_connect` are kept for the session and apply to
+//! every command started with `ssh_execute`, `ssh_execute_sync` and
+//! `ssh_run_helper`; an `env` passed to `ssh_execute` is merged over them.
+//!
+//! Each variable is first sent as an SSH `env` channel request. OpenSSH only
+//! accepts names listed in the server's `AcceptEnv` (usually `LANG` and
+//! `LC_*`), so variables the server rejects are set by prefixing the command
+//! with `export NAME='value';` instead. Values are never logged.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use russh::{ChannelMsg, client};
+use tracing::debug;
+
+use super::command_wrap::shell_quote;
+
+/// Largest number of variables per session or command
+const MAX_ENV_VARS: usize = 64;
+
+/// Variables given to `ssh_connect`, keyed by session ID.
+static SESSION_ENV: Lazy<DashMap<String, BTreeMap<String, String>>> = Lazy::new(DashMap::new);
+
+/// Check variable names (`[A-Za-z_][A-Za-z0-9_]*`) and values (no NUL).
+pub(crate) fn validate_env(env: &BTreeMap<String, String>) -> Result<(), String> {
+    if env.len() > MAX_ENV_VARS {
+        return Err(format!(
+            "Too many environment variables ({}); at most {} are allowed",
+            env.len(),
+            MAX_ENV_VARS
+        ));
+    }
+    for (name, value) in env {
+        let mut chars = name.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!(
+                "Invalid environment variable name '{}': use letters, digits and '_', not starting with a digit",
+                name
+            ));
+        }
+        if value.contains('\0') {
+            return Err(format!(
+                "Environment variable '{}' contains a NUL byte",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Keep `env` for `session_id`; an empty map clears it.
+pub(crate) fn set_session_env(session_id: &str, env: BTreeMap<String, String>) {
+    if env.is_empty() {
+        SESSION_ENV.remove(session_id);
+    } else {
+        SESSION_ENV.insert(session_id.to_string(), env);
+    }
+}
+
+/// The session's variables with `overrides` merged over them.
+pub(crate) fn command_env(
+    session_id: &str,
+    overrides: Option<BTreeMap<String, String>>,
+) -> BTreeMap<String, String> {
+    let mut env = SESSION_ENV
+        .get(session_id)
+        .map(|entry| entry.value().clone())
+        .unwrap_or_default();
+    env.extend(overrides.unwrap_or_default());
+    env
+}
+
+/// Drop the variables of a session that is gone.
+pub(crate) fn forget(session_id: &str) {
+    SESSION_ENV.remove(session_id);
+}
+
+/// `export` statements setting `names` from `env`, ready to prefix a command.
+fn export_prefix(env: &BTreeMap<String, String>, names: &[String]) -> String {
+    names
+        .iter()
+        .filter_map(|name| env.get(name).map(|value| (name, value)))
+        .map(|(name, value)| format!("export {}={}; ", name, shell_quote(value)))
+        .collect()
+}
+
+/// Send `env` as `env` requests on a channel before `exec`; returns the
+/// names the server rejected.
+///
+/// Replies are awaited in order, so this must run before anything else
+/// produces channel messages.
+async fn send_env(
+    channel: &mut russh::Channel<client::Msg>,
+    env: &BTreeMap<String, String>,
+    timeout: Duration,
+) -> Result<Vec<String>, String> {
+    for (name, value) in env {
+        channel
+            .set_env(true, name.as_str(), value.as_str())
+            .await
+            .map_err(|e| format!("Failed to set environment: {}", e))?;
+    }
+
+    let mut rejected = Vec::new();
+    let names: Vec<&String> = env.keys().collect();
+    let replies = async {
+        let mut next = 0;
+        while next < names.len() {
+            match channel.wait().await {
+                Some(ChannelMsg::Success) => next += 1,
+                Some(ChannelMsg::Failure) => {
+                    rejected.push(names[next].clone());
+                    next += 1;
+                }
+                Some(_) => {}
+                None => return Err("Failed to set environment: channel closed".to_string()),
+            }
+        }
+        Ok(())
+    };
+    match tokio::time::timeout(timeout, replies).await {
+        Ok(result) => result?,
+        Err(_) => {
+            return Err(format!(
+                "Failed to set environment: timeout after {}s (server not responding)",
+                timeout.as_secs()
+            ));
+        }
+    }
+    Ok(rejected)
+}
+
+/// Set `env` on a channel and return the command line to exec: `command`,
+/// prefixed with `export` statements for variables the server rejected.
+pub(crate) async fn apply_env(
+    channel: &mut russh::Channel<client::Msg>,
+    env: &BTreeMap<String, String>,
+    command: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    if env.is_empty() {
+        return Ok(command.to_string());
+    }
+    let rejected = send_env(channel, env, timeout).await?;
+    if !rejected.is_empty() {
+        debug!(
+            "Server rejected env request for {}; exporting instead",
+            rejected.join(", ")
+        );
+    }
+    Ok(format!("{}{}", export_prefix(env, &rejected), command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_names() {
+        assert!(validate_env(&env(&[("LANG", "C.UTF-8"), ("_X1", "")])).is_ok());
+        assert!(validate_env(&env(&[("1X", "a")])).is_err());
+        assert!(validate_env(&env(&[("A-B", "a")])).is_err());
+        assert!(validate_env(&env(&[("A", "a\0b")])).is_err());
+    }
+
+    #[test]
+    fn test_export_prefix_quotes_values() {
+        let vars = env(&[("GREETING", "it's here"), ("LANG", "C")]);
+        assert_eq!(
+            export_prefix(&vars, &["GREETING".to_string()]),
+            r"export GREETING='it'\''s here'; "
+        );
+        assert_eq!(export_prefix(&vars, &[]), "");
+    }
+
+    #[test]
+    fn test_command_env_merges_over_session() {
+        set_session_env("env-test", env(&[("LANG", "C"), ("TOKEN", "a")]));
+        let merged = command_env("env-test", Some(env(&[("TOKEN", "b")])));
+        assert_eq!(merged, env(&[("LANG", "C"), ("TOKEN", "b")]));
+
+        forget("env-test");
+        assert!(command_env("env-test", None).is_empty());
+    }
+}
@@ -0,0 +1,235 @@
+//! SFTP subsystem access.
+//!
+//! `ssh_list_dir` reads directories through the SFTP subsystem instead of
+//! parsing `ls -la`: the server returns typed attributes (size, mode, owner
+//! ids, mtime), so there is no locale, column or file-name quoting to get
+//! wrong. Each call opens its own `sftp` subsystem channel and closes it when
+//! done; hosts that disable the subsystem report a clear error.
+//!
+//! Symbolic links are listed with `lstat` semantics (the link itself) and
+//! their target is read with `readlink`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::client;
+use russh_sftp::client::SftpSession;
+use russh_sftp::protocol::{FileAttributes, FileType};
+
+use super::client::channel_step;
+use super::config::resolve_channel_open_timeout;
+use super::session::SshClientHandler;
+use super::types::{DirEntryInfo, ManifestEntryKind};
+
+/// Entries returned when `max_entries` is not given
+pub(crate) const DEFAULT_LIST_DIR_ENTRIES: usize = 1000;
+
+/// Largest accepted `max_entries`
+pub(crate) const MAX_LIST_DIR_ENTRIES: usize = 10_000;
+
+/// Time allowed for each SFTP request
+const SFTP_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resolve the entry limit, clamped to 1..=`MAX_LIST_DIR_ENTRIES`.
+pub(crate) fn resolve_list_dir_entries(max_entries: Option<usize>) -> usize {
+    max_entries
+        .unwrap_or(DEFAULT_LIST_DIR_ENTRIES)
+        .clamp(1, MAX_LIST_DIR_ENTRIES)
+}
+
+/// Open an SFTP session over a new subsystem channel.
+pub(crate) async fn open_sftp(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+) -> Result<SftpSession, String> {
+    let open_timeout = resolve_channel_open_timeout();
+    let channel = channel_step("open channel", open_timeout, handle.channel_open_session()).await?;
+    channel_step(
+        "start sftp subsystem",
+        open_timeout,
+        channel.request_subsystem(true, "sftp"),
+    )
+    .await?;
+    SftpSession::new_opts(channel.into_stream(), Some(SFTP_REQUEST_TIMEOUT.as_secs()))
+        .await
+        .map_err(|e| format!("SFTP subsystem not available: {}", e))
+}
+
+/// A directory read through SFTP.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct DirListing {
+    /// Absolute path of the directory as resolved by the server
+    pub path: String,
+    /// Entries sorted by name, at most `limit`
+    pub entries: Vec<DirEntryInfo>,
+    /// More entries existed than `limit`
+    pub truncated: bool,
+}
+
+/// List the directory at `path`, skipping dot files unless `include_hidden`.
+pub(crate) async fn list_dir(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    path: &str,
+    include_hidden: bool,
+    limit: usize,
+) -> Result<DirListing, String> {
+    let sftp = open_sftp(handle).await?;
+    let result = read_listing(&sftp, path, include_hidden, limit).await;
+    let _ = sftp.close().await;
+    result
+}
+
+async fn read_listing(
+    sftp: &SftpSession,
+    path: &str,
+    include_hidden: bool,
+    limit: usize,
+) -> Result<DirListing, String> {
+    let resolved = sftp
+        .canonicalize(path)
+        .await
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let entries = sftp
+        .read_dir(resolved.as_str())
+        .await
+        .map_err(|e| format!("{}: {}", resolved, e))?;
+
+    let mut listing = DirListing {
+        path: resolved,
+        ..Default::default()
+    };
+    for entry in entries {
+        let name = entry.file_name();
+        if !include_hidden && name.starts_with('.') {
+            continue;
+        }
+        listing.entries.push(entry_info(name, &entry.metadata()));
+    }
+    listing.entries.sort_by(|a, b| a.name.cmp(&b.name));
+    listing.truncated = listing.entries.len() > limit;
+    listing.entries.truncate(limit);
+
+    for entry in &mut listing.entries {
+        if entry.kind == ManifestEntryKind::Symlink {
+            let link = format!("{}/{}", listing.path.trim_end_matches('/'), entry.name);
+            entry.target = sftp.read_link(link).await.ok();
+        }
+    }
+    Ok(listing)
+}
+
+/// Typed entry for `name` from its SFTP attributes.
+pub(crate) fn entry_info(name: String, attrs: &FileAttributes) -> DirEntryInfo {
+    let kind = match attrs.file_type() {
+        FileType::Dir => ManifestEntryKind::Directory,
+        FileType::File => ManifestEntryKind::File,
+        FileType::Symlink => ManifestEntryKind::Symlink,
+        FileType::Other => ManifestEntryKind::Other,
+    };
+    DirEntryInfo {
+        name,
+        kind,
+        size: attrs.size,
+        mtime: attrs.mtime.and_then(|secs| {
+            chrono::DateTime::from_timestamp(i64::from(secs), 0).map(|time| time.to_rfc3339())
+        }),
+        mode: attrs
+            .permissions
+            .map(|bits| format!("{:04o}", bits & 0o7777)),
+        permissions: attrs.permissions.map(permission_string),
+        uid: attrs.uid,
+        gid: attrs.gid,
+        target: None,
+    }
+}
+
+/// `ls -l` style mode string, e.g. `drwxr-xr-x`.
+pub(crate) fn permission_string(bits: u32) -> String {
+    let kind = match bits & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o010000 => 'p',
+        0o140000 => 's',
+        _ => '-',
+    };
+    let mut out = String::with_capacity(10);
+    out.push(kind);
+    // (read, write, execute, special bit, special char when executable / not)
+    let triplets = [
+        (0o400, 0o200, 0o100, 0o4000, ('s', 'S')),
+        (0o040, 0o020, 0o010, 0o2000, ('s', 'S')),
+        (0o004, 0o002, 0o001, 0o1000, ('t', 'T')),
+    ];
+    for (read, write, exec, special, (set_exec, set_only)) in triplets {
+        out.push(if bits & read != 0 { 'r' } else { '-' });
+        out.push(if bits & write != 0 { 'w' } else { '-' });
+        out.push(match (bits & exec != 0, bits & special != 0) {
+            (true, true) => set_exec,
+            (false, true) => set_only,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attrs(permissions: u32, size: u64, mtime: u32) -> FileAttributes {
+        FileAttributes {
+            size: Some(size),
+            uid: Some(1000),
+            gid: Some(100),
+            permissions: Some(permissions),
+            mtime: Some(mtime),
+            ..FileAttributes::empty()
+        }
+    }
+
+    #[test]
+    fn test_permission_string() {
+        assert_eq!(permission_string(0o040755), "drwxr-xr-x");
+        assert_eq!(permission_string(0o100644), "-rw-r--r--");
+        assert_eq!(permission_string(0o120777), "lrwxrwxrwx");
+        assert_eq!(permission_string(0o104755), "-rwsr-xr-x");
+        assert_eq!(permission_string(0o041777), "drwxrwxrwt");
+        assert_eq!(permission_string(0o102640), "-rw-r-S---");
+    }
+
+    #[test]
+    fn test_entry_info() {
+        let file = entry_info("app.log".to_string(), &attrs(0o100640, 4096, 1_700_000_000));
+        assert_eq!(file.kind, ManifestEntryKind::File);
+        assert_eq!(file.size, Some(4096));
+        assert_eq!(file.mode.as_deref(), Some("0640"));
+        assert_eq!(file.permissions.as_deref(), Some("-rw-r-----"));
+        assert_eq!(file.mtime.as_deref(), Some("2023-11-14T22:13:20+00:00"));
+        assert_eq!((file.uid, file.gid), (Some(1000), Some(100)));
+
+        let dir = entry_info("conf.d".to_string(), &attrs(0o040755, 0, 0));
+        assert_eq!(dir.kind, ManifestEntryKind::Directory);
+        let link = entry_info("current".to_string(), &attrs(0o120777, 12, 0));
+        assert_eq!(link.kind, ManifestEntryKind::Symlink);
+    }
+
+    #[test]
+    fn test_missing_attributes() {
+        let entry = entry_info("x".to_string(), &FileAttributes::empty());
+        assert_eq!(entry.size, None);
+        assert_eq!(entry.mode, None);
+        assert_eq!(entry.mtime, None);
+    }
+
+    #[test]
+    fn test_entry_limit_clamped() {
+        assert_eq!(resolve_list_dir_entries(None), DEFAULT_LIST_DIR_ENTRIES);
+        assert_eq!(resolve_list_dir_entries(Some(0)), 1);
+        assert_eq!(
+            resolve_list_dir_entries(Some(1_000_000)),
+            MAX_LIST_DIR_ENTRIES
+        );
+    }
+}
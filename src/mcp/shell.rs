@@ -18,11 +18,12 @@
 
 use std::sync::Arc;
 
-use russh::ChannelWriteHalf;
 use russh::client;
-use tokio::sync::{Mutex, watch};
+use russh::{ChannelWriteHalf, Sig};
+use tokio::sync::{Mutex, broadcast, watch};
 use tokio_util::sync::CancellationToken;
 
+use super::traffic::ByteCounters;
 use super::types::{ShellInfo, ShellStatus};
 
 /// Write handle for sending input to a shell channel.
@@ -31,12 +32,20 @@ use super::types::{ShellInfo, ShellStatus};
 /// for writing data to the PTY channel without holding a lock on the read half.
 pub struct ChannelWriter {
     pub(crate) write_half: ChannelWriteHalf<client::Msg>,
+    traffic: Arc<ByteCounters>,
 }
 
 impl ChannelWriter {
-    /// Create a new channel writer from a channel write half.
-    pub fn new(write_half: ChannelWriteHalf<client::Msg>) -> Self {
-        Self { write_half }
+    /// Create a new channel writer from a channel write half; written bytes
+    /// are counted as sent on `traffic`.
+    pub(crate) fn new(
+        write_half: ChannelWriteHalf<client::Msg>,
+        traffic: Arc<ByteCounters>,
+    ) -> Self {
+        Self {
+            write_half,
+            traffic,
+        }
     }
 
     /// Send data (text, keystrokes, escape sequences) to the shell.
@@ -44,7 +53,17 @@ impl ChannelWriter {
         self.write_half
             .data(data)
             .await
-            .map_err(|e| format!("Failed to write to shell: {}", e))
+            .map_err(|e| format!("Failed to write to shell: {}", e))?;
+        self.traffic.record_sent(data.len());
+        Ok(())
+    }
+
+    /// Send a signal to the process the server started for the shell.
+    pub async fn signal(&self, signal: Sig) -> Result<(), String> {
+        self.write_half
+            .signal(signal)
+            .await
+            .map_err(|e| format!("Failed to send signal: {}", e))
     }
 
     /// Close the channel gracefully.
@@ -71,6 +90,8 @@ pub struct RunningShell {
     pub status_tx: watch::Sender<ShellStatus>,
     /// Receiver for status updates
     pub status_rx: watch::Receiver<ShellStatus>,
+    /// Live copy of every output chunk for `ssh_shell_subscribe`
+    pub output_tx: broadcast::Sender<Vec<u8>>,
 }
 
 /// Maximum number of concurrent shells per session
@@ -90,8 +111,8 @@ mod tests {
 
         #[test]
         fn test_max_shells_is_reasonable() {
-            assert!(MAX_SHELLS_PER_SESSION >= 1);
-            assert!(MAX_SHELLS_PER_SESSION <= 50);
+            const { assert!(MAX_SHELLS_PER_SESSION >= 1) };
+            const { assert!(MAX_SHELLS_PER_SESSION <= 50) };
         }
     }
 
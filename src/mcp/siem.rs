@@ -0,0 +1,760 @@
+//! Audit event export to SIEM pipelines.
+//!
+//! Each `[[audit.exporters]]` entry runs as a background task that batches
+//! [`AuditEvent`]s from its queue and delivers them:
+//!
+//! - **syslog**: RFC 5424 messages (facility `local0`) over UDP, one datagram
+//!   per event, or TCP with RFC 6587 octet-counted framing
+//! - **http**: one `POST` per batch; a JSON array for `json`, newline-separated
+//!   lines for `cef`. Only plain `http://` URLs are supported; put a TLS
+//!   forwarder (e.g. a local collector agent) in front of HTTPS endpoints.
+//! - **file**: one line per event appended to `path` (JSONL for `json`). The
+//!   file is created with mode `0600` and never truncated or rewritten.
+//!
+//! A batch is sent when it reaches `batch_size` events or `flush_interval_ms`
+//! after its first event. Failed deliveries are retried with exponential
+//! backoff; after `max_retries` the batch is dropped and logged, so a dead
+//! collector never blocks tool calls.
+//!
+//! # CEF mapping
+//!
+//! `CEF:0|ssh-mcp|ssh-mcp|<version>|<action>|<action>|<severity>|` followed by
+//! `rt` (epoch ms), `act`, `outcome`, `suser` (agent), `duser` (remote user),
+//! `dhost`, `cs1` (session id), `cs2` (command id), `cn1` (exit code), `msg`
+//! (target) and `reason` (error). Severity is 3 for successes and 7 for
+//! failures.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use backon::{ExponentialBuilder, Retryable};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use super::audit::{AuditEvent, AuditOutcome};
+use super::config::{AuditExporterConfig, AuditExporterKind, AuditFormat, SyslogTransport};
+
+/// Timeout for one delivery attempt (connect, write and HTTP response)
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// First retry delay; later retries back off exponentially up to [`MAX_RETRY_DELAY`]
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest wait between delivery retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Syslog PRI for facility local0 (16) at severity informational (6) and warning (4)
+const SYSLOG_PRI_SUCCESS: u8 = 16 * 8 + 6;
+const SYSLOG_PRI_FAILURE: u8 = 16 * 8 + 4;
+
+/// Resolved destination of an exporter.
+#[derive(Debug, Clone, PartialEq)]
+enum Sink {
+    Syslog {
+        address: String,
+        transport: SyslogTransport,
+    },
+    Http(HttpTarget),
+    File(PathBuf),
+}
+
+/// Parsed `http://` collector URL.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HttpTarget {
+    /// `host:port` to connect to
+    pub address: String,
+    /// Value of the `Host` header
+    pub host: String,
+    /// Request path including any query
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// Validate an exporter config and start its delivery task.
+pub(crate) fn spawn_exporter(
+    config: &AuditExporterConfig,
+) -> Result<mpsc::Sender<AuditEvent>, String> {
+    let sink = match config.kind {
+        AuditExporterKind::Syslog => Sink::Syslog {
+            address: config
+                .address
+                .clone()
+                .ok_or("syslog exporter requires 'address'")?,
+            transport: config.transport,
+        },
+        AuditExporterKind::Http => {
+            let url = config
+                .url
+                .as_deref()
+                .ok_or("http exporter requires 'url'")?;
+            let mut target = parse_http_url(url)?;
+            target.headers = config.headers.clone();
+            Sink::Http(target)
+        }
+        AuditExporterKind::File => Sink::File(PathBuf::from(
+            config
+                .path
+                .as_deref()
+                .ok_or("file exporter requires 'path'")?,
+        )),
+    };
+
+    let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+    let exporter = Exporter {
+        sink,
+        format: config.format,
+        batch_size: config.batch_size.max(1),
+        flush_interval: Duration::from_millis(config.flush_interval_ms),
+        max_retries: config.max_retries,
+    };
+    info!("Audit exporter started: {}", exporter.describe());
+    tokio::spawn(exporter.run(rx));
+    Ok(tx)
+}
+
+struct Exporter {
+    sink: Sink,
+    format: AuditFormat,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_retries: usize,
+}
+
+impl Exporter {
+    fn describe(&self) -> String {
+        match &self.sink {
+            Sink::Syslog { address, transport } => {
+                format!("syslog {:?} {} ({:?})", transport, address, self.format)
+            }
+            Sink::Http(target) => {
+                format!("http {}{} ({:?})", target.address, target.path, self.format)
+            }
+            Sink::File(path) => format!("file {} ({:?})", path.display(), self.format),
+        }
+    }
+
+    /// Collect batches until every sender is gone, delivering each one.
+    async fn run(self, mut rx: mpsc::Receiver<AuditEvent>) {
+        while let Some(first) = rx.recv().await {
+            let mut batch = vec![first];
+            let deadline = tokio::time::Instant::now() + self.flush_interval;
+            while batch.len() < self.batch_size {
+                match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(event)) => batch.push(event),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            self.deliver_with_retry(&batch).await;
+        }
+    }
+
+    async fn deliver_with_retry(&self, batch: &[AuditEvent]) {
+        let backoff = ExponentialBuilder::default()
+            .with_min_delay(MIN_RETRY_DELAY)
+            .with_max_delay(MAX_RETRY_DELAY)
+            .with_max_times(self.max_retries)
+            .with_jitter();
+
+        let result = (|| async {
+            tokio::time::timeout(DELIVERY_TIMEOUT, self.deliver(batch))
+                .await
+                .unwrap_or_else(|_| Err("delivery timed out".to_string()))
+        })
+        .retry(backoff)
+        .notify(|e, delay| {
+            warn!(
+                "Audit delivery to {} failed ({}); retrying in {:?}",
+                self.describe(),
+                e,
+                delay
+            )
+        })
+        .await;
+
+        if let Err(e) = result {
+            warn!(
+                "Dropped {} audit event(s) for {}: {}",
+                batch.len(),
+                self.describe(),
+                e
+            );
+        }
+    }
+
+    async fn deliver(&self, batch: &[AuditEvent]) -> Result<(), String> {
+        match &self.sink {
+            Sink::Syslog { address, transport } => {
+                let messages: Vec<Vec<u8>> = batch
+                    .iter()
+                    .map(|event| syslog_message(event, &encode(event, self.format)))
+                    .collect();
+                send_syslog(address, *transport, &messages).await
+            }
+            Sink::Http(target) => {
+                let (body, content_type) = encode_batch(batch, self.format);
+                post_http(target, &body, content_type).await
+            }
+            Sink::File(path) => {
+                let lines: String = batch
+                    .iter()
+                    .map(|event| encode(event, self.format) + "\n")
+                    .collect();
+                append_file(path, lines.as_bytes()).await
+            }
+        }
+    }
+}
+
+/// Encode one event.
+fn encode(event: &AuditEvent, format: AuditFormat) -> String {
+    match format {
+        AuditFormat::Json => serde_json::to_string(event).unwrap_or_default(),
+        AuditFormat::Cef => format_cef(event),
+    }
+}
+
+/// Encode an HTTP batch and its content type.
+fn encode_batch(batch: &[AuditEvent], format: AuditFormat) -> (String, &'static str) {
+    match format {
+        AuditFormat::Json => (
+            serde_json::to_string(batch).unwrap_or_default(),
+            "application/json",
+        ),
+        AuditFormat::Cef => (
+            batch.iter().map(format_cef).collect::<Vec<_>>().join("\n"),
+            "text/plain; charset=utf-8",
+        ),
+    }
+}
+
+/// Format an event as a CEF line.
+fn format_cef(event: &AuditEvent) -> String {
+    let severity = match event.outcome {
+        AuditOutcome::Success => 3,
+        AuditOutcome::Failure => 7,
+    };
+    let outcome = match event.outcome {
+        AuditOutcome::Success => "success",
+        AuditOutcome::Failure => "failure",
+    };
+    let rt = chrono::DateTime::parse_from_rfc3339(&event.timestamp)
+        .map(|time| time.timestamp_millis())
+        .unwrap_or_default();
+
+    let mut extension = vec![
+        format!("rt={}", rt),
+        format!("act={}", cef_value(&event.action)),
+        format!("outcome={}", outcome),
+    ];
+    let optional = [
+        ("suser", &event.agent_id),
+        ("duser", &event.username),
+        ("dhost", &event.host),
+        ("msg", &event.target),
+        ("reason", &event.error),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            extension.push(format!("{}={}", key, cef_value(value)));
+        }
+    }
+    if let Some(session_id) = &event.session_id {
+        extension.push("cs1Label=sessionId".to_string());
+        extension.push(format!("cs1={}", cef_value(session_id)));
+    }
+    if let Some(command_id) = &event.command_id {
+        extension.push("cs2Label=commandId".to_string());
+        extension.push(format!("cs2={}", cef_value(command_id)));
+    }
+    if let Some(exit_code) = event.exit_code {
+        extension.push("cn1Label=exitCode".to_string());
+        extension.push(format!("cn1={}", exit_code));
+    }
+
+    let action = cef_header(&event.action);
+    format!(
+        "CEF:0|ssh-mcp|ssh-mcp|{}|{}|{}|{}|{}",
+        env!("CARGO_PKG_VERSION"),
+        action,
+        action,
+        severity,
+        extension.join(" ")
+    )
+}
+
+/// Escape a CEF header field (`\` and `|`).
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Escape a CEF extension value (`\`, `=` and line breaks).
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+/// Append `data` to the log at `path` in one write, creating it owner-only.
+async fn append_file(path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(path)
+        .await
+        .map_err(|e| format!("open {}: {}", path.display(), e))?;
+    file.write_all(data)
+        .await
+        .map_err(|e| format!("write {}: {}", path.display(), e))?;
+    file.sync_data()
+        .await
+        .map_err(|e| format!("sync {}: {}", path.display(), e))
+}
+
+/// Build an RFC 5424 syslog message.
+fn syslog_message(event: &AuditEvent, body: &str) -> Vec<u8> {
+    let pri = match event.outcome {
+        AuditOutcome::Success => SYSLOG_PRI_SUCCESS,
+        AuditOutcome::Failure => SYSLOG_PRI_FAILURE,
+    };
+    let hostname = std::env::var("HOSTNAME")
+        .ok()
+        .filter(|name| !name.is_empty() && !name.contains(' '))
+        .unwrap_or_else(|| "-".to_string());
+    format!(
+        "<{}>1 {} {} ssh-mcp {} {} - {}",
+        pri,
+        event.timestamp,
+        hostname,
+        std::process::id(),
+        event.action,
+        body
+    )
+    .into_bytes()
+}
+
+async fn send_syslog(
+    address: &str,
+    transport: SyslogTransport,
+    messages: &[Vec<u8>],
+) -> Result<(), String> {
+    match transport {
+        SyslogTransport::Udp => {
+            let bind = if address.starts_with('[') {
+                "[::]:0"
+            } else {
+                "0.0.0.0:0"
+            };
+            let socket = UdpSocket::bind(bind)
+                .await
+                .map_err(|e| format!("bind: {}", e))?;
+            socket
+                .connect(address)
+                .await
+                .map_err(|e| format!("connect {}: {}", address, e))?;
+            for message in messages {
+                socket
+                    .send(message)
+                    .await
+                    .map_err(|e| format!("send: {}", e))?;
+            }
+        }
+        SyslogTransport::Tcp => {
+            let mut stream = TcpStream::connect(address)
+                .await
+                .map_err(|e| format!("connect {}: {}", address, e))?;
+            let mut framed = Vec::new();
+            for message in messages {
+                framed.extend_from_slice(format!("{} ", message.len()).as_bytes());
+                framed.extend_from_slice(message);
+            }
+            stream
+                .write_all(&framed)
+                .await
+                .map_err(|e| format!("write: {}", e))?;
+            stream
+                .shutdown()
+                .await
+                .map_err(|e| format!("shutdown: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse an `http://host[:port][/path]` URL.
+pub(crate) fn parse_http_url(url: &str) -> Result<HttpTarget, String> {
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        format!(
+            "Unsupported URL '{}': only http:// is supported (use a local TLS forwarder)",
+            url
+        )
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() || authority.contains('@') {
+        return Err(format!("Invalid URL '{}'", url));
+    }
+    let has_port = match authority.rfind(':') {
+        Some(index) => !authority[index..].contains(']'),
+        None => false,
+    };
+    let address = if has_port {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    Ok(HttpTarget {
+        address,
+        host: authority.to_string(),
+        path: path.to_string(),
+        headers: HashMap::new(),
+    })
+}
+
+/// Build an HTTP/1.1 POST request.
+fn http_request(target: &HttpTarget, body: &str, content_type: &str) -> Vec<u8> {
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: ssh-mcp/{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        target.path,
+        target.host,
+        env!("CARGO_PKG_VERSION"),
+        content_type,
+        body.len()
+    );
+    for (name, value) in &target.headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    request.into_bytes()
+}
+
+/// Build an HTTP/1.0 GET request; 1.0 keeps the answer unchunked and closes
+/// the connection after it.
+fn http_get_request(target: &HttpTarget) -> Vec<u8> {
+    let mut request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: ssh-mcp/{}\r\nAccept: application/json\r\n",
+        target.path,
+        target.host,
+        env!("CARGO_PKG_VERSION"),
+    );
+    for (name, value) in &target.headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.into_bytes()
+}
+
+/// Extract the status code from an HTTP response head.
+fn http_status(response: &[u8]) -> Option<u16> {
+    let line = response.split(|b| *b == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?;
+    let mut parts = line.split_whitespace();
+    parts
+        .next()
+        .filter(|version| version.starts_with("HTTP/"))?;
+    parts.next()?.parse().ok()
+}
+
+/// POST `body` to `target`; succeeds on a 2xx answer.
+pub(crate) async fn post_http(
+    target: &HttpTarget,
+    body: &str,
+    content_type: &str,
+) -> Result<(), String> {
+    let mut stream = TcpStream::connect(&target.address)
+        .await
+        .map_err(|e| format!("connect {}: {}", target.address, e))?;
+    stream
+        .write_all(&http_request(target, body, content_type))
+        .await
+        .map_err(|e| format!("write: {}", e))?;
+
+    // Only the status line matters; read until it is complete or the server closes
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !response.contains(&b'\n') {
+        let read = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|e| format!("read: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        response.extend_from_slice(&chunk[..read]);
+    }
+
+    match http_status(&response) {
+        Some(status) if (200..300).contains(&status) => Ok(()),
+        Some(status) => Err(format!("collector answered HTTP {}", status)),
+        None => Err("invalid HTTP response".to_string()),
+    }
+}
+
+/// Largest response body `get_http` reads
+const MAX_GET_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+/// GET `target` and return the body of a 2xx answer.
+pub(crate) async fn get_http(target: &HttpTarget) -> Result<String, String> {
+    let mut stream = TcpStream::connect(&target.address)
+        .await
+        .map_err(|e| format!("connect {}: {}", target.address, e))?;
+    stream
+        .write_all(&http_get_request(target))
+        .await
+        .map_err(|e| format!("write: {}", e))?;
+
+    let mut response = Vec::new();
+    stream
+        .take(MAX_GET_RESPONSE_BYTES)
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| format!("read: {}", e))?;
+    match http_status(&response) {
+        Some(status) if (200..300).contains(&status) => {}
+        Some(status) => return Err(format!("server answered HTTP {}", status)),
+        None => return Err("invalid HTTP response".to_string()),
+    }
+    let body = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|index| &response[index + 4..])
+        .ok_or("invalid HTTP response")?;
+    String::from_utf8(body.to_vec()).map_err(|_| "response body is not UTF-8".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> AuditEvent {
+        let mut event = AuditEvent::new("ssh_execute")
+            .agent(Some("deploy"))
+            .host("web-1:22", "root")
+            .session_id("s-1")
+            .target("echo a=b | wc");
+        event.timestamp = "2025-01-15T10:00:00+00:00".to_string();
+        event
+    }
+
+    mod cef {
+        use super::*;
+
+        #[test]
+        fn test_format() {
+            let line = format_cef(&event());
+            assert!(line.starts_with("CEF:0|ssh-mcp|ssh-mcp|"));
+            assert!(line.contains("|ssh_execute|ssh_execute|3|rt=1736935200000 "));
+            assert!(line.contains("suser=deploy"));
+            assert!(line.contains("dhost=web-1:22"));
+            assert!(line.contains("msg=echo a\\=b | wc"));
+            assert!(line.contains("cs1Label=sessionId cs1=s-1"));
+            assert!(!line.contains("cn1"));
+
+            let line = format_cef(&event().command_id("c-1").exit_code(Some(127)));
+            assert!(line.contains("cs2Label=commandId cs2=c-1 cn1Label=exitCode cn1=127"));
+        }
+
+        #[test]
+        fn test_failure_severity_and_escaping() {
+            let line = format_cef(&event().failed("bad\nthing"));
+            assert!(line.contains("|7|"));
+            assert!(line.contains("outcome=failure"));
+            assert!(line.contains("reason=bad\\nthing"));
+            assert_eq!(cef_header("a|b\\c"), "a\\|b\\\\c");
+        }
+    }
+
+    mod syslog {
+        use super::*;
+
+        #[test]
+        fn test_message_header() {
+            let message = String::from_utf8(syslog_message(&event(), "{}")).unwrap();
+            assert!(message.starts_with("<134>1 2025-01-15T10:00:00+00:00 "));
+            assert!(message.contains(" ssh-mcp "));
+            assert!(message.ends_with(" ssh_execute - {}"));
+
+            let failed = String::from_utf8(syslog_message(&event().failed("x"), "{}")).unwrap();
+            assert!(failed.starts_with("<132>1 "));
+        }
+
+        #[tokio::test]
+        async fn test_udp_delivery() {
+            let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let address = collector.local_addr().unwrap().to_string();
+            send_syslog(&address, SyslogTransport::Udp, &[b"hello".to_vec()])
+                .await
+                .unwrap();
+            let mut buf = [0u8; 64];
+            let len = collector.recv(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], b"hello");
+        }
+
+        #[tokio::test]
+        async fn test_tcp_octet_counting() {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let server = tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                socket.read_to_end(&mut received).await.unwrap();
+                received
+            });
+            send_syslog(
+                &address,
+                SyslogTransport::Tcp,
+                &[b"one".to_vec(), b"three".to_vec()],
+            )
+            .await
+            .unwrap();
+            assert_eq!(server.await.unwrap(), b"3 one5 three");
+        }
+    }
+
+    mod http {
+        use super::*;
+
+        #[test]
+        fn test_parse_url() {
+            let target = parse_http_url("http://collector:8080/ingest?x=1").unwrap();
+            assert_eq!(target.address, "collector:8080");
+            assert_eq!(target.path, "/ingest?x=1");
+
+            let target = parse_http_url("http://collector").unwrap();
+            assert_eq!(target.address, "collector:80");
+            assert_eq!(target.host, "collector");
+            assert_eq!(target.path, "/");
+
+            assert_eq!(parse_http_url("http://[::1]").unwrap().address, "[::1]:80");
+            assert!(parse_http_url("https://collector").is_err());
+        }
+
+        #[test]
+        fn test_request_and_status() {
+            let mut target = parse_http_url("http://c:9/in").unwrap();
+            target
+                .headers
+                .insert("Authorization".to_string(), "Bearer t".to_string());
+            let request =
+                String::from_utf8(http_request(&target, "[]", "application/json")).unwrap();
+            assert!(request.starts_with("POST /in HTTP/1.1\r\nHost: c:9\r\n"));
+            assert!(request.contains("Content-Length: 2\r\n"));
+            assert!(request.contains("Authorization: Bearer t\r\n"));
+            assert!(request.ends_with("\r\n\r\n[]"));
+
+            assert_eq!(http_status(b"HTTP/1.1 204 No Content\r\n"), Some(204));
+            assert_eq!(http_status(b"garbage"), None);
+        }
+
+        #[test]
+        fn test_json_batch_is_array() {
+            let (body, content_type) = encode_batch(&[event(), event()], AuditFormat::Json);
+            assert_eq!(content_type, "application/json");
+            let parsed: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(parsed.as_array().unwrap().len(), 2);
+
+            let (body, _) = encode_batch(&[event(), event()], AuditFormat::Cef);
+            assert_eq!(body.lines().count(), 2);
+        }
+
+        #[tokio::test]
+        async fn test_post_checks_status() {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let target =
+                parse_http_url(&format!("http://{}/ingest", listener.local_addr().unwrap()))
+                    .unwrap();
+            tokio::spawn(async move {
+                for status in ["200 OK", "503 Service Unavailable"] {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await.unwrap();
+                    let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+
+            assert!(post_http(&target, "[]", "application/json").await.is_ok());
+            let error = post_http(&target, "[]", "application/json")
+                .await
+                .unwrap_err();
+            assert!(error.contains("503"));
+        }
+
+        #[tokio::test]
+        async fn test_get_returns_body() {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut target = parse_http_url(&format!(
+                "http://{}/v1/secret",
+                listener.local_addr().unwrap()
+            ))
+            .unwrap();
+            target
+                .headers
+                .insert("X-Vault-Token".to_string(), "t".to_string());
+            let server = tokio::spawn(async move {
+                let mut requests = Vec::new();
+                for response in [
+                    "HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\n{\"data\":{}}",
+                    "HTTP/1.1 403 Forbidden\r\n\r\n",
+                ] {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+                    let mut buf = [0u8; 4096];
+                    let read = socket.read(&mut buf).await.unwrap();
+                    requests.push(String::from_utf8_lossy(&buf[..read]).to_string());
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                }
+                requests
+            });
+
+            assert_eq!(get_http(&target).await.unwrap(), "{\"data\":{}}");
+            assert!(get_http(&target).await.unwrap_err().contains("403"));
+            let requests = server.await.unwrap();
+            assert!(requests[0].starts_with("GET /v1/secret HTTP/1.0\r\n"));
+            assert!(requests[0].contains("X-Vault-Token: t\r\n"));
+        }
+    }
+
+    mod file {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_appends_one_line_per_event() {
+            let path =
+                std::env::temp_dir().join(format!("ssh-mcp-audit-{}.jsonl", uuid::Uuid::new_v4()));
+            let exporter = Exporter {
+                sink: Sink::File(path.clone()),
+                format: AuditFormat::Json,
+                batch_size: 10,
+                flush_interval: Duration::ZERO,
+                max_retries: 0,
+            };
+            exporter.deliver(&[event(), event()]).await.unwrap();
+            exporter.deliver(&[event().failed("x")]).await.unwrap();
+
+            let content = std::fs::read_to_string(&path).unwrap();
+            let events: Vec<AuditEvent> = content
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(events.len(), 3);
+            assert_eq!(events[2].outcome, AuditOutcome::Failure);
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Signal delivery to shells and async commands (`ssh_shell_signal`,
+//! `ssh_command_signal`).
+//!
+//! Signals travel as SSH `signal` channel requests (RFC 4254 section 6.9),
+//! which the server delivers to the process it started for the channel: the
+//! command of an async command, or the shell itself for an interactive shell.
+//! The request carries no reply, so delivery can only be observed through the
+//! status of the command or shell afterwards. Servers that do not implement
+//! signal requests ignore them.
+//!
+//! An async command's channel is owned by its background task, so signals are
+//! handed to that task over a [`SignalSender`] and sent between channel reads.
+
+use std::time::Duration;
+
+use russh::Sig;
+use tokio::sync::{mpsc, oneshot};
+
+/// Signal names accepted by the signal tools, without the `SIG` prefix
+pub(crate) const SIGNAL_NAMES: &[&str] = &[
+    "INT", "TERM", "KILL", "HUP", "QUIT", "USR1", "ABRT", "ALRM", "PIPE",
+];
+
+/// Time a signalled command or shell gets to exit before its status is reported
+pub(crate) const SIGNAL_SETTLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Parse a signal name (`INT`, `SIGINT` or `int`) into the signal and its
+/// canonical name.
+pub(crate) fn parse_signal(name: &str) -> Result<(Sig, &'static str), String> {
+    let upper = name.trim().to_ascii_uppercase();
+    let bare = upper.strip_prefix("SIG").unwrap_or(&upper);
+    let parsed = match bare {
+        "INT" => (Sig::INT, "INT"),
+        "TERM" => (Sig::TERM, "TERM"),
+        "KILL" => (Sig::KILL, "KILL"),
+        "HUP" => (Sig::HUP, "HUP"),
+        "QUIT" => (Sig::QUIT, "QUIT"),
+        "USR1" => (Sig::USR1, "USR1"),
+        "ABRT" => (Sig::ABRT, "ABRT"),
+        "ALRM" => (Sig::ALRM, "ALRM"),
+        "PIPE" => (Sig::PIPE, "PIPE"),
+        _ => {
+            return Err(format!(
+                "Unknown signal '{}'; expected one of {}",
+                name,
+                SIGNAL_NAMES.join(", ")
+            ));
+        }
+    };
+    Ok(parsed)
+}
+
+/// A signal for a command's background task, with the outcome of sending it.
+pub struct SignalRequest {
+    pub signal: Sig,
+    pub reply: oneshot::Sender<Result<(), String>>,
+}
+
+/// Hands signals to the background task that owns a command's channel.
+pub type SignalSender = mpsc::UnboundedSender<SignalRequest>;
+
+/// Receiving end of a [`SignalSender`], polled by the background task.
+pub(crate) type SignalReceiver = mpsc::UnboundedReceiver<SignalRequest>;
+
+/// Create the channel between a command and its background task.
+pub(crate) fn signal_channel() -> (SignalSender, SignalReceiver) {
+    mpsc::unbounded_channel()
+}
+
+/// Ask a command's background task to send `signal` on its channel.
+pub(crate) async fn send(sender: &SignalSender, signal: Sig) -> Result<(), String> {
+    let (reply, outcome) = oneshot::channel();
+    sender
+        .send(SignalRequest { signal, reply })
+        .map_err(|_| "Command is no longer running".to_string())?;
+    outcome
+        .await
+        .map_err(|_| "Command finished before the signal was sent".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal_names() {
+        assert!(matches!(parse_signal("INT"), Ok((Sig::INT, "INT"))));
+        assert!(matches!(parse_signal("sigterm"), Ok((Sig::TERM, "TERM"))));
+        assert!(matches!(parse_signal(" Kill "), Ok((Sig::KILL, "KILL"))));
+        for name in SIGNAL_NAMES {
+            assert_eq!(
+                parse_signal(name).map(|(_, canonical)| canonical),
+                Ok(*name)
+            );
+        }
+        assert!(parse_signal("STOP").is_err());
+        assert!(parse_signal("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_reports_finished_command() {
+        let (sender, receiver) = signal_channel();
+        drop(receiver);
+        assert!(send(&sender, Sig::INT).await.is_err());
+
+        let (sender, mut receiver) = signal_channel();
+        tokio::spawn(async move {
+            let request = receiver.recv().await.unwrap();
+            let _ = request.reply.send(Ok(()));
+        });
+        assert!(send(&sender, Sig::TERM).await.is_ok());
+    }
+}
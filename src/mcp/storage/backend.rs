@@ -0,0 +1,141 @@
+//! Storage backend selection (`[storage] backend`, `SSH_STORAGE_BACKEND`).
+//!
+//! Tools reach sessions and async commands only through the
+//! [`SessionStorage`] and [`CommandStorage`] traits. The implementation
+//! behind them is chosen once per process: `SSH_STORAGE_BACKEND` first, then
+//! `[storage] backend`, then the in-memory DashMap storage. A backend that
+//! cannot be opened is logged and replaced by the in-memory one. The
+//! selection is process-global: the tools and the background tasks (health
+//! monitor, managed hosts, reboot watcher) all reach it through
+//! [`SESSION_STORAGE`] and [`COMMAND_STORAGE`], so a process cannot serve
+//! several storages side by side.
+//!
+//! A new backend implements both traits and adds a `StorageBackend` variant
+//! opened in [`Storage::open`]; the tools do not change. Embedders can also
+//! [`install`] their own implementations before the first handler is built.
+
+use std::sync::Arc;
+
+use once_cell::sync::{Lazy, OnceCell};
+use tracing::{error, info};
+
+use crate::mcp::config::{StorageBackend, StorageConfig, resolve_storage_backend, server_config};
+
+use super::command::DashMapCommandStorage;
+use super::session::DashMapSessionStorage;
+use super::traits::{CommandStorage, SessionStorage};
+
+/// Session and command storage shared by the tools and background tasks.
+#[derive(Clone)]
+pub struct Storage {
+    pub sessions: Arc<dyn SessionStorage>,
+    pub commands: Arc<dyn CommandStorage>,
+}
+
+impl Storage {
+    /// In-process DashMap storage.
+    pub fn memory() -> Self {
+        Self {
+            sessions: Arc::new(DashMapSessionStorage::new()),
+            commands: Arc::new(DashMapCommandStorage::new()),
+        }
+    }
+
+    /// Open the storage for a configured backend.
+    pub fn open(backend: StorageBackend, config: &StorageConfig) -> Result<Self, String> {
+        match backend {
+            StorageBackend::Memory => Ok(Self::memory()),
+            #[cfg(feature = "redis")]
+            StorageBackend::Redis => {
+                let (sessions, commands) = super::redis::open(&config.redis)?;
+                Ok(Self { sessions, commands })
+            }
+            #[cfg(not(feature = "redis"))]
+            StorageBackend::Redis => {
+                let _ = config;
+                Err(REDIS_DISABLED.to_string())
+            }
+        }
+    }
+}
+
+/// Why the redis backend is unavailable in builds without the `redis` feature
+#[cfg(not(feature = "redis"))]
+const REDIS_DISABLED: &str = "ssh-mcp was built without the redis feature";
+
+/// Check that `backend` can be opened with `config`, without connecting.
+pub(crate) fn check(backend: StorageBackend, config: &StorageConfig) -> Result<(), String> {
+    match backend {
+        StorageBackend::Memory => Ok(()),
+        #[cfg(feature = "redis")]
+        StorageBackend::Redis => super::redis::client(&config.redis).map(|_| ()),
+        #[cfg(not(feature = "redis"))]
+        StorageBackend::Redis => {
+            let _ = config;
+            Err(REDIS_DISABLED.to_string())
+        }
+    }
+}
+
+/// Storage selected for this process.
+static STORAGE: OnceCell<Storage> = OnceCell::new();
+
+/// Use `storage` for this process instead of the configured backend.
+///
+/// Fails, returning `storage`, once the backend has been selected: after
+/// another `install` or the first use of the storage.
+pub fn install(storage: Storage) -> Result<(), Storage> {
+    STORAGE.set(storage)
+}
+
+/// The storage for this process, opening the configured backend on first use.
+pub fn storage() -> &'static Storage {
+    STORAGE.get_or_init(|| {
+        let config = &server_config().storage;
+        let backend = resolve_storage_backend(config.backend);
+        match Storage::open(backend, config) {
+            Ok(storage) => {
+                info!(backend = backend.as_str(), "Session storage selected");
+                storage
+            }
+            Err(e) => {
+                error!(
+                    backend = backend.as_str(),
+                    error = %e,
+                    "Storage backend unavailable; keeping sessions in memory"
+                );
+                Storage::memory()
+            }
+        }
+    })
+}
+
+/// Global session storage instance.
+pub static SESSION_STORAGE: Lazy<Arc<dyn SessionStorage>> =
+    Lazy::new(|| storage().sessions.clone());
+
+/// Global command storage instance.
+pub static COMMAND_STORAGE: Lazy<Arc<dyn CommandStorage>> =
+    Lazy::new(|| storage().commands.clone());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_globals_share_selected_storage() {
+        let selected = storage();
+        assert!(Arc::ptr_eq(&SESSION_STORAGE, &selected.sessions));
+        assert!(Arc::ptr_eq(&COMMAND_STORAGE, &selected.commands));
+        assert!(install(Storage::memory()).is_err());
+    }
+
+    #[test]
+    fn test_check_backends() {
+        let config = StorageConfig::default();
+        assert!(check(StorageBackend::Memory, &config).is_ok());
+        // Without a url (or without the feature) the redis backend cannot open
+        assert!(check(StorageBackend::Redis, &config).is_err());
+        assert!(Storage::open(StorageBackend::Redis, &config).is_err());
+    }
+}
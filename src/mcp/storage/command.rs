@@ -7,9 +7,8 @@ use std::collections::HashSet;
 use std::sync::Arc;
 
 use dashmap::DashMap;
-use once_cell::sync::Lazy;
 
-use crate::mcp::async_command::RunningCommand;
+use crate::mcp::async_command::{RunningCommand, label_matches};
 use crate::mcp::types::{AsyncCommandInfo, AsyncCommandStatus};
 
 use super::traits::{CommandRef, CommandStorage};
@@ -49,6 +48,7 @@ impl DashMapCommandStorage {
     /// Returns a DashMap reference guard that provides access to the underlying
     /// `RunningCommand`. This is useful when you need to access multiple fields
     /// without cloning the entire struct.
+    #[allow(dead_code)]
     pub fn get_direct(
         &self,
         command_id: &str,
@@ -96,21 +96,22 @@ impl CommandStorage for DashMapCommandStorage {
     }
 
     fn get(&self, command_id: &str) -> Option<Arc<RunningCommand>> {
-        // Note: This creates a new Arc each time. For the current use case,
-        // callers typically access individual Arc-wrapped fields from the command.
-        // The trait returns Option<Arc<RunningCommand>> for API consistency,
-        // but in practice, get_direct() is preferred for direct field access.
+        // Note: This creates a new Arc each time. Callers typically access
+        // individual Arc-wrapped fields from the command, which stay shared.
         self.commands.get(command_id).map(|entry| {
             // Clone the RunningCommand's Arc-wrapped fields
             Arc::new(RunningCommand {
                 info: entry.info.clone(),
                 cancel_token: entry.cancel_token.clone(),
+                signal_tx: entry.signal_tx.clone(),
                 status_rx: entry.status_rx.clone(),
                 status_tx: entry.status_tx.clone(),
                 output: entry.output.clone(),
                 exit_code: entry.exit_code.clone(),
                 error: entry.error.clone(),
                 timed_out: entry.timed_out.clone(),
+                pattern_match: entry.pattern_match.clone(),
+                output_format: entry.output_format,
             })
         })
     }
@@ -121,12 +122,15 @@ impl CommandStorage for DashMapCommandStorage {
             running: Arc::new(RunningCommand {
                 info: entry.info.clone(),
                 cancel_token: entry.cancel_token.clone(),
+                signal_tx: entry.signal_tx.clone(),
                 status_rx: entry.status_rx.clone(),
                 status_tx: entry.status_tx.clone(),
                 output: entry.output.clone(),
                 exit_code: entry.exit_code.clone(),
                 error: entry.error.clone(),
                 timed_out: entry.timed_out.clone(),
+                pattern_match: entry.pattern_match.clone(),
+                output_format: entry.output_format,
             }),
         })
     }
@@ -160,6 +164,7 @@ impl CommandStorage for DashMapCommandStorage {
         &self,
         session_id: Option<&str>,
         status: Option<AsyncCommandStatus>,
+        label: Option<&str>,
     ) -> Vec<AsyncCommandInfo> {
         self.commands
             .iter()
@@ -170,7 +175,10 @@ impl CommandStorage for DashMapCommandStorage {
                 let status_matches = status
                     .map(|s| *entry.status_rx.borrow() == s)
                     .unwrap_or(true);
-                session_matches && status_matches
+                let label_matches = label
+                    .map(|query| label_matches(entry.info.label.as_deref(), query))
+                    .unwrap_or(true);
+                session_matches && status_matches && label_matches
             })
             .map(|entry| {
                 let mut info = entry.info.clone();
@@ -181,13 +189,12 @@ impl CommandStorage for DashMapCommandStorage {
     }
 }
 
-/// Global command storage instance.
-pub static COMMAND_STORAGE: Lazy<DashMapCommandStorage> = Lazy::new(DashMapCommandStorage::new);
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::mcp::async_command::OutputBuffer;
+    use crate::mcp::compress::OutputFormat;
+    use crate::mcp::signal::signal_channel;
     use crate::mcp::types::AsyncCommandStatus;
     use std::sync::atomic::AtomicBool;
     use tokio::sync::{Mutex, watch};
@@ -202,14 +209,18 @@ mod tests {
                 command: "test".to_string(),
                 status: AsyncCommandStatus::Running,
                 started_at: "2024-01-15T10:30:00Z".to_string(),
+                label: None,
             },
             cancel_token: CancellationToken::new(),
+            signal_tx: signal_channel().0,
             status_rx: rx,
             status_tx: tx,
             output: Arc::new(Mutex::new(OutputBuffer::default())),
             exit_code: Arc::new(Mutex::new(None)),
             error: Arc::new(Mutex::new(None)),
             timed_out: Arc::new(AtomicBool::new(false)),
+            pattern_match: Arc::new(Mutex::new(None)),
+            output_format: OutputFormat::Text,
         }
     }
 
@@ -226,14 +237,18 @@ mod tests {
                 command: "test".to_string(),
                 status,
                 started_at: "2024-01-15T10:30:00Z".to_string(),
+                label: None,
             },
             cancel_token: CancellationToken::new(),
+            signal_tx: signal_channel().0,
             status_rx: rx,
             status_tx: tx,
             output: Arc::new(Mutex::new(OutputBuffer::default())),
             exit_code: Arc::new(Mutex::new(None)),
             error: Arc::new(Mutex::new(None)),
             timed_out: Arc::new(AtomicBool::new(false)),
+            pattern_match: Arc::new(Mutex::new(None)),
+            output_format: OutputFormat::Text,
         }
     }
 
@@ -850,13 +865,13 @@ mod tests {
         storage.register(cmd_3.clone(), create_test_command(&cmd_3, &session_id_2));
 
         // Filter by session_id_1
-        let filtered = storage.list_filtered(Some(&session_id_1), None);
+        let filtered = storage.list_filtered(Some(&session_id_1), None, None);
         assert_eq!(filtered.len(), 2);
         assert!(filtered.iter().any(|c| c.command_id == cmd_1));
         assert!(filtered.iter().any(|c| c.command_id == cmd_2));
 
         // Filter by session_id_2
-        let filtered = storage.list_filtered(Some(&session_id_2), None);
+        let filtered = storage.list_filtered(Some(&session_id_2), None, None);
         assert_eq!(filtered.len(), 1);
         assert!(filtered.iter().any(|c| c.command_id == cmd_3));
 
@@ -888,7 +903,7 @@ mod tests {
         );
 
         // Filter by Running status
-        let running = storage.list_filtered(None, Some(AsyncCommandStatus::Running));
+        let running = storage.list_filtered(None, Some(AsyncCommandStatus::Running), None);
         let our_running: Vec<_> = running
             .iter()
             .filter(|c| c.command_id == running_id)
@@ -896,7 +911,7 @@ mod tests {
         assert_eq!(our_running.len(), 1);
 
         // Filter by Completed status
-        let completed = storage.list_filtered(None, Some(AsyncCommandStatus::Completed));
+        let completed = storage.list_filtered(None, Some(AsyncCommandStatus::Completed), None);
         let our_completed: Vec<_> = completed
             .iter()
             .filter(|c| c.command_id == completed_id)
@@ -933,13 +948,16 @@ mod tests {
 
         // Filter by session_id_1 and Running status
         let filtered =
-            storage.list_filtered(Some(&session_id_1), Some(AsyncCommandStatus::Running));
+            storage.list_filtered(Some(&session_id_1), Some(AsyncCommandStatus::Running), None);
         assert_eq!(filtered.len(), 1);
         assert_eq!(filtered[0].command_id, cmd_1);
 
         // Filter by session_id_1 and Completed status
-        let filtered =
-            storage.list_filtered(Some(&session_id_1), Some(AsyncCommandStatus::Completed));
+        let filtered = storage.list_filtered(
+            Some(&session_id_1),
+            Some(AsyncCommandStatus::Completed),
+            None,
+        );
         assert_eq!(filtered.len(), 1);
         assert_eq!(filtered[0].command_id, cmd_2);
 
@@ -958,7 +976,7 @@ mod tests {
         storage.register(cmd_id.clone(), create_test_command(&cmd_id, &session_id));
 
         // No filters should return all (at least our command)
-        let all = storage.list_filtered(None, None);
+        let all = storage.list_filtered(None, None, None);
         assert!(all.iter().any(|c| c.command_id == cmd_id));
 
         // Cleanup
@@ -978,7 +996,7 @@ mod tests {
 
         // Filter by Cancelled status (our command is Running)
         let filtered =
-            storage.list_filtered(Some(&session_id), Some(AsyncCommandStatus::Cancelled));
+            storage.list_filtered(Some(&session_id), Some(AsyncCommandStatus::Cancelled), None);
         assert!(filtered.is_empty());
 
         // Cleanup
@@ -990,7 +1008,28 @@ mod tests {
         let storage = DashMapCommandStorage::new();
         let unique_session = format!("nonexistent-{}", uuid::Uuid::new_v4());
 
-        let filtered = storage.list_filtered(Some(&unique_session), None);
+        let filtered = storage.list_filtered(Some(&unique_session), None, None);
+        assert!(filtered.is_empty());
+    }
+
+    #[test]
+    fn test_list_filtered_by_label() {
+        let storage = DashMapCommandStorage::new();
+        let session_id = format!("session-{}", uuid::Uuid::new_v4());
+        let mut deploy = create_test_command("cmd-deploy", &session_id);
+        deploy.info.label = Some("Deploy v1.2.3 step 2/5".to_string());
+        storage.register("cmd-deploy".to_string(), deploy);
+        storage.register(
+            "cmd-plain".to_string(),
+            create_test_command("cmd-plain", &session_id),
+        );
+
+        let filtered = storage.list_filtered(Some(&session_id), None, Some("step 2"));
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].command_id, "cmd-deploy");
+        assert_eq!(filtered[0].label.as_deref(), Some("Deploy v1.2.3 step 2/5"));
+
+        let filtered = storage.list_filtered(Some(&session_id), None, Some("rollback"));
         assert!(filtered.is_empty());
     }
 }
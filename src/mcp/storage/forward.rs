@@ -0,0 +1,217 @@
+//! DashMap-based port forward storage implementation.
+//!
+//! Keeps every local forward listener started by `ssh_forward` under a
+//! forward ID so it can be listed, cancelled, and closed with its session.
+//! Includes a secondary index for O(1) session-to-forwards lookups.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use tokio_util::sync::CancellationToken;
+
+use crate::mcp::types::ForwardInfo;
+
+/// A running local forward listener.
+pub struct RunningForward {
+    /// Forward metadata; `connections` is filled from the counter on read
+    pub info: ForwardInfo,
+    /// Cancels the listener and the connections it forwards
+    pub cancel_token: CancellationToken,
+    /// Connections accepted so far
+    pub connections: Arc<AtomicU64>,
+}
+
+impl RunningForward {
+    /// Forward metadata with the current connection count.
+    pub fn current_info(&self) -> ForwardInfo {
+        ForwardInfo {
+            connections: self.connections.load(Ordering::Relaxed),
+            ..self.info.clone()
+        }
+    }
+}
+
+/// Trait for port forward storage operations.
+///
+/// Implementations must be thread-safe (`Send + Sync`) for use across
+/// async tasks. The default implementation uses `DashMap` for lock-free
+/// concurrent access with a secondary index for O(1) session lookups.
+#[allow(dead_code)]
+pub trait ForwardStorage: Send + Sync {
+    /// Register a new forward.
+    fn register(&self, forward_id: String, forward: RunningForward);
+
+    /// Unregister a forward by ID, returning it if it existed.
+    fn unregister(&self, forward_id: &str) -> Option<RunningForward>;
+
+    /// Get a forward's info with its current connection count.
+    fn get_info(&self, forward_id: &str) -> Option<ForwardInfo>;
+
+    /// List all forward IDs for a session.
+    fn list_by_session(&self, session_id: &str) -> Vec<String>;
+
+    /// Count forwards for a session.
+    fn count_by_session(&self, session_id: &str) -> usize;
+
+    /// List forward info filtered by session.
+    fn list_filtered(&self, session_id: Option<&str>) -> Vec<ForwardInfo>;
+}
+
+/// DashMap-based implementation of `ForwardStorage`.
+///
+/// Uses two `DashMap` instances:
+/// - Primary storage: forward_id -> RunningForward
+/// - Secondary index: session_id -> HashSet<forward_id> for O(1) session lookups
+pub struct DashMapForwardStorage {
+    forwards: DashMap<String, RunningForward>,
+    forwards_by_session: DashMap<String, HashSet<String>>,
+}
+
+impl DashMapForwardStorage {
+    /// Create a new forward storage instance.
+    pub fn new() -> Self {
+        Self {
+            forwards: DashMap::new(),
+            forwards_by_session: DashMap::new(),
+        }
+    }
+}
+
+impl Default for DashMapForwardStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ForwardStorage for DashMapForwardStorage {
+    fn register(&self, forward_id: String, forward: RunningForward) {
+        let session_id = forward.info.session_id.clone();
+
+        // Insert into primary storage
+        self.forwards.insert(forward_id.clone(), forward);
+
+        // Update secondary index
+        self.forwards_by_session
+            .entry(session_id)
+            .or_default()
+            .insert(forward_id);
+    }
+
+    fn unregister(&self, forward_id: &str) -> Option<RunningForward> {
+        // Remove from primary storage
+        let removed = self.forwards.remove(forward_id).map(|(_, forward)| forward);
+
+        // Update secondary index if forward was found
+        if let Some(ref forward) = removed
+            && let Some(mut set) = self.forwards_by_session.get_mut(&forward.info.session_id)
+        {
+            set.remove(forward_id);
+            if set.is_empty() {
+                drop(set);
+                self.forwards_by_session.remove(&forward.info.session_id);
+            }
+        }
+
+        removed
+    }
+
+    fn get_info(&self, forward_id: &str) -> Option<ForwardInfo> {
+        self.forwards
+            .get(forward_id)
+            .map(|forward| forward.current_info())
+    }
+
+    fn list_by_session(&self, session_id: &str) -> Vec<String> {
+        self.forwards_by_session
+            .get(session_id)
+            .map(|set| set.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn count_by_session(&self, session_id: &str) -> usize {
+        self.forwards_by_session
+            .get(session_id)
+            .map(|set| set.len())
+            .unwrap_or(0)
+    }
+
+    fn list_filtered(&self, session_id: Option<&str>) -> Vec<ForwardInfo> {
+        self.forwards
+            .iter()
+            .filter(|entry| session_id.is_none_or(|sid| entry.info.session_id == sid))
+            .map(|entry| entry.current_info())
+            .collect()
+    }
+}
+
+/// Global forward storage instance.
+pub static FORWARD_STORAGE: Lazy<DashMapForwardStorage> = Lazy::new(DashMapForwardStorage::new);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forward(session_id: &str, local_port: u16) -> RunningForward {
+        RunningForward {
+            info: ForwardInfo {
+                forward_id: format!("fwd-{}", local_port),
+                session_id: session_id.to_string(),
+                local_address: format!("127.0.0.1:{}", local_port),
+                remote_address: "localhost:5432".to_string(),
+                created_at: "2025-01-15T10:00:00+00:00".to_string(),
+                connections: 0,
+                rate_limit_bytes_per_sec: None,
+            },
+            cancel_token: CancellationToken::new(),
+            connections: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    #[test]
+    fn test_storage_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<DashMapForwardStorage>();
+    }
+
+    #[test]
+    fn test_register_and_unregister_by_session() {
+        let storage = DashMapForwardStorage::new();
+        storage.register("fwd-8080".to_string(), forward("s-1", 8080));
+        storage.register("fwd-8081".to_string(), forward("s-1", 8081));
+        let other = forward("s-2", 9090);
+        let other_token = other.cancel_token.clone();
+        storage.register("fwd-9090".to_string(), other);
+
+        assert_eq!(storage.count_by_session("s-1"), 2);
+        assert_eq!(storage.list_filtered(Some("s-2")).len(), 1);
+        assert_eq!(storage.list_filtered(None).len(), 3);
+
+        for forward_id in storage.list_by_session("s-1") {
+            storage
+                .unregister(&forward_id)
+                .unwrap()
+                .cancel_token
+                .cancel();
+        }
+        assert_eq!(storage.count_by_session("s-1"), 0);
+        assert!(storage.list_by_session("s-1").is_empty());
+        assert_eq!(storage.count_by_session("s-2"), 1);
+        assert!(!other_token.is_cancelled());
+        assert!(storage.unregister("fwd-8080").is_none());
+    }
+
+    #[test]
+    fn test_info_reports_connection_count() {
+        let storage = DashMapForwardStorage::new();
+        let running = forward("s-1", 8080);
+        let connections = running.connections.clone();
+        storage.register("fwd-8080".to_string(), running);
+
+        connections.fetch_add(3, Ordering::Relaxed);
+        assert_eq!(storage.get_info("fwd-8080").unwrap().connections, 3);
+        assert!(storage.get_info("missing").is_none());
+    }
+}
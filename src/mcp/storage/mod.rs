@@ -4,20 +4,30 @@
 //! - Dependency injection for testability
 //! - Lock-free concurrent access via DashMap implementations
 //! - Clean separation between storage and business logic
+//! - Backend selection at startup (`backend`)
+//! - Optional session state file read back after a restart (`persistent`)
+//! - Redis registry shared by the replicas of a deployment (`redis` feature)
 
+pub mod backend;
 mod command;
+mod forward;
+pub(crate) mod persistent;
+#[cfg(feature = "redis")]
+pub(crate) mod redis;
 mod session;
 mod shell;
 mod traits;
 
-pub use command::COMMAND_STORAGE;
+pub use backend::{COMMAND_STORAGE, SESSION_STORAGE, Storage};
 #[allow(unused_imports)]
 pub use command::DashMapCommandStorage;
 #[allow(unused_imports)]
+pub use forward::DashMapForwardStorage;
+pub use forward::{FORWARD_STORAGE, ForwardStorage, RunningForward};
+#[allow(unused_imports)]
 pub use session::DashMapSessionStorage;
-pub use session::SESSION_STORAGE;
 #[allow(unused_imports)]
 pub use shell::DashMapShellStorage;
 pub use shell::SHELL_STORAGE;
 pub use shell::ShellStorage;
-pub use traits::{CommandStorage, SessionStorage};
+pub use traits::{CommandStorage, SessionRef, SessionStorage};
@@ -0,0 +1,504 @@
+//! Session state kept across server restarts (`[persistence]`).
+//!
+//! SSH connections cannot outlive the server process, so sessions and async
+//! commands stay in memory. With `[persistence] path` set, a background task
+//! writes what is open (session metadata and owning agents, the connect
+//! parameters without credentials, recent async commands) to a JSON file
+//! whenever it changes, and at least every minute.
+//!
+//! On the next start the file is read back as *lost* sessions and commands:
+//! a stale `session_id` or `command_id` then fails with `session_lost` /
+//! `command_lost` and the parameters to reconnect with, instead of a bare
+//! `*_not_found`, and `ssh_list_sessions` reports them in `lost_sessions`.
+//! Lost entries are carried over to later files until `retention_secs`
+//! passes.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::mcp::config::{PersistenceConfig, server_config};
+use crate::mcp::managed::find_managed_host;
+use crate::mcp::reconnect::{self, ConnectParams};
+use crate::mcp::types::{LostCommand, LostSession, ReconnectHint, SessionInfo};
+
+use super::traits::{CommandStorage, SessionStorage};
+use super::{COMMAND_STORAGE, SESSION_STORAGE};
+
+/// Format version of the state file
+const STATE_VERSION: u32 = 1;
+
+/// How often the open sessions are compared with the last file written
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest time between writes, so `last_seen_at` stays close to a crash
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What the state file records.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct StateSnapshot {
+    /// Sessions open when the file was written
+    #[serde(default)]
+    sessions: Vec<LostSession>,
+    /// Async commands known when the file was written
+    #[serde(default)]
+    commands: Vec<LostCommand>,
+    /// Sessions lost in an earlier restart, within retention
+    #[serde(default)]
+    lost_sessions: Vec<LostSession>,
+    /// Commands lost in an earlier restart, within retention
+    #[serde(default)]
+    lost_commands: Vec<LostCommand>,
+}
+
+/// The state file.
+#[derive(Debug, Serialize, Deserialize)]
+struct StateFile {
+    version: u32,
+    saved_at: String,
+    #[serde(flatten)]
+    state: StateSnapshot,
+}
+
+impl StateFile {
+    /// Stamp the open sessions and commands of `state` as seen at `saved_at`.
+    fn new(mut state: StateSnapshot, saved_at: String) -> Self {
+        for session in &mut state.sessions {
+            session.last_seen_at = saved_at.clone();
+        }
+        for command in &mut state.commands {
+            command.last_seen_at = saved_at.clone();
+        }
+        Self {
+            version: STATE_VERSION,
+            saved_at,
+            state,
+        }
+    }
+
+    /// Everything the file knew about, as lost entries.
+    fn into_lost(self) -> (Vec<LostSession>, Vec<LostCommand>) {
+        let StateFile {
+            saved_at, state, ..
+        } = self;
+        let mut sessions = state.lost_sessions;
+        sessions.extend(state.sessions.into_iter().map(|mut session| {
+            session.last_seen_at = saved_at.clone();
+            session
+        }));
+        let mut commands = state.lost_commands;
+        commands.extend(state.commands.into_iter().map(|mut command| {
+            command.last_seen_at = saved_at.clone();
+            command
+        }));
+        (sessions, commands)
+    }
+}
+
+/// Sessions and commands lost in a restart, by ID.
+#[derive(Default)]
+struct LostState {
+    sessions: DashMap<String, LostSession>,
+    commands: DashMap<String, LostCommand>,
+}
+
+static LOST: Lazy<LostState> = Lazy::new(LostState::default);
+
+/// Set once the state file has been read and the writer started.
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// The session `session_id` if it was lost in a restart.
+pub(crate) fn lost_session(session_id: &str) -> Option<LostSession> {
+    LOST.sessions
+        .get(session_id)
+        .map(|entry| entry.value().clone())
+}
+
+/// The command `command_id` if it was lost in a restart.
+pub(crate) fn lost_command(command_id: &str) -> Option<LostCommand> {
+    LOST.commands
+        .get(command_id)
+        .map(|entry| entry.value().clone())
+}
+
+/// Lost sessions, most recently seen first; only `agent_id`'s when given.
+pub(crate) fn lost_sessions(agent_id: Option<&str>) -> Vec<LostSession> {
+    let mut sessions: Vec<LostSession> = LOST
+        .sessions
+        .iter()
+        .filter(|entry| agent_id.is_none() || entry.agent_id.as_deref() == agent_id)
+        .map(|entry| entry.value().clone())
+        .collect();
+    sessions.sort_by(|a, b| b.last_seen_at.cmp(&a.last_seen_at));
+    sessions
+}
+
+/// Whether `last_seen_at` is within `retention` of `now`; unreadable times are dropped.
+fn within_retention(last_seen_at: &str, retention: Duration, now: DateTime<Utc>) -> bool {
+    let Ok(seen) = DateTime::parse_from_rfc3339(last_seen_at) else {
+        return false;
+    };
+    chrono::Duration::from_std(retention)
+        .map(|retention| seen.with_timezone(&Utc) + retention >= now)
+        .unwrap_or(true)
+}
+
+/// The ssh_connect parameters of a session, without credentials.
+fn reconnect_hint(params: &ConnectParams) -> ReconnectHint {
+    ReconnectHint {
+        address: params.address.clone(),
+        username: params.username.clone(),
+        auth: params.auth().to_string(),
+        credential_ref: params.credential_ref.clone(),
+        key_path: params.key_path.clone(),
+        jump_hosts: params
+            .jump_hosts
+            .iter()
+            .map(|hop| hop.label(&params.username))
+            .collect(),
+        compress: params.compress,
+    }
+}
+
+/// The record of an open session; `last_seen_at` is stamped when written.
+fn session_record(info: &SessionInfo, reconnect: Option<ReconnectHint>) -> LostSession {
+    LostSession {
+        session_id: info.session_id.clone(),
+        name: info.name.clone(),
+        agent_id: info.agent_id.clone(),
+        host: info.host.clone(),
+        username: info.username.clone(),
+        connected_at: info.connected_at.clone(),
+        last_seen_at: String::new(),
+        reconnect,
+    }
+}
+
+/// Snapshot the open sessions and commands, plus the lost entries still
+/// within retention.
+///
+/// Managed hosts are left out: the server connects them again itself.
+fn snapshot(
+    sessions: &dyn SessionStorage,
+    commands: &dyn CommandStorage,
+    config: &PersistenceConfig,
+) -> StateSnapshot {
+    let mut open_sessions: Vec<LostSession> = sessions
+        .list()
+        .iter()
+        .filter(|info| find_managed_host(&info.session_id).is_none())
+        .map(|info| {
+            let hint = reconnect::connect_params(&info.session_id).map(|p| reconnect_hint(&p));
+            session_record(info, hint)
+        })
+        .collect();
+    open_sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+
+    let mut open_commands: Vec<LostCommand> = commands
+        .list_all()
+        .into_iter()
+        .map(|info| LostCommand {
+            command_id: info.command_id,
+            session_id: info.session_id,
+            command: info.command,
+            label: info.label,
+            started_at: info.started_at,
+            status: info.status,
+            last_seen_at: String::new(),
+        })
+        .collect();
+    open_commands.sort_by(|a, b| {
+        b.started_at
+            .cmp(&a.started_at)
+            .then_with(|| a.command_id.cmp(&b.command_id))
+    });
+    open_commands.truncate(config.max_commands);
+
+    let retention = Duration::from_secs(config.retention_secs);
+    let now = Utc::now();
+    let mut lost_sessions: Vec<LostSession> = LOST
+        .sessions
+        .iter()
+        .filter(|entry| within_retention(&entry.last_seen_at, retention, now))
+        .map(|entry| entry.value().clone())
+        .collect();
+    lost_sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    let mut lost_commands: Vec<LostCommand> = LOST
+        .commands
+        .iter()
+        .filter(|entry| within_retention(&entry.last_seen_at, retention, now))
+        .map(|entry| entry.value().clone())
+        .collect();
+    lost_commands.sort_by(|a, b| a.command_id.cmp(&b.command_id));
+
+    StateSnapshot {
+        sessions: open_sessions,
+        commands: open_commands,
+        lost_sessions,
+        lost_commands,
+    }
+}
+
+/// Read the state file; `None` when it does not exist yet.
+fn read_state(path: &Path) -> Result<Option<StateFile>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e)),
+    };
+    let file: StateFile = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid state file {}: {}", path.display(), e))?;
+    if file.version != STATE_VERSION {
+        return Err(format!(
+            "Unsupported state file version {} in {}",
+            file.version,
+            path.display()
+        ));
+    }
+    Ok(Some(file))
+}
+
+/// Replace the state file atomically (temporary file, then rename); only
+/// the owner can read it.
+fn write_state(path: &Path, file: &StateFile) -> io::Result<()> {
+    let json = serde_json::to_vec(file).map_err(io::Error::other)?;
+    let mut tmp = PathBuf::from(path);
+    tmp.as_mut_os_string().push(".tmp");
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut out = options.open(&tmp)?;
+    out.write_all(&json)?;
+    out.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Load the lost entries of `file` that are within retention.
+fn load_lost(file: StateFile, retention: Duration) -> (usize, usize) {
+    let now = Utc::now();
+    let (sessions, commands) = file.into_lost();
+    for session in sessions {
+        if within_retention(&session.last_seen_at, retention, now) {
+            LOST.sessions.insert(session.session_id.clone(), session);
+        }
+    }
+    for command in commands {
+        if within_retention(&command.last_seen_at, retention, now) {
+            LOST.commands.insert(command.command_id.clone(), command);
+        }
+    }
+    (LOST.sessions.len(), LOST.commands.len())
+}
+
+/// Read the previous state file and keep writing the current state when
+/// `[persistence] path` is set; later calls do nothing.
+///
+/// Needs a Tokio runtime; called when an MCP handler is created.
+pub(crate) fn start() {
+    let config = &server_config().persistence;
+    let Some(path) = config.path.as_deref() else {
+        return;
+    };
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let path = PathBuf::from(path);
+
+    match read_state(&path) {
+        Ok(Some(file)) => {
+            let (sessions, commands) = load_lost(file, Duration::from_secs(config.retention_secs));
+            info!(
+                "Loaded {} lost session(s) and {} lost command(s) from {}",
+                sessions,
+                commands,
+                path.display()
+            );
+        }
+        Ok(None) => info!("No session state at {}; starting fresh", path.display()),
+        Err(e) => warn!("{}; starting without lost sessions", e),
+    }
+
+    if tokio::runtime::Handle::try_current().is_err() {
+        warn!("No async runtime; session state is not written");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut written: Option<(StateSnapshot, Instant)> = None;
+        let mut failing = false;
+        loop {
+            interval.tick().await;
+            let state = snapshot(&**SESSION_STORAGE, &**COMMAND_STORAGE, config);
+            if let Some((last, at)) = &written
+                && *last == state
+                && at.elapsed() < HEARTBEAT_INTERVAL
+            {
+                continue;
+            }
+            let file = StateFile::new(state.clone(), Utc::now().to_rfc3339());
+            let target = path.clone();
+            match tokio::task::spawn_blocking(move || write_state(&target, &file)).await {
+                Ok(Ok(())) => {
+                    written = Some((state, Instant::now()));
+                    failing = false;
+                }
+                // Warn once per failure streak, not every second
+                Ok(Err(e)) if !failing => {
+                    warn!("Cannot write session state to {}: {}", path.display(), e);
+                    failing = true;
+                }
+                Ok(Err(_)) => {}
+                Err(e) => warn!("Session state writer failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::config::Keepalive;
+    use crate::mcp::types::AsyncCommandStatus;
+
+    fn session(session_id: &str, last_seen_at: &str) -> LostSession {
+        LostSession {
+            session_id: session_id.to_string(),
+            name: None,
+            agent_id: Some("agent-a".to_string()),
+            host: "db1:22".to_string(),
+            username: "deploy".to_string(),
+            connected_at: "2026-10-01T10:00:00+00:00".to_string(),
+            last_seen_at: last_seen_at.to_string(),
+            reconnect: Some(ReconnectHint {
+                address: "db1".to_string(),
+                username: "deploy".to_string(),
+                auth: "key_path".to_string(),
+                credential_ref: None,
+                key_path: Some("~/.ssh/id_ed25519".to_string()),
+                jump_hosts: vec!["ops@bastion:22".to_string()],
+                compress: false,
+            }),
+        }
+    }
+
+    fn command(command_id: &str, session_id: &str) -> LostCommand {
+        LostCommand {
+            command_id: command_id.to_string(),
+            session_id: session_id.to_string(),
+            command: "make test".to_string(),
+            label: None,
+            started_at: "2026-10-01T10:05:00+00:00".to_string(),
+            status: AsyncCommandStatus::Running,
+            last_seen_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_open_entries_become_lost_at_save_time() {
+        let state = StateSnapshot {
+            sessions: vec![session("s-open", "")],
+            commands: vec![command("c-open", "s-open")],
+            lost_sessions: vec![session("s-old", "2026-09-30T08:00:00+00:00")],
+            lost_commands: Vec::new(),
+        };
+        let file = StateFile::new(state, "2026-10-01T11:00:00+00:00".to_string());
+        let json = serde_json::to_string(&file).unwrap();
+        let file: StateFile = serde_json::from_str(&json).unwrap();
+
+        let (sessions, commands) = file.into_lost();
+        assert_eq!(sessions.len(), 2);
+        let open = sessions.iter().find(|s| s.session_id == "s-open").unwrap();
+        assert_eq!(open.last_seen_at, "2026-10-01T11:00:00+00:00");
+        let old = sessions.iter().find(|s| s.session_id == "s-old").unwrap();
+        assert_eq!(old.last_seen_at, "2026-09-30T08:00:00+00:00");
+        assert_eq!(commands[0].last_seen_at, "2026-10-01T11:00:00+00:00");
+    }
+
+    #[test]
+    fn test_retention() {
+        let now = DateTime::parse_from_rfc3339("2026-10-02T10:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        let day = Duration::from_secs(86400);
+        assert!(within_retention("2026-10-01T11:00:00+00:00", day, now));
+        assert!(!within_retention("2026-10-01T09:00:00+00:00", day, now));
+        assert!(!within_retention("not a time", day, now));
+    }
+
+    #[test]
+    fn test_write_and_read_state_file() {
+        let path =
+            std::env::temp_dir().join(format!("ssh-mcp-state-{}.json", uuid::Uuid::new_v4()));
+        assert!(read_state(&path).unwrap().is_none());
+
+        let state = StateSnapshot {
+            sessions: vec![session("s-1", "")],
+            ..StateSnapshot::default()
+        };
+        let file = StateFile::new(state, "2026-10-01T11:00:00+00:00".to_string());
+        write_state(&path, &file).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let read = read_state(&path).unwrap().unwrap();
+        assert_eq!(read.saved_at, "2026-10-01T11:00:00+00:00");
+        assert_eq!(read.state.sessions[0].session_id, "s-1");
+
+        fs::write(&path, r#"{"version":99,"saved_at":""}"#).unwrap();
+        assert!(read_state(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_reconnect_hint_has_no_secrets() {
+        let params = ConnectParams {
+            address: "db1:2222".to_string(),
+            username: "deploy".to_string(),
+            password: Some("hunter2".to_string()),
+            key_path: None,
+            key_data: None,
+            timeout: Duration::from_secs(30),
+            inactivity_timeout: Duration::from_secs(300),
+            compress: true,
+            persistent: false,
+            keepalive: Keepalive::default(),
+            algorithms: Default::default(),
+            host_key_checking: Default::default(),
+            jump_hosts: Vec::new(),
+            auto_reconnect: false,
+            credential_ref: None,
+            agent_forwarding: false,
+            redacted: None,
+        };
+        let hint = reconnect_hint(&params);
+        assert_eq!(hint.auth, "password");
+        assert_eq!(reconnect_hint(&params.without_secrets()), hint);
+        assert!(!serde_json::to_string(&hint).unwrap().contains("hunter2"));
+
+        let hint = reconnect_hint(&ConnectParams {
+            credential_ref: Some("prod-db".to_string()),
+            ..params
+        });
+        assert_eq!(hint.auth, "credential_ref");
+        assert_eq!(hint.credential_ref.as_deref(), Some("prod-db"));
+        assert!(!serde_json::to_string(&hint).unwrap().contains("hunter2"));
+    }
+}
@@ -0,0 +1,586 @@
+//! Redis-backed session and command registry (`redis` feature,
+//! `[storage] backend = "redis"`).
+//!
+//! An SSH connection lives in the replica that opened it, so each replica
+//! keeps its handles in the DashMap storages. [`RedisSessionStorage`] and
+//! [`RedisCommandStorage`] wrap them and mirror the metadata of every session
+//! and async command to Redis as `{key_prefix}:session:{id}` and
+//! `{key_prefix}:command:{id}`, tagged with the owning replica. Keys expire
+//! after `ttl_secs` unless refreshed, so the entries of a replica that stops
+//! disappear on their own.
+//!
+//! A background task applies the writes, refreshes the local entries every
+//! third of the TTL and reads back the entries of the other replicas. A call
+//! that reaches a replica without the session or command then fails with
+//! `wrong_replica` naming the owner and its `advertise_url`, so a load
+//! balancer or client can route it there (session affinity).
+//!
+//! Redis outages never fail a tool call: writes are dropped with a warning and
+//! repaired by the next refresh, or by key expiry for removed entries.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ::redis::AsyncCommands;
+use ::redis::aio::MultiplexedConnection;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use russh::client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::mcp::async_command::RunningCommand;
+use crate::mcp::config::RedisStorageConfig;
+use crate::mcp::session::SshClientHandler;
+use crate::mcp::traffic::ByteCounters;
+use crate::mcp::types::{AsyncCommandInfo, AsyncCommandStatus, SessionActivity, SessionInfo};
+
+use super::command::DashMapCommandStorage;
+use super::session::DashMapSessionStorage;
+use super::traits::{CommandRef, CommandStorage, SessionRef, SessionStorage};
+
+/// Shortest refresh interval, whatever the TTL
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delay before reconnecting after Redis became unreachable
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Keys read per SCAN and MGET round trip
+const SCAN_BATCH: usize = 500;
+
+/// A session or command as stored in Redis, with the replica holding it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReplicaEntry<T> {
+    /// `replica_id` of the replica holding the connection
+    pub replica: String,
+    /// Where clients reach that replica, when it advertises one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica_url: Option<String>,
+    pub info: T,
+}
+
+/// Sessions held by other replicas, as of the last refresh.
+static REMOTE_SESSIONS: Lazy<DashMap<String, ReplicaEntry<SessionInfo>>> = Lazy::new(DashMap::new);
+
+/// Async commands held by other replicas, as of the last refresh.
+static REMOTE_COMMANDS: Lazy<DashMap<String, ReplicaEntry<AsyncCommandInfo>>> =
+    Lazy::new(DashMap::new);
+
+/// The other replica holding `session_id`, if any.
+pub(crate) fn session_owner(session_id: &str) -> Option<ReplicaEntry<SessionInfo>> {
+    REMOTE_SESSIONS.get(session_id).map(|entry| entry.clone())
+}
+
+/// The other replica holding `command_id`, if any.
+pub(crate) fn command_owner(command_id: &str) -> Option<ReplicaEntry<AsyncCommandInfo>> {
+    REMOTE_COMMANDS.get(command_id).map(|entry| entry.clone())
+}
+
+/// A change to mirror to Redis.
+enum Write {
+    Session(Box<SessionInfo>),
+    Command(AsyncCommandInfo),
+    Delete(String),
+}
+
+/// This replica's identity and key layout, shared by the storages and the task.
+struct Registry {
+    key_prefix: String,
+    replica: String,
+    replica_url: Option<String>,
+    ttl: Duration,
+    writes: mpsc::UnboundedSender<Write>,
+}
+
+impl Registry {
+    fn session_key(&self, session_id: &str) -> String {
+        format!("{}:session:{}", self.key_prefix, session_id)
+    }
+
+    fn command_key(&self, command_id: &str) -> String {
+        format!("{}:command:{}", self.key_prefix, command_id)
+    }
+
+    fn entry<T: Serialize>(&self, info: T) -> Option<String> {
+        serde_json::to_string(&ReplicaEntry {
+            replica: self.replica.clone(),
+            replica_url: self.replica_url.clone(),
+            info,
+        })
+        .ok()
+    }
+
+    fn send(&self, write: Write) {
+        // The task only stops with the runtime; nothing is left to mirror then
+        let _ = self.writes.send(write);
+    }
+}
+
+/// Resolve the replica name: config -> `HOSTNAME` -> random id.
+fn replica_id(config: &RedisStorageConfig) -> String {
+    config
+        .replica_id
+        .clone()
+        .or_else(|| {
+            std::env::var("HOSTNAME")
+                .ok()
+                .filter(|name| !name.is_empty())
+        })
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Build the Redis client for `[storage.redis]` without connecting.
+pub(crate) fn client(config: &RedisStorageConfig) -> Result<::redis::Client, String> {
+    let url = config
+        .url
+        .as_deref()
+        .ok_or("[storage.redis] url is required by the redis backend")?;
+    ::redis::Client::open(url).map_err(|e| format!("Invalid [storage.redis] url: {}", e))
+}
+
+/// Open the Redis-backed storages and start the task mirroring them.
+///
+/// Must be called inside a Tokio runtime.
+pub(crate) fn open(
+    config: &RedisStorageConfig,
+) -> Result<(Arc<RedisSessionStorage>, Arc<RedisCommandStorage>), String> {
+    let client = client(config)?;
+    let runtime = tokio::runtime::Handle::try_current()
+        .map_err(|_| "The redis backend needs a Tokio runtime".to_string())?;
+    let (writes, pending) = mpsc::unbounded_channel();
+    let registry = Arc::new(Registry {
+        key_prefix: config.key_prefix.clone(),
+        replica: replica_id(config),
+        replica_url: config.advertise_url.clone(),
+        ttl: Duration::from_secs(config.ttl_secs.max(3)),
+        writes,
+    });
+    let sessions = Arc::new(RedisSessionStorage {
+        local: DashMapSessionStorage::new(),
+        registry: registry.clone(),
+    });
+    let commands = Arc::new(RedisCommandStorage {
+        local: DashMapCommandStorage::new(),
+        registry: registry.clone(),
+    });
+    info!(
+        replica = %registry.replica,
+        key_prefix = %registry.key_prefix,
+        "Mirroring sessions to Redis"
+    );
+    runtime.spawn(mirror(
+        client,
+        registry,
+        sessions.clone(),
+        commands.clone(),
+        pending,
+    ));
+    Ok((sessions, commands))
+}
+
+/// Apply writes as they come and refresh both directions every third of the TTL.
+async fn mirror(
+    client: ::redis::Client,
+    registry: Arc<Registry>,
+    sessions: Arc<RedisSessionStorage>,
+    commands: Arc<RedisCommandStorage>,
+    mut pending: mpsc::UnboundedReceiver<Write>,
+) {
+    let mut interval = tokio::time::interval((registry.ttl / 3).max(MIN_REFRESH_INTERVAL));
+    let mut connection: Option<MultiplexedConnection> = None;
+    let mut failing = false;
+
+    loop {
+        let write = tokio::select! {
+            write = pending.recv() => match write {
+                Some(write) => Some(write),
+                None => return,
+            },
+            _ = interval.tick() => None,
+        };
+
+        if connection.is_none() {
+            match client.get_multiplexed_async_connection().await {
+                Ok(conn) => connection = Some(conn),
+                Err(e) => {
+                    if !failing {
+                        warn!(error = %e, "Redis registry unreachable; sessions are not shared");
+                        failing = true;
+                    }
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            }
+        }
+        let Some(conn) = connection.as_mut() else {
+            continue;
+        };
+
+        let result = match write {
+            Some(write) => apply(conn, &registry, write).await,
+            None => refresh(conn, &registry, &sessions, &commands).await,
+        };
+        match result {
+            Ok(()) if failing => {
+                info!("Redis registry reachable again");
+                failing = false;
+            }
+            Ok(()) => {}
+            Err(e) => {
+                if !failing {
+                    warn!(error = %e, "Redis registry write failed; sessions are not shared");
+                    failing = true;
+                }
+                connection = None;
+            }
+        }
+    }
+}
+
+/// Mirror one change.
+async fn apply(
+    conn: &mut MultiplexedConnection,
+    registry: &Registry,
+    write: Write,
+) -> ::redis::RedisResult<()> {
+    let ttl = registry.ttl.as_secs();
+    match write {
+        Write::Session(info) => {
+            let key = registry.session_key(&info.session_id);
+            match registry.entry(*info) {
+                Some(value) => conn.set_ex(key, value, ttl).await,
+                None => Ok(()),
+            }
+        }
+        Write::Command(info) => {
+            let key = registry.command_key(&info.command_id);
+            match registry.entry(info) {
+                Some(value) => conn.set_ex(key, value, ttl).await,
+                None => Ok(()),
+            }
+        }
+        Write::Delete(key) => conn.del(key).await,
+    }
+}
+
+/// Rewrite every local entry with a fresh TTL and read back the other replicas'.
+async fn refresh(
+    conn: &mut MultiplexedConnection,
+    registry: &Registry,
+    sessions: &RedisSessionStorage,
+    commands: &RedisCommandStorage,
+) -> ::redis::RedisResult<()> {
+    for info in sessions.local.list() {
+        apply(conn, registry, Write::Session(Box::new(info))).await?;
+    }
+    for info in commands.local.list_all() {
+        apply(conn, registry, Write::Command(info)).await?;
+    }
+
+    let remote_sessions = read_remote(conn, registry, "session").await?;
+    replace(&REMOTE_SESSIONS, remote_sessions, |info| &info.session_id);
+    let remote_commands = read_remote(conn, registry, "command").await?;
+    replace(&REMOTE_COMMANDS, remote_commands, |info| &info.command_id);
+    Ok(())
+}
+
+/// Read the entries of one kind written by other replicas.
+async fn read_remote<T: DeserializeOwned>(
+    conn: &mut MultiplexedConnection,
+    registry: &Registry,
+    kind: &str,
+) -> ::redis::RedisResult<Vec<ReplicaEntry<T>>> {
+    let pattern = format!("{}:{}:*", registry.key_prefix, kind);
+    let mut entries = Vec::new();
+    let mut cursor: u64 = 0;
+    loop {
+        let (next, keys): (u64, Vec<String>) = ::redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(&pattern)
+            .arg("COUNT")
+            .arg(SCAN_BATCH)
+            .query_async(conn)
+            .await?;
+        if !keys.is_empty() {
+            let values: Vec<Option<String>> = conn.mget(&keys).await?;
+            entries.extend(
+                values
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|value| parse_remote(registry, &value)),
+            );
+        }
+        if next == 0 {
+            return Ok(entries);
+        }
+        cursor = next;
+    }
+}
+
+/// Parse an entry, skipping this replica's own and unreadable ones.
+fn parse_remote<T: DeserializeOwned>(registry: &Registry, value: &str) -> Option<ReplicaEntry<T>> {
+    serde_json::from_str::<ReplicaEntry<T>>(value)
+        .ok()
+        .filter(|entry| entry.replica != registry.replica)
+}
+
+/// Replace the contents of a remote map with a fresh read.
+fn replace<T>(
+    map: &DashMap<String, ReplicaEntry<T>>,
+    entries: Vec<ReplicaEntry<T>>,
+    id: impl Fn(&T) -> &String,
+) {
+    let fresh: DashMap<String, ReplicaEntry<T>> = entries
+        .into_iter()
+        .map(|entry| (id(&entry.info).clone(), entry))
+        .collect();
+    map.retain(|key, _| fresh.contains_key(key));
+    for (key, entry) in fresh {
+        map.insert(key, entry);
+    }
+}
+
+/// Session storage holding handles locally and mirroring metadata to Redis.
+pub struct RedisSessionStorage {
+    local: DashMapSessionStorage,
+    registry: Arc<Registry>,
+}
+
+impl RedisSessionStorage {
+    /// Mirror the current metadata of a local session.
+    fn publish(&self, session_id: &str) {
+        if let Some(session_ref) = self.local.get(session_id) {
+            self.registry
+                .send(Write::Session(Box::new(session_ref.info)));
+        }
+    }
+
+    fn unpublish(&self, session_id: &str) {
+        self.registry
+            .send(Write::Delete(self.registry.session_key(session_id)));
+    }
+}
+
+impl SessionStorage for RedisSessionStorage {
+    fn insert(
+        &self,
+        session_id: String,
+        info: SessionInfo,
+        handle: Arc<client::Handle<SshClientHandler>>,
+        traffic: Arc<ByteCounters>,
+    ) {
+        self.local.insert(session_id.clone(), info, handle, traffic);
+        self.publish(&session_id);
+    }
+
+    fn get(&self, session_id: &str) -> Option<SessionRef> {
+        self.local.get(session_id)
+    }
+
+    fn remove(&self, session_id: &str) -> Option<SessionRef> {
+        let removed = self.local.remove(session_id);
+        if removed.is_some() {
+            self.unpublish(session_id);
+        }
+        removed
+    }
+
+    fn list(&self) -> Vec<SessionInfo> {
+        self.local.list()
+    }
+
+    fn contains(&self, session_id: &str) -> bool {
+        self.local.contains(session_id)
+    }
+
+    fn session_ids(&self) -> Vec<String> {
+        self.local.session_ids()
+    }
+
+    fn update_health(&self, session_id: &str, last_check: String, healthy: bool) {
+        self.local.update_health(session_id, last_check, healthy);
+    }
+
+    fn record_rtt(&self, session_id: &str, rtt: Duration) {
+        self.local.record_rtt(session_id, rtt);
+    }
+
+    fn set_rebooting(&self, session_id: &str, rebooting: bool) {
+        self.local.set_rebooting(session_id, rebooting);
+        self.publish(session_id);
+    }
+
+    fn record_activity(&self, session_id: &str, activity: SessionActivity) {
+        self.local.record_activity(session_id, activity);
+    }
+
+    fn set_labels(&self, session_id: &str, labels: BTreeMap<String, String>) {
+        self.local.set_labels(session_id, labels);
+        self.publish(session_id);
+    }
+
+    fn register_agent(&self, agent_id: &str, session_id: &str) {
+        self.local.register_agent(agent_id, session_id);
+        self.publish(session_id);
+    }
+
+    fn unregister_agent(&self, agent_id: &str, session_id: &str) {
+        self.local.unregister_agent(agent_id, session_id);
+        self.publish(session_id);
+    }
+
+    fn get_agent_sessions(&self, agent_id: &str) -> Vec<String> {
+        self.local.get_agent_sessions(agent_id)
+    }
+
+    fn remove_agent_sessions(&self, agent_id: &str) -> Vec<String> {
+        self.local.remove_agent_sessions(agent_id)
+    }
+}
+
+/// Command storage holding running commands locally and mirroring metadata to Redis.
+pub struct RedisCommandStorage {
+    local: DashMapCommandStorage,
+    registry: Arc<Registry>,
+}
+
+impl CommandStorage for RedisCommandStorage {
+    fn register(&self, command_id: String, command: RunningCommand) {
+        let info = command.info.clone();
+        self.local.register(command_id, command);
+        self.registry.send(Write::Command(info));
+    }
+
+    fn unregister(&self, command_id: &str) -> Option<RunningCommand> {
+        let removed = self.local.unregister(command_id);
+        if removed.is_some() {
+            self.registry
+                .send(Write::Delete(self.registry.command_key(command_id)));
+        }
+        removed
+    }
+
+    fn get(&self, command_id: &str) -> Option<Arc<RunningCommand>> {
+        self.local.get(command_id)
+    }
+
+    fn get_ref(&self, command_id: &str) -> Option<CommandRef> {
+        self.local.get_ref(command_id)
+    }
+
+    fn list_by_session(&self, session_id: &str) -> Vec<String> {
+        self.local.list_by_session(session_id)
+    }
+
+    fn count_by_session(&self, session_id: &str) -> usize {
+        self.local.count_by_session(session_id)
+    }
+
+    fn list_all(&self) -> Vec<AsyncCommandInfo> {
+        self.local.list_all()
+    }
+
+    fn list_filtered(
+        &self,
+        session_id: Option<&str>,
+        status: Option<AsyncCommandStatus>,
+        label: Option<&str>,
+    ) -> Vec<AsyncCommandInfo> {
+        self.local.list_filtered(session_id, status, label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(replica: &str) -> (Registry, mpsc::UnboundedReceiver<Write>) {
+        let (writes, pending) = mpsc::unbounded_channel();
+        let registry = Registry {
+            key_prefix: "ssh-mcp".to_string(),
+            replica: replica.to_string(),
+            replica_url: Some(format!("http://{}:8000", replica)),
+            ttl: Duration::from_secs(30),
+            writes,
+        };
+        (registry, pending)
+    }
+
+    fn command_info(command_id: &str) -> AsyncCommandInfo {
+        AsyncCommandInfo {
+            command_id: command_id.to_string(),
+            session_id: "s1".to_string(),
+            command: "sleep 60".to_string(),
+            status: AsyncCommandStatus::Running,
+            started_at: "2024-01-15T10:30:00Z".to_string(),
+            label: None,
+        }
+    }
+
+    #[test]
+    fn test_entries_round_trip_and_skip_own_replica() {
+        let (mcp1, _) = registry("mcp-1");
+        let (mcp2, _) = registry("mcp-2");
+        assert_eq!(mcp1.command_key("c1"), "ssh-mcp:command:c1");
+        assert_eq!(mcp1.session_key("s1"), "ssh-mcp:session:s1");
+
+        let value = mcp1.entry(command_info("c1")).unwrap();
+        assert!(parse_remote::<AsyncCommandInfo>(&mcp1, &value).is_none());
+        let entry = parse_remote::<AsyncCommandInfo>(&mcp2, &value).unwrap();
+        assert_eq!(entry.replica, "mcp-1");
+        assert_eq!(entry.replica_url.as_deref(), Some("http://mcp-1:8000"));
+        assert_eq!(entry.info.command_id, "c1");
+        assert!(parse_remote::<AsyncCommandInfo>(&mcp2, "not json").is_none());
+    }
+
+    #[test]
+    fn test_replace_drops_entries_gone_from_redis() {
+        let map = DashMap::new();
+        let entry = |id: &str| ReplicaEntry {
+            replica: "mcp-2".to_string(),
+            replica_url: None,
+            info: command_info(id),
+        };
+        replace(&map, vec![entry("a"), entry("b")], |info| &info.command_id);
+        replace(&map, vec![entry("b"), entry("c")], |info| &info.command_id);
+        let mut ids: Vec<String> = map.iter().map(|entry| entry.key().clone()).collect();
+        ids.sort();
+        assert_eq!(ids, ["b", "c"]);
+    }
+
+    #[test]
+    fn test_unknown_ids_write_nothing() {
+        let (registry, mut pending) = registry("mcp-1");
+        let registry = Arc::new(registry);
+        let sessions = RedisSessionStorage {
+            local: DashMapSessionStorage::new(),
+            registry: registry.clone(),
+        };
+        let commands = RedisCommandStorage {
+            local: DashMapCommandStorage::new(),
+            registry,
+        };
+        assert!(sessions.remove("missing").is_none());
+        sessions.set_rebooting("missing", true);
+        assert!(commands.unregister("missing").is_none());
+        assert!(pending.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_command_owner_lookup() {
+        let command_id = format!("remote-{}", uuid::Uuid::new_v4());
+        let entry = ReplicaEntry {
+            replica: "mcp-2".to_string(),
+            replica_url: None,
+            info: command_info(&command_id),
+        };
+        REMOTE_COMMANDS.insert(command_id.clone(), entry);
+        assert_eq!(command_owner(&command_id).unwrap().replica, "mcp-2");
+        REMOTE_COMMANDS.remove(&command_id);
+        assert!(command_owner(&command_id).is_none());
+    }
+}
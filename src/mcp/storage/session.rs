@@ -3,15 +3,16 @@
 //! Provides lock-free concurrent access to SSH sessions using `DashMap`.
 //! Includes a secondary index for O(1) agent-to-sessions lookups.
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
 use dashmap::DashMap;
-use once_cell::sync::Lazy;
 use russh::client;
 
 use crate::mcp::session::SshClientHandler;
-use crate::mcp::types::SessionInfo;
+use crate::mcp::traffic::ByteCounters;
+use crate::mcp::types::{SessionActivity, SessionInfo};
 
 use super::traits::{SessionRef, SessionStorage};
 
@@ -19,6 +20,19 @@ use super::traits::{SessionRef, SessionStorage};
 pub struct StoredSession {
     pub info: SessionInfo,
     pub handle: Arc<client::Handle<SshClientHandler>>,
+    pub traffic: Arc<ByteCounters>,
+}
+
+impl StoredSession {
+    /// Session info with the current byte counts and last activity.
+    fn current_info(&self) -> SessionInfo {
+        let mut info = self.info.clone();
+        info.bytes_sent = self.traffic.sent();
+        info.bytes_received = self.traffic.received();
+        info.algorithms = self.traffic.algorithms();
+        info.stats.last_activity = self.traffic.last_activity();
+        info
+    }
 }
 
 /// DashMap-based implementation of `SessionStorage`.
@@ -53,15 +67,23 @@ impl SessionStorage for DashMapSessionStorage {
         session_id: String,
         info: SessionInfo,
         handle: Arc<client::Handle<SshClientHandler>>,
+        traffic: Arc<ByteCounters>,
     ) {
-        self.sessions
-            .insert(session_id, StoredSession { info, handle });
+        self.sessions.insert(
+            session_id,
+            StoredSession {
+                info,
+                handle,
+                traffic,
+            },
+        );
     }
 
     fn get(&self, session_id: &str) -> Option<SessionRef> {
         self.sessions.get(session_id).map(|entry| SessionRef {
-            info: entry.info.clone(),
+            info: entry.current_info(),
             handle: entry.handle.clone(),
+            traffic: entry.traffic.clone(),
         })
     }
 
@@ -69,15 +91,16 @@ impl SessionStorage for DashMapSessionStorage {
         self.sessions
             .remove(session_id)
             .map(|(_, stored)| SessionRef {
-                info: stored.info,
+                info: stored.current_info(),
                 handle: stored.handle,
+                traffic: stored.traffic,
             })
     }
 
     fn list(&self) -> Vec<SessionInfo> {
         self.sessions
             .iter()
-            .map(|entry| entry.info.clone())
+            .map(|entry| entry.current_info())
             .collect()
     }
 
@@ -91,8 +114,32 @@ impl SessionStorage for DashMapSessionStorage {
 
     fn update_health(&self, session_id: &str, last_check: String, healthy: bool) {
         if let Some(mut stored) = self.sessions.get_mut(session_id) {
-            stored.info.last_health_check = Some(last_check);
-            stored.info.healthy = Some(healthy);
+            stored.info.record_health(last_check, healthy);
+        }
+    }
+
+    fn record_rtt(&self, session_id: &str, rtt: Duration) {
+        if let Some(mut stored) = self.sessions.get_mut(session_id) {
+            stored.info.record_rtt(rtt);
+        }
+    }
+
+    fn set_rebooting(&self, session_id: &str, rebooting: bool) {
+        if let Some(mut stored) = self.sessions.get_mut(session_id) {
+            stored.info.rebooting = rebooting;
+        }
+    }
+
+    fn record_activity(&self, session_id: &str, activity: SessionActivity) {
+        if let Some(mut stored) = self.sessions.get_mut(session_id) {
+            stored.info.record_activity(activity);
+            stored.traffic.touch();
+        }
+    }
+
+    fn set_labels(&self, session_id: &str, labels: BTreeMap<String, String>) {
+        if let Some(mut stored) = self.sessions.get_mut(session_id) {
+            stored.info.labels = labels;
         }
     }
 
@@ -128,9 +175,6 @@ impl SessionStorage for DashMapSessionStorage {
     }
 }
 
-/// Global session storage instance.
-pub static SESSION_STORAGE: Lazy<DashMapSessionStorage> = Lazy::new(DashMapSessionStorage::new);
-
 #[cfg(test)]
 mod tests {
     use super::*;
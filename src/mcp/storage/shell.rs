@@ -41,6 +41,9 @@ pub trait ShellStorage: Send + Sync {
 
     /// List shell info filtered by session.
     fn list_filtered(&self, session_id: Option<&str>) -> Vec<ShellInfo>;
+
+    /// Record the user a shell runs as; returns false if the shell is gone.
+    fn set_effective_user(&self, shell_id: &str, user: Option<String>) -> bool;
 }
 
 /// DashMap-based implementation of `ShellStorage`.
@@ -137,6 +140,16 @@ impl ShellStorage for DashMapShellStorage {
             .map(|entry| entry.info.clone())
             .collect()
     }
+
+    fn set_effective_user(&self, shell_id: &str, user: Option<String>) -> bool {
+        match self.shells.get_mut(shell_id) {
+            Some(mut shell) => {
+                shell.info.effective_user = user;
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 /// Global shell storage instance.
@@ -205,4 +218,11 @@ mod tests {
         let unique_id = format!("nonexistent-{}", uuid::Uuid::new_v4());
         assert!(storage.get_direct(&unique_id).is_none());
     }
+
+    #[test]
+    fn test_set_effective_user_nonexistent() {
+        let storage = DashMapShellStorage::new();
+        let unique_id = format!("nonexistent-{}", uuid::Uuid::new_v4());
+        assert!(!storage.set_effective_user(&unique_id, Some("app".to_string())));
+    }
 }
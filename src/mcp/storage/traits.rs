@@ -3,18 +3,23 @@
 //! These traits define the interface for storage implementations, enabling
 //! dependency injection and testability through mocking.
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use russh::client;
 
 use crate::mcp::async_command::RunningCommand;
 use crate::mcp::session::SshClientHandler;
-use crate::mcp::types::{AsyncCommandInfo, AsyncCommandStatus, SessionInfo};
+use crate::mcp::traffic::ByteCounters;
+use crate::mcp::types::{AsyncCommandInfo, AsyncCommandStatus, SessionActivity, SessionInfo};
 
 /// Reference to a stored session for read-only access.
+#[derive(Clone)]
 pub struct SessionRef {
     pub info: SessionInfo,
     pub handle: Arc<client::Handle<SshClientHandler>>,
+    pub traffic: Arc<ByteCounters>,
 }
 
 /// Trait for session storage operations.
@@ -30,6 +35,7 @@ pub trait SessionStorage: Send + Sync {
         session_id: String,
         info: SessionInfo,
         handle: Arc<client::Handle<SshClientHandler>>,
+        traffic: Arc<ByteCounters>,
     );
 
     /// Get a session by ID, returning a clone of its data.
@@ -50,6 +56,18 @@ pub trait SessionStorage: Send + Sync {
     /// Update session health status.
     fn update_health(&self, session_id: &str, last_check: String, healthy: bool);
 
+    /// Record a round-trip time sample for a session.
+    fn record_rtt(&self, session_id: &str, rtt: Duration);
+
+    /// Mark a session as rebooting (or not) while its host restarts.
+    fn set_rebooting(&self, session_id: &str, rebooting: bool);
+
+    /// Count tool activity (commands, shells, output, forwards) of a session.
+    fn record_activity(&self, session_id: &str, activity: SessionActivity);
+
+    /// Replace the labels of a session.
+    fn set_labels(&self, session_id: &str, labels: BTreeMap<String, String>);
+
     /// Register a session under an agent ID.
     fn register_agent(&self, agent_id: &str, session_id: &str);
 
@@ -98,10 +116,11 @@ pub trait CommandStorage: Send + Sync {
     /// List all commands.
     fn list_all(&self) -> Vec<AsyncCommandInfo>;
 
-    /// List commands filtered by optional session ID and/or status.
+    /// List commands filtered by optional session ID, status and label substring.
     fn list_filtered(
         &self,
         session_id: Option<&str>,
         status: Option<AsyncCommandStatus>,
+        label: Option<&str>,
     ) -> Vec<AsyncCommandInfo>;
 }
@@ -0,0 +1,295 @@
+//! Live shell output subscriptions (`ssh_shell_subscribe`).
+//!
+//! Every shell reader broadcasts its output chunks. A subscription forwards
+//! them to the subscribing client as MCP logging notifications until
+//! `ssh_shell_unsubscribe`, the shell closes or the client goes away:
+//!
+//! ```json
+//! {"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","logger":"ssh_shell",
+//!  "data":{"subscription_id":"...","shell_id":"...","seq":3,"output":"Reading package lists...\r\n"}}}
+//! ```
+//!
+//! The last notification of a subscription carries `"closed": true` and a
+//! `reason` (`unsubscribed` or `shell_closed`). Chunks that are ready together
+//! are sent as one notification, and a multi-byte character split across
+//! chunks is held back until it is complete. When the subscriber falls more
+//! than [`SHELL_OUTPUT_BROADCAST`] chunks behind, the next notification
+//! reports how many were skipped in `skipped_chunks`.
+//!
+//! Output also stays in the shell buffer, so `ssh_shell_read` works alongside
+//! a subscription. Output produced before subscribing is not replayed.
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::{Value, json};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::{broadcast, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::notify::{NotificationSink, NotifyError};
+use super::types::ShellStatus;
+
+/// Output chunks buffered per shell for slow subscribers
+pub(crate) const SHELL_OUTPUT_BROADCAST: usize = 256;
+
+/// Subscriptions allowed per shell
+pub(crate) const MAX_SUBSCRIPTIONS_PER_SHELL: usize = 4;
+
+/// Largest `output` of one notification; more ready output goes in the next one
+const MAX_NOTIFICATION_BYTES: usize = 64 * 1024;
+
+/// Notification method used for shell output
+pub(crate) const SHELL_OUTPUT_METHOD: &str = "notifications/message";
+
+/// `logger` of shell output notifications
+pub(crate) const SHELL_OUTPUT_LOGGER: &str = "ssh_shell";
+
+struct Subscription {
+    shell_id: String,
+    cancel: CancellationToken,
+}
+
+/// Active subscriptions by subscription_id.
+static SUBSCRIPTIONS: Lazy<DashMap<String, Subscription>> = Lazy::new(DashMap::new);
+
+/// Number of active subscriptions on `shell_id`.
+pub(crate) fn count_for_shell(shell_id: &str) -> usize {
+    SUBSCRIPTIONS
+        .iter()
+        .filter(|entry| entry.shell_id == shell_id)
+        .count()
+}
+
+/// Start forwarding `output` of `shell_id` to `sink`; returns the subscription_id.
+pub(crate) fn subscribe(
+    shell_id: &str,
+    output: broadcast::Receiver<Vec<u8>>,
+    status: watch::Receiver<ShellStatus>,
+    sink: NotificationSink,
+) -> String {
+    let subscription_id = Uuid::new_v4().to_string();
+    let cancel = CancellationToken::new();
+    SUBSCRIPTIONS.insert(
+        subscription_id.clone(),
+        Subscription {
+            shell_id: shell_id.to_string(),
+            cancel: cancel.clone(),
+        },
+    );
+    tokio::spawn(forward(
+        subscription_id.clone(),
+        shell_id.to_string(),
+        output,
+        status,
+        sink,
+        cancel,
+    ));
+    subscription_id
+}
+
+/// Stop a subscription; returns its shell_id, or `None` when it is not active.
+pub(crate) fn unsubscribe(subscription_id: &str) -> Option<String> {
+    let (_, subscription) = SUBSCRIPTIONS.remove(subscription_id)?;
+    subscription.cancel.cancel();
+    Some(subscription.shell_id)
+}
+
+/// Take the longest valid UTF-8 prefix of `pending` as text.
+///
+/// An incomplete character at the end stays in `pending` for the next chunk;
+/// invalid bytes elsewhere are replaced with U+FFFD.
+pub(crate) fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(pending) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => pending.len(),
+    };
+    let rest = pending.split_off(complete);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    text
+}
+
+fn notification(subscription_id: &str, shell_id: &str, data: Value) -> Value {
+    let mut data = data;
+    data["subscription_id"] = json!(subscription_id);
+    data["shell_id"] = json!(shell_id);
+    json!({"level": "info", "logger": SHELL_OUTPUT_LOGGER, "data": data})
+}
+
+/// Forward output chunks until cancelled, the shell closes or the client is gone.
+async fn forward(
+    subscription_id: String,
+    shell_id: String,
+    mut output: broadcast::Receiver<Vec<u8>>,
+    mut status: watch::Receiver<ShellStatus>,
+    sink: NotificationSink,
+    cancel: CancellationToken,
+) {
+    let mut pending = Vec::new();
+    let mut skipped: u64 = 0;
+    let mut seq: u64 = 0;
+
+    let reason = loop {
+        // Ready output goes out before a close is noticed
+        let received = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break "unsubscribed",
+            received = output.recv() => received,
+            _ = status.wait_for(|status| *status == ShellStatus::Closed) => break "shell_closed",
+        };
+        match received {
+            Ok(chunk) => pending.extend_from_slice(&chunk),
+            Err(RecvError::Lagged(count)) => skipped += count,
+            Err(RecvError::Closed) => break "shell_closed",
+        }
+        while pending.len() < MAX_NOTIFICATION_BYTES {
+            match output.try_recv() {
+                Ok(chunk) => pending.extend_from_slice(&chunk),
+                Err(TryRecvError::Lagged(count)) => skipped += count,
+                Err(_) => break,
+            }
+        }
+
+        let text = take_utf8(&mut pending);
+        if text.is_empty() && skipped == 0 {
+            continue;
+        }
+        seq += 1;
+        let mut data = json!({"seq": seq, "output": text});
+        if skipped > 0 {
+            data["skipped_chunks"] = json!(skipped);
+            skipped = 0;
+        }
+        match sink.notify(
+            SHELL_OUTPUT_METHOD,
+            notification(&subscription_id, &shell_id, data),
+        ) {
+            Ok(()) => {}
+            Err(NotifyError::Full) => {
+                warn!(
+                    "Client is not reading notifications; dropped shell output for subscription {}",
+                    subscription_id
+                );
+            }
+            Err(NotifyError::Closed) => {
+                debug!("Subscriber of shell {} is gone", shell_id);
+                SUBSCRIPTIONS.remove(&subscription_id);
+                return;
+            }
+        }
+    };
+
+    let data = json!({"seq": seq + 1, "closed": true, "reason": reason});
+    let _ = sink.notify(
+        SHELL_OUTPUT_METHOD,
+        notification(&subscription_id, &shell_id, data),
+    );
+    SUBSCRIPTIONS.remove(&subscription_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    mod utf8 {
+        use super::*;
+
+        #[test]
+        fn test_holds_back_split_character() {
+            // "é" is 0xC3 0xA9
+            let mut pending = b"caf\xC3".to_vec();
+            assert_eq!(take_utf8(&mut pending), "caf");
+            assert_eq!(pending, vec![0xC3]);
+            pending.extend_from_slice(b"\xA9!");
+            assert_eq!(take_utf8(&mut pending), "é!");
+            assert!(pending.is_empty());
+        }
+
+        #[test]
+        fn test_replaces_invalid_bytes() {
+            let mut pending = b"a\xFFb".to_vec();
+            assert_eq!(take_utf8(&mut pending), "a\u{FFFD}b");
+            assert!(pending.is_empty());
+        }
+    }
+
+    mod forwarding {
+        use super::*;
+        use tokio::sync::mpsc;
+
+        async fn next(rx: &mut mpsc::Receiver<Value>) -> Value {
+            tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap()
+        }
+
+        #[tokio::test]
+        async fn test_streams_until_unsubscribed() {
+            let (output_tx, output_rx) = broadcast::channel(SHELL_OUTPUT_BROADCAST);
+            let (_status_tx, status_rx) = watch::channel(ShellStatus::Open);
+            let (sink, mut rx) = NotificationSink::channel();
+
+            let id = subscribe("sh-1", output_rx, status_rx, sink);
+            assert_eq!(count_for_shell("sh-1"), 1);
+
+            output_tx.send(b"hello ".to_vec()).unwrap();
+            let first = next(&mut rx).await;
+            assert_eq!(first["method"], SHELL_OUTPUT_METHOD);
+            let data = &first["params"]["data"];
+            assert_eq!(first["params"]["logger"], SHELL_OUTPUT_LOGGER);
+            assert_eq!(data["subscription_id"], id.as_str());
+            assert_eq!(data["shell_id"], "sh-1");
+            assert_eq!(data["seq"], 1);
+            assert_eq!(data["output"], "hello ");
+
+            assert_eq!(unsubscribe(&id).as_deref(), Some("sh-1"));
+            let last = next(&mut rx).await;
+            assert_eq!(last["params"]["data"]["closed"], true);
+            assert_eq!(last["params"]["data"]["reason"], "unsubscribed");
+            assert_eq!(unsubscribe(&id), None);
+        }
+
+        #[tokio::test]
+        async fn test_ends_when_shell_closes() {
+            let (output_tx, output_rx) = broadcast::channel(SHELL_OUTPUT_BROADCAST);
+            let (status_tx, status_rx) = watch::channel(ShellStatus::Open);
+            let (sink, mut rx) = NotificationSink::channel();
+            let id = subscribe("sh-2", output_rx, status_rx, sink);
+
+            output_tx.send(b"logout\r\n".to_vec()).unwrap();
+            status_tx.send(ShellStatus::Closed).unwrap();
+
+            // Output sent before the close is still delivered
+            assert_eq!(
+                next(&mut rx).await["params"]["data"]["output"],
+                "logout\r\n"
+            );
+            let last = next(&mut rx).await;
+            assert_eq!(last["params"]["data"]["reason"], "shell_closed");
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(unsubscribe(&id), None);
+        }
+
+        #[tokio::test]
+        async fn test_reports_skipped_chunks() {
+            let (output_tx, output_rx) = broadcast::channel(2);
+            let (_status_tx, status_rx) = watch::channel(ShellStatus::Open);
+            let (sink, mut rx) = NotificationSink::channel();
+            // Fill past capacity before the forwarder runs
+            for chunk in ["a", "b", "c", "d"] {
+                output_tx.send(chunk.as_bytes().to_vec()).unwrap();
+            }
+            let id = subscribe("sh-3", output_rx, status_rx, sink);
+
+            let data = next(&mut rx).await["params"]["data"].clone();
+            assert_eq!(data["skipped_chunks"], 2);
+            assert_eq!(data["output"], "cd");
+            unsubscribe(&id);
+        }
+    }
+}
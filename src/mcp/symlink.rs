@@ -0,0 +1,138 @@
+//! Remote symbolic link operations.
+//!
+//! `ssh_symlink` and `ssh_readlink` run POSIX `ln`/`readlink` through an exec
+//! channel, so they work on any host with a shell and need no SFTP subsystem.
+//!
+//! Reading a link either **preserves** it (returns the stored target, which may
+//! be relative or dangling) or **follows** it (returns the canonical path at the
+//! end of the chain), so trees with symlinked config files resolve predictably.
+
+use super::command_wrap::shell_quote;
+use super::types::SshCommandResponse;
+
+/// Command creating `link_path` pointing at `target`.
+///
+/// With `force`, an existing file or link at `link_path` is replaced; `-n`
+/// keeps `ln` from descending into an existing link to a directory.
+pub(crate) fn symlink_command(target: &str, link_path: &str, force: bool) -> String {
+    format!(
+        "ln -s{} -- {} {}",
+        if force { "fn" } else { "" },
+        shell_quote(target),
+        shell_quote(link_path)
+    )
+}
+
+/// Command printing the target of the link at `path`.
+///
+/// Without `follow`, `path` must be a symbolic link and its stored target is
+/// printed as-is. With `follow`, every link in the chain is resolved and the
+/// canonical path is printed (regular files resolve to themselves).
+pub(crate) fn readlink_command(path: &str, follow: bool) -> String {
+    if follow {
+        return format!("readlink -f -- {}", shell_quote(path));
+    }
+    format!(
+        "p={}; if [ -L \"$p\" ]; then readlink -- \"$p\"; \
+         elif [ -e \"$p\" ]; then echo \"$p: not a symbolic link\" >&2; exit 1; \
+         else echo \"$p: No such file or directory\" >&2; exit 1; fi",
+        shell_quote(path)
+    )
+}
+
+/// Extract the link target from `readlink` output (`None` when empty).
+pub(crate) fn parse_link_target(stdout: &str) -> Option<String> {
+    let target = stdout.strip_suffix('\n').unwrap_or(stdout);
+    let target = target.strip_suffix('\r').unwrap_or(target);
+    (!target.is_empty()).then(|| target.to_string())
+}
+
+/// Describe a failed remote link operation from its stderr and exit code.
+pub(crate) fn failure_message(response: &SshCommandResponse) -> String {
+    if response.timed_out {
+        return "Remote command timed out".to_string();
+    }
+    response
+        .stderr
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Remote command exited with code {}", response.exit_code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod commands {
+        use super::*;
+
+        #[test]
+        fn test_symlink_command() {
+            assert_eq!(
+                symlink_command("../shared/app.conf", "/etc/app.conf", false),
+                "ln -s -- '../shared/app.conf' '/etc/app.conf'"
+            );
+        }
+
+        #[test]
+        fn test_symlink_force_replaces_without_descending() {
+            assert!(symlink_command("/opt/v2", "/opt/current", true).starts_with("ln -sfn -- "));
+        }
+
+        #[test]
+        fn test_readlink_preserve_requires_link() {
+            let command = readlink_command("/etc/app.conf", false);
+            assert!(command.starts_with("p='/etc/app.conf'; if [ -L \"$p\" ]"));
+            assert!(command.contains("not a symbolic link"));
+        }
+
+        #[test]
+        fn test_readlink_follow() {
+            assert_eq!(readlink_command("it's", true), r"readlink -f -- 'it'\''s'");
+        }
+    }
+
+    mod parsing {
+        use super::*;
+
+        fn response(stderr: &str, exit_code: i32) -> SshCommandResponse {
+            SshCommandResponse {
+                stdout: String::new(),
+                stderr: stderr.to_string(),
+                exit_code,
+                timed_out: false,
+            }
+        }
+
+        #[test]
+        fn test_target_keeps_inner_whitespace() {
+            assert_eq!(
+                parse_link_target("../my conf \n"),
+                Some("../my conf ".to_string())
+            );
+            assert_eq!(parse_link_target(""), None);
+        }
+
+        #[test]
+        fn test_failure_uses_first_stderr_line() {
+            let message = failure_message(&response(
+                "\nln: failed to create symbolic link '/etc/x': Permission denied\n",
+                1,
+            ));
+            assert_eq!(
+                message,
+                "ln: failed to create symbolic link '/etc/x': Permission denied"
+            );
+        }
+
+        #[test]
+        fn test_failure_without_stderr() {
+            assert_eq!(
+                failure_message(&response("", 2)),
+                "Remote command exited with code 2"
+            );
+        }
+    }
+}
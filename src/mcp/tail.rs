@@ -0,0 +1,187 @@
+//! File following (`ssh_tail_file`, `ssh_tail_read`).
+//!
+//! `ssh_tail_file` starts `tail -F` as an ordinary async command, so it shows
+//! up in `ssh_list_commands` and stops with `ssh_cancel_command`. A `pattern`
+//! pipes the output through `grep --line-buffered -E` on the remote host, so
+//! only matching lines cross the connection and reach the agent.
+//!
+//! `ssh_tail_read` returns complete lines after a byte `cursor` and the cursor
+//! to pass next time, so repeated reads see every line once without the
+//! whole buffer being resent. A line still being written is held back until
+//! its newline arrives (or the tail ends). Output is kept in a
+//! [`TAIL_BUFFER_BYTES`] ring; a reader that falls further behind is told how
+//! many bytes it missed in `skipped_bytes` and resumes at the next full line.
+
+use std::time::Duration;
+
+use super::async_command::{OutputLimit, OutputOverflow};
+use super::command_wrap::shell_quote;
+
+/// Lines of existing content shown before following
+pub(crate) const DEFAULT_TAIL_LINES: u32 = 10;
+
+/// Largest accepted `lines`
+pub(crate) const MAX_TAIL_LINES: u32 = 10_000;
+
+/// How long a tail runs when `timeout_secs` is not given
+pub(crate) const DEFAULT_TAIL_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Lines returned by one ssh_tail_read when `max_lines` is not given
+pub(crate) const DEFAULT_TAIL_READ_LINES: usize = 200;
+
+/// Largest accepted `max_lines`
+pub(crate) const MAX_TAIL_READ_LINES: usize = 5000;
+
+/// Largest accepted `wait_secs` of ssh_tail_read
+pub(crate) const MAX_TAIL_WAIT_SECS: u64 = 60;
+
+/// How often ssh_tail_read checks for new lines while waiting
+pub(crate) const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Output kept per tail; older lines are evicted
+pub(crate) const TAIL_BUFFER_BYTES: usize = 4 * 1024 * 1024;
+
+/// Ring buffer of a tail's output.
+pub(crate) const TAIL_OUTPUT_LIMIT: OutputLimit = OutputLimit {
+    max_bytes: TAIL_BUFFER_BYTES,
+    overflow: OutputOverflow::Ring,
+};
+
+/// Build the remote command following `path`, starting with its last `lines`
+/// lines and keeping only lines matching the extended regex `pattern`.
+pub(crate) fn tail_command(
+    path: &str,
+    lines: Option<u32>,
+    pattern: Option<&str>,
+    ignore_case: bool,
+) -> Result<String, String> {
+    if path.trim().is_empty() {
+        return Err("path must not be empty".to_string());
+    }
+    let lines = lines.unwrap_or(DEFAULT_TAIL_LINES);
+    if lines > MAX_TAIL_LINES {
+        return Err(format!(
+            "lines must be at most {} (got {})",
+            MAX_TAIL_LINES, lines
+        ));
+    }
+    let tail = format!("tail -n {} -F -- {}", lines, shell_quote(path));
+    Ok(match pattern.filter(|pattern| !pattern.is_empty()) {
+        Some(pattern) => format!(
+            "{} | grep --line-buffered{} -E -e {}",
+            tail,
+            if ignore_case { " -i" } else { "" },
+            shell_quote(pattern)
+        ),
+        None => tail,
+    })
+}
+
+/// Lines read from a tail's output.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct TailChunk {
+    pub lines: Vec<String>,
+    /// Stream position to continue from
+    pub cursor: u64,
+    /// Bytes evicted from the buffer before the reader got to them
+    pub skipped_bytes: u64,
+    /// Whether complete lines beyond `max_lines` are waiting
+    pub more: bool,
+}
+
+/// Read up to `max_lines` complete lines of `stdout` after stream position
+/// `cursor`. `stream_start` is the stream position of `stdout[0]`; once the
+/// tail has `finished`, a last line without newline is returned too.
+pub(crate) fn read_lines(
+    stdout: &[u8],
+    stream_start: u64,
+    cursor: u64,
+    max_lines: usize,
+    finished: bool,
+) -> TailChunk {
+    let end = stream_start + stdout.len() as u64;
+    let cursor = cursor.min(end);
+    let mut skipped_bytes = stream_start.saturating_sub(cursor);
+    let mut rest = &stdout[(cursor.max(stream_start) - stream_start) as usize..];
+    if skipped_bytes > 0 {
+        // Eviction may have cut a line; resume at the next full one
+        let partial = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(rest.len(), |newline| newline + 1);
+        skipped_bytes += partial as u64;
+        rest = &rest[partial..];
+    }
+
+    let mut lines = Vec::new();
+    while lines.len() < max_lines {
+        let (line, next) = match rest.iter().position(|&byte| byte == b'\n') {
+            Some(newline) => (&rest[..newline], &rest[newline + 1..]),
+            None if finished && !rest.is_empty() => (rest, &rest[rest.len()..]),
+            None => break,
+        };
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        lines.push(String::from_utf8_lossy(line).into_owned());
+        rest = next;
+    }
+
+    TailChunk {
+        lines,
+        cursor: end - rest.len() as u64,
+        skipped_bytes,
+        more: rest.contains(&b'\n') || (finished && !rest.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_command() {
+        assert_eq!(
+            tail_command("/var/log/syslog", None, None, false).unwrap(),
+            "tail -n 10 -F -- '/var/log/syslog'"
+        );
+        assert_eq!(
+            tail_command("/var/log/app's.log", Some(0), Some("ERROR|WARN"), true).unwrap(),
+            "tail -n 0 -F -- '/var/log/app'\\''s.log' | grep --line-buffered -i -E -e 'ERROR|WARN'"
+        );
+        assert!(tail_command(" ", None, None, false).is_err());
+        assert!(tail_command("/x", Some(MAX_TAIL_LINES + 1), None, false).is_err());
+    }
+
+    #[test]
+    fn test_read_complete_lines_only() {
+        let output = b"one\ntwo\r\nthr";
+        let chunk = read_lines(output, 0, 0, 10, false);
+        assert_eq!(chunk.lines, vec!["one", "two"]);
+        assert_eq!(chunk.cursor, 9);
+        assert!(!chunk.more);
+
+        // Nothing new until the line is finished
+        assert_eq!(
+            read_lines(output, 0, 9, 10, false).lines,
+            Vec::<String>::new()
+        );
+        let chunk = read_lines(output, 0, 9, 10, true);
+        assert_eq!(chunk.lines, vec!["thr"]);
+        assert_eq!(chunk.cursor, output.len() as u64);
+
+        let chunk = read_lines(output, 0, 0, 1, false);
+        assert_eq!(chunk.lines, vec!["one"]);
+        assert!(chunk.more);
+    }
+
+    #[test]
+    fn test_read_after_eviction() {
+        // Stream positions 0..100 were evicted; the buffer resumes mid-line
+        let chunk = read_lines(b"ial\nnext\n", 100, 40, 10, false);
+        assert_eq!(chunk.lines, vec!["next"]);
+        assert_eq!(chunk.skipped_bytes, 64);
+        assert_eq!(chunk.cursor, 109);
+
+        // A cursor past the end waits at the end
+        assert_eq!(read_lines(b"a\n", 0, 50, 10, false).cursor, 2);
+    }
+}
@@ -0,0 +1,159 @@
+//! Loopback SSH server for tests.
+//!
+//! Accepts any password, opens session channels, dials `direct-tcpip`
+//! destinations and serves `tcpip-forward` listeners, which is enough to
+//! connect real sessions (also through jump hosts) without a remote host.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use russh::keys::ssh_key::rand_core::OsRng;
+use russh::keys::{Algorithm, PrivateKey};
+use russh::server::{self, Auth, Msg, Server as _, Session};
+use russh::{Channel, ChannelStream, client};
+use tokio::io::copy_bidirectional;
+use tokio::net::{TcpListener, TcpStream};
+
+use super::algorithms::AlgorithmPreferences;
+use super::client::connect_to_ssh_with_retry;
+use super::config::Keepalive;
+use super::known_hosts::HostKeyChecking;
+use super::reverse::ReverseForwards;
+use super::session::SshClientHandler;
+use super::traffic::ByteCounters;
+use super::types::JumpHost;
+
+/// Password every [`TestServer`] accepts (any other works too).
+pub(crate) const TEST_PASSWORD: &str = "secret";
+
+/// Server state shared by all connections of one listener.
+#[derive(Clone, Default)]
+pub(crate) struct TestServer {
+    /// `host:port` of every `direct-tcpip` channel opened on it
+    pub dialled: Arc<Mutex<Vec<String>>>,
+}
+
+async fn bridge(mut channel: ChannelStream<Msg>, mut stream: TcpStream) {
+    let _ = copy_bidirectional(&mut channel, &mut stream).await;
+}
+
+impl server::Server for TestServer {
+    type Handler = Self;
+
+    fn new_client(&mut self, _peer: Option<std::net::SocketAddr>) -> Self {
+        self.clone()
+    }
+}
+
+impl server::Handler for TestServer {
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, _user: &str, _password: &str) -> Result<Auth, Self::Error> {
+        Ok(Auth::Accept)
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        _channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+
+    async fn channel_open_direct_tcpip(
+        &mut self,
+        channel: Channel<Msg>,
+        host: &str,
+        port: u32,
+        _originator_address: &str,
+        _originator_port: u32,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        let destination = format!("{}:{}", host, port);
+        self.dialled.lock().unwrap().push(destination.clone());
+        tokio::spawn(async move {
+            if let Ok(stream) = TcpStream::connect(destination).await {
+                bridge(channel.into_stream(), stream).await;
+            }
+        });
+        Ok(true)
+    }
+
+    async fn tcpip_forward(
+        &mut self,
+        address: &str,
+        port: &mut u32,
+        session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        let listener = TcpListener::bind((address, *port as u16)).await?;
+        *port = listener.local_addr()?.port().into();
+        let (address, bound, handle) = (address.to_string(), *port, session.handle());
+        tokio::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                let Ok(channel) = handle
+                    .channel_open_forwarded_tcpip(
+                        address.clone(),
+                        bound,
+                        peer.ip().to_string(),
+                        peer.port().into(),
+                    )
+                    .await
+                else {
+                    break;
+                };
+                tokio::spawn(bridge(channel.into_stream(), stream));
+            }
+        });
+        Ok(true)
+    }
+}
+
+/// Start a [`TestServer`] on a loopback port, returning it and its address.
+pub(crate) async fn start_server() -> (TestServer, String) {
+    let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+    let config = Arc::new(server::Config {
+        keys: vec![key],
+        auth_rejection_time: Duration::from_millis(10),
+        ..Default::default()
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let mut server = TestServer::default();
+    let running = server.clone();
+    tokio::spawn(async move {
+        let _ = server.run_on_socket(config, &listener).await;
+    });
+    (running, address)
+}
+
+/// Connect to `address` as `deploy`, through `jump_hosts` when given.
+pub(crate) async fn connect(
+    address: &str,
+    jump_hosts: &[JumpHost],
+    reverse: &Arc<ReverseForwards>,
+    traffic: &Arc<ByteCounters>,
+) -> client::Handle<SshClientHandler> {
+    let (handle, _) = connect_to_ssh_with_retry(
+        address,
+        "deploy",
+        Some(TEST_PASSWORD),
+        None,
+        None,
+        Duration::from_secs(5),
+        Duration::from_secs(60),
+        0,
+        Duration::from_millis(10),
+        false,
+        false,
+        Keepalive::default(),
+        &AlgorithmPreferences::default(),
+        HostKeyChecking::No,
+        jump_hosts,
+        false,
+        reverse,
+        traffic,
+    )
+    .await
+    .unwrap();
+    handle
+}
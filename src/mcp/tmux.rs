@@ -0,0 +1,240 @@
+//! Remote terminal multiplexer (tmux / GNU screen) helpers.
+//!
+//! Interactive shells opened with `ssh_shell_open` die with their SSH channel.
+//! `ssh_tmux_attach` instead runs the shell inside a named tmux (or screen)
+//! session on the remote host and bridges the attached client to the regular
+//! shell subsystem, so `ssh_shell_write`/`ssh_shell_read` work unchanged.
+//! Closing the shell, losing the connection or restarting this server only
+//! detaches the client; the multiplexer session keeps running and can be
+//! re-attached by name later.
+
+use std::fmt;
+
+use super::command_wrap::shell_quote;
+use super::types::MultiplexerSession;
+
+/// Longest accepted multiplexer session name
+const MAX_NAME_LEN: usize = 64;
+
+/// Terminal multiplexer used on the remote host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Multiplexer {
+    #[default]
+    Tmux,
+    Screen,
+}
+
+impl fmt::Display for Multiplexer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Multiplexer::Tmux => write!(f, "tmux"),
+            Multiplexer::Screen => write!(f, "screen"),
+        }
+    }
+}
+
+impl Multiplexer {
+    /// Parse the `backend` tool parameter (default: tmux).
+    pub fn from_param(backend: Option<&str>) -> Result<Self, String> {
+        match backend {
+            None | Some("tmux") => Ok(Multiplexer::Tmux),
+            Some("screen") => Ok(Multiplexer::Screen),
+            Some(other) => Err(format!(
+                "backend must be \"tmux\" or \"screen\", got \"{}\"",
+                other
+            )),
+        }
+    }
+
+    /// Shell input that replaces the login shell with a client attached to
+    /// `name`, creating the session if it does not exist yet.
+    ///
+    /// When the multiplexer is not installed the login shell stays open and
+    /// prints a notice instead.
+    pub fn attach_input(self, name: &str) -> String {
+        let name = shell_quote(name);
+        let attach = match self {
+            Multiplexer::Tmux => format!("exec tmux new-session -A -s {}", name),
+            Multiplexer::Screen => format!("exec screen -D -R -S {}", name),
+        };
+        format!(
+            "command -v {bin} >/dev/null 2>&1 && {attach} || echo '{bin} is not installed on this host'\n",
+            bin = self,
+            attach = attach
+        )
+    }
+
+    /// Command listing the multiplexer sessions of the remote user.
+    pub fn list_command(self) -> &'static str {
+        match self {
+            Multiplexer::Tmux => {
+                "tmux list-sessions -F '#{session_name}\t#{session_windows}\t#{session_attached}\t#{session_created}' 2>/dev/null || true"
+            }
+            Multiplexer::Screen => "screen -ls 2>/dev/null || true",
+        }
+    }
+
+    /// Parse the output of [`Multiplexer::list_command`].
+    pub fn parse_sessions(self, stdout: &str) -> Vec<MultiplexerSession> {
+        match self {
+            Multiplexer::Tmux => stdout.lines().filter_map(parse_tmux_line).collect(),
+            Multiplexer::Screen => stdout.lines().filter_map(parse_screen_line).collect(),
+        }
+    }
+}
+
+/// Validate a multiplexer session name.
+///
+/// Names are restricted to characters both tmux and screen accept as-is.
+pub(crate) fn validate_session_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!(
+            "name must be 1-{} characters, got {}",
+            MAX_NAME_LEN,
+            name.len()
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "name may only contain letters, digits, '-' and '_', got \"{}\"",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// `name<TAB>windows<TAB>attached_clients<TAB>created_epoch`
+fn parse_tmux_line(line: &str) -> Option<MultiplexerSession> {
+    let mut fields = line.trim_end_matches('\r').split('\t');
+    let name = fields.next().filter(|name| !name.is_empty())?.to_string();
+    let windows = fields.next().and_then(|v| v.parse().ok());
+    let attached = fields
+        .next()
+        .and_then(|v| v.parse::<u32>().ok())
+        .is_some_and(|clients| clients > 0);
+    let created_at = fields
+        .next()
+        .and_then(|v| v.parse::<i64>().ok())
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|created| created.to_rfc3339());
+
+    Some(MultiplexerSession {
+        name,
+        windows,
+        attached,
+        created_at,
+    })
+}
+
+/// `<TAB>12345.name<TAB>(date)<TAB>(Attached)` as printed by `screen -ls`
+fn parse_screen_line(line: &str) -> Option<MultiplexerSession> {
+    if !line.starts_with('\t') {
+        return None;
+    }
+    let mut fields = line.split('\t').map(str::trim).filter(|f| !f.is_empty());
+    let (_, name) = fields.next()?.split_once('.')?;
+    let attached = fields.any(|field| field.eq_ignore_ascii_case("(attached)"));
+
+    Some(MultiplexerSession {
+        name: name.to_string(),
+        windows: None,
+        attached,
+        created_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod params {
+        use super::*;
+
+        #[test]
+        fn test_backend_defaults_to_tmux() {
+            assert_eq!(Multiplexer::from_param(None), Ok(Multiplexer::Tmux));
+            assert_eq!(
+                Multiplexer::from_param(Some("screen")),
+                Ok(Multiplexer::Screen)
+            );
+            assert!(Multiplexer::from_param(Some("zellij")).is_err());
+        }
+
+        #[test]
+        fn test_valid_names() {
+            assert!(validate_session_name("deploy").is_ok());
+            assert!(validate_session_name("build_2024-01").is_ok());
+        }
+
+        #[test]
+        fn test_invalid_names() {
+            assert!(validate_session_name("").is_err());
+            assert!(validate_session_name("a.b").is_err());
+            assert!(validate_session_name("x; rm -rf /").is_err());
+            assert!(validate_session_name(&"a".repeat(65)).is_err());
+        }
+    }
+
+    mod commands {
+        use super::*;
+
+        #[test]
+        fn test_tmux_attach_creates_or_attaches() {
+            let input = Multiplexer::Tmux.attach_input("deploy");
+            assert!(input.contains("exec tmux new-session -A -s 'deploy'"));
+            assert!(input.starts_with("command -v tmux"));
+            assert!(input.ends_with('\n'));
+        }
+
+        #[test]
+        fn test_screen_attach() {
+            let input = Multiplexer::Screen.attach_input("deploy");
+            assert!(input.contains("exec screen -D -R -S 'deploy'"));
+            assert!(input.contains("screen is not installed"));
+        }
+    }
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn test_tmux_sessions() {
+            let sessions = Multiplexer::Tmux
+                .parse_sessions("deploy\t3\t1\t1700000000\nscratch\t1\t0\t1700000100\n");
+            assert_eq!(sessions.len(), 2);
+            assert_eq!(sessions[0].name, "deploy");
+            assert_eq!(sessions[0].windows, Some(3));
+            assert!(sessions[0].attached);
+            assert_eq!(
+                sessions[0].created_at.as_deref(),
+                Some("2023-11-14T22:13:20+00:00")
+            );
+            assert!(!sessions[1].attached);
+        }
+
+        #[test]
+        fn test_tmux_no_server() {
+            assert!(Multiplexer::Tmux.parse_sessions("").is_empty());
+        }
+
+        #[test]
+        fn test_screen_sessions() {
+            let output = "There are screens on:\n\t12345.deploy\t(01/15/2024 10:30:00 AM)\t(Detached)\n\t678.build\t(Attached)\n2 Sockets in /run/screen/S-deploy.\n";
+            let sessions = Multiplexer::Screen.parse_sessions(output);
+            assert_eq!(sessions.len(), 2);
+            assert_eq!(sessions[0].name, "deploy");
+            assert!(!sessions[0].attached);
+            assert_eq!(sessions[1].name, "build");
+            assert!(sessions[1].attached);
+        }
+
+        #[test]
+        fn test_screen_no_sessions() {
+            let output = "No Sockets found in /run/screen/S-deploy.\n";
+            assert!(Multiplexer::Screen.parse_sessions(output).is_empty());
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Per-session byte counters.
+//!
+//! Every connection has one [`ByteCounters`]. Received bytes are counted by
+//! the connection's handler, which sees the data of every channel (command
+//! output, shells, SFTP, forwards). Sent bytes are counted where channel data
+//! is written: shell input, uploads and relays, and forwarded streams through
+//! [`CountingStream`]. Only channel payload is counted, not SSH framing or
+//! exec request lines.
+//!
+//! The totals are reported as `bytes_sent` / `bytes_received` of each session.
+//! Once a session is registered its counters are also charged to its owning
+//! agent in `ssh_usage`, which keeps them after the session is gone.
+//!
+//! The counters also remember when the connection was last used (channel
+//! data, a channel opened or a command exiting), which the idle reaper reads
+//! and sessions report as `stats.last_activity`, and the algorithms of its
+//! last key exchange, reported with the session.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::accounting::USAGE;
+use super::types::NegotiatedAlgorithms;
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Bytes sent and received on one connection.
+#[derive(Debug)]
+pub struct ByteCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+    /// Agent the traffic is charged to in the usage ledger
+    billed: OnceLock<String>,
+    /// Unix time of the last activity, in milliseconds
+    last_activity: AtomicU64,
+    /// Algorithms of the last key exchange
+    algorithms: Mutex<Option<NegotiatedAlgorithms>>,
+}
+
+impl Default for ByteCounters {
+    fn default() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            billed: OnceLock::new(),
+            last_activity: AtomicU64::new(now_millis()),
+            algorithms: Mutex::new(None),
+        }
+    }
+}
+
+impl ByteCounters {
+    /// Charge traffic to `agent` from now on, including what was already
+    /// counted. Later calls do nothing.
+    pub(crate) fn bill_to(&self, agent: &str) {
+        if self.billed.set(agent.to_string()).is_ok() {
+            USAGE.record_traffic(agent, self.sent(), self.received());
+        }
+    }
+
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.touch();
+        self.sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Some(agent) = self.billed.get() {
+            USAGE.record_traffic(agent, bytes as u64, 0);
+        }
+    }
+
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.touch();
+        self.received.fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Some(agent) = self.billed.get() {
+            USAGE.record_traffic(agent, 0, bytes as u64);
+        }
+    }
+
+    pub(crate) fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Record activity that carries no payload.
+    pub(crate) fn touch(&self) {
+        self.last_activity
+            .fetch_max(now_millis(), Ordering::Relaxed);
+    }
+
+    /// Record the algorithms of a completed key exchange.
+    pub(crate) fn record_algorithms(&self, algorithms: NegotiatedAlgorithms) {
+        *self
+            .algorithms
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(algorithms);
+    }
+
+    /// Algorithms of the last key exchange, once one completed.
+    pub(crate) fn algorithms(&self) -> Option<NegotiatedAlgorithms> {
+        self.algorithms
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// When the connection was last used (RFC3339).
+    pub(crate) fn last_activity(&self) -> Option<String> {
+        let millis = self.last_activity.load(Ordering::Relaxed);
+        chrono::DateTime::from_timestamp_millis(millis as i64).map(|at| at.to_rfc3339())
+    }
+
+    /// Time since the connection was last used.
+    pub(crate) fn idle_for(&self) -> Duration {
+        let last = self.last_activity.load(Ordering::Relaxed);
+        Duration::from_millis(now_millis().saturating_sub(last))
+    }
+}
+
+/// A channel stream that counts the bytes written to it as sent.
+///
+/// Reads pass through uncounted; the handler already counts received data.
+pub(crate) struct CountingStream<S> {
+    inner: S,
+    traffic: Arc<ByteCounters>,
+}
+
+impl<S> CountingStream<S> {
+    pub(crate) fn new(inner: S, traffic: Arc<ByteCounters>) -> Self {
+        Self { inner, traffic }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.traffic.record_sent(written);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_billing_includes_earlier_traffic() {
+        let traffic = ByteCounters::default();
+        traffic.record_sent(100);
+        traffic.record_received(40);
+        traffic.bill_to("traffic-test-agent");
+        traffic.record_received(2);
+        // A second owner is ignored
+        traffic.bill_to("traffic-test-other");
+        traffic.record_sent(1);
+
+        assert_eq!((traffic.sent(), traffic.received()), (101, 42));
+        let usage = USAGE.snapshot(Some("traffic-test-agent"));
+        assert_eq!((usage[0].bytes_sent, usage[0].bytes_received), (101, 42));
+        assert!(USAGE.snapshot(Some("traffic-test-other")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stream_counts_writes_only() {
+        let traffic = Arc::new(ByteCounters::default());
+        let (near, mut far) = tokio::io::duplex(64);
+        let mut stream = CountingStream::new(near, traffic.clone());
+
+        stream.write_all(b"hello").await.unwrap();
+        far.write_all(b"world!").await.unwrap();
+        let mut buf = [0u8; 6];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        assert_eq!(&buf, b"world!");
+        assert_eq!(traffic.sent(), 5);
+        assert_eq!(traffic.received(), 0);
+    }
+
+    #[test]
+    fn test_traffic_resets_idle_time() {
+        let traffic = ByteCounters::default();
+        traffic
+            .last_activity
+            .store(now_millis().saturating_sub(60_000), Ordering::Relaxed);
+        assert!(traffic.idle_for() >= Duration::from_secs(59));
+
+        traffic.record_received(1);
+        assert!(traffic.idle_for() < Duration::from_secs(59));
+    }
+}
@@ -0,0 +1,1162 @@
+//! Directory transfer over exec channels.
+//!
+//! `ssh_upload_dir` walks a local directory tree and recreates it on the remote
+//! host; `ssh_collect` packs remote paths into a gzipped tar archive and
+//! downloads it. Like the other file tools neither needs an SFTP subsystem:
+//! directories are created with `mkdir -p`, each regular file is streamed into
+//! `cat` over its own exec channel, symbolic links are recreated with
+//! `ln -sfn`, and archives are read from the stdout of `tar -czf -`.
+//!
+//! # Walk
+//!
+//! Entries are visited in sorted order, parents before children, so the remote
+//! directories can be created in one pass before any file is sent. Exclude
+//! patterns use `*` and `?` wildcards; a pattern containing `/` is matched
+//! against the path relative to the upload root, any other pattern against
+//! each entry's name (so `target` or `*.pyc` prune at any depth). An excluded
+//! directory is skipped with everything below it.
+//!
+//! # Concurrency
+//!
+//! Files are uploaded over up to `concurrency` channels at once on the same
+//! session. One failed file does not abort the others; every file gets its
+//! own result entry.
+//!
+//! # Collection
+//!
+//! Log files often change while they are archived and some of the requested
+//! paths may not exist; `tar` reports both on stderr and still writes the
+//! rest. The archive is therefore kept whenever the download completes, and
+//! the warnings are returned next to it. Downloads are capped in size, and a
+//! partial archive is removed when the cap or the timeout is hit.
+//!
+//! # Relay
+//!
+//! `ssh_copy_between` bridges two exec channels: the stdout of `cat` on the
+//! source session is written chunk by chunk into `cat >` on the destination
+//! session, so nothing is buffered beyond one SSH window and the hosts never
+//! need to reach each other. The destination is written to a temporary file
+//! next to the target and renamed over it only after both sides succeeded; a
+//! failed or timed-out relay removes the temporary file and leaves any
+//! existing target untouched.
+//!
+//! # Local root
+//!
+//! Local paths are files of the MCP server host, so both tools are refused
+//! until `[transfer] local_root` names a directory: relative paths resolve
+//! against it, and a path that leaves it (through `..`, an absolute path or a
+//! symbolic link) is refused. Uploads only follow links to targets inside the
+//! uploaded directory, and archives are extracted without restoring their owners or
+//! permission bits.
+//!
+//! # Bandwidth
+//!
+//! Uploads, archive downloads and relays take an optional [`Bandwidth`]: the
+//! files of an upload share it, and downloads and relays pause their reading
+//! channel after each chunk, so the remote side is slowed down as well.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use russh::{Channel, ChannelMsg, client};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use super::client::{channel_step, execute_ssh_command};
+use super::command_wrap::shell_quote;
+use super::config::resolve_channel_open_timeout;
+use super::rate_limit::{Bandwidth, Throttled};
+use super::session::SshClientHandler;
+use super::symlink::{failure_message, symlink_command};
+use super::traffic::ByteCounters;
+use super::types::{FileTransferResult, TransferStatus};
+
+/// Files uploaded in parallel when `concurrency` is not given
+pub(crate) const DEFAULT_UPLOAD_CONCURRENCY: usize = 4;
+
+/// Largest accepted `concurrency`
+pub(crate) const MAX_UPLOAD_CONCURRENCY: usize = 16;
+
+/// Largest number of entries (files, directories and links) in one upload
+pub(crate) const MAX_UPLOAD_ENTRIES: usize = 10_000;
+
+/// Deepest directory nesting walked; guards against symlink loops
+const MAX_WALK_DEPTH: usize = 64;
+
+/// Directories created per `mkdir` command
+const MKDIR_BATCH: usize = 100;
+
+/// Maximum time for a `mkdir` or `ln` command
+const REMOTE_STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum time to wait for `cat` to exit once the file has been sent
+const UPLOAD_EXIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Archive size limit when `max_bytes` is not given (1 GiB)
+pub(crate) const DEFAULT_COLLECT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Time allowed to build and download an archive when `timeout_secs` is not given
+pub(crate) const DEFAULT_COLLECT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time allowed for a relay when `timeout_secs` is not given
+pub(crate) const DEFAULT_RELAY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `tar` notice for absolute member names; expected, not worth reporting
+const TAR_LEADING_SLASH_NOTICE: &str = "Removing leading";
+
+/// Resolve the upload concurrency, clamped to 1..=`MAX_UPLOAD_CONCURRENCY`.
+pub(crate) fn resolve_upload_concurrency(concurrency: Option<usize>) -> usize {
+    concurrency
+        .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+        .clamp(1, MAX_UPLOAD_CONCURRENCY)
+}
+
+/// Kind of a local entry found by [`walk_local_dir`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum LocalEntryKind {
+    Dir,
+    File { size: u64 },
+    Symlink { target: String },
+}
+
+/// A local entry with its path relative to the upload root.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LocalEntry {
+    /// Relative path with `/` separators
+    pub rel_path: String,
+    pub kind: LocalEntryKind,
+    /// Permission bits (Unix only)
+    pub mode: Option<u32>,
+}
+
+/// Match `text` against a pattern of literal characters, `*` and `?`.
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Whether the entry at `rel_path` is excluded by any of `patterns`.
+pub(crate) fn is_excluded(rel_path: &str, patterns: &[String]) -> bool {
+    let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+    patterns.iter().any(|pattern| {
+        if pattern.contains('/') {
+            wildcard_match(pattern.trim_matches('/'), rel_path)
+        } else {
+            wildcard_match(pattern, name)
+        }
+    })
+}
+
+/// Walk `root` and list every entry below it, parents before children.
+///
+/// With `follow_symlinks`, links are replaced by what they point to when that
+/// is inside `boundary`; dangling links and links leading out of it are still
+/// recreated as links. Fails when the tree holds more than
+/// [`MAX_UPLOAD_ENTRIES`] entries or nests deeper than 64 levels.
+pub(crate) fn walk_local_dir(
+    root: &Path,
+    boundary: &Path,
+    exclude: &[String],
+    follow_symlinks: bool,
+) -> Result<Vec<LocalEntry>, String> {
+    let mut entries = Vec::new();
+    let follow = follow_symlinks.then_some(boundary);
+    walk_into(root, "", 0, exclude, follow, &mut entries)?;
+    Ok(entries)
+}
+
+fn walk_into(
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    exclude: &[String],
+    follow: Option<&Path>,
+    entries: &mut Vec<LocalEntry>,
+) -> Result<(), String> {
+    if depth > MAX_WALK_DEPTH {
+        return Err(format!(
+            "{} is nested deeper than {} levels (symlink loop?)",
+            dir.display(),
+            MAX_WALK_DEPTH
+        ));
+    }
+
+    let mut children: Vec<_> = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        let rel_path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        if is_excluded(&rel_path, exclude) {
+            continue;
+        }
+
+        let path = child.path();
+        let mut metadata = std::fs::symlink_metadata(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if metadata.file_type().is_symlink() {
+            let followed = follow
+                .filter(|boundary| {
+                    std::fs::canonicalize(&path).is_ok_and(|target| target.starts_with(boundary))
+                })
+                .and_then(|_| std::fs::metadata(&path).ok());
+            match followed {
+                Some(target_metadata) => metadata = target_metadata,
+                None => {
+                    let target = std::fs::read_link(&path)
+                        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                    push_entry(
+                        entries,
+                        LocalEntry {
+                            rel_path,
+                            kind: LocalEntryKind::Symlink {
+                                target: target.to_string_lossy().into_owned(),
+                            },
+                            mode: None,
+                        },
+                    )?;
+                    continue;
+                }
+            }
+        }
+
+        let mode = local_mode(&metadata);
+        if metadata.is_dir() {
+            push_entry(
+                entries,
+                LocalEntry {
+                    rel_path: rel_path.clone(),
+                    kind: LocalEntryKind::Dir,
+                    mode,
+                },
+            )?;
+            walk_into(&path, &rel_path, depth + 1, exclude, follow, entries)?;
+        } else if metadata.is_file() {
+            push_entry(
+                entries,
+                LocalEntry {
+                    rel_path,
+                    kind: LocalEntryKind::File {
+                        size: metadata.len(),
+                    },
+                    mode,
+                },
+            )?;
+        }
+        // Sockets, FIFOs and device nodes are not transferable and are skipped.
+    }
+    Ok(())
+}
+
+fn push_entry(entries: &mut Vec<LocalEntry>, entry: LocalEntry) -> Result<(), String> {
+    if entries.len() >= MAX_UPLOAD_ENTRIES {
+        return Err(format!(
+            "Directory holds more than {} entries; upload a subdirectory or add exclude patterns",
+            MAX_UPLOAD_ENTRIES
+        ));
+    }
+    entries.push(entry);
+    Ok(())
+}
+
+/// Resolve a local path of a transfer tool inside `root` (`[transfer] local_root`).
+///
+/// Relative paths are taken from the root. The path is resolved as far as it
+/// exists, following symbolic links, and must stay inside the root; `..`
+/// components are refused outright.
+pub(crate) fn confine_local_path(root: Option<&str>, path: &str) -> Result<PathBuf, String> {
+    let root = root.ok_or(
+        "Local transfers are disabled: the server configuration has no [transfer] local_root",
+    )?;
+    let root = std::fs::canonicalize(root)
+        .map_err(|e| format!("[transfer] local_root {} is unusable: {}", root, e))?;
+    let requested = Path::new(path);
+    if path.is_empty()
+        || requested
+            .components()
+            .any(|component| component == Component::ParentDir)
+    {
+        return Err(format!(
+            "Local path '{}' must be non-empty and may not contain '..'",
+            path
+        ));
+    }
+    let joined = root.join(requested);
+
+    // Resolve the deepest existing ancestor, so links cannot lead out
+    let mut existing = joined.as_path();
+    let mut missing = Vec::new();
+    let resolved = loop {
+        if let Ok(resolved) = std::fs::canonicalize(existing) {
+            break resolved;
+        }
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return Err(format!("Local path '{}' cannot be resolved", path)),
+        }
+    };
+    let resolved = missing
+        .into_iter()
+        .rev()
+        .fold(resolved, |resolved, name| resolved.join(name));
+    if !resolved.starts_with(&root) {
+        return Err(format!(
+            "Local path '{}' is outside [transfer] local_root {}",
+            path,
+            root.display()
+        ));
+    }
+    Ok(resolved)
+}
+
+/// Permission bits of a local entry (`None` off Unix).
+#[cfg(unix)]
+pub(crate) fn local_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn local_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Remote path of `rel_path` under `remote_root`.
+pub(crate) fn remote_path(remote_root: &str, rel_path: &str) -> String {
+    format!("{}/{}", remote_root.trim_end_matches('/'), rel_path)
+}
+
+/// Command creating `dirs` (already joined to the remote root), each with its mode.
+pub(crate) fn mkdir_command(dirs: &[(String, Option<u32>)]) -> String {
+    dirs.iter()
+        .map(|(path, mode)| match mode {
+            Some(mode) => format!("mkdir -p -m {:o} -- {}", mode, shell_quote(path)),
+            None => format!("mkdir -p -- {}", shell_quote(path)),
+        })
+        .collect::<Vec<_>>()
+        .join(" && ")
+}
+
+/// Command writing stdin to `path`, then applying `mode` when given.
+pub(crate) fn upload_command(path: &str, mode: Option<u32>) -> String {
+    let quoted = shell_quote(path);
+    match mode {
+        Some(mode) => format!("cat > {} && chmod {:o} {}", quoted, mode, quoted),
+        None => format!("cat > {}", quoted),
+    }
+}
+
+/// Create the remote root and every directory in `entries`.
+///
+/// Returns the number of directories created below the root.
+pub(crate) async fn create_remote_dirs(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    remote_root: &str,
+    root_mode: Option<u32>,
+    entries: &[LocalEntry],
+) -> Result<usize, String> {
+    let mut dirs = vec![(remote_root.to_string(), root_mode)];
+    dirs.extend(
+        entries
+            .iter()
+            .filter(|entry| entry.kind == LocalEntryKind::Dir)
+            .map(|entry| (remote_path(remote_root, &entry.rel_path), entry.mode)),
+    );
+
+    for batch in dirs.chunks(MKDIR_BATCH) {
+        let response =
+            execute_ssh_command(handle, &mkdir_command(batch), REMOTE_STEP_TIMEOUT).await?;
+        if response.timed_out || response.exit_code != 0 {
+            return Err(failure_message(&response));
+        }
+    }
+    Ok(dirs.len() - 1)
+}
+
+/// Upload every file and link in `entries`, at most `concurrency` at a time
+/// and all together within `bandwidth`.
+///
+/// Results are returned in walk order.
+pub(crate) async fn upload_entries(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    traffic: &ByteCounters,
+    local_root: &Path,
+    remote_root: &str,
+    entries: Vec<LocalEntry>,
+    concurrency: usize,
+    bandwidth: Option<&Arc<Bandwidth>>,
+) -> Vec<FileTransferResult> {
+    let mut results: Vec<(usize, FileTransferResult)> = stream::iter(
+        entries
+            .into_iter()
+            .filter(|entry| entry.kind != LocalEntryKind::Dir)
+            .enumerate(),
+    )
+    .map(|(index, entry)| async move {
+        let remote = remote_path(remote_root, &entry.rel_path);
+        let (size, outcome) = match &entry.kind {
+            LocalEntryKind::File { size } => {
+                let local = local_root.join(&entry.rel_path);
+                (
+                    Some(*size),
+                    upload_file(handle, &local, &remote, entry.mode, bandwidth)
+                        .await
+                        .map(|()| {
+                            traffic.record_sent(*size as usize);
+                            TransferStatus::Uploaded
+                        }),
+                )
+            }
+            LocalEntryKind::Symlink { target } => (
+                None,
+                create_link(handle, target, &remote)
+                    .await
+                    .map(|()| TransferStatus::Linked),
+            ),
+            LocalEntryKind::Dir => unreachable!("directories are filtered out"),
+        };
+        let result = match outcome {
+            Ok(status) => FileTransferResult {
+                path: entry.rel_path,
+                size,
+                status,
+                error: None,
+            },
+            Err(e) => FileTransferResult {
+                path: entry.rel_path,
+                size,
+                status: TransferStatus::Failed,
+                error: Some(e),
+            },
+        };
+        (index, result)
+    })
+    .buffer_unordered(concurrency)
+    .collect()
+    .await;
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+async fn create_link(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    target: &str,
+    remote: &str,
+) -> Result<(), String> {
+    let command = symlink_command(target, remote, true);
+    let response = execute_ssh_command(handle, &command, REMOTE_STEP_TIMEOUT).await?;
+    if response.timed_out || response.exit_code != 0 {
+        return Err(failure_message(&response));
+    }
+    Ok(())
+}
+
+/// Stream the local file at `local` into `remote` over a new exec channel.
+async fn upload_file(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    local: &Path,
+    remote: &str,
+    mode: Option<u32>,
+    bandwidth: Option<&Arc<Bandwidth>>,
+) -> Result<(), String> {
+    // Open the file first so an unreadable file never truncates its remote copy.
+    let file = tokio::fs::File::open(local)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", local.display(), e))?;
+
+    let open_timeout = resolve_channel_open_timeout();
+    let channel = channel_step("open channel", open_timeout, handle.channel_open_session()).await?;
+    channel_step(
+        "execute command",
+        open_timeout,
+        channel.exec(true, upload_command(remote, mode)),
+    )
+    .await?;
+
+    channel
+        .data(Throttled::new(file, bandwidth.cloned()))
+        .await
+        .map_err(|e| format!("Failed to send file data: {}", e))?;
+    finish_remote_write(channel).await
+}
+
+/// Write `data` to `remote` over a new exec channel.
+pub(crate) async fn upload_bytes(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    remote: &str,
+    data: &[u8],
+) -> Result<(), String> {
+    let open_timeout = resolve_channel_open_timeout();
+    let channel = channel_step("open channel", open_timeout, handle.channel_open_session()).await?;
+    channel_step(
+        "execute command",
+        open_timeout,
+        channel.exec(true, upload_command(remote, None)),
+    )
+    .await?;
+
+    channel
+        .data(data)
+        .await
+        .map_err(|e| format!("Failed to send file data: {}", e))?;
+    finish_remote_write(channel).await
+}
+
+/// Send EOF to a channel running [`upload_command`] and wait for the write to finish.
+async fn finish_remote_write(channel: Channel<client::Msg>) -> Result<(), String> {
+    channel
+        .eof()
+        .await
+        .map_err(|e| format!("Failed to send EOF: {}", e))?;
+    wait_remote_write(channel).await
+}
+
+/// Wait for a remote write command to exit; its first stderr line on failure.
+async fn wait_remote_write(mut channel: Channel<client::Msg>) -> Result<(), String> {
+    let mut stderr = Vec::new();
+    let mut exit_code: Option<u32> = None;
+    let finished = tokio::time::timeout(UPLOAD_EXIT_TIMEOUT, async {
+        loop {
+            match channel.wait().await {
+                Some(ChannelMsg::ExtendedData { data, ext: 1 }) => {
+                    stderr.extend_from_slice(&data);
+                }
+                Some(ChannelMsg::ExitStatus { exit_status }) => {
+                    exit_code = Some(exit_status);
+                }
+                Some(ChannelMsg::Eof) if exit_code.is_some() => break,
+                Some(ChannelMsg::Close) | None => break,
+                Some(_) => {}
+            }
+        }
+    })
+    .await;
+    let _ = channel.close().await;
+
+    if finished.is_err() {
+        return Err(format!(
+            "Remote write did not finish within {}s",
+            UPLOAD_EXIT_TIMEOUT.as_secs()
+        ));
+    }
+    match exit_code {
+        Some(0) => Ok(()),
+        code => {
+            let stderr = String::from_utf8_lossy(&stderr);
+            Err(stderr
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| match code {
+                    Some(code) => format!("Remote command exited with code {}", code),
+                    None => "Channel closed before the remote write finished".to_string(),
+                }))
+        }
+    }
+}
+
+/// Command writing the remote file at `path` to stdout.
+pub(crate) fn read_command(path: &str) -> String {
+    format!("cat -- {}", shell_quote(path))
+}
+
+/// Temporary name a relayed file is written to before it replaces `path`.
+pub(crate) fn relay_temp_path(path: &str, token: &str) -> String {
+    format!("{}.ssh-mcp-{}", path, token)
+}
+
+/// A file relayed between two sessions.
+#[derive(Debug)]
+pub(crate) struct Relay {
+    pub bytes: u64,
+    /// Hex sha256 of the relayed bytes
+    pub sha256: String,
+}
+
+/// Which side of a relay failed.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RelayError {
+    /// Reading the source file failed
+    Source(String),
+    /// Writing or renaming the destination file failed
+    Destination(String),
+    /// The relay did not finish within the timeout
+    TimedOut(Duration),
+}
+
+/// Copy `source_path` on `source` to `dest_path` on `dest` through this server,
+/// within `bandwidth` when given.
+///
+/// The target is only replaced when the whole file arrived.
+pub(crate) async fn relay_file(
+    source: &Arc<client::Handle<SshClientHandler>>,
+    source_path: &str,
+    dest: &Arc<client::Handle<SshClientHandler>>,
+    dest_traffic: &ByteCounters,
+    dest_path: &str,
+    timeout: Duration,
+    bandwidth: Option<&Bandwidth>,
+) -> Result<Relay, RelayError> {
+    let token = Uuid::new_v4().simple().to_string();
+    let temp = relay_temp_path(dest_path, &token[..12]);
+    let result = tokio::time::timeout(
+        timeout,
+        stream_between(source, source_path, dest, dest_traffic, &temp, bandwidth),
+    )
+    .await
+    .unwrap_or(Err(RelayError::TimedOut(timeout)));
+
+    let finish = match &result {
+        Ok(_) => format!("mv -f -- {} {}", shell_quote(&temp), shell_quote(dest_path)),
+        Err(_) => format!("rm -f -- {}", shell_quote(&temp)),
+    };
+    let finished = execute_ssh_command(dest, &finish, REMOTE_STEP_TIMEOUT).await;
+    let relay = result?;
+    match finished {
+        Ok(response) if !response.timed_out && response.exit_code == 0 => Ok(relay),
+        Ok(response) => Err(RelayError::Destination(failure_message(&response))),
+        Err(e) => Err(RelayError::Destination(e)),
+    }
+}
+
+/// Pipe `cat` on the source into `cat >` on the destination.
+async fn stream_between(
+    source: &Arc<client::Handle<SshClientHandler>>,
+    source_path: &str,
+    dest: &Arc<client::Handle<SshClientHandler>>,
+    dest_traffic: &ByteCounters,
+    temp: &str,
+    bandwidth: Option<&Bandwidth>,
+) -> Result<Relay, RelayError> {
+    let open_timeout = resolve_channel_open_timeout();
+    let mut reader = channel_step("open channel", open_timeout, source.channel_open_session())
+        .await
+        .map_err(RelayError::Source)?;
+    channel_step(
+        "execute command",
+        open_timeout,
+        reader.exec(true, read_command(source_path)),
+    )
+    .await
+    .map_err(RelayError::Source)?;
+    let writer = channel_step("open channel", open_timeout, dest.channel_open_session())
+        .await
+        .map_err(RelayError::Destination)?;
+    channel_step(
+        "execute command",
+        open_timeout,
+        writer.exec(true, upload_command(temp, None)),
+    )
+    .await
+    .map_err(RelayError::Destination)?;
+
+    let mut hasher = Sha256::new();
+    let mut bytes: u64 = 0;
+    let mut stderr = Vec::new();
+    let mut exit_code: Option<u32> = None;
+    loop {
+        match reader.wait().await {
+            Some(ChannelMsg::Data { data }) => {
+                hasher.update(&data);
+                bytes += data.len() as u64;
+                if let Err(e) = writer.data(&data[..]).await {
+                    let _ = reader.close().await;
+                    let message = wait_remote_write(writer)
+                        .await
+                        .err()
+                        .unwrap_or_else(|| format!("Failed to send file data: {}", e));
+                    return Err(RelayError::Destination(message));
+                }
+                dest_traffic.record_sent(data.len());
+                if let Some(bandwidth) = bandwidth {
+                    bandwidth.read.pace(data.len()).await;
+                }
+            }
+            Some(ChannelMsg::ExtendedData { data, ext: 1 }) => {
+                stderr.extend_from_slice(&data);
+            }
+            Some(ChannelMsg::ExitStatus { exit_status }) => {
+                exit_code = Some(exit_status);
+            }
+            Some(ChannelMsg::Eof) if exit_code.is_some() => break,
+            Some(ChannelMsg::Close) | None => break,
+            Some(_) => {}
+        }
+    }
+    let _ = reader.close().await;
+
+    if exit_code != Some(0) {
+        let _ = writer.close().await;
+        let stderr = String::from_utf8_lossy(&stderr);
+        let message = stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| match exit_code {
+                Some(code) => format!("Remote read exited with code {}", code),
+                None => "Channel closed before the remote read finished".to_string(),
+            });
+        return Err(RelayError::Source(message));
+    }
+    finish_remote_write(writer)
+        .await
+        .map_err(RelayError::Destination)?;
+
+    Ok(Relay {
+        bytes,
+        sha256: format!("{:x}", hasher.finalize()),
+    })
+}
+
+/// Command writing a gzipped tar archive of `paths` to stdout.
+pub(crate) fn collect_command(paths: &[String]) -> String {
+    let quoted: Vec<String> = paths.iter().map(|path| shell_quote(path)).collect();
+    format!("tar -czf - -- {}", quoted.join(" "))
+}
+
+/// `tar` warnings worth reporting, one per non-empty stderr line.
+pub(crate) fn collect_warnings(stderr: &str) -> Vec<String> {
+    stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.contains(TAR_LEADING_SLASH_NOTICE))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Result of streaming a remote command's stdout into a local file.
+#[derive(Debug)]
+pub(crate) struct Download {
+    pub bytes: u64,
+    pub stderr: String,
+    /// Exit status of the remote command (`-1` if none was sent)
+    pub exit_code: i32,
+}
+
+/// Why a download was abandoned.
+#[derive(Debug, PartialEq)]
+pub(crate) enum DownloadError {
+    /// The output grew past the size limit
+    TooLarge(u64),
+    /// Channel, timeout or local file failure
+    Failed(String),
+}
+
+impl From<String> for DownloadError {
+    fn from(message: String) -> Self {
+        DownloadError::Failed(message)
+    }
+}
+
+/// Run `command` and write its stdout to `dest`, at most `max_bytes` and
+/// within `bandwidth` when given.
+///
+/// On failure, a partially written `dest` is removed.
+pub(crate) async fn download_command_output(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    command: &str,
+    dest: &Path,
+    max_bytes: u64,
+    timeout: Duration,
+    bandwidth: Option<&Bandwidth>,
+) -> Result<Download, DownloadError> {
+    let result = stream_to_file(handle, command, dest, max_bytes, timeout, bandwidth).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(dest).await;
+    }
+    result
+}
+
+async fn stream_to_file(
+    handle: &Arc<client::Handle<SshClientHandler>>,
+    command: &str,
+    dest: &Path,
+    max_bytes: u64,
+    timeout: Duration,
+    bandwidth: Option<&Bandwidth>,
+) -> Result<Download, DownloadError> {
+    if let Some(parent) = dest
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let mut file = tokio::fs::File::create(dest)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", dest.display(), e))?;
+
+    let open_timeout = resolve_channel_open_timeout();
+    let mut channel =
+        channel_step("open channel", open_timeout, handle.channel_open_session()).await?;
+    channel_step("execute command", open_timeout, channel.exec(true, command)).await?;
+
+    let mut bytes: u64 = 0;
+    let mut stderr = Vec::new();
+    let mut exit_code: Option<u32> = None;
+    let streamed = tokio::time::timeout(timeout, async {
+        loop {
+            match channel.wait().await {
+                Some(ChannelMsg::Data { data }) => {
+                    bytes += data.len() as u64;
+                    if bytes > max_bytes {
+                        return Err(DownloadError::TooLarge(max_bytes));
+                    }
+                    file.write_all(&data).await.map_err(|e| {
+                        DownloadError::Failed(format!("Failed to write {}: {}", dest.display(), e))
+                    })?;
+                    if let Some(bandwidth) = bandwidth {
+                        bandwidth.read.pace(data.len()).await;
+                    }
+                }
+                Some(ChannelMsg::ExtendedData { data, ext: 1 }) => {
+                    stderr.extend_from_slice(&data);
+                }
+                Some(ChannelMsg::ExitStatus { exit_status }) => {
+                    exit_code = Some(exit_status);
+                }
+                Some(ChannelMsg::Eof) if exit_code.is_some() => break,
+                Some(ChannelMsg::Close) | None => break,
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    })
+    .await;
+    let _ = channel.close().await;
+
+    match streamed {
+        Err(_) => {
+            return Err(DownloadError::Failed(format!(
+                "Archive download did not finish within {}s",
+                timeout.as_secs()
+            )));
+        }
+        Ok(Err(e)) => return Err(e),
+        Ok(Ok(())) => {}
+    }
+    file.flush()
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+
+    Ok(Download {
+        bytes,
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        exit_code: exit_code.map(|code| code as i32).unwrap_or(-1),
+    })
+}
+
+/// Extract the gzipped tar `archive` into `dir` with the local `tar`.
+///
+/// The archive comes from the remote host: owners and permission bits are not
+/// restored, so extracted files belong to the server user with its umask.
+pub(crate) async fn extract_archive(archive: &Path, dir: &Path) -> Result<(), String> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let output = tokio::process::Command::new("tar")
+        .arg("--no-same-owner")
+        .arg("--no-same-permissions")
+        .arg("-xzf")
+        .arg(archive)
+        .arg("-C")
+        .arg(dir)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run local tar: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| format!("Failed to extract archive: {}", line))
+        .unwrap_or_else(|| {
+            format!(
+                "Failed to extract archive: tar exited with {}",
+                output.status
+            )
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod wildcards {
+        use super::*;
+
+        #[test]
+        fn test_literal_and_wildcards() {
+            assert!(wildcard_match("target", "target"));
+            assert!(wildcard_match("*.pyc", "module.pyc"));
+            assert!(wildcard_match("file?.txt", "file1.txt"));
+            assert!(wildcard_match("a*b*c", "axxbyyc"));
+            assert!(!wildcard_match("*.pyc", "module.py"));
+            assert!(!wildcard_match("file?.txt", "file10.txt"));
+        }
+
+        #[test]
+        fn test_name_patterns_match_any_depth() {
+            let patterns = vec!["node_modules".to_string(), "*.log".to_string()];
+            assert!(is_excluded("node_modules", &patterns));
+            assert!(is_excluded("web/node_modules", &patterns));
+            assert!(is_excluded("logs/app.log", &patterns));
+            assert!(!is_excluded("src/main.rs", &patterns));
+        }
+
+        #[test]
+        fn test_path_patterns_match_from_root() {
+            let patterns = vec!["build/*".to_string()];
+            assert!(is_excluded("build/out.bin", &patterns));
+            assert!(!is_excluded("src/build/out.bin", &patterns));
+        }
+    }
+
+    mod commands {
+        use super::*;
+
+        #[test]
+        fn test_remote_path_join() {
+            assert_eq!(
+                remote_path("/srv/app/", "conf/a.toml"),
+                "/srv/app/conf/a.toml"
+            );
+            assert_eq!(remote_path("/", "etc"), "/etc");
+        }
+
+        #[test]
+        fn test_mkdir_command_with_modes() {
+            let dirs = vec![
+                ("/srv/app".to_string(), Some(0o755)),
+                ("/srv/app/secrets".to_string(), None),
+            ];
+            assert_eq!(
+                mkdir_command(&dirs),
+                "mkdir -p -m 755 -- '/srv/app' && mkdir -p -- '/srv/app/secrets'"
+            );
+        }
+
+        #[test]
+        fn test_upload_command() {
+            assert_eq!(upload_command("/srv/a b", None), "cat > '/srv/a b'");
+            assert_eq!(
+                upload_command("/srv/run.sh", Some(0o750)),
+                "cat > '/srv/run.sh' && chmod 750 '/srv/run.sh'"
+            );
+        }
+
+        #[test]
+        fn test_relay_commands() {
+            assert_eq!(
+                read_command("/var/log/a b.log"),
+                "cat -- '/var/log/a b.log'"
+            );
+            assert_eq!(
+                relay_temp_path("/srv/app.tar", "0123456789ab"),
+                "/srv/app.tar.ssh-mcp-0123456789ab"
+            );
+        }
+
+        #[test]
+        fn test_concurrency_clamped() {
+            assert_eq!(resolve_upload_concurrency(None), DEFAULT_UPLOAD_CONCURRENCY);
+            assert_eq!(resolve_upload_concurrency(Some(0)), 1);
+            assert_eq!(
+                resolve_upload_concurrency(Some(100)),
+                MAX_UPLOAD_CONCURRENCY
+            );
+        }
+    }
+
+    mod collect {
+        use super::*;
+
+        #[test]
+        fn test_collect_command_quotes_paths() {
+            let paths = vec!["/var/log/app".to_string(), "/etc/my app.conf".to_string()];
+            assert_eq!(
+                collect_command(&paths),
+                "tar -czf - -- '/var/log/app' '/etc/my app.conf'"
+            );
+        }
+
+        #[test]
+        fn test_warnings_skip_leading_slash_notice() {
+            let stderr = "tar: Removing leading `/' from member names\n\
+                          tar: /var/log/missing: Cannot stat: No such file or directory\n\n";
+            assert_eq!(
+                collect_warnings(stderr),
+                vec!["tar: /var/log/missing: Cannot stat: No such file or directory"]
+            );
+        }
+    }
+
+    mod walk {
+        use super::*;
+
+        fn scratch_dir(name: &str) -> std::path::PathBuf {
+            let dir = std::env::temp_dir().join(format!(
+                "ssh-mcp-transfer-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        #[test]
+        fn test_parents_before_children_and_excludes() {
+            let root = scratch_dir("walk");
+            std::fs::create_dir_all(root.join("conf/nested")).unwrap();
+            std::fs::create_dir_all(root.join("target/debug")).unwrap();
+            std::fs::write(root.join("conf/nested/a.toml"), "a = 1").unwrap();
+            std::fs::write(root.join("run.sh"), "#!/bin/sh").unwrap();
+            std::fs::write(root.join("target/debug/app"), "bin").unwrap();
+
+            let entries = walk_local_dir(&root, &root, &["target".to_string()], false).unwrap();
+            let paths: Vec<_> = entries.iter().map(|e| e.rel_path.as_str()).collect();
+            assert_eq!(
+                paths,
+                vec!["conf", "conf/nested", "conf/nested/a.toml", "run.sh"]
+            );
+            assert_eq!(entries[3].kind, LocalEntryKind::File { size: 9 });
+
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_symlinks_preserved_or_followed() {
+            let root = scratch_dir("links");
+            std::fs::write(root.join("real.conf"), "x").unwrap();
+            std::os::unix::fs::symlink("real.conf", root.join("app.conf")).unwrap();
+
+            let preserved = walk_local_dir(&root, &root, &[], false).unwrap();
+            assert_eq!(
+                preserved[0].kind,
+                LocalEntryKind::Symlink {
+                    target: "real.conf".to_string()
+                }
+            );
+
+            let followed = walk_local_dir(&root, &root, &[], true).unwrap();
+            assert_eq!(followed[0].kind, LocalEntryKind::File { size: 1 });
+
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_links_out_of_boundary_not_followed() {
+            let root = scratch_dir("boundary");
+            let outside = scratch_dir("boundary-outside");
+            std::fs::write(outside.join("id_ed25519"), "secret").unwrap();
+            std::os::unix::fs::symlink(outside.join("id_ed25519"), root.join("key")).unwrap();
+
+            let entries = walk_local_dir(&root, &root, &[], true).unwrap();
+            assert!(matches!(entries[0].kind, LocalEntryKind::Symlink { .. }));
+
+            std::fs::remove_dir_all(&root).unwrap();
+            std::fs::remove_dir_all(&outside).unwrap();
+        }
+    }
+
+    mod local_root {
+        use super::*;
+
+        fn scratch_root(name: &str) -> PathBuf {
+            let dir = std::env::temp_dir().join(format!(
+                "ssh-mcp-local-root-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::canonicalize(dir).unwrap()
+        }
+
+        #[test]
+        fn test_disabled_without_root() {
+            let error = confine_local_path(None, "incident.tar.gz").unwrap_err();
+            assert!(error.contains("[transfer] local_root"));
+        }
+
+        #[test]
+        fn test_paths_inside_root() {
+            let root = scratch_root("inside");
+            let root_str = root.to_str().unwrap();
+            assert_eq!(
+                confine_local_path(Some(root_str), "incidents/web1.tar.gz").unwrap(),
+                root.join("incidents/web1.tar.gz")
+            );
+            let absolute = root.join("out");
+            assert_eq!(
+                confine_local_path(Some(root_str), absolute.to_str().unwrap()).unwrap(),
+                absolute
+            );
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+
+        #[test]
+        fn test_paths_outside_root_refused() {
+            let root = scratch_root("outside");
+            let root_str = Some(root.to_str().unwrap());
+            assert!(confine_local_path(root_str, "/etc/cron.d/job").is_err());
+            assert!(confine_local_path(root_str, "../escape").is_err());
+            assert!(confine_local_path(root_str, "a/../../escape").is_err());
+            assert!(confine_local_path(root_str, "").is_err());
+
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink("/etc", root.join("etc")).unwrap();
+                assert!(confine_local_path(root_str, "etc/cron.d/job").is_err());
+            }
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Streamable HTTP transport.
+//!
+//! Implements the MCP streamable HTTP transport on a single route:
+//!
+//! - `POST` with an `initialize` request creates an MCP session and returns its
+//!   id in the `Mcp-Session-Id` header
+//! - `POST` with the `Mcp-Session-Id` header handles requests on that session,
+//!   answering as JSON or as an SSE stream depending on the `Accept` header;
+//!   a post of only responses (to server requests sent on the notification
+//!   stream) is answered `202 Accepted`
+//! - `GET` with the `Mcp-Session-Id` header opens the session's SSE stream of
+//!   server-to-client notifications (one stream per session at a time)
+//! - `DELETE` with the `Mcp-Session-Id` header ends the session
+//!
+//! MCP sessions idle for more than [`SESSION_TIMEOUT`] are dropped, unless
+//! their notification stream is open. With [`token_auth`](super::token_auth)
+//! on, a session only answers to the token that created it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use poem::http::StatusCode;
+use poem::web::sse::{Event, SSE};
+use poem::web::{Accept, Data, Json};
+use poem::{EndpointExt, IntoEndpoint, IntoResponse, Request, handler, post};
+use poem_mcpserver::protocol::rpc::BatchRequest;
+use serde_json::Value;
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::Instant;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use super::McpHandler;
+use super::token_auth::{Principal, principal};
+use crate::mcp::notify::NotificationSink;
+
+/// Idle time after which an MCP HTTP session is dropped
+const SESSION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Keep-alive interval of the notification stream
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Header carrying the MCP session id
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+type HandlerFactory = Box<dyn Fn(&Request) -> McpHandler + Send + Sync>;
+
+struct HttpSession {
+    handler: Arc<Mutex<McpHandler>>,
+    /// Routes the client's answers past a handler busy with a tool call
+    client: NotificationSink,
+    notifications: Arc<Mutex<mpsc::Receiver<Value>>>,
+    last_active: Instant,
+    /// Token that created the session
+    principal: Option<Principal>,
+}
+
+struct State {
+    factory: HandlerFactory,
+    sessions: StdMutex<HashMap<String, HttpSession>>,
+}
+
+impl State {
+    fn lock_sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, HttpSession>> {
+        // A poisoned map only means another request panicked; the data is still usable
+        self.sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The id in the `Mcp-Session-Id` header of `request`.
+fn session_id(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(SESSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// The session `session_id` of `sessions`, if `request` may use it; sessions
+/// of other tokens look unknown.
+fn find_session<'a>(
+    sessions: &'a mut HashMap<String, HttpSession>,
+    session_id: &str,
+    request: &Request,
+) -> Option<&'a mut HttpSession> {
+    sessions
+        .get_mut(session_id)
+        .filter(|session| session.principal == principal(request))
+}
+
+/// Whether `message` is a lone `initialize` request (alone in a batch too).
+fn is_initialize(message: &Value) -> bool {
+    let message = match message {
+        Value::Array(messages) if messages.len() == 1 => &messages[0],
+        message => message,
+    };
+    message.get("method").and_then(Value::as_str) == Some("initialize")
+}
+
+#[handler]
+async fn post_handler(
+    state: Data<&Arc<State>>,
+    request: &Request,
+    message: Json<Value>,
+    accept: Accept,
+) -> poem::Response {
+    let Some(accept) = accept.0.first() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    if is_initialize(&message) && !request.headers().contains_key(SESSION_HEADER) {
+        let session_id = Uuid::new_v4().simple().to_string();
+        let mut handler = (state.factory)(request);
+        let Some(notifications) = handler.take_notifications() else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        let client = handler.client();
+        let Some(Ok(batch_request)) = client
+            .intercept(message.0)
+            .map(serde_json::from_value::<BatchRequest>)
+        else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+
+        let mut responses = Vec::new();
+        for request in batch_request {
+            responses.extend(handler.handle_request(request).await);
+        }
+
+        state.lock_sessions().insert(
+            session_id.clone(),
+            HttpSession {
+                handler: Arc::new(Mutex::new(handler)),
+                client,
+                notifications: Arc::new(Mutex::new(notifications)),
+                last_active: Instant::now(),
+                principal: principal(request),
+            },
+        );
+
+        info!(session_id = session_id, "created MCP HTTP session");
+        return match responses.pop() {
+            Some(response) => Json(response)
+                .with_header(SESSION_HEADER, session_id)
+                .into_response(),
+            None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+    }
+
+    let Some(session_id) = session_id(request) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let (handler, client) = {
+        let mut sessions = state.lock_sessions();
+        let Some(session) = find_session(&mut sessions, session_id, request) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        session.last_active = Instant::now();
+        (session.handler.clone(), session.client.clone())
+    };
+    let Some(message) = client.intercept(message.0) else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+    let Ok(batch_request) = serde_json::from_value::<BatchRequest>(message) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    match accept.essence_str() {
+        "application/json" => {
+            let mut responses = Vec::new();
+            for request in batch_request {
+                debug!(session_id = session_id, request = ?request, "received request");
+                responses.extend(handler.lock().await.handle_request(request).await);
+            }
+            Json(responses)
+                .with_content_type("application/json")
+                .into_response()
+        }
+        "text/event-stream" => {
+            let stream = futures::stream::iter(batch_request)
+                .then(move |request| {
+                    let handler = handler.clone();
+                    async move { handler.lock().await.handle_request(request).await }
+                })
+                .map(|response| {
+                    Event::message(serde_json::to_string(&response).unwrap_or_default())
+                        .event_type("message")
+                });
+            SSE::new(stream).into_response()
+        }
+        _ => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+#[handler]
+async fn get_handler(state: Data<&Arc<State>>, request: &Request) -> poem::Response {
+    let Some(session_id) = session_id(request) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let notifications = {
+        let mut sessions = state.lock_sessions();
+        let Some(session) = find_session(&mut sessions, session_id, request) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        session.last_active = Instant::now();
+        session.notifications.clone()
+    };
+    let Ok(receiver) = notifications.try_lock_owned() else {
+        return StatusCode::CONFLICT.into_response();
+    };
+
+    info!(
+        session_id = session_id,
+        "opened MCP HTTP notification stream"
+    );
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        let notification = receiver.recv().await?;
+        let event = Event::message(serde_json::to_string(&notification).unwrap_or_default())
+            .event_type("message");
+        Some((event, receiver))
+    });
+    SSE::new(stream).keep_alive(KEEP_ALIVE).into_response()
+}
+
+#[handler]
+async fn delete_handler(state: Data<&Arc<State>>, request: &Request) -> StatusCode {
+    let Some(session_id) = session_id(request) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    {
+        let mut sessions = state.lock_sessions();
+        if find_session(&mut sessions, session_id, request).is_none() {
+            return StatusCode::NOT_FOUND;
+        }
+        sessions.remove(session_id);
+    }
+
+    info!(session_id = session_id, "deleted MCP HTTP session");
+    StatusCode::ACCEPTED
+}
+
+/// Create the streamable HTTP endpoint.
+///
+/// `factory` builds a fresh [`McpHandler`] for every MCP session.
+pub fn endpoint<F>(factory: F) -> impl IntoEndpoint
+where
+    F: Fn(&Request) -> McpHandler + Send + Sync + 'static,
+{
+    let state = Arc::new(State {
+        factory: Box::new(factory),
+        sessions: StdMutex::new(HashMap::new()),
+    });
+
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+            loop {
+                let now = interval.tick().await;
+                // An open notification stream holds the receiver lock
+                state.lock_sessions().retain(|_, session| {
+                    now - session.last_active < SESSION_TIMEOUT
+                        || session.notifications.try_lock().is_err()
+                });
+            }
+        }
+    });
+
+    post(post_handler)
+        .get(get_handler)
+        .delete(delete_handler)
+        .data(state)
+}
@@ -0,0 +1,373 @@
+//! MCP transports for the SSH tools.
+//!
+//! `poem-mcpserver` handles the JSON-RPC protocol, but some server features need
+//! to see or rewrite messages on their way in and out (for example injecting
+//! tool annotations into `tools/list`). [`McpHandler`] wraps the library server
+//! with these hooks, and the transports drive it:
+//!
+//! Each handler also owns the notification queue of its client (see
+//! [`notify`](super::notify)); requests run with that queue installed, and the
+//! transport takes the receiving end with [`McpHandler::take_notifications`].
+//! Incoming messages first pass [`NotificationSink::intercept`] (via
+//! [`McpHandler::client`]), which takes out the client's answers to server
+//! requests such as `elicitation/create`; a transport must do this without
+//! waiting for the handler, whose tool call may be the one waiting for them.
+//!
+//! - [`stdio`]: newline-delimited JSON-RPC over stdin/stdout
+//! - [`http`]: streamable HTTP endpoint for the Poem server
+//! - [`sse`]: HTTP+SSE routes for clients of the 2024-11-05 protocol revision
+//! - [`token_auth`]: bearer token middleware for the HTTP routes
+//! - [`tls`]: certificate and key of the HTTPS listener
+//!
+//! The `ssh-mcp` binary serves streamable HTTP, SSE or both on one port, as
+//! selected by [`MCP_TRANSPORT_ENV_VAR`]. What the process serves is recorded
+//! with [`set_serving`] so `ssh_capabilities` can report it.
+
+pub mod http;
+pub mod sse;
+pub mod stdio;
+pub mod tls;
+pub mod token_auth;
+
+use std::collections::HashSet;
+
+use once_cell::sync::{Lazy, OnceCell};
+use poem_mcpserver::McpServer;
+use poem_mcpserver::protocol::JSON_RPC_VERSION;
+use poem_mcpserver::protocol::rpc::{Request, Requests, Response, RpcError};
+use poem_mcpserver::tool::Tools;
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::access;
+use super::annotations::annotate_tools_list;
+use super::commands::McpSSHCommands;
+use super::config::{resolve_read_only, resolve_tool_prefix, server_config};
+use super::exposure::ToolExposure;
+use super::health;
+use super::managed;
+use super::notify::{self, NotificationSink};
+use super::pool;
+use super::reaper;
+use super::schema::enrich_tools_list;
+use super::storage::persistent;
+use token_auth::Principal;
+
+/// HTTP transports served by the `ssh-mcp` binary (default: both)
+pub const MCP_TRANSPORT_ENV_VAR: &str = "MCP_TRANSPORT";
+
+/// Which HTTP transports the `ssh-mcp` binary serves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HttpTransports {
+    /// Streamable HTTP on `/` only
+    StreamableHttp,
+    /// HTTP+SSE on `/sse` and `/messages` only
+    Sse,
+    /// Both, sharing the tool registry and SSH sessions
+    #[default]
+    Both,
+}
+
+impl HttpTransports {
+    /// Parse an `MCP_TRANSPORT` value.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "streamable_http" | "streamable-http" | "http" => Ok(Self::StreamableHttp),
+            "sse" => Ok(Self::Sse),
+            "both" | "all" => Ok(Self::Both),
+            other => Err(format!(
+                "Unknown transport '{}'. Use 'streamable_http', 'sse' or 'both'",
+                other
+            )),
+        }
+    }
+
+    /// Read `MCP_TRANSPORT`, falling back to both on an unset or invalid value.
+    pub fn from_env() -> Self {
+        match std::env::var(MCP_TRANSPORT_ENV_VAR) {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|e| {
+                warn!("{}: {}; serving both transports", MCP_TRANSPORT_ENV_VAR, e);
+                Self::Both
+            }),
+            Err(_) => Self::Both,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::StreamableHttp => "streamable_http",
+            Self::Sse => "sse",
+            Self::Both => "both",
+        }
+    }
+
+    /// Whether the streamable HTTP route is served.
+    pub fn streamable_http(self) -> bool {
+        self != Self::Sse
+    }
+
+    /// Whether the SSE routes are served.
+    pub fn sse(self) -> bool {
+        self != Self::StreamableHttp
+    }
+}
+
+/// How this process serves MCP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Serving {
+    /// The `ssh-mcp-stdio` binary
+    Stdio,
+    /// The `ssh-mcp` binary with these HTTP transports
+    Http(HttpTransports),
+}
+
+impl Serving {
+    /// Names of the transports served, as reported by `ssh_capabilities`.
+    pub fn transports(self) -> Vec<&'static str> {
+        match self {
+            Self::Stdio => vec!["stdio"],
+            Self::Http(transports) => [
+                transports.streamable_http().then_some("streamable_http"),
+                transports.sse().then_some("sse"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        }
+    }
+}
+
+/// Serving mode recorded by the binary.
+static SERVING: OnceCell<Serving> = OnceCell::new();
+
+/// Record how this process serves MCP; later calls are ignored.
+pub fn set_serving(mode: Serving) {
+    let _ = SERVING.set(mode);
+}
+
+/// How this process serves MCP; HTTP per `MCP_TRANSPORT` until recorded.
+pub(crate) fn serving() -> Serving {
+    SERVING
+        .get()
+        .copied()
+        .unwrap_or_else(|| Serving::Http(HttpTransports::from_env()))
+}
+
+/// Tools taking an `agent_id` argument.
+static AGENT_TOOLS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    McpSSHCommands::list()
+        .into_iter()
+        .filter(|tool| tool.input_schema["properties"].get("agent_id").is_some())
+        .map(|tool| tool.name)
+        .collect()
+});
+
+/// Make a call to `tool` act as `agent_id`, the agent the client is bound to.
+///
+/// Fills in a missing `agent_id` argument and refuses a different one.
+fn bind_agent(tool: &str, arguments: &mut Value, agent_id: &str) -> Result<(), String> {
+    if !AGENT_TOOLS.contains(tool) {
+        return Ok(());
+    }
+    if arguments.is_null() {
+        *arguments = json!({});
+    }
+    // Anything else is rejected by the tool's own argument parsing
+    let Some(arguments) = arguments.as_object_mut() else {
+        return Ok(());
+    };
+    match arguments.get("agent_id") {
+        Some(Value::String(given)) if given != agent_id => Err(format!(
+            "agent_id '{}' does not match agent '{}' this client's token is bound to",
+            given, agent_id
+        )),
+        _ => {
+            arguments.insert("agent_id".to_string(), json!(agent_id));
+            Ok(())
+        }
+    }
+}
+
+/// Request handler shared by all transports.
+pub struct McpHandler {
+    server: McpServer<McpSSHCommands>,
+    exposure: ToolExposure,
+    notifications: NotificationSink,
+    receiver: Option<mpsc::Receiver<Value>>,
+    /// Agent every tool call acts as (a token bound to an agent)
+    bound_agent: Option<String>,
+}
+
+impl McpHandler {
+    /// Create a handler serving the SSH tools allowed by the server configuration.
+    pub fn new() -> Self {
+        managed::start();
+        health::start();
+        persistent::start();
+        pool::start();
+        reaper::start();
+        let tools_config = &server_config().tools;
+        let exposure = ToolExposure::from_config(tools_config)
+            .with_read_only(resolve_read_only(None))
+            .with_prefix(resolve_tool_prefix(tools_config.prefix.as_deref()));
+        let (notifications, receiver) = NotificationSink::channel();
+        Self {
+            server: McpServer::new()
+                .tools(McpSSHCommands::new())
+                .disable_tools(exposure.disabled())
+                .with_server_info("ssh-mcp", env!("CARGO_PKG_VERSION")),
+            exposure,
+            notifications,
+            receiver: Some(receiver),
+            bound_agent: None,
+        }
+    }
+
+    /// Serve the client authenticated as `principal`; a token bound to an
+    /// agent makes every tool call act as that agent.
+    pub fn with_principal(mut self, principal: Option<Principal>) -> Self {
+        self.bound_agent = principal.and_then(|principal| principal.agent_id);
+        self
+    }
+
+    /// Take the receiver of server-to-client notifications; `None` after the first call.
+    pub fn take_notifications(&mut self) -> Option<mpsc::Receiver<Value>> {
+        self.receiver.take()
+    }
+
+    /// The client's queue, for routing its answers to server requests.
+    pub(crate) fn client(&self) -> NotificationSink {
+        self.notifications.clone()
+    }
+
+    /// Handle a single JSON-RPC request, returning the response if one is expected.
+    pub async fn handle_request(&mut self, mut request: Request) -> Option<Response<Value>> {
+        let is_tools_list = matches!(request.body, Requests::ToolsList { .. });
+        let is_initialize = matches!(request.body, Requests::Initialize { .. });
+
+        if let Requests::ToolsCall { params } = &mut request.body {
+            let error = match self.exposure.internal_name(&params.name) {
+                // The library only hides disabled tools from tools/list; reject calls too
+                Some(name) if !self.exposure.is_enabled(name) => Some(RpcError::method_not_found(
+                    format!("tool disabled by server configuration: {}", params.name),
+                )),
+                Some(name) => {
+                    params.name = name.to_string();
+                    self.bound_agent.as_deref().and_then(|agent_id| {
+                        bind_agent(&params.name, &mut params.arguments, agent_id)
+                            .err()
+                            .map(RpcError::invalid_params)
+                    })
+                }
+                None => Some(RpcError::method_not_found(format!(
+                    "method not found: {}",
+                    params.name
+                ))),
+            };
+
+            if let Some(error) = error {
+                return Some(Response {
+                    jsonrpc: JSON_RPC_VERSION.to_string(),
+                    id: request.id,
+                    result: None,
+                    error: Some(error),
+                });
+            }
+        }
+
+        let request = notify::with_client(
+            self.notifications.clone(),
+            self.server.handle_request(request),
+        );
+        let mut response = match self.bound_agent.clone() {
+            Some(agent_id) => access::with_bound_agent(agent_id, request).await,
+            None => request.await,
+        }?;
+
+        // Shell output subscriptions arrive as logging notifications
+        if is_initialize
+            && let Some(capabilities) = response
+                .result
+                .as_mut()
+                .and_then(|result| result.get_mut("capabilities"))
+                .and_then(Value::as_object_mut)
+        {
+            capabilities.insert("logging".to_string(), json!({}));
+        }
+
+        if is_tools_list && let Some(result) = response.result.as_mut() {
+            // Annotations are keyed by internal name, so annotate before prefixing
+            annotate_tools_list(result);
+            enrich_tools_list(result);
+            self.exposure.prefix_tools_list(result);
+        }
+
+        Some(response)
+    }
+}
+
+impl Default for McpHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_transports() {
+        assert_eq!(
+            HttpTransports::parse("streamable_http"),
+            Ok(HttpTransports::StreamableHttp)
+        );
+        assert_eq!(
+            HttpTransports::parse("HTTP"),
+            Ok(HttpTransports::StreamableHttp)
+        );
+        assert_eq!(HttpTransports::parse(" sse "), Ok(HttpTransports::Sse));
+        assert_eq!(HttpTransports::parse("both"), Ok(HttpTransports::Both));
+        assert!(HttpTransports::parse("websocket").is_err());
+    }
+
+    #[test]
+    fn test_served_routes() {
+        assert!(HttpTransports::Both.streamable_http() && HttpTransports::Both.sse());
+        assert!(!HttpTransports::Sse.streamable_http());
+        assert!(!HttpTransports::StreamableHttp.sse());
+        assert_eq!(HttpTransports::default(), HttpTransports::Both);
+    }
+
+    #[test]
+    fn test_serving_transports() {
+        assert_eq!(Serving::Stdio.transports(), vec!["stdio"]);
+        assert_eq!(
+            Serving::Http(HttpTransports::Both).transports(),
+            vec!["streamable_http", "sse"]
+        );
+        assert_eq!(Serving::Http(HttpTransports::Sse).transports(), vec!["sse"]);
+        assert_eq!(
+            Serving::Http(HttpTransports::StreamableHttp).transports(),
+            vec!["streamable_http"]
+        );
+    }
+
+    #[test]
+    fn test_bind_agent() {
+        let mut arguments = Value::Null;
+        bind_agent("ssh_list_sessions", &mut arguments, "ci").unwrap();
+        assert_eq!(arguments, json!({"agent_id": "ci"}));
+
+        let mut arguments = json!({"session_id": "s1", "agent_id": "ci"});
+        bind_agent("ssh_execute", &mut arguments, "ci").unwrap();
+        let mut arguments = json!({"session_id": "s1", "agent_id": "deploy"});
+        assert!(bind_agent("ssh_execute", &mut arguments, "ci").is_err());
+
+        // Tools without the parameter are left alone
+        let mut arguments = json!({});
+        bind_agent("ssh_list_managed_hosts", &mut arguments, "ci").unwrap();
+        assert_eq!(arguments, json!({}));
+    }
+}
@@ -0,0 +1,228 @@
+//! HTTP+SSE transport (MCP protocol revision 2024-11-05).
+//!
+//! For clients that predate streamable HTTP. Two routes share one session
+//! table:
+//!
+//! - `GET /sse` creates an MCP session and opens its event stream. The first
+//!   event is `endpoint`, whose data is the URL to post messages to
+//!   (`/messages?sessionId=<id>`); responses and notifications follow as
+//!   `message` events
+//! - `POST /messages?sessionId=<id>` takes a JSON-RPC message (or batch) and
+//!   answers `202 Accepted`; the response arrives on the event stream. The
+//!   client answers server requests (e.g. an elicitation) on this route too
+//!
+//! A session lives as long as its event stream: closing the stream ends it.
+//! With [`token_auth`](super::token_auth) on, messages are only accepted from
+//! the token that opened the stream.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use poem::http::StatusCode;
+use poem::web::sse::{Event, SSE};
+use poem::web::{Data, Json, Query};
+use poem::{EndpointExt, IntoResponse, Request, Route, get, handler, post};
+use poem_mcpserver::protocol::rpc::BatchRequest;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::{Mutex, mpsc};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+use super::McpHandler;
+use super::token_auth::{Principal, principal};
+use crate::mcp::notify::NotificationSink;
+
+/// Route of the event stream
+pub const SSE_PATH: &str = "/sse";
+
+/// Route clients post messages to
+pub const MESSAGES_PATH: &str = "/messages";
+
+/// Keep-alive interval of the event stream
+const KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Responses queued for a stream that is not being read
+const RESPONSE_QUEUE: usize = 64;
+
+type HandlerFactory = Box<dyn Fn(&Request) -> McpHandler + Send + Sync>;
+
+struct SseSession {
+    handler: Arc<Mutex<McpHandler>>,
+    /// Routes the client's answers past a handler busy with a tool call
+    client: NotificationSink,
+    responses: mpsc::Sender<Value>,
+    /// Token that opened the stream
+    principal: Option<Principal>,
+}
+
+struct State {
+    factory: HandlerFactory,
+    sessions: StdMutex<HashMap<String, SseSession>>,
+}
+
+impl State {
+    fn lock_sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, SseSession>> {
+        // A poisoned map only means another request panicked; the data is still usable
+        self.sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Removes the session when its event stream is dropped.
+struct SessionGuard {
+    state: Arc<State>,
+    session_id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.state.lock_sessions().remove(&self.session_id);
+        info!(session_id = self.session_id, "closed MCP SSE session");
+    }
+}
+
+/// URL of the message route for `session_id`, sent in the `endpoint` event.
+fn message_endpoint(session_id: &str) -> String {
+    format!("{}?sessionId={}", MESSAGES_PATH, session_id)
+}
+
+fn message_event(message: &Value) -> Event {
+    Event::message(serde_json::to_string(message).unwrap_or_default()).event_type("message")
+}
+
+#[handler]
+async fn sse_handler(state: Data<&Arc<State>>, request: &Request) -> poem::Response {
+    let session_id = Uuid::new_v4().simple().to_string();
+    let mut handler = (state.factory)(request);
+    let Some(notifications) = handler.take_notifications() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let (responses_tx, responses) = mpsc::channel(RESPONSE_QUEUE);
+
+    state.lock_sessions().insert(
+        session_id.clone(),
+        SseSession {
+            client: handler.client(),
+            handler: Arc::new(Mutex::new(handler)),
+            responses: responses_tx,
+            principal: principal(request),
+        },
+    );
+    info!(session_id = session_id, "created MCP SSE session");
+
+    let guard = SessionGuard {
+        state: state.0.clone(),
+        session_id: session_id.clone(),
+    };
+    let endpoint = futures::stream::once(async move {
+        Event::message(message_endpoint(&session_id)).event_type("endpoint")
+    });
+    let messages = futures::stream::unfold(
+        (responses, notifications, guard),
+        |(mut responses, mut notifications, guard)| async move {
+            let message = tokio::select! {
+                Some(message) = responses.recv() => message,
+                Some(message) = notifications.recv() => message,
+                else => return None,
+            };
+            Some((message_event(&message), (responses, notifications, guard)))
+        },
+    );
+    SSE::new(futures::StreamExt::chain(endpoint, messages))
+        .keep_alive(KEEP_ALIVE)
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+#[handler]
+async fn message_handler(
+    state: Data<&Arc<State>>,
+    request: &Request,
+    query: Query<MessageQuery>,
+    message: Json<Value>,
+) -> StatusCode {
+    let session_id = query.0.session_id;
+    let (handler, client, responses) = {
+        let sessions = state.lock_sessions();
+        // Sessions of other tokens look unknown
+        let Some(session) = sessions
+            .get(&session_id)
+            .filter(|session| session.principal == principal(request))
+        else {
+            return StatusCode::NOT_FOUND;
+        };
+        (
+            session.handler.clone(),
+            session.client.clone(),
+            session.responses.clone(),
+        )
+    };
+    let Some(message) = client.intercept(message.0) else {
+        return StatusCode::ACCEPTED;
+    };
+    let Ok(batch_request) = serde_json::from_value::<BatchRequest>(message) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    // Tool calls may run for minutes; answer now and stream the responses
+    tokio::spawn(async move {
+        for request in batch_request {
+            debug!(session_id = session_id, request = ?request, "received request");
+            let Some(response) = handler.lock().await.handle_request(request).await else {
+                continue;
+            };
+            let message = match serde_json::to_value(&response) {
+                Ok(message) => message,
+                Err(e) => {
+                    error!("Failed to serialize response: {}", e);
+                    continue;
+                }
+            };
+            if responses.send(message).await.is_err() {
+                debug!(session_id = session_id, "SSE stream closed before response");
+                break;
+            }
+        }
+    });
+    StatusCode::ACCEPTED
+}
+
+/// Add the SSE transport routes ([`SSE_PATH`], [`MESSAGES_PATH`]) to `route`.
+///
+/// `factory` builds a fresh [`McpHandler`] for every event stream.
+pub fn mount<F>(route: Route, factory: F) -> Route
+where
+    F: Fn(&Request) -> McpHandler + Send + Sync + 'static,
+{
+    let state = Arc::new(State {
+        factory: Box::new(factory),
+        sessions: StdMutex::new(HashMap::new()),
+    });
+
+    route
+        .at(SSE_PATH, get(sse_handler).data(state.clone()))
+        .at(MESSAGES_PATH, post(message_handler).data(state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_endpoint() {
+        assert_eq!(message_endpoint("abc123"), "/messages?sessionId=abc123");
+    }
+
+    #[test]
+    fn test_message_query_uses_camel_case() {
+        let query: MessageQuery = serde_json::from_str(r#"{"sessionId":"abc123"}"#).unwrap();
+        assert_eq!(query.session_id, "abc123");
+    }
+}
@@ -0,0 +1,94 @@
+//! Standard input/output transport.
+//!
+//! Reads one JSON-RPC message (or batch) per line from stdin and writes one
+//! response per line to stdout. Notifications are written to stdout as they
+//! are queued, between responses. Logs must go to stderr.
+//!
+//! Stdin is read by its own task, so the client's answer to a server request
+//! (e.g. an elicitation) gets through while the request waiting for it is
+//! still being handled.
+
+use poem_mcpserver::protocol::JSON_RPC_VERSION;
+use poem_mcpserver::protocol::rpc::{BatchRequest, Response, RpcError};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+use super::{McpHandler, Serving, set_serving};
+
+fn print_response(response: impl Serialize) {
+    match serde_json::to_string(&response) {
+        Ok(json) => println!("{}", json),
+        Err(e) => error!("Failed to serialize response: {}", e),
+    }
+}
+
+/// Serve MCP requests over stdin/stdout until stdin is closed.
+pub async fn serve(mut handler: McpHandler) -> std::io::Result<()> {
+    set_serving(Serving::Stdio);
+    let mut input = BufReader::new(tokio::io::stdin()).lines();
+    let client = handler.client();
+    let (requests_tx, mut requests) = mpsc::channel::<Value>(1);
+    let reader = tokio::spawn(async move {
+        while let Some(line) = input.next_line().await? {
+            debug!(request = &line, "received request");
+
+            let message = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    error!("Failed to parse request: {}", e);
+                    continue;
+                }
+            };
+            if let Some(message) = client.intercept(message)
+                && requests_tx.send(message).await.is_err()
+            {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    info!("stdio server started");
+
+    if let Some(mut notifications) = handler.take_notifications() {
+        tokio::spawn(async move {
+            while let Some(notification) = notifications.recv().await {
+                print_response(notification);
+            }
+        });
+    }
+
+    while let Some(message) = requests.recv().await {
+        let batch_request = match serde_json::from_value::<BatchRequest>(message) {
+            Ok(batch_request) => batch_request,
+            Err(e) => {
+                error!("Failed to parse request: {}", e);
+                continue;
+            }
+        };
+
+        for request in batch_request {
+            if request.jsonrpc != JSON_RPC_VERSION {
+                print_response(Response::<()> {
+                    jsonrpc: JSON_RPC_VERSION.to_string(),
+                    id: request.id,
+                    result: None,
+                    error: Some(RpcError::invalid_request(
+                        "invalid JSON-RPC version, expected `2.0`",
+                    )),
+                });
+                continue;
+            }
+
+            if let Some(response) = handler.handle_request(request).await {
+                debug!(response = ?response, "sending response");
+                print_response(response);
+            }
+        }
+    }
+
+    reader.await.map_err(std::io::Error::other)?
+}
@@ -0,0 +1,282 @@
+//! Bearer token authentication of the HTTP transports.
+//!
+//! Once a token is configured, every request to the `ssh-mcp` HTTP server
+//! must carry one in `Authorization: Bearer <token>` or `X-API-Key: <token>`;
+//! others are answered `401 Unauthorized`. Tokens come from
+//! [`AUTH_TOKEN_ENV_VAR`] and from `[[auth.tokens]]` entries of the server
+//! configuration, which name the environment variable holding each token:
+//!
+//! ```toml
+//! [[auth.tokens]]
+//! name = "ci"
+//! token_env = "MCP_TOKEN_CI"
+//! agent_id = "ci-runner"
+//! ```
+//!
+//! A token with an `agent_id` is bound to that agent: its tool calls act as
+//! the agent whatever `agent_id` they pass, so the client only reaches its
+//! own sessions and shared ones (see `access`). An MCP session stays with the
+//! token that opened it; requests for it with another token get `404`.
+//!
+//! The stdio binary serves a single local client and is not affected.
+
+use std::sync::Arc;
+
+use poem::http::{HeaderMap, StatusCode, header};
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::mcp::config::{AuthConfig, server_config};
+
+/// Token accepted by the HTTP transports, not bound to any agent
+pub const AUTH_TOKEN_ENV_VAR: &str = "MCP_AUTH_TOKEN";
+
+/// Header carrying an API key, as an alternative to `Authorization`
+const API_KEY_HEADER: &str = "X-API-Key";
+
+/// The client a request was authenticated as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// Name of the token (`MCP_AUTH_TOKEN` or the `[[auth.tokens]]` name)
+    pub name: String,
+    /// Agent the token is bound to
+    pub agent_id: Option<String>,
+}
+
+/// An accepted token, kept as its SHA-256 digest.
+#[derive(Debug)]
+struct Token {
+    digest: [u8; 32],
+    principal: Principal,
+}
+
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+/// Middleware requiring one of the configured tokens.
+#[derive(Debug, Clone)]
+pub struct TokenAuth {
+    tokens: Arc<Vec<Token>>,
+}
+
+impl TokenAuth {
+    /// Tokens of `config` and `env_token`, or `None` when neither configures any.
+    ///
+    /// Entries whose variable is unset or empty are skipped with a warning;
+    /// authentication stays required even when none is left.
+    fn new(config: &AuthConfig, env_token: Option<String>) -> Option<Self> {
+        let env_token = env_token.filter(|token| !token.is_empty());
+        if env_token.is_none() && config.tokens.is_empty() {
+            return None;
+        }
+        let mut tokens = Vec::new();
+        if let Some(token) = env_token {
+            tokens.push(Token {
+                digest: digest(&token),
+                principal: Principal {
+                    name: AUTH_TOKEN_ENV_VAR.to_string(),
+                    agent_id: None,
+                },
+            });
+        }
+        for entry in &config.tokens {
+            match std::env::var(&entry.token_env) {
+                Ok(token) if !token.is_empty() => tokens.push(Token {
+                    digest: digest(&token),
+                    principal: Principal {
+                        name: entry.name.clone(),
+                        agent_id: entry.agent_id.clone(),
+                    },
+                }),
+                _ => warn!(
+                    "Auth token '{}': {} is not set; the token is not accepted",
+                    entry.name, entry.token_env
+                ),
+            }
+        }
+        Some(Self {
+            tokens: Arc::new(tokens),
+        })
+    }
+
+    /// Tokens of the server configuration and `MCP_AUTH_TOKEN`, or `None` when
+    /// the HTTP transports are open.
+    pub fn from_env() -> Option<Self> {
+        Self::new(
+            &server_config().auth,
+            std::env::var(AUTH_TOKEN_ENV_VAR).ok(),
+        )
+    }
+
+    /// Number of accepted tokens.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// The principal of the token presented in `headers`.
+    fn authenticate(&self, headers: &HeaderMap) -> Option<&Principal> {
+        let presented = presented_token(headers)?;
+        let presented = digest(presented);
+        self.tokens
+            .iter()
+            .find(|token| token.digest == presented)
+            .map(|token| &token.principal)
+    }
+}
+
+/// The token of a request: `Authorization: Bearer`, else `X-API-Key`.
+fn presented_token(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let (scheme, token) = value.trim().split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        });
+    bearer
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        })
+        .filter(|token| !token.is_empty())
+}
+
+/// The principal a request was authenticated as; `None` when authentication is off.
+pub fn principal(request: &Request) -> Option<Principal> {
+    request.data::<Principal>().cloned()
+}
+
+impl<E: Endpoint> Middleware<E> for TokenAuth {
+    type Output = TokenAuthEndpoint<E>;
+
+    fn transform(&self, inner: E) -> Self::Output {
+        TokenAuthEndpoint {
+            inner,
+            auth: self.clone(),
+        }
+    }
+}
+
+/// Endpoint wrapped by [`TokenAuth`].
+pub struct TokenAuthEndpoint<E> {
+    inner: E,
+    auth: TokenAuth,
+}
+
+impl<E: Endpoint> Endpoint for TokenAuthEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut request: Request) -> poem::Result<Self::Output> {
+        let Some(principal) = self.auth.authenticate(request.headers()).cloned() else {
+            warn!(
+                "Rejected unauthenticated {} {} from {}",
+                request.method(),
+                request.uri().path(),
+                request.remote_addr()
+            );
+            return Ok(StatusCode::UNAUTHORIZED
+                .with_header(header::WWW_AUTHENTICATE, "Bearer")
+                .into_response());
+        };
+        request.set_data(principal);
+        self.inner
+            .call(request)
+            .await
+            .map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::config::AuthTokenConfig;
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_off_without_tokens() {
+        assert!(TokenAuth::new(&AuthConfig::default(), None).is_none());
+        assert!(TokenAuth::new(&AuthConfig::default(), Some(String::new())).is_none());
+    }
+
+    #[test]
+    fn test_bearer_and_api_key() {
+        let auth = TokenAuth::new(&AuthConfig::default(), Some("s3cret".to_string())).unwrap();
+        let principal = auth
+            .authenticate(&headers("authorization", "Bearer s3cret"))
+            .unwrap();
+        assert_eq!(principal.name, AUTH_TOKEN_ENV_VAR);
+        assert!(principal.agent_id.is_none());
+        assert!(
+            auth.authenticate(&headers("authorization", "bearer  s3cret "))
+                .is_some()
+        );
+        assert!(auth.authenticate(&headers("x-api-key", "s3cret")).is_some());
+
+        assert!(auth.authenticate(&HeaderMap::new()).is_none());
+        assert!(
+            auth.authenticate(&headers("authorization", "Bearer wrong"))
+                .is_none()
+        );
+        assert!(
+            auth.authenticate(&headers("authorization", "Basic s3cret"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_configured_tokens_bind_agents() {
+        let var = format!("SSH_MCP_TEST_TOKEN_{}", uuid::Uuid::new_v4().simple());
+        let config = AuthConfig {
+            tokens: vec![
+                AuthTokenConfig {
+                    name: "ci".to_string(),
+                    token_env: var.clone(),
+                    agent_id: Some("ci-runner".to_string()),
+                },
+                AuthTokenConfig {
+                    name: "ops".to_string(),
+                    token_env: format!("{}_UNSET", var),
+                    agent_id: None,
+                },
+            ],
+        };
+        // SAFETY: The variable name is unique to this test
+        unsafe {
+            std::env::set_var(&var, "ci-token");
+        }
+        let auth = TokenAuth::new(&config, None).unwrap();
+        // SAFETY: The variable name is unique to this test
+        unsafe {
+            std::env::remove_var(&var);
+        }
+        assert_eq!(auth.len(), 1);
+        let principal = auth
+            .authenticate(&headers("x-api-key", "ci-token"))
+            .unwrap();
+        assert_eq!(principal.agent_id.as_deref(), Some("ci-runner"));
+
+        // Required even when no configured token is usable
+        let auth = TokenAuth::new(
+            &AuthConfig {
+                tokens: config.tokens[1..].to_vec(),
+            },
+            None,
+        )
+        .unwrap();
+        assert!(auth.is_empty());
+        assert!(auth.authenticate(&headers("x-api-key", "")).is_none());
+    }
+}
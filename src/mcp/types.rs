@@ -4,67 +4,1527 @@
 //! All types implement `Serialize`, `Deserialize`, and `JsonSchema` for proper
 //! MCP protocol compatibility.
 
+use std::collections::BTreeMap;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use super::error::ErrorCode;
+
 /// Session metadata for tracking connection information
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-pub struct SessionInfo {
+pub struct SessionInfo {
+    pub session_id: String,
+    /// Optional human-readable name for the session (useful for LLM identification)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Optional agent identifier for grouping sessions by agent (the session owner)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    /// Access granted to other agents; absent when the session is private to its owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared: Option<ShareMode>,
+    pub host: String,
+    pub username: String,
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub connected_at: String,
+    /// Default timeout in seconds used for this session's connection
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub default_timeout_secs: u64,
+    /// Number of retry attempts needed to establish the connection
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub retry_attempts: u32,
+    /// Whether compression is enabled for this session
+    pub compression_enabled: bool,
+    /// zlib level used when compression is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub compression_level: Option<u32>,
+    /// Seconds between SSH keepalives while the server is silent (0: disabled)
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub keepalive_interval_secs: u64,
+    /// Unanswered keepalives after which the connection is dropped (0: never)
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub keepalive_max: u32,
+    /// Key exchange, host key, cipher and MAC algorithms in use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithms: Option<NegotiatedAlgorithms>,
+    /// Timestamp of last health check (RFC3339 format)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_datetime")]
+    pub last_health_check: Option<String>,
+    /// Whether session passed last health check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub healthy: Option<bool>,
+    /// Most recent health-check results, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub health_history: Vec<HealthCheck>,
+    /// Recent health checks alternated between passing and failing; do not
+    /// rely on this session for long-running work
+    #[serde(default)]
+    pub flapping: bool,
+    /// Effective remote identity reported by the post-connect probe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<RemoteIdentity>,
+    /// Most recent SSH round-trip time in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f64>,
+    /// Smoothed round-trip time in milliseconds (exponential moving average)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_avg_ms: Option<f64>,
+    /// Region hint given at connect time (e.g., "eu-west-1")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Datacenter hint given at connect time (e.g., "dub-2")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datacenter: Option<String>,
+    /// Jump hosts the connection is tunneled through, first hop first (`user@host:port`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jump_hosts: Vec<String>,
+    /// The host is being rebooted by ssh_reboot_and_wait; the session comes
+    /// back under the same session_id
+    #[serde(default)]
+    pub rebooting: bool,
+    /// Mutating commands and write tools are refused (ssh_connect `read_only` or SSH_READ_ONLY)
+    #[serde(default)]
+    pub read_only: bool,
+    /// Commands and shells get the local ssh-agent forwarded (ssh_connect `agent_forwarding`)
+    #[serde(default)]
+    pub agent_forwarding: bool,
+    /// Profile the session was opened from (ssh_connect `profile`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Default timeout of commands run on this session, used when a call gives none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub command_timeout_secs: Option<u64>,
+    /// Channel bytes sent on this session (commands input, shells, uploads, forwards)
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes_sent: u64,
+    /// Channel bytes received on this session (command output, shells, downloads, forwards)
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes_received: u64,
+    /// Commands, shells, output and forwards of this session
+    #[serde(default)]
+    pub stats: SessionStats,
+    /// Labels for selecting the session (ssh_connect `labels`, ssh_update_session)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Activity counters of a session
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SessionStats {
+    /// Commands started with ssh_execute, ssh_execute_batch and ssh_execute_dag
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub commands_executed: u64,
+    /// Interactive shells opened
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub shells_opened: u64,
+    /// Bytes of command output (stdout and stderr) collected
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub output_bytes: u64,
+    /// Local and reverse port forwards established
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub forwards_established: u64,
+    /// Last use of the connection (RFC3339): channel data, a channel opened or a command exiting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_datetime")]
+    pub last_activity: Option<String>,
+}
+
+/// Activity counted in [`SessionStats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionActivity {
+    CommandExecuted,
+    ShellOpened,
+    /// Bytes of command output collected
+    Output(usize),
+    ForwardEstablished,
+}
+
+/// One intermediate host of an ssh_connect `jump_hosts` chain (like ProxyJump)
+///
+/// Not `Debug` or `Serialize`: it carries credentials.
+#[derive(Clone, Deserialize, JsonSchema)]
+pub struct JumpHost {
+    /// Jump host address in format "host:port" (port defaults to 22), as reachable from the previous hop
+    pub address: String,
+    /// Username on the jump host (default: the target's username)
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Password for the jump host (optional if using key or agent)
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Path to a private key file for the jump host (optional)
+    #[serde(default)]
+    pub key_path: Option<String>,
+    /// Private key content for the jump host, OpenSSH or PEM (optional)
+    #[serde(default)]
+    pub key_data: Option<String>,
+}
+
+impl JumpHost {
+    /// `user@address` label of the hop, using `default_user` when no username is set.
+    pub fn label(&self, default_user: &str) -> String {
+        format!(
+            "{}@{}",
+            self.username.as_deref().unwrap_or(default_user),
+            self.address
+        )
+    }
+}
+
+/// Requested location of a session; every given field must match
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SessionAffinity {
+    /// Region the session was tagged with at connect time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Datacenter the session was tagged with at connect time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datacenter: Option<String>,
+}
+
+/// Response from ssh_select_session
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshSelectSessionResponse {
+    /// Best matching session; use it as session_id
+    pub session_id: String,
+    pub session: SessionInfo,
+    /// Other matching sessions, best first
+    pub alternatives: Vec<String>,
+    pub message: String,
+}
+
+/// Access other agents have to a shared session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareMode {
+    /// Visible and reusable, but commands, shells and forwards are reserved to the owner
+    ReadOnly,
+    /// Same access as the owner
+    Full,
+}
+
+/// Algorithms a connection negotiated in its last key exchange
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct NegotiatedAlgorithms {
+    /// Key exchange, e.g. "curve25519-sha256"
+    pub kex: String,
+    /// Server host key algorithm, e.g. "ssh-ed25519"
+    pub host_key: String,
+    /// Cipher, e.g. "chacha20-poly1305@openssh.com"
+    pub cipher: String,
+    /// MAC (not used by AEAD ciphers such as chacha20-poly1305 and AES-GCM)
+    pub mac: String,
+}
+
+/// One health-check result of a session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct HealthCheck {
+    /// When the check ran (RFC3339)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub at: String,
+    /// Whether the session answered
+    pub healthy: bool,
+}
+
+/// Weight of a new sample in the smoothed RTT (same as TCP's SRTT gain)
+const RTT_SMOOTHING: f64 = 0.125;
+
+/// Health-check results kept per session
+pub(crate) const HEALTH_HISTORY_LEN: usize = 10;
+
+/// Passing/failing changes within the history that mark a session as flapping
+const FLAP_TRANSITIONS: usize = 3;
+
+impl SessionInfo {
+    /// Record a round-trip time sample and update the smoothed average.
+    pub fn record_rtt(&mut self, rtt: std::time::Duration) {
+        let sample = round_ms(rtt.as_secs_f64() * 1000.0);
+        self.rtt_avg_ms = Some(match self.rtt_avg_ms {
+            Some(avg) => round_ms(avg + RTT_SMOOTHING * (sample - avg)),
+            None => sample,
+        });
+        self.rtt_ms = Some(sample);
+    }
+
+    /// Record a health-check result, keeping the last [`HEALTH_HISTORY_LEN`].
+    pub fn record_health(&mut self, at: String, healthy: bool) {
+        if self.health_history.len() == HEALTH_HISTORY_LEN {
+            self.health_history.remove(0);
+        }
+        self.health_history.push(HealthCheck {
+            at: at.clone(),
+            healthy,
+        });
+        self.last_health_check = Some(at);
+        self.healthy = Some(healthy);
+        let transitions = self
+            .health_history
+            .windows(2)
+            .filter(|pair| pair[0].healthy != pair[1].healthy)
+            .count();
+        self.flapping = transitions >= FLAP_TRANSITIONS;
+    }
+
+    /// Count `activity` in the session's stats.
+    pub fn record_activity(&mut self, activity: SessionActivity) {
+        let stats = &mut self.stats;
+        match activity {
+            SessionActivity::CommandExecuted => stats.commands_executed += 1,
+            SessionActivity::ShellOpened => stats.shells_opened += 1,
+            SessionActivity::Output(bytes) => stats.output_bytes += bytes as u64,
+            SessionActivity::ForwardEstablished => stats.forwards_established += 1,
+        }
+    }
+}
+
+/// Round milliseconds to two decimals for readable output.
+pub(crate) fn round_ms(ms: f64) -> f64 {
+    (ms * 100.0).round() / 100.0
+}
+
+/// Effective user on the remote host, as reported by `id` and `hostname`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RemoteIdentity {
+    /// Effective user name
+    pub user: String,
+    /// Effective numeric user id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub uid: Option<u32>,
+    /// Group names the user belongs to
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Remote host name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Whether the session runs with root privileges
+    pub is_root: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshConnectResponse {
+    pub session_id: String,
+    /// Agent ID echoed back for LLM memory (if provided during connect)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    pub message: String,
+    pub authenticated: bool,
+    /// Number of retry attempts needed to establish the connection
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub retry_attempts: u32,
+    /// Effective remote identity (user, uid, groups, hostname)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<RemoteIdentity>,
+    /// Reverse forwards of the previous connection re-established on this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reverse_forwards: Vec<ReverseForwardInfo>,
+    /// Whether an idle pooled connection was reused instead of dialing
+    #[serde(default)]
+    pub pooled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub(crate) struct SshCommandResponse {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    /// Whether the command timed out (partial output may be available)
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+/// Port forwarding response (only functional when port_forward feature is enabled)
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PortForwardingResponse {
+    /// Forward ID for ssh_list_forwards and ssh_cancel_forward
+    pub forward_id: String,
+    pub local_address: String,
+    pub remote_address: String,
+    pub active: bool,
+}
+
+/// A local listener whose connections are forwarded through a session
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ForwardInfo {
+    pub forward_id: String,
+    pub session_id: String,
+    /// Address the MCP server listens on (`127.0.0.1:port`)
+    pub local_address: String,
+    /// Destination as resolved by the SSH server (`host:port`)
+    pub remote_address: String,
+    /// When the listener was started (RFC3339)
+    pub created_at: String,
+    /// Connections forwarded so far
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub connections: u64,
+    /// Bandwidth cap shared by the forwarded connections, in bytes per second per direction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub rate_limit_bytes_per_sec: Option<u64>,
+}
+
+/// Response from ssh_list_forwards
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshListForwardsResponse {
+    pub forwards: Vec<ForwardInfo>,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+}
+
+/// Response from ssh_cancel_forward
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCancelForwardResponse {
+    pub forward_id: String,
+    pub session_id: String,
+    /// Listener that was closed (`127.0.0.1:port`)
+    pub local_address: String,
+    pub message: String,
+}
+
+/// A remote listener whose connections are bridged back to the MCP server
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReverseForwardInfo {
+    pub session_id: String,
+    /// Address and port the SSH server listens on (`bind_address:port`)
+    pub remote_address: String,
+    /// Port the SSH server listens on, as bound
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub remote_port: u16,
+    /// Where forwarded connections go, resolved on the MCP server
+    pub local_address: String,
+    /// When the listener was established (RFC3339)
+    pub created_at: String,
+    /// Connections forwarded so far
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub connections: u64,
+    /// Bandwidth cap shared by the forwarded connections, in bytes per second per direction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub rate_limit_bytes_per_sec: Option<u64>,
+}
+
+/// Response from ssh_reverse_forward
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshReverseForwardResponse {
+    #[serde(flatten)]
+    pub forward: ReverseForwardInfo,
+    pub message: String,
+}
+
+/// Response from ssh_cancel_reverse_forward
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCancelReverseForwardResponse {
+    pub session_id: String,
+    /// Listener that was removed (`bind_address:port`)
+    pub remote_address: String,
+    /// Whether the server confirmed the cancel-tcpip-forward request
+    pub confirmed: bool,
+    pub message: String,
+}
+
+/// Response from ssh_list_reverse_forwards
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshListReverseForwardsResponse {
+    pub forwards: Vec<ReverseForwardInfo>,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+}
+
+/// TCP reachability of a port as seen from the remote host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PortState {
+    /// A connection was accepted
+    Open,
+    /// The connection was actively refused
+    Closed,
+    /// No answer before the timeout (dropped by a firewall or host down)
+    Filtered,
+}
+
+/// Response from ssh_check_port
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCheckPortResponse {
+    /// Session the check ran through
+    pub session_id: String,
+    /// Target host as resolved by the remote side
+    pub host: String,
+    /// Target port
+    pub port: u16,
+    /// Port state
+    pub state: PortState,
+    /// How the check was made: "direct_tcpip" or "remote_probe"
+    pub method: String,
+    /// Time until the state was known, in milliseconds
+    pub elapsed_ms: f64,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Level of sudo access available to the session user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoAccess {
+    /// The session already runs as root
+    Root,
+    /// `sudo -n -l` listed rules; see `rules`
+    Allowed,
+    /// The user may have sudo rules but sudo needs a password to list them
+    PasswordRequired,
+    /// The user is not allowed to run sudo
+    Denied,
+    /// sudo is not installed
+    Unavailable,
+    /// sudo failed for another reason; see `detail`
+    Unknown,
+}
+
+/// One rule from `sudo -l`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SudoRule {
+    /// Users (and groups after `:`) the commands run as, e.g. "root" or "ALL : ALL"
+    pub run_as: String,
+    /// Whether the rule carries the NOPASSWD tag
+    pub nopasswd: bool,
+    /// All tags on the rule (NOPASSWD, SETENV, ...)
+    pub tags: Vec<String>,
+    /// Allowed commands; "ALL" means any command
+    pub commands: Vec<String>,
+}
+
+/// Response from ssh_check_privileges
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCheckPrivilegesResponse {
+    /// Session the probe ran through
+    pub session_id: String,
+    /// Numeric uid of the session user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub uid: Option<u32>,
+    /// Whether the session user is root
+    pub is_root: bool,
+    /// sudo access level
+    pub sudo: SudoAccess,
+    /// Rules listed by `sudo -l`
+    pub rules: Vec<SudoRule>,
+    /// Whether any command can be run as root (as root already, or via an ALL rule)
+    pub full_root: bool,
+    /// Whether any command can be run as root without a password
+    pub passwordless_root: bool,
+    /// sudo's explanation when access was refused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Operating system of a host, from `/etc/os-release`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct OsFacts {
+    /// Distribution id (e.g., "ubuntu", "rhel", "alpine")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Distributions this one derives from (e.g., ["debian"])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub id_like: Vec<String>,
+    /// Human-readable name (e.g., "Ubuntu 24.04.1 LTS")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Version number (e.g., "24.04")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Kernel of a host, from `uname`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct KernelFacts {
+    /// Kernel name (e.g., "Linux", "Darwin", "FreeBSD")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Kernel release (e.g., "6.8.0-45-generic")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    /// Machine architecture (e.g., "x86_64", "aarch64")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+/// Processors of a host
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CpuFacts {
+    /// Online logical CPUs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub count: Option<u32>,
+    /// CPU model name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 1, 5 and 15 minute load averages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub load_average: Vec<f64>,
+}
+
+/// Memory of a host, in bytes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MemoryFacts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub total_bytes: Option<u64>,
+    /// Memory available for new work without swapping
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub available_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub swap_total_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub swap_free_bytes: Option<u64>,
+}
+
+/// One mounted filesystem of a host, from `df -P`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DiskFacts {
+    /// Mount point
+    pub mount: String,
+    /// Device or source of the filesystem
+    pub filesystem: String,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub total_bytes: u64,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub used_bytes: u64,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub available_bytes: u64,
+    /// Percentage of the filesystem in use, as reported by df
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub used_percent: Option<u32>,
+}
+
+/// Facts about a host gathered by ssh_gather_facts
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct HostFacts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    pub os: OsFacts,
+    pub kernel: KernelFacts,
+    pub cpu: CpuFacts,
+    pub memory: MemoryFacts,
+    /// Real filesystems (tmpfs, overlay and similar are left out)
+    pub disks: Vec<DiskFacts>,
+    /// Seconds since boot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub uptime_secs: Option<u64>,
+    /// Preferred package manager (e.g., "apt", "dnf", "apk", "brew")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_manager: Option<String>,
+    /// Init system running as PID 1 (e.g., "systemd", "init", "launchd")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_system: Option<String>,
+    /// Virtualization or container technology (e.g., "kvm", "docker"); absent on bare metal or when unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtualization: Option<String>,
+}
+
+/// Response from ssh_gather_facts
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshGatherFactsResponse {
+    /// Session the probes ran through
+    pub session_id: String,
+    pub facts: HostFacts,
+    /// Facts that could not be determined on this host (e.g., "memory" without /proc)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unavailable: Vec<String>,
+    /// Human-readable summary
+    pub message: String,
+}
+
+/// Outcome of a privilege-drop check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivilegeDropStatus {
+    /// No privileged process outlived the sudo operation
+    Clean,
+    /// Privileged processes are still running; see `lingering`
+    Lingering,
+    /// The session user is root, so there is no privilege to drop
+    NotApplicable,
+}
+
+/// A privileged process still running after a sudo operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct LingeringProcess {
+    /// Process ID on the remote host
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub pid: u32,
+    /// Command line of the process
+    pub command: String,
+    /// Whether this is a `sudo` process started by the session user (e.g. an
+    /// open `sudo -i` shell) rather than a root process matching the command
+    pub sudo: bool,
+}
+
+/// Result of invalidating sudo credentials and looking for lingering processes
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PrivilegeDropReport {
+    /// Cleanup status
+    pub status: PrivilegeDropStatus,
+    /// Whether `sudo -k` invalidated the cached sudo credentials
+    pub credentials_invalidated: bool,
+    /// Commands wrapped by sudo that were searched for among root processes
+    pub searched: Vec<String>,
+    /// Privileged processes still running
+    pub lingering: Vec<LingeringProcess>,
+}
+
+/// Response from ssh_verify_privilege_drop
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshVerifyPrivilegeDropResponse {
+    /// Session the check ran through
+    pub session_id: String,
+    /// Cleanup status and lingering processes
+    pub privilege_drop: PrivilegeDropReport,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Cumulative usage of one agent
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AgentUsage {
+    /// Agent the usage is charged to ("anonymous" when none was given)
+    pub agent_id: String,
+    /// Successful SSH connects
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub connects: u64,
+    /// Commands started
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub commands: u64,
+    /// Output bytes produced by finished commands (stdout plus stderr)
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub output_bytes: u64,
+    /// Interactive shells opened
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub shells_opened: u64,
+    /// Interactive shells currently open
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub open_shells: u64,
+    /// Wall-clock seconds of interactive shells, including open ones
+    pub shell_seconds: f64,
+    /// Channel bytes sent on the agent's sessions
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes_sent: u64,
+    /// Channel bytes received on the agent's sessions
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes_received: u64,
+}
+
+/// A config-declared host in ssh_list_managed_hosts
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ManagedHostInfo {
+    /// Session ID to pass to other tools
+    pub name: String,
+    /// SSH server address
+    pub host: String,
+    pub username: String,
+    /// "startup" or "lazy"
+    pub connect: String,
+    /// Whether the session is currently open
+    pub connected: bool,
+    /// When the current connection was established (RFC3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_datetime")]
+    pub connected_at: Option<String>,
+    /// Successful connects after the first
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub reconnects: u64,
+    /// Error of the last failed connect, cleared by a successful one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Spare connections kept dialed (0 disables the pool)
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub pool_size: usize,
+    /// Spare connections currently open
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub warm: usize,
+    /// Spares used for reconnects or ssh_connect since startup
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub pool_hits: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datacenter: Option<String>,
+}
+
+/// Response from ssh_list_managed_hosts
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshListManagedHostsResponse {
+    pub hosts: Vec<ManagedHostInfo>,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+}
+
+/// A server-side connection target in ssh_list_profiles
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProfileInfo {
+    /// Name to pass as ssh_connect `profile`
+    pub name: String,
+    /// SSH server address (`host:port`)
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// How the profile authenticates: "key_path", "credential_ref" or "agent"
+    pub auth: String,
+    /// Named credential used, when `auth` is "credential_ref"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_ref: Option<String>,
+    /// Jump hosts, first hop first (`user@host:port`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jump_hosts: Vec<String>,
+    /// Default ssh_connect timeout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub connect_timeout_secs: Option<u64>,
+    /// Default timeout of commands run on the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub command_timeout_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datacenter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Response from ssh_list_profiles
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshListProfilesResponse {
+    pub profiles: Vec<ProfileInfo>,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+}
+
+/// Outcome of one configuration check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckLevel {
+    Ok,
+    /// Accepted, but probably not what was meant
+    Warning,
+    /// Ignored or unusable; the server refuses to start
+    Error,
+}
+
+/// One configuration check from ssh_check_config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ConfigCheck {
+    /// What was checked (an environment variable, "config_file", "managed_host:<name>", ...)
+    pub name: String,
+    pub level: CheckLevel,
+    /// What was found, or what is wrong and how it is handled
+    pub message: String,
+}
+
+/// Response from ssh_check_config
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCheckConfigResponse {
+    /// Whether no check reported an error
+    pub ok: bool,
+    /// Number of checks at level "error"
+    pub errors: usize,
+    /// Number of checks at level "warning"
+    pub warnings: usize,
+    pub checks: Vec<ConfigCheck>,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_capabilities
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCapabilitiesResponse {
+    /// Server version (crate version)
+    pub version: String,
+    /// Compile-time features of this build
+    pub features: ServerFeatures,
+    /// Transports this server is serving MCP over ("stdio", or "streamable_http" and/or "sse")
+    pub transports: Vec<String>,
+    /// Tools clients can call, with the configured name prefix applied
+    pub tools: Vec<String>,
+    /// Tools hidden by the `[tools]` configuration
+    pub disabled_tools: Vec<String>,
+    /// Prefix prepended to every tool name (omitted when none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_prefix: Option<String>,
+    /// Effective defaults and hard limits
+    pub limits: ServerLimits,
+    /// Operator policies in effect
+    pub policies: ServerPolicies,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Compile-time features of the server build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ServerFeatures {
+    /// ssh_forward and ssh_reverse_forward work (cargo feature `port_forward`)
+    pub port_forward: bool,
+    /// The SFTP subsystem is used (ssh_list_dir); file transfers use exec channels (cat, tar)
+    pub sftp: bool,
+}
+
+/// Effective defaults and hard limits of the server
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServerLimits {
+    /// Default ssh_connect timeout (`SSH_CONNECT_TIMEOUT`)
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub connect_timeout_secs: u64,
+    /// Default command timeout (`SSH_COMMAND_TIMEOUT`)
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub command_timeout_secs: u64,
+    /// Idle time before a non-persistent session is closed (`SSH_INACTIVITY_TIMEOUT`)
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub inactivity_timeout_secs: u64,
+    /// Default connect retries (`SSH_MAX_RETRIES`)
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_retries: u32,
+    /// Sessions allowed in total (`SSH_MAX_SESSIONS`, omitted when unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub max_sessions: Option<usize>,
+    /// Sessions allowed per agent_id (`SSH_MAX_SESSIONS_PER_AGENT`, omitted when unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub max_sessions_per_agent: Option<usize>,
+    /// Sessions per host for hosts without an override (omitted when unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub max_sessions_per_host: Option<usize>,
+    /// Running async commands per session
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_async_commands_per_session: usize,
+    /// Open interactive shells per session
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_shells_per_session: usize,
+    /// Nodes per ssh_execute_dag call
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_dag_nodes: usize,
+    /// Parallel nodes per ssh_execute_dag call
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_dag_concurrency: usize,
+    /// Sessions per ssh_execute_batch call
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_batch_targets: usize,
+    /// Parallel targets per ssh_execute_batch call
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_batch_concurrency: usize,
+    /// Parallel uploads per ssh_upload_dir call
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_upload_concurrency: usize,
+    /// Entries per ssh_upload_dir call
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_upload_entries: usize,
+    /// Longest ssh_lock_session lease
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub max_lease_ttl_secs: u64,
+}
+
+/// Operator policies in effect on the server
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServerPolicies {
+    /// `[sandbox]` ceiling applied to every command (omitted when unrestricted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxLimits>,
+    /// Per-host session caps from `[host_limits.hosts]` (0 = unlimited)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub host_session_limits: BTreeMap<String, usize>,
+    /// Audit exporter kinds configured ("syslog", "http", "file"); empty when not exporting
+    pub audit_exporters: Vec<String>,
+    /// Hosts ssh_execute `callback_url` may point to (`*` = any); empty when callbacks are off
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub callback_hosts: Vec<String>,
+    /// Whether ssh_connect probes the remote identity (`SSH_IDENTITY_PROBE`)
+    pub identity_probe: bool,
+    /// Default ssh_connect host key checking: "yes", "accept-new" or "no"
+    pub host_key_checking: String,
+    /// `[[policy.deny]]` command patterns refused with `policy_violation`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_commands: Vec<String>,
+    /// Whether every session is read-only (`SSH_READ_ONLY`)
+    #[serde(default)]
+    pub read_only: bool,
+    /// Names ssh_connect accepts as `credential_ref`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub credential_refs: Vec<String>,
+}
+
+/// Response from ssh_usage
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshUsageResponse {
+    /// When counting started (server start, RFC3339 format)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub since: String,
+    /// Usage per agent, sorted by agent ID
+    pub agents: Vec<AgentUsage>,
+    /// Prometheus text exposition of the same counters (format="prometheus")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<String>,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Outcome of an audited action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// One audited action.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuditEvent {
+    /// When the action happened (RFC3339)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub timestamp: String,
+    /// Tool name, e.g. `ssh_execute`
+    pub action: String,
+    pub outcome: AuditOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Remote host (`host:port`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Remote user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// What the action was applied to: a command line, path, shell or forward
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Async command the event belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_id: Option<String>,
+    /// Exit code of a finished command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response from ssh_get_audit_log
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshAuditLogResponse {
+    /// Matching events, newest first
+    pub events: Vec<AuditEvent>,
+    /// Number of events returned
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+    /// Whether older matching events were left out because of `limit`
+    pub truncated: bool,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_symlink
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshSymlinkResponse {
+    /// Session the link was created through
+    pub session_id: String,
+    /// Path of the new link
+    pub link_path: String,
+    /// Target stored in the link (not resolved)
+    pub target: String,
+    /// Human-readable message
+    pub message: String,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
+    #[serde(default)]
+    pub reconnected: bool,
+}
+
+/// Outcome of one entry in a directory transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    /// File contents were written
+    Uploaded,
+    /// Symbolic link was recreated
+    Linked,
+    /// Transfer failed; see `error`
+    Failed,
+}
+
+/// Result for a single file or link in ssh_upload_dir
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FileTransferResult {
+    /// Path relative to the upload root
+    pub path: String,
+    /// File size in bytes (absent for links)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub size: Option<u64>,
+    pub status: TransferStatus,
+    /// Why the transfer failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response from ssh_upload_dir
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshUploadDirResponse {
+    /// Session the tree was uploaded through
+    pub session_id: String,
+    /// Local directory that was walked
+    pub local_path: String,
+    /// Remote directory the tree was recreated in
+    pub remote_path: String,
+    /// Directories created below remote_path
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub directories: usize,
+    /// Per-file results in walk order
+    pub files: Vec<FileTransferResult>,
+    /// Files and links transferred successfully
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub uploaded: usize,
+    /// Files and links that failed
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub failed: usize,
+    /// Bytes of file content uploaded
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes: u64,
+    /// Wall-clock time of the whole upload
+    pub elapsed_ms: f64,
+}
+
+/// One command in an ssh_execute_dag request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DagNodeSpec {
+    /// Unique node name, referenced by other nodes' depends_on (e.g., "build")
+    pub id: String,
+    /// Session to run the command on (or give `affinity` instead)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Pick the best session in this region/datacenter instead of naming one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<SessionAffinity>,
+    /// Shell command to execute
+    pub command: String,
+    /// Ids of nodes that must succeed before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Command timeout in seconds (default: 180, env: SSH_COMMAND_TIMEOUT)
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Outcome of one ssh_execute_dag node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DagNodeStatus {
+    /// Exited with code 0 within its timeout
+    Succeeded,
+    /// Non-zero exit, timeout, or channel error
+    Failed,
+    /// Never started (failed dependency or fail_fast)
+    Skipped,
+}
+
+/// Result of one ssh_execute_dag node
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DagNodeResult {
+    /// Node id as given
+    pub id: String,
+    /// Session the node targeted
+    pub session_id: String,
+    pub status: DagNodeStatus,
+    /// Exit code (absent when the node did not run to completion)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Standard output
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    /// Standard error
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    /// The command hit its timeout
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Why the node failed or was skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Run time (absent for skipped nodes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+}
+
+/// Response from ssh_execute_dag
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshExecuteDagResponse {
+    /// Every node succeeded
+    pub success: bool,
+    /// Per-node results in request order
+    pub nodes: Vec<DagNodeResult>,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub succeeded: usize,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub failed: usize,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub skipped: usize,
+    /// Wall-clock time of the whole graph
+    pub elapsed_ms: f64,
+}
+
+/// Outcome of one ssh_connect_many address
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ConnectManyResult {
+    /// A session is open to the host
+    pub success: bool,
+    /// Session of the host (absent when the connect failed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Retry attempts needed to connect
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub retry_attempts: u32,
+    /// An idle pooled connection was reused instead of dialing
+    #[serde(default)]
+    pub pooled: bool,
+    /// Error class when the connect failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+    /// Why the connect failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response from ssh_connect_many
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshConnectManyResponse {
+    /// Every host connected
+    pub success: bool,
+    /// Per-host results keyed by address as given
+    pub results: BTreeMap<String, ConnectManyResult>,
+    /// Sessions opened, in request order (e.g. for ssh_execute_batch session_ids)
+    pub session_ids: Vec<String>,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub connected: usize,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub failed: usize,
+    /// Wall-clock time of all connects
+    pub elapsed_ms: f64,
+}
+
+/// One session of an ssh_execute_batch request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BatchTarget {
+    /// Session to run the command on
     pub session_id: String,
-    /// Optional human-readable name for the session (useful for LLM identification)
+    /// Shell command for this session (default: the batch `command`)
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub name: Option<String>,
-    /// Optional agent identifier for grouping sessions by agent
+    pub command: Option<String>,
+}
+
+/// Outcome of one ssh_execute_batch target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchTargetStatus {
+    /// Exited with code 0 within its timeout
+    Succeeded,
+    /// Non-zero exit, timeout, or channel error
+    Failed,
+    /// Never ran: unknown session, access denied or command refused
+    Rejected,
+}
+
+/// Result of one ssh_execute_batch target
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BatchTargetResult {
+    /// Command run on the session
+    pub command: String,
+    pub status: BatchTargetStatus,
+    /// Exit code (absent when the command did not run to completion)
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub agent_id: Option<String>,
-    pub host: String,
-    pub username: String,
-    pub connected_at: String,
-    /// Default timeout in seconds used for this session's connection
+    pub exit_code: Option<i32>,
+    /// Standard output
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    /// Standard error
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    /// The command hit its timeout
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Why the target failed or was rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Run time (absent for rejected targets)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+}
+
+/// Response from ssh_execute_batch
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshExecuteBatchResponse {
+    /// Every target succeeded
+    pub success: bool,
+    /// Per-target results keyed by session ID
+    pub results: BTreeMap<String, BatchTargetResult>,
     #[schemars(schema_with = "crate::mcp::schema::uint")]
-    pub default_timeout_secs: u64,
-    /// Number of retry attempts needed to establish the connection
+    pub succeeded: usize,
     #[schemars(schema_with = "crate::mcp::schema::uint")]
-    pub retry_attempts: u32,
-    /// Whether compression is enabled for this session
-    pub compression_enabled: bool,
-    /// Timestamp of last health check (RFC3339 format)
+    pub failed: usize,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub rejected: usize,
+    /// Wall-clock time of the whole batch
+    pub elapsed_ms: f64,
+}
+
+/// Response from ssh_collect
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCollectResponse {
+    /// Session the paths were collected from
+    pub session_id: String,
+    /// Remote paths as given
+    pub paths: Vec<String>,
+    /// Local path of the downloaded `.tar.gz` archive
+    pub archive_path: String,
+    /// Archive size in bytes
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes: u64,
+    /// Local directory the archive was extracted into
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub last_health_check: Option<String>,
-    /// Whether session passed last health check
+    pub extracted_to: Option<String>,
+    /// Every path was archived without warnings (remote tar exited 0)
+    pub complete: bool,
+    /// Exit code of the remote tar (-1 if none was reported)
+    pub exit_code: i32,
+    /// Missing paths, files changed while read, extraction problems
+    pub warnings: Vec<String>,
+    /// Wall-clock time of the whole collection
+    pub elapsed_ms: f64,
+}
+
+/// Response from ssh_copy_between
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCopyBetweenResponse {
+    /// Session the file was read from
+    pub session_a: String,
+    /// Source file path
+    pub path_a: String,
+    /// Session the file was written to
+    pub session_b: String,
+    /// Destination file path
+    pub path_b: String,
+    /// Bytes copied
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes: u64,
+    /// Hex sha256 of the copied bytes; compare with `sha256sum` on either host
+    pub sha256: String,
+    /// Wall-clock time of the copy
+    pub elapsed_ms: f64,
+}
+
+/// Response from ssh_write_file
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshWriteFileResponse {
+    pub session_id: String,
+    /// File written
+    pub path: String,
+    /// Bytes written
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes: u64,
+    /// Hex sha256 of the written content; compare with `sha256sum` on the host
+    pub sha256: String,
+    /// Whether the file did not exist before
+    pub created: bool,
+    /// Copy of the previous version (only with backup=true on an existing file)
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub healthy: Option<bool>,
+    pub backup_path: Option<String>,
+    /// Human-readable summary
+    pub message: String,
 }
 
+/// Response from ssh_patch_file
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
-pub struct SshConnectResponse {
+pub struct SshPatchFileResponse {
     pub session_id: String,
-    /// Agent ID echoed back for LLM memory (if provided during connect)
+    /// File patched
+    pub path: String,
+    /// Hunks applied
+    pub hunks: usize,
+    /// Hunks found away from the line numbers in their @@ header
+    #[serde(default)]
+    pub shifted_hunks: usize,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    /// Size of the patched file
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub bytes: u64,
+    /// Hex sha256 of the patched content
+    pub sha256: String,
+    /// Whether the patch created the file
+    pub created: bool,
+    /// Copy of the previous version (only with backup=true)
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub agent_id: Option<String>,
+    pub backup_path: Option<String>,
+    /// The patch was checked but not written (dry_run=true)
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Human-readable summary
     pub message: String,
-    pub authenticated: bool,
-    /// Number of retry attempts needed to establish the connection
+}
+
+/// Type of an entry in an ssh_manifest or ssh_list_dir listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestEntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// Device, socket or FIFO
+    Other,
+}
+
+/// One entry of an ssh_manifest listing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ManifestEntry {
+    /// Path relative to the manifest root ("." for the root itself)
+    pub path: String,
+    pub kind: ManifestEntryKind,
+    /// File size in bytes (files only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub size: Option<u64>,
+    /// File checksum in the response's `algorithm` (absent when unreadable)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// Target stored in a symbolic link (not followed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Response from ssh_manifest
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshManifestResponse {
+    /// Session the tree was listed on
+    pub session_id: String,
+    /// Root path as given
+    pub path: String,
+    /// Levels below the root that were listed
     #[schemars(schema_with = "crate::mcp::schema::uint")]
-    pub retry_attempts: u32,
+    pub depth: usize,
+    /// Checksum algorithm: "sha256", or "cksum" (POSIX CRC) when no SHA-256 tool exists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    /// Entries sorted by path
+    pub entries: Vec<ManifestEntry>,
+    /// Regular files listed
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub files: usize,
+    /// Sum of the listed file sizes
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub total_bytes: u64,
+    /// More entries existed than max_entries allowed
+    pub truncated: bool,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
+    #[serde(default)]
+    pub reconnected: bool,
 }
 
+/// One entry of an ssh_list_dir listing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DirEntryInfo {
+    /// File name within the listed directory
+    pub name: String,
+    /// Type of the entry itself; symbolic links are not followed
+    pub kind: ManifestEntryKind,
+    /// Size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub size: Option<u64>,
+    /// Last modification time (RFC 3339, UTC)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<String>,
+    /// Permission bits in octal, including setuid/setgid/sticky (e.g. "0755")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// `ls -l` style mode string (e.g. "drwxr-xr-x")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<String>,
+    /// Owner user ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub uid: Option<u32>,
+    /// Owner group ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub gid: Option<u32>,
+    /// Target stored in a symbolic link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Response from ssh_list_dir
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
-pub(crate) struct SshCommandResponse {
-    pub stdout: String,
-    pub stderr: String,
-    pub exit_code: i32,
-    /// Whether the command timed out (partial output may be available)
+pub struct SshListDirResponse {
+    /// Session the directory was listed on
+    pub session_id: String,
+    /// Absolute directory path as resolved by the remote host
+    pub path: String,
+    /// Entries sorted by name ("." and ".." are omitted)
+    pub entries: Vec<DirEntryInfo>,
+    /// Entries returned
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+    /// More entries existed than max_entries allowed
+    pub truncated: bool,
+}
+
+/// Response from ssh_glob
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshGlobResponse {
+    /// Session the pattern was expanded on
+    pub session_id: String,
+    /// Pattern as given
+    pub pattern: String,
+    /// Matching paths in the remote shell's sort order
+    pub matches: Vec<String>,
+    /// Number of paths returned
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+    /// More paths matched than max_matches allowed
+    pub truncated: bool,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
     #[serde(default)]
-    pub timed_out: bool,
+    pub reconnected: bool,
 }
 
-/// Port forwarding response (only functional when port_forward feature is enabled)
+/// Response from ssh_readlink
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
-pub struct PortForwardingResponse {
-    pub local_address: String,
-    pub remote_address: String,
-    pub active: bool,
+pub struct SshReadlinkResponse {
+    /// Session the link was read through
+    pub session_id: String,
+    /// Path that was read
+    pub path: String,
+    /// Stored link target, or the canonical path when `follow` was set
+    pub target: String,
+    /// Whether the link chain was followed to its end
+    pub follow: bool,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
+    #[serde(default)]
+    pub reconnected: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -74,6 +1534,98 @@ pub struct SessionListResponse {
     /// Total number of active sessions
     #[schemars(schema_with = "crate::mcp::schema::uint")]
     pub count: usize,
+    /// Health fields come from the background monitor, not from probes made by this call
+    #[serde(default)]
+    pub cached: bool,
+    /// Sessions that were open when the MCP server last stopped; reconnect them with ssh_connect
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lost_sessions: Vec<LostSession>,
+}
+
+/// A session that did not survive an MCP server restart (`[persistence]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LostSession {
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    pub host: String,
+    pub username: String,
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub connected_at: String,
+    /// Last time the server recorded the session as open (RFC 3339)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub last_seen_at: String,
+    /// How to open the session again; absent when it was not opened by ssh_connect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reconnect: Option<ReconnectHint>,
+}
+
+/// The ssh_connect parameters a lost session was opened with, without secrets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ReconnectHint {
+    /// `address` to pass to ssh_connect
+    pub address: String,
+    pub username: String,
+    /// Credential that must be supplied again: `password`, `key_path`, `key_data` or `agent`;
+    /// `credential_ref` when the session used the named credential in `credential_ref`
+    pub auth: String,
+    /// Named credential to pass to ssh_connect again, when `auth` is `credential_ref`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_ref: Option<String>,
+    /// Key file used, when `auth` is `key_path`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// Jump hosts, first hop first (`user@host:port`); their credentials must be supplied again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jump_hosts: Vec<String>,
+    #[serde(default)]
+    pub compress: bool,
+}
+
+/// An async command that did not survive an MCP server restart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LostCommand {
+    pub command_id: String,
+    pub session_id: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub started_at: String,
+    /// Status when the server last recorded it
+    pub status: AsyncCommandStatus,
+    /// Last time the server recorded the command (RFC 3339)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub last_seen_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshRebootAndWaitResponse {
+    /// Session ID, unchanged; it now uses the new connection
+    pub session_id: String,
+    /// Command that started the reboot
+    pub command: String,
+    /// When the reboot command was sent (RFC 3339)
+    pub rebooted_at: String,
+    /// When the session was usable again (RFC 3339)
+    pub back_at: String,
+    /// Seconds from the old connection going down to the new one being up
+    pub downtime_secs: f64,
+    /// Seconds from sending the reboot to the session being usable
+    pub total_secs: f64,
+    /// Connection attempts made while the host was down
+    pub reconnect_attempts: u32,
+    /// Whether the kernel boot id changed; false means the connection dropped
+    /// without a reboot. Absent when the boot id could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_id_changed: Option<bool>,
+    /// Remote identity of the new connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<RemoteIdentity>,
+    /// Human-readable summary
+    pub message: String,
 }
 
 /// Status of an async command execution
@@ -101,6 +1653,33 @@ impl std::fmt::Display for AsyncCommandStatus {
     }
 }
 
+/// Resource limits for a remote command (ssh_execute `sandbox`, `[sandbox]` in the config file)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SandboxLimits {
+    /// Wall-clock limit via timeout(1): SIGTERM, then SIGKILL 5s later (exit code 124)
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub wall_secs: Option<u64>,
+    /// CPU time limit in seconds (ulimit -t; the command gets SIGXCPU)
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub cpu_secs: Option<u64>,
+    /// Largest file the command may write, in MiB (ulimit -f; SIGXFSZ beyond it)
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub max_file_mb: Option<u64>,
+    /// Maximum open file descriptors (ulimit -n)
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub max_open_files: Option<u64>,
+    /// Virtual memory limit in MiB (ulimit -v)
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub max_memory_mb: Option<u64>,
+    /// cgroup memory cap in MiB via `systemd-run --user --scope -p MemoryMax` (skipped without systemd)
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub cgroup_memory_mb: Option<u64>,
+    /// cgroup CPU quota in percent of one core via `systemd-run -p CPUQuota` (e.g., 50, or 200 for two cores)
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub cgroup_cpu_percent: Option<u64>,
+}
+
 /// Response from ssh_execute
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct SshExecuteResponse {
@@ -113,10 +1692,23 @@ pub struct SshExecuteResponse {
     pub agent_id: Option<String>,
     /// The command that was started
     pub command: String,
+    /// Purpose label given to ssh_execute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// URL the final output is POSTed to when the command finishes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    /// Whether output is pushed to the client as `ssh_command` notifications
+    #[serde(default)]
+    pub streaming: bool,
     /// When the command was started (RFC3339 format)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
     pub started_at: String,
     /// Human-readable message about the command
     pub message: String,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
+    #[serde(default)]
+    pub reconnected: bool,
 }
 
 /// Response from ssh_get_command_output
@@ -130,15 +1722,315 @@ pub struct SshAsyncOutputResponse {
     pub stdout: String,
     /// Standard error collected so far
     pub stderr: String,
+    /// Encoding of `stdout` when not plain text (`output_encoding="base64"` or `compress=true`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_encoding: Option<OutputEncoding>,
+    /// Encoding of `stderr` when not plain text (`output_encoding="base64"` or `compress=true`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_encoding: Option<OutputEncoding>,
+    /// Plain-text `stdout` was not valid UTF-8 (binary data or a character cut
+    /// at the buffer edge) and had bytes replaced; use `output_encoding="base64"`
+    #[serde(default)]
+    pub stdout_is_truncated_utf8: bool,
+    /// Plain-text `stderr` was not valid UTF-8 and had bytes replaced
+    #[serde(default)]
+    pub stderr_is_truncated_utf8: bool,
+    /// Exit code (only present when completed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Error message (only present when failed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Whether the command timed out
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Output bytes lost to the `max_output_bytes` cap (both streams)
+    #[serde(default)]
+    pub dropped_bytes: u64,
+    /// How the wait went (only present when wait=true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait: Option<WaitProgress>,
+    /// The `success_pattern` or `failure_pattern` match that settled the status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_pattern: Option<PatternMatch>,
+}
+
+/// Which `ssh_execute` output pattern matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternOutcome {
+    /// `success_pattern`; the command is marked `completed`
+    Success,
+    /// `failure_pattern`; the command is marked `failed`
+    Failure,
+}
+
+/// Output pattern match that settled an async command before it exited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PatternMatch {
+    pub outcome: PatternOutcome,
+    /// The matched output
+    pub text: String,
+    /// Stream the match appeared on: "stdout" or "stderr"
+    pub stream: String,
+    /// Whether the command was sent SIGTERM and closed (`terminate_on_match`)
+    pub terminated: bool,
+    /// Whether the command is still running after the match; it can still be
+    /// cancelled or signalled
+    #[serde(default)]
+    pub running: bool,
+}
+
+/// Response from ssh_tail_file
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshTailFileResponse {
+    /// Pass to ssh_tail_read; also the command_id for ssh_cancel_command
+    pub tail_id: String,
+    pub session_id: String,
+    /// File being followed
+    pub path: String,
+    /// Remote command that follows the file
+    pub command: String,
+    /// When the tail was started (RFC3339 format)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub started_at: String,
+    /// Human-readable message about the tail
+    pub message: String,
+}
+
+/// Response from ssh_tail_read
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshTailReadResponse {
+    pub tail_id: String,
+    /// `running` while the file is followed; anything else means the tail ended
+    pub status: AsyncCommandStatus,
+    /// New lines since `cursor`, without line terminators
+    pub lines: Vec<String>,
+    /// Pass as `cursor` to the next ssh_tail_read
+    pub cursor: u64,
+    /// Output evicted before it was read (reader fell behind the 4 MiB buffer)
+    #[serde(default)]
+    pub skipped_bytes: u64,
+    /// More lines are waiting beyond `max_lines`; read again right away
+    #[serde(default)]
+    pub more: bool,
+    /// Last notice from tail or grep on stderr (e.g., file not found, file replaced)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+    /// Error message when the tail failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Encoding of an output field that is not plain text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum OutputEncoding {
+    /// Raw bytes, base64 encoded (standard alphabet, padded)
+    #[serde(rename = "base64")]
+    Base64,
+    /// Gzip-compressed bytes, base64 encoded (standard alphabet, padded)
+    #[serde(rename = "gzip+base64")]
+    GzipBase64,
+}
+
+/// Progress of a blocking ssh_get_command_output call
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WaitProgress {
+    /// Time spent waiting in this call
+    pub waited_ms: f64,
+    /// The wait ended before the command finished; it is still running
+    pub expired: bool,
+    /// Times the wait was extended because new output arrived (renew_wait=true)
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub renewals: u32,
+    /// Bytes of stdout and stderr received during this wait
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub new_output_bytes: usize,
+    /// What to do next when the wait expired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Response from ssh_execute_sync
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshExecuteSyncResponse {
+    /// Command ID (usable with ssh_get_command_output while still running)
+    pub command_id: String,
+    /// Session ID where the command ran
+    pub session_id: String,
+    /// Agent ID that owns this session (if set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    /// The command that was executed
+    pub command: String,
+    /// Final status, or `running` if the wait timed out
+    pub status: AsyncCommandStatus,
+    /// Standard output
+    pub stdout: String,
+    /// Standard error
+    pub stderr: String,
+    /// Encoding of `stdout` when not plain text (`compress=true`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_encoding: Option<OutputEncoding>,
+    /// Encoding of `stderr` when not plain text (`compress=true`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_encoding: Option<OutputEncoding>,
+    /// Exit code (only present when completed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Error message (only present when failed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Whether the command hit its execution timeout
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Privilege-drop check run after the command (`verify_privilege_drop=true`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub privilege_drop: Option<PrivilegeDropReport>,
+    /// Human-readable message about the result
+    pub message: String,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
+    #[serde(default)]
+    pub reconnected: bool,
+}
+
+/// One filesystem line of `df -h`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DfRow {
+    /// Device or source of the filesystem
+    pub filesystem: String,
+    /// Size as printed (e.g. "20G", or 1K blocks without -h)
+    pub size: String,
+    pub used: String,
+    pub available: String,
+    /// Percentage in use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub used_percent: Option<u32>,
+    /// Mount point
+    pub mounted_on: String,
+}
+
+/// One process line of `ps aux`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PsRow {
+    pub user: String,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub pid: u32,
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    /// Virtual memory size in KiB
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub vsz_kb: u64,
+    /// Resident set size in KiB
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub rss_kb: u64,
+    /// Controlling terminal, "?" for none
+    pub tty: String,
+    /// Process state codes (e.g. "Ss", "R+")
+    pub stat: String,
+    /// Start time or date
+    pub start: String,
+    /// Accumulated CPU time
+    pub time: String,
+    /// Command line
+    pub command: String,
+}
+
+/// Process owning a socket, from the `users:(...)` column of `ss -p`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SocketProcess {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub pid: Option<u32>,
+}
+
+/// One socket line of `ss -tlnp`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SsRow {
+    /// Socket type ("tcp", "udp", ...), present when ss printed a Netid column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub netid: Option<String>,
+    /// Socket state (e.g. "LISTEN", "UNCONN")
+    pub state: String,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub recv_q: u64,
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub send_q: u64,
+    /// Local address as printed (e.g. "0.0.0.0", "[::]", "127.0.0.53%lo")
+    pub local_address: String,
+    /// Local port; absent for "*"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub local_port: Option<u16>,
+    /// Peer address and port as printed (e.g. "0.0.0.0:*")
+    pub peer: String,
+    /// Owning processes; empty without -p or without the privileges to see them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<SocketProcess>,
+}
+
+/// One unit line of `systemctl list-units`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SystemctlRow {
+    pub unit: String,
+    /// Whether the unit definition loaded (e.g. "loaded", "not-found")
+    pub load: String,
+    /// High-level state (e.g. "active", "failed")
+    pub active: String,
+    /// Unit-type specific state (e.g. "running", "exited")
+    pub sub: String,
+    pub description: String,
+}
+
+/// Response from ssh_execute_parsed
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshExecuteParsedResponse {
+    /// Command ID (usable with ssh_get_command_output while still running)
+    pub command_id: String,
+    pub session_id: String,
+    /// The command that was executed
+    pub command: String,
+    /// Parser applied to stdout: "df", "ps", "ss" or "systemctl"
+    pub parser: String,
+    /// Final status, or `running` if the wait timed out (nothing is parsed then)
+    pub status: AsyncCommandStatus,
     /// Exit code (only present when completed)
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub exit_code: Option<i32>,
+    pub exit_code: Option<i32>,
+    /// Filesystems (parser "df")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filesystems: Option<Vec<DfRow>>,
+    /// Processes (parser "ps")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes: Option<Vec<PsRow>>,
+    /// Sockets (parser "ss")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sockets: Option<Vec<SsRow>>,
+    /// Units (parser "systemctl")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<Vec<SystemctlRow>>,
+    /// Number of parsed rows
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+    /// Lines of stdout the parser did not recognize (first 20)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unparsed_lines: Vec<String>,
+    /// Standard error, when the command wrote any
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
     /// Error message (only present when failed)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
-    /// Whether the command timed out
+    /// Whether the command hit its execution timeout
     #[serde(default)]
     pub timed_out: bool,
+    /// Human-readable message about the result
+    pub message: String,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
+    #[serde(default)]
+    pub reconnected: bool,
 }
 
 /// Response from ssh_cancel_command
@@ -156,6 +2048,84 @@ pub struct SshCancelCommandResponse {
     pub stderr: String,
 }
 
+/// Response from ssh_command_signal
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCommandSignalResponse {
+    /// Command ID that was signalled
+    pub command_id: String,
+    /// Signal that was sent, without the `SIG` prefix
+    pub signal: String,
+    /// Command status shortly after the signal
+    pub status: AsyncCommandStatus,
+    /// Exit code when the command has already exited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_cancel_session_commands
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshCancelSessionCommandsResponse {
+    /// Session whose commands were cancelled
+    pub session_id: String,
+    /// Number of running commands that were cancelled
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub cancelled: usize,
+    /// IDs of the cancelled commands
+    pub command_ids: Vec<String>,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Exclusive lease held by an agent on a session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SessionLease {
+    /// Locked session
+    pub session_id: String,
+    /// Agent holding the lease
+    pub holder_agent_id: String,
+    /// When the lease was first acquired (RFC3339 format)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub acquired_at: String,
+    /// When the lease lapses unless renewed (RFC3339 format)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
+    pub expires_at: String,
+}
+
+/// Response from ssh_lock_session
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshLockSessionResponse {
+    /// The lease now held by the caller
+    pub lease: SessionLease,
+    /// Whether an existing lease of the caller was extended
+    pub renewed: bool,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_unlock_session
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshUnlockSessionResponse {
+    /// Session that was unlocked
+    pub session_id: String,
+    /// Whether an active lease was released (false if the session was not locked)
+    pub released: bool,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_update_session
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshUpdateSessionResponse {
+    /// Session that was updated
+    pub session_id: String,
+    /// Labels of the session after the update
+    pub labels: BTreeMap<String, String>,
+    /// Human-readable message
+    pub message: String,
+}
+
 /// Response from ssh_disconnect_agent
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct AgentDisconnectResponse {
@@ -167,6 +2137,14 @@ pub struct AgentDisconnectResponse {
     /// Number of async commands that were cancelled
     #[schemars(schema_with = "crate::mcp::schema::uint")]
     pub commands_cancelled: usize,
+    /// Number of interactive shells that were closed
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub shells_closed: usize,
+    /// Number of port forwards (local and reverse) that were closed
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub forwards_closed: usize,
     /// Human-readable message
     pub message: String,
 }
@@ -183,7 +2161,43 @@ pub struct AsyncCommandInfo {
     /// Current status of the command
     pub status: AsyncCommandStatus,
     /// When the command was started (RFC3339 format)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
     pub started_at: String,
+    /// Purpose label given to ssh_execute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Status of one command in an ssh_wait_commands response
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CommandWaitStatus {
+    /// Command ID
+    pub command_id: String,
+    /// Session ID where the command runs
+    pub session_id: String,
+    /// Current status of the command
+    pub status: AsyncCommandStatus,
+    /// Exit code (only present when completed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Whether the command hit its execution timeout
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+/// Response from ssh_wait_commands
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshWaitCommandsResponse {
+    /// Whether the wait condition ("all" or "any") was met before the timeout
+    pub done: bool,
+    /// Number of commands no longer running
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub finished: usize,
+    /// Number of commands still running
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub running: usize,
+    /// Status of every listed command, in request order
+    pub commands: Vec<CommandWaitStatus>,
 }
 
 /// Response from ssh_list_commands
@@ -231,7 +2245,27 @@ pub struct ShellInfo {
     #[schemars(schema_with = "crate::mcp::schema::uint")]
     pub rows: u32,
     /// When the shell was opened (RFC3339 format)
+    #[schemars(schema_with = "crate::mcp::schema::datetime")]
     pub opened_at: String,
+    /// User the shell runs as after ssh_shell_switch_user (omitted for the login user)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_user: Option<String>,
+}
+
+/// Response from ssh_shell_switch_user
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshShellSwitchUserResponse {
+    pub shell_id: String,
+    /// User reported by `whoami` after the switch
+    pub effective_user: String,
+    /// User before the switch (omitted for the login user)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_user: Option<String>,
+    /// "sudo" or "su"
+    pub method: String,
+    /// Whether a password prompt was answered
+    pub password_sent: bool,
+    pub message: String,
 }
 
 /// Response from ssh_shell_open
@@ -250,6 +2284,18 @@ pub struct SshShellOpenResponse {
     pub message: String,
 }
 
+/// Pager prompt waiting for a key at the end of shell output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PagerPrompt {
+    /// `more` prompt (`--More--`)
+    More,
+    /// `less` prompt mid-document (`:` or `lines X-Y`)
+    Less,
+    /// `less` at the end of the document (`(END)`)
+    End,
+}
+
 /// Response from ssh_shell_read
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct SshShellReadResponse {
@@ -259,6 +2305,53 @@ pub struct SshShellReadResponse {
     pub data: String,
     /// Current shell status
     pub status: ShellStatus,
+    /// Pager prompt still waiting for a key after the read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pager_prompt: Option<PagerPrompt>,
+    /// Keys sent to answer pager prompts during this read
+    #[serde(default)]
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub pager_keys_sent: u32,
+}
+
+/// Response from ssh_shell_expect
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshShellExpectResponse {
+    /// Shell ID that was waited on
+    pub shell_id: String,
+    /// Whether the pattern matched
+    pub matched: bool,
+    /// Text that matched the pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_text: Option<String>,
+    /// Capture groups of a regex pattern (`null` for groups that did not take part)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Option<String>>,
+    /// Output before the match; all unread output when nothing matched
+    pub before: String,
+    /// Current shell status
+    pub status: ShellStatus,
+    /// Whether the wait ended at `timeout_secs` without a match
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Time spent waiting in milliseconds
+    pub waited_ms: f64,
+    /// What to do next when nothing matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Response from ssh_shell_signal
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshShellSignalResponse {
+    /// Shell ID that was signalled
+    pub shell_id: String,
+    /// Signal that was sent, without the `SIG` prefix
+    pub signal: String,
+    /// Shell status shortly after the signal
+    pub status: ShellStatus,
+    /// Human-readable message
+    pub message: String,
 }
 
 /// Response from ssh_shell_close
@@ -272,6 +2365,106 @@ pub struct SshShellCloseResponse {
     pub message: String,
 }
 
+/// Response from ssh_shell_subscribe
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshShellSubscribeResponse {
+    /// Subscription ID to pass to ssh_shell_unsubscribe
+    pub subscription_id: String,
+    /// Shell whose output is streamed
+    pub shell_id: String,
+    /// JSON-RPC method of the output notifications
+    pub notification_method: String,
+    /// `logger` field identifying the output notifications
+    pub logger: String,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_shell_unsubscribe
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshShellUnsubscribeResponse {
+    /// Subscription ID that was stopped
+    pub subscription_id: String,
+    /// Shell the subscription was streaming
+    pub shell_id: String,
+    /// Whether the subscription was stopped
+    pub unsubscribed: bool,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_open_console
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshOpenConsoleResponse {
+    /// Shell ID of the console (use with ssh_shell_read/ssh_shell_write)
+    pub shell_id: String,
+    /// Session ID where the console is running
+    pub session_id: String,
+    /// Console preset in use
+    pub console: String,
+    /// Command sent to attach the console (absent for raw consoles)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Terminal type configured for the console
+    pub term_type: String,
+    /// Input that leaves the console; send it with ssh_shell_write
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_sequence: Option<String>,
+    /// Human-readable form of the exit sequence
+    pub exit_hint: String,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// Response from ssh_tmux_attach
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshTmuxAttachResponse {
+    /// Shell ID of the attached client (use with ssh_shell_read/ssh_shell_write)
+    pub shell_id: String,
+    /// Session ID where the shell is running
+    pub session_id: String,
+    /// Multiplexer session name on the remote host
+    pub name: String,
+    /// Multiplexer in use: "tmux" or "screen"
+    pub backend: String,
+    /// Human-readable message
+    pub message: String,
+}
+
+/// A tmux/screen session on the remote host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct MultiplexerSession {
+    /// Session name
+    pub name: String,
+    /// Number of windows (tmux only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_uint")]
+    pub windows: Option<u32>,
+    /// Whether a client is currently attached
+    pub attached: bool,
+    /// Creation time (tmux only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::mcp::schema::optional_datetime")]
+    pub created_at: Option<String>,
+}
+
+/// Response from ssh_tmux_list
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SshTmuxListResponse {
+    /// Session ID the listing was taken on
+    pub session_id: String,
+    /// Multiplexer queried: "tmux" or "screen"
+    pub backend: String,
+    /// Multiplexer sessions of the remote user
+    pub sessions: Vec<MultiplexerSession>,
+    /// Number of sessions
+    #[schemars(schema_with = "crate::mcp::schema::uint")]
+    pub count: usize,
+    /// Whether the connection was lost and re-established first (`auto_reconnect`)
+    #[serde(default)]
+    pub reconnected: bool,
+}
+
 #[cfg(test)]
 mod response_serialization {
     use super::*;
@@ -287,6 +2480,9 @@ mod response_serialization {
                 message: "Connected successfully".to_string(),
                 authenticated: true,
                 retry_attempts: 2,
+                identity: None,
+                reverse_forwards: Vec::new(),
+                pooled: false,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -307,6 +2503,9 @@ mod response_serialization {
                 message: "msg".to_string(),
                 authenticated: false,
                 retry_attempts: 0,
+                identity: None,
+                reverse_forwards: Vec::new(),
+                pooled: false,
             };
 
             let json = serde_json::to_value(&response).unwrap();
@@ -327,6 +2526,9 @@ mod response_serialization {
                 message: "msg".to_string(),
                 authenticated: true,
                 retry_attempts: 0,
+                identity: None,
+                reverse_forwards: Vec::new(),
+                pooled: false,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -341,6 +2543,9 @@ mod response_serialization {
                 message: "msg".to_string(),
                 authenticated: true,
                 retry_attempts: 0,
+                identity: None,
+                reverse_forwards: Vec::new(),
+                pooled: false,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -366,6 +2571,9 @@ mod response_serialization {
                 message: message.clone(),
                 authenticated: true,
                 retry_attempts: 0,
+                identity: None,
+                reverse_forwards: Vec::new(),
+                pooled: false,
             };
 
             // Verify message format
@@ -402,6 +2610,9 @@ mod response_serialization {
                 message,
                 authenticated: true,
                 retry_attempts: retry_attempts as u32,
+                identity: None,
+                reverse_forwards: Vec::new(),
+                pooled: false,
             };
 
             // Verify optional parts are present
@@ -437,6 +2648,9 @@ mod response_serialization {
                 message,
                 authenticated: true,
                 retry_attempts: 0,
+                identity: None,
+                reverse_forwards: Vec::new(),
+                pooled: false,
             };
 
             // Verify persistent is present
@@ -542,20 +2756,166 @@ mod response_serialization {
     mod session_info {
         use super::*;
 
+        fn empty_session_info() -> SessionInfo {
+            serde_json::from_str(
+                r#"{"session_id":"s","host":"h:22","username":"u","connected_at":"2024-01-15T10:30:00Z","default_timeout_secs":30,"retry_attempts":0,"compression_enabled":true}"#,
+            )
+            .unwrap()
+        }
+
+        #[test]
+        fn test_first_rtt_sample_sets_average() {
+            let mut info = empty_session_info();
+            info.record_rtt(std::time::Duration::from_micros(12_345));
+            assert_eq!(info.rtt_ms, Some(12.35));
+            assert_eq!(info.rtt_avg_ms, Some(12.35));
+        }
+
+        #[test]
+        fn test_activity_counted_in_stats() {
+            let mut info = empty_session_info();
+            assert_eq!(info.stats, SessionStats::default());
+            info.record_activity(SessionActivity::CommandExecuted);
+            info.record_activity(SessionActivity::CommandExecuted);
+            info.record_activity(SessionActivity::Output(120));
+            info.record_activity(SessionActivity::Output(30));
+            info.record_activity(SessionActivity::ShellOpened);
+            info.record_activity(SessionActivity::ForwardEstablished);
+            assert_eq!(
+                info.stats,
+                SessionStats {
+                    commands_executed: 2,
+                    shells_opened: 1,
+                    output_bytes: 150,
+                    forwards_established: 1,
+                    last_activity: None,
+                }
+            );
+        }
+
+        #[test]
+        fn test_health_history_is_capped() {
+            let mut info = empty_session_info();
+            for i in 0..HEALTH_HISTORY_LEN + 3 {
+                info.record_health(format!("t{}", i), true);
+            }
+            assert_eq!(info.health_history.len(), HEALTH_HISTORY_LEN);
+            assert_eq!(info.health_history[0].at, "t3");
+            assert_eq!(info.last_health_check.as_deref(), Some("t12"));
+            assert!(!info.flapping);
+        }
+
+        #[test]
+        fn test_alternating_health_is_flapping() {
+            let mut info = empty_session_info();
+            for healthy in [true, false, true] {
+                info.record_health("t".to_string(), healthy);
+            }
+            // Two transitions: an outage and a recovery
+            assert!(!info.flapping);
+            info.record_health("t".to_string(), false);
+            assert!(info.flapping);
+            assert_eq!(info.healthy, Some(false));
+
+            // Stable again once the transitions age out of the history
+            for _ in 0..HEALTH_HISTORY_LEN {
+                info.record_health("t".to_string(), true);
+            }
+            assert!(!info.flapping);
+        }
+
+        #[test]
+        fn test_rtt_average_is_smoothed() {
+            let mut info = empty_session_info();
+            info.record_rtt(std::time::Duration::from_millis(10));
+            info.record_rtt(std::time::Duration::from_millis(90));
+            assert_eq!(info.rtt_ms, Some(90.0));
+            // 10 + 0.125 * (90 - 10)
+            assert_eq!(info.rtt_avg_ms, Some(20.0));
+        }
+
+        #[test]
+        fn test_rtt_omitted_until_measured() {
+            let json = serde_json::to_string(&empty_session_info()).unwrap();
+            assert!(!json.contains("rtt_ms"));
+            assert!(!json.contains("rtt_avg_ms"));
+        }
+
+        #[test]
+        fn test_shared_serialization() {
+            let mut info = empty_session_info();
+            assert!(!serde_json::to_string(&info).unwrap().contains("shared"));
+
+            info.shared = Some(ShareMode::ReadOnly);
+            let json = serde_json::to_value(&info).unwrap();
+            assert_eq!(json["shared"], "read_only");
+
+            let deserialized: SessionInfo = serde_json::from_value(json).unwrap();
+            assert_eq!(deserialized.shared, Some(ShareMode::ReadOnly));
+        }
+
+        #[test]
+        fn test_identity_serialization() {
+            let identity = RemoteIdentity {
+                user: "deploy".to_string(),
+                uid: Some(1001),
+                groups: vec!["deploy".to_string(), "docker".to_string()],
+                hostname: Some("web-01".to_string()),
+                is_root: false,
+            };
+
+            let json = serde_json::to_value(&identity).unwrap();
+            assert_eq!(json["user"], "deploy");
+            assert_eq!(json["uid"], 1001);
+            assert_eq!(json["groups"][1], "docker");
+
+            let deserialized: RemoteIdentity = serde_json::from_value(json).unwrap();
+            assert_eq!(deserialized, identity);
+        }
+
+        #[test]
+        fn test_identity_defaults_when_absent() {
+            let json = r#"{"session_id":"s","host":"h:22","username":"u","connected_at":"2024-01-15T10:30:00Z","default_timeout_secs":30,"retry_attempts":0,"compression_enabled":true}"#;
+            let info: SessionInfo = serde_json::from_str(json).unwrap();
+            assert!(info.identity.is_none());
+        }
+
         #[test]
         fn test_serialize_and_deserialize() {
             let info = SessionInfo {
                 session_id: "uuid-123".to_string(),
                 name: Some("production-db".to_string()),
                 agent_id: Some("my-agent".to_string()),
+                shared: None,
                 host: "192.168.1.1:22".to_string(),
                 username: "testuser".to_string(),
                 connected_at: "2024-01-15T10:30:00Z".to_string(),
                 default_timeout_secs: 30,
                 retry_attempts: 1,
                 compression_enabled: true,
+                compression_level: None,
+                keepalive_interval_secs: 30,
+                keepalive_max: 3,
+                algorithms: None,
                 last_health_check: Some("2024-01-15T10:35:00Z".to_string()),
                 healthy: Some(true),
+                health_history: Vec::new(),
+                flapping: false,
+                identity: None,
+                rtt_ms: None,
+                rtt_avg_ms: None,
+                region: None,
+                datacenter: None,
+                jump_hosts: Vec::new(),
+                rebooting: false,
+                read_only: false,
+                agent_forwarding: false,
+                profile: None,
+                command_timeout_secs: None,
+                bytes_sent: 0,
+                bytes_received: 0,
+                stats: Default::default(),
+                labels: Default::default(),
             };
 
             let json = serde_json::to_string(&info).unwrap();
@@ -583,14 +2943,36 @@ mod response_serialization {
                 session_id: "uuid-456".to_string(),
                 name: None,
                 agent_id: None,
+                shared: None,
                 host: "192.168.1.1:22".to_string(),
                 username: "testuser".to_string(),
                 connected_at: "2024-01-15T10:30:00Z".to_string(),
                 default_timeout_secs: 30,
                 retry_attempts: 0,
                 compression_enabled: true,
+                compression_level: None,
+                keepalive_interval_secs: 30,
+                keepalive_max: 3,
+                algorithms: None,
                 last_health_check: None,
                 healthy: None,
+                health_history: Vec::new(),
+                flapping: false,
+                identity: None,
+                rtt_ms: None,
+                rtt_avg_ms: None,
+                region: None,
+                datacenter: None,
+                jump_hosts: Vec::new(),
+                rebooting: false,
+                read_only: false,
+                agent_forwarding: false,
+                profile: None,
+                command_timeout_secs: None,
+                bytes_sent: 0,
+                bytes_received: 0,
+                stats: Default::default(),
+                labels: Default::default(),
             };
 
             let json = serde_json::to_string(&info).unwrap();
@@ -616,14 +2998,36 @@ mod response_serialization {
                 session_id: "uuid-789".to_string(),
                 name: None,
                 agent_id: Some("claude-code-agent".to_string()),
+                shared: None,
                 host: "192.168.1.1:22".to_string(),
                 username: "testuser".to_string(),
                 connected_at: "2024-01-15T10:30:00Z".to_string(),
                 default_timeout_secs: 30,
                 retry_attempts: 0,
                 compression_enabled: true,
+                compression_level: None,
+                keepalive_interval_secs: 30,
+                keepalive_max: 3,
+                algorithms: None,
                 last_health_check: None,
                 healthy: None,
+                health_history: Vec::new(),
+                flapping: false,
+                identity: None,
+                rtt_ms: None,
+                rtt_avg_ms: None,
+                region: None,
+                datacenter: None,
+                jump_hosts: Vec::new(),
+                rebooting: false,
+                read_only: false,
+                agent_forwarding: false,
+                profile: None,
+                command_timeout_secs: None,
+                bytes_sent: 0,
+                bytes_received: 0,
+                stats: Default::default(),
+                labels: Default::default(),
             };
 
             let json = serde_json::to_string(&info).unwrap();
@@ -640,14 +3044,36 @@ mod response_serialization {
                 session_id: "clone-test".to_string(),
                 name: Some("test-session".to_string()),
                 agent_id: Some("agent-1".to_string()),
+                shared: None,
                 host: "host".to_string(),
                 username: "user".to_string(),
                 connected_at: "now".to_string(),
                 default_timeout_secs: 60,
                 retry_attempts: 0,
                 compression_enabled: false,
+                compression_level: None,
+                keepalive_interval_secs: 30,
+                keepalive_max: 3,
+                algorithms: None,
                 last_health_check: Some("2024-01-15T10:30:00Z".to_string()),
                 healthy: Some(true),
+                health_history: Vec::new(),
+                flapping: false,
+                identity: None,
+                rtt_ms: None,
+                rtt_avg_ms: None,
+                region: None,
+                datacenter: None,
+                jump_hosts: Vec::new(),
+                rebooting: false,
+                read_only: false,
+                agent_forwarding: false,
+                profile: None,
+                command_timeout_secs: None,
+                bytes_sent: 0,
+                bytes_received: 0,
+                stats: Default::default(),
+                labels: Default::default(),
             };
 
             let cloned = info.clone();
@@ -669,6 +3095,8 @@ mod response_serialization {
             let response = SessionListResponse {
                 sessions: vec![],
                 count: 0,
+                cached: false,
+                lost_sessions: Vec::new(),
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -684,32 +3112,78 @@ mod response_serialization {
                 session_id: "s1".to_string(),
                 name: Some("production".to_string()),
                 agent_id: Some("agent-1".to_string()),
+                shared: None,
                 host: "host1".to_string(),
                 username: "user1".to_string(),
                 connected_at: "t1".to_string(),
                 default_timeout_secs: 30,
                 retry_attempts: 0,
                 compression_enabled: true,
+                compression_level: None,
+                keepalive_interval_secs: 30,
+                keepalive_max: 3,
+                algorithms: None,
                 last_health_check: Some("2024-01-15T10:30:00Z".to_string()),
                 healthy: Some(true),
+                health_history: Vec::new(),
+                flapping: false,
+                identity: None,
+                rtt_ms: None,
+                rtt_avg_ms: None,
+                region: None,
+                datacenter: None,
+                jump_hosts: Vec::new(),
+                rebooting: false,
+                read_only: false,
+                agent_forwarding: false,
+                profile: None,
+                command_timeout_secs: None,
+                bytes_sent: 0,
+                bytes_received: 0,
+                stats: Default::default(),
+                labels: Default::default(),
             };
             let session2 = SessionInfo {
                 session_id: "s2".to_string(),
                 name: None,
                 agent_id: None,
+                shared: None,
                 host: "host2".to_string(),
                 username: "user2".to_string(),
                 connected_at: "t2".to_string(),
                 default_timeout_secs: 60,
                 retry_attempts: 2,
                 compression_enabled: false,
+                compression_level: None,
+                keepalive_interval_secs: 30,
+                keepalive_max: 3,
+                algorithms: None,
                 last_health_check: None,
                 healthy: None,
+                health_history: Vec::new(),
+                flapping: false,
+                identity: None,
+                rtt_ms: None,
+                rtt_avg_ms: None,
+                region: None,
+                datacenter: None,
+                jump_hosts: Vec::new(),
+                rebooting: false,
+                read_only: false,
+                agent_forwarding: false,
+                profile: None,
+                command_timeout_secs: None,
+                bytes_sent: 0,
+                bytes_received: 0,
+                stats: Default::default(),
+                labels: Default::default(),
             };
 
             let response = SessionListResponse {
                 sessions: vec![session1, session2],
                 count: 2,
+                cached: true,
+                lost_sessions: Vec::new(),
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -717,6 +3191,7 @@ mod response_serialization {
 
             assert_eq!(deserialized.sessions.len(), 2);
             assert_eq!(deserialized.count, 2);
+            assert!(deserialized.cached);
             assert_eq!(deserialized.sessions[0].session_id, "s1");
             assert_eq!(
                 deserialized.sessions[0].name,
@@ -741,6 +3216,7 @@ mod response_serialization {
         #[test]
         fn test_serialize_and_deserialize() {
             let response = PortForwardingResponse {
+                forward_id: "fwd-1".to_string(),
                 local_address: "127.0.0.1:8080".to_string(),
                 remote_address: "localhost:3306".to_string(),
                 active: true,
@@ -815,6 +3291,7 @@ mod response_serialization {
         }
 
         #[test]
+        #[allow(clippy::clone_on_copy)]
         fn test_clone_and_copy() {
             let status = AsyncCommandStatus::Running;
             let cloned = status.clone();
@@ -840,8 +3317,12 @@ mod response_serialization {
                 session_id: "sess-456".to_string(),
                 agent_id: Some("my-agent".to_string()),
                 command: "sleep 10".to_string(),
+                label: None,
+                callback_url: None,
+                streaming: false,
                 started_at: "2024-01-15T10:30:00Z".to_string(),
                 message: "Command started".to_string(),
+                reconnected: false,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -862,8 +3343,12 @@ mod response_serialization {
                 session_id: "sid".to_string(),
                 agent_id: None,
                 command: "cmd".to_string(),
+                label: None,
+                callback_url: None,
+                streaming: false,
                 started_at: "time".to_string(),
                 message: "msg".to_string(),
+                reconnected: false,
             };
 
             let json = serde_json::to_value(&response).unwrap();
@@ -883,8 +3368,12 @@ mod response_serialization {
                 session_id: "sid".to_string(),
                 agent_id: None,
                 command: "cmd".to_string(),
+                label: None,
+                callback_url: None,
+                streaming: false,
                 started_at: "time".to_string(),
                 message: "msg".to_string(),
+                reconnected: false,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -898,8 +3387,12 @@ mod response_serialization {
                 session_id: "sid".to_string(),
                 agent_id: Some("agent-123".to_string()),
                 command: "cmd".to_string(),
+                label: None,
+                callback_url: None,
+                streaming: false,
                 started_at: "time".to_string(),
                 message: "msg".to_string(),
+                reconnected: false,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -918,9 +3411,16 @@ mod response_serialization {
                 status: AsyncCommandStatus::Running,
                 stdout: "partial output".to_string(),
                 stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                stdout_is_truncated_utf8: false,
+                stderr_is_truncated_utf8: false,
                 exit_code: None,
                 error: None,
                 timed_out: false,
+                dropped_bytes: 0,
+                wait: None,
+                matched_pattern: None,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -938,9 +3438,16 @@ mod response_serialization {
                 status: AsyncCommandStatus::Completed,
                 stdout: "full output".to_string(),
                 stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                stdout_is_truncated_utf8: false,
+                stderr_is_truncated_utf8: false,
                 exit_code: Some(0),
                 error: None,
                 timed_out: false,
+                dropped_bytes: 0,
+                wait: None,
+                matched_pattern: None,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -957,9 +3464,16 @@ mod response_serialization {
                 status: AsyncCommandStatus::Failed,
                 stdout: String::new(),
                 stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                stdout_is_truncated_utf8: false,
+                stderr_is_truncated_utf8: false,
                 exit_code: None,
                 error: Some("Failed to open channel".to_string()),
                 timed_out: false,
+                dropped_bytes: 0,
+                wait: None,
+                matched_pattern: None,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -979,9 +3493,16 @@ mod response_serialization {
                 status: AsyncCommandStatus::Completed,
                 stdout: "partial".to_string(),
                 stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                stdout_is_truncated_utf8: false,
+                stderr_is_truncated_utf8: false,
                 exit_code: None,
                 error: None,
                 timed_out: true,
+                dropped_bytes: 0,
+                wait: None,
+                matched_pattern: None,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -990,6 +3511,43 @@ mod response_serialization {
             assert!(deserialized.timed_out);
         }
 
+        #[test]
+        fn test_dropped_bytes_defaults_to_zero() {
+            let json = r#"{"command_id":"cmd-123","status":"running","stdout":"","stderr":""}"#;
+            let response: SshAsyncOutputResponse = serde_json::from_str(json).unwrap();
+            assert_eq!(response.dropped_bytes, 0);
+        }
+
+        #[test]
+        fn test_expired_wait_progress() {
+            let response = SshAsyncOutputResponse {
+                command_id: "cmd-123".to_string(),
+                status: AsyncCommandStatus::Running,
+                stdout: String::new(),
+                stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                stdout_is_truncated_utf8: false,
+                stderr_is_truncated_utf8: false,
+                exit_code: None,
+                error: None,
+                timed_out: false,
+                dropped_bytes: 0,
+                wait: Some(WaitProgress {
+                    waited_ms: 300000.0,
+                    expired: true,
+                    renewals: 0,
+                    new_output_bytes: 0,
+                    message: Some("still running".to_string()),
+                }),
+                matched_pattern: None,
+            };
+
+            let json = serde_json::to_value(&response).unwrap();
+            assert_eq!(json["wait"]["expired"], true);
+            assert_eq!(json["wait"]["message"], "still running");
+        }
+
         #[test]
         fn test_optional_fields_omitted_when_none() {
             let response = SshAsyncOutputResponse {
@@ -997,15 +3555,131 @@ mod response_serialization {
                 status: AsyncCommandStatus::Running,
                 stdout: String::new(),
                 stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                stdout_is_truncated_utf8: false,
+                stderr_is_truncated_utf8: false,
                 exit_code: None,
                 error: None,
                 timed_out: false,
+                dropped_bytes: 0,
+                wait: None,
+                matched_pattern: None,
             };
 
             let json = serde_json::to_string(&response).unwrap();
             // exit_code and error should be omitted when None
             assert!(!json.contains("exit_code"));
             assert!(!json.contains("error"));
+            assert!(!json.contains("encoding"));
+        }
+
+        #[test]
+        fn test_output_encoding_name() {
+            let json = serde_json::to_value(OutputEncoding::GzipBase64).unwrap();
+            assert_eq!(json, "gzip+base64");
+            let json = serde_json::to_value(OutputEncoding::Base64).unwrap();
+            assert_eq!(json, "base64");
+        }
+    }
+
+    mod ssh_execute_sync_response {
+        use super::*;
+
+        fn response(status: AsyncCommandStatus, exit_code: Option<i32>) -> SshExecuteSyncResponse {
+            SshExecuteSyncResponse {
+                command_id: "cmd-123".to_string(),
+                session_id: "sess-456".to_string(),
+                agent_id: None,
+                command: "uname -a".to_string(),
+                status,
+                stdout: "Linux host 6.1.0\n".to_string(),
+                stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                exit_code,
+                error: None,
+                timed_out: false,
+                privilege_drop: None,
+                message: "Command completed with exit code 0".to_string(),
+                reconnected: false,
+            }
+        }
+
+        #[test]
+        fn test_completed_roundtrip() {
+            let json =
+                serde_json::to_string(&response(AsyncCommandStatus::Completed, Some(0))).unwrap();
+            let deserialized: SshExecuteSyncResponse = serde_json::from_str(&json).unwrap();
+
+            assert_eq!(deserialized.status, AsyncCommandStatus::Completed);
+            assert_eq!(deserialized.exit_code, Some(0));
+            assert_eq!(deserialized.stdout, "Linux host 6.1.0\n");
+        }
+
+        #[test]
+        fn test_running_omits_optional_fields() {
+            let json = serde_json::to_string(&response(AsyncCommandStatus::Running, None)).unwrap();
+
+            assert!(json.contains("\"status\":\"running\""));
+            assert!(!json.contains("exit_code"));
+            assert!(!json.contains("agent_id"));
+            assert!(!json.contains("\"error\""));
+        }
+    }
+
+    mod ssh_wait_commands_response {
+        use super::*;
+
+        #[test]
+        fn test_serialization() {
+            let response = SshWaitCommandsResponse {
+                done: false,
+                finished: 1,
+                running: 1,
+                commands: vec![
+                    CommandWaitStatus {
+                        command_id: "cmd-1".to_string(),
+                        session_id: "sess-1".to_string(),
+                        status: AsyncCommandStatus::Completed,
+                        exit_code: Some(0),
+                        timed_out: false,
+                    },
+                    CommandWaitStatus {
+                        command_id: "cmd-2".to_string(),
+                        session_id: "sess-2".to_string(),
+                        status: AsyncCommandStatus::Running,
+                        exit_code: None,
+                        timed_out: false,
+                    },
+                ],
+            };
+
+            let json: serde_json::Value = serde_json::to_value(&response).unwrap();
+            assert_eq!(json["done"], false);
+            assert_eq!(json["commands"][0]["exit_code"], 0);
+            assert_eq!(json["commands"][1]["status"], "running");
+            assert!(json["commands"][1].get("exit_code").is_none());
+        }
+    }
+
+    mod ssh_command_signal_response {
+        use super::*;
+
+        #[test]
+        fn test_exit_code_omitted_while_running() {
+            let response = SshCommandSignalResponse {
+                command_id: "cmd-123".to_string(),
+                signal: "TERM".to_string(),
+                status: AsyncCommandStatus::Running,
+                exit_code: None,
+                message: "Sent SIGTERM".to_string(),
+            };
+
+            let json = serde_json::to_value(&response).unwrap();
+            assert_eq!(json["signal"], "TERM");
+            assert_eq!(json["status"], "running");
+            assert!(json.get("exit_code").is_none());
         }
     }
 
@@ -1058,6 +3732,7 @@ mod response_serialization {
                 command: "ls -la".to_string(),
                 status: AsyncCommandStatus::Running,
                 started_at: "2024-01-15T10:30:00Z".to_string(),
+                label: None,
             };
 
             let json = serde_json::to_string(&info).unwrap();
@@ -1077,6 +3752,7 @@ mod response_serialization {
                 command: "echo test".to_string(),
                 status: AsyncCommandStatus::Completed,
                 started_at: "2024-01-15T10:30:00Z".to_string(),
+                label: None,
             };
 
             let cloned = info.clone();
@@ -1110,6 +3786,7 @@ mod response_serialization {
                 command: "sleep 10".to_string(),
                 status: AsyncCommandStatus::Running,
                 started_at: "2024-01-15T10:30:00Z".to_string(),
+                label: None,
             };
             let cmd2 = AsyncCommandInfo {
                 command_id: "cmd-2".to_string(),
@@ -1117,6 +3794,7 @@ mod response_serialization {
                 command: "ls -la".to_string(),
                 status: AsyncCommandStatus::Completed,
                 started_at: "2024-01-15T10:31:00Z".to_string(),
+                label: None,
             };
 
             let response = SshListCommandsResponse {
@@ -1139,6 +3817,27 @@ mod response_serialization {
         }
     }
 
+    mod ssh_cancel_session_commands_response {
+        use super::*;
+
+        #[test]
+        fn test_serialization() {
+            let response = SshCancelSessionCommandsResponse {
+                session_id: "sess-1".to_string(),
+                cancelled: 2,
+                command_ids: vec!["cmd-1".to_string(), "cmd-2".to_string()],
+                message: "Cancelled 2 running command(s) on session 'sess-1'".to_string(),
+            };
+
+            let json = serde_json::to_string(&response).unwrap();
+            let deserialized: SshCancelSessionCommandsResponse =
+                serde_json::from_str(&json).unwrap();
+
+            assert_eq!(deserialized.cancelled, 2);
+            assert_eq!(deserialized.command_ids, vec!["cmd-1", "cmd-2"]);
+        }
+    }
+
     mod agent_disconnect_response {
         use super::*;
 
@@ -1148,6 +3847,8 @@ mod response_serialization {
                 agent_id: "my-agent-123".to_string(),
                 sessions_disconnected: 3,
                 commands_cancelled: 5,
+                shells_closed: 0,
+                forwards_closed: 0,
                 message: "All sessions disconnected".to_string(),
             };
 
@@ -1166,6 +3867,8 @@ mod response_serialization {
                 agent_id: "agent-with-no-sessions".to_string(),
                 sessions_disconnected: 0,
                 commands_cancelled: 0,
+                shells_closed: 0,
+                forwards_closed: 0,
                 message: "No sessions found for agent".to_string(),
             };
 
@@ -1182,6 +3885,8 @@ mod response_serialization {
                 agent_id: "agent".to_string(),
                 sessions_disconnected: 1,
                 commands_cancelled: 2,
+                shells_closed: 0,
+                forwards_closed: 0,
                 message: "msg".to_string(),
             };
 
@@ -1229,6 +3934,7 @@ mod response_serialization {
         }
 
         #[test]
+        #[allow(clippy::clone_on_copy)]
         fn test_clone_and_copy() {
             let status = ShellStatus::Open;
             let cloned = status.clone();
@@ -1250,6 +3956,7 @@ mod response_serialization {
                 cols: 80,
                 rows: 24,
                 opened_at: "2024-01-15T10:30:00Z".to_string(),
+                effective_user: None,
             };
 
             let json = serde_json::to_string(&info).unwrap();
@@ -1271,6 +3978,7 @@ mod response_serialization {
                 cols: 80,
                 rows: 24,
                 opened_at: "2024-01-15T10:30:00Z".to_string(),
+                effective_user: None,
             };
 
             let cloned = info.clone();
@@ -1323,6 +4031,8 @@ mod response_serialization {
                 shell_id: "shell-123".to_string(),
                 data: "$ ls\nfile1\nfile2\n".to_string(),
                 status: ShellStatus::Open,
+                pager_prompt: None,
+                pager_keys_sent: 0,
             };
 
             let json = serde_json::to_string(&response).unwrap();
@@ -1330,6 +4040,22 @@ mod response_serialization {
 
             assert_eq!(deserialized.shell_id, "shell-123");
             assert_eq!(deserialized.status, ShellStatus::Open);
+            assert!(!json.contains("pager_prompt"));
+        }
+
+        #[test]
+        fn test_pager_prompt_serialization() {
+            let response = SshShellReadResponse {
+                shell_id: "shell-123".to_string(),
+                data: "line\n(END)".to_string(),
+                status: ShellStatus::Open,
+                pager_prompt: Some(PagerPrompt::End),
+                pager_keys_sent: 3,
+            };
+
+            let json = serde_json::to_value(&response).unwrap();
+            assert_eq!(json["pager_prompt"], "end");
+            assert_eq!(json["pager_keys_sent"], 3);
         }
     }
 
@@ -0,0 +1,210 @@
+//! Switching the remote user inside an interactive shell.
+//!
+//! `ssh_shell_switch_user` types `sudo -i -u <user>` or `su - <user>` into an
+//! open shell and watches the output:
+//!
+//! 1. A password prompt is answered once with the given password. The PTY does
+//!    not echo it, so it never reaches the shell buffer or the audit log.
+//! 2. A second prompt or an error such as `Sorry, try again` aborts with Ctrl+C.
+//! 3. Once the output settles, [`VERIFY_COMMAND`] prints the effective user
+//!    between markers, and the switch only counts when it names the target.
+//!
+//! The verified user is recorded on the shell's `ShellInfo`. Leaving the login
+//! shell again (`exit` through `ssh_shell_write`) is not tracked.
+
+use std::time::Duration;
+
+use super::command_wrap::shell_quote;
+use super::pager::strip_ansi;
+
+/// Default time allowed for the whole switch
+pub(crate) const DEFAULT_SWITCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Largest accepted `timeout_secs`
+pub(crate) const MAX_SWITCH_TIMEOUT_SECS: u64 = 120;
+
+/// Quiet period after which the switch command is considered done prompting
+pub(crate) const SWITCH_QUIET_PERIOD: Duration = Duration::from_millis(800);
+
+/// Longest accepted user name (Linux `useradd` limit)
+const MAX_USER_LEN: usize = 32;
+
+/// Marker around the verified user name in the shell output
+const WHOAMI_MARKER: &str = "@@ssh-mcp-user:";
+
+/// Prints the effective user between markers. The echoed command line still
+/// contains `$(whoami)`, so only the command's output parses as a user.
+pub(crate) const VERIFY_COMMAND: &str = "echo \"@@ssh-mcp-user:$(whoami)@@\"\n";
+
+/// Output fragments (lower-cased) that mean the switch failed
+const FAILURE_PATTERNS: &[&str] = &[
+    "sorry, try again",
+    "authentication failure",
+    "incorrect password",
+    "is not in the sudoers file",
+    "is not allowed to",
+    "unknown user",
+    "does not exist",
+    "unknown id",
+    "permission denied",
+    "a password is required",
+];
+
+/// How the user is switched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SwitchMethod {
+    /// `sudo -i -u <user>`: asks for the caller's own password
+    Sudo,
+    /// `su - <user>`: asks for the target user's password
+    Su,
+}
+
+impl SwitchMethod {
+    /// Parse the `method` tool parameter (default: `sudo`).
+    pub(crate) fn parse(name: Option<&str>) -> Result<Self, String> {
+        match name.map(str::trim) {
+            None | Some("") | Some("sudo") => Ok(Self::Sudo),
+            Some("su") => Ok(Self::Su),
+            Some(other) => Err(format!(
+                "Unknown switch method '{}'. Use 'sudo' or 'su'",
+                other
+            )),
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Sudo => "sudo",
+            Self::Su => "su",
+        }
+    }
+
+    /// Line typed into the shell to become `user`.
+    pub(crate) fn command(self, user: &str) -> String {
+        match self {
+            Self::Sudo => format!("sudo -i -u {}\n", shell_quote(user)),
+            Self::Su => format!("su - {}\n", shell_quote(user)),
+        }
+    }
+}
+
+/// Validate a user name: `[A-Za-z0-9._-]`, not starting with `-`, at most 32 characters.
+pub(crate) fn validate_user(user: &str) -> Result<&str, String> {
+    let user = user.trim();
+    let valid = !user.is_empty()
+        && user.len() <= MAX_USER_LEN
+        && !user.starts_with('-')
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(user)
+    } else {
+        Err(format!("Invalid user name '{}'", user))
+    }
+}
+
+/// Whether the output ends in a password prompt waiting for input.
+pub(crate) fn detect_password_prompt(output: &[u8]) -> bool {
+    let text = strip_ansi(&String::from_utf8_lossy(output));
+    let last_line = text
+        .rsplit(['\n', '\r'])
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("");
+    let line = last_line.trim_end().to_ascii_lowercase();
+    line.ends_with(':') && (line.contains("password") || line.contains("passphrase"))
+}
+
+/// First failure message in the output, if any.
+pub(crate) fn detect_failure(output: &[u8]) -> Option<String> {
+    let text = strip_ansi(&String::from_utf8_lossy(output));
+    text.lines()
+        .find(|line| {
+            let line = line.to_ascii_lowercase();
+            FAILURE_PATTERNS
+                .iter()
+                .any(|pattern| line.contains(pattern))
+        })
+        .map(|line| line.trim().to_string())
+}
+
+/// User printed by [`VERIFY_COMMAND`], if its output has arrived.
+pub(crate) fn parse_verified_user(output: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(output);
+    text.match_indices(WHOAMI_MARKER).find_map(|(index, _)| {
+        let rest = &text[index + WHOAMI_MARKER.len()..];
+        let (user, _) = rest.split_once("@@")?;
+        validate_user(user).ok().map(str::to_string)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod method {
+        use super::*;
+
+        #[test]
+        fn test_parse_and_command() {
+            assert_eq!(SwitchMethod::parse(None).unwrap(), SwitchMethod::Sudo);
+            assert_eq!(SwitchMethod::parse(Some("su")).unwrap(), SwitchMethod::Su);
+            assert!(SwitchMethod::parse(Some("doas")).is_err());
+            assert_eq!(
+                SwitchMethod::Sudo.command("postgres"),
+                "sudo -i -u 'postgres'\n"
+            );
+            assert_eq!(SwitchMethod::Su.command("app"), "su - 'app'\n");
+        }
+
+        #[test]
+        fn test_validate_user() {
+            assert_eq!(validate_user(" deploy ").unwrap(), "deploy");
+            assert!(validate_user("svc.app-1_x").is_ok());
+            assert!(validate_user("").is_err());
+            assert!(validate_user("-c").is_err());
+            assert!(validate_user("root; reboot").is_err());
+            assert!(validate_user(&"a".repeat(33)).is_err());
+        }
+    }
+
+    mod prompts {
+        use super::*;
+
+        #[test]
+        fn test_password_prompts() {
+            assert!(detect_password_prompt(
+                b"$ sudo -i -u app\r\n[sudo] password for alice: "
+            ));
+            assert!(detect_password_prompt(b"su - app\r\nPassword:"));
+            assert!(detect_password_prompt(b"\x1b[1mPassword:\x1b[0m "));
+            assert!(!detect_password_prompt(b"Password: \r\napp@host:~$ "));
+            assert!(!detect_password_prompt(b"echo password\r\npassword\r\n$ "));
+        }
+
+        #[test]
+        fn test_failures() {
+            assert_eq!(
+                detect_failure(b"[sudo] password for alice: \r\nSorry, try again.\r\n"),
+                Some("Sorry, try again.".to_string())
+            );
+            assert!(detect_failure(b"su: Authentication failure").is_some());
+            assert!(detect_failure(b"alice is not in the sudoers file.").is_some());
+            assert!(detect_failure(b"app@host:~$ ").is_none());
+        }
+    }
+
+    mod verification {
+        use super::*;
+
+        #[test]
+        fn test_echoed_command_is_ignored() {
+            let output = b"app@host:~$ echo \"@@ssh-mcp-user:$(whoami)@@\"\r\n@@ssh-mcp-user:app@@\r\napp@host:~$ ";
+            assert_eq!(parse_verified_user(output).as_deref(), Some("app"));
+            assert_eq!(
+                parse_verified_user(b"$ echo \"@@ssh-mcp-user:$(whoami)@@\"\r\n"),
+                None
+            );
+        }
+    }
+}
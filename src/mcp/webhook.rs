@@ -0,0 +1,290 @@
+//! Command result webhooks.
+//!
+//! `ssh_execute(callback_url = ...)` POSTs the command's final
+//! [`SshAsyncOutputResponse`] as JSON to the URL once the command finishes, so
+//! an orchestrator can launch a long job through MCP and collect the result
+//! without keeping an MCP connection open. The payload adds the `session_id`
+//! and keeps only the last `[callbacks] max_output_bytes` of stdout and of
+//! stderr, setting `truncated` when it cut either; the whole output stays
+//! available through `ssh_get_command_output`. A command started with
+//! `output_encoding: "base64"` has its fields cut on a 4-character boundary,
+//! so the kept tail still decodes.
+//!
+//! The server only calls hosts listed in `[callbacks] allowed_hosts`; without
+//! that list every `callback_url` is refused. Like the audit HTTP exporter,
+//! only plain `http://` URLs are supported. Failed deliveries are retried with
+//! exponential backoff, then dropped and logged; the command output stays
+//! available through `ssh_get_command_output` either way.
+
+use std::time::Duration;
+
+use backon::{ExponentialBuilder, Retryable};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use super::audit::{self, AuditEvent};
+use super::config::CallbacksConfig;
+use super::host_limit::host_key;
+use super::siem::{HttpTarget, parse_http_url, post_http};
+use super::types::{OutputEncoding, SshAsyncOutputResponse};
+
+/// Timeout for one delivery attempt (connect, write and HTTP response)
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// First retry delay; later retries back off exponentially up to [`MAX_RETRY_DELAY`]
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between delivery retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Body POSTed to a callback URL.
+#[derive(Serialize)]
+struct CallbackPayload<'a> {
+    session_id: &'a str,
+    #[serde(flatten)]
+    output: &'a SshAsyncOutputResponse,
+    /// stdout or stderr was cut to its last `max_output_bytes`
+    truncated: bool,
+}
+
+/// Keep the last `max_bytes` of `text` (0 keeps all), starting on a character
+/// boundary, or on a base64 quantum for an encoded field. Returns whether
+/// anything was cut.
+fn keep_tail(text: &mut String, max_bytes: usize, encoding: Option<OutputEncoding>) -> bool {
+    if max_bytes == 0 || text.len() <= max_bytes {
+        return false;
+    }
+    let mut start = text.len() - max_bytes;
+    if encoding.is_some() {
+        start = start.div_ceil(4) * 4;
+    }
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text.drain(..start);
+    true
+}
+
+/// Validate `url` against `config` and return where to POST it.
+pub(crate) fn resolve_callback(url: &str, config: &CallbacksConfig) -> Result<HttpTarget, String> {
+    let mut target = parse_http_url(url)?;
+    let host = host_key(&target.address);
+    if !config.allows(&host) {
+        return Err(if config.allowed_hosts.is_empty() {
+            "Callbacks are disabled: the server configuration has no [callbacks] allowed_hosts"
+                .to_string()
+        } else {
+            format!(
+                "Callback host '{}' is not in [callbacks] allowed_hosts",
+                host
+            )
+        });
+    }
+    target.headers = config.headers.clone();
+    Ok(target)
+}
+
+/// POST the final `output` of a command on `session_id` to `target`, with
+/// stdout and stderr cut to their last `config.max_output_bytes`.
+pub(crate) async fn deliver_callback(
+    target: &HttpTarget,
+    url: &str,
+    session_id: &str,
+    mut output: SshAsyncOutputResponse,
+    config: &CallbacksConfig,
+) {
+    let stdout_cut = keep_tail(
+        &mut output.stdout,
+        config.max_output_bytes,
+        output.stdout_encoding,
+    );
+    let stderr_cut = keep_tail(
+        &mut output.stderr,
+        config.max_output_bytes,
+        output.stderr_encoding,
+    );
+    let payload = CallbackPayload {
+        session_id,
+        output: &output,
+        truncated: stdout_cut || stderr_cut,
+    };
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Cannot serialize callback for {}: {}", output.command_id, e);
+            return;
+        }
+    };
+    let backoff = ExponentialBuilder::default()
+        .with_min_delay(MIN_RETRY_DELAY)
+        .with_max_delay(MAX_RETRY_DELAY)
+        .with_max_times(config.max_retries)
+        .with_jitter();
+
+    let result = (|| async {
+        tokio::time::timeout(
+            CALLBACK_TIMEOUT,
+            post_http(target, &body, "application/json"),
+        )
+        .await
+        .unwrap_or_else(|_| Err("delivery timed out".to_string()))
+    })
+    .retry(backoff)
+    .notify(|e, delay| {
+        warn!(
+            "Callback for command {} to {} failed ({}); retrying in {:?}",
+            output.command_id, url, e, delay
+        )
+    })
+    .await;
+
+    let event = AuditEvent::new("command_callback")
+        .session_id(session_id)
+        .target(url);
+    match result {
+        Ok(()) => {
+            info!(
+                "Delivered callback for command {} to {}",
+                output.command_id, url
+            );
+            audit::record(event);
+        }
+        Err(e) => {
+            warn!(
+                "Dropped callback for command {} to {}: {}",
+                output.command_id, url, e
+            );
+            audit::record(event.failed(e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::types::AsyncCommandStatus;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn config(hosts: &[&str]) -> CallbacksConfig {
+        CallbacksConfig {
+            allowed_hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            ..CallbacksConfig::default()
+        }
+    }
+
+    mod allowlist {
+        use super::*;
+
+        #[test]
+        fn test_disabled_without_allowed_hosts() {
+            let err = resolve_callback("http://orchestrator/done", &config(&[])).unwrap_err();
+            assert!(err.contains("disabled"));
+        }
+
+        #[test]
+        fn test_host_must_be_listed() {
+            let allowed = config(&["Orchestrator.internal"]);
+            let target = resolve_callback("http://orchestrator.internal:8080/jobs", &allowed);
+            assert_eq!(target.unwrap().path, "/jobs");
+            let err = resolve_callback("http://169.254.169.254/latest", &allowed).unwrap_err();
+            assert!(err.contains("169.254.169.254"));
+        }
+
+        #[test]
+        fn test_wildcard_and_https() {
+            assert!(resolve_callback("http://anywhere:9000", &config(&["*"])).is_ok());
+            assert!(resolve_callback("https://anywhere", &config(&["*"])).is_err());
+        }
+    }
+
+    mod payload {
+        use super::*;
+
+        #[test]
+        fn test_keep_tail_on_char_boundary() {
+            let mut text = "log line\nfinal: ok\n".to_string();
+            assert!(!keep_tail(&mut text, 0, None));
+            assert!(!keep_tail(&mut text, 100, None));
+            assert!(keep_tail(&mut text, 10, None));
+            assert_eq!(text, "final: ok\n");
+
+            // A cut inside a multi-byte character moves past it
+            let mut text = "aé€".to_string();
+            assert!(keep_tail(&mut text, 4, None));
+            assert_eq!(text, "€");
+        }
+
+        #[test]
+        fn test_keep_tail_base64_decodes() {
+            use base64::Engine;
+            use base64::engine::general_purpose::STANDARD;
+
+            let mut text = STANDARD.encode(b"\x00\x01binary tail\xff");
+            assert!(keep_tail(&mut text, 10, Some(OutputEncoding::Base64)));
+            assert_eq!(text.len() % 4, 0);
+            assert!(text.len() <= 10);
+            assert!(STANDARD.decode(&text).unwrap().ends_with(b"tail\xff"));
+        }
+    }
+
+    mod delivery {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_posts_output_json() {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/done", listener.local_addr().unwrap());
+            let server = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut chunk = [0u8; 4096];
+                while !String::from_utf8_lossy(&request).contains("\"truncated\"") {
+                    let read = stream.read(&mut chunk).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&chunk[..read]);
+                }
+                stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                    .await
+                    .unwrap();
+                String::from_utf8(request).unwrap()
+            });
+
+            let target = resolve_callback(&url, &config(&["127.0.0.1"])).unwrap();
+            let output = SshAsyncOutputResponse {
+                command_id: "cmd-1".to_string(),
+                status: AsyncCommandStatus::Completed,
+                stdout: "build output\ndone\n".to_string(),
+                stderr: String::new(),
+                stdout_encoding: None,
+                stderr_encoding: None,
+                stdout_is_truncated_utf8: false,
+                stderr_is_truncated_utf8: false,
+                exit_code: Some(0),
+                error: None,
+                timed_out: false,
+                dropped_bytes: 0,
+                wait: None,
+                matched_pattern: None,
+            };
+            let settings = CallbacksConfig {
+                max_retries: 0,
+                max_output_bytes: 5,
+                ..CallbacksConfig::default()
+            };
+            deliver_callback(&target, &url, "s-1", output, &settings).await;
+
+            let request = server.await.unwrap();
+            assert!(request.starts_with("POST /done HTTP/1.1"));
+            assert!(request.contains("Content-Type: application/json"));
+            assert!(request.contains("\"command_id\":\"cmd-1\""));
+            assert!(request.contains("\"session_id\":\"s-1\""));
+            assert!(request.contains("\"stdout\":\"done\\n\""));
+            assert!(request.contains("\"exit_code\":0"));
+            assert!(request.contains("\"truncated\":true"));
+        }
+    }
+}